@@ -6,19 +6,28 @@
 
 use datafusion::sql::TableReference;
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fmt::Debug;
 use std::sync::Arc;
 use std::time::SystemTime;
 
+use crate::datasource::delete_vector::is_delete_vector;
+use crate::datasource::statistics::StoredFileStatistics;
 use crate::error::{LakeSoulError, Result};
 use crate::lakesoul_table::helpers::create_io_config_builder_from_table_info;
 use crate::serialize::arrow_java::ArrowJavaSchema;
-use lakesoul_io::lakesoul_io_config::{LakeSoulIOConfig, LakeSoulIOConfigBuilder};
+use chrono::Utc;
+use lakesoul_io::lakesoul_io_config::{
+    LakeSoulIOConfig, LakeSoulIOConfigBuilder, OPTION_KEY_ENCRYPTION_KEY_ID,
+    OPTION_KEY_HASH_BUCKET_NUM, OPTION_KEY_KEEP_PARTITION_COLUMNS,
+    OPTION_KEY_MERGE_STRATEGY, OPTION_KEY_NULLS_FIRST, OPTION_KEY_PARQUET_COMPRESSION,
+    OPTION_KEY_PARTITION_PATH_ENCODING, OPTION_KEY_PRIMARY_KEY_BLOOM_FILTER,
+};
 use lakesoul_metadata::{LakeSoulMetaDataError, MetaDataClientRef};
 use proto::proto::entity::{
-    CommitOp, DataCommitInfo, DataFileOp, FileOp, TableInfo, Uuid,
+    CommitOp, DataCommitInfo, DataFileOp, DiscardCompressedFileInfo, FileOp,
+    FileStatistics, PartitionInfo, TableInfo, Uuid,
 };
 
 pub mod lakesoul_catalog;
@@ -88,6 +97,99 @@ pub struct LakeSoulTableProperty {
     /// Whether use cdc is enabled for the LakeSoul table.
     #[serde(rename = "use_cdc", default, skip_serializing_if = "Option::is_none")]
     pub use_cdc: Option<String>,
+    /// The SQL expressions of the generated columns by their column names, see
+    /// [`LakeSoulIOConfigBuilder::with_generated_column`].
+    #[serde(
+        rename = "generatedColumns",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub generated_columns: Option<HashMap<String, String>>,
+    /// Whether the null values of the primary keys are ordered first in the data files,
+    /// see [`LakeSoulIOConfigBuilder::with_nulls_first`]. Absent means they are.
+    #[serde(
+        rename = "nullsFirst",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub nulls_first: Option<bool>,
+    /// The columns encrypted in the data files with the keys of the key management
+    /// service, see [`LakeSoulIOConfigBuilder::with_encrypted_columns`].
+    #[serde(
+        rename = "encryptedColumns",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub encrypted_columns: Option<Vec<String>>,
+    /// The name of the key management service of the encrypted columns.
+    #[serde(
+        rename = "encryptionKms",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub encryption_kms: Option<String>,
+    /// The id of the footer key of the encryption, see
+    /// [`LakeSoulIOConfigBuilder::with_encryption_key_id`].
+    #[serde(
+        rename = "encryptionKeyId",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub encryption_key_id: Option<String>,
+    /// The codec of the written parquet files, see
+    /// [`LakeSoulIOConfigBuilder::with_parquet_compression`]. The compactions write with
+    /// it whatever the codec of the session, absent means the default codec.
+    #[serde(
+        rename = "parquetCompression",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub parquet_compression: Option<String>,
+    /// The column ordering the versions of a primary key when merging, see
+    /// [`LakeSoulIOConfigBuilder::with_sequence_column`]. Absent means the versions are
+    /// merged in the order of the files.
+    #[serde(
+        rename = "sequenceColumn",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub sequence_column: Option<String>,
+    /// The encoding of the range partition values into the sub paths of the data files, see
+    /// [`LakeSoulIOConfigBuilder::with_partition_path_encoding`]. Absent means the Hive
+    /// style encoding.
+    #[serde(
+        rename = "partitionPathEncoding",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub partition_path_encoding: Option<String>,
+    /// Whether the range partition columns are written into the data files as well as
+    /// encoded into their paths, see [`LakeSoulIOConfigBuilder::with_keep_partition_columns`].
+    /// Absent means they are not.
+    #[serde(
+        rename = "keepPartitionColumns",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub keep_partition_columns: Option<bool>,
+    /// Whether bloom filters of the primary key columns are written, see
+    /// [`LakeSoulIOConfigBuilder::with_primary_key_bloom_filter`]. The point lookups on the
+    /// primary keys only read the bloom filters of the tables writing them.
+    #[serde(
+        rename = "primaryKeyBloomFilter",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub primary_key_bloom_filter: Option<bool>,
+    /// How the versions of a primary key are merged on read, see
+    /// [`LakeSoulIOConfigBuilder::with_merge_strategy`]. Absent means the last version
+    /// replaces the whole row.
+    #[serde(
+        rename = "mergeStrategy",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub merge_strategy: Option<String>,
 }
 
 /// Register a LakeSoul table in the LakeSoul metadata.
@@ -97,6 +199,17 @@ pub(crate) async fn create_table(
     config: LakeSoulIOConfig,
 ) -> Result<()> {
     info!("create_table: {:?}", &table_name);
+    // the tables have 4 hash buckets unless the config sets their number
+    let hash_bucket_num = match config.option(OPTION_KEY_HASH_BUCKET_NUM) {
+        Some(_) => config.hash_bucket_num(),
+        None => 4,
+    };
+    let cdc_column = config.cdc_column();
+    let use_cdc = !cdc_column.is_empty();
+    // the encoding of the paths is checked before it is persisted
+    config.partition_path_encoder()?;
+    // so is the merge strategy
+    config.merge_strategy()?;
     client
         .create_table(TableInfo {
             table_id: format!("table_{}", uuid::Uuid::new_v4()),
@@ -114,7 +227,34 @@ pub(crate) async fn create_table(
             )?,
             table_namespace: "default".to_string(),
             properties: serde_json::to_string(&LakeSoulTableProperty {
-                hash_bucket_num: Some(4),
+                hash_bucket_num: Some(hash_bucket_num),
+                cdc_change_column: use_cdc.then_some(cdc_column),
+                use_cdc: use_cdc.then(|| "true".to_string()),
+                generated_columns: (!config.generated_columns().is_empty())
+                    .then(|| config.generated_columns().clone()),
+                nulls_first: config
+                    .option(OPTION_KEY_NULLS_FIRST)
+                    .map(|_| config.nulls_first()),
+                encrypted_columns: (!config.encrypted_columns().is_empty())
+                    .then(|| config.encrypted_columns()),
+                encryption_kms: config.encryption_kms().cloned(),
+                encryption_key_id: config
+                    .option(OPTION_KEY_ENCRYPTION_KEY_ID)
+                    .map(|_| config.encryption_key_id().to_string()),
+                parquet_compression: config
+                    .option(OPTION_KEY_PARQUET_COMPRESSION)
+                    .cloned(),
+                sequence_column: config.sequence_column(),
+                partition_path_encoding: config
+                    .option(OPTION_KEY_PARTITION_PATH_ENCODING)
+                    .cloned(),
+                keep_partition_columns: config
+                    .option(OPTION_KEY_KEEP_PARTITION_COLUMNS)
+                    .map(|_| config.keep_partition_columns()),
+                primary_key_bloom_filter: config
+                    .option(OPTION_KEY_PRIMARY_KEY_BLOOM_FILTER)
+                    .map(|_| config.primary_key_bloom_filter()),
+                merge_strategy: config.option(OPTION_KEY_MERGE_STRATEGY).cloned(),
                 ..Default::default()
             })?,
             partitions: format!(
@@ -153,9 +293,13 @@ pub(crate) async fn create_io_config_builder(
             .await?;
         if let Some(table_info) = table_info {
             let data_files = if fetch_files {
+                // the delete vectors are only applied by the scans of the table provider
                 client
                     .get_data_files_by_table_name(table_name, namespace)
                     .await?
+                    .into_iter()
+                    .filter(|path| !is_delete_vector(path))
+                    .collect()
             } else {
                 vec![]
             };
@@ -214,6 +358,16 @@ pub(crate) fn parse_table_info_partitions(
     ))
 }
 
+/// Parse the number of hash buckets the rows of a table are written into by the hash of
+/// their hash partition keys from the properties of the table, 1 if not set.
+///
+/// The buckets of the rows decide the files they are written into and the files scanned
+/// for the keys, so unreadable properties are an error instead of a single bucket.
+pub(crate) fn parse_table_info_hash_bucket_num(properties: &str) -> Result<usize> {
+    let properties = serde_json::from_str::<LakeSoulTableProperty>(properties)?;
+    Ok(properties.hash_bucket_num.unwrap_or(1).max(1))
+}
+
 pub(crate) fn format_table_info_partitions(
     range_keys: &[String],
     hash_keys: &[String],
@@ -221,13 +375,182 @@ pub(crate) fn format_table_info_partitions(
     format!("{};{}", range_keys.join(","), hash_keys.join(","))
 }
 
-/// Commit the data files to the LakeSoul metadata.
-pub(crate) async fn commit_data(
+/// Commit the data files of multiple partitions to the LakeSoul metadata.
+///
+/// All partitions are committed in one metadata transaction, so either all or none of them
+/// are visible to readers. The statistics of the files are stored in the same transaction,
+/// so that committed files always have their statistics stored and a failed commit leaves
+/// none behind. The commit fails if one of the `read_partitions` was committed since it was
+/// read.
+pub(crate) async fn commit_data_batch(
     client: MetaDataClientRef,
     table_name: &str,
-    partition_desc: String,
-    files: &[String],
+    partitioned_files: impl IntoIterator<Item = (String, Vec<(String, StoredFileStatistics)>)>,
+    read_partitions: Vec<PartitionInfo>,
 ) -> Result<()> {
+    let (data_commit_info_list, file_statistics) = build_data_commit_infos(
+        client.clone(),
+        table_name,
+        partitioned_files,
+        CommitOp::AppendCommit,
+    )
+    .await?;
+    client
+        .commit_data_commit_info_batch_with_statistics(
+            data_commit_info_list,
+            file_statistics,
+            read_partitions,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Commit the compacted data files of multiple partitions to the LakeSoul metadata.
+///
+/// The compacted files and the `retained_files` of each partition, committed again with
+/// their file ops, replace the whole snapshot of their partitions, so the other files of the
+/// `read_partitions` the files were compacted from are no longer visible to readers. The commit
+/// fails if one of the partitions was committed since it was read, or was created since if
+/// it is missing from the `read_partitions`.
+pub(crate) async fn commit_compaction_batch(
+    client: MetaDataClientRef,
+    table_name: &str,
+    partitioned_files: impl IntoIterator<Item = (String, Vec<(String, StoredFileStatistics)>)>,
+    retained_files: Vec<(String, Vec<DataFileOp>)>,
+    mut read_partitions: Vec<PartitionInfo>,
+) -> Result<()> {
+    let (mut data_commit_info_list, file_statistics) = build_data_commit_infos(
+        client.clone(),
+        table_name,
+        partitioned_files,
+        CommitOp::CompactionCommit,
+    )
+    .await?;
+    let Some(table_id) = data_commit_info_list
+        .first()
+        .map(|info| info.table_id.clone())
+    else {
+        return Ok(());
+    };
+    let retained_files = retained_files.into_iter().collect::<HashMap<_, _>>();
+    for info in data_commit_info_list.iter_mut() {
+        if let Some(file_ops) = retained_files.get(&info.partition_desc) {
+            info.file_ops.extend(file_ops.iter().cloned());
+        }
+    }
+    // the other files of the read snapshots are superseded by the compacted files
+    let mut superseded_paths = Vec::with_capacity(read_partitions.len());
+    for partition_info in &read_partitions {
+        let retained = retained_files
+            .get(&partition_info.partition_desc)
+            .map(|file_ops| {
+                file_ops
+                    .iter()
+                    .map(|file_op| file_op.path.as_str())
+                    .collect::<HashSet<_>>()
+            })
+            .unwrap_or_default();
+        let paths = client
+            .get_data_files_of_single_partition(partition_info)
+            .await?
+            .into_iter()
+            .filter(|path| !retained.contains(path.as_str()))
+            .collect();
+        superseded_paths.push((partition_info.partition_desc.clone(), paths));
+    }
+    require_absent_partitions(&mut read_partitions, &data_commit_info_list, &table_id);
+    client
+        .commit_data_commit_info_batch_with_statistics(
+            data_commit_info_list,
+            file_statistics,
+            read_partitions,
+        )
+        .await?;
+    record_discarded_files(client, &table_id, superseded_paths).await;
+    Ok(())
+}
+
+/// Commit the merged data files of multiple partitions to the LakeSoul metadata.
+///
+/// Unlike [`commit_compaction_batch`], only the `superseded_files` of each partition are
+/// removed from its snapshot in the same transaction adding the merged files, the other
+/// files stay visible. The commit fails if one of the `read_partitions` was committed since
+/// it was read, or was created since if it is missing from the `read_partitions`.
+pub(crate) async fn commit_merge_batch(
+    client: MetaDataClientRef,
+    table_name: &str,
+    partitioned_files: impl IntoIterator<Item = (String, Vec<(String, StoredFileStatistics)>)>,
+    superseded_files: Vec<(String, Vec<String>)>,
+    mut read_partitions: Vec<PartitionInfo>,
+) -> Result<()> {
+    let (mut data_commit_info_list, file_statistics) = build_data_commit_infos(
+        client.clone(),
+        table_name,
+        partitioned_files,
+        CommitOp::AppendCommit,
+    )
+    .await?;
+    let Some(table_id) = data_commit_info_list
+        .first()
+        .map(|info| info.table_id.clone())
+    else {
+        return Ok(());
+    };
+    for info in data_commit_info_list.iter_mut() {
+        let Some((_, paths)) = superseded_files
+            .iter()
+            .find(|(partition_desc, _)| partition_desc == &info.partition_desc)
+        else {
+            continue;
+        };
+        info.file_ops.extend(paths.iter().map(|path| DataFileOp {
+            file_op: FileOp::Del as i32,
+            path: path.clone(),
+            ..Default::default()
+        }));
+    }
+    require_absent_partitions(&mut read_partitions, &data_commit_info_list, &table_id);
+    client
+        .commit_data_commit_info_batch_with_statistics(
+            data_commit_info_list,
+            file_statistics,
+            read_partitions,
+        )
+        .await?;
+    record_discarded_files(client, &table_id, superseded_files).await;
+    Ok(())
+}
+
+/// Add the partitions of the data commit infos which were not read to the read partitions
+/// with a negative version, so that the commit fails if they were created meanwhile.
+fn require_absent_partitions(
+    read_partitions: &mut Vec<PartitionInfo>,
+    data_commit_info_list: &[DataCommitInfo],
+    table_id: &str,
+) {
+    for info in data_commit_info_list {
+        if !read_partitions
+            .iter()
+            .any(|partition_info| partition_info.partition_desc == info.partition_desc)
+        {
+            read_partitions.push(PartitionInfo {
+                table_id: table_id.to_string(),
+                partition_desc: info.partition_desc.clone(),
+                version: -1,
+                ..Default::default()
+            });
+        }
+    }
+}
+
+/// Build the data commit infos of the partitions of the data files, along with the
+/// statistics of the files to store in the commit transaction.
+async fn build_data_commit_infos(
+    client: MetaDataClientRef,
+    table_name: &str,
+    partitioned_files: impl IntoIterator<Item = (String, Vec<(String, StoredFileStatistics)>)>,
+    commit_op: CommitOp,
+) -> Result<(Vec<DataCommitInfo>, Vec<FileStatistics>)> {
     let table_ref = TableReference::from(table_name);
     let table_name_id = client
         .get_table_name_id_by_table_name(
@@ -236,22 +559,123 @@ pub(crate) async fn commit_data(
         )
         .await?
         .ok_or(LakeSoulError::Internal("table not found".to_string()))?;
+    let mut partitioned_paths = Vec::new();
+    let mut file_statistics = Vec::new();
+    for (partition_desc, files) in partitioned_files {
+        let mut paths = Vec::with_capacity(files.len());
+        for (path, statistics) in files {
+            file_statistics.push(FileStatistics {
+                table_id: table_name_id.table_id.clone(),
+                file_path: path.clone(),
+                partition_desc: partition_desc.clone(),
+                statistics: serde_json::to_string(&statistics)?,
+            });
+            paths.push(path);
+        }
+        partitioned_paths.push((partition_desc, paths));
+    }
+    let data_commit_info_list = data_commit_infos(
+        &table_name_id.table_id,
+        partitioned_paths,
+        FileOp::Add,
+        commit_op,
+    )?;
+    Ok((data_commit_info_list, file_statistics))
+}
+
+/// Commit tombstones of data files of multiple partitions to the LakeSoul metadata.
+///
+/// The tombstoned files are left out of the snapshots of their partitions, so readers no
+/// longer scan them, but they stay in the object store until they are vacuumed, see
+/// [`LakeSoulTable::vacuum`](crate::lakesoul_table::LakeSoulTable::vacuum). All
+/// partitions are committed in one metadata transaction.
+pub(crate) async fn commit_tombstones(
+    client: MetaDataClientRef,
+    table_id: &str,
+    partitioned_paths: Vec<(String, Vec<String>)>,
+) -> Result<()> {
+    let data_commit_info_list = data_commit_infos(
+        table_id,
+        partitioned_paths.clone(),
+        FileOp::Del,
+        CommitOp::AppendCommit,
+    )?;
     client
-        .commit_data_commit_info(DataCommitInfo {
-            table_id: table_name_id.table_id,
+        .commit_data_commit_info_batch(data_commit_info_list)
+        .await?;
+    record_discarded_files(client, table_id, partitioned_paths).await;
+    Ok(())
+}
+
+/// Record the files which are no longer part of the snapshots of their partitions, to be
+/// deleted by a vacuum once they are older than its retention.
+///
+/// The files are already committed away, so a failed record is only logged. The files
+/// which are not recorded are found as orphan files by the vacuum instead.
+async fn record_discarded_files(
+    client: MetaDataClientRef,
+    table_id: &str,
+    partitioned_paths: Vec<(String, Vec<String>)>,
+) {
+    let recorded = async {
+        let table_info = client
+            .get_table_info_by_table_id(table_id)
+            .await?
+            .ok_or(LakeSoulError::Internal("table not found".to_string()))?;
+        let table_path = &table_info.table_path;
+        let now = Utc::now();
+        let discard_compressed_file_info = partitioned_paths
+            .into_iter()
+            .flat_map(|(partition_desc, paths)| {
+                paths
+                    .into_iter()
+                    .map(move |file_path| DiscardCompressedFileInfo {
+                        file_path,
+                        table_path: table_path.clone(),
+                        partition_desc: partition_desc.clone(),
+                        timestamp: now.timestamp_millis(),
+                        t_date: now.format("%Y-%m-%d").to_string(),
+                    })
+            })
+            .collect();
+        client
+            .insert_discard_compressed_file_info(discard_compressed_file_info)
+            .await?;
+        Ok::<_, LakeSoulError>(())
+    };
+    if let Err(e) = recorded.await {
+        warn!(
+            "failed to record discarded files of table {}: {}",
+            table_id, e
+        );
+    }
+}
+
+/// Build the data commit infos applying the file op to the files of their partitions.
+fn data_commit_infos(
+    table_id: &str,
+    partitioned_paths: Vec<(String, Vec<String>)>,
+    file_op: FileOp,
+    commit_op: CommitOp,
+) -> Result<Vec<DataCommitInfo>> {
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs() as i64;
+    Ok(partitioned_paths
+        .into_iter()
+        .map(|(partition_desc, files)| DataCommitInfo {
+            table_id: table_id.to_string(),
             partition_desc,
             file_ops: files
-                .iter()
-                .map(|file| DataFileOp {
-                    file_op: FileOp::Add as i32,
-                    path: file.clone(),
+                .into_iter()
+                .map(|path| DataFileOp {
+                    file_op: file_op as i32,
+                    path,
                     ..Default::default()
                 })
                 .collect(),
-            commit_op: CommitOp::AppendCommit as i32,
-            timestamp: SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)?
-                .as_secs() as i64,
+            commit_op: commit_op as i32,
+            timestamp,
             commit_id: {
                 let (high, low) = uuid::Uuid::new_v4().as_u64_pair();
                 Some(Uuid { high, low })
@@ -259,6 +683,5 @@ pub(crate) async fn commit_data(
             committed: false,
             domain: "public".to_string(),
         })
-        .await?;
-    Ok(())
+        .collect())
 }
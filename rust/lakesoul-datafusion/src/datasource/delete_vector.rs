@@ -0,0 +1,138 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Positional delete vectors of the data files of LakeSoul tables.
+//!
+//! A delete vector is a sidecar file next to a data file holding the indexes of the
+//! deleted rows of the data file, serialized as a 64-bit roaring bitmap like the deletion
+//! vectors of Delta Lake. It deletes rows from the merge on read without writing rows of
+//! the cdc column with the `delete` kind, which are read and filtered by every scan.
+//!
+//! The delete vectors are committed as files of the partition of their data file and
+//! named after it, see [`delete_vector_path`], so that the snapshots of the partitions
+//! track them like the data files. Deleting more rows of a data file commits a new delete
+//! vector with all its deleted rows, removing the previous ones in the same commit, see
+//! [`LakeSoulTable::delete_rows`](crate::lakesoul_table::LakeSoulTable::delete_rows).
+//! The scans of [`LakeSoulMetaDataParquetFormat`] drop the deleted rows of each data file
+//! before the merge, the rows of all the delete vectors of a data file are deleted.
+//!
+//! As the delete vectors are listed with the data files of the partitions, the paths
+//! listing the files of a partition skip them, see [`is_delete_vector`].
+//!
+//! [`LakeSoulMetaDataParquetFormat`]: super::file_format::LakeSoulMetaDataParquetFormat
+
+use std::collections::HashMap;
+
+use datafusion::datasource::listing::PartitionedFile;
+use datafusion::datasource::physical_plan::{FileGroup, FileScanConfig};
+use datafusion::error::Result;
+use object_store::ObjectStore;
+use object_store::path::Path;
+use uuid::Uuid;
+
+use super::delta::deletion_vector::{decode_bitmap_array, encode_bitmap_array};
+
+/// The extension of the delete vector files.
+pub const DELETE_VECTOR_EXTENSION: &str = "dv";
+
+/// The path of a new delete vector of the data file, the path of the data file followed
+/// by a unique id and the extension of the delete vectors.
+pub fn delete_vector_path(data_file_path: &str) -> String {
+    format!(
+        "{}.{}.{}",
+        data_file_path,
+        Uuid::new_v4().simple(),
+        DELETE_VECTOR_EXTENSION
+    )
+}
+
+/// The path of the data file of the delete vector, `None` if the path is not the path of
+/// a delete vector.
+pub fn delete_vector_data_file(path: &str) -> Option<&str> {
+    let (data_file_path, _) = path
+        .strip_suffix(DELETE_VECTOR_EXTENSION)?
+        .strip_suffix('.')?
+        .rsplit_once('.')?;
+    Some(data_file_path)
+}
+
+/// Whether the path is the path of a delete vector rather than of a data file.
+pub fn is_delete_vector(path: &str) -> bool {
+    delete_vector_data_file(path).is_some()
+}
+
+/// Read the sorted indexes of the deleted rows of the delete vector.
+pub(crate) async fn read_delete_vector(
+    store: &dyn ObjectStore,
+    location: &Path,
+) -> Result<Vec<u64>> {
+    let bytes = store.get(location).await?.bytes().await?;
+    decode_bitmap_array(&bytes)
+}
+
+/// Write the delete vector of the sorted and distinct indexes of the deleted rows,
+/// returning the size of the file.
+pub(crate) async fn write_delete_vector(
+    store: &dyn ObjectStore,
+    location: &Path,
+    rows: &[u64],
+) -> Result<u64> {
+    let bytes = encode_bitmap_array(rows);
+    let size = bytes.len() as u64;
+    store.put(location, bytes.into()).await?;
+    Ok(size)
+}
+
+/// Read the sorted and distinct indexes of the rows deleted by any of the delete vectors.
+pub(crate) async fn read_delete_vectors(
+    store: &dyn ObjectStore,
+    locations: impl IntoIterator<Item = &Path>,
+) -> Result<Vec<u64>> {
+    let mut deleted_rows = vec![];
+    for location in locations {
+        deleted_rows.extend(read_delete_vector(store, location).await?);
+    }
+    deleted_rows.sort_unstable();
+    deleted_rows.dedup();
+    Ok(deleted_rows)
+}
+
+/// Remove the delete vectors from the files of the scan, returning them keyed by the
+/// location of their data files.
+///
+/// The file groups are left as they are without delete vectors. Otherwise their
+/// statistics are dropped, as the row counts of the data files do not account for the
+/// deleted rows.
+pub(crate) fn split_delete_vectors(
+    mut conf: FileScanConfig,
+) -> (FileScanConfig, HashMap<Path, Vec<PartitionedFile>>) {
+    let mut delete_vectors = HashMap::<Path, Vec<PartitionedFile>>::new();
+    for file in conf.file_groups.iter().flat_map(|group| group.files()) {
+        let Some(data_file) = delete_vector_data_file(file.object_meta.location.as_ref())
+            .and_then(|data_file| Path::parse(data_file).ok())
+        else {
+            continue;
+        };
+        delete_vectors
+            .entry(data_file)
+            .or_default()
+            .push(file.clone());
+    }
+    if !delete_vectors.is_empty() {
+        conf.file_groups = conf
+            .file_groups
+            .iter()
+            .map(|group| {
+                let files = group
+                    .files()
+                    .iter()
+                    .filter(|file| !is_delete_vector(file.object_meta.location.as_ref()))
+                    .cloned()
+                    .collect();
+                FileGroup::new(files)
+            })
+            .collect();
+    }
+    (conf, delete_vectors)
+}
@@ -0,0 +1,483 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Decoding of the Delta Lake deletion vectors and the filtering of the deleted rows.
+//!
+//! A deletion vector is a 64-bit roaring bitmap of the indexes of the deleted rows of a
+//! data file, stored inline in the log or in a file next to the data files, see
+//! <https://github.com/delta-io/delta/blob/master/PROTOCOL.md#deletion-vectors>. The
+//! delete vectors of the LakeSoul tables are serialized alike, see
+//! [`crate::datasource::delete_vector`].
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use arrow::array::{BooleanArray, BooleanBufferBuilder};
+use arrow::compute::filter_record_batch;
+use arrow::datatypes::SchemaRef;
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::TaskContext;
+use datafusion::execution::runtime_env::RuntimeEnv;
+use datafusion::physical_plan::execution_plan::{Boundedness, EmissionType};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::{
+    DisplayAs, DisplayFormatType, ExecutionPlan, ExecutionPlanProperties, Partitioning,
+    PlanProperties, SendableRecordBatchStream,
+};
+use futures::StreamExt;
+use lakesoul_io::helpers::resolve_file_url;
+use serde::Deserialize;
+use url::Url;
+use uuid::Uuid;
+
+/// The magic number starting a serialized deletion vector.
+const DELETION_VECTOR_MAGIC: u32 = 1681511377;
+/// The cookie of the roaring bitmaps with run containers.
+const SERIAL_COOKIE: u32 = 12347;
+/// The cookie of the roaring bitmaps without run containers.
+const SERIAL_COOKIE_NO_RUNCONTAINER: u32 = 12346;
+/// The number of containers from which the offsets are serialized with run containers.
+const NO_OFFSET_THRESHOLD: u32 = 4;
+/// The maximum cardinality of the array containers of the roaring bitmaps.
+const ARRAY_CONTAINER_MAX_CARDINALITY: u32 = 4096;
+/// The characters of the Z85 encoding, see <https://rfc.zeromq.org/spec/32/>.
+const Z85_ALPHABET: &[u8; 85] =
+    b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#";
+
+/// The descriptor of a deletion vector of a data file in the Delta log.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct DeletionVectorDescriptor {
+    /// `u` for a file with a UUID name, `p` for a file with an absolute path or `i` for
+    /// a deletion vector stored inline.
+    storage_type: String,
+    path_or_inline_dv: String,
+    /// The offset of the deletion vector in its file.
+    offset: Option<i32>,
+    size_in_bytes: i32,
+    /// The number of deleted rows.
+    cardinality: i64,
+}
+
+impl DeletionVectorDescriptor {
+    /// The id of the deletion vector, identifying a data file together with its path.
+    pub(super) fn unique_id(&self) -> String {
+        match self.offset {
+            Some(offset) => {
+                format!("{}{}@{}", self.storage_type, self.path_or_inline_dv, offset)
+            }
+            None => format!("{}{}", self.storage_type, self.path_or_inline_dv),
+        }
+    }
+
+    /// Read the sorted indexes of the deleted rows.
+    pub(super) async fn read(
+        &self,
+        runtime_env: &RuntimeEnv,
+        table_url: &Url,
+    ) -> Result<Vec<u64>> {
+        let size = usize::try_from(self.size_in_bytes).map_err(|_| {
+            invalid_deletion_vector(format!("invalid size {}", self.size_in_bytes))
+        })?;
+        let rows = if self.storage_type == "i" {
+            let bytes = decode_z85(&self.path_or_inline_dv)?;
+            if bytes.len() < size {
+                return Err(invalid_deletion_vector(format!(
+                    "inline deletion vector of {} bytes is shorter than {} bytes",
+                    bytes.len(),
+                    size
+                )));
+            }
+            decode_bitmap_array(&bytes[..size])?
+        } else {
+            let path = self.file_path()?;
+            let (store_url, location) = resolve_file_url(&path, table_url)?;
+            let store = runtime_env.object_store(&store_url)?;
+            let offset = self.offset.unwrap_or(1) as u64;
+            let bytes = store
+                .get_range(&location, offset..offset + 4 + size as u64)
+                .await?;
+            let stored_size = u32::from_be_bytes(bytes[..4].try_into().unwrap()) as usize;
+            if stored_size != size {
+                return Err(invalid_deletion_vector(format!(
+                    "{} holds a deletion vector of {} bytes, expected {} bytes",
+                    path, stored_size, size
+                )));
+            }
+            decode_bitmap_array(&bytes[4..])?
+        };
+        if rows.len() as i64 != self.cardinality {
+            return Err(invalid_deletion_vector(format!(
+                "{} rows are deleted, expected {}",
+                rows.len(),
+                self.cardinality
+            )));
+        }
+        Ok(rows)
+    }
+
+    /// The path of the file of the deletion vector, relative to the table for UUID names.
+    fn file_path(&self) -> Result<String> {
+        match self.storage_type.as_str() {
+            "u" => {
+                let dv = &self.path_or_inline_dv;
+                let Some(prefix) = dv.get(..dv.len().saturating_sub(20)) else {
+                    return Err(invalid_deletion_vector(format!("invalid path {}", dv)));
+                };
+                let uuid = Uuid::from_slice(&decode_z85(&dv[prefix.len()..])?)
+                    .map_err(|e| invalid_deletion_vector(e.to_string()))?;
+                Ok(match prefix {
+                    "" => format!("deletion_vector_{}.bin", uuid),
+                    prefix => format!("{}/deletion_vector_{}.bin", prefix, uuid),
+                })
+            }
+            "p" => Ok(self.path_or_inline_dv.clone()),
+            storage_type => Err(invalid_deletion_vector(format!(
+                "unknown storage type {}",
+                storage_type
+            ))),
+        }
+    }
+}
+
+fn invalid_deletion_vector(reason: String) -> DataFusionError {
+    DataFusionError::Execution(format!("invalid deletion vector: {}", reason))
+}
+
+/// Decode the Z85 encoded bytes.
+fn decode_z85(encoded: &str) -> Result<Vec<u8>> {
+    if encoded.len() % 5 != 0 {
+        return Err(invalid_deletion_vector(format!(
+            "Z85 encoded length {} is not a multiple of 5",
+            encoded.len()
+        )));
+    }
+    let mut bytes = Vec::with_capacity(encoded.len() / 5 * 4);
+    for chunk in encoded.as_bytes().chunks(5) {
+        let mut value = 0u64;
+        for c in chunk {
+            let Some(digit) = Z85_ALPHABET.iter().position(|a| a == c) else {
+                return Err(invalid_deletion_vector(format!(
+                    "invalid Z85 character {}",
+                    *c as char
+                )));
+            };
+            value = value * 85 + digit as u64;
+        }
+        let value = u32::try_from(value)
+            .map_err(|_| invalid_deletion_vector("Z85 value overflows".to_string()))?;
+        bytes.extend_from_slice(&value.to_be_bytes());
+    }
+    Ok(bytes)
+}
+
+/// A little endian reader of the serialized bitmaps.
+struct BitmapReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitmapReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let Some(bytes) = self.bytes.get(self.pos..self.pos + len) else {
+            return Err(invalid_deletion_vector(
+                "unexpected end of bitmap".to_string(),
+            ));
+        };
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn read_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+/// Decode the deletion vector, the magic number followed by a 64-bit roaring bitmap in
+/// the portable format.
+pub(crate) fn decode_bitmap_array(bytes: &[u8]) -> Result<Vec<u64>> {
+    let mut reader = BitmapReader { bytes, pos: 0 };
+    let magic = reader.read_u32()?;
+    if magic != DELETION_VECTOR_MAGIC {
+        return Err(invalid_deletion_vector(format!(
+            "invalid magic number {}",
+            magic
+        )));
+    }
+    let mut rows = Vec::new();
+    for _ in 0..reader.read_u64()? {
+        let high = (reader.read_u32()? as u64) << 32;
+        decode_roaring_bitmap(&mut reader, high, &mut rows)?;
+    }
+    // the bitmaps are stored in ascending order of their high bits
+    if !rows.is_sorted() {
+        rows.sort_unstable();
+    }
+    Ok(rows)
+}
+
+/// Encode the sorted and distinct indexes of the deleted rows as a deletion vector, the
+/// inverse of [`decode_bitmap_array`].
+///
+/// The containers are written as arrays or bitmaps, without run containers.
+pub(crate) fn encode_bitmap_array(rows: &[u64]) -> Vec<u8> {
+    let mut bitmaps = BTreeMap::<u32, BTreeMap<u16, Vec<u16>>>::new();
+    for row in rows {
+        bitmaps
+            .entry((row >> 32) as u32)
+            .or_default()
+            .entry((row >> 16) as u16)
+            .or_default()
+            .push(*row as u16);
+    }
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&DELETION_VECTOR_MAGIC.to_le_bytes());
+    bytes.extend_from_slice(&(bitmaps.len() as u64).to_le_bytes());
+    for (high, containers) in bitmaps {
+        bytes.extend_from_slice(&high.to_le_bytes());
+        let start = bytes.len();
+        bytes.extend_from_slice(&SERIAL_COOKIE_NO_RUNCONTAINER.to_le_bytes());
+        bytes.extend_from_slice(&(containers.len() as u32).to_le_bytes());
+        for (key, values) in &containers {
+            bytes.extend_from_slice(&key.to_le_bytes());
+            bytes.extend_from_slice(&((values.len() - 1) as u16).to_le_bytes());
+        }
+        // the offsets of the containers from the start of the roaring bitmap
+        let mut offset = bytes.len() - start + 4 * containers.len();
+        for values in containers.values() {
+            bytes.extend_from_slice(&(offset as u32).to_le_bytes());
+            offset += if values.len() as u32 <= ARRAY_CONTAINER_MAX_CARDINALITY {
+                2 * values.len()
+            } else {
+                8 * 1024
+            };
+        }
+        for values in containers.values() {
+            if values.len() as u32 <= ARRAY_CONTAINER_MAX_CARDINALITY {
+                for value in values {
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+            } else {
+                let mut words = [0u64; 1024];
+                for value in values {
+                    words[*value as usize / 64] |= 1 << (value % 64);
+                }
+                for word in words {
+                    bytes.extend_from_slice(&word.to_le_bytes());
+                }
+            }
+        }
+    }
+    bytes
+}
+
+/// Decode a 32-bit roaring bitmap, see
+/// <https://github.com/RoaringBitmap/RoaringFormatSpec>.
+fn decode_roaring_bitmap(
+    reader: &mut BitmapReader,
+    high: u64,
+    rows: &mut Vec<u64>,
+) -> Result<()> {
+    let cookie = reader.read_u32()?;
+    let (containers, run_flags, has_offsets) = if cookie & 0xFFFF == SERIAL_COOKIE {
+        let containers = (cookie >> 16) + 1;
+        let run_flags = reader.take(containers.div_ceil(8) as usize)?;
+        (
+            containers,
+            Some(run_flags),
+            containers >= NO_OFFSET_THRESHOLD,
+        )
+    } else if cookie == SERIAL_COOKIE_NO_RUNCONTAINER {
+        (reader.read_u32()?, None, true)
+    } else {
+        return Err(invalid_deletion_vector(format!(
+            "invalid roaring bitmap cookie {}",
+            cookie
+        )));
+    };
+    let mut headers = Vec::with_capacity(containers as usize);
+    for _ in 0..containers {
+        let key = reader.read_u16()? as u64;
+        let cardinality = reader.read_u16()? as u32 + 1;
+        headers.push((key, cardinality));
+    }
+    if has_offsets {
+        reader.take(4 * containers as usize)?;
+    }
+    for (i, (key, cardinality)) in headers.into_iter().enumerate() {
+        let base = high | (key << 16);
+        let is_run = run_flags.is_some_and(|flags| flags[i / 8] & (1 << (i % 8)) != 0);
+        if is_run {
+            for _ in 0..reader.read_u16()? {
+                let start = reader.read_u16()? as u64;
+                let length = reader.read_u16()? as u64;
+                rows.extend((start..=start + length).map(|low| base | low));
+            }
+        } else if cardinality <= ARRAY_CONTAINER_MAX_CARDINALITY {
+            for _ in 0..cardinality {
+                rows.push(base | reader.read_u16()? as u64);
+            }
+        } else {
+            for word_index in 0..1024u64 {
+                let mut word = reader.read_u64()?;
+                while word != 0 {
+                    let bit = word.trailing_zeros() as u64;
+                    rows.push(base | (word_index * 64 + bit));
+                    word &= word - 1;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// [`ExecutionPlan`] implementation dropping the rows of a data file deleted by its
+/// deletion vector.
+///
+/// The rows are identified by their index in the file, so the input must scan the whole
+/// file in order, without filters and limits. The partitions of the input are read one
+/// after another, as the byte ranges of a file split into partitions are ordered.
+#[derive(Debug)]
+pub(crate) struct DeletionVectorExec {
+    input: Arc<dyn ExecutionPlan>,
+    /// The sorted indexes of the deleted rows.
+    deleted_rows: Arc<Vec<u64>>,
+    properties: PlanProperties,
+}
+
+impl DeletionVectorExec {
+    pub(crate) fn new(
+        input: Arc<dyn ExecutionPlan>,
+        deleted_rows: Arc<Vec<u64>>,
+    ) -> Self {
+        let properties = PlanProperties::new(
+            input.equivalence_properties().clone(),
+            Partitioning::UnknownPartitioning(1),
+            EmissionType::Incremental,
+            Boundedness::Bounded,
+        );
+        Self {
+            input,
+            deleted_rows,
+            properties,
+        }
+    }
+}
+
+impl DisplayAs for DeletionVectorExec {
+    fn fmt_as(
+        &self,
+        _t: DisplayFormatType,
+        f: &mut std::fmt::Formatter,
+    ) -> std::fmt::Result {
+        write!(
+            f,
+            "DeletionVectorExec: deleted_rows={}",
+            self.deleted_rows.len()
+        )
+    }
+}
+
+impl ExecutionPlan for DeletionVectorExec {
+    fn name(&self) -> &str {
+        "DeletionVectorExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        &self.properties
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.input]
+    }
+
+    fn maintains_input_order(&self) -> Vec<bool> {
+        vec![true]
+    }
+
+    fn benefits_from_input_partitioning(&self) -> Vec<bool> {
+        vec![false]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        mut children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        if children.len() != 1 {
+            return Err(DataFusionError::Internal(format!(
+                "DeletionVectorExec requires exactly one child, got {}",
+                children.len()
+            )));
+        }
+        Ok(Arc::new(Self::new(
+            children.remove(0),
+            self.deleted_rows.clone(),
+        )))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        if partition != 0 {
+            return Err(DataFusionError::Internal(format!(
+                "Invalid requested partition {partition}. DeletionVectorExec requires a single input partition."
+            )));
+        }
+        let streams = (0..self.input.output_partitioning().partition_count())
+            .map(|i| self.input.execute(i, context.clone()))
+            .collect::<Result<Vec<_>>>()?;
+        let deleted_rows = self.deleted_rows.clone();
+        let mut row_index = 0u64;
+        let stream = futures::stream::iter(streams).flatten().map(
+            move |batch| -> Result<RecordBatch> {
+                let (batch, num_rows) =
+                    remove_deleted_rows(batch?, row_index, &deleted_rows)?;
+                row_index += num_rows;
+                Ok(batch)
+            },
+        );
+        Ok(Box::pin(RecordBatchStreamAdapter::new(
+            self.schema(),
+            stream,
+        )))
+    }
+}
+
+/// Remove the deleted rows from the batch starting at the row index of the file,
+/// returning the remaining rows and the number of rows of the batch.
+fn remove_deleted_rows(
+    batch: RecordBatch,
+    row_index: u64,
+    deleted_rows: &[u64],
+) -> Result<(RecordBatch, u64)> {
+    let num_rows = batch.num_rows() as u64;
+    let start = deleted_rows.partition_point(|row| *row < row_index);
+    let end = deleted_rows.partition_point(|row| *row < row_index + num_rows);
+    if start == end {
+        return Ok((batch, num_rows));
+    }
+    let mut mask = BooleanBufferBuilder::new(batch.num_rows());
+    mask.append_n(batch.num_rows(), true);
+    for row in &deleted_rows[start..end] {
+        mask.set_bit((row - row_index) as usize, false);
+    }
+    let batch = filter_record_batch(&batch, &BooleanArray::new(mask.finish(), None))?;
+    Ok((batch, num_rows))
+}
@@ -0,0 +1,254 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! The [`TableProvider`] implementation reading Delta Lake tables.
+//!
+//! The active files of a table version are resolved from its `_delta_log` instead of the
+//! LakeSoul metadata, and are read like the files of an append only LakeSoul table: each
+//! file is scanned on its own with the values of the partition columns filled in, and the
+//! scans are unioned by the same [`union_file_scans`] as the LakeSoul scans. The rows deleted by the deletion vectors of the files are filtered
+//! out by their index in the files.
+
+pub(crate) mod deletion_vector;
+mod transaction_log;
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow::datatypes::{Schema, SchemaRef};
+use async_trait::async_trait;
+use chrono::DateTime;
+use datafusion::catalog::{Session, TableProviderFactory};
+use datafusion::common::{ToDFSchema, project_schema};
+use datafusion::datasource::TableProvider;
+use datafusion::datasource::listing::{ListingTableUrl, PartitionedFile};
+#[allow(deprecated)]
+use datafusion::datasource::physical_plan::parquet::ParquetExecBuilder;
+use datafusion::datasource::physical_plan::{
+    FileGroup, FileScanConfigBuilder, ParquetSource,
+};
+use datafusion::error::Result;
+use datafusion::logical_expr::utils::conjunction;
+use datafusion::logical_expr::{
+    CreateExternalTable, Expr, TableProviderFilterPushDown, TableType,
+};
+use datafusion::physical_expr::create_physical_expr;
+use datafusion::physical_plan::ExecutionPlan;
+use lakesoul_io::constant::LAKESOUL_NULL_STRING;
+use lakesoul_io::helpers::resolve_file_url;
+use object_store::ObjectMeta;
+use url::Url;
+
+use self::deletion_vector::DeletionVectorExec;
+use self::transaction_log::{AddFile, delta_schema_to_arrow, load_snapshot};
+use crate::datasource::file_format::union_file_scans;
+
+/// Reads a version of a Delta Lake table.
+///
+/// The deletion vectors, the `timestampNtz` type and the checkpoints of the log are
+/// supported, the tables with column mapping are rejected.
+#[derive(Debug)]
+pub struct DeltaTableProvider {
+    /// The URL of the table directory.
+    table_url: Url,
+    version: i64,
+    schema: SchemaRef,
+    /// The columns of the schema absent in the data files.
+    partition_columns: Vec<String>,
+    files: Vec<AddFile>,
+}
+
+impl DeltaTableProvider {
+    /// Load the latest version of the Delta table at the location.
+    pub async fn try_new(state: &dyn Session, location: &str) -> Result<Self> {
+        Self::try_new_with_version(state, location, None).await
+    }
+
+    /// Load the version of the Delta table at the location, or the latest version if not
+    /// set.
+    pub async fn try_new_with_version(
+        state: &dyn Session,
+        location: &str,
+        version: Option<i64>,
+    ) -> Result<Self> {
+        let table_url =
+            <ListingTableUrl as AsRef<Url>>::as_ref(&ListingTableUrl::parse(location)?)
+                .clone();
+        let (store_url, table_path) = resolve_file_url(table_url.as_str(), &table_url)?;
+        let store = state.runtime_env().object_store(&store_url)?;
+        let snapshot = load_snapshot(store, &table_path, version).await?;
+        debug!(
+            "load delta table {} of version {} with {} files",
+            table_url,
+            snapshot.version,
+            snapshot.files.len()
+        );
+        Ok(Self {
+            table_url,
+            version: snapshot.version,
+            schema: Arc::new(delta_schema_to_arrow(&snapshot.metadata.schema_string)?),
+            partition_columns: snapshot.metadata.partition_columns,
+            files: snapshot.files,
+        })
+    }
+
+    /// The loaded version of the table.
+    pub fn version(&self) -> i64 {
+        self.version
+    }
+
+    /// The schema of the data files, the table schema without the partition columns.
+    fn file_schema(&self) -> SchemaRef {
+        Arc::new(Schema::new(
+            self.schema
+                .fields()
+                .iter()
+                .filter(|field| !self.partition_columns.contains(field.name()))
+                .cloned()
+                .collect::<Vec<_>>(),
+        ))
+    }
+
+    /// The values of the partition columns of the file, as filled in by
+    /// [`union_file_scans`].
+    fn partition_values(&self, file: &AddFile) -> HashMap<String, String> {
+        self.partition_columns
+            .iter()
+            .map(|column| {
+                // an empty value is the null of the partition column
+                let value = match file.partition_values.get(column) {
+                    Some(Some(value)) if !value.is_empty() => value.clone(),
+                    _ => LAKESOUL_NULL_STRING.to_string(),
+                };
+                (column.clone(), value)
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl TableProvider for DeltaTableProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let target_schema = project_schema(&self.schema, projection)?;
+        let file_schema = self.file_schema();
+        let file_projection = target_schema
+            .fields()
+            .iter()
+            .filter_map(|field| file_schema.index_of(field.name()).ok())
+            .collect::<Vec<_>>();
+        // the filters on the columns of the files are pushed down into the files without
+        // deletion vectors, whose rows are not identified by their index
+        let predicate = conjunction(
+            filters
+                .iter()
+                .filter(|filter| {
+                    filter
+                        .column_refs()
+                        .iter()
+                        .all(|column| file_schema.index_of(column.name()).is_ok())
+                })
+                .cloned(),
+        )
+        .map(|filter| {
+            create_physical_expr(
+                &filter,
+                &file_schema.clone().to_dfschema()?,
+                state.execution_props(),
+            )
+        })
+        .transpose()?;
+
+        let mut file_execs = Vec::with_capacity(self.files.len());
+        for file in &self.files {
+            let (store_url, location) = resolve_file_url(&file.path, &self.table_url)?;
+            let object_meta = ObjectMeta {
+                location,
+                last_modified: DateTime::from_timestamp_millis(file.modification_time)
+                    .unwrap_or_default(),
+                size: file.size as u64,
+                e_tag: None,
+                version: None,
+            };
+            let config = FileScanConfigBuilder::new(
+                store_url,
+                file_schema.clone(),
+                Arc::new(ParquetSource::default()),
+            )
+            .with_file_group(FileGroup::new(vec![PartitionedFile::from(object_meta)]))
+            .with_projection(Some(file_projection.clone()));
+            let scan_exec: Arc<dyn ExecutionPlan> = match &file.deletion_vector {
+                Some(deletion_vector) => {
+                    let deleted_rows = deletion_vector
+                        .read(state.runtime_env(), &self.table_url)
+                        .await?;
+                    #[allow(deprecated)]
+                    let parquet_exec = ParquetExecBuilder::new(config.build()).build();
+                    Arc::new(DeletionVectorExec::new(
+                        Arc::new(parquet_exec),
+                        Arc::new(deleted_rows),
+                    ))
+                }
+                None => {
+                    #[allow(deprecated)]
+                    let mut builder =
+                        ParquetExecBuilder::new(config.with_limit(limit).build());
+                    if let Some(predicate) = predicate.clone() {
+                        builder = builder.with_predicate(predicate);
+                    }
+                    Arc::new(builder.build())
+                }
+            };
+            file_execs.push((scan_exec, Arc::new(self.partition_values(file))));
+        }
+        union_file_scans(file_execs, target_schema)
+    }
+
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> Result<Vec<TableProviderFilterPushDown>> {
+        Ok(vec![TableProviderFilterPushDown::Inexact; filters.len()])
+    }
+}
+
+/// The [`TableProviderFactory`] of the `CREATE EXTERNAL TABLE ... STORED AS DELTA`
+/// statements, reading the latest version of the Delta table at the location.
+#[derive(Debug, Default)]
+pub struct DeltaTableProviderFactory;
+
+#[async_trait]
+impl TableProviderFactory for DeltaTableProviderFactory {
+    async fn create(
+        &self,
+        state: &dyn Session,
+        cmd: &CreateExternalTable,
+    ) -> Result<Arc<dyn TableProvider>> {
+        info!(
+            "DeltaTableProviderFactory::create: {:?}, {:?}",
+            cmd.name, cmd.location
+        );
+        Ok(Arc::new(
+            DeltaTableProvider::try_new(state, &cmd.location).await?,
+        ))
+    }
+}
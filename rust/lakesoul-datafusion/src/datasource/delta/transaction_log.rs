@@ -0,0 +1,458 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Replay of the Delta Lake transaction log into the active files of a table version, see
+//! <https://github.com/delta-io/delta/blob/master/PROTOCOL.md>.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use arrow::datatypes::{DataType, Field, Fields, Schema, TimeUnit};
+use arrow::json::ArrayWriter;
+use datafusion::error::{DataFusionError, Result};
+use futures::TryStreamExt;
+use object_store::ObjectStore;
+use object_store::path::Path;
+use parquet::arrow::ProjectionMask;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::deletion_vector::DeletionVectorDescriptor;
+
+/// The directory of the transaction log in the table directory.
+pub(super) const DELTA_LOG_DIR: &str = "_delta_log";
+
+/// The reader features of the protocol supported by the scans.
+const SUPPORTED_READER_FEATURES: &[&str] =
+    &["deletionVectors", "timestampNtz", "vacuumProtocolCheck"];
+
+/// The actions of the log replayed into the snapshot, the other columns of the
+/// checkpoints are not read.
+const REPLAYED_ACTIONS: &[&str] = &["add", "remove", "metaData", "protocol"];
+
+/// A data file added to the table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct AddFile {
+    /// The URL encoded path of the file, relative to the table unless absolute.
+    pub(super) path: String,
+    /// The values of the partition columns, `None` for nulls.
+    #[serde(default)]
+    pub(super) partition_values: HashMap<String, Option<String>>,
+    pub(super) size: i64,
+    #[serde(default)]
+    pub(super) modification_time: i64,
+    pub(super) deletion_vector: Option<DeletionVectorDescriptor>,
+}
+
+impl AddFile {
+    /// The key of the file in the snapshot.
+    fn key(&self) -> (String, Option<String>) {
+        file_key(&self.path, self.deletion_vector.as_ref())
+    }
+}
+
+/// A data file removed from the table.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RemoveFile {
+    path: String,
+    deletion_vector: Option<DeletionVectorDescriptor>,
+}
+
+/// The metadata of the table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct Metadata {
+    /// The schema of the table serialized as JSON.
+    pub(super) schema_string: String,
+    #[serde(default)]
+    pub(super) partition_columns: Vec<String>,
+    #[serde(default)]
+    pub(super) configuration: HashMap<String, Option<String>>,
+}
+
+/// The protocol versions and features required to read the table.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Protocol {
+    min_reader_version: i32,
+    reader_features: Option<Vec<String>>,
+}
+
+/// An action of a commit, holding one of the actions.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Action {
+    add: Option<AddFile>,
+    remove: Option<RemoveFile>,
+    meta_data: Option<Metadata>,
+    protocol: Option<Protocol>,
+}
+
+/// The files of the log, a commit or a part of a checkpoint of a version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LogFileKind {
+    Commit,
+    /// The part and the number of parts of the checkpoint.
+    Checkpoint(u32, u32),
+}
+
+/// Parse the name of a file of the log, `None` for the other files.
+fn parse_log_file_name(name: &str) -> Option<(i64, LogFileKind)> {
+    let (version, suffix) = name.split_at_checked(20)?;
+    if !version.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let version = version.parse().ok()?;
+    match suffix {
+        ".json" => Some((version, LogFileKind::Commit)),
+        ".checkpoint.parquet" => Some((version, LogFileKind::Checkpoint(1, 1))),
+        _ => {
+            let parts = suffix
+                .strip_prefix(".checkpoint.")?
+                .strip_suffix(".parquet")?;
+            let (part, num_parts) = parts.split_once('.')?;
+            if part.len() != 10 || num_parts.len() != 10 {
+                return None;
+            }
+            Some((
+                version,
+                LogFileKind::Checkpoint(part.parse().ok()?, num_parts.parse().ok()?),
+            ))
+        }
+    }
+}
+
+fn file_key(
+    path: &str,
+    deletion_vector: Option<&DeletionVectorDescriptor>,
+) -> (String, Option<String>) {
+    (
+        path.to_string(),
+        deletion_vector.map(DeletionVectorDescriptor::unique_id),
+    )
+}
+
+/// A version of a Delta table.
+#[derive(Debug, Clone)]
+pub(super) struct DeltaSnapshot {
+    pub(super) version: i64,
+    pub(super) metadata: Metadata,
+    /// The active data files of the version.
+    pub(super) files: Vec<AddFile>,
+}
+
+/// The state of the log replay.
+#[derive(Default)]
+struct LogReplay {
+    files: HashMap<(String, Option<String>), AddFile>,
+    metadata: Option<Metadata>,
+    protocol: Option<Protocol>,
+}
+
+impl LogReplay {
+    fn apply(&mut self, action: Action) {
+        if let Some(add) = action.add {
+            self.files.insert(add.key(), add);
+        }
+        if let Some(remove) = action.remove {
+            self.files
+                .remove(&file_key(&remove.path, remove.deletion_vector.as_ref()));
+        }
+        if let Some(metadata) = action.meta_data {
+            self.metadata = Some(metadata);
+        }
+        if let Some(protocol) = action.protocol {
+            self.protocol = Some(protocol);
+        }
+    }
+
+    fn apply_commit(&mut self, bytes: &[u8]) -> Result<()> {
+        for line in bytes.split(|b| *b == b'\n') {
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            self.apply(
+                serde_json::from_slice(line)
+                    .map_err(|e| DataFusionError::External(Box::new(e)))?,
+            );
+        }
+        Ok(())
+    }
+
+    /// Apply the actions of a checkpoint, read as JSON the same way as the commits.
+    fn apply_checkpoint(&mut self, bytes: bytes::Bytes) -> Result<()> {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(bytes)?;
+        let roots = builder
+            .schema()
+            .fields()
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| REPLAYED_ACTIONS.contains(&field.name().as_str()))
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+        let mask = ProjectionMask::roots(builder.parquet_schema(), roots);
+        for batch in builder.with_projection(mask).build()? {
+            let batch = batch?;
+            if batch.num_rows() == 0 {
+                continue;
+            }
+            let mut writer = ArrayWriter::new(Vec::new());
+            writer.write(&batch)?;
+            writer.finish()?;
+            let actions: Vec<Action> = serde_json::from_slice(&writer.into_inner())
+                .map_err(|e| DataFusionError::External(Box::new(e)))?;
+            for action in actions {
+                // the removed files of a checkpoint are only kept for the vacuum
+                self.apply(Action {
+                    remove: None,
+                    ..action
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn into_snapshot(self, version: i64) -> Result<DeltaSnapshot> {
+        let Some(metadata) = self.metadata else {
+            return Err(DataFusionError::Execution(format!(
+                "no metadata found in the delta log of version {}",
+                version
+            )));
+        };
+        if let Some(protocol) = &self.protocol {
+            check_protocol(protocol, &metadata)?;
+        }
+        let mut files = self.files.into_values().collect::<Vec<_>>();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(DeltaSnapshot {
+            version,
+            metadata,
+            files,
+        })
+    }
+}
+
+/// Check the scans support the features required to read the table.
+fn check_protocol(protocol: &Protocol, metadata: &Metadata) -> Result<()> {
+    if protocol.min_reader_version > 3 {
+        return Err(DataFusionError::NotImplemented(format!(
+            "delta reader version {} is not supported",
+            protocol.min_reader_version
+        )));
+    }
+    let column_mapping = metadata
+        .configuration
+        .get("delta.columnMapping.mode")
+        .cloned()
+        .flatten();
+    if column_mapping.as_deref().is_some_and(|mode| mode != "none") {
+        return Err(DataFusionError::NotImplemented(
+            "delta column mapping is not supported".to_string(),
+        ));
+    }
+    for feature in protocol.reader_features.iter().flatten() {
+        // without a column mapping mode the physical columns are the logical ones
+        if !SUPPORTED_READER_FEATURES.contains(&feature.as_str())
+            && feature != "columnMapping"
+        {
+            return Err(DataFusionError::NotImplemented(format!(
+                "delta reader feature {} is not supported",
+                feature
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Load the snapshot of the version of the table, or of the latest version if not set.
+///
+/// The log is replayed from the latest complete checkpoint not newer than the version.
+pub(super) async fn load_snapshot(
+    store: Arc<dyn ObjectStore>,
+    table_path: &Path,
+    version: Option<i64>,
+) -> Result<DeltaSnapshot> {
+    let log_path = table_path.child(DELTA_LOG_DIR);
+    let mut commits = BTreeMap::new();
+    // the listed parts of the checkpoints of each version
+    let mut checkpoints = BTreeMap::<i64, (u32, Vec<(u32, Path)>)>::new();
+    let mut listing = store.list(Some(&log_path));
+    while let Some(meta) = listing.try_next().await? {
+        let Some(name) = meta.location.filename() else {
+            continue;
+        };
+        match parse_log_file_name(name) {
+            Some((v, LogFileKind::Commit)) => {
+                commits.insert(v, meta.location);
+            }
+            Some((v, LogFileKind::Checkpoint(part, num_parts))) => {
+                let (_, parts) = checkpoints.entry(v).or_insert((num_parts, Vec::new()));
+                parts.push((part, meta.location));
+            }
+            None => {}
+        }
+    }
+
+    let Some(latest) = commits.keys().chain(checkpoints.keys()).max().copied() else {
+        return Err(DataFusionError::Execution(format!(
+            "no delta log found at {}",
+            log_path
+        )));
+    };
+    let version = match version {
+        Some(version) if version > latest || version < 0 => {
+            return Err(DataFusionError::Execution(format!(
+                "delta table version {} does not exist, the latest version is {}",
+                version, latest
+            )));
+        }
+        Some(version) => version,
+        None => latest,
+    };
+
+    let mut replay = LogReplay::default();
+    let checkpoint = checkpoints
+        .into_iter()
+        .rev()
+        .find(|(v, (num_parts, parts))| {
+            *v <= version && parts.len() == *num_parts as usize
+        });
+    let start = match checkpoint {
+        Some((checkpoint_version, (_, mut parts))) => {
+            parts.sort_by_key(|(part, _)| *part);
+            for (_, location) in parts {
+                replay.apply_checkpoint(store.get(&location).await?.bytes().await?)?;
+            }
+            checkpoint_version + 1
+        }
+        None => 0,
+    };
+    for v in start..=version {
+        let Some(location) = commits.get(&v) else {
+            return Err(DataFusionError::Execution(format!(
+                "commit of version {} is missing in the delta log at {}",
+                v, log_path
+            )));
+        };
+        replay.apply_commit(&store.get(location).await?.bytes().await?)?;
+    }
+    replay.into_snapshot(version)
+}
+
+/// Convert the JSON schema of a Delta table into an arrow schema.
+pub(super) fn delta_schema_to_arrow(schema_string: &str) -> Result<Schema> {
+    let schema: Value = serde_json::from_str(schema_string)
+        .map_err(|e| DataFusionError::External(Box::new(e)))?;
+    match delta_type_to_arrow(&schema)? {
+        DataType::Struct(fields) => Ok(Schema::new(fields)),
+        data_type => Err(DataFusionError::Execution(format!(
+            "delta schema must be a struct, got {}",
+            data_type
+        ))),
+    }
+}
+
+fn delta_field_to_arrow(field: &Value) -> Result<Field> {
+    let Some(name) = field.get("name").and_then(Value::as_str) else {
+        return Err(invalid_schema(field));
+    };
+    let Some(data_type) = field.get("type") else {
+        return Err(invalid_schema(field));
+    };
+    let nullable = field
+        .get("nullable")
+        .and_then(Value::as_bool)
+        .unwrap_or(true);
+    Ok(Field::new(name, delta_type_to_arrow(data_type)?, nullable))
+}
+
+fn delta_type_to_arrow(data_type: &Value) -> Result<DataType> {
+    if let Some(name) = data_type.as_str() {
+        return Ok(match name {
+            "string" => DataType::Utf8,
+            "long" => DataType::Int64,
+            "integer" => DataType::Int32,
+            "short" => DataType::Int16,
+            "byte" => DataType::Int8,
+            "float" => DataType::Float32,
+            "double" => DataType::Float64,
+            "boolean" => DataType::Boolean,
+            "binary" => DataType::Binary,
+            "date" => DataType::Date32,
+            "timestamp" => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            "timestamp_ntz" => DataType::Timestamp(TimeUnit::Microsecond, None),
+            _ => {
+                let Some((precision, scale)) = name
+                    .strip_prefix("decimal(")
+                    .and_then(|s| s.strip_suffix(')'))
+                    .and_then(|s| s.split_once(','))
+                else {
+                    return Err(invalid_schema(data_type));
+                };
+                match (precision.trim().parse(), scale.trim().parse()) {
+                    (Ok(precision), Ok(scale)) => DataType::Decimal128(precision, scale),
+                    _ => return Err(invalid_schema(data_type)),
+                }
+            }
+        });
+    }
+    match data_type.get("type").and_then(Value::as_str) {
+        Some("struct") => {
+            let Some(fields) = data_type.get("fields").and_then(Value::as_array) else {
+                return Err(invalid_schema(data_type));
+            };
+            Ok(DataType::Struct(
+                fields
+                    .iter()
+                    .map(delta_field_to_arrow)
+                    .collect::<Result<Fields>>()?,
+            ))
+        }
+        Some("array") => {
+            let Some(element_type) = data_type.get("elementType") else {
+                return Err(invalid_schema(data_type));
+            };
+            let contains_null = data_type
+                .get("containsNull")
+                .and_then(Value::as_bool)
+                .unwrap_or(true);
+            Ok(DataType::List(Arc::new(Field::new(
+                "element",
+                delta_type_to_arrow(element_type)?,
+                contains_null,
+            ))))
+        }
+        Some("map") => {
+            let (Some(key_type), Some(value_type)) =
+                (data_type.get("keyType"), data_type.get("valueType"))
+            else {
+                return Err(invalid_schema(data_type));
+            };
+            let value_contains_null = data_type
+                .get("valueContainsNull")
+                .and_then(Value::as_bool)
+                .unwrap_or(true);
+            let entries = Fields::from(vec![
+                Field::new("key", delta_type_to_arrow(key_type)?, false),
+                Field::new(
+                    "value",
+                    delta_type_to_arrow(value_type)?,
+                    value_contains_null,
+                ),
+            ]);
+            Ok(DataType::Map(
+                Arc::new(Field::new("key_value", DataType::Struct(entries), false)),
+                false,
+            ))
+        }
+        _ => Err(invalid_schema(data_type)),
+    }
+}
+
+fn invalid_schema(value: &Value) -> DataFusionError {
+    DataFusionError::NotImplemented(format!("unsupported delta schema type {}", value))
+}
@@ -0,0 +1,190 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! The [`TableProvider`] implementation reading a directory of parquet files not managed
+//! by LakeSoul, e.g. a landing zone whose files are not ingested yet.
+//!
+//! The files are resolved by listing the directory instead of from the LakeSoul metadata,
+//! and are read like the files of an append only LakeSoul table, i.e. without primary
+//! keys and cdc column: each file is scanned on its own and the scans are unioned without
+//! merge by [`LakeSoulMetaDataParquetFormat`]. The table is read-only.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use arrow::datatypes::SchemaRef;
+use async_trait::async_trait;
+use datafusion::catalog::Session;
+use datafusion::common::{ToDFSchema, project_schema};
+use datafusion::datasource::TableProvider;
+use datafusion::datasource::file_format::FileFormat;
+use datafusion::datasource::file_format::parquet::ParquetFormat;
+use datafusion::datasource::listing::{ListingTableUrl, PartitionedFile};
+use datafusion::datasource::physical_plan::{FileGroup, FileScanConfigBuilder};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::utils::conjunction;
+use datafusion::logical_expr::{Expr, TableProviderFilterPushDown, TableType};
+use datafusion::physical_expr::create_physical_expr;
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::physical_plan::empty::EmptyExec;
+use futures::TryStreamExt;
+use lakesoul_io::lakesoul_io_config::{DataFileFormat, LakeSoulIOConfigBuilder};
+use lakesoul_metadata::MetaDataClientRef;
+use object_store::ObjectMeta;
+use proto::proto::entity::TableInfo;
+
+use super::file_format::LakeSoulMetaDataParquetFormat;
+use crate::serialize::arrow_java::ArrowJavaSchema;
+
+/// Reads the parquet files under a directory as a read-only LakeSoul table without
+/// primary keys and cdc column.
+///
+/// The schema is inferred from the files when the provider is created, the files are
+/// listed again by each scan, so that the files added to the directory in the meantime
+/// are read as well.
+#[derive(Debug)]
+pub struct ExternalParquetTableProvider {
+    /// The URL of the directory.
+    table_url: ListingTableUrl,
+    schema: SchemaRef,
+    format: Arc<LakeSoulMetaDataParquetFormat>,
+}
+
+impl ExternalParquetTableProvider {
+    /// Create a provider of the parquet files under the location.
+    ///
+    /// The metadata client is optional: the external files have no statistics stored in
+    /// the metadata, so the directory is read without connecting to it.
+    pub async fn try_new(
+        state: &dyn Session,
+        client: Option<MetaDataClientRef>,
+        location: &str,
+    ) -> Result<Self> {
+        let table_url = ListingTableUrl::parse(location)?;
+        let parquet_format = ParquetFormat::new().with_force_view_types(false);
+        let store = state.runtime_env().object_store(&table_url)?;
+        let files = list_parquet_files(state, &table_url).await?;
+        if files.is_empty() {
+            return Err(DataFusionError::Plan(format!(
+                "no parquet file found under {} to infer the schema from",
+                table_url
+            )));
+        }
+        let schema = parquet_format.infer_schema(state, &store, &files).await?;
+        debug!(
+            "load external parquet table {} of {} files",
+            table_url,
+            files.len()
+        );
+
+        let table_info = Arc::new(TableInfo {
+            table_name: table_url.to_string(),
+            table_path: table_url.to_string(),
+            table_schema: serde_json::to_string::<ArrowJavaSchema>(
+                &schema.clone().into(),
+            )
+            .map_err(|e| DataFusionError::External(Box::new(e)))?,
+            table_namespace: "default".to_string(),
+            properties: "{}".to_string(),
+            partitions: ";".to_string(),
+            domain: "public".to_string(),
+            ..Default::default()
+        });
+        let conf = LakeSoulIOConfigBuilder::new()
+            .with_schema(schema.clone())
+            .with_prefix(table_url.to_string())
+            .build();
+        let format = LakeSoulMetaDataParquetFormat::new_with_client(
+            client,
+            Arc::new(parquet_format),
+            table_info,
+            conf,
+        )
+        .await
+        .map_err(|e| DataFusionError::External(Box::new(e)))?;
+        Ok(Self {
+            table_url,
+            schema,
+            format: Arc::new(format),
+        })
+    }
+}
+
+/// List the parquet files under the directory, recursively.
+async fn list_parquet_files(
+    state: &dyn Session,
+    table_url: &ListingTableUrl,
+) -> Result<Vec<ObjectMeta>> {
+    let store = state.runtime_env().object_store(table_url)?;
+    let extension = DataFileFormat::Parquet.extension();
+    let mut files = store
+        .list(Some(table_url.prefix()))
+        .try_filter(|object_meta| {
+            futures::future::ready(object_meta.location.extension() == Some(extension))
+        })
+        .try_collect::<Vec<_>>()
+        .await?;
+    files.sort_by(|a, b| a.location.cmp(&b.location));
+    Ok(files)
+}
+
+#[async_trait]
+impl TableProvider for ExternalParquetTableProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    async fn scan(
+        &self,
+        state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let files = list_parquet_files(state, &self.table_url).await?;
+        if files.is_empty() {
+            let projected_schema = project_schema(&self.schema, projection)?;
+            return Ok(Arc::new(EmptyExec::new(projected_schema)));
+        }
+        let predicate = conjunction(filters.to_vec())
+            .map(|filter| {
+                create_physical_expr(
+                    &filter,
+                    &self.schema.clone().to_dfschema()?,
+                    state.execution_props(),
+                )
+            })
+            .transpose()?;
+        let config = FileScanConfigBuilder::new(
+            self.table_url.object_store(),
+            self.schema.clone(),
+            self.format.file_source(),
+        )
+        .with_file_group(FileGroup::new(
+            files.into_iter().map(PartitionedFile::from).collect(),
+        ))
+        .with_projection(projection.cloned())
+        .with_limit(limit)
+        .build();
+        self.format
+            .create_physical_plan(state, config, predicate.as_ref())
+            .await
+    }
+
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> Result<Vec<TableProviderFilterPushDown>> {
+        // the filters prune the files and row groups by their statistics only
+        Ok(vec![TableProviderFilterPushDown::Inexact; filters.len()])
+    }
+}
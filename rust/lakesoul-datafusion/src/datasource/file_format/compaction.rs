@@ -0,0 +1,361 @@
+// SPDX-FileCopyrightText: 2023 LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! The execution plan compacting the small data files of the partitions of a LakeSoul table.
+
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, UInt64Array};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::TaskContext;
+use datafusion::logical_expr::Expr;
+use datafusion::physical_expr::EquivalenceProperties;
+use datafusion::physical_plan::execution_plan::{Boundedness, EmissionType};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::{
+    DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning, PlanProperties,
+    SendableRecordBatchStream,
+};
+use datafusion::sql::TableReference;
+use futures::StreamExt;
+use lakesoul_io::helpers::extract_hash_bucket_id;
+use lakesoul_metadata::MetaDataClientRef;
+use log::debug;
+use proto::proto::entity::{DataFileOp, TableInfo};
+use rand::distr::SampleString;
+
+use crate::catalog::{commit_compaction_batch, parse_table_info_partitions};
+use crate::datasource::delete_vector::delete_vector_data_file;
+use crate::lakesoul_table::helpers::prune_partitions;
+use crate::serialize::arrow_java::schema_from_metadata_str;
+
+use super::metadata_format::{
+    CommitHook, LakeSoulHashSinkExec, RegisteredCommitHook, file_paths_by_partition,
+    into_stored_partitioned_files, merge_files,
+};
+
+/// [`ExecutionPlan`] implementation which compacts the data files of the partitions of a
+/// table.
+///
+/// The files of each hash bucket of a partition are read in their commit order, whatever
+/// their data file format, through
+/// [`MergeParquetExec`](lakesoul_io::datasource::physical_plan::MergeParquetExec), so that
+/// the rows of the same primary keys are merged, and written into a single compacted parquet
+/// file with [`MultiPartAsyncWriter`](lakesoul_io::async_writer::MultiPartAsyncWriter). The
+/// compacted files of all partitions are committed in one transaction superseding the files
+/// they were compacted from, which fails if one of the partitions is committed meanwhile.
+/// The superseded files are kept, as they are still read by the earlier versions.
+///
+/// The hash buckets with a single file are not rewritten, their files are carried over into
+/// the compacted snapshot, and partitions with a single file per hash bucket are left as
+/// they are. The rows deleted by the delete vectors of a bucket are dropped from the
+/// compacted file. The plan outputs a single row with the number of compacted rows.
+pub struct LakeSoulCompactionExec {
+    /// The metadata client of the table.
+    client: MetaDataClientRef,
+    /// The table to compact.
+    table_info: Arc<TableInfo>,
+    /// The filters on the range partition columns selecting the partitions to compact.
+    partition_filters: Vec<Expr>,
+    /// The hook invoked after the commit, see [`Self::with_commit_hook`].
+    commit_hook: Option<RegisteredCommitHook>,
+    /// The schema of the output, the number of compacted rows.
+    schema: SchemaRef,
+    properties: PlanProperties,
+}
+
+impl Debug for LakeSoulCompactionExec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "LakeSoulCompactionExec table: {}",
+            self.table_info.table_name
+        )
+    }
+}
+
+impl LakeSoulCompactionExec {
+    /// Create a new [`LakeSoulCompactionExec`].
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The metadata client of the table
+    /// * `table_info` - The table to compact
+    /// * `partition_filter` - The filter on the range partition columns selecting the
+    ///   partitions to compact, all partitions are compacted if `None`
+    pub fn new(
+        client: MetaDataClientRef,
+        table_info: Arc<TableInfo>,
+        partition_filter: Option<Expr>,
+    ) -> Self {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "count",
+            DataType::UInt64,
+            false,
+        )]));
+        let properties = PlanProperties::new(
+            EquivalenceProperties::new(schema.clone()),
+            Partitioning::UnknownPartitioning(1),
+            EmissionType::Final,
+            Boundedness::Bounded,
+        );
+        Self {
+            client,
+            table_info,
+            partition_filters: partition_filter.into_iter().collect(),
+            commit_hook: None,
+            schema,
+            properties,
+        }
+    }
+
+    /// Invoke the hook for each compacted partition once the compacted files are committed.
+    ///
+    /// Its errors are logged, and with `fail_on_error` they also fail the compaction. The
+    /// compacted files stay committed in any case.
+    pub fn with_commit_hook(
+        self,
+        commit_hook: Arc<dyn CommitHook>,
+        fail_on_error: bool,
+    ) -> Self {
+        self.with_registered_commit_hook(RegisteredCommitHook::new(
+            commit_hook,
+            fail_on_error,
+        ))
+    }
+
+    pub(crate) fn with_registered_commit_hook(
+        mut self,
+        commit_hook: RegisteredCommitHook,
+    ) -> Self {
+        self.commit_hook = Some(commit_hook);
+        self
+    }
+
+    /// The table to compact.
+    pub fn table_info(&self) -> Arc<TableInfo> {
+        self.table_info.clone()
+    }
+
+    /// Compact the partitions selected by the filters, returning the number of compacted rows.
+    async fn compact(
+        client: MetaDataClientRef,
+        table_info: Arc<TableInfo>,
+        partition_filters: Vec<Expr>,
+        commit_hook: Option<RegisteredCommitHook>,
+        context: Arc<TaskContext>,
+    ) -> Result<u64> {
+        let (range_partitions, _) =
+            parse_table_info_partitions(&table_info.partitions)
+                .map_err(|e| DataFusionError::External(Box::new(e)))?;
+        let table_schema = schema_from_metadata_str(&table_info.table_schema);
+        let partition_cols = range_partitions
+            .iter()
+            .map(|name| {
+                let field = table_schema.field_with_name(name)?;
+                Ok((name.clone(), field.data_type().clone()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let file_schema = Arc::new(Schema::new(
+            table_schema
+                .fields()
+                .iter()
+                .filter(|field| !range_partitions.contains(field.name()))
+                .cloned()
+                .collect::<Vec<_>>(),
+        ));
+        let all_partition_info = client
+            .get_all_partition_info(&table_info.table_id)
+            .await
+            .map_err(|e| DataFusionError::External(Box::new(e)))?;
+        let partitions =
+            prune_partitions(all_partition_info, &partition_filters, &partition_cols)
+                .await
+                .map_err(|e| DataFusionError::External(Box::new(e)))?;
+
+        let compaction_id = rand::distr::Alphanumeric.sample_string(&mut rand::rng(), 16);
+        // The compacted files are always written as parquet, with the codec of the table
+        // rather than the one of the session, so that compacting the same files gives the
+        // same files whichever session runs it.
+        let write_options = Arc::new(HashMap::new());
+        let mut read_partitions = vec![];
+        let mut compacted_files = vec![];
+        let mut retained_files = vec![];
+        let mut count = 0;
+        for partition_info in partitions {
+            let file_ops = client
+                .get_data_file_ops_of_single_partition(&partition_info)
+                .await
+                .map_err(|e| DataFusionError::External(Box::new(e)))?;
+            // the compacted files are written into the directory of the partition
+            let Some(directory) = file_ops
+                .first()
+                .and_then(|file_op| file_op.path.rsplit_once('/'))
+                .map(|(directory, _)| directory.to_string())
+            else {
+                continue;
+            };
+            let mut bucket_files = BTreeMap::<Option<u32>, Vec<DataFileOp>>::new();
+            for file_op in file_ops {
+                // the delete vectors are merged with their data files
+                let data_file =
+                    delete_vector_data_file(&file_op.path).unwrap_or(&file_op.path);
+                bucket_files
+                    .entry(extract_hash_bucket_id(data_file))
+                    .or_default()
+                    .push(file_op);
+            }
+            if bucket_files.values().all(|file_ops| file_ops.len() == 1) {
+                continue;
+            }
+            let mut partition_files = Vec::with_capacity(bucket_files.len());
+            let mut partition_retained_files = vec![];
+            for (hash_bucket_id, mut file_ops) in bucket_files {
+                // the single file of a bucket has nothing to be merged with
+                if file_ops.len() == 1 {
+                    partition_retained_files.append(&mut file_ops);
+                    continue;
+                }
+                let paths = file_ops
+                    .into_iter()
+                    .map(|file_op| file_op.path)
+                    .collect::<Vec<_>>();
+                // The hash bucket id must stay the last number of the file name.
+                let file_path = match hash_bucket_id {
+                    Some(hash_bucket_id) => format!(
+                        "{}/part-{}-compacted_{:0>4}.parquet",
+                        directory, compaction_id, hash_bucket_id
+                    ),
+                    None => {
+                        format!("{}/part-{}-compacted.parquet", directory, compaction_id)
+                    }
+                };
+                debug!("compact files {:?} into {}", paths, file_path);
+                let (file_path, stats) = merge_files(
+                    paths,
+                    file_path,
+                    &partition_info.partition_desc,
+                    file_schema.clone(),
+                    table_info.clone(),
+                    write_options.clone(),
+                    &HashMap::new(),
+                    context.clone(),
+                )
+                .await?;
+                count += stats.num_rows();
+                partition_files.push((file_path, stats));
+            }
+            compacted_files
+                .push((partition_info.partition_desc.clone(), partition_files));
+            retained_files.push((
+                partition_info.partition_desc.clone(),
+                partition_retained_files,
+            ));
+            read_partitions.push(partition_info);
+        }
+        if compacted_files.is_empty() {
+            debug!("table: {} has no files to compact", table_info.table_name);
+            return Ok(0);
+        }
+
+        let table_ref = TableReference::Partial {
+            schema: table_info.table_namespace.clone().into(),
+            table: table_info.table_name.clone().into(),
+        };
+        let committed = file_paths_by_partition(&compacted_files);
+        commit_compaction_batch(
+            client,
+            &table_ref.to_string(),
+            into_stored_partitioned_files(compacted_files)?,
+            retained_files,
+            read_partitions,
+        )
+        .await
+        .map_err(|e| DataFusionError::External(Box::new(e)))?;
+        LakeSoulHashSinkExec::run_commit_hook(
+            commit_hook.as_ref(),
+            &table_ref.to_string(),
+            committed,
+        )
+        .await?;
+        Ok(count)
+    }
+}
+
+impl DisplayAs for LakeSoulCompactionExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "LakeSoulCompactionExec: table={}, partition_filters={:?}",
+            self.table_info.table_name, self.partition_filters
+        )
+    }
+}
+
+impl ExecutionPlan for LakeSoulCompactionExec {
+    fn name(&self) -> &str {
+        "LakeSoulCompactionExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        &self.properties
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        _children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(self)
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        if partition != 0 {
+            return Err(DataFusionError::Internal(format!(
+                "Invalid partition {} of LakeSoulCompactionExec with 1 partition",
+                partition
+            )));
+        }
+        let compaction = Self::compact(
+            self.client.clone(),
+            self.table_info.clone(),
+            self.partition_filters.clone(),
+            self.commit_hook.clone(),
+            context,
+        );
+        let schema = self.schema.clone();
+        let stream = futures::stream::once(async move {
+            let count = compaction.await?;
+            Ok(RecordBatch::try_new(
+                schema,
+                vec![Arc::new(UInt64Array::from(vec![count])) as ArrayRef],
+            )?)
+        })
+        .boxed();
+        Ok(Box::pin(RecordBatchStreamAdapter::new(
+            self.schema.clone(),
+            stream,
+        )))
+    }
+}
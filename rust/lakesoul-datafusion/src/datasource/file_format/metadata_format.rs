@@ -4,34 +4,59 @@
 
 //! The [`datafusion::datasource::file_format::FileFormat`] implementation for the LakeSoul Parquet format with metadata.
 
-use arrow::array::{ArrayRef, StringArray, UInt64Array};
+use arrow::array::{
+    ArrayRef, BooleanArray, ListBuilder, StringArray, StringBuilder, UInt64Array,
+};
+use arrow::compute::SortOptions;
 use arrow::record_batch::RecordBatch;
+use arrow::row::{OwnedRow, RowConverter, SortField};
 use async_trait::async_trait;
 use rand::distr::SampleString;
 use std::any::Any;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{self, Debug};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
-use arrow::datatypes::{DataType, Field, Schema, SchemaBuilder, SchemaRef};
+use arrow::datatypes::{
+    DataType, Field, FieldRef, Fields, Schema, SchemaBuilder, SchemaRef,
+};
 use datafusion::catalog::Session;
 use datafusion::common::parsers::CompressionTypeVariant;
+use datafusion::common::stats::Precision;
 use datafusion::common::{DFSchema, GetExt, Statistics, project_schema};
 use datafusion::datasource::file_format::file_compression_type::FileCompressionType;
 use datafusion::datasource::file_format::parquet::ParquetFormatFactory;
-use datafusion::datasource::listing::ListingOptions;
-use datafusion::datasource::physical_plan::FileSource;
+use datafusion::datasource::listing::PartitionedFile;
+use datafusion::datasource::listing::{ListingOptions, ListingTableUrl};
 #[allow(deprecated)]
 use datafusion::datasource::physical_plan::parquet::ParquetExecBuilder;
+use datafusion::datasource::physical_plan::{
+    FileGroup, FileScanConfigBuilder, FileSource, ParquetSource,
+};
 use datafusion::error::DataFusionError;
-use datafusion::execution::TaskContext;
+use datafusion::execution::memory_pool::MemoryConsumer;
+use datafusion::execution::object_store::ObjectStoreUrl;
+use datafusion::execution::{SessionStateBuilder, TaskContext};
 use datafusion::logical_expr::dml::InsertOp;
+use datafusion::physical_expr::expressions::Column;
+use datafusion::physical_expr::utils::{
+    collect_columns, conjunction, reassign_predicate_columns, split_conjunction,
+};
 use datafusion::physical_expr::{
-    EquivalenceProperties, LexOrdering, LexRequirement, create_physical_expr,
+    EquivalenceProperties, LexOrdering, LexRequirement, PhysicalSortExpr,
+    PhysicalSortRequirement, create_physical_expr,
 };
+use datafusion::physical_plan::coalesce_batches::CoalesceBatchesExec;
+use datafusion::physical_plan::coalesce_partitions::CoalescePartitionsExec;
+use datafusion::physical_plan::empty::EmptyExec;
 use datafusion::physical_plan::execution_plan::{Boundedness, EmissionType};
 use datafusion::physical_plan::filter::FilterExec;
+use datafusion::physical_plan::limit::LocalLimitExec;
+use datafusion::physical_plan::metrics::{
+    BaselineMetrics, Count, ExecutionPlanMetricsSet, MetricBuilder, MetricsSet, Time,
+};
 use datafusion::physical_plan::projection::ProjectionExec;
+use datafusion::physical_plan::sorts::sort::SortExec;
 use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
 use datafusion::physical_plan::union::UnionExec;
 use datafusion::physical_plan::{
@@ -48,37 +73,187 @@ use datafusion::{
     error::Result,
     physical_plan::{ExecutionPlan, PhysicalExpr},
 };
-use futures::StreamExt;
-use lakesoul_io::async_writer::{AsyncBatchWriter, MultiPartAsyncWriter};
+use futures::channel::mpsc::{self, UnboundedSender};
+use futures::stream::FuturesUnordered;
+use futures::{StreamExt, TryStreamExt};
+use lakesoul_io::async_writer::{
+    ArrowIpcAsyncWriter, AsyncBatchWriter, FileIntegrity, MultiPartAsyncWriter,
+    WriterFlushResult,
+};
+use lakesoul_io::constant::DEFAULT_PARTITION_DESC;
 use lakesoul_io::datasource::file_format::{
-    compute_project_column_indices, flatten_file_scan_config,
+    SkippedFile, coerce_schema_timestamps, collect_primary_key_equalities,
+    compute_project_column_indices, file_object_store_url, flatten_file_scan_config,
+    flatten_file_scan_config_skipping_unreadable, infer_file_schema, is_file_split,
+    limit_file_scan_configs, prune_file_scan_configs_by_bloom_filter,
+    prune_file_scan_configs_by_statistics, with_file_object_store_url,
 };
-use lakesoul_io::datasource::physical_plan::MergeParquetExec;
+use lakesoul_io::datasource::physical_plan::defatul_column::DefaultColumnExec;
+use lakesoul_io::datasource::physical_plan::merge::primary_key_ordering;
+use lakesoul_io::datasource::physical_plan::{
+    ArrowIpcScanExec, BucketedScanExec, EmptySchemaScanExec, MergeParquetExec,
+    OrcScanExec, SkippedFilesExec, TableStatisticsExec, is_arrow_ipc_file,
+    is_arrow_ipc_scan_config, is_orc_file, is_orc_scan_config,
+};
+use lakesoul_io::encryption::ScanDecryption;
+use lakesoul_io::file_name::FileNameTemplate;
 use lakesoul_io::helpers::{
-    columnar_values_to_partition_desc, columnar_values_to_sub_path, get_columnar_values,
-    partition_desc_from_file_scan_config,
+    check_normalized_column_names, columnar_values_to_partition_desc,
+    extract_hash_bucket_id, generated_columns_projection, get_columnar_values,
+    get_columns_with_nan, partition_desc_from_file_scan_config, resolve_file_url,
+    split_batch_by_columns, view_type_field,
+};
+use lakesoul_io::lakesoul_cache::cache::lru_cache::LruCache;
+use lakesoul_io::lakesoul_io_config::{
+    DataFileFormat, LakeSoulIOConfig, LakeSoulIOConfigBuilder,
+    OPTION_KEY_DATA_FILE_FORMAT, OPTION_KEY_KEEP_PARTITION_COLUMNS,
+    OPTION_KEY_MERGE_STRATEGY, OPTION_KEY_PARQUET_COMPRESSION,
+    OPTION_KEY_PARTITION_PATH_ENCODING, OPTION_KEY_SEQUENCE_COLUMN,
+    OPTION_KEY_STATISTICS_DISABLED_COLUMNS,
 };
-use lakesoul_io::lakesoul_io_config::LakeSoulIOConfig;
+use lakesoul_io::projection::ProjectionStream;
+use lakesoul_io::repartition::{BatchPartitioner, RepartitionByRangeAndHashExec};
 use lakesoul_metadata::{MetaDataClient, MetaDataClientRef};
+use object_store::path::Path;
 use object_store::{ObjectMeta, ObjectStore};
-use proto::proto::entity::TableInfo;
+use parquet::basic::Compression;
+use parquet::format::FileMetaData;
+use proto::proto::entity::{PartitionInfo, TableInfo};
+use url::Url;
 
-use crate::catalog::{commit_data, parse_table_info_partitions};
+use crate::catalog::{
+    LakeSoulTableProperty, commit_data_batch, commit_merge_batch,
+    parse_table_info_hash_bucket_num, parse_table_info_partitions,
+};
+use crate::datasource::delete_vector::{
+    delete_vector_data_file, is_delete_vector, read_delete_vectors, split_delete_vectors,
+};
+use crate::datasource::delta::deletion_vector::DeletionVectorExec;
+#[cfg(feature = "validate-pruning")]
+use crate::datasource::file_format::PruningValidationExec;
+use crate::datasource::statistics::{
+    DataFileStats, StoredFileStatistics, aggregate_table_statistics,
+};
+use crate::error::LakeSoulWriteError;
 use crate::lakesoul_table::helpers::create_io_config_builder_from_table_info;
-use log::debug;
-use tokio::sync::Mutex;
+use crate::serialize::arrow_java::{ArrowJavaSchema, schema_from_metadata_str};
+use log::{debug, warn};
+use tokio::sync::{Mutex, Semaphore, watch};
 use tokio::task::JoinHandle;
 
+/// A data file to be scanned, as resolved by [`LakeSoulMetaDataParquetFormat::plan_scan`].
+#[derive(Debug, Clone)]
+pub struct ScanFile {
+    /// The partition descriptor of the file.
+    pub partition_desc: String,
+    /// The object metadata of the file.
+    pub object_meta: ObjectMeta,
+    /// The estimated number of rows of the file.
+    pub num_rows: Precision<usize>,
+}
+
+/// The key of a data file in the statistics cache, the (path, e_tag, size) of the object.
+/// A rewritten object gets a new e_tag, so its stale statistics are never hit.
+type StatsCacheKey = (Path, String, u64);
+
+/// The sorted keys of the scanned files of a table, with the table schema and the statistics
+/// aggregated for them.
+type TableStatsCacheEntry = (Vec<String>, SchemaRef, Statistics);
+
+/// The number of tables whose statistics for the snapshot last scanned are cached.
+const TABLE_STATS_CACHE_SIZE: u64 = 256;
+
+/// The statistics inferred from the footers of the data files with the table schema they
+/// were inferred for, shared by the formats of all the providers so that planning another
+/// scan of the same files does not read the footers again.
+static FILE_STATS_CACHE: OnceLock<
+    std::sync::Mutex<LruCache<StatsCacheKey, (SchemaRef, Statistics)>>,
+> = OnceLock::new();
+
+/// The estimated statistics of the tables by table id for the snapshot last scanned, shared
+/// by the formats of all the providers so that planning another scan of the same snapshot
+/// does not query the metadata again.
+static TABLE_STATS_CACHE: OnceLock<
+    std::sync::Mutex<LruCache<String, TableStatsCacheEntry>>,
+> = OnceLock::new();
+
+/// Returns the shared cache of the file statistics, grown to hold at least `capacity` files.
+fn file_stats_cache(
+    capacity: u64,
+) -> &'static std::sync::Mutex<LruCache<StatsCacheKey, (SchemaRef, Statistics)>> {
+    let cache =
+        FILE_STATS_CACHE.get_or_init(|| std::sync::Mutex::new(LruCache::new(capacity)));
+    {
+        let mut cache = cache.lock().unwrap();
+        if cache.capacity() < capacity {
+            cache.set_capacity(capacity);
+        }
+    }
+    cache
+}
+
+/// Returns the shared cache of the table statistics.
+fn table_stats_cache() -> &'static std::sync::Mutex<LruCache<String, TableStatsCacheEntry>>
+{
+    TABLE_STATS_CACHE
+        .get_or_init(|| std::sync::Mutex::new(LruCache::new(TABLE_STATS_CACHE_SIZE)))
+}
+
+/// A hook invoked after the commits into a LakeSoul table, e.g. to mirror them into an
+/// external catalog.
+///
+/// The hook is invoked for the writes of [`LakeSoulHashSinkExec`] and
+/// [`LakeSoulStreamingSink`](super::LakeSoulStreamingSink), the compactions, the
+/// tombstones and the deletes of a [`LakeSoulTable`](crate::lakesoul_table::LakeSoulTable)
+/// registered with the hook.
+#[async_trait]
+pub trait CommitHook: Debug + Send + Sync {
+    /// Called once for each committed partition of the table.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_ref` - The reference of the table, `namespace.table_name`
+    /// * `partition_desc` - The descriptor of the committed partition
+    /// * `files` - The paths of the files committed to the partition, empty if the files of
+    ///   the partition were tombstoned
+    async fn on_commit(
+        &self,
+        table_ref: &str,
+        partition_desc: &str,
+        files: &[String],
+    ) -> Result<()>;
+}
+
+/// A [`CommitHook`] with whether its errors fail the write.
+#[derive(Debug, Clone)]
+pub(crate) struct RegisteredCommitHook {
+    hook: Arc<dyn CommitHook>,
+    fail_on_error: bool,
+}
+
+impl RegisteredCommitHook {
+    pub(crate) fn new(hook: Arc<dyn CommitHook>, fail_on_error: bool) -> Self {
+        Self {
+            hook,
+            fail_on_error,
+        }
+    }
+}
+
 /// The wrapper of the [`ParquetFormat`] with LakeSoul metadata. It is used to read and write data files while interacting with LakeSoul metadata.
 pub struct LakeSoulMetaDataParquetFormat {
     /// The inner [`ParquetFormat`].
     parquet_format: Arc<ParquetFormat>,
-    /// The metadata client.
-    client: MetaDataClientRef,
+    /// The metadata client, absent for the directories not managed by LakeSoul which are
+    /// read without stored statistics and not written.
+    client: Option<MetaDataClientRef>,
     /// The table info.
     table_info: Arc<TableInfo>,
     /// The io config.
     conf: LakeSoulIOConfig,
+    /// The hook invoked after the commits of the writes, see
+    /// [`LakeSoulMetaDataParquetFormatBuilder::with_commit_hook`].
+    commit_hook: Option<RegisteredCommitHook>,
 }
 
 impl Debug for LakeSoulMetaDataParquetFormat {
@@ -93,105 +268,343 @@ impl LakeSoulMetaDataParquetFormat {
         parquet_format: Arc<ParquetFormat>,
         table_info: Arc<TableInfo>,
         conf: LakeSoulIOConfig,
+    ) -> crate::error::Result<Self> {
+        Self::new_with_client(Some(client), parquet_format, table_info, conf).await
+    }
+
+    /// Create the format with an optional metadata client, the files are read without
+    /// their stored statistics and cannot be written without it.
+    pub(crate) async fn new_with_client(
+        client: Option<MetaDataClientRef>,
+        parquet_format: Arc<ParquetFormat>,
+        table_info: Arc<TableInfo>,
+        conf: LakeSoulIOConfig,
     ) -> crate::error::Result<Self> {
         debug!("LakeSoulMetaDataParquetFormat::new, conf: {:?}", conf);
+        // the view types set for the table override the ones of the format
+        let parquet_format = match conf.force_view_types() {
+            Some(force_view_types) => Arc::new(
+                ParquetFormat::new()
+                    .with_options(parquet_format.options().clone())
+                    .with_force_view_types(force_view_types),
+            ),
+            None => parquet_format,
+        };
         Ok(Self {
             parquet_format,
             client,
             table_info,
             conf,
+            commit_hook: None,
         })
     }
 
-    fn client(&self) -> MetaDataClientRef {
-        self.client.clone()
+    /// Create a [`LakeSoulMetaDataParquetFormatBuilder`] to set the read options of the inner
+    /// [`ParquetFormat`].
+    pub fn builder(
+        client: MetaDataClientRef,
+        table_info: Arc<TableInfo>,
+        conf: LakeSoulIOConfig,
+    ) -> LakeSoulMetaDataParquetFormatBuilder {
+        LakeSoulMetaDataParquetFormatBuilder::new(client, table_info, conf)
+    }
+
+    fn client(&self) -> Result<MetaDataClientRef> {
+        self.client.clone().ok_or_else(|| {
+            DataFusionError::Plan(format!(
+                "table {} is read-only without a metadata client",
+                self.table_info.table_name
+            ))
+        })
     }
 
     pub fn table_info(&self) -> Arc<TableInfo> {
         self.table_info.clone()
     }
 
+    /// The schema of the data files from the table schema stored in the metadata, i.e.
+    /// without the range partition columns, `None` if the metadata has no schema.
+    ///
+    /// The string columns are of the view types if the format forces them, like the
+    /// schemas inferred from the files.
+    fn file_schema_from_metadata(&self) -> Option<SchemaRef> {
+        let table_schema = &self.table_info.table_schema;
+        let schema = serde_json::from_str::<Schema>(table_schema)
+            .map(SchemaRef::new)
+            .or_else(|_| {
+                serde_json::from_str::<ArrowJavaSchema>(table_schema).map(SchemaRef::from)
+            })
+            .ok()
+            .filter(|schema| !schema.fields().is_empty())?;
+        let (range_partitions, _) =
+            parse_table_info_partitions(&self.table_info.partitions).ok()?;
+        let force_view_types = self.parquet_format.force_view_types();
+        Some(Arc::new(Schema::new_with_metadata(
+            schema
+                .fields()
+                .iter()
+                .filter(|field| !range_partitions.contains(field.name()))
+                .map(|field| match force_view_types {
+                    true => view_type_field(field),
+                    false => field.clone(),
+                })
+                .collect::<Fields>(),
+            schema.metadata().clone(),
+        )))
+    }
+
+    /// The listing options of the format with a metadata client created from the
+    /// environment, see [`Self::listing_options`].
     pub async fn default_listing_options() -> Result<ListingOptions> {
+        Self::listing_options(Arc::new(
+            MetaDataClient::from_env()
+                .await
+                .map_err(|e| DataFusionError::External(Box::new(e)))?,
+        ))
+        .await
+    }
+
+    /// The listing options of the format with the default read options, whose metadata is
+    /// read with the client, e.g. one connected with the credentials of a tenant.
+    pub async fn listing_options(client: MetaDataClientRef) -> Result<ListingOptions> {
         Ok(ListingOptions::new(Arc::new(
-            Self::new(
-                Arc::new(
-                    MetaDataClient::from_env()
-                        .await
-                        .map_err(|e| DataFusionError::External(Box::new(e)))?,
-                ),
-                Arc::new(ParquetFormat::new().with_force_view_types(false)),
+            Self::builder(
+                client,
                 Arc::new(TableInfo::default()),
                 LakeSoulIOConfig::default(),
             )
+            .build()
             .await
             .map_err(|e| DataFusionError::External(Box::new(e)))?,
         )))
     }
-}
-
-#[async_trait]
-impl FileFormat for LakeSoulMetaDataParquetFormat {
-    fn as_any(&self) -> &dyn Any {
-        self
-    }
 
-    fn get_ext(&self) -> String {
-        ParquetFormatFactory::new().get_ext()
+    /// Resolve the data files a scan would read after pruning, without building the physical plan.
+    ///
+    /// This runs the same file flattening and pruning as
+    /// [`FileFormat::create_physical_plan`], so the returned files are the scan footprint of
+    /// the query. The row counts are estimated from the file statistics.
+    pub async fn plan_scan(
+        &self,
+        state: &dyn Session,
+        conf: FileScanConfig,
+        filters: Option<&Arc<dyn PhysicalExpr>>,
+    ) -> Result<Vec<ScanFile>> {
+        let (predicate, _) = self.scan_predicates(filters);
+        let (_, target_schema) = self.scan_schemas(&conf)?;
+        let (conf, delete_vectors) = split_delete_vectors(conf);
+        let (flatten_conf, _) = self
+            .flatten_and_prune(
+                state,
+                conf,
+                predicate.as_ref(),
+                target_schema,
+                &delete_vectors,
+            )
+            .await?;
+        let mut scan_files = vec![];
+        for config in &flatten_conf {
+            let (partition_desc, _) = partition_desc_from_file_scan_config(config)?;
+            for group in &config.file_groups {
+                let num_rows = group
+                    .statistics()
+                    .map_or(Precision::Absent, |statistics| statistics.num_rows);
+                for file in group.files() {
+                    scan_files.push(ScanFile {
+                        partition_desc: partition_desc.clone(),
+                        object_meta: file.object_meta.clone(),
+                        num_rows,
+                    });
+                }
+            }
+        }
+        Ok(scan_files)
     }
 
-    fn get_ext_with_compression(
+    /// Plan a scan reading no column without filters, e.g. of `SELECT count(*)`, from the row
+    /// counts stored in the metadata at commit instead of scanning the files.
+    ///
+    /// Returns `None` if the files have to be scanned: the rows of primary key tables are
+    /// merged on read, the cdc column marks deleted rows, and the files committed without
+    /// statistics have no stored row count.
+    async fn count_only_plan(
         &self,
-        file_compression_type: &FileCompressionType,
-    ) -> Result<String> {
-        let ext = self.get_ext();
-        match file_compression_type.get_variant() {
-            CompressionTypeVariant::UNCOMPRESSED => Ok(ext),
-            _ => Err(DataFusionError::Internal(
-                "Parquet FileFormat does not support compression.".into(),
-            )),
+        conf: &FileScanConfig,
+        filters: Option<&Arc<dyn PhysicalExpr>>,
+        target_schema: &SchemaRef,
+    ) -> Result<Option<Arc<dyn ExecutionPlan>>> {
+        if filters.is_some()
+            || !target_schema.fields().is_empty()
+            || !self.conf.primary_keys_slice().is_empty()
+            || !self.conf.cdc_column().is_empty()
+            || self.conf.change_feed()?.is_some()
+            || conf
+                .file_groups
+                .iter()
+                .flat_map(|group| group.files())
+                .any(is_file_split)
+        {
+            return Ok(None);
         }
+        let files = conf.file_groups.iter().flat_map(|group| group.files());
+        let stored = match self
+            .stored_file_statistics(conf, &conf.object_store_url, files.clone())
+            .await
+        {
+            Ok(stored) => stored,
+            Err(e) => {
+                debug!("get stored file statistics failed, fallback to scan: {}", e);
+                return Ok(None);
+            }
+        };
+        let mut count = 0;
+        for file in files {
+            let key = (
+                file_object_store_url(file, &conf.object_store_url),
+                file.object_meta.location.clone(),
+            );
+            match stored.get(&key).and_then(|stored| stored.num_rows) {
+                Some(num_rows) => count += num_rows as usize,
+                None => return Ok(None),
+            }
+        }
+        let count = conf.limit.map_or(count, |limit| count.min(limit));
+        debug!(
+            "plan count only scan of {} rows from the stored row counts",
+            count
+        );
+        Ok(Some(Arc::new(EmptySchemaScanExec::new(count))))
     }
 
-    async fn infer_schema(
+    /// Estimate the statistics of the table from the statistics stored in the metadata for
+    /// the files of the scan, see [`aggregate_table_statistics`].
+    ///
+    /// The statistics are cached for the snapshot of the table, i.e. the scanned files, in a
+    /// cache shared by all the providers of the table. If the stored statistics can not be
+    /// read, the statistics are unknown.
+    async fn table_statistics(
         &self,
-        state: &dyn Session,
-        store: &Arc<dyn ObjectStore>,
-        objects: &[ObjectMeta],
-    ) -> Result<SchemaRef> {
-        self.parquet_format
-            .infer_schema(state, store, objects)
+        conf: &FileScanConfig,
+        table_schema: &SchemaRef,
+    ) -> Result<Statistics> {
+        let files = conf
+            .file_groups
+            .iter()
+            .flat_map(|group| group.files())
+            .map(|file| {
+                (
+                    file_object_store_url(file, &conf.object_store_url),
+                    file.object_meta.location.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut snapshot = files
+            .iter()
+            .map(|(object_store_url, location)| {
+                format!("{}{}", object_store_url.as_str(), location)
+            })
+            .collect::<Vec<_>>();
+        snapshot.sort_unstable();
+        if let Some((cached_snapshot, schema, statistics)) = table_stats_cache()
+            .lock()
+            .unwrap()
+            .get(&self.table_info.table_id)
+        {
+            if *cached_snapshot == snapshot && schema == table_schema {
+                return Ok(statistics.clone());
+            }
+        }
+
+        let stored = match self
+            .stored_file_statistics(
+                conf,
+                &conf.object_store_url,
+                conf.file_groups.iter().flat_map(|group| group.files()),
+            )
             .await
+        {
+            Ok(stored) => stored,
+            Err(e) => {
+                debug!(
+                    "get stored file statistics failed, statistics unknown: {}",
+                    e
+                );
+                return Ok(Statistics::new_unknown(table_schema));
+            }
+        };
+        let statistics = aggregate_table_statistics(
+            &files.iter().map(|key| stored.get(key)).collect::<Vec<_>>(),
+            table_schema,
+            self.conf.primary_keys_slice(),
+        );
+        debug!(
+            "aggregate statistics of {} files of table {}: {} rows",
+            files.len(),
+            self.table_info.table_name,
+            statistics.num_rows
+        );
+        table_stats_cache().lock().unwrap().insert(
+            self.table_info.table_id.clone(),
+            (snapshot, table_schema.clone(), statistics.clone()),
+        );
+        Ok(statistics)
     }
 
-    async fn infer_stats(
+    /// Get the statistics stored in the metadata at commit of the scanned files, keyed by
+    /// object store and location.
+    ///
+    /// Only the statistics of the partitions of the files are queried, and only the ones of
+    /// the files themselves are returned.
+    async fn stored_file_statistics<'a>(
         &self,
-        state: &dyn Session,
-        store: &Arc<dyn ObjectStore>,
-        table_schema: SchemaRef,
-        object: &ObjectMeta,
-    ) -> Result<Statistics> {
-        self.parquet_format
-            .infer_stats(state, store, table_schema, object)
+        conf: &FileScanConfig,
+        object_store_url: &ObjectStoreUrl,
+        files: impl IntoIterator<Item = &'a PartitionedFile>,
+    ) -> Result<HashMap<(ObjectStoreUrl, Path), StoredFileStatistics>> {
+        let mut partition_descs = HashSet::new();
+        let mut keys = HashSet::new();
+        for file in files {
+            partition_descs.insert(file_partition_desc(conf, file));
+            keys.insert((
+                file_object_store_url(file, object_store_url),
+                file.object_meta.location.clone(),
+            ));
+        }
+        // the files of a table not registered in the metadata have no stored statistics
+        let Some(client) = self
+            .client
+            .as_ref()
+            .filter(|_| !self.table_info.table_id.is_empty())
+        else {
+            return Ok(HashMap::new());
+        };
+        let file_statistics = client
+            .get_file_statistics_by_table_id_and_partition_list(
+                &self.table_info.table_id,
+                &partition_descs.into_iter().collect::<Vec<_>>(),
+            )
             .await
+            .map_err(|e| DataFusionError::External(Box::new(e)))?;
+        let table_url = ListingTableUrl::parse(&self.table_info.table_path)?;
+        Ok(file_statistics
+            .into_iter()
+            .filter_map(|file_statistics| {
+                let key =
+                    resolve_file_url(&file_statistics.file_path, table_url.as_ref())
+                        .ok()
+                        .filter(|key| keys.contains(key))?;
+                let stored = serde_json::from_str(&file_statistics.statistics).ok()?;
+                Some((key, stored))
+            })
+            .collect())
     }
 
-    /// Create a physical plan for the scan LakeSoul table.
-    /// The overall process is as follows:
-    /// 1. Get the predicate from the filters.
-    /// 2. Get each file metadata from the file scan config.
-    /// 3. Create [`datafusion::datasource::physical_plan::parquet::ParquetExec`] for each file.
-    /// 4. Merge the [`datafusion::datasource::physical_plan::parquet::ParquetExec`]s according to the partition columns.
-    /// 5. Apply the operations on the merged [`datafusion::physical_plan::ExecutionPlan`].
-    async fn create_physical_plan(
+    /// Split the filters into the predicate pruning the files and the predicate applied after
+    /// the merge on read.
+    fn scan_predicates(
         &self,
-        state: &dyn Session,
-        conf: FileScanConfig,
         filters: Option<&Arc<dyn PhysicalExpr>>,
-    ) -> Result<Arc<dyn ExecutionPlan>> {
-        info!(
-            "LakeSoulMetaDataParquetFormat::create_physical_plan with conf= {:?}, filters= {:?}",
-            &conf, &filters
-        );
+    ) -> (Option<Arc<dyn PhysicalExpr>>, Option<Arc<dyn PhysicalExpr>>) {
         // If enable pruning then combine the filters to build the predicate.
         // If disable pruning then set the predicate to None, thus readers
         // will not prune data based on the statistics.
@@ -200,7 +613,28 @@ impl FileFormat for LakeSoulMetaDataParquetFormat {
             .enable_pruning()
             .then(|| filters.cloned())
             .flatten();
+        // The merge on read emits the latest version of each primary key, so only the conjuncts
+        // on columns shared by all versions may prune the files, the rest is applied after the merge.
+        if self.conf.primary_keys_slice().is_empty() {
+            (predicate, None)
+        } else {
+            let stable_columns = self
+                .conf
+                .primary_keys_slice()
+                .iter()
+                .chain(self.conf.range_partitions_slice())
+                .map(String::as_str)
+                .collect::<HashSet<_>>();
+            split_merge_on_read_predicate(
+                predicate,
+                &stable_columns,
+                &self.conf.cdc_column(),
+            )
+        }
+    }
 
+    /// Compute the table schema and the projected target schema of the scan.
+    fn scan_schemas(&self, conf: &FileScanConfig) -> Result<(SchemaRef, SchemaRef)> {
         let file_schema = conf.file_schema.clone();
         let mut builder = SchemaBuilder::from(file_schema.fields());
         for field in &conf.table_partition_cols {
@@ -209,64 +643,336 @@ impl FileFormat for LakeSoulMetaDataParquetFormat {
 
         let table_schema = Arc::new(builder.finish());
 
-        let projection = conf.projection.clone();
-        let target_schema = project_schema(&table_schema, projection.as_ref())?;
+        // The output follows the order of the projection, which the plan of the scan
+        // expects, rather than the column order of the table.
+        let target_schema = project_schema(&table_schema, conf.projection.as_ref())?;
+        Ok((table_schema, target_schema))
+    }
+
+    /// The URLs of the data files of the scan whose statistics do not exclude the filters,
+    /// i.e. the files read by the scan, without reading their rows.
+    ///
+    /// Unlike the scan of a table with primary keys, the files are pruned by all the
+    /// filters, as they are matched by the rows they store instead of the merged rows,
+    /// e.g. the files storing an outdated version of a row are matched as well. A file is
+    /// matched if any of its row groups may satisfy the filters. The bloom filters only
+    /// prune the equalities on the primary keys, the lookups of other columns are matched
+    /// by the statistics alone.
+    pub async fn prune_files(
+        &self,
+        state: &dyn Session,
+        conf: FileScanConfig,
+        filters: Option<&Arc<dyn PhysicalExpr>>,
+    ) -> Result<Vec<String>> {
+        let (_, target_schema) = self.scan_schemas(&conf)?;
+        let predicate = self
+            .parquet_format
+            .enable_pruning()
+            .then(|| filters.cloned())
+            .flatten();
+        let (conf, delete_vectors) = split_delete_vectors(conf);
+        let (flatten_conf, _) = self
+            .flatten_and_prune(
+                state,
+                conf,
+                predicate.as_ref(),
+                target_schema,
+                &delete_vectors,
+            )
+            .await?;
+        let mut files = flatten_conf
+            .iter()
+            .flat_map(|config| {
+                config.file_groups.iter().flat_map(|group| {
+                    group.files().iter().map(|file| {
+                        format!(
+                            "{}{}",
+                            config.object_store_url.as_str(),
+                            file.object_meta.location
+                        )
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        files.sort_unstable();
+        files.dedup();
+        debug!(
+            "{} files of table {} match the filters {:?}",
+            files.len(),
+            self.table_info.table_name,
+            filters
+        );
+        Ok(files)
+    }
+
+    /// Plan the scan wrapped into a [`PruningValidationExec`], which compares the numbers of
+    /// rows matching the filters with and without the pruning by statistics before the
+    /// scan is executed.
+    ///
+    /// Both compared scans read all the columns, the filters are applied to their output
+    /// so that only the rows the pruning may skip are compared. They are only run when the
+    /// scan is executed, e.g. not for `EXPLAIN`.
+    #[cfg(feature = "validate-pruning")]
+    async fn plan_pruning_validation(
+        &self,
+        state: &dyn Session,
+        conf: FileScanConfig,
+        filters: &Arc<dyn PhysicalExpr>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let exec = self
+            .plan_table_scan(state, conf.clone(), Some(filters))
+            .await?;
+        let conf = FileScanConfig {
+            projection: None,
+            limit: None,
+            ..conf
+        };
+        let unpruned_format = Self {
+            parquet_format: Arc::new(
+                ParquetFormat::new()
+                    .with_options(self.parquet_format.options().clone())
+                    .with_enable_pruning(false),
+            ),
+            client: self.client.clone(),
+            table_info: self.table_info(),
+            conf: self.conf.clone(),
+            commit_hook: None,
+        };
+        let mut scans = Vec::with_capacity(2);
+        for format in [self, &unpruned_format] {
+            let scan = format
+                .plan_table_scan(state, conf.clone(), Some(filters))
+                .await?;
+            let predicate =
+                reassign_predicate_columns(filters.clone(), &scan.schema(), false)?;
+            scans
+                .push(Arc::new(FilterExec::try_new(predicate, scan)?)
+                    as Arc<dyn ExecutionPlan>);
+        }
+        let unpruned = scans.pop().unwrap();
+        let pruned = scans.pop().unwrap();
+        Ok(Arc::new(PruningValidationExec::new(
+            exec,
+            pruned,
+            unpruned,
+            self.table_info.table_name.clone(),
+            filters.clone(),
+        )))
+    }
+
+    /// Create a physical plan for the scan LakeSoul table.
+    /// The overall process is as follows:
+    /// 1. Get the predicate from the filters.
+    /// 2. Get each file metadata from the file scan config.
+    /// 3. Create [`datafusion::datasource::physical_plan::parquet::ParquetExec`] for each file,
+    ///    or [`OrcScanExec`] for each ORC file and [`ArrowIpcScanExec`] for each Arrow IPC file.
+    /// 4. Merge the [`datafusion::datasource::physical_plan::parquet::ParquetExec`]s according to the partition columns,
+    ///    or union them without merging for append only tables without primary keys and cdc column.
+    /// 5. Apply the operations on the merged [`datafusion::physical_plan::ExecutionPlan`].
+    ///
+    /// A limit of the scan skips the files of append only tables beyond the ones holding
+    /// enough rows, see [`limit_file_scan_configs`]. The rows of the other tables are only
+    /// known after the merge and the cdc filter, the limit is applied on top of them instead.
+    ///
+    /// The files of `conf` may be splits of parquet files assigned by an external scheduler,
+    /// see [`is_file_split`], so that several scans share the row groups of a large file.
+    /// Splits are only supported for append only tables, the rows of a primary key are
+    /// merged across whole files, and for the files without delete vectors.
+    async fn plan_table_scan(
+        &self,
+        state: &dyn Session,
+        conf: FileScanConfig,
+        filters: Option<&Arc<dyn PhysicalExpr>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        info!(
+            "LakeSoulMetaDataParquetFormat::plan_table_scan with conf= {:?}, filters= {:?}",
+            &conf, &filters
+        );
+        let (predicate, merge_predicate) = self.scan_predicates(filters);
+        let (table_schema, target_schema) = self.scan_schemas(&conf)?;
+        let (conf, delete_vectors) = split_delete_vectors(conf);
+        let count_exec = if delete_vectors.is_empty() {
+            self.count_only_plan(&conf, filters, &target_schema).await?
+        } else {
+            // the stored row counts include the rows deleted by the delete vectors
+            None
+        };
+        if let Some(count_exec) = count_exec {
+            if self.conf.validate_scan_schema() {
+                validate_scan_schema(&target_schema, &count_exec.schema())?;
+            }
+            return Ok(count_exec);
+        }
+        // the merged scans do not derive the statistics of the table from their inputs,
+        // the optimizer gets them from the metadata to order the joins
+        let table_statistics = if state.config_options().optimizer.join_reordering {
+            Some(self.table_statistics(&conf, &table_schema).await?)
+        } else {
+            None
+        };
 
         let merged_projection = compute_project_column_indices(
             table_schema.clone(),
             target_schema.clone(),
-            self.conf.primary_keys_slice(),
+            &self.conf.merge_columns(),
             &self.conf.cdc_column(),
         );
         let merged_schema = project_schema(&table_schema, merged_projection.as_ref())?;
 
+        let cdc_column = self.conf.cdc_column();
+        let append_only =
+            self.conf.primary_keys_slice().is_empty() && cdc_column.is_empty();
+        let splits = conf
+            .file_groups
+            .iter()
+            .flat_map(|group| group.files())
+            .filter(|file| is_file_split(file))
+            .collect::<Vec<_>>();
+        if let Some(split) = splits.first().filter(|_| !append_only) {
+            return Err(DataFusionError::NotImplemented(format!(
+                "split of file {} of table {}, splits are only supported for tables \
+                without primary keys and cdc column",
+                split.object_meta.location, self.table_info.table_name
+            )));
+        }
+        if let Some(split) = splits.iter().find(|file| {
+            is_orc_file(&file.object_meta) || is_arrow_ipc_file(&file.object_meta)
+        }) {
+            return Err(DataFusionError::NotImplemented(format!(
+                "split of file {}, only parquet files can be split",
+                split.object_meta.location
+            )));
+        }
+        // the delete vector addresses the deleted rows by their index in the whole file
+        if let Some(split) = splits
+            .iter()
+            .find(|file| delete_vectors.contains_key(&file.object_meta.location))
+        {
+            return Err(DataFusionError::NotImplemented(format!(
+                "split of file {}, files with a delete vector can not be split",
+                split.object_meta.location
+            )));
+        }
+        let limit = conf.limit;
+        let decryption =
+            ScanDecryption::try_new(&self.conf, state.runtime_env().clone())?;
+
         // files to read
-        let flatten_conf = flatten_file_scan_config(
-            state,
-            self.parquet_format.clone(),
-            conf,
-            self.conf.primary_keys_slice(),
-            &self.conf.cdc_column(),
-            self.conf.partition_schema(),
-            target_schema.clone(),
-        )
-        .await?;
+        let (flatten_conf, skipped) = self
+            .flatten_and_prune(
+                state,
+                conf,
+                predicate.as_ref(),
+                target_schema.clone(),
+                &delete_vectors,
+            )
+            .await?;
+        let flatten_conf = match limit {
+            Some(limit) if append_only && delete_vectors.is_empty() => {
+                limit_file_scan_configs(flatten_conf, limit)
+            }
+            // the files are read entirely by the merge, which is limited instead, as are
+            // the files whose rows are deleted by delete vectors
+            Some(_) => flatten_conf
+                .into_iter()
+                .map(|config| FileScanConfig {
+                    limit: None,
+                    ..config
+                })
+                .collect(),
+            None => flatten_conf,
+        };
 
         let mut inputs_map: HashMap<
             String,
-            (Arc<HashMap<String, String>>, Vec<Arc<dyn ExecutionPlan>>),
+            (
+                Arc<HashMap<String, String>>,
+                Vec<(Option<u32>, Arc<dyn ExecutionPlan>)>,
+            ),
         > = HashMap::new();
         let mut column_nullable = HashSet::<String>::new();
+        // the number of scanned files containing each column, the columns absent in some of
+        // the files are read as nulls from these files
+        let mut column_file_count = HashMap::<String, usize>::new();
 
         for config in &flatten_conf {
             let (partition_desc, partition_columnar_value) =
                 partition_desc_from_file_scan_config(config)?;
             let partition_columnar_value = Arc::new(partition_columnar_value);
+            let hash_bucket_id = scanned_file(config).and_then(|file| {
+                extract_hash_bucket_id(file.object_meta.location.as_ref())
+            });
+            // the deleted rows are identified by their index in the file, so the file is
+            // scanned entirely without the predicate
+            let delete_vectors = scanned_file(config)
+                .and_then(|file| delete_vectors.get(&file.object_meta.location));
 
-            let parquet_exec = Arc::new({
-                debug!(
-                    "create parquet exec with config= {:?}, predicate= {:?}",
-                    &config, &predicate
-                );
-                #[allow(deprecated)]
-                let mut builder = ParquetExecBuilder::new(config.clone());
-                if let Some(predicate) = predicate.clone() {
-                    builder = builder.with_predicate(predicate);
+            // ORC, Arrow IPC and parquet files of a partition are merged alike
+            let scan_exec: Arc<dyn ExecutionPlan> = if is_orc_scan_config(config) {
+                debug!("create orc exec with config= {:?}", &config);
+                Arc::new(OrcScanExec::try_new(config.clone())?)
+            } else if is_arrow_ipc_scan_config(config) {
+                debug!("create arrow ipc exec with config= {:?}", &config);
+                Arc::new(ArrowIpcScanExec::try_new(config.clone())?)
+            } else {
+                Arc::new({
+                    debug!(
+                        "create parquet exec with config= {:?}, predicate= {:?}",
+                        &config, &predicate
+                    );
+                    #[allow(deprecated)]
+                    let mut builder = ParquetExecBuilder::new(config.clone());
+                    if let Some(predicate) =
+                        predicate.clone().filter(|_| delete_vectors.is_none())
+                    {
+                        builder = builder.with_predicate(predicate);
+                    }
+                    if let Some(decryption) = &decryption {
+                        builder = builder.with_parquet_file_reader_factory(
+                            decryption.reader_factory(config)?,
+                        );
+                    }
+                    builder.build()
+                })
+            };
+            let scan_exec: Arc<dyn ExecutionPlan> = match delete_vectors {
+                Some(delete_vectors) => {
+                    // concurrent deletes may leave several delete vectors of the file
+                    let store =
+                        state.runtime_env().object_store(file_object_store_url(
+                            &delete_vectors[0],
+                            &config.object_store_url,
+                        ))?;
+                    let deleted_rows = read_delete_vectors(
+                        store.as_ref(),
+                        delete_vectors.iter().map(|file| &file.object_meta.location),
+                    )
+                    .await?;
+                    debug!(
+                        "apply {} delete vectors of {} deleted rows",
+                        delete_vectors.len(),
+                        deleted_rows.len()
+                    );
+                    Arc::new(DeletionVectorExec::new(scan_exec, Arc::new(deleted_rows)))
                 }
-                builder.build()
-            });
-            for field in parquet_exec.schema().fields().iter() {
+                None => scan_exec,
+            };
+            for field in scan_exec.schema().fields().iter() {
                 if field.is_nullable() {
                     column_nullable.insert(field.name().clone());
                 }
+                *column_file_count.entry(field.name().clone()).or_default() += 1;
             }
 
             if let Some((_, inputs)) = inputs_map.get_mut(&partition_desc) {
-                inputs.push(parquet_exec);
+                inputs.push((hash_bucket_id, scan_exec));
             } else {
                 inputs_map.insert(
                     partition_desc.clone(),
-                    (partition_columnar_value.clone(), vec![parquet_exec]),
+                    (
+                        partition_columnar_value.clone(),
+                        vec![(hash_bucket_id, scan_exec)],
+                    ),
                 );
             }
         }
@@ -279,32 +985,129 @@ impl FileFormat for LakeSoulMetaDataParquetFormat {
                     Field::new(
                         field.name(),
                         field.data_type().clone(),
-                        field.is_nullable() | column_nullable.contains(field.name()),
+                        field.is_nullable()
+                            | column_nullable.contains(field.name())
+                            | (column_file_count.get(field.name()).copied().unwrap_or(0)
+                                < flatten_conf.len()),
                     )
                 })
                 .collect::<Vec<_>>(),
         ));
 
-        let mut partitioned_exec = Vec::new();
-        for (_, (partition_columnar_values, inputs)) in inputs_map {
-            let merge_exec = Arc::new(MergeParquetExec::new_with_inputs(
+        // Files of different hash buckets never share a primary key, so when every file
+        // belongs to a known bucket each bucket can be merged on its own and exposed as
+        // one output partition.
+        let hash_bucket_num = table_hash_bucket_num(&self.table_info)?;
+        let hash_partitioned = self.conf.hash_partitioned_scan()
+            && !self.conf.primary_keys_slice().is_empty()
+            && inputs_map.values().all(|(_, inputs)| {
+                inputs.iter().all(|(hash_bucket_id, _)| {
+                    hash_bucket_id.is_some_and(|id| (id as usize) < hash_bucket_num)
+                })
+            });
+        if self.conf.hash_partitioned_scan() && !hash_partitioned {
+            debug!(
+                "hash partitioned scan is not applicable, fallback to unknown partitioning"
+            );
+        }
+
+        let merge_predicate = match merge_predicate
+            .map(|predicate| reassign_predicate_columns(predicate, &merged_schema, false))
+            .transpose()
+        {
+            Ok(merge_predicate) => merge_predicate,
+            Err(e) => {
+                debug!("predicate is not applicable after merge: {}", e);
+                None
+            }
+        };
+
+        let exec = if hash_partitioned {
+            let mut bucket_execs = vec![Vec::new(); hash_bucket_num];
+            for (_, (partition_columnar_values, inputs)) in inputs_map {
+                let mut bucket_inputs = vec![Vec::new(); hash_bucket_num];
+                for (hash_bucket_id, input) in inputs {
+                    if let Some(hash_bucket_id) = hash_bucket_id {
+                        bucket_inputs[hash_bucket_id as usize].push(input);
+                    }
+                }
+                for (hash_bucket_id, inputs) in bucket_inputs.into_iter().enumerate() {
+                    if inputs.is_empty() {
+                        continue;
+                    }
+                    bucket_execs[hash_bucket_id].push(Arc::new(
+                        MergeParquetExec::new_with_inputs(
+                            merged_schema.clone(),
+                            inputs,
+                            self.conf.clone(),
+                            partition_columnar_values.clone(),
+                        )?
+                        .with_predicate(merge_predicate.clone()),
+                    )
+                        as Arc<dyn ExecutionPlan>);
+                }
+            }
+            let buckets = bucket_execs
+                .into_iter()
+                .map(|mut execs| match execs.len() {
+                    0 => Arc::new(EmptyExec::new(merged_schema.clone()))
+                        as Arc<dyn ExecutionPlan>,
+                    1 => execs.remove(0),
+                    _ => Arc::new(CoalescePartitionsExec::new(Arc::new(UnionExec::new(
+                        execs,
+                    )))),
+                })
+                .collect::<Vec<_>>();
+            Arc::new(BucketedScanExec::try_new(
+                buckets,
+                self.conf.primary_keys_slice().to_vec(),
+            )?) as Arc<dyn ExecutionPlan>
+        } else if append_only {
+            // Without primary keys nor cdc column there is nothing to merge, every file is
+            // scanned as its own output partition with the missing columns filled in.
+            // Like the merged scan, no output ordering is guaranteed.
+            union_file_scans(
+                inputs_map.into_values().flat_map(
+                    |(partition_columnar_values, inputs)| {
+                        inputs.into_iter().map(move |(_, input)| {
+                            (input, partition_columnar_values.clone())
+                        })
+                    },
+                ),
                 merged_schema.clone(),
-                inputs,
-                self.conf.clone(),
-                partition_columnar_values.clone(),
-            )?) as Arc<dyn ExecutionPlan>;
-            partitioned_exec.push(merge_exec);
-        }
-        let exec = if partitioned_exec.len() > 1 {
-            Arc::new(UnionExec::new(partitioned_exec)) as Arc<dyn ExecutionPlan>
+            )?
         } else {
-            partitioned_exec.first().unwrap().clone()
+            let mut partitioned_exec = Vec::new();
+            for (_, (partition_columnar_values, inputs)) in inputs_map {
+                let merge_exec = Arc::new(
+                    MergeParquetExec::new_with_inputs(
+                        merged_schema.clone(),
+                        inputs.into_iter().map(|(_, input)| input).collect(),
+                        self.conf.clone(),
+                        partition_columnar_values.clone(),
+                    )?
+                    .with_predicate(merge_predicate.clone()),
+                ) as Arc<dyn ExecutionPlan>;
+                partitioned_exec.push(merge_exec);
+            }
+            match partitioned_exec.len() {
+                0 => Arc::new(EmptyExec::new(merged_schema.clone()))
+                    as Arc<dyn ExecutionPlan>,
+                1 => partitioned_exec.remove(0),
+                _ => Arc::new(UnionExec::new(partitioned_exec)) as Arc<dyn ExecutionPlan>,
+            }
         };
 
-        let cdc_column = self.conf.cdc_column();
-        let exec = if !cdc_column.is_empty() {
+        // the change feed keeps the deleted rows, so consumers can replicate the deletes
+        let exec = if !cdc_column.is_empty() && self.conf.change_feed()?.is_none() {
             let dfschema = DFSchema::try_from(exec.schema().as_ref().clone())?;
-            let cdc_filter = ident(cdc_column).not_eq(lit("delete"));
+            let delete_markers = self
+                .conf
+                .cdc_delete_markers()
+                .into_iter()
+                .map(lit)
+                .collect::<Vec<_>>();
+            let cdc_filter = ident(cdc_column).in_list(delete_markers, true);
             let expr =
                 create_physical_expr(&cdc_filter, &dfschema, state.execution_props())?;
 
@@ -312,8 +1115,21 @@ impl FileFormat for LakeSoulMetaDataParquetFormat {
         } else {
             exec
         };
+        let exec: Arc<dyn ExecutionPlan> = match limit {
+            Some(limit) if !append_only => Arc::new(LocalLimitExec::new(exec, limit)),
+            _ => exec,
+        };
 
-        if target_schema.fields().len() < merged_schema.fields().len() {
+        // The merged schema follows the table column order and may carry extra primary key
+        // or cdc columns, so reindex unless it is already exactly the target schema.
+        let is_identity_projection = target_schema.fields().len()
+            == merged_schema.fields().len()
+            && target_schema
+                .fields()
+                .iter()
+                .zip(merged_schema.fields())
+                .all(|(target, merged)| target.name() == merged.name());
+        let exec: Arc<dyn ExecutionPlan> = if !is_identity_projection {
             let mut projection_expr = vec![];
             for field in target_schema.fields() {
                 projection_expr.push((
@@ -324,16 +1140,407 @@ impl FileFormat for LakeSoulMetaDataParquetFormat {
                     field.name().clone(),
                 ));
             }
-            Ok(Arc::new(ProjectionExec::try_new(projection_expr, exec)?))
+            Arc::new(ProjectionExec::try_new(projection_expr, exec)?)
         } else {
-            Ok(exec)
-        }
-    }
+            exec
+        };
 
-    /// Create a physical plan for the write LakeSoul table.
-    /// The overall process is as follows:
-    /// 1. Check if the insert operation is overwrite.
-    /// 2. Create a [`LakeSoulHashSinkExec`] for the input plan.
+        // coalesced above the projection, so that the batches of the merge and the cdc
+        // filter are coalesced as well
+        let exec: Arc<dyn ExecutionPlan> = if self.conf.coalesce_scan_batches() {
+            let batch_size = self.conf.coalesce_scan_batch_size()?;
+            Arc::new(CoalesceBatchesExec::new(exec, batch_size))
+        } else {
+            exec
+        };
+
+        let exec: Arc<dyn ExecutionPlan> = match table_statistics {
+            Some(mut statistics) if statistics.num_rows != Precision::Absent => {
+                let projection = target_schema
+                    .fields()
+                    .iter()
+                    .map(|field| table_schema.index_of(field.name()))
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                statistics = statistics.project(Some(&projection));
+                if let Some(limit) = limit {
+                    statistics.num_rows = statistics.num_rows.map(|rows| rows.min(limit));
+                }
+                Arc::new(TableStatisticsExec::try_new(exec, statistics)?)
+            }
+            _ => exec,
+        };
+        let exec: Arc<dyn ExecutionPlan> = match skipped {
+            Some((skipped_files, skipped_rows)) => {
+                Arc::new(SkippedFilesExec::new(exec, skipped_files, skipped_rows))
+            }
+            None => exec,
+        };
+        // the projection of the merged schema back to the target schema is easy to get
+        // wrong, e.g. for the cdc column, so the output is checked before being returned
+        if self.conf.validate_scan_schema() {
+            validate_scan_schema(&target_schema, &exec.schema())?;
+        }
+        Ok(exec)
+    }
+
+    /// Flatten the file scan config into one config per file, skipping the pruned files and
+    /// row groups.
+    ///
+    /// The files that can not be opened are skipped as well if
+    /// [`LakeSoulIOConfig::skip_unreadable_files`], the numbers of the skipped files and of
+    /// their approximate rows are returned if any.
+    async fn flatten_and_prune(
+        &self,
+        state: &dyn Session,
+        conf: FileScanConfig,
+        predicate: Option<&Arc<dyn PhysicalExpr>>,
+        target_schema: SchemaRef,
+        delete_vectors: &HashMap<Path, Vec<PartitionedFile>>,
+    ) -> Result<(Vec<FileScanConfig>, Option<(usize, usize)>)> {
+        // the skipped files are looked up in the partitions of the scan
+        let scan_conf = conf.clone();
+        let (flatten_conf, skipped_files, footers) =
+            flatten_file_scan_config_skipping_unreadable(
+                state,
+                self.parquet_format.clone(),
+                conf,
+                &self.conf.merge_columns(),
+                &self.conf.cdc_column(),
+                self.conf.partition_schema(),
+                target_schema,
+                self.conf.meta_fetch_concurrency()?,
+                self.conf.coerce_timestamp_unit()?,
+                self.conf.skip_unreadable_files(),
+            )
+            .await?;
+        let skipped = if skipped_files.is_empty() {
+            None
+        } else {
+            let skipped_rows =
+                self.warn_skipped_files(&scan_conf, &skipped_files).await?;
+            Some((skipped_files.len(), skipped_rows))
+        };
+        let Some(predicate) = predicate else {
+            return Ok((flatten_conf, skipped));
+        };
+        // the rows of the files with a delete vector are identified by their index in the
+        // file, so either the whole file is pruned or all its row groups are read
+        let unpruned = flatten_conf
+            .iter()
+            .filter_map(|config| {
+                let location = &scanned_file(config)?.object_meta.location;
+                delete_vectors
+                    .contains_key(location)
+                    .then(|| (location.clone(), config.clone()))
+            })
+            .collect::<HashMap<_, _>>();
+        // the row groups are skipped before the merge, the predicate only refers to the
+        // columns shared by all versions of a row
+        let flatten_conf = prune_file_scan_configs_by_statistics(
+            state,
+            flatten_conf,
+            predicate,
+            &footers,
+            self.parquet_format.metadata_size_hint(),
+            self.conf.meta_fetch_concurrency()?,
+        )
+        .await?;
+        // point lookups on the primary keys skip the files whose bloom filters miss the key,
+        // only read from the tables writing them
+        let flatten_conf = if self.conf.primary_key_bloom_filter()
+            && self.parquet_format.options().global.bloom_filter_on_read
+        {
+            let equalities =
+                collect_primary_key_equalities(predicate, self.conf.primary_keys_slice());
+            prune_file_scan_configs_by_bloom_filter(state, flatten_conf, &equalities)
+                .await?
+        } else {
+            flatten_conf
+        };
+        let flatten_conf = flatten_conf
+            .into_iter()
+            .map(|config| {
+                scanned_file(&config)
+                    .and_then(|file| unpruned.get(&file.object_meta.location))
+                    .cloned()
+                    .unwrap_or(config)
+            })
+            .collect();
+        Ok((flatten_conf, skipped))
+    }
+
+    /// Log the files skipped by the scan as they could not be opened, and return the
+    /// approximate number of rows lost, from the row counts stored in the metadata at commit.
+    ///
+    /// The files committed without statistics count no rows, as do all files if the stored
+    /// statistics can not be read.
+    async fn warn_skipped_files(
+        &self,
+        conf: &FileScanConfig,
+        skipped_files: &[SkippedFile],
+    ) -> Result<usize> {
+        let object_store_url = &conf.object_store_url;
+        let stored = match self
+            .stored_file_statistics(
+                conf,
+                object_store_url,
+                skipped_files.iter().map(|skipped| &skipped.file),
+            )
+            .await
+        {
+            Ok(stored) => stored,
+            Err(e) => {
+                debug!(
+                    "get stored file statistics failed, skipped rows unknown: {}",
+                    e
+                );
+                HashMap::new()
+            }
+        };
+        let mut skipped_rows = 0;
+        for SkippedFile { file, error } in skipped_files {
+            let key = (
+                file_object_store_url(file, object_store_url),
+                file.object_meta.location.clone(),
+            );
+            let num_rows = stored
+                .get(&key)
+                .and_then(|stored| stored.num_rows)
+                .map(|num_rows| num_rows as usize);
+            skipped_rows += num_rows.unwrap_or(0);
+            warn!(
+                "skip unreadable file {}{} of table {} with {} rows: {}",
+                key.0.as_str(),
+                key.1,
+                self.table_info.table_name,
+                num_rows.map_or("unknown".to_string(), |num_rows| num_rows.to_string()),
+                error
+            );
+        }
+        warn!(
+            "scan of table {} skipped {} unreadable files, about {} rows lost",
+            self.table_info.table_name,
+            skipped_files.len(),
+            skipped_rows
+        );
+        Ok(skipped_rows)
+    }
+}
+
+/// Builder of a [`LakeSoulMetaDataParquetFormat`] with the read options of its inner
+/// [`ParquetFormat`].
+///
+/// The defaults are the ones of [`LakeSoulMetaDataParquetFormat::default_listing_options`],
+/// i.e. strings and binaries are read as `Utf8`/`Binary` instead of their view types, with
+/// statistics pruning and the page index enabled.
+pub struct LakeSoulMetaDataParquetFormatBuilder {
+    client: MetaDataClientRef,
+    table_info: Arc<TableInfo>,
+    conf: LakeSoulIOConfig,
+    parquet_format: ParquetFormat,
+    commit_hook: Option<RegisteredCommitHook>,
+}
+
+impl LakeSoulMetaDataParquetFormatBuilder {
+    /// Create a builder of the format of the table with the default read options.
+    pub fn new(
+        client: MetaDataClientRef,
+        table_info: Arc<TableInfo>,
+        conf: LakeSoulIOConfig,
+    ) -> Self {
+        Self {
+            client,
+            table_info,
+            conf,
+            parquet_format: ParquetFormat::new().with_force_view_types(false),
+            commit_hook: None,
+        }
+    }
+
+    /// Read the string and binary columns as `Utf8View`/`BinaryView` instead of `Utf8`/`Binary`.
+    pub fn with_force_view_types(mut self, force_view_types: bool) -> Self {
+        self.parquet_format = self.parquet_format.with_force_view_types(force_view_types);
+        self
+    }
+
+    /// Prune the row groups and files of a scan by their statistics.
+    pub fn with_enable_pruning(mut self, enable_pruning: bool) -> Self {
+        self.parquet_format = self.parquet_format.with_enable_pruning(enable_pruning);
+        self
+    }
+
+    /// Read the page index of the parquet files to prune their pages.
+    pub fn with_enable_page_index(mut self, enable_page_index: bool) -> Self {
+        let mut options = self.parquet_format.options().clone();
+        options.global.enable_page_index = enable_page_index;
+        self.parquet_format = self.parquet_format.with_options(options);
+        self
+    }
+
+    /// Invoke the hook after the commit of each write through the format, see
+    /// [`LakeSoulHashSinkExec::with_commit_hook`].
+    pub fn with_commit_hook(
+        mut self,
+        commit_hook: Arc<dyn CommitHook>,
+        fail_on_error: bool,
+    ) -> Self {
+        self.commit_hook = Some(RegisteredCommitHook {
+            hook: commit_hook,
+            fail_on_error,
+        });
+        self
+    }
+
+    /// Build the [`LakeSoulMetaDataParquetFormat`].
+    pub async fn build(self) -> crate::error::Result<LakeSoulMetaDataParquetFormat> {
+        let mut format = LakeSoulMetaDataParquetFormat::new(
+            self.client,
+            Arc::new(self.parquet_format),
+            self.table_info,
+            self.conf,
+        )
+        .await?;
+        format.commit_hook = self.commit_hook;
+        Ok(format)
+    }
+}
+
+#[async_trait]
+impl FileFormat for LakeSoulMetaDataParquetFormat {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn get_ext(&self) -> String {
+        ParquetFormatFactory::new().get_ext()
+    }
+
+    fn get_ext_with_compression(
+        &self,
+        file_compression_type: &FileCompressionType,
+    ) -> Result<String> {
+        let ext = self.get_ext();
+        match file_compression_type.get_variant() {
+            CompressionTypeVariant::UNCOMPRESSED => Ok(ext),
+            _ => Err(DataFusionError::Internal(
+                "Parquet FileFormat does not support file level compression (e.g. gzip of the whole file), \
+                 set the parquet internal codec with the parquet_compression io config option instead."
+                    .into(),
+            )),
+        }
+    }
+
+    async fn infer_schema(
+        &self,
+        state: &dyn Session,
+        store: &Arc<dyn ObjectStore>,
+        objects: &[ObjectMeta],
+    ) -> Result<SchemaRef> {
+        let coerce_timestamp_unit = self.conf.coerce_timestamp_unit()?;
+        let schema = match (self.file_schema_from_metadata(), coerce_timestamp_unit) {
+            // the schema of the table is authoritative, so no file is opened
+            (Some(schema), Some(unit)) => {
+                Arc::new(coerce_schema_timestamps(&schema, unit))
+            }
+            (Some(schema), None) => schema,
+            // the files are merged once their timestamps are in the same unit
+            (None, Some(unit)) => {
+                let schemas = futures::stream::iter(objects)
+                    .map(|object| {
+                        let format = self.parquet_format.as_ref();
+                        infer_file_schema(state, format, store, object)
+                    })
+                    .buffered(state.config_options().execution.meta_fetch_concurrency)
+                    .try_collect::<Vec<_>>()
+                    .await?;
+                Arc::new(Schema::try_merge(
+                    schemas
+                        .iter()
+                        .map(|schema| coerce_schema_timestamps(schema, unit)),
+                )?)
+            }
+            (None, None) => {
+                self.parquet_format
+                    .infer_schema(state, store, objects)
+                    .await?
+            }
+        };
+        check_normalized_column_names(&schema)?;
+        Ok(schema)
+    }
+
+    async fn infer_stats(
+        &self,
+        state: &dyn Session,
+        store: &Arc<dyn ObjectStore>,
+        table_schema: SchemaRef,
+        object: &ObjectMeta,
+    ) -> Result<Statistics> {
+        if is_orc_file(object) || is_arrow_ipc_file(object) {
+            return Ok(Statistics::new_unknown(&table_schema));
+        }
+        // objects without e_tag can not be told apart from a rewritten object of the same size
+        let stats_cache_size = self.conf.stats_cache_size()?;
+        let cache_key = object
+            .e_tag
+            .as_ref()
+            .filter(|_| stats_cache_size > 0)
+            .map(|e_tag| (object.location.clone(), e_tag.clone(), object.size));
+        let stats_cache = file_stats_cache(stats_cache_size);
+        if let Some(key) = &cache_key {
+            let mut cache = stats_cache.lock().unwrap();
+            if let Some((schema, statistics)) = cache.get(key) {
+                if *schema == table_schema {
+                    return Ok(statistics.clone());
+                }
+            }
+        }
+        let statistics = self
+            .parquet_format
+            .infer_stats(state, store, table_schema.clone(), object)
+            .await?;
+        if let Some(key) = cache_key {
+            stats_cache
+                .lock()
+                .unwrap()
+                .insert(key, (table_schema, statistics.clone()));
+        }
+        Ok(statistics)
+    }
+
+    /// Create a physical plan for the scan LakeSoul table, see
+    /// [`LakeSoulMetaDataParquetFormat::plan_table_scan`].
+    ///
+    /// With the `validate-pruning` feature, the pruning of the scans with filters is
+    /// validated when they are executed, see
+    /// [`LakeSoulMetaDataParquetFormat::plan_pruning_validation`].
+    ///
+    /// The output follows the order of the projection, unless
+    /// [`LakeSoulIOConfig::output_table_column_order`] reindexes it to the column order of
+    /// the table.
+    async fn create_physical_plan(
+        &self,
+        state: &dyn Session,
+        conf: FileScanConfig,
+        filters: Option<&Arc<dyn PhysicalExpr>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let projection = conf
+            .projection
+            .clone()
+            .filter(|_| self.conf.output_table_column_order());
+        #[cfg(feature = "validate-pruning")]
+        if let Some(filters) = filters.filter(|_| self.parquet_format.enable_pruning()) {
+            let exec = self.plan_pruning_validation(state, conf, filters).await?;
+            return table_column_order_plan(exec, projection.as_deref());
+        }
+        let exec = self.plan_table_scan(state, conf, filters).await?;
+        table_column_order_plan(exec, projection.as_deref())
+    }
+
+    /// Create a physical plan for the write LakeSoul table.
+    /// The overall process is as follows:
+    /// 1. Check if the insert operation is overwrite.
+    /// 2. Create a [`LakeSoulHashSinkExec`] for the input plan.
     /// 3. Return the physical plan.
     async fn create_writer_physical_plan(
         &self,
@@ -341,6 +1548,27 @@ impl FileFormat for LakeSoulMetaDataParquetFormat {
         _state: &dyn Session,
         conf: FileSinkConfig,
         order_requirements: Option<LexRequirement>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        self.create_sink_exec(input, conf, order_requirements, self.commit_hook.as_ref())
+            .await
+    }
+
+    fn file_source(&self) -> Arc<dyn FileSource> {
+        self.parquet_format
+            .file_source()
+            .with_statistics(Statistics::default())
+    }
+}
+
+impl LakeSoulMetaDataParquetFormat {
+    /// Create the [`LakeSoulHashSinkExec`] of a write, invoking the commit hook after its
+    /// commit, see [`FileFormat::create_writer_physical_plan`].
+    pub(crate) async fn create_sink_exec(
+        &self,
+        input: Arc<dyn ExecutionPlan>,
+        conf: FileSinkConfig,
+        order_requirements: Option<LexRequirement>,
+        commit_hook: Option<&RegisteredCommitHook>,
     ) -> Result<Arc<dyn ExecutionPlan>> {
         if conf.insert_op == InsertOp::Overwrite {
             return Err(DataFusionError::NotImplemented(
@@ -348,21 +1576,525 @@ impl FileFormat for LakeSoulMetaDataParquetFormat {
             ));
         }
 
-        Ok(Arc::new(
-            LakeSoulHashSinkExec::new(
+        let cluster_by_primary_keys = (self.conf.cluster_by_primary_keys()
+            && !self.conf.primary_keys_slice().is_empty())
+        .then(|| self.conf.primary_key_sort_options());
+        let input = match cluster_by_primary_keys {
+            Some(options) => cluster_by_primary_keys_plan(
                 input,
-                order_requirements,
-                self.table_info(),
-                self.client(),
+                self.conf.primary_keys_slice(),
+                options,
+                table_hash_bucket_num(&self.table_info)?,
+            )?,
+            None => input,
+        };
+
+        let mut sink_exec = LakeSoulHashSinkExec::new(
+            input,
+            order_requirements,
+            self.table_info(),
+            self.client()?,
+        )
+        .await?
+        .with_rolling_file_limits(
+            self.conf.max_file_size_option(),
+            self.conf.max_file_rows_option()?,
+        )
+        .with_max_buffered_bytes(self.conf.max_buffered_bytes_option()?)
+        .with_max_row_group_size(Some(self.conf.max_row_group_size()?))
+        .with_max_concurrent_writers(self.conf.max_concurrent_writers()?)
+        .with_merge_on_write(self.conf.merge_on_write())
+        .with_merge_operators(self.conf.merge_operators().clone())
+        .with_commit_per_partition(self.conf.commit_per_partition())
+        .with_commit_version_check(self.conf.commit_version_check())
+        .await?
+        .with_partitioned_output(self.conf.partitioned_sink())
+        .with_cluster_by_primary_keys(cluster_by_primary_keys);
+        if let Some(write_id) = self.conf.write_id() {
+            sink_exec = sink_exec.with_write_id(write_id);
+        }
+        if let Some(template) = self.conf.file_name_template() {
+            sink_exec = sink_exec.with_file_name_template(template);
+        }
+        if let Some(compression) = self.conf.option(OPTION_KEY_PARQUET_COMPRESSION) {
+            sink_exec =
+                sink_exec.with_write_option(OPTION_KEY_PARQUET_COMPRESSION, compression);
+        }
+        if let Some(format) = self.conf.option(OPTION_KEY_DATA_FILE_FORMAT) {
+            sink_exec = sink_exec.with_write_option(OPTION_KEY_DATA_FILE_FORMAT, format);
+        }
+        if let Some(encoding) = self.conf.option(OPTION_KEY_PARTITION_PATH_ENCODING) {
+            sink_exec =
+                sink_exec.with_write_option(OPTION_KEY_PARTITION_PATH_ENCODING, encoding);
+        }
+        if let Some(commit_hook) = commit_hook {
+            sink_exec = sink_exec
+                .with_commit_hook(commit_hook.hook.clone(), commit_hook.fail_on_error);
+        }
+        if let Some(keep) = self.conf.option(OPTION_KEY_KEEP_PARTITION_COLUMNS) {
+            sink_exec =
+                sink_exec.with_write_option(OPTION_KEY_KEEP_PARTITION_COLUMNS, keep);
+        }
+        // the merge on write merges the versions of the keys like the scans
+        for key in [OPTION_KEY_MERGE_STRATEGY, OPTION_KEY_SEQUENCE_COLUMN] {
+            if let Some(value) = self.conf.option(key) {
+                sink_exec = sink_exec.with_write_option(key, value);
+            }
+        }
+        Ok(Arc::new(sink_exec) as _)
+    }
+}
+
+/// Split the predicate of a merge on read scan into the conjuncts that only reference the stable
+/// columns, i.e. primary keys and range partitions whose values are the same for all versions of
+/// a row, and the conjuncts on the other columns to apply after the merge. Conjuncts on the cdc
+/// column are left to the cdc filter.
+fn split_merge_on_read_predicate(
+    predicate: Option<Arc<dyn PhysicalExpr>>,
+    stable_columns: &HashSet<&str>,
+    cdc_column: &str,
+) -> (Option<Arc<dyn PhysicalExpr>>, Option<Arc<dyn PhysicalExpr>>) {
+    let Some(predicate) = predicate else {
+        return (None, None);
+    };
+    let mut scan_conjuncts = vec![];
+    let mut merge_conjuncts = vec![];
+    for conjunct in split_conjunction(&predicate) {
+        let columns = collect_columns(conjunct);
+        if columns
+            .iter()
+            .all(|column| stable_columns.contains(column.name()))
+        {
+            scan_conjuncts.push(conjunct.clone());
+        } else if columns.iter().all(|column| column.name() != cdc_column) {
+            merge_conjuncts.push(conjunct.clone());
+        }
+    }
+    let into_predicate = |conjuncts: Vec<Arc<dyn PhysicalExpr>>| {
+        (!conjuncts.is_empty()).then(|| conjunction(conjuncts))
+    };
+    (
+        into_predicate(scan_conjuncts),
+        into_predicate(merge_conjuncts),
+    )
+}
+
+/// Partition the written rows into the hash buckets of their primary keys and sort each
+/// bucket by the keys, so that the sink writes a single sorted file per bucket and range
+/// partition, see [`LakeSoulHashSinkExec::with_cluster_by_primary_keys`].
+///
+/// The rows are bucketed with the hash of LakeSoul like in the sink, as the hash
+/// partitioning of DataFusion would spread each bucket over all the partitions.
+fn cluster_by_primary_keys_plan(
+    input: Arc<dyn ExecutionPlan>,
+    primary_keys: &[String],
+    options: SortOptions,
+    hash_bucket_num: usize,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    let schema = input.schema();
+    let hash_exprs = primary_keys
+        .iter()
+        .map(|pk| {
+            Ok(Arc::new(Column::new_with_schema(pk, &schema)?) as Arc<dyn PhysicalExpr>)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let Some(ordering) = primary_key_ordering(&schema, primary_keys, options) else {
+        return Ok(input);
+    };
+    // the repartition requires its input partitions ordered by the keys
+    let sorted =
+        Arc::new(SortExec::new(ordering.clone(), input).with_preserve_partitioning(true));
+    let repartition = Arc::new(RepartitionByRangeAndHashExec::try_new(
+        sorted,
+        vec![],
+        Partitioning::Hash(hash_exprs, hash_bucket_num),
+    )?);
+    Ok(Arc::new(
+        SortExec::new(ordering, repartition).with_preserve_partitioning(true),
+    ))
+}
+
+/// The number of hash buckets of the table from its properties, 1 if not set.
+fn table_hash_bucket_num(table_info: &TableInfo) -> Result<usize> {
+    parse_table_info_hash_bucket_num(&table_info.properties)
+        .map_err(|e| DataFusionError::External(Box::new(e)))
+}
+
+/// The descriptor of the partition of a scanned file, as built for the files of a partition
+/// by [`partition_desc_from_file_scan_config`].
+fn file_partition_desc(conf: &FileScanConfig, file: &PartitionedFile) -> String {
+    if conf.table_partition_cols.is_empty() {
+        return DEFAULT_PARTITION_DESC.to_string();
+    }
+    conf.table_partition_cols
+        .iter()
+        .zip(&file.partition_values)
+        .map(|(col, value)| format!("{}={}", col.name(), value))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Reindex the output of a finished scan plan, which follows the order of the projection,
+/// to the column order of the table.
+///
+/// The plan is returned as is without a projection or if the projection is already in the
+/// column order of the table.
+fn table_column_order_plan(
+    exec: Arc<dyn ExecutionPlan>,
+    projection: Option<&[usize]>,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    let Some(projection) = projection.filter(|p| !p.is_sorted()) else {
+        return Ok(exec);
+    };
+    let schema = exec.schema();
+    let mut positions = (0..projection.len()).collect::<Vec<_>>();
+    positions.sort_by_key(|&i| projection[i]);
+    let projection_expr = positions
+        .into_iter()
+        .map(|i| {
+            let name = schema.field(i).name();
+            (
+                Arc::new(Column::new(name, i)) as Arc<dyn PhysicalExpr>,
+                name.clone(),
             )
-            .await?,
-        ) as _)
+        })
+        .collect::<Vec<_>>();
+    Ok(Arc::new(ProjectionExec::try_new(projection_expr, exec)?))
+}
+
+/// The file scanned by the config, flattened to a single file.
+fn scanned_file(config: &FileScanConfig) -> Option<&PartitionedFile> {
+    config
+        .file_groups
+        .first()
+        .and_then(|group| group.files().first())
+}
+
+/// Check that the output schema of a scan plan has exactly the names and types of the
+/// columns of the target schema, in the same order, the nullability may differ.
+///
+/// The error lists every column which differs.
+pub(crate) fn validate_scan_schema(
+    target_schema: &Schema,
+    output_schema: &Schema,
+) -> Result<()> {
+    let describe = |field: Option<&FieldRef>| match field {
+        Some(field) => format!("{}: {}", field.name(), field.data_type()),
+        None => "none".to_string(),
+    };
+    let column_num = target_schema
+        .fields()
+        .len()
+        .max(output_schema.fields().len());
+    let diff = (0..column_num)
+        .filter_map(|i| {
+            let target = target_schema.fields().get(i);
+            let output = output_schema.fields().get(i);
+            let matched = match (target, output) {
+                (Some(target), Some(output)) => {
+                    target.name() == output.name()
+                        && target.data_type() == output.data_type()
+                }
+                _ => false,
+            };
+            (!matched).then(|| {
+                format!(
+                    "column {}: expected {}, found {}",
+                    i,
+                    describe(target),
+                    describe(output)
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+    if diff.is_empty() {
+        Ok(())
+    } else {
+        Err(DataFusionError::Internal(format!(
+            "the output schema of the scan differs from the projected schema: {}",
+            diff.join("; ")
+        )))
     }
+}
 
-    fn file_source(&self) -> Arc<dyn FileSource> {
-        self.parquet_format
-            .file_source()
-            .with_statistics(Statistics::default())
+/// Delete the data files which are never committed, ignoring the failed deletes.
+pub(super) async fn delete_data_files(
+    context: &TaskContext,
+    table_info: &TableInfo,
+    file_paths: Vec<String>,
+) {
+    for file_path in file_paths {
+        let deleted = async {
+            let (object_store_url, location) =
+                resolve_file_url(&file_path, &table_url(table_info)?)?;
+            context
+                .runtime_env()
+                .object_store(&object_store_url)?
+                .delete(&location)
+                .await
+                .map_err(DataFusionError::ObjectStore)
+        };
+        if let Err(e) = deleted.await {
+            debug!("failed to delete uncommitted file {}: {}", file_path, e);
+        }
+    }
+}
+
+/// Parse the format of the written data files from the write options, parquet if unset.
+pub(super) fn parse_data_file_format(
+    write_options: &HashMap<String, String>,
+) -> Result<DataFileFormat> {
+    Ok(write_options
+        .get(OPTION_KEY_DATA_FILE_FORMAT)
+        .map(|format| format.parse::<DataFileFormat>())
+        .transpose()?
+        .unwrap_or_default())
+}
+
+/// Create the writer of a data file of the format, writing the last file of the config.
+pub(super) async fn create_writer(
+    data_file_format: DataFileFormat,
+    config: &mut LakeSoulIOConfig,
+    context: Arc<TaskContext>,
+) -> Result<Box<dyn AsyncBatchWriter + Send>> {
+    Ok(match data_file_format {
+        DataFileFormat::Parquet => {
+            Box::new(MultiPartAsyncWriter::try_new_with_context(config, context).await?)
+        }
+        DataFileFormat::ArrowIpc => {
+            Box::new(ArrowIpcAsyncWriter::try_new_with_context(config, context).await?)
+        }
+    })
+}
+
+/// Check the columns of a written batch against the table schema, reordering them into
+/// the order of the table schema if they only differ in their order, so that the files
+/// store their columns in the order of the table whatever the order of the producer.
+///
+/// The batch may leave out nullable table columns, which are read as nulls from its
+/// files, but each of its columns must be a column of the table with the same type.
+pub(super) fn conform_batch_to_table_schema(
+    batch: RecordBatch,
+    table_schema: &Schema,
+) -> Result<RecordBatch> {
+    let schema = batch.schema();
+    let mut table_indices = Vec::with_capacity(schema.fields().len());
+    for (idx, field) in schema.fields().iter().enumerate() {
+        let Ok(table_idx) = table_schema.index_of(field.name()) else {
+            return Err(LakeSoulWriteError::SchemaMismatch {
+                column: field.name().clone(),
+                reason: "is not a column of the table".to_string(),
+            }
+            .into());
+        };
+        let table_field = table_schema.field(table_idx);
+        let compatible = match (field.data_type(), table_field.data_type()) {
+            // the time zones of the timestamps are uniformed by the writers
+            (
+                DataType::Timestamp(unit, Some(_)),
+                DataType::Timestamp(table_unit, Some(_)),
+            ) => unit == table_unit,
+            (data_type, table_data_type) => {
+                DFSchema::datatype_is_logically_equal(data_type, table_data_type)
+            }
+        };
+        if !compatible {
+            return Err(LakeSoulWriteError::SchemaMismatch {
+                column: field.name().clone(),
+                reason: format!(
+                    "has type {}, but the table column has type {}",
+                    field.data_type(),
+                    table_field.data_type()
+                ),
+            }
+            .into());
+        }
+        table_indices.push((table_idx, idx));
+    }
+    if let Some(missing) = table_schema
+        .fields()
+        .iter()
+        .find(|field| !field.is_nullable() && schema.index_of(field.name()).is_err())
+    {
+        return Err(LakeSoulWriteError::SchemaMismatch {
+            column: missing.name().clone(),
+            reason: "is missing, but the table column is not nullable".to_string(),
+        }
+        .into());
+    }
+    if table_indices.is_sorted() {
+        return Ok(batch);
+    }
+    table_indices.sort_unstable();
+    let projection = table_indices
+        .into_iter()
+        .map(|(_, idx)| idx)
+        .collect::<Vec<_>>();
+    Ok(batch.project(&projection)?)
+}
+
+/// Compute the generated columns of the written batches from their SQL expressions, see
+/// [`LakeSoulIOConfigBuilder::with_generated_column`].
+///
+/// The columns are evaluated like a projection. If a range partition column is generated,
+/// the batches are split by the values of the range partition columns, so that the rows of
+/// each batch still belong to a single range partition.
+fn generate_columns(
+    input: SendableRecordBatchStream,
+    generated_columns: &HashMap<String, String>,
+    table_schema: &Schema,
+    range_partitions: &[String],
+    partition: usize,
+) -> Result<SendableRecordBatchStream> {
+    let (schema, exprs) =
+        generated_columns_projection(generated_columns, &input.schema(), table_schema)?;
+    let projected = ProjectionStream::new(
+        schema.clone(),
+        exprs,
+        input,
+        BaselineMetrics::new(&ExecutionPlanMetricsSet::new(), partition),
+    );
+    let split_columns = if range_partitions
+        .iter()
+        .any(|column| generated_columns.contains_key(column))
+    {
+        range_partitions.to_vec()
+    } else {
+        vec![]
+    };
+    let batches = projected.flat_map(move |batch| {
+        let batches = match batch
+            .and_then(|batch| split_batch_by_columns(&batch, &split_columns))
+        {
+            Ok(batches) => batches.into_iter().map(Ok).collect(),
+            Err(e) => vec![Err(e)],
+        };
+        futures::stream::iter(batches)
+    });
+    Ok(Box::pin(RecordBatchStreamAdapter::new(schema, batches)))
+}
+
+/// Parse the path of the table, against which the relative paths of its data files are
+/// resolved, see [`resolve_file_url`].
+fn table_url(table_info: &TableInfo) -> Result<Url> {
+    Url::parse(&table_info.table_path).map_err(|e| {
+        LakeSoulWriteError::InvalidPath {
+            path: table_info.table_path.clone(),
+            source: Box::new(e),
+        }
+        .into()
+    })
+}
+
+/// The writer of a range partition in [`LakeSoulHashSinkExec`].
+struct PartitionWriter {
+    /// The writer of the current file, a parquet or an Arrow IPC writer.
+    writer: Box<dyn AsyncBatchWriter + Send>,
+    /// The schema of the batches written into the current file.
+    schema: SchemaRef,
+    /// The absolute path of the current file.
+    file_path: String,
+    /// The number of rows written into the current file.
+    num_rows: u64,
+    /// The columns written without statistics, as they contain NaN values.
+    statistics_disabled_columns: HashSet<String>,
+    /// The index of the current file in the partition.
+    file_index: usize,
+    /// The statistics of the current file.
+    stats: DataFileStats,
+}
+
+/// The metrics of writing an input partition of [`LakeSoulHashSinkExec`], shown by
+/// `EXPLAIN ANALYZE` next to the `commit_time` of the whole write.
+#[derive(Debug, Clone)]
+struct SinkMetrics {
+    /// The number of rows written from the input partition.
+    output_rows: Count,
+    /// The number of bytes of the closed files.
+    bytes_written: Count,
+    /// The number of files created.
+    files_created: Count,
+}
+
+impl SinkMetrics {
+    fn new(metrics: &ExecutionPlanMetricsSet, partition: usize) -> Self {
+        Self {
+            output_rows: MetricBuilder::new(metrics).output_rows(partition),
+            bytes_written: MetricBuilder::new(metrics)
+                .counter("bytes_written", partition),
+            files_created: MetricBuilder::new(metrics)
+                .counter("files_created", partition),
+        }
+    }
+}
+
+/// Verifies that the rows written into each range partition follow the sort order of
+/// [`LakeSoulHashSinkExec`].
+///
+/// Only the sort key of the last written row of each partition is kept in memory.
+struct SortOrderChecker {
+    /// The required sort order of the input.
+    requirement: LexRequirement,
+    /// The converter of the sort keys into comparable rows.
+    converter: RowConverter,
+    /// The sort key of the last written row, keyed by partition desc.
+    last_rows: HashMap<String, OwnedRow>,
+}
+
+impl SortOrderChecker {
+    fn try_new(requirement: LexRequirement, schema: &Schema) -> Result<Self> {
+        let sort_fields = requirement
+            .iter()
+            .map(|requirement| {
+                Ok(SortField::new_with_options(
+                    requirement.expr.data_type(schema)?,
+                    requirement.options.unwrap_or_default(),
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            requirement,
+            converter: RowConverter::new(sort_fields)?,
+            last_rows: HashMap::new(),
+        })
+    }
+
+    /// Check that the batch is sorted and does not start before the last row written into the partition.
+    fn check(&mut self, partition_desc: &str, batch: &RecordBatch) -> Result<()> {
+        if batch.num_rows() == 0 {
+            return Ok(());
+        }
+        let sort_columns = self
+            .requirement
+            .iter()
+            .map(|requirement| {
+                requirement
+                    .expr
+                    .evaluate(batch)?
+                    .into_array(batch.num_rows())
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let rows = self.converter.convert_columns(&sort_columns)?;
+        let mut prev = self.last_rows.get(partition_desc).map(|row| row.row());
+        for row in rows.iter() {
+            if prev.is_some_and(|prev| prev > row) {
+                return Err(DataFusionError::Execution(format!(
+                    "Input of LakeSoulHashSinkExec is not sorted by [{}] in partition {}",
+                    self.requirement
+                        .iter()
+                        .map(|requirement| requirement.expr.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    partition_desc
+                )));
+            }
+            prev = Some(row);
+        }
+        self.last_rows.insert(
+            partition_desc.to_string(),
+            rows.row(rows.num_rows() - 1).owned(),
+        );
+        Ok(())
     }
 }
 
@@ -375,8 +2107,19 @@ pub struct LakeSoulHashSinkExec {
     sink_schema: SchemaRef,
 
     /// Optional required sort order for output data.
+    ///
+    /// The sort itself is done by the `SortExec` the optimizer inserts below the sink for
+    /// [`ExecutionPlan::required_input_ordering`], which buffers the rows of each input
+    /// partition within the memory pool of the session and spills sorted runs to disk once
+    /// its reservation cannot grow. The sink only verifies that the rows of each written file
+    /// arrive in this order and fails the write otherwise, unless it sorts its input
+    /// itself, see [`Self::with_sort_in_sink`].
     sort_order: Option<LexRequirement>,
 
+    /// Whether the sink sorts each input partition by the sort order itself instead of
+    /// requiring the sorted input, see [`Self::with_sort_in_sink`].
+    sort_in_sink: bool,
+
     /// The table info of LakeSoul table.
     table_info: Arc<TableInfo>,
 
@@ -386,6 +2129,80 @@ pub struct LakeSoulHashSinkExec {
     /// The range partitions.
     range_partitions: Arc<Vec<String>>,
 
+    /// The primary keys.
+    primary_keys: Arc<Vec<String>>,
+
+    /// The number of hash buckets the rows are written into by the hash of their primary
+    /// keys.
+    hash_bucket_num: usize,
+
+    /// The size in bytes after which a file is closed and a new one is started.
+    max_file_size: Option<u64>,
+
+    /// The number of rows after which a file is closed and a new one is started.
+    max_file_rows: Option<u64>,
+
+    /// The bytes buffered by the open writers of an input partition after which the file of
+    /// the writer buffering the most is closed and a new one is started.
+    max_buffered_bytes: Option<u64>,
+
+    /// The maximum number of rows per row group of the written files, the default of the
+    /// io config if unset.
+    max_row_group_size: Option<usize>,
+
+    /// The io config options of the written files.
+    write_options: Arc<HashMap<String, String>>,
+
+    /// The maximum number of input partitions written concurrently, each one holding its own
+    /// open writers. The other input partitions wait for a permit before being pulled.
+    max_concurrent_writers: usize,
+
+    /// The id embedded in the names of the written files, random for each execution if unset.
+    write_id: Option<String>,
+
+    /// The template of the names of the written files, see
+    /// [`Self::with_file_name_template`].
+    file_name_template: Option<String>,
+
+    /// Whether the written files are merged with the committed files of their hash buckets
+    /// before the commit, see [`Self::with_merge_on_write`].
+    merge_on_write: bool,
+
+    /// The merge operators of the columns applied by the merge on write, see
+    /// [`Self::with_merge_operators`].
+    merge_operators: Arc<HashMap<String, String>>,
+
+    /// Whether the written partitions are committed independently, see
+    /// [`Self::with_commit_per_partition`].
+    commit_per_partition: bool,
+
+    /// The versions of the partitions of the table when the write was planned, the commit
+    /// fails if one of the written partitions was committed since, see
+    /// [`Self::with_commit_version_check`].
+    planned_versions: Option<Arc<HashMap<String, i32>>>,
+
+    /// Whether each input partition is written and committed by its own output partition,
+    /// see [`Self::with_partitioned_output`].
+    partitioned_output: bool,
+
+    /// The options of the primary key order of each input partition if the input is
+    /// clustered by the primary keys, see [`Self::with_cluster_by_primary_keys`].
+    cluster_by_primary_keys: Option<SortOptions>,
+
+    /// The options of the order of the rows sorted within the sink, by the null ordering
+    /// of the table, see [`Self::with_sort_in_sink`].
+    sort_options: SortOptions,
+
+    /// The hook invoked after the commit, see [`Self::with_commit_hook`].
+    commit_hook: Option<RegisteredCommitHook>,
+
+    /// Whether the output reports the progress of the write before its result, see
+    /// [`Self::with_progress_events`].
+    progress_events: bool,
+
+    /// The metrics of the write, see [`SinkMetrics`].
+    metrics: ExecutionPlanMetricsSet,
+
     /// The properties of the plan.
     properties: PlanProperties,
 }
@@ -404,27 +2221,320 @@ impl LakeSoulHashSinkExec {
         table_info: Arc<TableInfo>,
         metadata_client: MetaDataClientRef,
     ) -> Result<Self> {
-        let (range_partitions, _) = parse_table_info_partitions(&table_info.partitions)
-            .map_err(|_| {
-            DataFusionError::External("parse table_info.partitions failed".into())
-        })?;
+        let (range_partitions, primary_keys) =
+            parse_table_info_partitions(&table_info.partitions).map_err(|_| {
+                DataFusionError::External("parse table_info.partitions failed".into())
+            })?;
         let range_partitions = Arc::new(range_partitions);
+        let hash_bucket_num = table_hash_bucket_num(&table_info)?;
+        let nulls_first =
+            serde_json::from_str::<LakeSoulTableProperty>(&table_info.properties)
+                .map_err(|e| DataFusionError::External(Box::new(e)))?
+                .nulls_first
+                .unwrap_or(true);
+        let sort_options = SortOptions {
+            descending: false,
+            nulls_first,
+        };
+        // the sort order without options orders the nulls like the table
+        let sort_order = sort_order.map(|sort_order| {
+            LexRequirement::new(
+                sort_order
+                    .into_iter()
+                    .map(|requirement| PhysicalSortRequirement {
+                        options: requirement.options.or(Some(sort_options)),
+                        ..requirement
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        });
+        let properties = Self::compute_properties(&input, false);
         Ok(Self {
             input,
             sink_schema: make_sink_schema(),
             sort_order,
+            sort_in_sink: false,
             table_info,
             metadata_client,
             range_partitions,
-            properties: PlanProperties::new(
-                EquivalenceProperties::new(make_sink_schema()),
-                Partitioning::UnknownPartitioning(1),
-                EmissionType::Incremental,
-                Boundedness::Bounded,
-            ),
+            primary_keys: Arc::new(primary_keys),
+            hash_bucket_num,
+            max_file_size: None,
+            max_file_rows: None,
+            max_buffered_bytes: None,
+            max_row_group_size: None,
+            write_options: Default::default(),
+            max_concurrent_writers: std::thread::available_parallelism()
+                .map_or(1, |n| n.get()),
+            write_id: None,
+            file_name_template: None,
+            merge_on_write: false,
+            merge_operators: Default::default(),
+            commit_per_partition: false,
+            planned_versions: None,
+            partitioned_output: false,
+            cluster_by_primary_keys: None,
+            sort_options,
+            commit_hook: None,
+            progress_events: false,
+            metrics: ExecutionPlanMetricsSet::new(),
+            properties,
         })
     }
 
+    /// The properties of the sink, with one output partition per input partition if the
+    /// output is partitioned.
+    fn compute_properties(
+        input: &Arc<dyn ExecutionPlan>,
+        partitioned_output: bool,
+    ) -> PlanProperties {
+        let partition_count = if partitioned_output {
+            input.output_partitioning().partition_count()
+        } else {
+            1
+        };
+        PlanProperties::new(
+            EquivalenceProperties::new(make_sink_schema()),
+            Partitioning::UnknownPartitioning(partition_count),
+            EmissionType::Incremental,
+            Boundedness::Bounded,
+        )
+    }
+
+    /// Roll over to a new file once the written file reaches the size in bytes or the number of rows.
+    pub fn with_rolling_file_limits(
+        mut self,
+        max_file_size: Option<u64>,
+        max_file_rows: Option<u64>,
+    ) -> Self {
+        self.max_file_size = max_file_size;
+        self.max_file_rows = max_file_rows;
+        self
+    }
+
+    /// Bound the bytes buffered in memory by the open writers of each input partition.
+    ///
+    /// When the writers of all range partitions written by an input partition buffer more than
+    /// `max_buffered_bytes`, the file of the largest writer is closed and its range partition
+    /// continues in a new file, so that a wide fan-out of partitions does not buffer them all.
+    ///
+    /// The buffered bytes are reserved from the memory pool of the session regardless of
+    /// this limit, and the largest files are flushed the same way while the pool cannot
+    /// grant them.
+    pub fn with_max_buffered_bytes(mut self, max_buffered_bytes: Option<u64>) -> Self {
+        self.max_buffered_bytes = max_buffered_bytes;
+        self
+    }
+
+    /// Set the maximum number of rows per row group of the written files, see
+    /// [`LakeSoulIOConfigBuilder::with_max_row_group_size`].
+    pub fn with_max_row_group_size(mut self, max_row_group_size: Option<usize>) -> Self {
+        self.max_row_group_size = max_row_group_size;
+        self
+    }
+
+    /// Limit the number of input partitions written concurrently (defaults to the number of CPUs).
+    ///
+    /// The bytes buffered by the running partitions are still reserved from the memory pool
+    /// of the session, see [`Self::with_max_buffered_bytes`].
+    pub fn with_max_concurrent_writers(mut self, max_concurrent_writers: usize) -> Self {
+        self.max_concurrent_writers = max_concurrent_writers.max(1);
+        self
+    }
+
+    /// Set an io config option of the written files, e.g. the parquet compression codec.
+    pub fn with_write_option(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        Arc::make_mut(&mut self.write_options).insert(key.into(), value.into());
+        self
+    }
+
+    /// Use a stable id in the names of the written files instead of a random one.
+    ///
+    /// The files of a write are named after the write id, the input partition and the range
+    /// partition, so a retry with the same id overwrites the uncommitted files of the failed
+    /// attempt instead of writing new ones next to them.
+    pub fn with_write_id(mut self, write_id: impl Into<String>) -> Self {
+        self.write_id = Some(write_id.into());
+        self
+    }
+
+    /// Name the written files by a template instead of `part-{write_id}_{bucket}`.
+    ///
+    /// The placeholders `{write_id}`, `{partition}` (the input partition), `{bucket}` (the
+    /// hash bucket) and `{seq}` (the index of the file rolled by the input partition into
+    /// its range partition and bucket) are replaced when each file is opened, and the
+    /// extension of the data file format is appended. The template is validated when the
+    /// write starts, so that no two files of a write get the same name, see
+    /// [`FileNameTemplate::try_new`]. The merged files of [`Self::with_merge_on_write`] keep
+    /// their own names.
+    pub fn with_file_name_template(mut self, template: impl Into<String>) -> Self {
+        self.file_name_template = Some(template.into());
+        self
+    }
+
+    /// The id embedded in the names of the written files, if set.
+    pub fn write_id(&self) -> Option<&str> {
+        self.write_id.as_deref()
+    }
+
+    /// Merge the written rows into the committed files at write time, for primary key tables.
+    ///
+    /// The written files of each hash bucket are merged on the primary keys with the files
+    /// committed to the bucket before, the written rows superseding the committed rows of the
+    /// same keys. The merged files are committed in place of the files of their buckets,
+    /// the buckets without written rows are left as they are, so that reads of the table
+    /// have no merge on read left to do.
+    pub fn with_merge_on_write(mut self, merge_on_write: bool) -> Self {
+        self.merge_on_write = merge_on_write;
+        self
+    }
+
+    /// The merge operators of the columns by column name, applied by
+    /// [`Self::with_merge_on_write`] like the merge on read of the scans.
+    pub fn with_merge_operators(
+        mut self,
+        merge_operators: HashMap<String, String>,
+    ) -> Self {
+        self.merge_operators = Arc::new(merge_operators);
+        self
+    }
+
+    /// Commit each written partition on its own instead of all of them in one transaction.
+    ///
+    /// A failed partition commit no longer fails the whole write: the other partitions stay
+    /// committed, and the `msg` column of the output holds a JSON object mapping each partition
+    /// descriptor to its status, e.g. `{"date=2024-01-01":{"success":true,"rows":42}}` or
+    /// `{"success":false,"error":"..."}`, so that a retry can target the failed partitions.
+    /// The `success` column is false if any partition failed and `count` only counts the
+    /// rows of the committed partitions.
+    pub fn with_commit_per_partition(mut self, commit_per_partition: bool) -> Self {
+        self.commit_per_partition = commit_per_partition;
+        self
+    }
+
+    /// Fail the commit if one of the written partitions was committed since the write was
+    /// planned.
+    ///
+    /// The versions of the partitions of the table are read when the check is enabled, i.e.
+    /// when the write is planned, and are checked by the transaction committing the written
+    /// partitions. If one of them changed, e.g. by a concurrent write or compaction of the
+    /// same partition, the commit fails with [`LakeSoulWriteError::CommitConflict`] instead
+    /// of committing on top of the concurrent commit, and the written files are left
+    /// uncommitted.
+    ///
+    /// The conflict is retriable, see [`LakeSoulWriteError::is_retriable`]: the caller
+    /// replans the write against the new versions of the table and runs it again with the
+    /// same write id, see [`Self::with_write_id`], so that the retry overwrites the
+    /// uncommitted files of the failed attempt. With [`Self::with_commit_per_partition`]
+    /// only the conflicting partitions fail and have to be written again.
+    pub async fn with_commit_version_check(
+        mut self,
+        commit_version_check: bool,
+    ) -> Result<Self> {
+        self.planned_versions = match commit_version_check {
+            true => Some(Arc::new(
+                self.metadata_client
+                    .get_all_partition_info(&self.table_info.table_id)
+                    .await
+                    .map_err(LakeSoulWriteError::MetaData)?
+                    .into_iter()
+                    .map(|p| (p.partition_desc, p.version))
+                    .collect(),
+            )),
+            false => None,
+        };
+        Ok(self)
+    }
+
+    /// Write and commit each input partition in its own output partition instead of all of
+    /// them in output partition 0.
+    ///
+    /// The input is then required to be hash partitioned on the primary keys, or kept in its
+    /// partitioning for tables without primary keys, so that the optimizer no longer funnels
+    /// it through a single partition. Each output partition commits the files of its input
+    /// partition in its own transaction and reports them in its own output row, a failed
+    /// partition leaves the others committed. Not supported with merge on write, as the
+    /// merges of a hash bucket written by several partitions would race each other.
+    pub fn with_partitioned_output(mut self, partitioned_output: bool) -> Self {
+        self.partitioned_output = partitioned_output;
+        self.properties = Self::compute_properties(&self.input, partitioned_output);
+        self
+    }
+
+    /// Require the input to be partitioned into the hash buckets of the primary keys and
+    /// sorted by the keys instead of funneled through a single partition.
+    ///
+    /// Each input partition is written by its own writers, so that each written file holds
+    /// the rows of one hash bucket sorted by the keys, ordered by `sort_options`.
+    /// The clustered files make the merge on read cheaper, as the keys of a file overlap
+    /// few other files. [`LakeSoulMetaDataParquetFormat`] plans the repartition and the
+    /// sort of the input, see [`LakeSoulIOConfig::cluster_by_primary_keys`]. The sort order
+    /// of the sink takes precedence over the order of the primary keys. Ignored for tables
+    /// without primary keys.
+    pub fn with_cluster_by_primary_keys(
+        mut self,
+        sort_options: Option<SortOptions>,
+    ) -> Self {
+        self.cluster_by_primary_keys =
+            sort_options.filter(|_| !self.primary_keys.is_empty());
+        self
+    }
+
+    /// Sort the rows of each input partition by the range partition columns and the sort
+    /// order within the sink, instead of requiring the input to be sorted.
+    ///
+    /// The rows are buffered within the memory pool of the session, the buffer is sorted
+    /// and spilled to the temporary files of the disk manager as a sorted run once its
+    /// reservation cannot grow, and the runs are merged before the rows are written, like
+    /// a `SortExec`. The rows of each range partition then arrive one partition after the
+    /// other, so a large skewed partition neither buffers its whole input in memory nor
+    /// keeps the writers of the other partitions open. Without a sort order, the input is
+    /// written as is. The nulls are ordered like the table unless the sort order sets its
+    /// own null ordering.
+    pub fn with_sort_in_sink(mut self, sort_in_sink: bool) -> Self {
+        self.sort_in_sink = sort_in_sink;
+        self
+    }
+
+    /// Invoke the hook for each committed partition once the written files are committed.
+    ///
+    /// The hook sees the files actually committed, i.e. the merged files on merge on write.
+    /// Its errors are logged, and with `fail_on_error` they also fail the write. The files
+    /// stay committed to LakeSoul in any case, only the output of the write reports the error.
+    pub fn with_commit_hook(
+        mut self,
+        commit_hook: Arc<dyn CommitHook>,
+        fail_on_error: bool,
+    ) -> Self {
+        self.commit_hook = Some(RegisteredCommitHook {
+            hook: commit_hook,
+            fail_on_error,
+        });
+        self
+    }
+
+    /// Emit a progress row each time an input partition is written, before the result row
+    /// of the write.
+    ///
+    /// The progress rows are successful and have no files. Their `count` is null, so that
+    /// summing the `count` of the output only counts the written rows once, and their `msg`
+    /// is a JSON object like `{"progress":{"rows":42,"completed_partitions":1,"partitions":4}}`
+    /// with the rows written so far, not yet committed. The result row of the write stays
+    /// the last row of the output.
+    ///
+    /// The progress is reported per input partition. Unless the output is partitioned, see
+    /// [`Self::with_partitioned_output`], or clustered by the primary keys, see
+    /// [`Self::with_cluster_by_primary_keys`], the sink requires a single input partition,
+    /// so only one progress row is emitted, once all rows are written.
+    pub fn with_progress_events(mut self, progress_events: bool) -> Self {
+        self.progress_events = progress_events;
+        self
+    }
+
     /// Input execution plan
     pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
         &self.input
@@ -443,6 +2553,35 @@ impl LakeSoulHashSinkExec {
         self.metadata_client.clone()
     }
 
+    /// The input pulled by the writers, sorted by the range partition columns and the
+    /// sort order within each input partition if the sink sorts its input, see
+    /// [`Self::with_sort_in_sink`].
+    fn sink_input(&self) -> Result<Arc<dyn ExecutionPlan>> {
+        let Some(sort_order) = self.sort_order.as_ref().filter(|_| self.sort_in_sink)
+        else {
+            return Ok(self.input.clone());
+        };
+        let schema = self.input.schema();
+        // the generated range partition columns are absent from the input
+        let sort_exprs = self
+            .range_partitions
+            .iter()
+            .filter_map(|column| Column::new_with_schema(column, &schema).ok())
+            .map(|column| PhysicalSortExpr {
+                expr: Arc::new(column),
+                options: self.sort_options,
+            })
+            .chain(sort_order.iter().map(|requirement| PhysicalSortExpr {
+                expr: requirement.expr.clone(),
+                options: requirement.options.unwrap_or(self.sort_options),
+            }))
+            .collect::<Vec<_>>();
+        Ok(Arc::new(
+            SortExec::new(LexOrdering::new(sort_exprs), self.input.clone())
+                .with_preserve_partitioning(true),
+        ))
+    }
+
     #[instrument(skip(context, input, table_info, partitioned_file_path_and_row_count))]
     async fn pull_and_sink(
         input: Arc<dyn ExecutionPlan>,
@@ -450,130 +2589,1227 @@ impl LakeSoulHashSinkExec {
         context: Arc<TaskContext>,
         table_info: Arc<TableInfo>,
         range_partitions: Arc<Vec<String>>,
+        primary_keys: Arc<Vec<String>>,
+        hash_bucket_num: usize,
         write_id: String,
+        file_name_template: Option<Arc<FileNameTemplate>>,
         partitioned_file_path_and_row_count: Arc<
-            Mutex<HashMap<String, (Vec<String>, u64)>>,
+            Mutex<HashMap<String, (Vec<(String, DataFileStats)>, u64)>>,
         >,
+        max_file_size: Option<u64>,
+        max_file_rows: Option<u64>,
+        max_buffered_bytes: Option<u64>,
+        max_row_group_size: Option<usize>,
+        write_options: Arc<HashMap<String, String>>,
+        sort_order: Option<LexRequirement>,
+        metrics: SinkMetrics,
+        mut cancelled: watch::Receiver<()>,
     ) -> Result<u64> {
         debug!("{}", input.name());
+        let table_schema = schema_from_metadata_str(&table_info.table_schema);
         let mut data = input.execute(partition, context.clone())?;
-        // O(nm), n = number of data fields, m = number of range partitions
-        let schema_projection_excluding_range = data
-            .schema()
-            .fields()
+        let generated_columns =
+            serde_json::from_str::<LakeSoulTableProperty>(&table_info.properties)
+                .ok()
+                .and_then(|properties| properties.generated_columns)
+                .unwrap_or_default();
+        if !generated_columns.is_empty() {
+            data = generate_columns(
+                data,
+                &generated_columns,
+                &table_schema,
+                &range_partitions,
+                partition,
+            )?;
+        }
+        let mut sort_order_checker = sort_order
+            .map(|requirement| SortOrderChecker::try_new(requirement, &data.schema()))
+            .transpose()?;
+        // the ordered range partitions encode the paths, the set is for the lookups
+        let range_partition_set = range_partitions
             .iter()
-            .enumerate()
-            .filter_map(
-                |(idx, field)| match range_partitions.contains(field.name()) {
-                    true => None,
-                    false => Some(idx),
-                },
-            )
-            .collect::<Vec<_>>();
+            .map(String::as_str)
+            .collect::<HashSet<_>>();
 
+        let data_file_format = parse_data_file_format(&write_options)?;
+        // the layout of the table applies unless the write options override it
+        let table_config = create_io_config_builder_from_table_info(
+            table_info.clone(),
+            write_options.as_ref().clone(),
+            HashMap::new(),
+        )
+        .map_err(|e| LakeSoulWriteError::Serialization(Box::new(e)))?
+        .build();
+        let path_encoder = table_config.partition_path_encoder()?;
+        // the range partition columns are encoded into the file paths, and only written
+        // into the files on request
+        let keep_partition_columns = table_config.keep_partition_columns();
         let mut row_count = 0;
         // let mut async_writer = MultiPartAsyncWriter::try_new(lakesoul_io_config).await?;
-        let mut partitioned_writer = HashMap::<String, Box<MultiPartAsyncWriter>>::new();
-        while let Some(batch) = data.next().await.transpose()? {
+        // The writers of the range partitions and hash buckets.
+        let mut partitioned_writer = HashMap::<(String, usize), PartitionWriter>::new();
+        // The next file index and statistics disabled columns of the range partitions and
+        // hash buckets whose file was rolled.
+        let mut rolled_partitions =
+            HashMap::<(String, usize), (usize, HashSet<String>)>::new();
+        // The bytes buffered by the writers, released as their files are flushed. The
+        // writers spill by flushing their files early.
+        let mut reservation =
+            MemoryConsumer::new(format!("LakeSoulHashSinkExec[{partition}]"))
+                .with_can_spill(true)
+                .register(context.memory_pool());
+        loop {
+            let batch = tokio::select! {
+                batch = data.next() => batch,
+                // the sender is dropped with the output stream of a cancelled write
+                _ = cancelled.changed() => {
+                    Self::abort_writers(partitioned_writer).await;
+                    return Err(LakeSoulWriteError::Cancelled.into());
+                }
+            };
+            let Some(batch) = batch.transpose()? else {
+                break;
+            };
             debug!("write record_batch with {} rows", batch.num_rows());
             let columnar_values = get_columnar_values(&batch, range_partitions.clone())?;
             let partition_desc = columnar_values_to_partition_desc(&columnar_values);
             debug!("{partition_desc}");
-            let batch_excluding_range =
-                batch.project(&schema_projection_excluding_range)?;
-            let file_absolute_path = format!(
-                "{}{}part-{}_{:0>4}.parquet",
-                table_info.table_path,
-                columnar_values_to_sub_path(&columnar_values),
-                write_id,
-                partition
-            );
+            if let Some(checker) = sort_order_checker.as_mut() {
+                checker.check(&partition_desc, &batch)?;
+            }
+            // The rows of a table with primary keys are written into the file of the
+            // hash bucket of their keys, so that equal keys land in the files of the same
+            // bucket whatever the partitioning of the input. The input partition is the
+            // bucket of the tables without primary keys.
+            let mut bucket_batches = Vec::new();
+            if primary_keys.is_empty() {
+                bucket_batches.push((partition, batch));
+            } else {
+                let schema = batch.schema();
+                let hash_exprs = primary_keys
+                    .iter()
+                    .map(|pk| {
+                        Ok(Arc::new(Column::new_with_schema(pk, &schema)?)
+                            as Arc<dyn PhysicalExpr>)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                BatchPartitioner::try_new(
+                    vec![],
+                    Partitioning::Hash(hash_exprs, hash_bucket_num),
+                    Time::new(),
+                )?
+                .partition(batch, |bucket, batch| {
+                    bucket_batches.push((bucket, batch));
+                    Ok(())
+                })?;
+            }
 
-            if !partitioned_writer.contains_key(&partition_desc) {
-                let mut config = create_io_config_builder_from_table_info(
-                    table_info.clone(),
-                    HashMap::new(),
-                    HashMap::new(),
+            for (hash_bucket_id, batch) in bucket_batches {
+                let writer_key = (partition_desc.clone(), hash_bucket_id);
+                let conformed = conform_batch_to_table_schema(batch, &table_schema)?;
+                let schema_projection_excluding_range = conformed
+                    .schema()
+                    .fields()
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(idx, field)| {
+                        match !keep_partition_columns
+                            && range_partition_set.contains(field.name().as_str())
+                        {
+                            true => None,
+                            false => Some(idx),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                let batch_excluding_range =
+                    conformed.project(&schema_projection_excluding_range)?;
+
+                // The min/max statistics exclude NaN values, so that a range filter
+                // matching NaN could wrongly prune the file. Columns containing NaN are
+                // written without statistics, which requires starting a new file once NaN
+                // shows up in a column. A batch with other columns than the file of its
+                // partition, e.g. a subset of the table columns, is written into a new
+                // file as well.
+                let columns_with_nan = get_columns_with_nan(&batch_excluding_range);
+                let need_new_writer = match partitioned_writer.get(&writer_key) {
+                    Some(partition_writer) => {
+                        partition_writer.schema != batch_excluding_range.schema()
+                            || columns_with_nan.iter().any(|column| {
+                                !partition_writer
+                                    .statistics_disabled_columns
+                                    .contains(column)
+                            })
+                    }
+                    None => true,
+                };
+                if need_new_writer {
+                    let (file_index, mut statistics_disabled_columns) =
+                        match partitioned_writer.remove(&writer_key) {
+                            Some(partition_writer) => {
+                                let next_file = (
+                                    partition_writer.file_index + 1,
+                                    partition_writer.statistics_disabled_columns.clone(),
+                                );
+                                Self::finish_writer(
+                                    &partition_desc,
+                                    partition_writer,
+                                    &partitioned_file_path_and_row_count,
+                                    &metrics,
+                                )
+                                .await?;
+                                next_file
+                            }
+                            None => {
+                                rolled_partitions.remove(&writer_key).unwrap_or_default()
+                            }
+                        };
+                    statistics_disabled_columns.extend(columns_with_nan);
+
+                    // The hash bucket id must stay the last number of the file name. The
+                    // input partition tells apart the files of a bucket written by
+                    // different input partitions.
+                    let file_name = match &file_name_template {
+                        Some(template) => template.render(
+                            &write_id,
+                            partition,
+                            hash_bucket_id,
+                            file_index,
+                            data_file_format.extension(),
+                        ),
+                        None => format!(
+                            "part-{}{}{}_{:0>4}.{}",
+                            write_id,
+                            if partition == hash_bucket_id {
+                                String::new()
+                            } else {
+                                format!("-p{:0>4}", partition)
+                            },
+                            if file_index == 0 {
+                                String::new()
+                            } else {
+                                format!("-{:0>4}", file_index)
+                            },
+                            hash_bucket_id,
+                            data_file_format.extension(),
+                        ),
+                    };
+                    let file_absolute_path = format!(
+                        "{}{}{}",
+                        table_info.table_path,
+                        path_encoder.encode(&columnar_values),
+                        file_name,
+                    );
+                    let mut options = write_options.as_ref().clone();
+                    options.insert(
+                        OPTION_KEY_STATISTICS_DISABLED_COLUMNS.to_string(),
+                        statistics_disabled_columns
+                            .iter()
+                            .cloned()
+                            .collect::<Vec<_>>()
+                            .join(","),
+                    );
+                    let mut builder = create_io_config_builder_from_table_info(
+                        table_info.clone(),
+                        options,
+                        HashMap::new(),
+                    )
+                    .map_err(|e| LakeSoulWriteError::Serialization(Box::new(e)))?
+                    .with_files(vec![file_absolute_path.clone()])
+                    .with_schema(batch_excluding_range.schema());
+                    if let Some(max_row_group_size) = max_row_group_size {
+                        builder = builder.with_max_row_group_size(max_row_group_size);
+                    }
+                    let mut config = builder.build();
+                    let writer =
+                        create_writer(data_file_format, &mut config, context.clone())
+                            .await?;
+                    // the plaintext min/max of the encrypted columns are not stored
+                    let mut stats_excluded_columns = statistics_disabled_columns.clone();
+                    stats_excluded_columns.extend(config.encrypted_columns());
+                    let stats = DataFileStats::try_new(
+                        &batch_excluding_range.schema(),
+                        &stats_excluded_columns,
+                    )?;
+                    partitioned_writer.insert(
+                        writer_key.clone(),
+                        PartitionWriter {
+                            writer,
+                            schema: batch_excluding_range.schema(),
+                            file_path: file_absolute_path,
+                            num_rows: 0,
+                            statistics_disabled_columns,
+                            file_index,
+                            stats,
+                        },
+                    );
+                }
+
+                if let Some(partition_writer) = partitioned_writer.get_mut(&writer_key) {
+                    row_count += batch_excluding_range.num_rows();
+                    metrics.output_rows.add(batch_excluding_range.num_rows());
+                    partition_writer.num_rows += batch_excluding_range.num_rows() as u64;
+                    partition_writer.stats.update(&batch_excluding_range)?;
+                    partition_writer
+                        .writer
+                        .write_record_batch(batch_excluding_range)
+                        .await?;
+                    let exceeds_limit = max_file_size.is_some_and(|size| {
+                        partition_writer.writer.buffered_size() >= size
+                    }) || max_file_rows
+                        .is_some_and(|rows| partition_writer.num_rows >= rows);
+                    if exceeds_limit {
+                        if let Some(partition_writer) =
+                            partitioned_writer.remove(&writer_key)
+                        {
+                            debug!(
+                                "roll file {} of partition {}",
+                                partition_writer.file_path, partition_desc
+                            );
+                            let next_file = (
+                                partition_writer.file_index + 1,
+                                partition_writer.statistics_disabled_columns.clone(),
+                            );
+                            Self::finish_writer(
+                                &partition_desc,
+                                partition_writer,
+                                &partitioned_file_path_and_row_count,
+                                &metrics,
+                            )
+                            .await?;
+                            rolled_partitions.insert(writer_key, next_file);
+                        }
+                    }
+                }
+            }
+
+            // flush the largest writer once the open writers together buffer too much
+            let total_buffered = Self::buffered_bytes(&partitioned_writer);
+            if max_buffered_bytes.is_some_and(|limit| total_buffered > limit) {
+                debug!("{} bytes buffered by all writers", total_buffered);
+                Self::flush_largest_writer(
+                    &mut partitioned_writer,
+                    &mut rolled_partitions,
+                    &partitioned_file_path_and_row_count,
+                    &metrics,
                 )
-                .map_err(|e| DataFusionError::External(Box::new(e)))?
-                .with_files(vec![file_absolute_path])
-                .with_schema(batch_excluding_range.schema())
-                .build();
-                let writer = MultiPartAsyncWriter::try_new_with_context(
-                    &mut config,
-                    context.clone(),
+                .await?;
+            }
+            // the buffered bytes are reserved from the memory pool of the session, the
+            // largest writers are flushed while the pool cannot grant them
+            while let Err(e) =
+                reservation.try_resize(Self::buffered_bytes(&partitioned_writer) as usize)
+            {
+                let flushed = Self::flush_largest_writer(
+                    &mut partitioned_writer,
+                    &mut rolled_partitions,
+                    &partitioned_file_path_and_row_count,
+                    &metrics,
                 )
                 .await?;
-                partitioned_writer.insert(partition_desc.clone(), Box::new(writer));
+                if !flushed {
+                    return Err(e);
+                }
             }
+        }
 
-            if let Some(async_writer) = partitioned_writer.get_mut(&partition_desc) {
-                row_count += batch_excluding_range.num_rows();
-                async_writer
-                    .write_record_batch(batch_excluding_range)
-                    .await?;
-            }
+        for ((partition_desc, _), partition_writer) in partitioned_writer.into_iter() {
+            Self::finish_writer(
+                &partition_desc,
+                partition_writer,
+                &partitioned_file_path_and_row_count,
+                &metrics,
+            )
+            .await?;
         }
+        reservation.free();
 
-        // TODO: apply rolling strategy
-        for (partition_desc, writer) in partitioned_writer.into_iter() {
-            {
-                let mut partitioned_file_path_and_row_count_locked =
-                    partitioned_file_path_and_row_count.lock().await;
-                let file_absolute_path = writer.absolute_path();
-                let num_rows = writer.nun_rows();
-                if let Some(file_path_and_row_count) =
-                    partitioned_file_path_and_row_count_locked.get_mut(&partition_desc)
-                {
-                    file_path_and_row_count.0.push(file_absolute_path);
-                    file_path_and_row_count.1 += num_rows;
-                } else {
-                    partitioned_file_path_and_row_count_locked.insert(
-                        partition_desc.clone(),
-                        (vec![file_absolute_path], num_rows),
-                    );
-                }
-                // release guard
+        Ok(row_count as u64)
+    }
+
+    /// The bytes buffered in memory by the open writers.
+    fn buffered_bytes(
+        partitioned_writer: &HashMap<(String, usize), PartitionWriter>,
+    ) -> u64 {
+        partitioned_writer
+            .values()
+            .map(|partition_writer| partition_writer.writer.memory_size())
+            .sum()
+    }
+
+    /// Flush the file of the writer buffering the most bytes, its range partition and
+    /// hash bucket continue in a new file. Returns false if there is no open writer.
+    async fn flush_largest_writer(
+        partitioned_writer: &mut HashMap<(String, usize), PartitionWriter>,
+        rolled_partitions: &mut HashMap<(String, usize), (usize, HashSet<String>)>,
+        partitioned_file_path_and_row_count: &Mutex<
+            HashMap<String, (Vec<(String, DataFileStats)>, u64)>,
+        >,
+        metrics: &SinkMetrics,
+    ) -> Result<bool> {
+        let Some(largest) = partitioned_writer
+            .iter()
+            .max_by_key(|(_, partition_writer)| partition_writer.writer.memory_size())
+            .map(|(writer_key, _)| writer_key.clone())
+        else {
+            return Ok(false);
+        };
+        let Some(partition_writer) = partitioned_writer.remove(&largest) else {
+            return Ok(false);
+        };
+        debug!(
+            "flush file {} of partition {}, {} bytes buffered",
+            partition_writer.file_path,
+            largest.0,
+            partition_writer.writer.memory_size()
+        );
+        let next_file = (
+            partition_writer.file_index + 1,
+            partition_writer.statistics_disabled_columns.clone(),
+        );
+        Self::finish_writer(
+            &largest.0,
+            partition_writer,
+            partitioned_file_path_and_row_count,
+            metrics,
+        )
+        .await?;
+        rolled_partitions.insert(largest, next_file);
+        Ok(true)
+    }
+
+    /// Abort the uploads of the open writers of a cancelled write.
+    async fn abort_writers(
+        partitioned_writer: HashMap<(String, usize), PartitionWriter>,
+    ) {
+        for (_, partition_writer) in partitioned_writer {
+            if let Err(e) = partition_writer.writer.abort_and_close().await {
+                debug!("failed to abort file {}: {}", partition_writer.file_path, e);
             }
-            writer.flush_and_close().await?;
         }
+    }
 
-        Ok(row_count as u64)
+    /// Flush and close the writer, then record the file and its statistics into the files of the partition.
+    async fn finish_writer(
+        partition_desc: &str,
+        partition_writer: PartitionWriter,
+        partitioned_file_path_and_row_count: &Mutex<
+            HashMap<String, (Vec<(String, DataFileStats)>, u64)>,
+        >,
+        metrics: &SinkMetrics,
+    ) -> Result<()> {
+        let PartitionWriter {
+            writer,
+            file_path: file_absolute_path,
+            num_rows,
+            mut stats,
+            ..
+        } = partition_writer;
+        let flush_result = writer.flush_and_close().await?;
+        record_flushed_file(&mut stats, &flush_result);
+        if let Some((_, _, object_meta, _)) = flush_result.first() {
+            metrics.bytes_written.add(object_meta.size as usize);
+        }
+        metrics.files_created.add(1);
+        let mut partitioned_file_path_and_row_count_locked =
+            partitioned_file_path_and_row_count.lock().await;
+        if let Some(file_path_and_row_count) =
+            partitioned_file_path_and_row_count_locked.get_mut(partition_desc)
+        {
+            file_path_and_row_count.0.push((file_absolute_path, stats));
+            file_path_and_row_count.1 += num_rows;
+        } else {
+            partitioned_file_path_and_row_count_locked.insert(
+                partition_desc.to_string(),
+                (vec![(file_absolute_path, stats)], num_rows),
+            );
+        }
+        Ok(())
     }
 
+    /// Wait for the write tasks and commit their written files, returning the number of
+    /// committed rows with the commit report of the partitions when they are committed
+    /// independently, and the paths of the committed files.
+    #[allow(clippy::too_many_arguments)]
     async fn wait_for_commit(
         join_handles: Vec<JoinHandle<Result<u64>>>,
         client: MetaDataClientRef,
         table_name: String,
+        table_info: Arc<TableInfo>,
         partitioned_file_path_and_row_count: Arc<
-            Mutex<HashMap<String, (Vec<String>, u64)>>,
+            Mutex<HashMap<String, (Vec<(String, DataFileStats)>, u64)>>,
         >,
-    ) -> Result<u64> {
-        let count = futures::future::join_all(join_handles)
+        merge_operators: Option<Arc<HashMap<String, String>>>,
+        commit_per_partition: bool,
+        planned_versions: Option<Arc<HashMap<String, i32>>>,
+        write_id: String,
+        write_options: Arc<HashMap<String, String>>,
+        context: Arc<TaskContext>,
+        commit_time: Time,
+        cancelled: watch::Receiver<()>,
+        commit_hook: Option<RegisteredCommitHook>,
+        progress: Option<UnboundedSender<SinkProgress>>,
+    ) -> Result<(u64, Option<PartitionCommitReport>, Vec<String>)> {
+        let partitions = join_handles.len();
+        let mut pending = join_handles.into_iter().collect::<FuturesUnordered<_>>();
+        let mut results = Vec::with_capacity(partitions);
+        let mut written_rows = 0;
+        while let Some(result) = pending.next().await {
+            if let Ok(Ok(count)) = &result {
+                written_rows += count;
+            }
+            results.push(result);
+            if let Some(progress) = &progress {
+                // the output stream may be gone, the write is then cancelled below
+                let _ = progress.unbounded_send(SinkProgress {
+                    rows: written_rows,
+                    completed_partitions: results.len(),
+                    partitions,
+                });
+            }
+        }
+        drop(progress);
+        // the files flushed by a cancelled write are never committed
+        if cancelled.has_changed().is_err() {
+            let flushed_files =
+                std::mem::take(&mut *partitioned_file_path_and_row_count.lock().await)
+                    .into_values()
+                    .flat_map(|(files, _)| files.into_iter().map(|(path, _)| path))
+                    .collect::<Vec<_>>();
+            debug!(
+                "table: {} insert cancelled, delete {} flushed files",
+                &table_name,
+                flushed_files.len()
+            );
+            delete_data_files(&context, &table_info, flushed_files).await;
+            return Err(LakeSoulWriteError::Cancelled.into());
+        }
+        // the errors of the write tasks are passed on as they are
+        let count =
+            results
+                .into_iter()
+                .try_fold(0u64, |counter, result| match result {
+                    Ok(Ok(count)) => Ok(counter + count),
+                    Ok(Err(e)) => Err(e),
+                    Err(e) => Err(LakeSoulWriteError::TaskJoin(e).into()),
+                })?;
+        let partitioned_files =
+            std::mem::take(&mut *partitioned_file_path_and_row_count.lock().await)
+                .into_iter()
+                // partitions without written files have nothing to commit
+                .filter(|(_, (files, _))| !files.is_empty())
+                .collect::<Vec<_>>();
+        if partitioned_files.is_empty() {
+            debug!("table: {} insert wrote no files, skip commit", &table_name);
+            return Ok((count, commit_per_partition.then(Default::default), vec![]));
+        }
+
+        let timer = commit_time.timer();
+        let result = if commit_per_partition {
+            let mut report = PartitionCommitReport::new();
+            let mut committed_rows = 0;
+            let mut committed_files = vec![];
+            for (partition_desc, (files, num_rows)) in partitioned_files {
+                let status = Self::commit_partitions(
+                    client.clone(),
+                    &table_name,
+                    table_info.clone(),
+                    vec![(partition_desc.clone(), files)],
+                    merge_operators.as_deref(),
+                    planned_versions.as_deref(),
+                    &write_id,
+                    write_options.clone(),
+                    &context,
+                )
+                .await;
+                let status = match status {
+                    Ok(committed) => {
+                        let files = committed
+                            .iter()
+                            .flat_map(|(_, files)| files.iter().cloned())
+                            .collect::<Vec<_>>();
+                        let hook = commit_hook.as_ref();
+                        Self::run_commit_hook(hook, &table_name, committed)
+                            .await
+                            .map(|_| files)
+                    }
+                    Err(e) => Err(e),
+                }
+                .map_err(|e| e.to_string());
+                let status = match status {
+                    Ok(files) => {
+                        committed_files.extend(files);
+                        Ok(num_rows)
+                    }
+                    Err(e) => Err(e),
+                };
+                match &status {
+                    Ok(num_rows) => committed_rows += num_rows,
+                    Err(e) => debug!(
+                        "table: {} commit of partition {} failed: {}",
+                        &table_name, partition_desc, e
+                    ),
+                }
+                report.insert(partition_desc, status);
+            }
+            (committed_rows, Some(report), committed_files)
+        } else {
+            // all partitions are committed in one transaction, so the insert is atomic
+            let committed = Self::commit_partitions(
+                client,
+                &table_name,
+                table_info,
+                partitioned_files
+                    .into_iter()
+                    .map(|(partition_desc, (files, _))| (partition_desc, files))
+                    .collect(),
+                merge_operators.as_deref(),
+                planned_versions.as_deref(),
+                &write_id,
+                write_options,
+                &context,
+            )
+            .await?;
+            let committed_files = committed
+                .iter()
+                .flat_map(|(_, files)| files.iter().cloned())
+                .collect();
+            Self::run_commit_hook(commit_hook.as_ref(), &table_name, committed).await?;
+            (count, None, committed_files)
+        };
+        timer.done();
+        debug!(
+            "table: {} insert success at {:?}",
+            &table_name,
+            std::time::SystemTime::now()
+        );
+        Ok(result)
+    }
+
+    /// Commit the written files of the partitions in one transaction, merging them first
+    /// with the committed files of their hash buckets with the `merge_operators` on merge on
+    /// write.
+    ///
+    /// With the `planned_versions` of the partitions, the transaction fails with
+    /// [`LakeSoulWriteError::CommitConflict`] if one of the partitions was committed since.
+    ///
+    /// Returns the paths of the committed files by partition descriptor.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) async fn commit_partitions(
+        client: MetaDataClientRef,
+        table_name: &str,
+        table_info: Arc<TableInfo>,
+        partitioned_files: Vec<(String, Vec<(String, DataFileStats)>)>,
+        merge_operators: Option<&HashMap<String, String>>,
+        planned_versions: Option<&HashMap<String, i32>>,
+        write_id: &str,
+        write_options: Arc<HashMap<String, String>>,
+        context: &Arc<TaskContext>,
+    ) -> Result<Vec<(String, Vec<String>)>> {
+        let committed = if let Some(merge_operators) = merge_operators {
+            let written_files = partitioned_files
+                .iter()
+                .flat_map(|(_, files)| files.iter().map(|(path, _)| path.clone()))
+                .collect::<Vec<_>>();
+            let (read_partitions, merged_files, superseded_files) =
+                Self::merge_partitions(
+                    client.clone(),
+                    table_info.clone(),
+                    partitioned_files,
+                    planned_versions,
+                    write_id,
+                    write_options,
+                    merge_operators,
+                    context.clone(),
+                )
+                .await?;
+            verify_data_files(context, &table_info, &merged_files).await?;
+            let committed = file_paths_by_partition(&merged_files);
+            commit_merge_batch(
+                client,
+                table_name,
+                into_stored_partitioned_files(merged_files)?,
+                superseded_files,
+                read_partitions,
+            )
             .await
-            .iter()
-            .try_fold(0u64, |counter, result| match &result {
-                Ok(Ok(count)) => Ok(counter + count),
-                Ok(Err(e)) => Err(DataFusionError::Execution(format!("{}", e))),
-                Err(e) => Err(DataFusionError::Execution(format!("{}", e))),
-            })?;
-        let partitioned_file_path_and_row_count =
-            partitioned_file_path_and_row_count.lock().await;
+            .map_err(LakeSoulWriteError::metadata_commit)?;
+            // the written files are superseded by the merged files and never committed
+            delete_data_files(context, &table_info, written_files).await;
+            committed
+        } else {
+            verify_data_files(context, &table_info, &partitioned_files).await?;
+            let committed = file_paths_by_partition(&partitioned_files);
+            // the partitions must keep their versions since the write was planned, the ones
+            // absent at the planning with a negative version must still be absent
+            let read_partitions = planned_versions
+                .map(|planned_versions| {
+                    partitioned_files
+                        .iter()
+                        .map(|(partition_desc, _)| PartitionInfo {
+                            table_id: table_info.table_id.clone(),
+                            partition_desc: partition_desc.clone(),
+                            version: planned_versions
+                                .get(partition_desc)
+                                .copied()
+                                .unwrap_or(-1),
+                            ..Default::default()
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            commit_data_batch(
+                client,
+                table_name,
+                into_stored_partitioned_files(partitioned_files)?,
+                read_partitions,
+            )
+            .await
+            .map_err(LakeSoulWriteError::metadata_commit)?;
+            committed
+        };
+        Ok(committed)
+    }
 
-        for (partition_desc, (files, _)) in partitioned_file_path_and_row_count.iter() {
-            commit_data(client.clone(), &table_name, partition_desc.clone(), files)
+    /// Invoke the commit hook for each committed partition.
+    ///
+    /// The errors of the hook are logged and only returned if the hook is registered to fail
+    /// the write, the files stay committed either way.
+    pub(crate) async fn run_commit_hook(
+        commit_hook: Option<&RegisteredCommitHook>,
+        table_ref: &str,
+        committed: Vec<(String, Vec<String>)>,
+    ) -> Result<()> {
+        let Some(commit_hook) = commit_hook else {
+            return Ok(());
+        };
+        for (partition_desc, files) in committed {
+            if let Err(e) = commit_hook
+                .hook
+                .on_commit(table_ref, &partition_desc, &files)
                 .await
-                .map_err(|e| DataFusionError::External(Box::new(e)))?;
-            debug!(
-                "table: {} insert success at {:?}",
-                &table_name,
-                std::time::SystemTime::now()
+            {
+                warn!(
+                    "commit hook of table {} failed for partition {}: {}",
+                    table_ref, partition_desc, e
+                );
+                if commit_hook.fail_on_error {
+                    return Err(LakeSoulWriteError::CommitHook {
+                        partition_desc,
+                        source: e,
+                    }
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Merge the written files of each partition with the committed files of their hash
+    /// buckets, returning the read versions of the partitions, the merged files and the
+    /// committed files they supersede.
+    ///
+    /// The files of a bucket are merged in their commit order followed by the written files,
+    /// so the written rows win over the committed rows of the same primary keys. Only the
+    /// buckets with written files are merged, the files of the other buckets stay committed.
+    /// With the `planned_versions` of the partitions, nothing is merged if one of them was
+    /// committed since.
+    #[allow(clippy::too_many_arguments)]
+    #[allow(clippy::type_complexity)]
+    async fn merge_partitions(
+        client: MetaDataClientRef,
+        table_info: Arc<TableInfo>,
+        partitioned_files: Vec<(String, Vec<(String, DataFileStats)>)>,
+        planned_versions: Option<&HashMap<String, i32>>,
+        write_id: &str,
+        write_options: Arc<HashMap<String, String>>,
+        merge_operators: &HashMap<String, String>,
+        context: Arc<TaskContext>,
+    ) -> Result<(
+        Vec<PartitionInfo>,
+        Vec<(String, Vec<(String, DataFileStats)>)>,
+        Vec<(String, Vec<String>)>,
+    )> {
+        let partition_descs = partitioned_files
+            .iter()
+            .map(|(partition_desc, _)| partition_desc.clone())
+            .collect::<Vec<_>>();
+        let read_partitions = client
+            .get_partition_info_by_table_id_and_partition_list(
+                &table_info.table_id,
+                &partition_descs,
             )
+            .await
+            .map_err(LakeSoulWriteError::MetaData)?;
+        if let Some(planned_versions) = planned_versions {
+            for partition_desc in &partition_descs {
+                let planned_version = planned_versions.get(partition_desc).copied();
+                let current_version = read_partitions
+                    .iter()
+                    .find(|partition_info| {
+                        &partition_info.partition_desc == partition_desc
+                    })
+                    .map(|partition_info| partition_info.version);
+                if planned_version != current_version {
+                    return Err(LakeSoulWriteError::CommitConflict {
+                        partition_desc: partition_desc.clone(),
+                        planned_version,
+                        current_version,
+                    }
+                    .into());
+                }
+            }
+        }
+        let (range_partitions, _) =
+            parse_table_info_partitions(&table_info.partitions)
+                .map_err(|e| LakeSoulWriteError::Serialization(Box::new(e)))?;
+        let table_schema = schema_from_metadata_str(&table_info.table_schema);
+        let file_schema = Arc::new(Schema::new(
+            table_schema
+                .fields()
+                .iter()
+                .filter(|field| !range_partitions.contains(field.name()))
+                .cloned()
+                .collect::<Vec<_>>(),
+        ));
+        let extension = parse_data_file_format(&write_options)?.extension();
+
+        let mut merged_files = Vec::with_capacity(partitioned_files.len());
+        let mut superseded_files = Vec::with_capacity(partitioned_files.len());
+        for (partition_desc, files) in partitioned_files {
+            let committed_files = match read_partitions
+                .iter()
+                .find(|partition_info| partition_info.partition_desc == partition_desc)
+            {
+                Some(partition_info) => client
+                    .get_data_files_of_single_partition(partition_info)
+                    .await
+                    .map_err(LakeSoulWriteError::MetaData)?,
+                None => vec![],
+            };
+            // the written files of a partition share the directory of the partition
+            let Some((directory, _)) =
+                files.first().and_then(|(path, _)| path.rsplit_once('/'))
+            else {
+                continue;
+            };
+            let directory = directory.to_string();
+            let mut bucket_files = BTreeMap::<Option<u32>, Vec<String>>::new();
+            for (path, _) in &files {
+                bucket_files
+                    .entry(extract_hash_bucket_id(path))
+                    .or_default();
+            }
+            // the committed files of the buckets without written files are left as they are
+            let mut partition_superseded_files = vec![];
+            for path in committed_files {
+                // the delete vectors are merged with their data files
+                let data_file = delete_vector_data_file(&path).unwrap_or(&path);
+                if let Some(paths) =
+                    bucket_files.get_mut(&extract_hash_bucket_id(data_file))
+                {
+                    paths.push(path.clone());
+                    partition_superseded_files.push(path);
+                }
+            }
+            for (path, _) in files {
+                if let Some(paths) = bucket_files.get_mut(&extract_hash_bucket_id(&path))
+                {
+                    paths.push(path);
+                }
+            }
+            let mut partition_merged_files = Vec::with_capacity(bucket_files.len());
+            for (hash_bucket_id, paths) in bucket_files {
+                // The hash bucket id must stay the last number of the file name.
+                let file_path = match hash_bucket_id {
+                    Some(hash_bucket_id) => format!(
+                        "{}/part-{}-merged_{:0>4}.{}",
+                        directory, write_id, hash_bucket_id, extension
+                    ),
+                    None => {
+                        format!("{}/part-{}-merged.{}", directory, write_id, extension)
+                    }
+                };
+                debug!("merge files {:?} into {}", paths, file_path);
+                partition_merged_files.push(
+                    merge_files(
+                        paths,
+                        file_path,
+                        &partition_desc,
+                        file_schema.clone(),
+                        table_info.clone(),
+                        write_options.clone(),
+                        merge_operators,
+                        context.clone(),
+                    )
+                    .await?,
+                );
+            }
+            superseded_files.push((partition_desc.clone(), partition_superseded_files));
+            merged_files.push((partition_desc, partition_merged_files));
+        }
+        Ok((read_partitions, merged_files, superseded_files))
+    }
+}
+
+/// Union the scans of the files of an append only table, each file scanned as its own
+/// output partition with the columns of the schema it lacks filled in by a
+/// [`DefaultColumnExec`] from the values of its partition columns.
+pub(crate) fn union_file_scans(
+    inputs: impl IntoIterator<Item = (Arc<dyn ExecutionPlan>, Arc<HashMap<String, String>>)>,
+    schema: SchemaRef,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    let mut file_execs = Vec::new();
+    for (input, partition_columnar_values) in inputs {
+        // a file already holding every column of the scan needs no filling
+        let input_schema = input.schema();
+        if input_schema.fields().len() == schema.fields().len()
+            && input_schema
+                .fields()
+                .iter()
+                .zip(schema.fields())
+                .all(|(field, merged)| {
+                    field.name() == merged.name()
+                        && field.data_type() == merged.data_type()
+                        && field.is_nullable() == merged.is_nullable()
+                })
+        {
+            file_execs.push(input);
+            continue;
+        }
+        file_execs.push(Arc::new(DefaultColumnExec::new(
+            input,
+            schema.clone(),
+            partition_columnar_values,
+        )?) as Arc<dyn ExecutionPlan>);
+    }
+    Ok(match file_execs.len() {
+        0 => Arc::new(EmptyExec::new(schema)),
+        1 => file_execs.remove(0),
+        _ => Arc::new(UnionExec::new(file_execs)),
+    })
+}
+
+/// Merge the files of a hash bucket on the primary keys into a new file, the rows of the
+/// later files superseding the rows of the same keys in the earlier files.
+///
+/// The files are read like the scans of the table, whatever their data file format and
+/// schema, and merged with the `merge_operators` of the columns. The delete vectors among
+/// the paths are not merged, the rows they delete are dropped from their data files before
+/// the merge. The merged file holds the whole history of the bucket, so the rows deleted
+/// through the cdc column of the table are dropped as well. The range partition columns are
+/// written into the merged file from the `partition_desc` of the files if the table keeps
+/// them, see [`LakeSoulIOConfigBuilder::with_keep_partition_columns`].
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn merge_files(
+    paths: Vec<String>,
+    file_path: String,
+    partition_desc: &str,
+    file_schema: SchemaRef,
+    table_info: Arc<TableInfo>,
+    write_options: Arc<HashMap<String, String>>,
+    merge_operators: &HashMap<String, String>,
+    context: Arc<TaskContext>,
+) -> Result<(String, DataFileStats)> {
+    let (delete_vector_paths, paths): (Vec<_>, Vec<_>) =
+        paths.into_iter().partition(|path| is_delete_vector(path));
+    let table_url = table_url(&table_info)?;
+    let runtime_env = context.runtime_env();
+    let mut deleted_rows = HashMap::<String, Vec<u64>>::new();
+    for path in &delete_vector_paths {
+        let Some(data_file) = delete_vector_data_file(path) else {
+            continue;
+        };
+        let (store_url, location) = resolve_file_url(path, &table_url)?;
+        let store = runtime_env.object_store(&store_url)?;
+        deleted_rows
+            .entry(data_file.to_string())
+            .or_default()
+            .extend(read_delete_vectors(store.as_ref(), [&location]).await?);
+    }
+    let mut files = Vec::with_capacity(paths.len());
+    let mut object_store_url = None;
+    for path in &paths {
+        let (file_object_store_url, location) = resolve_file_url(path, &table_url)?;
+        let store = runtime_env.object_store(&file_object_store_url)?;
+        let object_meta = store
+            .head(&location)
+            .await
+            .map_err(LakeSoulWriteError::ObjectStore)?;
+        files.push(with_file_object_store_url(
+            PartitionedFile::from(object_meta),
+            file_object_store_url.clone(),
+        ));
+        object_store_url.get_or_insert(file_object_store_url);
+    }
+    let Some(object_store_url) = object_store_url else {
+        return Err(DataFusionError::Internal(format!(
+            "No data files to merge into {}",
+            file_path
+        )));
+    };
+
+    let table_schema = schema_from_metadata_str(&table_info.table_schema);
+    let mut builder = create_io_config_builder_from_table_info(
+        table_info,
+        write_options.as_ref().clone(),
+        HashMap::new(),
+    )
+    .map_err(|e| LakeSoulWriteError::Serialization(Box::new(e)))?
+    .with_files(vec![file_path.clone()])
+    .with_schema(file_schema.clone());
+    for (field_name, merge_op) in merge_operators {
+        builder = builder.with_merge_op(field_name.clone(), merge_op.clone());
+    }
+    let io_config = builder.build();
+
+    // the files are flattened like the scans, each one read by its own format and schema,
+    // without the range partition columns kept in the files
+    let partition_schema = Arc::new(Schema::new(
+        table_schema
+            .fields()
+            .iter()
+            .filter(|field| io_config.range_partitions_slice().contains(field.name()))
+            .cloned()
+            .collect::<Vec<_>>(),
+    ));
+    let state = SessionStateBuilder::new()
+        .with_config(context.session_config().clone())
+        .with_runtime_env(context.runtime_env())
+        .build();
+    let flatten_configs = flatten_file_scan_config(
+        &state,
+        Arc::new(ParquetFormat::default()),
+        FileScanConfigBuilder::new(
+            object_store_url,
+            file_schema.clone(),
+            Arc::new(ParquetSource::default()),
+        )
+        .with_file_group(FileGroup::new(files))
+        .build(),
+        &io_config.merge_columns(),
+        &io_config.cdc_column(),
+        partition_schema.clone(),
+        file_schema.clone(),
+        io_config.meta_fetch_concurrency()?,
+        io_config.coerce_timestamp_unit()?,
+    )
+    .await?;
+
+    // The writers leave out the min/max of the floating point columns holding NaN, see
+    // `get_columns_with_nan`, so the merged file does the same for the columns lacking them
+    // in one of the merged files.
+    let statistics_disabled_columns = file_schema
+        .fields()
+        .iter()
+        .filter(|field| field.data_type().is_floating())
+        .filter(|field| {
+            flatten_configs.iter().any(|config| {
+                let Ok(idx) = config.file_schema.index_of(field.name()) else {
+                    return false;
+                };
+                config.file_groups[0]
+                    .statistics()
+                    .and_then(|statistics| statistics.column_statistics.get(idx))
+                    .is_none_or(|column_statistics| {
+                        column_statistics.min_value == Precision::Absent
+                            || column_statistics.max_value == Precision::Absent
+                    })
+            })
+        })
+        .map(|field| field.name().clone())
+        .collect::<HashSet<_>>();
+    let io_config = LakeSoulIOConfigBuilder::from(io_config)
+        .with_option(
+            OPTION_KEY_STATISTICS_DISABLED_COLUMNS,
+            statistics_disabled_columns
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(","),
+        )
+        .build();
+
+    let decryption = ScanDecryption::try_new(&io_config, context.runtime_env())?;
+    let inputs =
+        MergeParquetExec::scan_inputs(flatten_configs, None, None, decryption.as_ref())?
+            .into_iter()
+            .zip(&paths)
+            .map(|(input, path)| match deleted_rows.remove(path) {
+                Some(mut deleted_rows) => {
+                    deleted_rows.sort_unstable();
+                    deleted_rows.dedup();
+                    Arc::new(DeletionVectorExec::new(input, Arc::new(deleted_rows)))
+                        as Arc<dyn ExecutionPlan>
+                }
+                None => input,
+            })
+            .collect();
+    let merge_exec: Arc<dyn ExecutionPlan> = Arc::new(
+        MergeParquetExec::new_with_scan_inputs(file_schema, inputs, io_config.clone())?,
+    );
+    let cdc_column = io_config.cdc_column();
+    let merge_exec = if cdc_column.is_empty() {
+        merge_exec
+    } else {
+        let dfschema = DFSchema::try_from(merge_exec.schema().as_ref().clone())?;
+        let delete_markers = io_config
+            .cdc_delete_markers()
+            .into_iter()
+            .map(lit)
+            .collect::<Vec<_>>();
+        let cdc_filter = ident(cdc_column).in_list(delete_markers, true);
+        let expr = create_physical_expr(&cdc_filter, &dfschema, state.execution_props())?;
+        Arc::new(FilterExec::try_new(expr, merge_exec)?)
+    };
+    let merge_exec: Arc<dyn ExecutionPlan> =
+        if io_config.keep_partition_columns() && !partition_schema.fields().is_empty() {
+            // the partition columns are filled in from the values of the partition
+            let mut fields = merge_exec.schema().fields().to_vec();
+            fields.extend(partition_schema.fields().iter().cloned());
+            let partition_values = partition_desc
+                .split(',')
+                .filter_map(|part| part.split_once('='))
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<HashMap<_, _>>();
+            Arc::new(DefaultColumnExec::new(
+                merge_exec,
+                Arc::new(Schema::new(fields)),
+                Arc::new(partition_values),
+            )?)
+        } else {
+            merge_exec
+        };
+    let mut data = merge_exec.execute(0, context.clone())?;
+
+    // the merged rows may be nullable where the table schema is not
+    let mut config = LakeSoulIOConfigBuilder::from(io_config)
+        .with_schema(merge_exec.schema())
+        .build();
+    let mut writer = create_writer(
+        parse_data_file_format(&write_options)?,
+        &mut config,
+        context,
+    )
+    .await?;
+    let mut stats_excluded_columns = statistics_disabled_columns;
+    stats_excluded_columns.extend(config.encrypted_columns());
+    let mut stats =
+        DataFileStats::try_new(&merge_exec.schema(), &stats_excluded_columns)?;
+    while let Some(batch) = data.next().await.transpose()? {
+        stats.update(&batch)?;
+        writer.write_record_batch(batch).await?;
+    }
+    let flush_result = writer.flush_and_close().await?;
+    record_flushed_file(&mut stats, &flush_result);
+    Ok((file_path, stats))
+}
+
+/// Record the size, checksum and compression codec of a flushed file into its statistics.
+///
+/// The size is the number of bytes uploaded by the writer when it reports them, so that
+/// [`verify_data_files`] detects a file changed by the object store since its upload.
+pub(super) fn record_flushed_file(
+    stats: &mut DataFileStats,
+    flush_result: &WriterFlushResult,
+) {
+    let Some((_, _, object_meta, file_meta)) = flush_result.first() else {
+        return;
+    };
+    if let Some(compression) = file_compression(file_meta) {
+        stats.set_compression(compression);
+    }
+    match FileIntegrity::from_file_metadata(file_meta) {
+        Some(integrity) => {
+            stats.set_file_size(integrity.size);
+            stats.set_checksum(integrity.checksum);
         }
-        Ok(count)
+        None => stats.set_file_size(object_meta.size),
     }
 }
 
+/// The compression codec of the column chunks of a parquet file, absent for a file without
+/// row groups or of another data file format.
+///
+/// The readers decode each column chunk by its own codec, so the files of a table may mix
+/// codecs, the recorded codec tells which one a file was written with.
+fn file_compression(file_meta: &FileMetaData) -> Option<String> {
+    let codec = file_meta
+        .row_groups
+        .first()?
+        .columns
+        .first()?
+        .meta_data
+        .as_ref()?
+        .codec;
+    let name = match Compression::try_from(codec).ok()? {
+        Compression::UNCOMPRESSED => "UNCOMPRESSED",
+        Compression::SNAPPY => "SNAPPY",
+        Compression::GZIP(_) => "GZIP",
+        Compression::LZO => "LZO",
+        Compression::BROTLI(_) => "BROTLI",
+        Compression::LZ4 => "LZ4",
+        Compression::ZSTD(_) => "ZSTD",
+        Compression::LZ4_RAW => "LZ4_RAW",
+    };
+    Some(name.to_string())
+}
+
+/// Check that the object store reports the recorded size of each file to commit, failing
+/// the commit on a missing or truncated file rather than registering it into the metadata.
+async fn verify_data_files(
+    context: &TaskContext,
+    table_info: &TableInfo,
+    partitioned_files: &[(String, Vec<(String, DataFileStats)>)],
+) -> Result<()> {
+    let table_url = &table_url(table_info)?;
+    let files = partitioned_files
+        .iter()
+        .flat_map(|(_, files)| files)
+        .filter_map(|(path, stats)| stats.file_size().map(|size| (path, size)));
+    futures::future::try_join_all(files.map(|(path, expected)| async move {
+        let (store_url, location) = resolve_file_url(path, table_url)?;
+        let actual = context
+            .runtime_env()
+            .object_store(&store_url)?
+            .head(&location)
+            .await
+            .map_err(LakeSoulWriteError::ObjectStore)?
+            .size;
+        if actual != expected {
+            return Err(LakeSoulWriteError::FileSizeMismatch {
+                path: path.clone(),
+                expected,
+                actual,
+            }
+            .into());
+        }
+        Ok::<_, DataFusionError>(())
+    }))
+    .await?;
+    Ok(())
+}
+
+/// Finish the statistics of the files of each partition into the statistics stored in the metadata.
+pub(super) fn into_stored_partitioned_files(
+    partitioned_files: Vec<(String, Vec<(String, DataFileStats)>)>,
+) -> Result<Vec<(String, Vec<(String, StoredFileStatistics)>)>> {
+    partitioned_files
+        .into_iter()
+        .map(|(partition_desc, files)| {
+            let files = files
+                .into_iter()
+                .map(|(path, stats)| {
+                    let stats = stats
+                        .into_stored()
+                        .map_err(|e| LakeSoulWriteError::Serialization(Box::new(e)))?;
+                    Ok((path, stats))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok((partition_desc, files))
+        })
+        .collect()
+}
+
 impl DisplayAs for LakeSoulHashSinkExec {
     fn fmt_as(&self, _t: DisplayFormatType, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "LakeSoulHashSinkExec")
@@ -622,9 +3858,27 @@ impl ExecutionPlan for LakeSoulHashSinkExec {
     }
 
     fn required_input_distribution(&self) -> Vec<Distribution> {
-        // DataSink is responsible for dynamically partitioning its
-        // own input at execution time, and so requires a single input partition.
-        vec![Distribution::SinglePartition; self.children().len()]
+        if !self.partitioned_output && self.cluster_by_primary_keys.is_none() {
+            // DataSink is responsible for dynamically partitioning its
+            // own input at execution time, and so requires a single input partition.
+            return vec![Distribution::SinglePartition; self.children().len()];
+        }
+        // the rows of a primary key are written by the same partition
+        let input_schema = self.input.schema();
+        let hash_exprs = self
+            .primary_keys
+            .iter()
+            .map(|pk| {
+                Ok(Arc::new(Column::new_with_schema(pk, &input_schema)?)
+                    as Arc<dyn PhysicalExpr>)
+            })
+            .collect::<Result<Vec<_>>>();
+        match hash_exprs {
+            Ok(hash_exprs) if !hash_exprs.is_empty() => {
+                vec![Distribution::HashPartitioned(hash_exprs)]
+            }
+            _ => vec![Distribution::UnspecifiedDistribution],
+        }
     }
 
     fn required_input_ordering(&self) -> Vec<Option<LexRequirement>> {
@@ -636,8 +3890,21 @@ impl ExecutionPlan for LakeSoulHashSinkExec {
         // More rationale:
         // https://github.com/apache/arrow-datafusion/pull/6354#discussion_r1195284178
         match &self.sort_order {
-            Some(requirements) => vec![Some(requirements.clone())],
-            None => vec![],
+            Some(requirements) if !self.sort_in_sink => vec![Some(requirements.clone())],
+            Some(_) => vec![],
+            // the clustered input keeps the rows of each partition sorted by the keys
+            None => self
+                .cluster_by_primary_keys
+                .and_then(|options| {
+                    primary_key_ordering(
+                        &self.input.schema(),
+                        &self.primary_keys,
+                        options,
+                    )
+                })
+                .map_or(vec![], |ordering| {
+                    vec![Some(LexRequirement::from(ordering))]
+                }),
         }
     }
 
@@ -647,8 +3914,8 @@ impl ExecutionPlan for LakeSoulHashSinkExec {
 
     fn benefits_from_input_partitioning(&self) -> Vec<bool> {
         // DataSink is responsible for dynamically partitioning its
-        // own input at execution time.
-        vec![false]
+        // own input at execution time, unless each input partition is written on its own.
+        vec![self.partitioned_output || self.cluster_by_primary_keys.is_some()]
     }
 
     fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
@@ -661,18 +3928,41 @@ impl ExecutionPlan for LakeSoulHashSinkExec {
     ) -> Result<Arc<dyn ExecutionPlan>> {
         println!("len is {}", children.len());
 
+        let input = if children.is_empty() {
+            self.input.clone()
+        } else {
+            children[0].clone()
+        };
+        let properties = Self::compute_properties(&input, self.partitioned_output);
         Ok(Arc::new(Self {
-            input: if children.is_empty() {
-                self.input.clone()
-            } else {
-                children[0].clone()
-            },
+            input,
             sink_schema: self.sink_schema.clone(),
             sort_order: self.sort_order.clone(),
+            sort_in_sink: self.sort_in_sink,
             table_info: self.table_info.clone(),
             range_partitions: self.range_partitions.clone(),
+            primary_keys: self.primary_keys.clone(),
+            hash_bucket_num: self.hash_bucket_num,
             metadata_client: self.metadata_client.clone(),
-            properties: self.properties.clone(),
+            max_file_size: self.max_file_size,
+            max_file_rows: self.max_file_rows,
+            max_buffered_bytes: self.max_buffered_bytes,
+            max_row_group_size: self.max_row_group_size,
+            write_options: self.write_options.clone(),
+            max_concurrent_writers: self.max_concurrent_writers,
+            write_id: self.write_id.clone(),
+            file_name_template: self.file_name_template.clone(),
+            merge_on_write: self.merge_on_write,
+            merge_operators: self.merge_operators.clone(),
+            commit_per_partition: self.commit_per_partition,
+            planned_versions: self.planned_versions.clone(),
+            partitioned_output: self.partitioned_output,
+            cluster_by_primary_keys: self.cluster_by_primary_keys,
+            sort_options: self.sort_options,
+            commit_hook: self.commit_hook.clone(),
+            progress_events: self.progress_events,
+            metrics: ExecutionPlanMetricsSet::new(),
+            properties,
         }))
     }
 
@@ -684,30 +3974,108 @@ impl ExecutionPlan for LakeSoulHashSinkExec {
         partition: usize,
         context: Arc<TaskContext>,
     ) -> Result<SendableRecordBatchStream> {
-        if partition != 0 {
+        let num_input_partitions = self.input.output_partitioning().partition_count();
+        debug!("num_input_partitions {}", num_input_partitions);
+        // each output partition writes its input partition, or partition 0 all of them
+        let input_partitions = if self.partitioned_output {
+            if partition >= num_input_partitions {
+                return Err(DataFusionError::Internal(format!(
+                    "Invalid partition {partition} of LakeSoulHashSinkExec with {num_input_partitions} input partitions"
+                )));
+            }
+            if self.merge_on_write {
+                return Err(DataFusionError::Plan(
+                    "Merge on write is not supported with a partitioned sink output"
+                        .to_string(),
+                ));
+            }
+            vec![partition]
+        } else if partition != 0 {
             return Err(DataFusionError::NotImplemented(
                 "FileSinkExec can only be called on partition 0!".to_string(),
             ));
-        }
-        let num_input_partitions = self.input.output_partitioning().partition_count();
-        debug!("num_input_partitions {}", num_input_partitions);
+        } else {
+            (0..num_input_partitions).collect()
+        };
         // launch one async task per *input* partition
         let mut join_handles = vec![];
+        let input = self.sink_input()?;
 
-        let write_id = rand::distr::Alphanumeric.sample_string(&mut rand::rng(), 16);
+        let write_id = match &self.write_id {
+            // the write id must not be confused with the file index or hash bucket id
+            Some(write_id)
+                if write_id.is_empty()
+                    || !write_id.chars().all(|c| c.is_ascii_alphanumeric()) =>
+            {
+                return Err(DataFusionError::Plan(format!(
+                    "Invalid write id '{}' of LakeSoulHashSinkExec, it must be alphanumeric",
+                    write_id
+                )));
+            }
+            Some(write_id) => write_id.clone(),
+            None => rand::distr::Alphanumeric.sample_string(&mut rand::rng(), 16),
+        };
+        let file_name_template = self
+            .file_name_template
+            .as_deref()
+            .map(|template| {
+                FileNameTemplate::try_new(template, !self.primary_keys.is_empty())
+                    .map(Arc::new)
+            })
+            .transpose()?;
+        if self.merge_on_write {
+            let (_, primary_keys) =
+                parse_table_info_partitions(&self.table_info.partitions)
+                    .map_err(|e| DataFusionError::External(Box::new(e)))?;
+            if primary_keys.is_empty() {
+                return Err(DataFusionError::Plan(format!(
+                    "Merge on write requires primary keys, table {} has none",
+                    self.table_info.table_name
+                )));
+            }
+        }
 
-        let partitioned_file_path_and_row_count =
-            Arc::new(Mutex::new(HashMap::<String, (Vec<String>, u64)>::new()));
-        for i in 0..num_input_partitions {
-            let sink_task = tokio::spawn(Self::pull_and_sink(
-                self.input().clone(),
+        let partitioned_file_path_and_row_count = Arc::new(Mutex::new(HashMap::<
+            String,
+            (Vec<(String, DataFileStats)>, u64),
+        >::new()));
+        // The spawned write and commit tasks outlive the output stream. The sender is
+        // dropped with the stream, telling them that the write was cancelled before its
+        // commit, so that they abort the open uploads and delete the flushed files.
+        let (cancel_sender, cancel_receiver) = watch::channel(());
+        // bound the number of input partitions holding open writers at the same time, the
+        // remaining tasks queue on the semaphore. The bytes buffered by the running ones are
+        // reserved from the memory pool of the session, which bounds them all together by
+        // flushing the largest writers of a partition when it cannot grow.
+        let writer_permits = Arc::new(Semaphore::new(self.max_concurrent_writers));
+        for i in input_partitions {
+            let permit = writer_permits.clone().acquire_owned();
+            let sink = Self::pull_and_sink(
+                input.clone(),
                 i,
                 context.clone(),
                 self.table_info(),
                 self.range_partitions.clone(),
+                self.primary_keys.clone(),
+                self.hash_bucket_num,
                 write_id.clone(),
+                file_name_template.clone(),
                 partitioned_file_path_and_row_count.clone(),
-            ));
+                self.max_file_size,
+                self.max_file_rows,
+                self.max_buffered_bytes,
+                self.max_row_group_size,
+                self.write_options.clone(),
+                self.sort_order.clone(),
+                SinkMetrics::new(&self.metrics, i),
+                cancel_receiver.clone(),
+            );
+            let sink_task = tokio::spawn(async move {
+                let _permit = permit
+                    .await
+                    .map_err(|e| DataFusionError::External(Box::new(e)))?;
+                sink.await
+            });
             // // In a separate task, wait for each input to be done
             // // (and pass along any errors, including panic!s)
             join_handles.push(sink_task);
@@ -717,17 +4085,31 @@ impl ExecutionPlan for LakeSoulHashSinkExec {
             schema: self.table_info().table_namespace.clone().into(),
             table: self.table_info().table_name.clone().into(),
         };
+        let (progress_sender, progress_receiver) = match self.progress_events {
+            true => {
+                let (sender, receiver) = mpsc::unbounded();
+                (Some(sender), Some(receiver))
+            }
+            false => (None, None),
+        };
         let join_handle = tokio::spawn(Self::wait_for_commit(
             join_handles,
             self.metadata_client(),
             table_ref.to_string(),
+            self.table_info(),
             partitioned_file_path_and_row_count,
+            self.merge_on_write.then(|| self.merge_operators.clone()),
+            self.commit_per_partition,
+            self.planned_versions.clone(),
+            write_id.clone(),
+            self.write_options.clone(),
+            context,
+            MetricBuilder::new(&self.metrics).subset_time("commit_time", partition),
+            cancel_receiver,
+            self.commit_hook.clone(),
+            progress_sender,
         ));
 
-        // });
-
-        // let abort_helper = Arc::new(AbortOnDropMany(join_handles));
-
         let sink_schema = self.sink_schema.clone();
         // let count = futures::future::join_all(join_handles).await;
         // for (columnar_values, result) in partitioned_file_path_and_row_count.lock().await.iter() {
@@ -737,39 +4119,173 @@ impl ExecutionPlan for LakeSoulHashSinkExec {
         //     }
         // }
 
+        let progress_write_id = write_id.clone();
         let stream = futures::stream::once(async move {
-            match join_handle.await {
-                Ok(Ok(count)) => Ok(make_sink_batch(count, String::from(""))),
+            let _cancel_sender = cancel_sender;
+            // the failed write is passed on as the typed error, see [`LakeSoulWriteError::find`]
+            let (success, count, msg, files) = match join_handle.await {
+                Ok(Ok((count, None, files))) => (true, count, String::new(), files),
+                Ok(Ok((count, Some(report), files))) => {
+                    let success = report.values().all(Result::is_ok);
+                    (success, count, partition_report_json(&report), files)
+                }
                 Ok(Err(e)) => {
                     debug!("{e:?}");
-                    Ok(make_sink_batch(u64::MAX, e.to_string()))
+                    return Err(e);
                 }
                 Err(e) => {
                     debug!("{e:?}");
-                    Ok(make_sink_batch(u64::MAX, e.to_string()))
+                    return Err(LakeSoulWriteError::TaskJoin(e).into());
                 }
-            }
-        })
-        .boxed();
+            };
+            Ok(make_sink_batch(success, Some(count), msg, &write_id, files))
+        });
+        // the progress ends with the write tasks, the result follows their commit
+        let stream = match progress_receiver {
+            Some(progress_receiver) => progress_receiver
+                .map(move |progress| Ok(progress.to_batch(&progress_write_id)))
+                .chain(stream)
+                .boxed(),
+            None => stream.boxed(),
+        };
 
         Ok(Box::pin(RecordBatchStreamAdapter::new(sink_schema, stream)))
     }
+
+    fn metrics(&self) -> Option<MetricsSet> {
+        Some(self.metrics.clone_inner())
+    }
+}
+
+/// The paths of the files of each partition.
+pub(super) fn file_paths_by_partition(
+    partitioned_files: &[(String, Vec<(String, DataFileStats)>)],
+) -> Vec<(String, Vec<String>)> {
+    partitioned_files
+        .iter()
+        .map(|(partition_desc, files)| {
+            let paths = files.iter().map(|(path, _)| path.clone()).collect();
+            (partition_desc.clone(), paths)
+        })
+        .collect()
 }
 
-fn make_sink_batch(count: u64, msg: String) -> RecordBatch {
+/// Make the result batch of the sink, with the write id and the paths of the committed
+/// files for validating or indexing the written files.
+///
+/// The batch fails only when the partitions are committed independently and some of them
+/// failed, with the commit report of the partitions as `msg`, otherwise a failed write is
+/// an error of the stream.
+fn make_sink_batch(
+    success: bool,
+    count: Option<u64>,
+    msg: String,
+    write_id: &str,
+    files: Vec<String>,
+) -> RecordBatch {
     let count_array = Arc::new(UInt64Array::from(vec![count])) as ArrayRef;
     let msg_array = Arc::new(StringArray::from(vec![msg])) as ArrayRef;
+    let success_array = Arc::new(BooleanArray::from(vec![success])) as ArrayRef;
+    let write_id_array = Arc::new(StringArray::from(vec![write_id])) as ArrayRef;
+    let mut files_builder = ListBuilder::new(StringBuilder::new());
+    files_builder.append_value(files.into_iter().map(Some));
+    let files_array = Arc::new(files_builder.finish()) as ArrayRef;
     RecordBatch::try_from_iter_with_nullable(vec![
-        ("count", count_array, false),
+        ("count", count_array, true),
         ("msg", msg_array, false),
+        ("success", success_array, false),
+        ("write_id", write_id_array, false),
+        ("files", files_array, false),
     ])
     .unwrap()
 }
 
+/// The progress of a write, reported after each written input partition.
+#[derive(Debug, Clone, Copy)]
+struct SinkProgress {
+    /// The number of rows written by the completed input partitions.
+    rows: u64,
+    /// The number of completed input partitions.
+    completed_partitions: usize,
+    /// The number of written input partitions.
+    partitions: usize,
+}
+
+impl SinkProgress {
+    /// Make the progress row of the sink output.
+    fn to_batch(self, write_id: &str) -> RecordBatch {
+        let msg = serde_json::json!({
+            "progress": {
+                "rows": self.rows,
+                "completed_partitions": self.completed_partitions,
+                "partitions": self.partitions,
+            }
+        });
+        make_sink_batch(true, None, msg.to_string(), write_id, vec![])
+    }
+}
+
+/// The commit status of each written partition by partition descriptor, the number of
+/// committed rows or the error of the failed commit.
+type PartitionCommitReport = BTreeMap<String, std::result::Result<u64, String>>;
+
+/// Serialize the commit report of the partitions into the JSON `msg` of the sink batch.
+fn partition_report_json(report: &PartitionCommitReport) -> String {
+    serde_json::Value::Object(
+        report
+            .iter()
+            .map(|(partition_desc, status)| {
+                let status = match status {
+                    Ok(rows) => serde_json::json!({ "success": true, "rows": rows }),
+                    Err(e) => serde_json::json!({ "success": false, "error": e }),
+                };
+                (partition_desc.clone(), status)
+            })
+            .collect(),
+    )
+    .to_string()
+}
+
 fn make_sink_schema() -> SchemaRef {
     // define a schema.
     Arc::new(Schema::new(vec![
-        Field::new("count", DataType::UInt64, false),
+        Field::new("count", DataType::UInt64, true),
         Field::new("msg", DataType::Utf8, false),
+        Field::new("success", DataType::Boolean, false),
+        Field::new("write_id", DataType::Utf8, false),
+        Field::new(
+            "files",
+            DataType::List(Arc::new(Field::new_list_field(DataType::Utf8, true))),
+            false,
+        ),
     ]))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_scan_schema() {
+        let int_field = |name: &str| Field::new(name, DataType::Int32, true);
+        let target = Schema::new(vec![int_field("hash"), int_field("value")]);
+        let nullable = Schema::new(vec![
+            Field::new("hash", DataType::Int32, false),
+            int_field("value"),
+        ]);
+        assert!(validate_scan_schema(&target, &nullable).is_ok());
+
+        // a drift of the output schema is reported column by column
+        let swapped = Schema::new(vec![int_field("value"), int_field("hash")]);
+        let err = validate_scan_schema(&target, &swapped)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("column 0: expected hash: Int32, found value: Int32"));
+        assert!(err.contains("column 1: expected value: Int32, found hash: Int32"));
+        let err = validate_scan_schema(&Schema::new(vec![int_field("value")]), &swapped)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("column 1: expected none, found hash: Int32"));
+        assert!(!err.contains("column 0"));
+    }
+}
@@ -2,6 +2,20 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+mod compaction;
 mod metadata_format;
+#[cfg(feature = "validate-pruning")]
+mod pruning_validation;
+mod streaming_sink;
 
-pub use metadata_format::LakeSoulMetaDataParquetFormat;
+pub use compaction::LakeSoulCompactionExec;
+pub use metadata_format::{
+    CommitHook, LakeSoulMetaDataParquetFormat, LakeSoulMetaDataParquetFormatBuilder,
+    ScanFile,
+};
+pub(crate) use metadata_format::{
+    LakeSoulHashSinkExec, RegisteredCommitHook, union_file_scans,
+};
+#[cfg(feature = "validate-pruning")]
+pub use pruning_validation::{PruningValidationExec, invalid_pruning_count};
+pub use streaming_sink::LakeSoulStreamingSink;
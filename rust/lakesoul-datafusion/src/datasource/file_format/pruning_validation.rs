@@ -0,0 +1,200 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Implementation of the execution plan validating the pruning of a table scan by
+//! statistics, compiled with the `validate-pruning` feature only.
+
+use std::any::Any;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use arrow::datatypes::SchemaRef;
+use arrow::record_batch::RecordBatch;
+use datafusion::common::{DataFusionError, Result, Statistics};
+use datafusion::execution::TaskContext;
+use datafusion::physical_expr::PhysicalExpr;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::{
+    DisplayAs, DisplayFormatType, ExecutionPlan, PlanProperties,
+    SendableRecordBatchStream, collect,
+};
+use futures::StreamExt;
+
+/// The number of validated scans whose pruning dropped rows matching their filters.
+static INVALID_PRUNINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the number of scans whose pruning by statistics was found invalid by a
+/// [`PruningValidationExec`] since the start of the process.
+pub fn invalid_pruning_count() -> usize {
+    INVALID_PRUNINGS.load(Ordering::Relaxed)
+}
+
+/// [`ExecutionPlan`] implementation which passes the batches of a table scan through, after
+/// comparing the numbers of rows matching the filters of the scan with and without the
+/// pruning of the files and row groups by their statistics.
+///
+/// The compared scans are run once, before the first partition of the scan is read. A
+/// difference is logged as a warning and counted, see [`invalid_pruning_count`], as the
+/// pruning skipped rows matching the filters.
+#[derive(Debug)]
+pub struct PruningValidationExec {
+    /// The scan of the table.
+    input: Arc<dyn ExecutionPlan>,
+    /// The pruned scan of all columns, filtered by the filters of the scan.
+    pruned: Arc<dyn ExecutionPlan>,
+    /// The unpruned scan of all columns, filtered by the filters of the scan.
+    unpruned: Arc<dyn ExecutionPlan>,
+    /// The name of the scanned table.
+    table_name: String,
+    /// The filters of the scan.
+    filters: Arc<dyn PhysicalExpr>,
+}
+
+impl PruningValidationExec {
+    /// Create a new [`PruningValidationExec`].
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The scan of the table
+    /// * `pruned` - The pruned scan filtered by the filters
+    /// * `unpruned` - The unpruned scan filtered by the filters
+    /// * `table_name` - The name of the scanned table
+    /// * `filters` - The filters of the scan
+    pub fn new(
+        input: Arc<dyn ExecutionPlan>,
+        pruned: Arc<dyn ExecutionPlan>,
+        unpruned: Arc<dyn ExecutionPlan>,
+        table_name: String,
+        filters: Arc<dyn PhysicalExpr>,
+    ) -> Self {
+        Self {
+            input,
+            pruned,
+            unpruned,
+            table_name,
+            filters,
+        }
+    }
+}
+
+/// Count the rows of all partitions of the plan.
+async fn count_rows(
+    plan: Arc<dyn ExecutionPlan>,
+    context: Arc<TaskContext>,
+) -> Result<usize> {
+    let batches = collect(plan, context).await?;
+    Ok(batches.iter().map(RecordBatch::num_rows).sum())
+}
+
+/// Compare the rows of the pruned and unpruned scans, see [`PruningValidationExec`].
+async fn validate_pruning(
+    pruned: Arc<dyn ExecutionPlan>,
+    unpruned: Arc<dyn ExecutionPlan>,
+    table_name: String,
+    filters: Arc<dyn PhysicalExpr>,
+    context: Arc<TaskContext>,
+) -> Result<()> {
+    let pruned_rows = count_rows(pruned, context.clone()).await?;
+    let unpruned_rows = count_rows(unpruned, context).await?;
+    if pruned_rows != unpruned_rows {
+        INVALID_PRUNINGS.fetch_add(1, Ordering::Relaxed);
+        warn!(
+            "pruning of the scan of table {} with filters {} is invalid: {} rows match \
+            with pruning, {} rows without",
+            table_name, filters, pruned_rows, unpruned_rows
+        );
+    } else {
+        debug!(
+            "pruning of the scan of table {} is valid, {} rows match",
+            table_name, pruned_rows
+        );
+    }
+    Ok(())
+}
+
+impl DisplayAs for PruningValidationExec {
+    fn fmt_as(
+        &self,
+        _t: DisplayFormatType,
+        f: &mut std::fmt::Formatter,
+    ) -> std::fmt::Result {
+        write!(f, "PruningValidationExec: filters={}", self.filters)
+    }
+}
+
+impl ExecutionPlan for PruningValidationExec {
+    fn name(&self) -> &str {
+        "PruningValidationExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        self.input.properties()
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.input]
+    }
+
+    fn maintains_input_order(&self) -> Vec<bool> {
+        vec![true]
+    }
+
+    fn benefits_from_input_partitioning(&self) -> Vec<bool> {
+        vec![false]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        mut children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        if children.len() != 1 {
+            return Err(DataFusionError::Internal(format!(
+                "PruningValidationExec requires exactly one child, got {}",
+                children.len()
+            )));
+        }
+        Ok(Arc::new(Self::new(
+            children.remove(0),
+            self.pruned.clone(),
+            self.unpruned.clone(),
+            self.table_name.clone(),
+            self.filters.clone(),
+        )))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        let input = self.input.execute(partition, context.clone())?;
+        if partition != 0 {
+            return Ok(input);
+        }
+        let validation = futures::stream::once(validate_pruning(
+            self.pruned.clone(),
+            self.unpruned.clone(),
+            self.table_name.clone(),
+            self.filters.clone(),
+            context,
+        ))
+        .filter_map(|result| async move { result.err().map(Err::<RecordBatch, _>) });
+        Ok(Box::pin(RecordBatchStreamAdapter::new(
+            self.schema(),
+            validation.chain(input),
+        )))
+    }
+
+    fn statistics(&self) -> Result<Statistics> {
+        self.input.statistics()
+    }
+}
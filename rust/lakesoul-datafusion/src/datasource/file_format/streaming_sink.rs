@@ -0,0 +1,575 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! The sink writing a stream of micro-batches into a LakeSoul table, committing them
+//! periodically.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use arrow::datatypes::SchemaRef;
+use arrow::record_batch::RecordBatch;
+use datafusion::common::ScalarValue;
+use datafusion::error::Result;
+use datafusion::execution::TaskContext;
+use datafusion::physical_expr::PhysicalExpr;
+use datafusion::physical_expr::expressions::Column;
+use datafusion::physical_plan::metrics::Time;
+use datafusion::physical_plan::{Partitioning, SendableRecordBatchStream};
+use datafusion::sql::TableReference;
+use futures::StreamExt;
+use lakesoul_io::async_writer::{AsyncBatchWriter, SortAsyncWriter};
+use lakesoul_io::helpers::{
+    columnar_values_to_partition_desc, generate_batch_columns, get_columnar_values,
+};
+use lakesoul_io::lakesoul_io_config::{
+    OPTION_KEY_KEEP_PARTITION_COLUMNS, OPTION_KEY_STATISTICS_DISABLED_COLUMNS,
+};
+use lakesoul_io::repartition::BatchPartitioner;
+use lakesoul_metadata::MetaDataClientRef;
+use log::debug;
+use proto::proto::entity::TableInfo;
+use rand::distr::SampleString;
+
+use crate::catalog::{LakeSoulTableProperty, parse_table_info_partitions};
+use crate::datasource::statistics::DataFileStats;
+use crate::error::LakeSoulWriteError;
+use crate::lakesoul_table::helpers::create_io_config_builder_from_table_info;
+use crate::serialize::arrow_java::schema_from_metadata_str;
+
+use super::metadata_format::{
+    CommitHook, LakeSoulHashSinkExec, RegisteredCommitHook,
+    conform_batch_to_table_schema, create_writer, delete_data_files,
+    parse_data_file_format, record_flushed_file,
+};
+
+/// The open file of a range partition and hash bucket in [`LakeSoulStreamingSink`].
+struct OpenFile {
+    /// The writer of the file.
+    writer: Box<dyn AsyncBatchWriter + Send>,
+    /// The schema of the batches written into the file.
+    schema: SchemaRef,
+    /// The absolute path of the file.
+    file_path: String,
+    /// The statistics of the file.
+    stats: DataFileStats,
+}
+
+/// A long-lived sink writing micro-batches into a LakeSoul table, for continuous ingest.
+///
+/// Unlike the `LakeSoulHashSinkExec`, which writes and commits its whole input within one
+/// execution, the sink keeps the file of each range partition and hash bucket open across
+/// the written batches, and commits the files of all partitions in one transaction once
+/// the commit interval has elapsed or the number of uncommitted rows is reached, or when
+/// [`Self::commit`] is called. [`Self::write_stream`] commits on a timer, also while its
+/// input is idle. The commit overhead is thus paid once per interval instead
+/// of once per micro-batch. The files are committed with their statistics like the files
+/// of the hash sink. The rows of a primary key table are sorted on the primary keys
+/// within each file, buffering them within the memory pool of the context until the
+/// commit.
+///
+/// # Crash recovery
+///
+/// The written rows only become visible with the commit of their files. After a crash,
+/// the rows written since the last successful commit are lost: their files are either
+/// incomplete uploads or complete files never registered into the metadata, which are
+/// never read and are removed as orphan files by
+/// [`LakeSoulTable::vacuum`](crate::lakesoul_table::LakeSoulTable::vacuum). A source
+/// should therefore only acknowledge its input up to the last commit, e.g. store its
+/// offsets once [`Self::commit`] returns, and replay the rest after a restart. The
+/// replayed rows are upserted again on the primary keys, while a table without primary
+/// keys gets them appended twice if the crash happens between the commit and storing the
+/// offsets. A failed commit deletes the files of its rows, which are to be replayed the
+/// same way, and the sink goes on with the next batches. Dropping the sink discards the
+/// uncommitted rows, aborting the uploads of their files in the background.
+pub struct LakeSoulStreamingSink {
+    /// The metadata client.
+    client: MetaDataClientRef,
+    /// The table written into.
+    table_info: Arc<TableInfo>,
+    /// The context of the writers, providing the object stores and the memory pool.
+    context: Arc<TaskContext>,
+    /// The schema of the table.
+    table_schema: SchemaRef,
+    /// The range partitions.
+    range_partitions: Arc<Vec<String>>,
+    /// The primary keys.
+    primary_keys: Vec<String>,
+    /// The number of hash buckets of the table.
+    hash_bucket_num: usize,
+    /// The SQL expressions of the generated columns of the table by their names.
+    generated_columns: HashMap<String, String>,
+    /// Whether the table keeps the range partition columns in its data files.
+    keep_partition_columns: bool,
+    /// The io config options of the written files.
+    write_options: Arc<HashMap<String, String>>,
+    /// The time after which the written rows are committed.
+    commit_interval: Duration,
+    /// The number of uncommitted rows after which they are committed.
+    commit_rows: Option<u64>,
+    /// The random id embedded in the names of the written files.
+    write_id: String,
+    /// The number of commits so far, embedded in the names of the written files.
+    epoch: usize,
+    /// The open files, keyed by partition desc and hash bucket id.
+    open_files: HashMap<(String, usize), OpenFile>,
+    /// The number of rows written since the last commit.
+    uncommitted_rows: u64,
+    /// The time of the first row written since the last commit.
+    uncommitted_since: Option<Instant>,
+    /// The hook invoked after each commit, see [`Self::with_commit_hook`].
+    commit_hook: Option<RegisteredCommitHook>,
+}
+
+impl Debug for LakeSoulStreamingSink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "LakeSoulStreamingSink table: {}, open files: {}, uncommitted rows: {}",
+            self.table_info.table_name,
+            self.open_files.len(),
+            self.uncommitted_rows
+        )
+    }
+}
+
+impl LakeSoulStreamingSink {
+    /// Create a new [`LakeSoulStreamingSink`], committing every minute by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The metadata client
+    /// * `table_info` - The table to write into
+    /// * `context` - The context of the writers
+    pub fn try_new(
+        client: MetaDataClientRef,
+        table_info: Arc<TableInfo>,
+        context: Arc<TaskContext>,
+    ) -> Result<Self> {
+        let (range_partitions, primary_keys) =
+            parse_table_info_partitions(&table_info.partitions)
+                .map_err(|e| LakeSoulWriteError::Serialization(Box::new(e)))?;
+        let properties =
+            serde_json::from_str::<LakeSoulTableProperty>(&table_info.properties)
+                .map_err(|e| LakeSoulWriteError::Serialization(Box::new(e)))?;
+        Ok(Self {
+            client,
+            table_schema: schema_from_metadata_str(&table_info.table_schema),
+            table_info,
+            context,
+            range_partitions: Arc::new(range_partitions),
+            primary_keys,
+            hash_bucket_num: properties.hash_bucket_num.unwrap_or(1).max(1),
+            generated_columns: properties.generated_columns.unwrap_or_default(),
+            keep_partition_columns: properties.keep_partition_columns.unwrap_or(false),
+            write_options: Default::default(),
+            commit_interval: Duration::from_secs(60),
+            commit_rows: None,
+            write_id: rand::distr::Alphanumeric.sample_string(&mut rand::rng(), 16),
+            epoch: 0,
+            open_files: HashMap::new(),
+            uncommitted_rows: 0,
+            uncommitted_since: None,
+            commit_hook: None,
+        })
+    }
+
+    /// Commit the written rows once the interval has elapsed since the first uncommitted
+    /// row.
+    pub fn with_commit_interval(mut self, commit_interval: Duration) -> Self {
+        self.commit_interval = commit_interval;
+        self
+    }
+
+    /// Commit the written rows once their number reaches `commit_rows`.
+    pub fn with_commit_rows(mut self, commit_rows: Option<u64>) -> Self {
+        self.commit_rows = commit_rows;
+        self
+    }
+
+    /// Set an io config option of the written files, e.g. the parquet compression codec.
+    pub fn with_write_option(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        Arc::make_mut(&mut self.write_options).insert(key.into(), value.into());
+        self
+    }
+
+    /// Invoke the hook for each committed partition once the written files are committed.
+    ///
+    /// Its errors are logged, and with `fail_on_error` they also fail the commit. The files
+    /// stay committed in any case, so the failed commit is not to be replayed.
+    pub fn with_commit_hook(
+        self,
+        commit_hook: Arc<dyn CommitHook>,
+        fail_on_error: bool,
+    ) -> Self {
+        self.with_registered_commit_hook(RegisteredCommitHook::new(
+            commit_hook,
+            fail_on_error,
+        ))
+    }
+
+    pub(crate) fn with_registered_commit_hook(
+        mut self,
+        commit_hook: RegisteredCommitHook,
+    ) -> Self {
+        self.commit_hook = Some(commit_hook);
+        self
+    }
+
+    /// The number of rows written since the last commit.
+    pub fn uncommitted_rows(&self) -> u64 {
+        self.uncommitted_rows
+    }
+
+    /// Write the batch into the open files of its partitions, committing the written rows
+    /// if they are due. Returns whether they were committed.
+    pub async fn write_batch(&mut self, batch: RecordBatch) -> Result<bool> {
+        // the generated range partition columns decide the partitions of the rows
+        let batch = match self.generated_columns.is_empty() {
+            true => batch,
+            false => generate_batch_columns(
+                &batch,
+                &self.generated_columns,
+                &self.table_schema,
+            )?,
+        };
+        let batch = conform_batch_to_table_schema(batch, &self.table_schema)?;
+        if batch.num_rows() > 0 {
+            for (hash_bucket_id, batch) in self.partition_batch(batch)? {
+                self.write_partition_batch(hash_bucket_id, batch).await?;
+            }
+        }
+        self.commit_if_due().await
+    }
+
+    /// Write the batches of the stream until it ends, then commit the rows left.
+    ///
+    /// The written rows are committed once their number is reached, and once the commit
+    /// interval elapses by a timer, so that they are committed on time while the input is
+    /// idle.
+    pub async fn write_stream(
+        &mut self,
+        mut input: SendableRecordBatchStream,
+    ) -> Result<()> {
+        loop {
+            let deadline = self
+                .uncommitted_since
+                .map(|since| tokio::time::Instant::from(since + self.commit_interval));
+            let batch = tokio::select! {
+                batch = input.next() => batch,
+                _ = sleep_until(deadline) => {
+                    self.commit().await?;
+                    continue;
+                }
+            };
+            let Some(batch) = batch.transpose()? else {
+                break;
+            };
+            self.write_batch(batch).await?;
+        }
+        self.commit().await?;
+        Ok(())
+    }
+
+    /// Commit the written rows if the commit interval has elapsed or the number of
+    /// uncommitted rows is reached, e.g. to commit on time while the input is idle.
+    /// Returns whether they were committed.
+    pub async fn commit_if_due(&mut self) -> Result<bool> {
+        let due = self.uncommitted_rows > 0
+            && (self
+                .uncommitted_since
+                .is_some_and(|since| since.elapsed() >= self.commit_interval)
+                || self
+                    .commit_rows
+                    .is_some_and(|rows| self.uncommitted_rows >= rows));
+        if due {
+            self.commit().await?;
+        }
+        Ok(due)
+    }
+
+    /// Close the open files and commit them in one transaction, returning the number of
+    /// committed rows. The next written batches go into new files.
+    pub async fn commit(&mut self) -> Result<u64> {
+        let open_files = std::mem::take(&mut self.open_files);
+        let num_rows = std::mem::take(&mut self.uncommitted_rows);
+        self.uncommitted_since = None;
+        self.epoch += 1;
+        if open_files.is_empty() {
+            return Ok(0);
+        }
+
+        let file_paths = open_files
+            .values()
+            .map(|file| file.file_path.clone())
+            .collect::<Vec<_>>();
+        let mut partitioned_files =
+            BTreeMap::<String, Vec<(String, DataFileStats)>>::new();
+        let mut flush_error = None;
+        for ((partition_desc, _), file) in open_files {
+            if flush_error.is_some() {
+                Self::abort_files(std::iter::once(file)).await;
+                continue;
+            }
+            let OpenFile {
+                writer,
+                file_path,
+                mut stats,
+                ..
+            } = file;
+            match writer.flush_and_close().await {
+                Ok(flush_result) => {
+                    record_flushed_file(&mut stats, &flush_result);
+                    partitioned_files
+                        .entry(partition_desc)
+                        .or_default()
+                        .push((file_path, stats));
+                }
+                Err(e) => flush_error = Some(e),
+            }
+        }
+        if let Some(e) = flush_error {
+            delete_data_files(&self.context, &self.table_info, file_paths).await;
+            return Err(e);
+        }
+
+        let table_ref = TableReference::Partial {
+            schema: self.table_info.table_namespace.clone().into(),
+            table: self.table_info.table_name.clone().into(),
+        };
+        let committed = match LakeSoulHashSinkExec::commit_partitions(
+            self.client.clone(),
+            &table_ref.to_string(),
+            self.table_info.clone(),
+            partitioned_files.into_iter().collect(),
+            None,
+            None,
+            &self.write_id,
+            self.write_options.clone(),
+            &self.context,
+        )
+        .await
+        {
+            Ok(committed) => committed,
+            Err(e) => {
+                debug!(
+                    "table: {} streaming commit failed, delete {} files: {}",
+                    table_ref,
+                    file_paths.len(),
+                    e
+                );
+                delete_data_files(&self.context, &self.table_info, file_paths).await;
+                return Err(e);
+            }
+        };
+        debug!(
+            "table: {} streaming commit of {} rows in {} files",
+            table_ref,
+            num_rows,
+            file_paths.len()
+        );
+        LakeSoulHashSinkExec::run_commit_hook(
+            self.commit_hook.as_ref(),
+            &table_ref.to_string(),
+            committed,
+        )
+        .await?;
+        Ok(num_rows)
+    }
+
+    /// Abort the open files, discarding the rows written since the last commit.
+    pub async fn abort(mut self) {
+        Self::abort_files(self.open_files.drain().map(|(_, file)| file)).await;
+    }
+
+    async fn abort_files(files: impl Iterator<Item = OpenFile>) {
+        for file in files {
+            if let Err(e) = file.writer.abort_and_close().await {
+                debug!("failed to abort file {}: {}", file.file_path, e);
+            }
+        }
+    }
+
+    /// Split the batch by range partition and hash bucket, the way the input of the hash
+    /// sink is split by the `RepartitionByRangeAndHashExec` below it.
+    fn partition_batch(&self, batch: RecordBatch) -> Result<Vec<(usize, RecordBatch)>> {
+        let schema = batch.schema();
+        let columns = |names: &[String]| {
+            names
+                .iter()
+                .map(|name| {
+                    Ok(Arc::new(Column::new_with_schema(name, &schema)?)
+                        as Arc<dyn PhysicalExpr>)
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        let mut partitioner = BatchPartitioner::try_new(
+            columns(&self.range_partitions)?,
+            Partitioning::Hash(columns(&self.primary_keys)?, self.hash_bucket_num),
+            Time::new(),
+        )?;
+        let mut batches = vec![];
+        partitioner.partition(batch, |hash_bucket_id, batch| {
+            batches.push((hash_bucket_id, batch));
+            Ok(())
+        })?;
+        Ok(batches)
+    }
+
+    /// Write the batch of a single range partition and hash bucket into its open file.
+    async fn write_partition_batch(
+        &mut self,
+        hash_bucket_id: usize,
+        batch: RecordBatch,
+    ) -> Result<()> {
+        if batch.num_rows() == 0 {
+            return Ok(());
+        }
+        let columnar_values = get_columnar_values(&batch, self.range_partitions.clone())?;
+        let partition_desc = columnar_values_to_partition_desc(&columnar_values);
+        // the range partition columns are encoded into the file paths, and only written
+        // into the files on request, the write options overriding the table
+        let keep_partition_columns = self
+            .write_options
+            .get(OPTION_KEY_KEEP_PARTITION_COLUMNS)
+            .map_or(self.keep_partition_columns, |keep| keep == "true");
+        let projection = batch
+            .schema()
+            .fields()
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| {
+                keep_partition_columns || !self.range_partitions.contains(field.name())
+            })
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+        let batch = batch.project(&projection)?;
+
+        let key = (partition_desc, hash_bucket_id);
+        if self
+            .open_files
+            .get(&key)
+            .is_some_and(|file| file.schema != batch.schema())
+        {
+            // the open files are committed before writing a batch with other columns
+            self.commit().await?;
+        }
+        if !self.open_files.contains_key(&key) {
+            let file = self
+                .open_file(&columnar_values, hash_bucket_id, batch.schema())
+                .await?;
+            self.open_files.insert(key.clone(), file);
+        }
+        let Some(file) = self.open_files.get_mut(&key) else {
+            return Ok(());
+        };
+        file.stats.update(&batch)?;
+        self.uncommitted_rows += batch.num_rows() as u64;
+        self.uncommitted_since.get_or_insert_with(Instant::now);
+        file.writer.write_record_batch(batch).await
+    }
+
+    /// Open the file of a range partition and hash bucket of the current epoch.
+    async fn open_file(
+        &self,
+        columnar_values: &[(String, ScalarValue)],
+        hash_bucket_id: usize,
+        schema: SchemaRef,
+    ) -> Result<OpenFile> {
+        let data_file_format = parse_data_file_format(&self.write_options)?;
+        // NaN may show up in any later batch, so the min/max of the floating point
+        // columns are not collected, see `get_columns_with_nan`
+        let statistics_disabled_columns = schema
+            .fields()
+            .iter()
+            .filter(|field| field.data_type().is_floating())
+            .map(|field| field.name().clone())
+            .collect::<HashSet<_>>();
+        let mut options = self.write_options.as_ref().clone();
+        options.insert(
+            OPTION_KEY_STATISTICS_DISABLED_COLUMNS.to_string(),
+            statistics_disabled_columns
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        let builder = create_io_config_builder_from_table_info(
+            self.table_info.clone(),
+            options,
+            HashMap::new(),
+        )
+        .map_err(|e| LakeSoulWriteError::Serialization(Box::new(e)))?
+        .with_schema(schema.clone());
+        // the encoding of the table applies unless the write options override it
+        let path_encoder = builder.clone().build().partition_path_encoder()?;
+        // The hash bucket id must stay the last number of the file name.
+        let file_path = format!(
+            "{}{}part-{}-{:0>4}_{:0>4}.{}",
+            self.table_info.table_path,
+            path_encoder.encode(columnar_values),
+            self.write_id,
+            self.epoch,
+            hash_bucket_id,
+            data_file_format.extension(),
+        );
+        let mut config = builder.with_files(vec![file_path.clone()]).build();
+        let writer =
+            create_writer(data_file_format, &mut config, self.context.clone()).await?;
+        let writer = if self.primary_keys.is_empty() {
+            writer
+        } else {
+            // the rows of all micro-batches of the file are sorted on the primary keys
+            Box::new(SortAsyncWriter::try_new_with_writer(
+                writer,
+                self.context.clone(),
+                config.clone(),
+            )?)
+        };
+        // the plaintext min/max of the encrypted columns are not stored
+        let mut stats_excluded_columns = statistics_disabled_columns;
+        stats_excluded_columns.extend(config.encrypted_columns());
+        let stats = DataFileStats::try_new(&schema, &stats_excluded_columns)?;
+        Ok(OpenFile {
+            writer,
+            schema,
+            file_path,
+            stats,
+        })
+    }
+}
+
+impl Drop for LakeSoulStreamingSink {
+    fn drop(&mut self) {
+        if self.open_files.is_empty() {
+            return;
+        }
+        let files = std::mem::take(&mut self.open_files);
+        // the uploads of the uncommitted files would be left behind otherwise
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(Self::abort_files(files.into_values()));
+            }
+            Err(_) => debug!(
+                "table: {} no runtime to abort {} uncommitted files",
+                self.table_info.table_name,
+                files.len()
+            ),
+        }
+    }
+}
+
+/// Sleep until the deadline, forever without one.
+async fn sleep_until(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
@@ -4,6 +4,80 @@
 
 //! The [`datafusion::datasource`] implementation for the LakeSoul.
 
+pub mod delete_vector;
+pub mod delta;
+pub mod external_parquet;
 pub mod file_format;
+pub mod statistics;
+pub mod substrait;
 pub mod table_factory;
 pub mod table_provider;
+
+use std::collections::HashMap;
+
+use datafusion::logical_expr::Expr;
+use datafusion::logical_expr::utils::conjunction;
+use datafusion::physical_plan::SendableRecordBatchStream;
+use datafusion::sql::TableReference;
+use lakesoul_io::lakesoul_io_config::create_session_context;
+use lakesoul_metadata::MetaDataClientRef;
+
+use crate::catalog::create_io_config_builder;
+use crate::error::Result;
+use crate::lakesoul_table::LakeSoulTable;
+
+/// Scan the LakeSoul table, returning the stream of the rows of the columns matching all
+/// the filters.
+///
+/// The scan runs in a session of its own, so the rows can be read without setting up a
+/// DataFusion session. All the columns are read if `columns` is empty.
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn example(
+/// #     client: lakesoul_metadata::MetaDataClientRef,
+/// # ) -> lakesoul_datafusion::Result<()> {
+/// use datafusion::prelude::{col, lit};
+/// use futures::TryStreamExt;
+///
+/// let stream = lakesoul_datafusion::datasource::scan(
+///     client,
+///     "default.orders",
+///     &["id", "amount"],
+///     vec![col("amount").gt(lit(100))],
+/// )
+/// .await?;
+/// let batches = stream.try_collect::<Vec<_>>().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn scan(
+    client: MetaDataClientRef,
+    table_ref: impl Into<TableReference>,
+    columns: &[&str],
+    filters: Vec<Expr>,
+) -> Result<SendableRecordBatchStream> {
+    let table =
+        LakeSoulTable::for_table_reference(&table_ref.into(), Some(client.clone()))
+            .await?;
+    let mut config = create_io_config_builder(
+        client,
+        Some(table.table_name()),
+        true,
+        table.table_namespace(),
+        HashMap::new(),
+        HashMap::new(),
+    )
+    .await?
+    .build();
+    let context = create_session_context(&mut config)?;
+    let mut dataframe = table.to_dataframe(&context).await?;
+    if let Some(filter) = conjunction(filters) {
+        dataframe = dataframe.filter(filter)?;
+    }
+    if !columns.is_empty() {
+        dataframe = dataframe.select_columns(columns)?;
+    }
+    Ok(dataframe.execute_stream().await?)
+}
@@ -0,0 +1,362 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! The statistics of data files stored in the LakeSoul metadata.
+
+use std::collections::{BTreeMap, HashSet};
+
+use arrow::datatypes::{DataType, Schema};
+use arrow::record_batch::RecordBatch;
+use datafusion::common::stats::Precision;
+use datafusion::common::{ColumnStatistics, ScalarValue, Statistics};
+use datafusion::error::Result;
+use datafusion::functions_aggregate::min_max::{MaxAccumulator, MinAccumulator};
+use datafusion::logical_expr::Accumulator;
+use serde::{Deserialize, Serialize};
+
+/// The statistics of a single column stored in the metadata.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredColumnStatistics {
+    /// The number of null values.
+    pub null_count: Option<u64>,
+    /// The minimum value, formatted as string.
+    pub min: Option<String>,
+    /// The maximum value, formatted as string.
+    pub max: Option<String>,
+}
+
+/// The statistics of a data file stored in the metadata.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredFileStatistics {
+    /// The number of rows of the file.
+    pub num_rows: Option<u64>,
+    /// The total byte size of the file.
+    pub total_byte_size: Option<u64>,
+    /// The statistics of each column, keyed by column name.
+    pub columns: BTreeMap<String, StoredColumnStatistics>,
+    /// The hex encoded md5 checksum of the file, recorded when the file is written.
+    pub checksum: Option<String>,
+    /// The compression codec of the column chunks of the file, e.g. `SNAPPY` or `ZSTD`,
+    /// recorded when a parquet file is written.
+    pub compression: Option<String>,
+}
+
+/// Returns whether a value of the data type survives the round trip through its string format.
+fn is_string_round_trip_type(data_type: &DataType) -> bool {
+    data_type.is_numeric()
+        || matches!(
+            data_type,
+            DataType::Boolean
+                | DataType::Utf8
+                | DataType::LargeUtf8
+                | DataType::Utf8View
+                | DataType::Date32
+                | DataType::Date64
+                | DataType::Timestamp(_, _)
+        )
+}
+
+fn value_to_string(value: &Precision<ScalarValue>) -> Option<String> {
+    match value.get_value() {
+        Some(value)
+            if !value.is_null() && is_string_round_trip_type(&value.data_type()) =>
+        {
+            Some(value.to_string())
+        }
+        _ => None,
+    }
+}
+
+fn string_to_value(
+    value: &Option<String>,
+    data_type: &DataType,
+) -> Precision<ScalarValue> {
+    match value {
+        Some(value) if is_string_round_trip_type(data_type) => {
+            ScalarValue::try_from_string(value.clone(), data_type)
+                .map(Precision::Inexact)
+                .unwrap_or(Precision::Absent)
+        }
+        _ => Precision::Absent,
+    }
+}
+
+impl StoredFileStatistics {
+    /// Create the stored statistics from the [`Statistics`] of a file with the given schema.
+    pub fn from_statistics(statistics: &Statistics, schema: &Schema) -> Self {
+        let columns = schema
+            .fields()
+            .iter()
+            .zip(statistics.column_statistics.iter())
+            .map(|(field, column)| {
+                (
+                    field.name().clone(),
+                    StoredColumnStatistics {
+                        null_count: column
+                            .null_count
+                            .get_value()
+                            .map(|count| *count as u64),
+                        min: value_to_string(&column.min_value),
+                        max: value_to_string(&column.max_value),
+                    },
+                )
+            })
+            .collect();
+        Self {
+            num_rows: statistics.num_rows.get_value().map(|rows| *rows as u64),
+            total_byte_size: statistics
+                .total_byte_size
+                .get_value()
+                .map(|size| *size as u64),
+            columns,
+            checksum: None,
+            compression: None,
+        }
+    }
+
+    /// Convert the stored statistics to the [`Statistics`] of the given schema.
+    ///
+    /// Columns without stored statistics are reported as unknown.
+    /// The min/max values are inexact, as they may have been truncated when formatted.
+    pub fn to_statistics(&self, schema: &Schema) -> Statistics {
+        let column_statistics = schema
+            .fields()
+            .iter()
+            .map(|field| match self.columns.get(field.name()) {
+                Some(column) => ColumnStatistics {
+                    null_count: column.null_count.map_or(Precision::Absent, |count| {
+                        Precision::Exact(count as usize)
+                    }),
+                    max_value: string_to_value(&column.max, field.data_type()),
+                    min_value: string_to_value(&column.min, field.data_type()),
+                    sum_value: Precision::Absent,
+                    distinct_count: Precision::Absent,
+                },
+                None => ColumnStatistics::new_unknown(),
+            })
+            .collect();
+        Statistics {
+            num_rows: self
+                .num_rows
+                .map_or(Precision::Absent, |rows| Precision::Exact(rows as usize)),
+            total_byte_size: self
+                .total_byte_size
+                .map_or(Precision::Absent, |size| Precision::Exact(size as usize)),
+            column_statistics,
+        }
+    }
+}
+
+/// Aggregate the statistics of files into the statistics of all the files.
+pub fn merge_file_statistics(
+    file_statistics: &[Statistics],
+    file_schema: &Schema,
+) -> Statistics {
+    let Some((first, rest)) = file_statistics.split_first() else {
+        return Statistics {
+            num_rows: Precision::Exact(0),
+            total_byte_size: Precision::Exact(0),
+            column_statistics: Statistics::unknown_column(file_schema),
+        };
+    };
+    rest.iter()
+        .fold(first.clone(), |merged, statistics| Statistics {
+            num_rows: merged.num_rows.add(&statistics.num_rows),
+            total_byte_size: merged.total_byte_size.add(&statistics.total_byte_size),
+            column_statistics: merged
+                .column_statistics
+                .iter()
+                .zip(&statistics.column_statistics)
+                .map(|(merged, column)| ColumnStatistics {
+                    null_count: merged.null_count.add(&column.null_count),
+                    max_value: merged.max_value.max(&column.max_value),
+                    min_value: merged.min_value.min(&column.min_value),
+                    sum_value: Precision::Absent,
+                    distinct_count: Precision::Absent,
+                })
+                .collect(),
+        })
+}
+
+/// Aggregate the stored statistics of the data files of a table into the estimated
+/// statistics of the table, e.g. for the optimizer to order joins.
+///
+/// All values are inexact, as the rows of a primary key table are merged on read. A file
+/// without stored statistics makes the row count unknown. The distinct count of a single
+/// primary key is estimated as the row count, the one of a column whose min and max are
+/// equal as one.
+pub fn aggregate_table_statistics(
+    files: &[Option<&StoredFileStatistics>],
+    schema: &Schema,
+    primary_keys: &[String],
+) -> Statistics {
+    let file_statistics = files
+        .iter()
+        .map(|stored| match stored {
+            Some(stored) => stored.to_statistics(schema),
+            None => Statistics::new_unknown(schema),
+        })
+        .collect::<Vec<_>>();
+    let mut statistics = merge_file_statistics(&file_statistics, schema).to_inexact();
+    for (field, column) in schema
+        .fields()
+        .iter()
+        .zip(&mut statistics.column_statistics)
+    {
+        let single_primary_key = matches!(primary_keys, [pk] if pk == field.name());
+        column.distinct_count = if single_primary_key {
+            statistics.num_rows
+        } else {
+            match (column.min_value.get_value(), column.max_value.get_value()) {
+                (Some(min), Some(max)) if min == max => Precision::Inexact(1),
+                _ => Precision::Absent,
+            }
+        };
+    }
+    statistics
+}
+
+/// The min/max accumulators of a column whose values survive the round trip through the stored string format.
+#[derive(Debug)]
+struct MinMaxCollector {
+    min: MinAccumulator,
+    max: MaxAccumulator,
+}
+
+/// The statistics of a column collected from the batches written into a data file.
+#[derive(Debug)]
+struct ColumnStatsCollector {
+    /// The name of the column.
+    name: String,
+    /// The number of null values.
+    null_count: u64,
+    /// The min/max accumulators, absent if the min/max of the column are not collected.
+    min_max: Option<MinMaxCollector>,
+}
+
+/// The statistics of a data file collected while its batches are written, so that they can be
+/// stored into the metadata at commit time without reading the footer of the file again.
+#[derive(Debug)]
+pub struct DataFileStats {
+    /// The number of rows written into the file.
+    num_rows: u64,
+    /// The size of the file in bytes, known once the file is closed.
+    file_size: Option<u64>,
+    /// The checksum of the file content, known once the file is closed.
+    checksum: Option<String>,
+    /// The compression codec of the column chunks, known once the file is closed.
+    compression: Option<String>,
+    /// The statistics of each written column.
+    columns: Vec<ColumnStatsCollector>,
+}
+
+impl DataFileStats {
+    /// Create the statistics of a data file with the given schema.
+    ///
+    /// The min/max of the `statistics_disabled_columns` are not collected, matching the
+    /// statistics written into the file footer.
+    pub fn try_new(
+        schema: &Schema,
+        statistics_disabled_columns: &HashSet<String>,
+    ) -> Result<Self> {
+        let columns = schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let min_max = if is_string_round_trip_type(field.data_type())
+                    && !statistics_disabled_columns.contains(field.name())
+                {
+                    Some(MinMaxCollector {
+                        min: MinAccumulator::try_new(field.data_type())?,
+                        max: MaxAccumulator::try_new(field.data_type())?,
+                    })
+                } else {
+                    None
+                };
+                Ok(ColumnStatsCollector {
+                    name: field.name().clone(),
+                    null_count: 0,
+                    min_max,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            num_rows: 0,
+            file_size: None,
+            checksum: None,
+            compression: None,
+            columns,
+        })
+    }
+
+    /// Update the statistics with a batch written into the file.
+    pub fn update(&mut self, batch: &RecordBatch) -> Result<()> {
+        self.num_rows += batch.num_rows() as u64;
+        for (column, array) in self.columns.iter_mut().zip(batch.columns()) {
+            column.null_count += array.null_count() as u64;
+            if let Some(min_max) = column.min_max.as_mut() {
+                min_max.min.update_batch(std::slice::from_ref(array))?;
+                min_max.max.update_batch(std::slice::from_ref(array))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The number of rows written into the file.
+    pub fn num_rows(&self) -> u64 {
+        self.num_rows
+    }
+
+    /// Set the size of the closed file in bytes.
+    pub fn set_file_size(&mut self, file_size: u64) {
+        self.file_size = Some(file_size);
+    }
+
+    /// The size of the closed file in bytes, if set.
+    pub fn file_size(&self) -> Option<u64> {
+        self.file_size
+    }
+
+    /// Set the hex encoded md5 checksum of the closed file.
+    pub fn set_checksum(&mut self, checksum: String) {
+        self.checksum = Some(checksum);
+    }
+
+    /// Set the compression codec of the column chunks of the closed file.
+    pub fn set_compression(&mut self, compression: String) {
+        self.compression = Some(compression);
+    }
+
+    /// Finish the collection into the statistics stored in the metadata.
+    pub fn into_stored(self) -> Result<StoredFileStatistics> {
+        let columns = self
+            .columns
+            .into_iter()
+            .map(|column| {
+                let (min, max) = match column.min_max {
+                    Some(mut min_max) => (
+                        value_to_string(&Precision::Exact(min_max.min.evaluate()?)),
+                        value_to_string(&Precision::Exact(min_max.max.evaluate()?)),
+                    ),
+                    None => (None, None),
+                };
+                Ok((
+                    column.name,
+                    StoredColumnStatistics {
+                        null_count: Some(column.null_count),
+                        min,
+                        max,
+                    },
+                ))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()?;
+        Ok(StoredFileStatistics {
+            num_rows: Some(self.num_rows),
+            total_byte_size: self.file_size,
+            columns,
+            checksum: self.checksum,
+            compression: self.compression,
+        })
+    }
+}
@@ -0,0 +1,393 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! The Substrait representation of the scans of LakeSoul tables, so that a planner in
+//! another language can describe a read of a LakeSoul table which is executed here.
+//!
+//! A scan is the read relation at the root of a Substrait plan: the named table
+//! `[namespace, table]`, the projection as the mask of the columns of the table schema
+//! and the conjunction of the filters as its filter. The properties of the read which
+//! Substrait has no field for, i.e. the snapshot and the merge of the rows by the primary
+//! keys, are carried by the enhancement of the read relation, see
+//! [`LakeSoulReadExtension`].
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use datafusion::common::{Column, DFSchema};
+use datafusion::datasource::{TableProvider, provider_as_source};
+use datafusion::error::DataFusionError;
+use datafusion::execution::context::SessionState;
+use datafusion::logical_expr::utils::{conjunction, split_conjunction_owned};
+use datafusion::logical_expr::{Expr, LogicalPlanBuilder};
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::sql::TableReference;
+use datafusion_substrait::extensions::Extensions;
+use datafusion_substrait::logical_plan::consumer::{
+    DefaultSubstraitConsumer, from_substrait_rex,
+};
+use datafusion_substrait::logical_plan::producer::to_substrait_plan;
+use datafusion_substrait::substrait::proto::extensions::AdvancedExtension;
+use datafusion_substrait::substrait::proto::plan_rel::RelType as PlanRelType;
+use datafusion_substrait::substrait::proto::read_rel::ReadType;
+use datafusion_substrait::substrait::proto::rel::RelType;
+use datafusion_substrait::substrait::proto::{Plan, ReadRel};
+use lakesoul_io::lakesoul_io_config::{
+    OPTION_KEY_SNAPSHOT_TIMESTAMP, OPTION_KEY_SNAPSHOT_VERSION,
+};
+use lakesoul_metadata::MetaDataClientRef;
+use serde::{Deserialize, Serialize};
+
+use super::table_provider::{LakeSoulTableProvider, TableSnapshot};
+use crate::catalog::create_io_config_builder;
+use crate::error::Result;
+use crate::lakesoul_table::LakeSoulTable;
+
+/// The type URL of the enhancement of the read relations of LakeSoul tables, whose value
+/// is the JSON of a [`LakeSoulReadExtension`].
+pub const LAKESOUL_READ_EXTENSION_TYPE_URL: &str = "lakesoul.ReadExtension";
+
+/// The properties of a read of a LakeSoul table which Substrait has no field for.
+///
+/// The merge is described so that a planner knows the semantics of the read, a plan whose
+/// merge differs from the one of the table is rejected instead of being read otherwise.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LakeSoulReadExtension {
+    /// The primary keys the rows of the files are merged by, the rows of a table without
+    /// primary keys are not merged.
+    #[serde(default)]
+    pub primary_keys: Vec<String>,
+    /// The cdc column whose deleted rows are dropped after the merge.
+    #[serde(default)]
+    pub cdc_column: Option<String>,
+    #[serde(default)]
+    pub snapshot_version: Option<i32>,
+    /// The snapshot timestamp in milliseconds, ignored if the version is set.
+    #[serde(default)]
+    pub snapshot_timestamp: Option<i64>,
+}
+
+impl LakeSoulReadExtension {
+    fn snapshot(&self) -> Option<TableSnapshot> {
+        match (self.snapshot_version, self.snapshot_timestamp) {
+            (Some(version), _) => Some(TableSnapshot::Version(version)),
+            (None, Some(timestamp)) => Some(TableSnapshot::Timestamp(timestamp)),
+            (None, None) => None,
+        }
+    }
+
+    fn to_advanced_extension(&self) -> Result<AdvancedExtension> {
+        // the type of the `Any` message depends on the features of the substrait crate,
+        // so it is built from its default instead of being named
+        let mut enhancement =
+            AdvancedExtension::default().enhancement.unwrap_or_default();
+        enhancement.type_url = LAKESOUL_READ_EXTENSION_TYPE_URL.to_string();
+        enhancement.value = serde_json::to_vec(self)?.into();
+        Ok(AdvancedExtension {
+            enhancement: Some(enhancement),
+            ..Default::default()
+        })
+    }
+
+    fn from_advanced_extension(
+        extension: Option<&AdvancedExtension>,
+    ) -> Result<Option<Self>> {
+        match extension.and_then(|extension| extension.enhancement.as_ref()) {
+            Some(enhancement)
+                if enhancement.type_url == LAKESOUL_READ_EXTENSION_TYPE_URL =>
+            {
+                Ok(Some(serde_json::from_slice(&enhancement.value)?))
+            }
+            // an enhancement changes the semantics of the read, so it cannot be ignored
+            Some(enhancement) => Err(DataFusionError::NotImplemented(format!(
+                "enhancement {} of the read relation",
+                enhancement.type_url
+            ))
+            .into()),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A scan of a LakeSoul table, i.e. the inputs of the physical plan created by
+/// [`LakeSoulMetaDataParquetFormat`], which is serialized to and deserialized from a
+/// Substrait plan.
+///
+/// [`LakeSoulMetaDataParquetFormat`]: super::file_format::LakeSoulMetaDataParquetFormat
+#[derive(Debug, Clone)]
+pub struct LakeSoulScan {
+    pub table_ref: TableReference,
+    /// The indices of the read columns in the table schema, all columns if not set.
+    pub projection: Option<Vec<usize>>,
+    /// The filters the read rows match.
+    pub filters: Vec<Expr>,
+    /// The snapshot to read instead of the latest committed files if set.
+    pub snapshot: Option<TableSnapshot>,
+}
+
+impl LakeSoulScan {
+    /// Create a scan of all the rows of the table.
+    pub fn new(table_ref: impl Into<TableReference>) -> Self {
+        Self {
+            table_ref: table_ref.into(),
+            projection: None,
+            filters: vec![],
+            snapshot: None,
+        }
+    }
+
+    pub fn with_projection(mut self, projection: Option<Vec<usize>>) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn with_filters(mut self, filters: Vec<Expr>) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    pub fn with_snapshot(mut self, snapshot: Option<TableSnapshot>) -> Self {
+        self.snapshot = snapshot;
+        self
+    }
+
+    /// Serialize the scan into a Substrait plan whose root is the read relation of the
+    /// table.
+    pub async fn to_substrait_plan(
+        &self,
+        client: MetaDataClientRef,
+        state: &SessionState,
+    ) -> Result<Plan> {
+        let (table_ref, provider, extension) = self.provider(client, state).await?;
+        let logical_plan = LogicalPlanBuilder::scan_with_filters(
+            table_ref,
+            provider_as_source(Arc::new(provider)),
+            self.projection.clone(),
+            self.filters.clone(),
+        )?
+        .build()?;
+        let mut plan = *to_substrait_plan(&logical_plan, state)?;
+        let read_rel = plan
+            .relations
+            .first_mut()
+            .and_then(|plan_rel| match &mut plan_rel.rel_type {
+                Some(PlanRelType::Root(root)) => root.input.as_mut(),
+                Some(PlanRelType::Rel(rel)) => Some(rel),
+                None => None,
+            })
+            .and_then(|rel| match &mut rel.rel_type {
+                Some(RelType::Read(read_rel)) => Some(read_rel),
+                _ => None,
+            })
+            .ok_or_else(|| {
+                DataFusionError::Internal(format!(
+                    "the substrait plan of the scan of {} has no root read relation",
+                    self.table_ref
+                ))
+            })?;
+        read_rel.advanced_extension = Some(extension.to_advanced_extension()?);
+        Ok(plan)
+    }
+
+    /// Deserialize the scan from a Substrait plan whose root is the read relation of a
+    /// LakeSoul table.
+    ///
+    /// The read is checked against the current table, the projection must be within its
+    /// columns and the merge described by the [`LakeSoulReadExtension`], if any, must be
+    /// the one of the table.
+    pub async fn from_substrait_plan(
+        client: MetaDataClientRef,
+        state: &SessionState,
+        plan: &Plan,
+    ) -> Result<Self> {
+        let read_rel = root_read_rel(plan)?;
+        let table_ref = match &read_rel.read_type {
+            Some(ReadType::NamedTable(named_table)) => {
+                match named_table.names.as_slice() {
+                    [table] => TableReference::bare(table.as_str()),
+                    [namespace, table] => {
+                        TableReference::partial(namespace.as_str(), table.as_str())
+                    }
+                    [catalog, namespace, table] => TableReference::full(
+                        catalog.as_str(),
+                        namespace.as_str(),
+                        table.as_str(),
+                    ),
+                    names => {
+                        return Err(DataFusionError::Plan(format!(
+                            "invalid table name {:?} of the read relation",
+                            names
+                        ))
+                        .into());
+                    }
+                }
+            }
+            _ => {
+                return Err(DataFusionError::NotImplemented(
+                    "read relation of other than a named table".to_string(),
+                )
+                .into());
+            }
+        };
+        let extension = LakeSoulReadExtension::from_advanced_extension(
+            read_rel.advanced_extension.as_ref(),
+        )?;
+        let scan = Self::new(table_ref)
+            .with_snapshot(extension.as_ref().and_then(LakeSoulReadExtension::snapshot));
+        let (table_ref, provider, table_extension) = scan.provider(client, state).await?;
+        if let Some(extension) = extension.filter(|extension| {
+            extension.primary_keys != table_extension.primary_keys
+                || extension.cdc_column != table_extension.cdc_column
+        }) {
+            return Err(DataFusionError::Plan(format!(
+                "the read of {} merges by the primary keys {:?} and cdc column {:?}, but \
+                the table has the primary keys {:?} and cdc column {:?}",
+                table_ref,
+                extension.primary_keys,
+                extension.cdc_column,
+                table_extension.primary_keys,
+                table_extension.cdc_column
+            ))
+            .into());
+        }
+
+        let schema = provider.schema();
+        let projection = read_rel
+            .projection
+            .as_ref()
+            .and_then(|mask| mask.select.as_ref())
+            .map(|select| {
+                select
+                    .struct_items
+                    .iter()
+                    .map(|item| match usize::try_from(item.field) {
+                        Ok(index) if index < schema.fields().len() => Ok(index),
+                        _ => Err(DataFusionError::Plan(format!(
+                            "projected column {} out of the {} columns of {}",
+                            item.field,
+                            schema.fields().len(),
+                            table_ref
+                        ))),
+                    })
+                    .collect::<std::result::Result<Vec<_>, _>>()
+            })
+            .transpose()?;
+
+        let mut filters = vec![];
+        if let Some(filter) = &read_rel.filter {
+            let extensions = Extensions::try_from(&plan.extensions)?;
+            let consumer = DefaultSubstraitConsumer::new(&extensions, state);
+            let df_schema = DFSchema::try_from_qualified_schema(table_ref, &schema)?;
+            filters = split_conjunction_owned(
+                from_substrait_rex(&consumer, filter, &df_schema).await?,
+            );
+        }
+        Ok(scan.with_projection(projection).with_filters(filters))
+    }
+
+    /// Create the physical plan of the scan, whose output has the projected columns of
+    /// the rows matching all the filters.
+    ///
+    /// The filters are pushed down to the scan of the table by the optimizer, and the
+    /// rows are filtered again when the scan does not apply a filter exactly.
+    pub async fn create_physical_plan(
+        &self,
+        client: MetaDataClientRef,
+        state: &SessionState,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let (table_ref, provider, _) = self.provider(client, state).await?;
+        let schema = provider.schema();
+        let mut builder = LogicalPlanBuilder::scan(
+            table_ref,
+            provider_as_source(Arc::new(provider)),
+            None,
+        )?;
+        if let Some(filter) = conjunction(self.filters.clone()) {
+            builder = builder.filter(filter)?;
+        }
+        if let Some(projection) = &self.projection {
+            builder = builder.project(projection.iter().map(|index| {
+                Expr::Column(Column::new_unqualified(schema.field(*index).name()))
+            }))?;
+        }
+        Ok(state.create_physical_plan(&builder.build()?).await?)
+    }
+
+    /// The provider of the table of the scan as of its snapshot, with the resolved
+    /// reference of the table and the extension describing the read.
+    async fn provider(
+        &self,
+        client: MetaDataClientRef,
+        state: &SessionState,
+    ) -> Result<(TableReference, LakeSoulTableProvider, LakeSoulReadExtension)> {
+        let table =
+            LakeSoulTable::for_table_reference(&self.table_ref, Some(client.clone()))
+                .await?;
+        let mut options = HashMap::new();
+        match self.snapshot {
+            Some(TableSnapshot::Version(version)) => {
+                options
+                    .insert(OPTION_KEY_SNAPSHOT_VERSION.to_string(), version.to_string());
+            }
+            Some(TableSnapshot::Timestamp(timestamp)) => {
+                options.insert(
+                    OPTION_KEY_SNAPSHOT_TIMESTAMP.to_string(),
+                    timestamp.to_string(),
+                );
+            }
+            None => {}
+        }
+        let config = create_io_config_builder(
+            client.clone(),
+            Some(table.table_name()),
+            true,
+            table.table_namespace(),
+            options,
+            HashMap::new(),
+        )
+        .await?
+        .build();
+        let cdc_column = config.cdc_column();
+        let extension = LakeSoulReadExtension {
+            primary_keys: config.primary_keys_slice().to_vec(),
+            cdc_column: (!cdc_column.is_empty()).then_some(cdc_column),
+            snapshot_version: config.snapshot_version()?,
+            snapshot_timestamp: config.snapshot_timestamp()?,
+        };
+        let provider = LakeSoulTableProvider::try_new(
+            state,
+            client,
+            config,
+            table.table_info(),
+            false,
+        )
+        .await?;
+        Ok((
+            TableReference::partial(table.table_namespace(), table.table_name()),
+            provider,
+            extension,
+        ))
+    }
+}
+
+/// The read relation at the root of the plan.
+fn root_read_rel(plan: &Plan) -> Result<&ReadRel> {
+    plan.relations
+        .first()
+        .and_then(|plan_rel| match &plan_rel.rel_type {
+            Some(PlanRelType::Root(root)) => root.input.as_ref(),
+            Some(PlanRelType::Rel(rel)) => Some(rel),
+            None => None,
+        })
+        .and_then(|rel| match &rel.rel_type {
+            Some(RelType::Read(read_rel)) => Some(read_rel.as_ref()),
+            _ => None,
+        })
+        .ok_or_else(|| {
+            DataFusionError::NotImplemented(
+                "substrait plan whose root is not a read relation".to_string(),
+            )
+            .into()
+        })
+}
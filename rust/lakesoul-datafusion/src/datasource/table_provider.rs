@@ -5,29 +5,34 @@
 //! The [`datafusion::datasource::TableProvider`] implementation for LakeSoul table.
 
 use std::any::Any;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::ops::Deref;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use arrow::compute::SortOptions;
-use arrow::datatypes::{DataType, Field, Schema, SchemaBuilder, SchemaRef};
+use arrow::datatypes::{DataType, Field, Fields, Schema, SchemaBuilder, SchemaRef};
 use async_trait::async_trait;
 use datafusion::catalog::Session;
-use datafusion::common::{Constraint, Statistics, ToDFSchema, project_schema};
+use datafusion::common::{
+    ColumnStatistics, Constraint, Statistics, ToDFSchema, project_schema,
+};
 use datafusion::datasource::TableProvider;
 use datafusion::datasource::file_format::FileFormat;
 use datafusion::datasource::file_format::file_compression_type::FileCompressionType;
-use datafusion::datasource::file_format::parquet::ParquetFormat;
 use datafusion::datasource::listing::{ListingOptions, ListingTableUrl, PartitionedFile};
 use datafusion::datasource::physical_plan::{FileGroup, FileScanConfig, FileSinkConfig};
 use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::object_store::ObjectStoreUrl;
 use datafusion::logical_expr::dml::InsertOp;
 use datafusion::logical_expr::expr::Sort;
 use datafusion::logical_expr::utils::conjunction;
 use datafusion::logical_expr::{
     CreateExternalTable, TableProviderFilterPushDown, TableType,
 };
-use datafusion::physical_expr::{LexOrdering, PhysicalSortExpr, create_physical_expr};
+use datafusion::physical_expr::{
+    LexOrdering, PhysicalExpr, PhysicalSortExpr, create_physical_expr,
+};
 use datafusion::physical_plan::ExecutionPlan;
 use datafusion::physical_plan::empty::EmptyExec;
 use datafusion::scalar::ScalarValue;
@@ -35,21 +40,44 @@ use datafusion::{execution::context::SessionState, logical_expr::Expr};
 use futures::StreamExt;
 use futures::stream::FuturesUnordered;
 
-use lakesoul_io::helpers::listing_table_from_lakesoul_io_config;
+use lakesoul_io::datasource::file_format::{
+    file_object_store_url, with_file_object_store_url,
+};
+use lakesoul_io::helpers::{
+    extract_hash_bucket_id, listing_table_from_lakesoul_io_config, resolve_file_url,
+    view_type_field,
+};
 use lakesoul_io::lakesoul_io_config::LakeSoulIOConfig;
 use lakesoul_metadata::MetaDataClientRef;
+use object_store::path::Path;
 use proto::proto::entity::TableInfo;
+use url::Url;
 
 use crate::catalog::{
-    LakeSoulTableProperty, format_table_info_partitions, parse_table_info_partitions,
+    LakeSoulTableProperty, format_table_info_partitions,
+    parse_table_info_hash_bucket_num, parse_table_info_partitions,
 };
 use crate::lakesoul_table::helpers::{
-    case_fold_column_name, case_fold_table_name, listing_partition_info,
-    parse_partitions_for_partition_desc, prune_partitions,
+    case_fold_column_name, case_fold_table_name, hash_buckets_from_filters,
+    listing_partition_info, parse_partitions_for_partition_desc,
+    partition_descs_from_filters, prune_partitions,
 };
 use crate::serialize::arrow_java::{ArrowJavaSchema, schema_from_metadata_str};
 
-use super::file_format::LakeSoulMetaDataParquetFormat;
+use super::delete_vector::{delete_vector_data_file, is_delete_vector};
+use super::file_format::{
+    CommitHook, LakeSoulMetaDataParquetFormat, RegisteredCommitHook, ScanFile,
+};
+use super::statistics::{StoredFileStatistics, merge_file_statistics};
+
+/// The snapshot of a LakeSoul table to read instead of the latest committed files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableSnapshot {
+    /// The latest version of each partition not newer than the version.
+    Version(i32),
+    /// The latest version of each partition committed at or before the timestamp in milliseconds.
+    Timestamp(i64),
+}
 
 /// Reads data from LakeSoul
 ///
@@ -65,6 +93,15 @@ use super::file_format::LakeSoulMetaDataParquetFormat;
 #[derive(Debug)]
 pub struct LakeSoulTableProvider {
     pub(crate) listing_options: ListingOptions,
+    /// The snapshot to read, or the latest committed files if not set.
+    pub(crate) snapshot: Option<TableSnapshot>,
+    /// The exclusive start and inclusive end version of the change feed to read if set.
+    pub(crate) change_feed: Option<(i32, i32)>,
+    /// The versions of the partitions after which the partitions read were committed, all
+    /// partitions are read if not set, see [`Self::with_changed_since`].
+    pub(crate) changed_since: Option<HashMap<String, i32>>,
+    /// The versions of the partitions up to the changed partitions listed by the last scan.
+    pub(crate) observed_versions: Arc<Mutex<Option<HashMap<String, i32>>>>,
     pub(crate) listing_table_paths: Vec<ListingTableUrl>,
     pub(crate) client: MetaDataClientRef,
     pub(crate) table_info: Arc<TableInfo>,
@@ -73,6 +110,8 @@ pub struct LakeSoulTableProvider {
     pub(crate) file_schema: SchemaRef,
     pub(crate) primary_keys: Vec<String>,
     pub(crate) range_partitions: Vec<String>,
+    /// The hook invoked after the commits of the inserts, see [`Self::with_commit_hook`].
+    pub(crate) commit_hook: Option<RegisteredCommitHook>,
 }
 
 impl LakeSoulTableProvider {
@@ -97,6 +136,30 @@ impl LakeSoulTableProvider {
             };
         }
 
+        // the columns read from the files take the view types the files are read with, so
+        // that the merge sees the same types in the table and in the files
+        let force_view_types = lakesoul_io_config.force_view_types().unwrap_or(
+            session_state
+                .config_options()
+                .execution
+                .parquet
+                .schema_force_view_types,
+        );
+        let table_schema = if force_view_types {
+            Schema::new_with_metadata(
+                table_schema
+                    .fields()
+                    .iter()
+                    .map(|field| match range_partitions.contains(field.name()) {
+                        true => field.clone(),
+                        false => view_type_field(field),
+                    })
+                    .collect::<Fields>(),
+                table_schema.metadata().clone(),
+            )
+        } else {
+            table_schema.as_ref().clone()
+        };
         let file_schema = Arc::new(table_schema.project(&file_schema_projection)?);
         let table_schema =
             Arc::new(table_schema.project(
@@ -104,20 +167,13 @@ impl LakeSoulTableProvider {
             )?);
 
         let file_format: Arc<dyn FileFormat> = Arc::new(
-            LakeSoulMetaDataParquetFormat::new(
+            LakeSoulMetaDataParquetFormat::builder(
                 client.clone(),
-                Arc::new(
-                    ParquetFormat::new().with_force_view_types(
-                        session_state
-                            .config_options()
-                            .execution
-                            .parquet
-                            .schema_force_view_types,
-                    ),
-                ),
                 table_info.clone(),
                 lakesoul_io_config.clone(),
             )
+            .with_force_view_types(force_view_types)
+            .build()
             .await?,
         );
 
@@ -131,8 +187,20 @@ impl LakeSoulTableProvider {
 
         let listing_options = listing_table.options().clone();
         let listing_table_paths = listing_table.table_paths().clone();
+        let snapshot = match (
+            lakesoul_io_config.snapshot_version()?,
+            lakesoul_io_config.snapshot_timestamp()?,
+        ) {
+            (Some(version), _) => Some(TableSnapshot::Version(version)),
+            (None, Some(timestamp)) => Some(TableSnapshot::Timestamp(timestamp)),
+            (None, None) => None,
+        };
         Ok(Self {
             listing_options,
+            snapshot,
+            change_feed: lakesoul_io_config.change_feed()?,
+            changed_since: None,
+            observed_versions: Default::default(),
             listing_table_paths,
             client,
             table_info,
@@ -140,6 +208,7 @@ impl LakeSoulTableProvider {
             file_schema,
             primary_keys: hash_partitions,
             range_partitions,
+            commit_hook: None,
         })
     }
 
@@ -215,6 +284,48 @@ impl LakeSoulTableProvider {
                 datafusion_properties: Some(cmd.options.clone()),
                 cdc_change_column: cdc_column,
                 use_cdc,
+                nulls_first: cmd
+                    .options
+                    .get("format.nulls_first")
+                    .map(|nulls_first| nulls_first == "true"),
+                encrypted_columns: cmd.options.get("format.encrypted_columns").map(
+                    |columns| {
+                        columns
+                            .split(',')
+                            .filter(|column| !column.is_empty())
+                            .map(String::from)
+                            .collect()
+                    },
+                ),
+                encryption_kms: cmd.options.get("format.encryption_kms").cloned(),
+                encryption_key_id: cmd.options.get("format.encryption_key_id").cloned(),
+                parquet_compression: cmd
+                    .options
+                    .get("format.parquet_compression")
+                    .cloned(),
+                sequence_column: cmd
+                    .options
+                    .get("format.sequence_column")
+                    .filter(|column| !column.is_empty())
+                    .cloned(),
+                partition_path_encoding: cmd
+                    .options
+                    .get("format.partition_path_encoding")
+                    .filter(|encoding| !encoding.is_empty())
+                    .cloned(),
+                keep_partition_columns: cmd
+                    .options
+                    .get("format.keep_partition_columns")
+                    .map(|keep| keep == "true"),
+                primary_key_bloom_filter: cmd
+                    .options
+                    .get("format.primary_key_bloom_filter")
+                    .map(|bloom_filter| bloom_filter == "true"),
+                merge_strategy: cmd
+                    .options
+                    .get("format.merge_strategy")
+                    .filter(|strategy| !strategy.is_empty())
+                    .cloned(),
                 ..Default::default()
             })
             .unwrap(),
@@ -232,8 +343,14 @@ impl LakeSoulTableProvider {
             domain: "public".to_string(),
         });
         Ok(Self {
-            listing_options: LakeSoulMetaDataParquetFormat::default_listing_options()
-                .await?,
+            listing_options: LakeSoulMetaDataParquetFormat::listing_options(
+                client.clone(),
+            )
+            .await?,
+            snapshot: None,
+            change_feed: None,
+            changed_since: None,
+            observed_versions: Default::default(),
             listing_table_paths: vec![],
             client,
             table_info,
@@ -252,6 +369,62 @@ impl LakeSoulTableProvider {
         &self.primary_keys
     }
 
+    /// Whether a scan may return fewer rows than its files hold: the rows of primary key
+    /// tables are merged on read, and the rows of cdc tables marked as deleted are filtered
+    /// out. The row and null counts of the files are then only upper bounds.
+    fn scan_drops_file_rows(&self) -> bool {
+        !self.primary_keys.is_empty()
+            || serde_json::from_str::<LakeSoulTableProperty>(&self.table_info.properties)
+                .ok()
+                .is_none_or(|properties| {
+                    properties
+                        .cdc_change_column
+                        .is_some_and(|column| !column.is_empty())
+                })
+    }
+
+    /// Read only the partitions with a commit after their version in `versions`, i.e.
+    /// whose latest version is newer, with all the files of their latest version.
+    ///
+    /// The versions are counted per partition like for the change feed, so each partition
+    /// is compared with its own version, and the partitions missing from `versions` are
+    /// read entirely. The versions of the partitions listed by a scan are returned by
+    /// [`Self::observed_versions`], so that an incremental reader resumes from them.
+    pub fn with_changed_since(mut self, versions: HashMap<String, i32>) -> Self {
+        self.changed_since = Some(versions);
+        self
+    }
+
+    /// Invoke the hook for each committed partition once the files of an insert into the
+    /// table are committed, instead of the hook of its format.
+    ///
+    /// Its errors are logged, and with `fail_on_error` they also fail the insert. The files
+    /// stay committed in any case.
+    pub fn with_commit_hook(
+        self,
+        commit_hook: Arc<dyn CommitHook>,
+        fail_on_error: bool,
+    ) -> Self {
+        self.with_registered_commit_hook(RegisteredCommitHook::new(
+            commit_hook,
+            fail_on_error,
+        ))
+    }
+
+    pub(crate) fn with_registered_commit_hook(
+        mut self,
+        commit_hook: RegisteredCommitHook,
+    ) -> Self {
+        self.commit_hook = Some(commit_hook);
+        self
+    }
+
+    /// The versions the partitions changed since, updated with the versions of the changed
+    /// partitions listed by the last scan, see [`Self::with_changed_since`].
+    pub fn observed_versions(&self) -> Option<HashMap<String, i32>> {
+        self.observed_versions.lock().unwrap().clone()
+    }
+
     pub fn table_info(&self) -> Arc<TableInfo> {
         self.table_info.clone()
     }
@@ -268,6 +441,37 @@ impl LakeSoulTableProvider {
         &self.table_info.table_id
     }
 
+    /// The hash buckets of the rows matched by the filters, see [`hash_buckets_from_filters`],
+    /// or `None` if the files of all buckets are read.
+    fn hash_buckets_from_filters(
+        &self,
+        filters: &[Expr],
+    ) -> Result<Option<HashSet<u32>>> {
+        if self.primary_keys.is_empty() {
+            return Ok(None);
+        }
+        // the keys are hashed with their stored types, not the view types they are read with
+        let schema = schema_from_metadata_str(&self.table_info.table_schema);
+        let hash_partition_cols = self
+            .primary_keys
+            .iter()
+            .map(|key| {
+                Ok((
+                    key.clone(),
+                    schema.field_with_name(key)?.data_type().clone(),
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let hash_bucket_num =
+            parse_table_info_hash_bucket_num(&self.table_info.properties)
+                .map_err(|e| DataFusionError::External(Box::new(e)))?;
+        Ok(hash_buckets_from_filters(
+            filters,
+            &hash_partition_cols,
+            hash_bucket_num,
+        ))
+    }
+
     fn is_partition_filter(&self, f: &Expr) -> bool {
         info!("is_partition_filter: {:?}", f);
         // O(nm), n = number of expr fields, m = number of range partitions
@@ -276,6 +480,13 @@ impl LakeSoulTableProvider {
             .all(|col| self.range_partitions.contains(&col.name))
     }
 
+    fn is_hash_partition_filter(&self, f: &Expr) -> bool {
+        !self.primary_keys.is_empty()
+            && f.column_refs()
+                .iter()
+                .all(|col| self.primary_keys.contains(&col.name))
+    }
+
     pub fn options(&self) -> &ListingOptions {
         &self.listing_options
     }
@@ -324,49 +535,271 @@ impl LakeSoulTableProvider {
         Ok(all_sort_orders)
     }
 
+    /// The filters combined into the predicate of the scan.
+    fn physical_filters(
+        &self,
+        session_state: &SessionState,
+        filters: &[Expr],
+    ) -> Result<Option<Arc<dyn PhysicalExpr>>> {
+        conjunction(filters.to_vec())
+            .map(|expr| {
+                // NOTE: Use the table schema (NOT file schema) here because `expr` may
+                // contain references to partition columns.
+                let table_df_schema = self.schema().as_ref().clone().to_dfschema()?;
+                create_physical_expr(
+                    &expr,
+                    &table_df_schema,
+                    session_state.execution_props(),
+                )
+            })
+            .transpose()
+    }
+
+    /// The config of the scan of the listed files, `None` if the table has no path.
+    fn file_scan_config(
+        &self,
+        partitioned_file_lists: Vec<Vec<PartitionedFile>>,
+        statistics: Statistics,
+        projection: Option<&Vec<usize>>,
+        limit: Option<usize>,
+    ) -> Result<Option<FileScanConfig>> {
+        let Some(url) = self.table_paths().first() else {
+            return Ok(None);
+        };
+        // extract types of partition columns
+        // O(nm), n = number of partitions, m = number of columns
+        let table_partition_cols = self
+            .options()
+            .table_partition_cols
+            .iter()
+            .map(|col| Ok(self.schema().field_with_name(&col.0)?.clone()))
+            .collect::<Result<Vec<_>>>()?;
+        let statistics = Arc::new(statistics);
+        let file_source = self
+            .options()
+            .format
+            .file_source()
+            .with_statistics(Statistics::new_unknown(&self.schema()));
+        Ok(Some(FileScanConfig {
+            object_store_url: url.object_store(),
+            file_schema: self.schema(),
+            file_groups: partitioned_file_lists
+                .into_iter()
+                .map(|files| FileGroup::new(files).with_statistics(statistics.clone()))
+                .collect(),
+            constraints: Default::default(),
+            // projection for Table instead of File
+            projection: projection.cloned(),
+            limit,
+            output_ordering: self.try_create_output_ordering()?,
+            file_compression_type: FileCompressionType::ZSTD,
+            new_lines_in_values: false,
+            file_source,
+            table_partition_cols,
+            batch_size: None,
+        }))
+    }
+
+    /// The URLs of the data files of the table whose statistics do not exclude the
+    /// filters, without reading their rows, see
+    /// [`LakeSoulMetaDataParquetFormat::prune_files`].
+    ///
+    /// The partitions are pruned by the filters on the range partition columns like for a
+    /// scan.
+    pub async fn matching_files(
+        &self,
+        session_state: &SessionState,
+        filters: &[Expr],
+    ) -> Result<Vec<String>> {
+        let format = self.lakesoul_format("matching files")?;
+        let Some(config) = self.unpruned_scan_config(session_state, filters).await?
+        else {
+            return Ok(vec![]);
+        };
+        let filters = self.physical_filters(session_state, filters)?;
+        format
+            .prune_files(session_state, config, filters.as_ref())
+            .await
+    }
+
+    /// The data files a scan of the table with the filters would read after pruning, with
+    /// their partitions and estimated numbers of rows, without building the physical plan,
+    /// see [`LakeSoulMetaDataParquetFormat::plan_scan`].
+    pub async fn scan_files(
+        &self,
+        session_state: &SessionState,
+        filters: &[Expr],
+    ) -> Result<Vec<ScanFile>> {
+        let format = self.lakesoul_format("scan files")?;
+        let Some(config) = self.unpruned_scan_config(session_state, filters).await?
+        else {
+            return Ok(vec![]);
+        };
+        let filters = self.physical_filters(session_state, filters)?;
+        format
+            .plan_scan(session_state, config, filters.as_ref())
+            .await
+    }
+
+    /// The LakeSoul format reading the table, `what` names the requested information in
+    /// the error of the tables read by another format.
+    fn lakesoul_format(&self, what: &str) -> Result<&LakeSoulMetaDataParquetFormat> {
+        self.options()
+            .format
+            .as_any()
+            .downcast_ref::<LakeSoulMetaDataParquetFormat>()
+            .ok_or_else(|| {
+                DataFusionError::NotImplemented(format!(
+                    "{} of table {} not read by the LakeSoul format",
+                    what,
+                    self.table_info().table_name
+                ))
+            })
+    }
+
+    /// The config of the scan of all the files of the partitions matching the filters,
+    /// before the files are pruned, `None` if there is no file to scan.
+    async fn unpruned_scan_config(
+        &self,
+        session_state: &SessionState,
+        filters: &[Expr],
+    ) -> Result<Option<FileScanConfig>> {
+        let (partitioned_file_lists, statistics) = self
+            .list_files_for_scan(session_state, filters, None)
+            .await?;
+        if partitioned_file_lists.is_empty() {
+            return Ok(None);
+        }
+        self.file_scan_config(partitioned_file_lists, statistics, None, None)
+    }
+
     async fn list_files_for_scan<'a>(
         &'a self,
         ctx: &'a SessionState,
         filters: &'a [Expr],
         _limit: Option<usize>,
     ) -> Result<(Vec<Vec<PartitionedFile>>, Statistics)> {
-        let store = if let Some(url) = self.table_paths().first() {
-            ctx.runtime_env().object_store(url)?
+        let (table_url, table_store_url) = if let Some(url) = self.table_paths().first() {
+            (
+                <ListingTableUrl as AsRef<Url>>::as_ref(url).clone(),
+                url.object_store(),
+            )
         } else {
             return Ok((vec![], Statistics::new_unknown(&self.file_schema())));
         };
 
-        let all_partition_info = self
-            .client
-            .get_all_partition_info(self.table_id())
-            .await
-            .map_err(|e| {
-                DataFusionError::External(
-                    format!(
-                        "get all partition_info of table {} failed: {}",
-                        &self.table_info().table_name,
-                        e
-                    )
-                    .into(),
-                )
-            })?;
         let partition_filters = filters
             .iter()
             .filter(|f| self.is_partition_filter(f))
             .cloned()
             .collect::<Vec<Expr>>();
+        // equality filters on all range partition columns name the partitions to read, so
+        // only these are looked up instead of all partitions of the table
+        let partition_descs =
+            partition_descs_from_filters(&partition_filters, self.table_partition_cols());
+        // equality filters on the hash partition key name the hash buckets to read, so the
+        // files of the other buckets are skipped
+        let hash_buckets = self.hash_buckets_from_filters(filters)?;
 
+        let all_partition_info = match self.snapshot {
+            None => match &partition_descs {
+                Some(partition_descs) => {
+                    debug!("look up partitions {:?}", partition_descs);
+                    self.client
+                        .get_partition_info_by_table_id_and_partition_list(
+                            self.table_id(),
+                            partition_descs,
+                        )
+                        .await
+                }
+                None => self.client.get_all_partition_info(self.table_id()).await,
+            },
+            Some(TableSnapshot::Version(version)) => {
+                self.client
+                    .get_all_partition_info_as_of_version(self.table_id(), version)
+                    .await
+            }
+            // partitions created after the timestamp are not returned, so a timestamp before
+            // the first commit results in an empty scan
+            Some(TableSnapshot::Timestamp(timestamp)) => {
+                self.client
+                    .get_all_partition_info_as_of_timestamp(self.table_id(), timestamp)
+                    .await
+            }
+        }
+        .map_err(|e| {
+            DataFusionError::External(
+                format!(
+                    "get all partition_info of table {} failed: {}",
+                    &self.table_info().table_name,
+                    e
+                )
+                .into(),
+            )
+        })?;
+        // only the partitions committed after the version are read
+        let all_partition_info = match &self.changed_since {
+            Some(since_versions) => {
+                let changed = all_partition_info
+                    .into_iter()
+                    .filter(|partition_info| {
+                        since_versions
+                            .get(&partition_info.partition_desc)
+                            .is_none_or(|version| partition_info.version > *version)
+                    })
+                    .collect::<Vec<_>>();
+                let mut observed_versions = since_versions.clone();
+                observed_versions.extend(changed.iter().map(|partition_info| {
+                    (
+                        partition_info.partition_desc.clone(),
+                        partition_info.version,
+                    )
+                }));
+                debug!(
+                    "{} partitions changed since versions {:?}",
+                    changed.len(),
+                    since_versions
+                );
+                *self.observed_versions.lock().unwrap() = Some(observed_versions);
+                changed
+            }
+            None => all_partition_info,
+        };
+        // the change feed reads only the commits within the version range of each partition
+        let all_partition_info = match self.change_feed {
+            Some((from_version, to_version)) => {
+                let mut changed_partition_info = vec![];
+                for mut partition_info in all_partition_info {
+                    partition_info.snapshot = self
+                        .client
+                        .get_changed_commits_of_partition(
+                            self.table_id(),
+                            &partition_info.partition_desc,
+                            from_version,
+                            to_version,
+                        )
+                        .await
+                        .map_err(|e| DataFusionError::External(Box::new(e)))?;
+                    if !partition_info.snapshot.is_empty() {
+                        changed_partition_info.push(partition_info);
+                    }
+                }
+                changed_partition_info
+            }
+            None => all_partition_info,
+        };
         let prune_partition_info = prune_partitions(
             all_partition_info,
             partition_filters.as_slice(),
             self.table_partition_cols(),
         )
         .await
-        .map_err(|_| {
+        .map_err(|e| {
             DataFusionError::External(
                 format!(
-                    "prune partitions for all partitions of table {} failed",
-                    &self.table_info().table_name
+                    "prune partitions for all partitions of table {} failed: {}",
+                    &self.table_info().table_name,
+                    e
                 )
                 .into(),
             )
@@ -378,12 +811,14 @@ impl LakeSoulTableProvider {
         for partition in prune_partition_info {
             futures.push(listing_partition_info(
                 partition,
-                store.as_ref(),
+                ctx.runtime_env().as_ref(),
+                &table_url,
                 self.client(),
             ))
         }
 
         let mut file_groups = Vec::new();
+        let mut partition_descs = Vec::new();
 
         while let Some((partition, object_metas)) = futures.next().await.transpose()? {
             let cols = self.table_partition_cols().iter().map(|x| x.0.as_str());
@@ -399,22 +834,196 @@ impl LakeSoulTableProvider {
                 })
                 .collect::<Result<Vec<_>>>()?;
 
+            // the files may be stored in other object stores than the table path
             let files = object_metas
                 .into_iter()
-                .map(|object_meta| PartitionedFile {
-                    object_meta,
-                    partition_values: partition_values.clone(),
-                    range: None,
-                    statistics: None,
-                    extensions: None,
-                    metadata_size_hint: None,
+                .filter(|(_, object_meta)| {
+                    // the delete vectors are read with their data files
+                    let path = object_meta.location.as_ref();
+                    hash_buckets.as_ref().is_none_or(|hash_buckets| {
+                        extract_hash_bucket_id(
+                            delete_vector_data_file(path).unwrap_or(path),
+                        )
+                        .is_none_or(|hash_bucket_id| {
+                            hash_buckets.contains(&hash_bucket_id)
+                        })
+                    })
+                })
+                .map(|(object_store_url, object_meta)| {
+                    with_file_object_store_url(
+                        PartitionedFile {
+                            object_meta,
+                            partition_values: partition_values.clone(),
+                            range: None,
+                            statistics: None,
+                            extensions: None,
+                            metadata_size_hint: None,
+                        },
+                        object_store_url,
+                    )
                 })
                 .collect::<Vec<_>>();
-            file_groups.push(files)
+            if !files.is_empty() {
+                file_groups.push(files);
+                partition_descs.push(partition.partition_desc);
+            }
         }
         info!("file_groups: {:?}", file_groups);
 
-        Ok((file_groups, Statistics::new_unknown(self.schema().deref())))
+        let statistics = if ctx.config_options().execution.collect_statistics {
+            self.collect_statistics(
+                ctx,
+                &table_url,
+                &table_store_url,
+                &partition_descs,
+                &mut file_groups,
+            )
+            .await
+        } else {
+            Statistics::new_unknown(self.schema().deref())
+        };
+
+        Ok((file_groups, statistics))
+    }
+
+    /// Get the statistics stored in the metadata of the scanned data files of the table, keyed
+    /// by object store and location.
+    ///
+    /// The statistics are collected while the files are written, so reading them avoids
+    /// opening the footer of each file. Only the statistics of the scanned partitions are
+    /// queried, and only the ones of the scanned files are returned.
+    async fn stored_file_statistics(
+        &self,
+        table_url: &Url,
+        partition_descs: &[String],
+        files: &HashSet<(ObjectStoreUrl, Path)>,
+    ) -> HashMap<(ObjectStoreUrl, Path), StoredFileStatistics> {
+        match self
+            .client()
+            .get_file_statistics_by_table_id_and_partition_list(
+                self.table_id(),
+                partition_descs,
+            )
+            .await
+        {
+            Ok(file_statistics) => file_statistics
+                .into_iter()
+                .filter_map(|file_statistics| {
+                    let file_url =
+                        resolve_file_url(&file_statistics.file_path, table_url)
+                            .ok()
+                            .filter(|file_url| files.contains(file_url))?;
+                    let stored =
+                        serde_json::from_str(&file_statistics.statistics).ok()?;
+                    Some((file_url, stored))
+                })
+                .collect(),
+            Err(e) => {
+                warn!(
+                    "get stored file statistics failed, fallback to footers: {}",
+                    e
+                );
+                HashMap::new()
+            }
+        }
+    }
+
+    /// Infer the statistics of the files with bounded concurrency, attach them to the files
+    /// and aggregate them into the statistics of the table.
+    ///
+    /// The statistics stored in the metadata are used when present, otherwise they are inferred
+    /// from the file footers with the concurrency of `datafusion.execution.meta_fetch_concurrency`.
+    /// A file whose statistics can not be read is treated as having unknown statistics.
+    async fn collect_statistics(
+        &self,
+        ctx: &SessionState,
+        table_url: &Url,
+        table_store_url: &ObjectStoreUrl,
+        partition_descs: &[String],
+        file_groups: &mut [Vec<PartitionedFile>],
+    ) -> Statistics {
+        let file_schema = self.file_schema();
+        let format = self.options().format.clone();
+        let files = file_groups
+            .iter()
+            .flatten()
+            .map(|file| {
+                (
+                    file_object_store_url(file, table_store_url),
+                    file.object_meta.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let stored_file_statistics = self
+            .stored_file_statistics(
+                table_url,
+                partition_descs,
+                &files
+                    .iter()
+                    .map(|(object_store_url, object_meta)| {
+                        (object_store_url.clone(), object_meta.location.clone())
+                    })
+                    .collect(),
+            )
+            .await;
+        let stored_file_statistics = &stored_file_statistics;
+        let file_statistics = futures::stream::iter(files)
+            .map(|(object_store_url, object_meta)| {
+                let format = format.clone();
+                let file_schema = file_schema.clone();
+                async move {
+                    // the row counts of the data files include the rows deleted by the delete
+                    // vectors, the unknown statistics of a delete vector make them inexact
+                    if is_delete_vector(object_meta.location.as_ref()) {
+                        return Statistics::new_unknown(&file_schema);
+                    }
+                    let key = (object_store_url, object_meta.location.clone());
+                    if let Some(stored) = stored_file_statistics.get(&key) {
+                        return stored.to_statistics(&file_schema);
+                    }
+                    let inferred = async {
+                        let store = ctx.runtime_env().object_store(&key.0)?;
+                        format
+                            .infer_stats(ctx, &store, file_schema.clone(), &object_meta)
+                            .await
+                    };
+                    match inferred.await {
+                        Ok(statistics) => statistics,
+                        Err(e) => {
+                            warn!(
+                                "infer stats of {} failed, fallback to unknown: {}",
+                                object_meta.location, e
+                            );
+                            Statistics::new_unknown(&file_schema)
+                        }
+                    }
+                }
+            })
+            .buffered(ctx.config_options().execution.meta_fetch_concurrency)
+            .collect::<Vec<_>>()
+            .await;
+
+        // the merge on read and the cdc filter drop some of the rows counted in the files
+        let file_statistics = if self.scan_drops_file_rows() {
+            file_statistics
+                .into_iter()
+                .map(Statistics::to_inexact)
+                .collect::<Vec<_>>()
+        } else {
+            file_statistics
+        };
+        for (file, statistics) in file_groups.iter_mut().flatten().zip(&file_statistics) {
+            file.statistics = Some(Arc::new(statistics.clone()));
+        }
+
+        let mut statistics =
+            merge_file_statistics(&file_statistics, file_schema.as_ref());
+        // the range partition columns are not stored in the files
+        statistics.column_statistics.resize(
+            self.schema().fields().len(),
+            ColumnStatistics::new_unknown(),
+        );
+        statistics
     }
 }
 
@@ -454,67 +1063,15 @@ impl TableProvider for LakeSoulTableProvider {
             return Ok(Arc::new(EmptyExec::new(projected_schema)));
         }
 
-        // extract types of partition columns
-        // O(nm), n = number of partitions, m = number of columns
-        let table_partition_cols = self
-            .options()
-            .table_partition_cols
-            .iter()
-            .map(|col| Ok(self.schema().field_with_name(&col.0)?.clone()))
-            .collect::<Result<Vec<_>>>()?;
-
-        let filters = if let Some(expr) = conjunction(filters.to_vec()) {
-            // NOTE: Use the table schema (NOT file schema) here because `expr` may contain references to partition columns.
-            let table_df_schema = self.schema().as_ref().clone().to_dfschema()?;
-            let filters = create_physical_expr(
-                &expr,
-                &table_df_schema,
-                session_state.execution_props(),
-            )?;
-            Some(filters)
-        } else {
-            None
-        };
-
-        let object_store_url = if let Some(url) = self.table_paths().first() {
-            url.object_store()
-        } else {
+        let filters = self.physical_filters(session_state, filters)?;
+        let Some(config) =
+            self.file_scan_config(partitioned_file_lists, statistics, projection, limit)?
+        else {
             return Ok(Arc::new(EmptyExec::new(Arc::new(Schema::empty()))));
         };
-
-        // create the execution plan
-        let statistics = Arc::new(statistics);
-        let file_source = self
-            .options()
-            .format
-            .file_source()
-            .with_statistics(Statistics::new_unknown(&self.schema()));
         self.options()
             .format
-            .create_physical_plan(
-                session_state,
-                FileScanConfig {
-                    object_store_url,
-                    file_schema: self.schema(),
-                    file_groups: partitioned_file_lists
-                        .into_iter()
-                        .map(|files| {
-                            FileGroup::new(files).with_statistics(statistics.clone())
-                        })
-                        .collect(),
-                    constraints: Default::default(),
-                    // projection for Table instead of File
-                    projection: projection.cloned(),
-                    limit,
-                    output_ordering: self.try_create_output_ordering()?,
-                    file_compression_type: FileCompressionType::ZSTD,
-                    new_lines_in_values: false,
-                    file_source,
-                    table_partition_cols,
-                    batch_size: None,
-                },
-                filters.as_ref(),
-            )
+            .create_physical_plan(session_state, config, filters.as_ref())
             .await
     }
 
@@ -528,6 +1085,9 @@ impl TableProvider for LakeSoulTableProvider {
             .map(|f| {
                 if self.is_partition_filter(f) {
                     Ok(TableProviderFilterPushDown::Exact)
+                } else if self.is_hash_partition_filter(f) {
+                    // the filters on the hash partition keys select the hash buckets read
+                    Ok(TableProviderFilterPushDown::Inexact)
                 } else {
                     Ok(TableProviderFilterPushDown::Unsupported)
                 }
@@ -570,9 +1130,28 @@ impl TableProvider for LakeSoulTableProvider {
         // todo: fix this
         let order_requirements = None;
 
-        self.options()
-            .format
-            .create_writer_physical_plan(input, state, config, order_requirements)
-            .await
+        // the hook of the provider replaces the one of the format
+        let format = self.options().format.as_any();
+        match (
+            &self.commit_hook,
+            format.downcast_ref::<LakeSoulMetaDataParquetFormat>(),
+        ) {
+            (Some(commit_hook), Some(format)) => {
+                format
+                    .create_sink_exec(
+                        input,
+                        config,
+                        order_requirements,
+                        Some(commit_hook),
+                    )
+                    .await
+            }
+            _ => {
+                self.options()
+                    .format
+                    .create_writer_physical_plan(input, state, config, order_requirements)
+                    .await
+            }
+        }
     }
 }
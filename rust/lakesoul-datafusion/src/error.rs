@@ -43,3 +43,157 @@ pub enum LakeSoulError {
     )]
     Internal(String),
 }
+
+/// Error type of the write path of the LakeSoul sink, so that callers can tell the failure
+/// categories apart, e.g. to retry a write whose metadata commit failed.
+///
+/// It is carried by [`DataFusionError::External`] through the [`ExecutionPlan`] API, see
+/// [`LakeSoulWriteError::find`].
+///
+/// [`ExecutionPlan`]: datafusion::physical_plan::ExecutionPlan
+#[derive(Debug, thiserror::Error)]
+pub enum LakeSoulWriteError {
+    #[error("metadata commit error: {0}")]
+    MetadataCommit(LakeSoulError),
+    #[error("metadata error: {0}")]
+    MetaData(#[from] LakeSoulMetaDataError),
+    #[error("object store error: {0}")]
+    ObjectStore(#[from] object_store::Error),
+    #[error("serialization error: {0}")]
+    Serialization(GenericError),
+    #[error("invalid data file path {path}: {source}")]
+    InvalidPath { path: String, source: GenericError },
+    #[error("write task failed: {0}")]
+    TaskJoin(#[from] tokio::task::JoinError),
+    #[error("the write was cancelled before its commit")]
+    Cancelled,
+    #[error("column {column} of the written batch {reason}")]
+    SchemaMismatch { column: String, reason: String },
+    #[error(
+        "data file {path} has {actual} bytes in the object store, \
+    but {expected} bytes were written"
+    )]
+    FileSizeMismatch {
+        path: String,
+        expected: u64,
+        actual: u64,
+    },
+    #[error(
+        "files of partition {partition_desc} committed, \
+    but the commit hook failed: {source}"
+    )]
+    CommitHook {
+        partition_desc: String,
+        source: DataFusionError,
+    },
+    #[error(
+        "partition {partition_desc} was committed concurrently since the write was planned, \
+    version {planned_version:?} at the planning, {current_version:?} at the commit"
+    )]
+    CommitConflict {
+        partition_desc: String,
+        planned_version: Option<i32>,
+        current_version: Option<i32>,
+    },
+}
+
+impl LakeSoulWriteError {
+    /// Find the [`LakeSoulWriteError`] wrapped by a [`DataFusionError`], if any.
+    pub fn find(error: &DataFusionError) -> Option<&LakeSoulWriteError> {
+        match error {
+            DataFusionError::External(e) => e.downcast_ref::<LakeSoulWriteError>(),
+            DataFusionError::Context(_, e) => Self::find(e),
+            DataFusionError::Shared(e) => Self::find(e),
+            _ => None,
+        }
+    }
+
+    /// Wrap an error of the metadata commit, keeping a commit conflict detected by the
+    /// commit transaction typed as [`LakeSoulWriteError::CommitConflict`] and the failed
+    /// serialization of the statistics as [`LakeSoulWriteError::Serialization`].
+    pub(crate) fn metadata_commit(e: LakeSoulError) -> Self {
+        match e {
+            LakeSoulError::SerdeJsonError(e) => {
+                LakeSoulWriteError::Serialization(Box::new(e))
+            }
+            LakeSoulError::MetaDataError(LakeSoulMetaDataError::CommitConflict {
+                partition_desc,
+                read_version,
+                current_version,
+                ..
+            }) => LakeSoulWriteError::CommitConflict {
+                partition_desc,
+                planned_version: read_version,
+                current_version,
+            },
+            e => LakeSoulWriteError::MetadataCommit(e),
+        }
+    }
+
+    /// Whether the write failed without committing anything and can be retried as it is.
+    pub fn is_retriable(&self) -> bool {
+        matches!(self, LakeSoulWriteError::CommitConflict { .. })
+    }
+}
+
+impl From<LakeSoulWriteError> for DataFusionError {
+    fn from(e: LakeSoulWriteError) -> Self {
+        DataFusionError::External(Box::new(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit_conflict() -> LakeSoulMetaDataError {
+        LakeSoulMetaDataError::CommitConflict {
+            table_id: "table_id".to_string(),
+            partition_desc: "-5".to_string(),
+            read_version: Some(1),
+            current_version: Some(2),
+        }
+    }
+
+    #[test]
+    fn test_metadata_commit_error_categories() {
+        let e = LakeSoulWriteError::metadata_commit(commit_conflict().into());
+        assert!(matches!(
+            &e,
+            LakeSoulWriteError::CommitConflict {
+                partition_desc,
+                planned_version: Some(1),
+                current_version: Some(2),
+            } if partition_desc == "-5"
+        ));
+        assert!(e.is_retriable());
+
+        let json_error = serde_json::from_str::<u64>("{").unwrap_err();
+        let e = LakeSoulWriteError::metadata_commit(json_error.into());
+        assert!(matches!(e, LakeSoulWriteError::Serialization(_)));
+        assert!(!e.is_retriable());
+
+        let e = LakeSoulWriteError::metadata_commit(LakeSoulError::Internal(
+            "table not found".to_string(),
+        ));
+        assert!(matches!(e, LakeSoulWriteError::MetadataCommit(_)));
+        assert!(!e.is_retriable());
+
+        // a conflict of a metadata query outside of the commit is not retried as a write
+        let e = LakeSoulWriteError::MetaData(commit_conflict());
+        assert!(!e.is_retriable());
+    }
+
+    #[test]
+    fn test_find_write_error() {
+        let error: DataFusionError = LakeSoulWriteError::Cancelled.into();
+        let error = DataFusionError::Shared(Arc::new(error.context("sink")));
+        assert!(matches!(
+            LakeSoulWriteError::find(&error),
+            Some(LakeSoulWriteError::Cancelled)
+        ));
+
+        let error = DataFusionError::Execution("the write was cancelled".to_string());
+        assert!(LakeSoulWriteError::find(&error).is_none());
+    }
+}
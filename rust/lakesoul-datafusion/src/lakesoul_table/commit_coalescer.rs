@@ -0,0 +1,201 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Coalescing of commits from high-frequency writers.
+//!
+//! Streaming writers may flush a small set of files every few seconds. Committing each flush
+//! separately bloats the commit history, so the [`CommitCoalescer`] buffers the flush results of
+//! each table and commits them together at most once per interval or accumulated size.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use lakesoul_io::async_writer::WriterFlushResult;
+use tokio::sync::Mutex;
+
+use super::LakeSoulTable;
+use crate::error::Result;
+
+/// The flush results of a table waiting to be committed.
+#[derive(Debug)]
+struct PendingCommit {
+    /// The table to commit into.
+    table: Arc<LakeSoulTable>,
+    /// The accumulated flush results.
+    result: WriterFlushResult,
+    /// The accumulated size of the files in bytes.
+    bytes: u64,
+    /// The time of the first buffered flush result.
+    since: Instant,
+}
+
+/// Buffers the flush results of streaming writers and commits them in one commit per table.
+///
+/// The flush results stay buffered until their commit succeeds: a failed commit puts them
+/// back, to be committed again with the next commit of the table. On crash, at most the data
+/// written since the last successful commit is lost.
+#[derive(Debug)]
+pub struct CommitCoalescer {
+    /// The maximum time a flush result is buffered before committing.
+    interval: Duration,
+    /// The accumulated size of files in bytes that triggers a commit.
+    max_bytes: u64,
+    /// The pending commits keyed by table id.
+    pending: Mutex<HashMap<String, PendingCommit>>,
+}
+
+impl CommitCoalescer {
+    /// Create a new [`CommitCoalescer`].
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - The maximum time a flush result is buffered before committing
+    /// * `max_bytes` - The accumulated size of files in bytes that triggers a commit
+    pub fn new(interval: Duration, max_bytes: u64) -> Self {
+        Self {
+            interval,
+            max_bytes,
+            pending: Default::default(),
+        }
+    }
+
+    /// Returns the maximum time a flush result is buffered before committing.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Buffer the flush result of a writer, committing the buffered results of the table
+    /// if the interval has elapsed or the accumulated size has been reached.
+    ///
+    /// Once buffered, the flush result is committed eventually: if the commit fails, the
+    /// error is logged and the results stay buffered, so the writer must not write them
+    /// again.
+    pub async fn append(
+        &self,
+        table: Arc<LakeSoulTable>,
+        result: WriterFlushResult,
+    ) -> Result<()> {
+        let bytes = result.iter().map(|(_, _, meta, _)| meta.size).sum::<u64>();
+        let due = {
+            let mut pending = self.pending.lock().await;
+            let entry = pending
+                .entry(table.table_info().table_id.clone())
+                .or_insert_with(|| PendingCommit {
+                    table,
+                    result: vec![],
+                    bytes: 0,
+                    since: Instant::now(),
+                });
+            entry.result.extend(result);
+            entry.bytes += bytes;
+            if self.is_due(entry) {
+                let table_id = entry.table.table_info().table_id.clone();
+                pending.remove(&table_id)
+            } else {
+                None
+            }
+        };
+        if let Some(pending) = due {
+            if let Err(e) = self.commit(pending).await {
+                error!(
+                    "commit coalesced stream writes failed, retrying later: {}",
+                    e
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Commit the buffered results of all tables whose interval has elapsed.
+    ///
+    /// The results of the tables whose commit fails stay buffered, the first error is
+    /// returned once the other tables are committed.
+    pub async fn flush_expired(&self) -> Result<()> {
+        let due = {
+            let mut pending = self.pending.lock().await;
+            let table_ids = pending
+                .iter()
+                .filter(|(_, entry)| self.is_due(entry))
+                .map(|(table_id, _)| table_id.clone())
+                .collect::<Vec<_>>();
+            table_ids
+                .iter()
+                .filter_map(|table_id| pending.remove(table_id))
+                .collect::<Vec<_>>()
+        };
+        self.commit_all(due).await
+    }
+
+    /// Commit the buffered results of all tables.
+    ///
+    /// The results of the tables whose commit fails stay buffered, the first error is
+    /// returned once the other tables are committed.
+    pub async fn flush_all(&self) -> Result<()> {
+        let due = {
+            let mut pending = self.pending.lock().await;
+            pending.drain().map(|(_, entry)| entry).collect::<Vec<_>>()
+        };
+        self.commit_all(due).await
+    }
+
+    fn is_due(&self, pending: &PendingCommit) -> bool {
+        pending.since.elapsed() >= self.interval || pending.bytes >= self.max_bytes
+    }
+
+    async fn commit_all(&self, due: Vec<PendingCommit>) -> Result<()> {
+        let mut first_error = None;
+        for pending in due {
+            if let Err(e) = self.commit(pending).await {
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+
+    /// Commit the buffered results of a table, buffering them again if the commit fails.
+    async fn commit(&self, pending: PendingCommit) -> Result<()> {
+        debug!(
+            "Committing {} coalesced files ({} bytes) of table {}",
+            pending.result.len(),
+            pending.bytes,
+            pending.table.table_name()
+        );
+        match pending
+            .table
+            .commit_flush_result(pending.result.clone())
+            .await
+        {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                self.restore(pending).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Put the results of a failed commit back in front of the results buffered since.
+    async fn restore(&self, failed: PendingCommit) {
+        let mut pending = self.pending.lock().await;
+        let table_id = failed.table.table_info().table_id.clone();
+        match pending.remove(&table_id) {
+            Some(buffered) => {
+                let mut result = failed.result;
+                result.extend(buffered.result);
+                pending.insert(
+                    table_id,
+                    PendingCommit {
+                        table: buffered.table,
+                        result,
+                        bytes: failed.bytes + buffered.bytes,
+                        since: failed.since.min(buffered.since),
+                    },
+                );
+            }
+            None => {
+                pending.insert(table_id, failed);
+            }
+        }
+    }
+}
@@ -4,11 +4,11 @@
 
 //! The utilities for LakeSoul table.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use arrow::{
-    array::{Array, ArrayRef, AsArray, StringBuilder},
+    array::{Array, ArrayRef, AsArray, BooleanArray, StringBuilder},
     compute::prep_null_mask_filter,
     datatypes::{DataType, Field, Fields, Schema},
     record_batch::RecordBatch,
@@ -17,17 +17,29 @@ use arrow_arith::boolean::and;
 use arrow_cast::cast;
 
 use datafusion::{
-    common::DFSchema, error::DataFusionError, execution::context::ExecutionProps,
-    logical_expr::Expr, physical_expr::create_physical_expr,
+    common::DFSchema,
+    error::DataFusionError,
+    execution::context::ExecutionProps,
+    execution::object_store::ObjectStoreUrl,
+    execution::runtime_env::RuntimeEnv,
+    logical_expr::{
+        BinaryExpr, Cast, Expr, Operator, TryCast, expr::InList, utils::split_conjunction,
+    },
+    physical_expr::create_physical_expr,
+    scalar::ScalarValue,
+};
+use lakesoul_io::helpers::{
+    columnar_values_to_partition_desc, compute_scalar_hash, resolve_file_url,
 };
 use lakesoul_metadata::MetaDataClientRef;
-use object_store::{ObjectMeta, ObjectStore, path::Path};
+use object_store::ObjectMeta;
 use url::Url;
 
 use crate::error::Result;
 use crate::serialize::arrow_java::schema_from_metadata_str;
 use lakesoul_io::lakesoul_io_config::{
-    LakeSoulIOConfigBuilder, OPTION_KEY_CDC_COLUMN, OPTION_KEY_STABLE_SORT,
+    LakeSoulIOConfigBuilder, OPTION_KEY_CDC_COLUMN, OPTION_KEY_MERGE_STRATEGY,
+    OPTION_KEY_PARQUET_COMPRESSION, OPTION_KEY_STABLE_SORT,
 };
 use proto::proto::entity::{PartitionInfo, TableInfo};
 
@@ -61,6 +73,45 @@ pub(crate) fn create_io_config_builder_from_table_info(
         .with_option(OPTION_KEY_STABLE_SORT, use_cdc)
         .with_option(OPTION_KEY_CDC_COLUMN, cdc_column);
 
+    if let Some(nulls_first) = properties.nulls_first {
+        builder = builder.with_nulls_first(nulls_first);
+    }
+    if let Some(sequence_column) = properties.sequence_column {
+        builder = builder.with_sequence_column(sequence_column);
+    }
+    // the layout of the table is kept unless the options of the session override it
+    if let Some(encoding) = properties.partition_path_encoding {
+        builder = builder.with_partition_path_encoding(encoding);
+    }
+    if let Some(keep_partition_columns) = properties.keep_partition_columns {
+        builder = builder.with_keep_partition_columns(keep_partition_columns);
+    }
+    if let Some(bloom_filter) = properties.primary_key_bloom_filter {
+        builder = builder.with_primary_key_bloom_filter(bloom_filter);
+    }
+    if let Some(strategy) = properties.merge_strategy {
+        builder = builder.with_option(OPTION_KEY_MERGE_STRATEGY, strategy);
+    }
+
+    // the encryption of the table is kept unless the options of the session override it
+    if let (Some(kms), Some(columns)) =
+        (properties.encryption_kms, properties.encrypted_columns)
+    {
+        builder = builder.with_encrypted_columns(kms, columns);
+    }
+    if let Some(key_id) = properties.encryption_key_id {
+        builder = builder.with_encryption_key_id(key_id);
+    }
+
+    // the codec of the table is kept unless the options of the session override it
+    if let Some(compression) = properties.parquet_compression {
+        builder = builder.with_option(OPTION_KEY_PARQUET_COMPRESSION, compression);
+    }
+
+    for (field_name, expr) in properties.generated_columns.unwrap_or_default() {
+        builder = builder.with_generated_column(field_name, expr);
+    }
+
     for (key, value) in options {
         builder = builder.with_option(key, value);
     }
@@ -131,23 +182,37 @@ pub async fn prune_partitions(
     // TODO: Plumb this down
     let props = ExecutionProps::new();
 
-    // Applies `filter` to `batch` returning `None` on error
-    let do_filter = |filter| -> Option<ArrayRef> {
-        let expr = create_physical_expr(filter, &df_schema, &props).ok()?;
-        expr.evaluate(&batch)
-            .ok()?
-            .into_array(all_partition_info.len())
-            .ok()
+    // Applies `filter` to `batch`
+    let do_filter = |filter| -> Result<ArrayRef> {
+        let expr = create_physical_expr(filter, &df_schema, &props)?;
+        Ok(expr
+            .evaluate(&batch)?
+            .into_array(all_partition_info.len())?)
     };
 
-    //.Compute the conjunction of the filters, ignoring errors
-    let mask = filters
-        .iter()
-        .fold(None, |acc, filter| match (acc, do_filter(filter)) {
-            (Some(a), Some(b)) => Some(and(&a, b.as_boolean()).unwrap_or(a)),
-            (None, Some(r)) => Some(r.as_boolean().clone()),
-            (r, None) => r,
+    // Compute the conjunction of the filters. The filters on the partition columns are
+    // pushed down exactly and never evaluated on the rows, as the partition columns are
+    // not stored in the files, so a filter which can not be evaluated fails the scan
+    // instead of being ignored.
+    let mut mask: Option<BooleanArray> = None;
+    for filter in filters {
+        let result = do_filter(filter).map_err(|e| {
+            DataFusionError::Plan(format!(
+                "filter {} on the partition columns can not be evaluated: {}",
+                filter, e
+            ))
+        })?;
+        let result = result.as_boolean_opt().ok_or_else(|| {
+            DataFusionError::Plan(format!(
+                "filter {} on the partition columns is not a predicate",
+                filter
+            ))
+        })?;
+        mask = Some(match mask {
+            Some(mask) => and(&mask, result)?,
+            None => result.clone(),
         });
+    }
 
     let mask = match mask {
         Some(mask) => mask,
@@ -172,6 +237,158 @@ pub async fn prune_partitions(
     Ok(filtered)
 }
 
+/// The maximum number of partition descriptions derived from the filters to look up.
+const MAX_PARTITION_DESC_LOOKUP: usize = 1024;
+
+/// Returns the values a filter restricts the column to, if it only matches a finite set of
+/// values, e.g. `dt = '2024-01-01'`, `dt IN ('2024-01-01', '2024-01-02')` or a disjunction
+/// of both.
+fn partition_column_values(
+    filter: &Expr,
+    column: &str,
+    data_type: &DataType,
+) -> Option<Vec<ScalarValue>> {
+    let is_column = |expr: &Expr| matches!(expr, Expr::Column(c) if c.name == column);
+    let literal = |expr: &Expr| {
+        let value = match expr {
+            Expr::Literal(value) => value,
+            Expr::Cast(Cast { expr, .. }) | Expr::TryCast(TryCast { expr, .. }) => {
+                match expr.as_ref() {
+                    Expr::Literal(value) => value,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        value
+            .cast_to(data_type)
+            .ok()
+            .filter(|value| !value.is_null())
+    };
+    match filter {
+        Expr::BinaryExpr(BinaryExpr {
+            left,
+            op: Operator::Eq,
+            right,
+        }) => {
+            if is_column(left) {
+                literal(right).map(|value| vec![value])
+            } else if is_column(right) {
+                literal(left).map(|value| vec![value])
+            } else {
+                None
+            }
+        }
+        Expr::BinaryExpr(BinaryExpr {
+            left,
+            op: Operator::Or,
+            right,
+        }) => {
+            let mut values = partition_column_values(left, column, data_type)?;
+            values.extend(partition_column_values(right, column, data_type)?);
+            Some(values)
+        }
+        Expr::InList(InList {
+            expr,
+            list,
+            negated: false,
+        }) if is_column(expr) => list.iter().map(literal).collect(),
+        _ => None,
+    }
+}
+
+/// Returns the descriptions of the partitions the filters may match, if the filters restrict
+/// every range partition column to a finite set of values.
+///
+/// The partitions can then be looked up in the metadata directly instead of listing all
+/// partitions of the table. Filters on ranges of values are left to [`prune_partitions`].
+pub fn partition_descs_from_filters(
+    filters: &[Expr],
+    partition_cols: &[(String, DataType)],
+) -> Option<Vec<String>> {
+    if partition_cols.is_empty() {
+        return None;
+    }
+    let conjuncts = filters
+        .iter()
+        .flat_map(split_conjunction)
+        .collect::<Vec<_>>();
+    let mut descs = vec![Vec::<(String, ScalarValue)>::new()];
+    for (column, data_type) in partition_cols {
+        let mut values = conjuncts
+            .iter()
+            .find_map(|filter| partition_column_values(filter, column, data_type))?;
+        values.dedup();
+        if descs.len() * values.len() > MAX_PARTITION_DESC_LOOKUP {
+            return None;
+        }
+        descs = descs
+            .into_iter()
+            .flat_map(|desc| {
+                values.iter().map(move |value| {
+                    let mut desc = desc.clone();
+                    desc.push((column.clone(), value.clone()));
+                    desc
+                })
+            })
+            .collect();
+    }
+    Some(
+        descs
+            .iter()
+            .map(|desc| columnar_values_to_partition_desc(desc))
+            .collect(),
+    )
+}
+
+/// Returns the hash buckets of the rows the filters may match, if the filters restrict the
+/// hash partition key to a finite set of values, like [`partition_descs_from_filters`] for
+/// the range partitions.
+///
+/// The rows of a table with primary keys are written into the files of the bucket of the
+/// hash of their keys, the last number of the file names, so only the files of these
+/// buckets need to be read. The values are hashed with the type of the key in the table
+/// schema like the written rows, and the tables with several hash partition keys are not
+/// pruned.
+pub fn hash_buckets_from_filters(
+    filters: &[Expr],
+    hash_partition_cols: &[(String, DataType)],
+    hash_bucket_num: usize,
+) -> Option<HashSet<u32>> {
+    let [(column, data_type)] = hash_partition_cols else {
+        return None;
+    };
+    // the types hashed alike by the writers and by the literals of the filters
+    if !matches!(
+        data_type,
+        DataType::Int8
+            | DataType::Int16
+            | DataType::Int32
+            | DataType::Int64
+            | DataType::UInt8
+            | DataType::UInt16
+            | DataType::UInt32
+            | DataType::UInt64
+            | DataType::Utf8
+            | DataType::LargeUtf8
+            | DataType::Binary
+            | DataType::LargeBinary
+    ) {
+        return None;
+    }
+    filters
+        .iter()
+        .flat_map(split_conjunction)
+        .filter_map(|filter| partition_column_values(filter, column, data_type))
+        .map(|values| {
+            values
+                .iter()
+                .map(|value| compute_scalar_hash(value) % hash_bucket_num as u32)
+                .collect::<HashSet<_>>()
+        })
+        .reduce(|buckets, other| buckets.intersection(&other).copied().collect())
+}
+
 /// Parse the partition description and the table partition columns.
 pub fn parse_partitions_for_partition_desc<'a, I>(
     partition_desc: &'a str,
@@ -197,11 +414,20 @@ where
 }
 
 /// Listing the partition info and the files from the metadata client.
+///
+/// Each file is looked up in the object store named by the scheme and authority of its path,
+/// a path without a scheme is relative to `table_url`. The delete vectors of the data files
+/// are listed among the files, the scans of [`LakeSoulMetaDataParquetFormat`] apply them to
+/// their data files, see [`is_delete_vector`].
+///
+/// [`LakeSoulMetaDataParquetFormat`]: crate::datasource::file_format::LakeSoulMetaDataParquetFormat
+/// [`is_delete_vector`]: crate::datasource::delete_vector::is_delete_vector
 pub async fn listing_partition_info(
     partition_info: PartitionInfo,
-    store: &dyn ObjectStore,
+    runtime_env: &RuntimeEnv,
+    table_url: &Url,
     client: MetaDataClientRef,
-) -> datafusion::error::Result<(PartitionInfo, Vec<ObjectMeta>)> {
+) -> datafusion::error::Result<(PartitionInfo, Vec<(ObjectStoreUrl, ObjectMeta)>)> {
     info!("Listing partition {:?}", partition_info);
     let paths = client
         .get_data_files_of_single_partition(&partition_info)
@@ -209,14 +435,9 @@ pub async fn listing_partition_info(
         .map_err(|_| DataFusionError::External("listing partition info failed".into()))?;
     let mut files = Vec::new();
     for path in paths {
-        let result = store
-            .head(&Path::from_url_path(
-                Url::parse(path.as_str())
-                    .map_err(|e| DataFusionError::External(Box::new(e)))?
-                    .path(),
-            )?)
-            .await?;
-        files.push(result);
+        let (object_store_url, location) = resolve_file_url(&path, table_url)?;
+        let store = runtime_env.object_store(&object_store_url)?;
+        files.push((object_store_url, store.head(&location).await?));
     }
     Ok((partition_info, files))
 }
@@ -230,3 +451,63 @@ pub fn case_fold_table_name(name: &str) -> String {
 pub fn case_fold_column_name(name: &str) -> String {
     name.to_ascii_lowercase()
 }
+
+/// Returns whether every value of the `from` type is represented exactly by the `to` type,
+/// so that a column can be widened in the table schema and the older files cast up when
+/// they are read.
+pub fn is_widening_cast(from: &DataType, to: &DataType) -> bool {
+    use DataType::*;
+    match (from, to) {
+        _ if from == to => true,
+        (Int8, Int16 | Int32 | Int64 | Float32 | Float64)
+        | (Int16, Int32 | Int64 | Float32 | Float64)
+        | (Int32, Int64 | Float64)
+        | (UInt8, UInt16 | UInt32 | UInt64 | Int16 | Int32 | Int64 | Float32 | Float64)
+        | (UInt16, UInt32 | UInt64 | Int32 | Int64 | Float32 | Float64)
+        | (UInt32, UInt64 | Int64 | Float64)
+        | (Float16, Float32 | Float64)
+        | (Float32, Float64)
+        | (Utf8, LargeUtf8)
+        | (Binary, LargeBinary)
+        | (Date32, Date64) => true,
+        (Decimal128(from_precision, from_scale), Decimal128(to_precision, to_scale)) => {
+            to_scale >= from_scale
+                && (*to_precision as i16 - *to_scale as i16)
+                    >= (*from_precision as i16 - *from_scale as i16)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::prelude::{col, lit};
+
+    #[test]
+    fn test_partition_descs_from_filters() {
+        // the equality filter names the only partition to look up
+        let partition_cols = [("dt".to_string(), DataType::Utf8)];
+        let filter = col("dt").eq(lit("2024-01-01"));
+        assert_eq!(
+            partition_descs_from_filters(&[filter], &partition_cols),
+            Some(vec!["dt=2024-01-01".to_string()])
+        );
+        assert_eq!(
+            partition_descs_from_filters(
+                &[col("dt").in_list(vec![lit("2024-01-01"), lit("2024-01-02")], false)],
+                &partition_cols
+            ),
+            Some(vec![
+                "dt=2024-01-01".to_string(),
+                "dt=2024-01-02".to_string()
+            ])
+        );
+        // the range filters name no partition, all of them are looked up
+        let filter = col("dt").gt(lit("2024-01-01"));
+        assert_eq!(
+            partition_descs_from_filters(&[filter], &partition_cols),
+            None
+        );
+    }
+}
@@ -0,0 +1,443 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Ingestion of JSON and CSV input into a LakeSoul table.
+//!
+//! The text input is decoded into batches of the table schema and written through the same
+//! sink as an insert, so the data files stay parquet. The values are first decoded as
+//! strings and then cast to the types of the table columns row by row, so that a value
+//! which can not be coerced only rejects its row. The ingestion fails without committing
+//! once more rows are rejected than the options tolerate.
+
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use arrow::array::{Array, ArrayRef, AsArray, BooleanArray, new_null_array};
+use arrow::compute::filter_record_batch;
+use arrow::csv::reader::Format;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use arrow_cast::cast::{CastOptions, cast_with_options};
+use bytes::{Buf, Bytes};
+use datafusion::catalog::streaming::StreamingTable;
+use datafusion::error::{DataFusionError, Result as DataFusionResult};
+use datafusion::execution::TaskContext;
+use datafusion::physical_plan::SendableRecordBatchStream;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::streaming::PartitionStream;
+use datafusion::prelude::SessionContext;
+use futures::{Stream, StreamExt};
+
+use super::LakeSoulTable;
+use crate::error::Result;
+
+/// The text format of the input of an ingestion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IngestFormat {
+    /// Newline delimited JSON objects keyed by the column names. Keys which are not columns
+    /// of the table are ignored.
+    Json,
+    /// Delimited text starting with a header line naming the columns of the table.
+    Csv {
+        /// The delimiter of the values.
+        delimiter: u8,
+    },
+}
+
+/// The options of [`LakeSoulTable::ingest`].
+#[derive(Debug, Clone)]
+pub struct IngestOptions {
+    format: IngestFormat,
+    max_rejected_rows: usize,
+    batch_size: usize,
+}
+
+impl IngestOptions {
+    /// Create the options of an ingestion of the format, rejecting no rows.
+    pub fn new(format: IngestFormat) -> Self {
+        Self {
+            format,
+            max_rejected_rows: 0,
+            batch_size: 8192,
+        }
+    }
+
+    /// Tolerate up to `max_rejected_rows` rows whose values can not be coerced to the table
+    /// schema, they are skipped and listed in the [`IngestReport`].
+    pub fn with_max_rejected_rows(mut self, max_rejected_rows: usize) -> Self {
+        self.max_rejected_rows = max_rejected_rows;
+        self
+    }
+
+    /// Set the number of rows of the decoded batches.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+}
+
+/// A row of the input rejected by an ingestion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectedRow {
+    /// The index of the row in the input, starting at 0 and not counting the CSV header.
+    pub row: usize,
+    /// Why the row was rejected.
+    pub reason: String,
+}
+
+/// The outcome of an ingestion.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IngestReport {
+    /// The number of rows written into the table.
+    pub num_rows: u64,
+    /// The rows skipped as their values do not match the table schema.
+    pub rejected_rows: Vec<RejectedRow>,
+}
+
+/// Ingest the JSON or CSV input into the table, see [`LakeSoulTable::ingest`].
+pub(crate) async fn ingest_table<S>(
+    table: &LakeSoulTable,
+    input: S,
+    options: IngestOptions,
+) -> Result<IngestReport>
+where
+    S: Stream<Item = DataFusionResult<Bytes>> + Send + Unpin + 'static,
+{
+    let schema = table.schema();
+    let report = Arc::new(Mutex::new(IngestReport::default()));
+    let state = IngestState {
+        input,
+        buffered: Bytes::new(),
+        decoder: None,
+        finished: false,
+        coercion: Coercion {
+            table_schema: schema.clone(),
+            next_row: 0,
+            max_rejected_rows: options.max_rejected_rows,
+            report: report.clone(),
+        },
+        options,
+    };
+    let stream = futures::stream::try_unfold(state, |mut state| async move {
+        let batch = state.next_batch().await?;
+        Ok(batch.map(|batch| (batch, state)))
+    });
+    let partition = Arc::new(IngestPartition {
+        schema: schema.clone(),
+        stream: Mutex::new(Some(Box::pin(RecordBatchStreamAdapter::new(
+            schema.clone(),
+            stream,
+        )))),
+    });
+    let input = StreamingTable::try_new(schema, vec![partition])?;
+    let dataframe = SessionContext::new().read_table(Arc::new(input))?;
+    table.upsert_dataframe(dataframe).await?;
+
+    let report = std::mem::take(&mut *report.lock().unwrap());
+    info!(
+        "ingest into table {}: {} rows written, {} rows rejected",
+        table.table_name(),
+        report.num_rows,
+        report.rejected_rows.len()
+    );
+    Ok(report)
+}
+
+/// The single partition of the decoded input, which can only be executed once.
+struct IngestPartition {
+    schema: SchemaRef,
+    stream: Mutex<Option<SendableRecordBatchStream>>,
+}
+
+impl std::fmt::Debug for IngestPartition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IngestPartition")
+            .field("schema", &self.schema)
+            .finish()
+    }
+}
+
+impl PartitionStream for IngestPartition {
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    fn execute(&self, _ctx: Arc<TaskContext>) -> SendableRecordBatchStream {
+        match self.stream.lock().unwrap().take() {
+            Some(stream) => stream,
+            None => Box::pin(RecordBatchStreamAdapter::new(
+                self.schema.clone(),
+                futures::stream::once(async {
+                    Err(DataFusionError::Execution(
+                        "the ingested input can only be read once".to_string(),
+                    ))
+                }),
+            )),
+        }
+    }
+}
+
+/// The decoder of the values of the input as strings, the nested JSON values keep the type
+/// of their column.
+enum RawDecoder {
+    Json(arrow::json::reader::Decoder),
+    Csv(arrow::csv::reader::Decoder),
+}
+
+impl RawDecoder {
+    fn decode(&mut self, buf: &[u8]) -> DataFusionResult<usize> {
+        Ok(match self {
+            RawDecoder::Json(decoder) => decoder.decode(buf)?,
+            RawDecoder::Csv(decoder) => decoder.decode(buf)?,
+        })
+    }
+
+    fn flush(&mut self) -> DataFusionResult<Option<RecordBatch>> {
+        Ok(match self {
+            RawDecoder::Json(decoder) => decoder.flush()?,
+            RawDecoder::Csv(decoder) => decoder.flush()?,
+        })
+    }
+}
+
+/// The state of the decoding of the input into batches of the table schema.
+struct IngestState<S> {
+    input: S,
+    /// The bytes of the input not yet decoded.
+    buffered: Bytes,
+    /// The decoder, created with the first batch once the CSV header is read.
+    decoder: Option<RawDecoder>,
+    /// Whether the input is exhausted.
+    finished: bool,
+    coercion: Coercion,
+    options: IngestOptions,
+}
+
+impl<S> IngestState<S>
+where
+    S: Stream<Item = DataFusionResult<Bytes>> + Unpin,
+{
+    /// Decode the next batch of the input, `None` once the input is exhausted.
+    async fn next_batch(&mut self) -> DataFusionResult<Option<RecordBatch>> {
+        if self.decoder.is_none() {
+            self.decoder = Some(self.create_decoder().await?);
+        }
+        loop {
+            if self.buffered.is_empty() && !self.finished {
+                match self.input.next().await.transpose()? {
+                    Some(bytes) => self.buffered = bytes,
+                    None => self.finished = true,
+                }
+            }
+            // an empty buffer signals the end of the input to the decoder
+            let decoder = self.decoder.as_mut().unwrap();
+            let decoded = decoder.decode(&self.buffered)?;
+            self.buffered.advance(decoded);
+            // the decoder stops before the end of the buffer once a batch is full
+            if !self.buffered.is_empty() || self.finished {
+                match decoder.flush()? {
+                    Some(batch) => return self.coercion.coerce(batch).map(Some),
+                    None if self.finished && self.buffered.is_empty() => return Ok(None),
+                    None => {}
+                }
+            }
+        }
+    }
+
+    /// Create the decoder of the values as strings, reading the header of a CSV input.
+    async fn create_decoder(&mut self) -> DataFusionResult<RawDecoder> {
+        let table_schema = self.coercion.table_schema.clone();
+        Ok(match self.options.format {
+            IngestFormat::Json => {
+                let raw_schema = Schema::new(
+                    table_schema
+                        .fields()
+                        .iter()
+                        .map(|field| raw_field(field))
+                        .collect::<Vec<_>>(),
+                );
+                RawDecoder::Json(
+                    arrow::json::ReaderBuilder::new(Arc::new(raw_schema))
+                        .with_batch_size(self.options.batch_size)
+                        .with_coerce_primitive(true)
+                        .build_decoder()?,
+                )
+            }
+            IngestFormat::Csv { delimiter } => {
+                let header = self.read_header().await?;
+                let (header_schema, _) = Format::default()
+                    .with_header(true)
+                    .with_delimiter(delimiter)
+                    .infer_schema(Cursor::new(header), Some(0))?;
+                let raw_schema = csv_raw_schema(&header_schema, &table_schema)?;
+                RawDecoder::Csv(
+                    arrow::csv::ReaderBuilder::new(Arc::new(raw_schema))
+                        .with_batch_size(self.options.batch_size)
+                        .with_delimiter(delimiter)
+                        .build_decoder(),
+                )
+            }
+        })
+    }
+
+    /// Read the CSV header line including its line break, which may span several chunks
+    /// of the input.
+    async fn read_header(&mut self) -> DataFusionResult<Bytes> {
+        loop {
+            if let Some(idx) = self.buffered.iter().position(|b| *b == b'\n') {
+                return Ok(self.buffered.split_to(idx + 1));
+            }
+            match self.input.next().await.transpose()? {
+                Some(bytes) => {
+                    let buffered = [self.buffered.as_ref(), bytes.as_ref()].concat();
+                    self.buffered = Bytes::from(buffered);
+                }
+                None => {
+                    self.finished = true;
+                    return Ok(std::mem::take(&mut self.buffered));
+                }
+            }
+        }
+    }
+}
+
+/// The field of the values of a column as decoded from the input.
+fn raw_field(field: &Field) -> Field {
+    if field.data_type().is_nested() {
+        field.clone().with_nullable(true)
+    } else {
+        Field::new(field.name(), DataType::Utf8, true)
+    }
+}
+
+/// The schema of the columns of the CSV header, which must be columns of the table.
+fn csv_raw_schema(
+    header_schema: &Schema,
+    table_schema: &Schema,
+) -> DataFusionResult<Schema> {
+    let fields = header_schema
+        .fields()
+        .iter()
+        .map(|header_field| {
+            let field =
+                table_schema
+                    .field_with_name(header_field.name())
+                    .map_err(|_| {
+                        DataFusionError::Plan(format!(
+                            "CSV column {} is not a column of the table",
+                            header_field.name()
+                        ))
+                    })?;
+            if field.data_type().is_nested() {
+                return Err(DataFusionError::NotImplemented(format!(
+                    "column {} of type {} can not be ingested from CSV",
+                    field.name(),
+                    field.data_type()
+                )));
+            }
+            Ok(raw_field(field))
+        })
+        .collect::<DataFusionResult<Vec<_>>>()?;
+    Ok(Schema::new(fields))
+}
+
+/// The coercion of the decoded batches to the table schema, rejecting the rows whose values
+/// can not be cast to the type of their column.
+struct Coercion {
+    table_schema: SchemaRef,
+    /// The index in the input of the first row of the next batch.
+    next_row: usize,
+    max_rejected_rows: usize,
+    report: Arc<Mutex<IngestReport>>,
+}
+
+impl Coercion {
+    fn coerce(&mut self, raw: RecordBatch) -> DataFusionResult<RecordBatch> {
+        let num_rows = raw.num_rows();
+        let mut rejected: Vec<Option<String>> = vec![None; num_rows];
+        let columns = self
+            .table_schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let Some(raw_column) = raw.column_by_name(field.name()) else {
+                    return Ok(new_null_array(field.data_type(), num_rows));
+                };
+                if raw_column.data_type() == field.data_type() {
+                    return Ok(raw_column.clone());
+                }
+                let options = CastOptions {
+                    safe: true,
+                    ..Default::default()
+                };
+                let column = cast_with_options(raw_column, field.data_type(), &options)?;
+                for (row, reason) in rejected.iter_mut().enumerate() {
+                    let invalid = raw_column.is_valid(row) && column.is_null(row);
+                    if reason.is_none() && invalid {
+                        *reason = Some(format!(
+                            "value {} of column {} is not a valid {}",
+                            raw_column.as_string::<i32>().value(row),
+                            field.name(),
+                            field.data_type()
+                        ));
+                    }
+                }
+                Ok(column)
+            })
+            .collect::<DataFusionResult<Vec<ArrayRef>>>()?;
+        for (field, column) in self.table_schema.fields().iter().zip(&columns) {
+            if field.is_nullable() || column.null_count() == 0 {
+                continue;
+            }
+            for (row, reason) in rejected.iter_mut().enumerate() {
+                if reason.is_none() && column.is_null(row) {
+                    *reason = Some(format!("column {} must not be null", field.name()));
+                }
+            }
+        }
+
+        let first_row = self.next_row;
+        self.next_row += num_rows;
+        let mut report = self.report.lock().unwrap();
+        report
+            .rejected_rows
+            .extend(rejected.iter().enumerate().filter_map(|(row, reason)| {
+                reason.as_ref().map(|reason| RejectedRow {
+                    row: first_row + row,
+                    reason: reason.clone(),
+                })
+            }));
+        if report.rejected_rows.len() > self.max_rejected_rows {
+            let first = &report.rejected_rows[0];
+            return Err(DataFusionError::Execution(format!(
+                "{} rows rejected, more than the tolerated {}, first rejected row {}: {}",
+                report.rejected_rows.len(),
+                self.max_rejected_rows,
+                first.row,
+                first.reason
+            )));
+        }
+
+        // the rows violating the non-nullable columns are only filtered out afterwards
+        let nullable_schema = Schema::new(
+            self.table_schema
+                .fields()
+                .iter()
+                .map(|field| field.as_ref().clone().with_nullable(true))
+                .collect::<Vec<_>>(),
+        );
+        let mut batch = RecordBatch::try_new(Arc::new(nullable_schema), columns)?;
+        if rejected.iter().any(Option::is_some) {
+            let accepted = rejected
+                .iter()
+                .map(|reason| Some(reason.is_none()))
+                .collect::<BooleanArray>();
+            batch = filter_record_batch(&batch, &accepted)?;
+        }
+        let batch =
+            RecordBatch::try_new(self.table_schema.clone(), batch.columns().to_vec())?;
+        report.num_rows += batch.num_rows() as u64;
+        Ok(batch)
+    }
+}
@@ -4,24 +4,44 @@
 
 //! The interface of LakeSoul table.
 
+pub mod commit_coalescer;
 pub mod helpers;
+pub mod ingest;
+pub mod vacuum;
+pub mod validate;
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::LakeSoulError;
-use crate::datasource::file_format::LakeSoulMetaDataParquetFormat;
-use crate::serialize::arrow_java::schema_from_metadata_str;
+use crate::datasource::delete_vector::{
+    delete_vector_data_file, delete_vector_path, is_delete_vector, read_delete_vector,
+    write_delete_vector,
+};
+use crate::datasource::file_format::{
+    CommitHook, LakeSoulCompactionExec, LakeSoulHashSinkExec,
+    LakeSoulMetaDataParquetFormat, LakeSoulStreamingSink, RegisteredCommitHook, ScanFile,
+};
+use crate::datasource::statistics::StoredFileStatistics;
+use crate::serialize::arrow_java::{ArrowJavaSchema, schema_from_metadata_str};
 use crate::{
     catalog::{
-        LakeSoulTableProperty, create_io_config_builder, parse_table_info_partitions,
+        LakeSoulTableProperty, commit_tombstones, create_io_config_builder,
+        parse_table_info_partitions,
     },
     error::Result,
     planner::query_planner::LakeSoulQueryPlanner,
 };
-use arrow::datatypes::SchemaRef;
+use arrow::array::AsArray;
+use arrow::datatypes::{DataType, Schema, SchemaRef, UInt64Type};
 use arrow_cast::pretty::pretty_format_batches;
+use bytes::Bytes;
 use chrono::Utc;
+use datafusion::datasource::file_format::FileFormat;
+use datafusion::datasource::file_format::parquet::ParquetFormat;
 use datafusion::datasource::provider_as_source;
+use datafusion::error::DataFusionError;
+use datafusion::execution::object_store::ObjectStoreUrl;
 use datafusion::logical_expr::dml::InsertOp;
 use datafusion::sql::TableReference;
 use datafusion::{
@@ -29,21 +49,40 @@ use datafusion::{
     dataframe::DataFrame,
     datasource::TableProvider,
     execution::context::{SessionContext, SessionState},
-    logical_expr::LogicalPlanBuilder,
+    logical_expr::{Expr, LogicalPlanBuilder},
+    physical_plan::{SendableRecordBatchStream, collect},
 };
-use helpers::case_fold_table_name;
+use futures::Stream;
+use helpers::{case_fold_table_name, is_widening_cast};
+use ingest::{IngestOptions, IngestReport};
 use lakesoul_io::async_writer::{
     AsyncBatchWriter, AsyncSendableMutableLakeSoulWriter, WriterFlushResult,
 };
+use lakesoul_io::helpers::resolve_file_url;
 use lakesoul_io::lakesoul_io_config::OPTION_KEY_MEM_LIMIT;
 use lakesoul_io::lakesoul_io_config::create_session_context_with_planner;
 use lakesoul_metadata::{LakeSoulMetaDataError, MetaDataClient, MetaDataClientRef};
-use proto::proto::entity::{CommitOp, DataCommitInfo, DataFileOp, FileOp, TableInfo};
-use std::collections::HashMap;
+use object_store::path::Path;
+use proto::proto::entity::{
+    CommitOp, DataCommitInfo, DataFileOp, FileOp, FileStatistics, TableInfo,
+};
+use std::collections::{HashMap, HashSet};
+use url::Url;
 use uuid::Uuid;
+use vacuum::{DEFAULT_MIN_VACUUM_RETENTION, VacuumReport};
+use validate::ValidationReport;
 
 use crate::datasource::table_provider::LakeSoulTableProvider;
 
+/// The rows of the partitions changed since a version, see
+/// [`LakeSoulTable::read_changed_partitions`].
+pub struct ChangedPartitions {
+    pub stream: SendableRecordBatchStream,
+    /// The versions of the partitions read so far by partition descriptor, to read the
+    /// partitions changed since from the next time.
+    pub versions: HashMap<String, i32>,
+}
+
 #[derive(Debug)]
 pub struct LakeSoulTable {
     client: MetaDataClientRef,
@@ -53,6 +92,10 @@ pub struct LakeSoulTable {
     primary_keys: Vec<String>,
     range_partitions: Vec<String>,
     properties: LakeSoulTableProperty,
+    /// The hook invoked after the commits into the table, see [`Self::with_commit_hook`].
+    commit_hook: Option<RegisteredCommitHook>,
+    /// The shortest retention of a vacuum, see [`Self::with_min_vacuum_retention`].
+    min_vacuum_retention: Duration,
 }
 
 impl LakeSoulTable {
@@ -131,9 +174,50 @@ impl LakeSoulTable {
             primary_keys: hash_partitions,
             range_partitions,
             properties,
+            commit_hook: None,
+            min_vacuum_retention: DEFAULT_MIN_VACUUM_RETENTION,
         })
     }
 
+    /// Invoke the hook for each committed partition after the commits through the table,
+    /// i.e. its inserts and upserts, compactions, tombstones, deletes and streaming sinks.
+    ///
+    /// Its errors are logged, and with `fail_on_error` they also fail the operation. The
+    /// files stay committed in any case.
+    pub fn with_commit_hook(
+        mut self,
+        commit_hook: Arc<dyn CommitHook>,
+        fail_on_error: bool,
+    ) -> Self {
+        self.commit_hook = Some(RegisteredCommitHook::new(commit_hook, fail_on_error));
+        self
+    }
+
+    /// Reject the vacuums of the table with a shorter retention, so that the files of the
+    /// versions still read by long running queries or time travel are not deleted.
+    ///
+    /// It defaults to [`DEFAULT_MIN_VACUUM_RETENTION`].
+    pub fn with_min_vacuum_retention(mut self, min_vacuum_retention: Duration) -> Self {
+        self.min_vacuum_retention = min_vacuum_retention;
+        self
+    }
+
+    /// Invoke the commit hook, if any, for the files committed to each partition.
+    pub(crate) async fn run_commit_hook(
+        &self,
+        committed: Vec<(String, Vec<String>)>,
+    ) -> Result<()> {
+        let table_ref =
+            TableReference::partial(self.table_namespace(), self.table_name());
+        LakeSoulHashSinkExec::run_commit_hook(
+            self.commit_hook.as_ref(),
+            &table_ref.to_string(),
+            committed,
+        )
+        .await?;
+        Ok(())
+    }
+
     pub async fn upsert_dataframe(&self, dataframe: DataFrame) -> Result<()> {
         let builder = create_io_config_builder(
             self.client.clone(),
@@ -265,22 +349,28 @@ impl LakeSoulTable {
         )
         .await?
         .with_prefix(self.table_info.table_path.clone());
-        Ok(Arc::new(
-            LakeSoulTableProvider::try_new(
-                session_state,
-                self.client(),
-                config_builder.build(),
-                self.table_info(),
-                true,
-            )
-            .await?,
-        ))
+        let provider = LakeSoulTableProvider::try_new(
+            session_state,
+            self.client(),
+            config_builder.build(),
+            self.table_info(),
+            true,
+        )
+        .await?;
+        Ok(Arc::new(match &self.commit_hook {
+            Some(commit_hook) => {
+                provider.with_registered_commit_hook(commit_hook.clone())
+            }
+            None => provider,
+        }))
     }
 
     pub async fn as_provider(&self) -> Result<Arc<dyn TableProvider>> {
         Ok(Arc::new(LakeSoulTableProvider {
-            listing_options: LakeSoulMetaDataParquetFormat::default_listing_options()
-                .await?,
+            listing_options: LakeSoulMetaDataParquetFormat::listing_options(
+                self.client(),
+            )
+            .await?,
             listing_table_paths: vec![],
             client: self.client(),
             table_info: self.table_info(),
@@ -288,9 +378,536 @@ impl LakeSoulTable {
             file_schema: self.schema(),
             primary_keys: self.primary_keys().to_vec(),
             range_partitions: self.range_partitions().to_vec(),
+            snapshot: None,
+            change_feed: None,
+            changed_since: None,
+            observed_versions: Default::default(),
+            commit_hook: self.commit_hook.clone(),
         }))
     }
 
+    /// Backfill the stored statistics of the data files which have no statistics yet.
+    ///
+    /// The statistics are computed from the parquet footers of the files. Files that already have
+    /// stored statistics are skipped, so an interrupted repair is resumed by running it again.
+    /// Returns the number of files whose statistics have been backfilled.
+    pub async fn repair_statistics(&self, context: &SessionContext) -> Result<usize> {
+        let table_id = &self.table_info.table_id;
+        let existing = self
+            .client
+            .get_file_statistics_by_table_id(table_id)
+            .await?
+            .into_iter()
+            .map(|file_statistics| file_statistics.file_path)
+            .collect::<HashSet<_>>();
+        let state = context.state();
+        let format = ParquetFormat::new().with_force_view_types(false);
+
+        let mut backfilled = 0;
+        for partition_info in self.client.get_all_partition_info(table_id).await? {
+            for file_path in self
+                .client
+                .get_data_files_of_single_partition(&partition_info)
+                .await?
+            {
+                if existing.contains(&file_path) || is_delete_vector(&file_path) {
+                    continue;
+                }
+                let url = Url::parse(&file_path)
+                    .map_err(|e| DataFusionError::External(Box::new(e)))?;
+                let store = state.runtime_env().object_store(ObjectStoreUrl::parse(
+                    &url[..url::Position::BeforePath],
+                )?)?;
+                let object_meta = store
+                    .head(
+                        &Path::from_url_path(url.path())
+                            .map_err(DataFusionError::from)?,
+                    )
+                    .await
+                    .map_err(DataFusionError::from)?;
+                let file_schema = format
+                    .infer_schema(&state, &store, std::slice::from_ref(&object_meta))
+                    .await?;
+                let statistics = format
+                    .infer_stats(&state, &store, file_schema.clone(), &object_meta)
+                    .await?;
+                let stored =
+                    StoredFileStatistics::from_statistics(&statistics, &file_schema);
+                self.client
+                    .insert_file_statistics(FileStatistics {
+                        table_id: table_id.clone(),
+                        file_path,
+                        partition_desc: partition_info.partition_desc.clone(),
+                        statistics: serde_json::to_string(&stored)?,
+                    })
+                    .await?;
+                backfilled += 1;
+            }
+        }
+        info!(
+            "repair_statistics of table {}: {} files backfilled, {} files already had statistics",
+            self.table_name,
+            backfilled,
+            existing.len()
+        );
+        Ok(backfilled)
+    }
+
+    /// Widen the types of the columns in the table schema, e.g. from Int32 to Int64, without
+    /// rewriting the data files, see [`is_widening_cast`].
+    ///
+    /// The files written before are cast up to the widened types when they are scanned. A
+    /// change that could lose values is rejected, as are changes of the primary key and range
+    /// partition columns, which would move the rows to other buckets and partitions.
+    ///
+    /// The types are widened upon the current schema of the table, which is only replaced if
+    /// no other change of the schema was committed meanwhile, so that none is lost.
+    ///
+    /// Returns the table with the widened schema.
+    pub async fn widen_column_types(
+        &self,
+        columns: &[(String, DataType)],
+    ) -> Result<Self> {
+        let table_info = loop {
+            let current = self
+                .client
+                .get_table_info_by_table_id(&self.table_info.table_id)
+                .await?
+                .ok_or_else(|| {
+                    LakeSoulMetaDataError::NotFound(format!(
+                        "table {} not found",
+                        self.table_name
+                    ))
+                })?;
+            let table_schema = self.widened_schema(
+                &schema_from_metadata_str(&current.table_schema),
+                columns,
+            )?;
+            let table_info = TableInfo {
+                table_schema: serde_json::to_string::<ArrowJavaSchema>(
+                    &table_schema.into(),
+                )?,
+                ..current.clone()
+            };
+            if self
+                .client
+                .compare_and_swap_table_schema(
+                    &table_info.table_id,
+                    &current.table_schema,
+                    &table_info.table_schema,
+                )
+                .await?
+            {
+                break table_info;
+            }
+            debug!(
+                "widen_column_types of table {}: the schema changed meanwhile, retrying",
+                self.table_name
+            );
+        };
+        info!(
+            "widen_column_types of table {}: {} columns widened",
+            self.table_name,
+            columns.len()
+        );
+        let table =
+            Self::try_new_with_client_and_table_info(self.client(), table_info).await?;
+        Ok(Self {
+            commit_hook: self.commit_hook.clone(),
+            min_vacuum_retention: self.min_vacuum_retention,
+            ..table
+        })
+    }
+
+    /// The table schema with the types of the columns widened, see
+    /// [`Self::widen_column_types`].
+    fn widened_schema(
+        &self,
+        table_schema: &Schema,
+        columns: &[(String, DataType)],
+    ) -> Result<SchemaRef> {
+        let mut fields = table_schema.fields().to_vec();
+        for (name, data_type) in columns {
+            let Some((idx, field)) = table_schema.column_with_name(name) else {
+                return Err(DataFusionError::Plan(format!(
+                    "column {} not found in table {}",
+                    name, self.table_name
+                ))
+                .into());
+            };
+            if self.primary_keys.contains(name) || self.range_partitions.contains(name) {
+                return Err(DataFusionError::Plan(format!(
+                    "can not change the type of the primary key or range partition column {} \
+                    of table {}",
+                    name, self.table_name
+                ))
+                .into());
+            }
+            if !is_widening_cast(field.data_type(), data_type) {
+                return Err(DataFusionError::Plan(format!(
+                    "can not change the type of column {} of table {} from {} to {}, \
+                    as it could lose values, rewrite the table instead",
+                    name,
+                    self.table_name,
+                    field.data_type(),
+                    data_type
+                ))
+                .into());
+            }
+            fields[idx] = Arc::new(field.clone().with_data_type(data_type.clone()));
+        }
+        Ok(Arc::new(Schema::new_with_metadata(
+            fields,
+            table_schema.metadata().clone(),
+        )))
+    }
+
+    /// Compact the data files of the partitions selected by the filter on the range
+    /// partition columns, all partitions if `None`, see [`LakeSoulCompactionExec`].
+    ///
+    /// Returns the number of compacted rows.
+    pub async fn compact(
+        &self,
+        context: &SessionContext,
+        partition_filter: Option<Expr>,
+    ) -> Result<u64> {
+        let mut exec = LakeSoulCompactionExec::new(
+            self.client(),
+            self.table_info(),
+            partition_filter,
+        );
+        if let Some(commit_hook) = &self.commit_hook {
+            exec = exec.with_registered_commit_hook(commit_hook.clone());
+        }
+        let exec = Arc::new(exec);
+        let batches = collect(exec, context.task_ctx()).await?;
+        let count = batches
+            .first()
+            .map(|batch| batch.column(0).as_primitive::<UInt64Type>().value(0))
+            .unwrap_or(0);
+        info!(
+            "compact table {}: {} rows compacted",
+            self.table_name, count
+        );
+        Ok(count)
+    }
+
+    /// Tombstone all data files of the partitions, so that they are no longer read without
+    /// rewriting or deleting them, see [`commit_tombstones`].
+    ///
+    /// Returns the number of tombstoned files.
+    pub async fn tombstone_partitions(
+        &self,
+        partition_descs: &[String],
+    ) -> Result<usize> {
+        let partition_infos = self
+            .client
+            .get_partition_info_by_table_id_and_partition_list(
+                &self.table_info.table_id,
+                partition_descs,
+            )
+            .await?;
+        let mut partitioned_paths = Vec::with_capacity(partition_infos.len());
+        for partition_info in partition_infos {
+            let paths = self
+                .client
+                .get_data_files_of_single_partition(&partition_info)
+                .await?;
+            if !paths.is_empty() {
+                partitioned_paths.push((partition_info.partition_desc, paths));
+            }
+        }
+        let count = partitioned_paths
+            .iter()
+            .map(|(_, paths)| paths.len())
+            .sum::<usize>();
+        let tombstoned = partitioned_paths
+            .iter()
+            .map(|(partition_desc, _)| (partition_desc.clone(), vec![]))
+            .collect();
+        commit_tombstones(self.client(), &self.table_info.table_id, partitioned_paths)
+            .await?;
+        self.run_commit_hook(tombstoned).await?;
+        info!(
+            "tombstone table {}: {} files tombstoned",
+            self.table_name, count
+        );
+        Ok(count)
+    }
+
+    /// Delete the files which are no longer read by the table and older than the retention
+    /// from the object store, see [`vacuum`].
+    ///
+    /// These are the files discarded by compactions, merges on write and tombstones, and the
+    /// orphan data files and delete vectors under the table path which are not referenced by
+    /// the metadata. The files of the versions readable within the retention are kept. With
+    /// `dry_run`, the files are only listed without deleting them.
+    ///
+    /// Fails if the retention is shorter than the minimum retention of the table, see
+    /// [`Self::with_min_vacuum_retention`].
+    pub async fn vacuum(
+        &self,
+        context: &SessionContext,
+        retention: Duration,
+        dry_run: bool,
+    ) -> Result<VacuumReport> {
+        vacuum::vacuum_table(self, context, retention, dry_run).await
+    }
+
+    /// Check that every data file of the current snapshots exists in the object store with
+    /// the size recorded in its commit, see [`validate`].
+    ///
+    /// The dangling files, e.g. the files of a crashed write which was committed but never
+    /// fully uploaded, are only reported. With `remove_dangling`, they are tombstoned as
+    /// well, see [`commit_tombstones`].
+    pub async fn validate_files(
+        &self,
+        context: &SessionContext,
+        remove_dangling: bool,
+    ) -> Result<ValidationReport> {
+        validate::validate_table(self, context, remove_dangling).await
+    }
+
+    /// List the URLs of the data files whose statistics do not exclude the filters,
+    /// without reading their rows, see
+    /// [`LakeSoulTableProvider::matching_files`].
+    ///
+    /// The files are matched by the rows they store, including the outdated versions of
+    /// the rows of a table with primary keys, e.g. to find the files to rewrite for
+    /// deleting the rows of a user.
+    pub async fn matching_files(
+        &self,
+        context: &SessionContext,
+        filters: Vec<Expr>,
+    ) -> Result<Vec<String>> {
+        let provider = self.read_provider(context).await?;
+        Ok(provider.matching_files(&context.state(), &filters).await?)
+    }
+
+    /// List the data files a scan with the filters would read after pruning, with their
+    /// partitions and estimated numbers of rows, without running the scan, see
+    /// [`LakeSoulTableProvider::scan_files`].
+    pub async fn scan_files(
+        &self,
+        context: &SessionContext,
+        filters: Vec<Expr>,
+    ) -> Result<Vec<ScanFile>> {
+        let provider = self.read_provider(context).await?;
+        Ok(provider.scan_files(&context.state(), &filters).await?)
+    }
+
+    async fn read_provider(
+        &self,
+        context: &SessionContext,
+    ) -> Result<LakeSoulTableProvider> {
+        let config_builder = create_io_config_builder(
+            self.client(),
+            Some(self.table_name()),
+            true,
+            self.table_namespace(),
+            HashMap::new(),
+            HashMap::new(),
+        )
+        .await?;
+        Ok(LakeSoulTableProvider::try_new(
+            &context.state(),
+            self.client(),
+            config_builder.build(),
+            self.table_info(),
+            false,
+        )
+        .await?)
+    }
+
+    /// Delete rows of a data file of the table by their index in the file, without
+    /// rewriting the file, see [`delete_vector`](crate::datasource::delete_vector).
+    ///
+    /// The rows are added to the rows already deleted from the file, and committed as a
+    /// new delete vector of the file replacing the previous ones. The commit fails if the
+    /// partition of the file was committed concurrently since it was read. The scans drop the
+    /// deleted rows of each file before the merge, so deleting the latest version of a
+    /// row of a table with primary keys reads its previous version instead. The delete
+    /// vectors are only applied by the scans of [`LakeSoulMetaDataParquetFormat`].
+    ///
+    /// Returns the number of rows deleted from the file in total.
+    pub async fn delete_rows(
+        &self,
+        context: &SessionContext,
+        file_path: &str,
+        rows: impl IntoIterator<Item = u64>,
+    ) -> Result<usize> {
+        let table_url = Url::parse(&self.table_info.table_path)
+            .map_err(|e| DataFusionError::External(Box::new(e)))?;
+        let runtime_env = context.runtime_env();
+        let mut data_file = None;
+        for partition_info in self
+            .client
+            .get_all_partition_info(&self.table_info.table_id)
+            .await?
+        {
+            let paths = self
+                .client
+                .get_data_files_of_single_partition(&partition_info)
+                .await?;
+            if paths.iter().any(|path| path == file_path) {
+                let previous = paths
+                    .into_iter()
+                    .filter(|path| delete_vector_data_file(path) == Some(file_path))
+                    .collect::<Vec<_>>();
+                data_file = Some((partition_info, previous));
+                break;
+            }
+        }
+        let Some((partition_info, previous)) = data_file else {
+            return Err(DataFusionError::Execution(format!(
+                "data file {} not found in table {}",
+                file_path, self.table_name
+            ))
+            .into());
+        };
+
+        let mut deleted_rows = rows.into_iter().collect::<Vec<_>>();
+        for previous in &previous {
+            let (object_store_url, location) = resolve_file_url(previous, &table_url)?;
+            let store = runtime_env.object_store(&object_store_url)?;
+            deleted_rows.extend(read_delete_vector(store.as_ref(), &location).await?);
+        }
+        deleted_rows.sort_unstable();
+        deleted_rows.dedup();
+
+        let path = delete_vector_path(file_path);
+        let (object_store_url, location) = resolve_file_url(&path, &table_url)?;
+        let store = runtime_env.object_store(&object_store_url)?;
+        let size = write_delete_vector(store.as_ref(), &location, &deleted_rows).await?;
+        let partition_desc = partition_info.partition_desc.clone();
+        let committed = vec![(partition_desc.clone(), vec![path.clone()])];
+        let mut file_ops = vec![DataFileOp {
+            path,
+            file_op: FileOp::Add.into(),
+            size: size as i64,
+            ..Default::default()
+        }];
+        // the previous delete vectors are replaced in the same commit
+        file_ops.extend(previous.into_iter().map(|path| DataFileOp {
+            path,
+            file_op: FileOp::Del.into(),
+            ..Default::default()
+        }));
+        // a concurrent delete of the partition would commit a delete vector missing the
+        // rows deleted here, so the partition must still be at the version read
+        self.client
+            .commit_data_commit_info_batch_with_read_partitions(
+                vec![DataCommitInfo {
+                    table_id: self.table_info.table_id.clone(),
+                    partition_desc,
+                    commit_id: {
+                        let (high, low) = Uuid::new_v4().as_u64_pair();
+                        Some(proto::proto::entity::Uuid { high, low })
+                    },
+                    file_ops,
+                    commit_op: CommitOp::AppendCommit.into(),
+                    timestamp: Utc::now().timestamp_millis(),
+                    committed: false,
+                    domain: self.table_info.domain.clone(),
+                }],
+                vec![partition_info],
+            )
+            .await?;
+        self.run_commit_hook(committed).await?;
+        info!(
+            "delete {} rows of {} of table {}",
+            deleted_rows.len(),
+            file_path,
+            self.table_name
+        );
+        Ok(deleted_rows.len())
+    }
+
+    /// Read the rows of the partitions with a commit after their version in
+    /// `since_versions`, with all the files of their latest version, see
+    /// [`LakeSoulTableProvider::with_changed_since`].
+    ///
+    /// The versions are counted per partition, the partitions missing from
+    /// `since_versions` are read entirely. The returned versions are `since_versions`
+    /// updated with the versions of the partitions read. Reading from the returned
+    /// versions again skips the partitions read, e.g. for an incremental job checkpointing
+    /// the versions it has processed.
+    pub async fn read_changed_partitions(
+        &self,
+        context: &SessionContext,
+        since_versions: HashMap<String, i32>,
+    ) -> Result<ChangedPartitions> {
+        let config_builder = create_io_config_builder(
+            self.client(),
+            Some(self.table_name()),
+            true,
+            self.table_namespace(),
+            HashMap::new(),
+            HashMap::new(),
+        )
+        .await?;
+        let provider = Arc::new(
+            LakeSoulTableProvider::try_new(
+                &context.state(),
+                self.client(),
+                config_builder.build(),
+                self.table_info(),
+                false,
+            )
+            .await?
+            .with_changed_since(since_versions.clone()),
+        );
+        // the partitions are listed when the physical plan is created
+        let stream = context
+            .read_table(provider.clone())?
+            .execute_stream()
+            .await?;
+        Ok(ChangedPartitions {
+            stream,
+            versions: provider.observed_versions().unwrap_or(since_versions),
+        })
+    }
+
+    /// Ingest newline delimited JSON or CSV input into the table, see [`ingest`].
+    ///
+    /// The values are validated against and cast to the table schema, the rows which do not
+    /// match it are rejected within the tolerance of the options and listed in the report.
+    /// The data is written as an upsert of the whole input in one commit.
+    pub async fn ingest<S>(
+        &self,
+        input: S,
+        options: IngestOptions,
+    ) -> Result<IngestReport>
+    where
+        S: Stream<Item = std::result::Result<Bytes, DataFusionError>>
+            + Send
+            + Unpin
+            + 'static,
+    {
+        ingest::ingest_table(self, input, options).await
+    }
+
+    /// Create a [`LakeSoulStreamingSink`] writing micro-batches into the table, which keeps
+    /// its files open across the batches and commits them periodically.
+    pub fn streaming_sink(
+        &self,
+        context: &SessionContext,
+    ) -> Result<LakeSoulStreamingSink> {
+        let sink = LakeSoulStreamingSink::try_new(
+            self.client(),
+            self.table_info(),
+            context.task_ctx(),
+        )?;
+        Ok(match &self.commit_hook {
+            Some(commit_hook) => sink.with_registered_commit_hook(commit_hook.clone()),
+            None => sink,
+        })
+    }
+
+    pub fn min_vacuum_retention(&self) -> Duration {
+        self.min_vacuum_retention
+    }
+
     pub fn table_name(&self) -> &str {
         &self.table_name
     }
@@ -364,8 +981,17 @@ impl LakeSoulTable {
         debug!("Committing DataCommitInfo={:?}", data_commit_info_list);
         for commit_info in data_commit_info_list {
             let commit_id = commit_info.commit_id;
+            let committed = vec![(
+                commit_info.partition_desc.clone(),
+                commit_info
+                    .file_ops
+                    .iter()
+                    .map(|file_op| file_op.path.clone())
+                    .collect(),
+            )];
             self.client.commit_data_commit_info(commit_info).await?;
             debug!("Commit done for commit_id={:?}", commit_id);
+            self.run_commit_hook(committed).await?;
         }
 
         Ok(())
@@ -0,0 +1,213 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Vacuum of the data files which are no longer read by a LakeSoul table.
+//!
+//! The files superseded by a compaction or a merge on write and the tombstoned files are
+//! recorded as discarded in the metadata when they are committed away. A vacuum deletes
+//! them from the object store once they are discarded longer than its retention, together
+//! with the orphan files under the table path which are not referenced by the metadata at
+//! all, e.g. the files of a failed write.
+//!
+//! The files of the partition versions still readable within the retention, by version or
+//! by timestamp, are never deleted. The retention can not be shorter than the minimum
+//! retention of the table, see [`LakeSoulTable::with_min_vacuum_retention`].
+
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+
+use datafusion::datasource::listing::ListingTableUrl;
+use datafusion::error::DataFusionError;
+use datafusion::execution::context::SessionContext;
+use futures::TryStreamExt;
+use lakesoul_io::datasource::physical_plan::is_orc_file;
+use lakesoul_io::helpers::resolve_file_url;
+use lakesoul_io::lakesoul_io_config::DataFileFormat;
+use object_store::ObjectMeta;
+use url::Url;
+
+use super::LakeSoulTable;
+use crate::datasource::delete_vector::is_delete_vector;
+use crate::error::Result;
+
+/// The default minimum retention of a vacuum, see
+/// [`LakeSoulTable::with_min_vacuum_retention`].
+pub const DEFAULT_MIN_VACUUM_RETENTION: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// The files deleted by a vacuum, or to be deleted by a dry run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VacuumReport {
+    /// The discarded files older than the retention.
+    pub obsolete_files: Vec<String>,
+    /// The data files under the table path older than the retention which are not
+    /// referenced by the metadata.
+    pub orphan_files: Vec<String>,
+    /// The delete vectors under the table path older than the retention which are not
+    /// referenced by the metadata.
+    pub orphan_delete_vectors: Vec<String>,
+    /// Whether the files were only listed and not deleted.
+    pub dry_run: bool,
+}
+
+/// Delete the obsolete and orphan files of the table older than the retention, see
+/// [`LakeSoulTable::vacuum`].
+pub(crate) async fn vacuum_table(
+    table: &LakeSoulTable,
+    context: &SessionContext,
+    retention: Duration,
+    dry_run: bool,
+) -> Result<VacuumReport> {
+    if retention < table.min_vacuum_retention() {
+        return Err(DataFusionError::Configuration(format!(
+            "vacuum retention {:?} of table {} is shorter than its minimum retention {:?}",
+            retention,
+            table.table_name(),
+            table.min_vacuum_retention()
+        ))
+        .into());
+    }
+    let client = table.client();
+    let table_info = table.table_info();
+    let runtime_env = context.runtime_env();
+    let table_url = Url::parse(&table_info.table_path)
+        .map_err(|e| DataFusionError::External(Box::new(e)))?;
+    let file_key = |path: &str| -> Result<String> {
+        let (object_store_url, location) = resolve_file_url(path, &table_url)?;
+        Ok(format!("{}{}", object_store_url.as_str(), location))
+    };
+    let cutoff = SystemTime::now()
+        .checked_sub(retention)
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_millis() as i64;
+
+    // the files of the versions readable within the retention, i.e. the version current at
+    // the cutoff and the later ones, are never deleted, even if recorded as discarded
+    let partition_infos = client.get_all_partition_info(&table_info.table_id).await?;
+    let cutoff_partition_infos = client
+        .get_all_partition_info_as_of_timestamp(&table_info.table_id, cutoff)
+        .await?;
+    let mut retained_files = HashSet::new();
+    for partition_info in &partition_infos {
+        let cutoff_version = cutoff_partition_infos
+            .iter()
+            .find(|cutoff_info| {
+                cutoff_info.partition_desc == partition_info.partition_desc
+            })
+            .map_or(0, |cutoff_info| cutoff_info.version);
+        for version_info in client
+            .get_partition_versions_in_range(
+                &table_info.table_id,
+                &partition_info.partition_desc,
+                cutoff_version,
+                partition_info.version,
+            )
+            .await?
+        {
+            for path in client
+                .get_data_files_of_single_partition(&version_info)
+                .await?
+            {
+                retained_files.insert(file_key(&path)?);
+            }
+        }
+    }
+
+    let mut report = VacuumReport {
+        dry_run,
+        ..Default::default()
+    };
+    let mut discarded_files = HashSet::new();
+    for partition_info in &partition_infos {
+        for discarded in client
+            .get_discard_compressed_file_info_before(
+                &table_info.table_path,
+                &partition_info.partition_desc,
+                i64::MAX,
+            )
+            .await?
+        {
+            let key = file_key(&discarded.file_path)?;
+            discarded_files.insert(key.clone());
+            if discarded.timestamp >= cutoff || retained_files.contains(&key) {
+                continue;
+            }
+            if !dry_run {
+                let (object_store_url, location) =
+                    resolve_file_url(&discarded.file_path, &table_url)?;
+                let store = runtime_env.object_store(&object_store_url)?;
+                match store.delete(&location).await {
+                    Ok(()) | Err(object_store::Error::NotFound { .. }) => {}
+                    Err(e) => return Err(DataFusionError::ObjectStore(e).into()),
+                }
+                let file_path = &discarded.file_path;
+                client
+                    .delete_file_statistics_by_file_path(&table_info.table_id, file_path)
+                    .await?;
+                client
+                    .delete_discard_compressed_file_info_by_file_path(file_path)
+                    .await?;
+            }
+            report.obsolete_files.push(discarded.file_path);
+        }
+    }
+
+    // files of in-progress writes are not committed yet, the retention keeps them as well
+    let listing_url = ListingTableUrl::parse(&table_info.table_path)?;
+    let object_store_url = listing_url.object_store();
+    let store = runtime_env.object_store(&object_store_url)?;
+    let mut objects = store.list(Some(listing_url.prefix()));
+    let mut orphan_locations = Vec::new();
+    while let Some(object_meta) = objects
+        .try_next()
+        .await
+        .map_err(DataFusionError::ObjectStore)?
+    {
+        let key = format!("{}{}", object_store_url.as_str(), object_meta.location);
+        let orphans = if is_data_file(&object_meta) {
+            &mut report.orphan_files
+        } else if is_delete_vector(object_meta.location.as_ref()) {
+            &mut report.orphan_delete_vectors
+        } else {
+            continue;
+        };
+        if object_meta.last_modified.timestamp_millis() < cutoff
+            && !retained_files.contains(&key)
+            && !discarded_files.contains(&key)
+        {
+            orphan_locations.push(object_meta.location);
+            orphans.push(key);
+        }
+    }
+    if !dry_run {
+        for location in orphan_locations {
+            match store.delete(&location).await {
+                Ok(()) | Err(object_store::Error::NotFound { .. }) => {}
+                Err(e) => return Err(DataFusionError::ObjectStore(e).into()),
+            }
+        }
+    }
+
+    info!(
+        "vacuum table {}: {} obsolete files, {} orphan files, {} orphan delete vectors, \
+        dry run {}",
+        table.table_name(),
+        report.obsolete_files.len(),
+        report.orphan_files.len(),
+        report.orphan_delete_vectors.len(),
+        dry_run
+    );
+    Ok(report)
+}
+
+/// Whether the object is a data file of a LakeSoul table, judged by its file extension.
+///
+/// The delete vectors are not data files, see [`is_delete_vector`].
+fn is_data_file(object_meta: &ObjectMeta) -> bool {
+    !is_delete_vector(object_meta.location.as_ref())
+        && ([DataFileFormat::Parquet, DataFileFormat::ArrowIpc]
+            .iter()
+            .any(|format| object_meta.location.extension() == Some(format.extension()))
+            || is_orc_file(object_meta))
+}
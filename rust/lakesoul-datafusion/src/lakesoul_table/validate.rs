@@ -0,0 +1,121 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Validation of the data files committed to the metadata of a LakeSoul table.
+//!
+//! A crash between a commit and the end of the upload of its files, e.g. a writer which
+//! was never flushed and closed, leaves commits referencing files which are missing from
+//! the object store or truncated. Such dangling files fail every read of their
+//! partitions. The validation heads each file of the current snapshots and compares it
+//! with its recorded size, and optionally tombstones the dangling files, so that the
+//! partitions are readable again without their rows.
+
+use std::collections::BTreeMap;
+
+use datafusion::error::DataFusionError;
+use datafusion::execution::context::SessionContext;
+use lakesoul_io::helpers::resolve_file_url;
+use url::Url;
+
+use super::LakeSoulTable;
+use crate::catalog::commit_tombstones;
+use crate::error::Result;
+
+/// A committed data file which is missing from the object store or not of its recorded
+/// size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DanglingFile {
+    /// The path of the file as committed.
+    pub file_path: String,
+    pub partition_desc: String,
+    /// The size of the file recorded in its commit, 0 if it was not recorded.
+    pub recorded_size: i64,
+    /// The size of the file in the object store, `None` if the file is missing.
+    pub actual_size: Option<u64>,
+}
+
+/// The result of a validation of the data files of a table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// The number of data files of the current snapshots which were checked.
+    pub checked_files: usize,
+    pub dangling_files: Vec<DanglingFile>,
+    /// Whether the dangling files were tombstoned.
+    pub removed: bool,
+}
+
+/// Check the data files of the current snapshots of the table against the object store,
+/// see [`LakeSoulTable::validate_files`].
+pub(crate) async fn validate_table(
+    table: &LakeSoulTable,
+    context: &SessionContext,
+    remove_dangling: bool,
+) -> Result<ValidationReport> {
+    let client = table.client();
+    let table_info = table.table_info();
+    let runtime_env = context.runtime_env();
+    let table_url = Url::parse(&table_info.table_path)
+        .map_err(|e| DataFusionError::External(Box::new(e)))?;
+
+    let mut report = ValidationReport::default();
+    for partition_info in client.get_all_partition_info(&table_info.table_id).await? {
+        for file_op in client
+            .get_data_file_ops_of_single_partition(&partition_info)
+            .await?
+        {
+            report.checked_files += 1;
+            let (object_store_url, location) =
+                resolve_file_url(&file_op.path, &table_url)?;
+            let store = runtime_env.object_store(&object_store_url)?;
+            let actual_size = match store.head(&location).await {
+                Ok(object_meta) => Some(object_meta.size),
+                Err(object_store::Error::NotFound { .. }) => None,
+                Err(e) => return Err(DataFusionError::ObjectStore(e).into()),
+            };
+            // a size of 0 is not recorded, then only the existence is checked
+            let dangling = match actual_size {
+                Some(size) => file_op.size > 0 && size != file_op.size as u64,
+                None => true,
+            };
+            if dangling {
+                report.dangling_files.push(DanglingFile {
+                    file_path: file_op.path,
+                    partition_desc: partition_info.partition_desc.clone(),
+                    recorded_size: file_op.size,
+                    actual_size,
+                });
+            }
+        }
+    }
+
+    if remove_dangling && !report.dangling_files.is_empty() {
+        let mut partitioned_paths = BTreeMap::<String, Vec<String>>::new();
+        for file in &report.dangling_files {
+            partitioned_paths
+                .entry(file.partition_desc.clone())
+                .or_default()
+                .push(file.file_path.clone());
+        }
+        let tombstoned = partitioned_paths
+            .keys()
+            .map(|partition_desc| (partition_desc.clone(), vec![]))
+            .collect();
+        commit_tombstones(
+            client,
+            &table_info.table_id,
+            partitioned_paths.into_iter().collect(),
+        )
+        .await?;
+        table.run_commit_hook(tombstoned).await?;
+    }
+    report.removed = remove_dangling;
+    info!(
+        "validate table {}: {} files checked, {} dangling files, removed {}",
+        table.table_name(),
+        report.checked_files,
+        report.dangling_files.len(),
+        remove_dangling
+    );
+    Ok(report)
+}
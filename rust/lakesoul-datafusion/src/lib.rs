@@ -23,6 +23,7 @@ use datafusion::{
     },
     prelude::{SessionConfig, SessionContext},
 };
+use datasource::delta::DeltaTableProviderFactory;
 use datasource::table_factory::LakeSoulTableProviderFactory;
 pub use error::{LakeSoulError, Result};
 
@@ -33,6 +34,7 @@ use lakesoul_io::lakesoul_io_config::{
 };
 // use lakesoul_metadata::MetaDataClientRef;
 use object_store::local::LocalFileSystem;
+pub use planner::lakesoul_physical_optimizer_rules;
 pub use planner::query_planner::LakeSoulQueryPlanner;
 use url::Url;
 
@@ -76,6 +78,7 @@ pub fn create_lakesoul_session_ctx(
         .with_runtime_env(Arc::new(RuntimeEnv::default()))
         .with_default_features()
         .with_query_planner(planner)
+        .with_physical_optimizer_rules(lakesoul_physical_optimizer_rules())
         .build();
     state.table_factories_mut().insert(
         "LAKESOUL".to_string(),
@@ -84,6 +87,9 @@ pub fn create_lakesoul_session_ctx(
             args.warehouse_prefix.clone(),
         )),
     );
+    state
+        .table_factories_mut()
+        .insert("DELTA".to_string(), Arc::new(DeltaTableProviderFactory));
     let ctx = Arc::new(SessionContext::new_with_state(state));
 
     let catalog = Arc::new(LakeSoulCatalog::new(meta_client.clone(), ctx.clone()));
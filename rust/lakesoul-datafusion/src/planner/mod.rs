@@ -4,5 +4,20 @@
 
 //! The [`datafusion::physical_plan`] and [`datafusion::logical_expr`] implementation for LakeSoul table.
 
+use std::sync::Arc;
+
+use datafusion::physical_optimizer::PhysicalOptimizerRule;
+use datafusion::physical_optimizer::optimizer::PhysicalOptimizer;
+use lakesoul_io::datasource::physical_plan::BucketedJoinRule;
+
 mod physical_planner;
 pub mod query_planner;
+
+/// The physical optimizer rules of DataFusion, preceded by the ones of LakeSoul which must
+/// see the plan before its distribution is enforced, see [`BucketedJoinRule`].
+pub fn lakesoul_physical_optimizer_rules()
+-> Vec<Arc<dyn PhysicalOptimizerRule + Send + Sync>> {
+    let mut rules = PhysicalOptimizer::new().rules;
+    rules.insert(0, Arc::new(BucketedJoinRule::new()));
+    rules
+}
@@ -3,178 +3,746 @@
 // SPDX-License-Identifier: Apache-2.0
 
 mod compaction_tests {
+    use std::collections::HashSet;
     use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
 
-    use arrow::array::StringArray;
-    use lakesoul_io::lakesoul_io_config::LakeSoulIOConfigBuilder;
-    
-    use arrow::record_batch::RecordBatch;
     use arrow::array::{ArrayRef, Int32Array};
-    use lakesoul_metadata::MetaDataClient;
-
-    use crate::catalog::compaction_with_merge_op;
-    use crate::catalog::{create_table, compaction};
+    use arrow::record_batch::RecordBatch;
+    use datafusion::datasource::memory::MemorySourceConfig;
+    use datafusion::error::DataFusionError;
+    use datafusion::physical_plan::collect;
+    use datafusion::prelude::{SessionContext, col, lit};
+    use lakesoul_io::helpers::extract_hash_bucket_id;
+    use lakesoul_io::lakesoul_io_config::{
+        LakeSoulIOConfigBuilder, OPTION_KEY_DATA_FILE_FORMAT, OPTION_KEY_HASH_BUCKET_NUM,
+        OPTION_KEY_PARQUET_COMPRESSION, create_session_context,
+    };
+    use lakesoul_metadata::{MetaDataClient, MetaDataClientRef};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use parquet::basic::Compression;
+    use url::Url;
+
+    use crate::catalog::{create_io_config_builder, create_table};
+    use crate::datasource::file_format::LakeSoulHashSinkExec;
+    use crate::datasource::statistics::StoredFileStatistics;
     use crate::error::Result;
-
-    async fn check_record_batch() -> Result<()> {
-        Ok(())
+    use crate::lakesoul_table::LakeSoulTable;
+    use crate::lakesoul_table::vacuum::DEFAULT_MIN_VACUUM_RETENTION;
+    use crate::test::assert_batches_eq;
+
+    fn create_batch(range: &[i32], hash: &[i32], value: &[i32]) -> Result<RecordBatch> {
+        Ok(RecordBatch::try_from_iter([
+            (
+                "range",
+                Arc::new(Int32Array::from(range.to_vec())) as ArrayRef,
+            ),
+            (
+                "hash",
+                Arc::new(Int32Array::from(hash.to_vec())) as ArrayRef,
+            ),
+            (
+                "value",
+                Arc::new(Int32Array::from(value.to_vec())) as ArrayRef,
+            ),
+        ])?)
     }
 
-    async fn check_data_info() -> Result<()> {
-        Ok(())
+    async fn init_table(table_name: &str, client: MetaDataClientRef) -> Result<()> {
+        let batch = create_batch(&[1, 1, 2], &[1, 2, 1], &[1, 2, 3])?;
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_schema(batch.schema())
+            .with_primary_keys(vec!["hash".to_string()])
+            .with_range_partitions(vec!["range".to_string()]);
+        create_table(client, table_name, builder.build()).await?;
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        lakesoul_table.execute_upsert(batch).await?;
+        lakesoul_table
+            .execute_upsert(create_batch(&[1, 1, 2], &[1, 3, 1], &[11, 33, 33])?)
+            .await
     }
 
-    async fn test_simple_compaction() -> Result<()> {
-        let client = Arc::new(MetaDataClient::from_env().await?);
-        let table_name = "test_simple_compaction";
-
-        let _record_batch_1 = RecordBatch::try_from_iter_with_nullable(
-            vec![
-                ("range", Arc::new(Int32Array::from(vec![1, 2, 3, 1, 1])) as ArrayRef, false),
-                ("hash", Arc::new(Int32Array::from(vec![1, 1, 1, 2, 3])) as ArrayRef, false),
-                ("value", Arc::new(Int32Array::from(vec![1, 1, 1, 2, 3])) as ArrayRef, true),
-            ]
-        )?;
-
-        create_table(client.clone(), table_name, LakeSoulIOConfigBuilder::default().build()).await?;
-
-
-        check_data_info().await?;
-
-        let _record_batch_2 = RecordBatch::try_from_iter_with_nullable(
-            vec![
-                ("range", Arc::new(Int32Array::from(vec![1, 2, 3, 1, 1])) as ArrayRef, false),
-                ("hash", Arc::new(Int32Array::from(vec![1, 1, 1, 2, 3])) as ArrayRef, false),
-                ("value", Arc::new(Int32Array::from(vec![1, 1, 1, 2, 3])) as ArrayRef, true),
-            ]
-        )?;
+    /// Returns the data files of each partition of the table.
+    async fn list_files(
+        lakesoul_table: &LakeSoulTable,
+        client: MetaDataClientRef,
+    ) -> Result<Vec<(String, Vec<String>)>> {
+        let mut files = vec![];
+        for partition_info in client
+            .get_all_partition_info(&lakesoul_table.table_info().table_id)
+            .await?
+        {
+            let paths = client
+                .get_data_files_of_single_partition(&partition_info)
+                .await?;
+            files.push((partition_info.partition_desc, paths));
+        }
+        files.sort();
+        Ok(files)
+    }
 
+    /// Returns whether each hash bucket of the partition has a single data file.
+    fn is_compacted(paths: &[String]) -> bool {
+        let buckets = paths
+            .iter()
+            .map(|path| extract_hash_bucket_id(path))
+            .collect::<HashSet<_>>();
+        buckets.len() == paths.len()
+    }
 
-        check_data_info().await?;
+    /// Returns the data files of the hash buckets with a single file, which are not
+    /// rewritten by the compactions.
+    fn single_file_buckets(files: &[(String, Vec<String>)]) -> HashSet<String> {
+        let mut singles = HashSet::new();
+        for (_, paths) in files {
+            for path in paths {
+                let bucket = extract_hash_bucket_id(path);
+                if paths
+                    .iter()
+                    .filter(|other| extract_hash_bucket_id(other) == bucket)
+                    .count()
+                    == 1
+                {
+                    singles.insert(path.clone());
+                }
+            }
+        }
+        singles
+    }
 
-        compaction(client.clone(), table_name, "").await?;
+    /// Returns the data files listed before a compaction which are no longer listed after.
+    fn superseded_files(
+        before: Vec<(String, Vec<String>)>,
+        after: &[(String, Vec<String>)],
+    ) -> HashSet<String> {
+        let after = after
+            .iter()
+            .flat_map(|(_, paths)| paths)
+            .collect::<HashSet<_>>();
+        before
+            .into_iter()
+            .flat_map(|(_, paths)| paths)
+            .filter(|path| !after.contains(path))
+            .collect()
+    }
 
-        check_data_info().await?;
+    async fn create_context(client: MetaDataClientRef) -> Result<SessionContext> {
+        let builder = create_io_config_builder(
+            client,
+            None,
+            false,
+            "default",
+            Default::default(),
+            Default::default(),
+        )
+        .await?;
+        Ok(create_session_context(&mut builder.build())?)
+    }
 
+    async fn check_table(
+        lakesoul_table: &LakeSoulTable,
+        sess_ctx: &SessionContext,
+        expected: &[&str],
+    ) -> Result<()> {
+        let result = lakesoul_table
+            .to_dataframe(sess_ctx)
+            .await?
+            .select_columns(&["range", "hash", "value"])?
+            .collect()
+            .await?;
+        assert_batches_eq(lakesoul_table.table_name(), expected, &result);
         Ok(())
     }
 
-    async fn test_simple_compaction_with_condition() -> Result<()> {
+    async fn test_compaction() -> Result<()> {
+        let table_name = "test_compaction";
         let client = Arc::new(MetaDataClient::from_env().await?);
-        let table_name = "test_simple_compaction_with_condition";
-
-        let _record_batch_1 = RecordBatch::try_from_iter_with_nullable(
-            vec![
-                ("range", Arc::new(Int32Array::from(vec![1, 1, 1, 1])) as ArrayRef, false),
-                ("hash", Arc::new(Int32Array::from(vec![1, 2, 3, 4])) as ArrayRef, false),
-                ("value", Arc::new(Int32Array::from(vec![1, 2, 3, 4])) as ArrayRef, true),
-            ]
-        )?;
-
-        let _record_batch_2 = RecordBatch::try_from_iter_with_nullable(
-            vec![
-                ("range", Arc::new(Int32Array::from(vec![1, 1, 1])) as ArrayRef, false),
-                ("hash", Arc::new(Int32Array::from(vec![1, 2, 3])) as ArrayRef, false),
-                ("name", Arc::new(Int32Array::from(vec![11, 22, 33])) as ArrayRef, true),
-            ]
-        )?;
-
-        create_table(client.clone(), table_name, LakeSoulIOConfigBuilder::default().build()).await?;
-
-
-        check_data_info().await?;
-
-        compaction(client.clone(), table_name, "range=1").await?;
-
-        check_data_info().await?;
-
-        check_data_info().await?;
-        
+        init_table(table_name, client.clone()).await?;
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        let sess_ctx = create_context(client.clone()).await?;
+        let expected = [
+            "+-------+------+-------+",
+            "| range | hash | value |",
+            "+-------+------+-------+",
+            "| 1     | 1    | 11    |",
+            "| 1     | 2    | 2     |",
+            "| 1     | 3    | 33    |",
+            "| 2     | 1    | 33    |",
+            "+-------+------+-------+",
+        ];
+        check_table(&lakesoul_table, &sess_ctx, &expected).await?;
+        let retained =
+            single_file_buckets(&list_files(&lakesoul_table, client.clone()).await?);
+
+        // the rows of hash 1 are written twice in each partition
+        let count = lakesoul_table.compact(&sess_ctx, None).await?;
+        assert!((2..=4).contains(&count), "{}", count);
+        let files = list_files(&lakesoul_table, client.clone()).await?;
+        assert!(files.iter().all(|(_, paths)| is_compacted(paths)));
+        // the buckets with a single file are not rewritten
+        let paths = files
+            .iter()
+            .flat_map(|(_, paths)| paths.iter().cloned())
+            .collect::<HashSet<_>>();
+        assert!(retained.is_subset(&paths));
+        check_table(&lakesoul_table, &sess_ctx, &expected).await?;
+
+        // a compacted table has nothing left to compact
+        assert_eq!(lakesoul_table.compact(&sess_ctx, None).await?, 0);
+        assert_eq!(list_files(&lakesoul_table, client).await?, files);
         Ok(())
     }
 
-    async fn test_upsert_after_compaction() -> Result<()> {
+    async fn test_compaction_with_partition_filter() -> Result<()> {
+        let table_name = "test_compaction_with_partition_filter";
         let client = Arc::new(MetaDataClient::from_env().await?);
-        let table_name = "test_upsert_after_compaction";
-
-        let _record_batch_1 = RecordBatch::try_from_iter_with_nullable(
-            vec![
-                ("range", Arc::new(Int32Array::from(vec![1, 1, 1, 1])) as ArrayRef, false),
-                ("hash", Arc::new(Int32Array::from(vec![1, 2, 3, 4])) as ArrayRef, false),
-                ("value", Arc::new(Int32Array::from(vec![1, 2, 3, 4])) as ArrayRef, true),
-            ]
-        )?;
-
-        let _record_batch_2 = RecordBatch::try_from_iter_with_nullable(
-            vec![
-                ("range", Arc::new(Int32Array::from(vec![1, 1, 1])) as ArrayRef, false),
-                ("hash", Arc::new(Int32Array::from(vec![1, 2, 3])) as ArrayRef, false),
-                ("value", Arc::new(Int32Array::from(vec![11, 22, 33])) as ArrayRef, true),
-            ]
-        )?;
-
-        let _record_batch_3 = RecordBatch::try_from_iter_with_nullable(
-            vec![
-                ("range", Arc::new(Int32Array::from(vec![1, 1, 1, 1])) as ArrayRef, false),
-                ("hash", Arc::new(Int32Array::from(vec![2, 3, 4, 5])) as ArrayRef, false),
-                ("value", Arc::new(Int32Array::from(vec![222, 333, 444, 555])) as ArrayRef, true),
-            ]
-        )?;
+        init_table(table_name, client.clone()).await?;
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        let sess_ctx = create_context(client.clone()).await?;
+        let files = list_files(&lakesoul_table, client.clone()).await?;
+        assert!(!is_compacted(&files[1].1));
+
+        let count = lakesoul_table
+            .compact(&sess_ctx, Some(col("range").eq(lit(2))))
+            .await?;
+        assert_eq!(count, 1);
+        let compacted_files = list_files(&lakesoul_table, client).await?;
+        // only the files of the filtered partition are compacted
+        assert_eq!(compacted_files[0], files[0]);
+        assert_eq!(compacted_files[1].1.len(), 1);
+        check_table(
+            &lakesoul_table,
+            &sess_ctx,
+            &[
+                "+-------+------+-------+",
+                "| range | hash | value |",
+                "+-------+------+-------+",
+                "| 1     | 1    | 11    |",
+                "| 1     | 2    | 2     |",
+                "| 1     | 3    | 33    |",
+                "| 2     | 1    | 33    |",
+                "+-------+------+-------+",
+            ],
+        )
+        .await
+    }
 
-        create_table(client.clone(), table_name, LakeSoulIOConfigBuilder::default().build()).await?;
-        
-        compaction(client.clone(), table_name, "range=1").await?;
+    async fn test_compaction_with_table_compression() -> Result<()> {
+        let table_name = "test_compaction_with_table_compression";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_schema(create_batch(&[], &[], &[])?.schema())
+            .with_primary_keys(vec!["hash".to_string()])
+            .with_range_partitions(vec!["range".to_string()])
+            .with_option(OPTION_KEY_HASH_BUCKET_NUM, "1")
+            .with_parquet_compression(Compression::SNAPPY);
+        create_table(client.clone(), table_name, builder.build()).await?;
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+
+        // the files are written with codecs other than the one of the table
+        for (compression, batch) in [
+            ("GZIP", create_batch(&[1, 1], &[1, 2], &[1, 2])?),
+            ("ZSTD(3)", create_batch(&[1, 1], &[2, 3], &[22, 33])?),
+        ] {
+            let schema = batch.schema();
+            let input = MemorySourceConfig::try_new_exec(&[vec![batch]], schema, None)?;
+            let sink = LakeSoulHashSinkExec::new(
+                input,
+                None,
+                lakesoul_table.table_info(),
+                client.clone(),
+            )
+            .await?
+            .with_write_option(OPTION_KEY_PARQUET_COMPRESSION, compression);
+            collect(Arc::new(sink), SessionContext::new().task_ctx()).await?;
+        }
+
+        // the compaction keeps the codec of the table whatever the codec of the session
+        let builder = create_io_config_builder(
+            client.clone(),
+            None,
+            false,
+            "default",
+            Default::default(),
+            Default::default(),
+        )
+        .await?
+        .with_parquet_compression(Compression::LZ4_RAW);
+        let sess_ctx = create_session_context(&mut builder.build())?;
+        assert_eq!(lakesoul_table.compact(&sess_ctx, None).await?, 3);
+        let compressions = client
+            .get_file_statistics_by_table_id(&lakesoul_table.table_info().table_id)
+            .await?
+            .iter()
+            .filter(|stored| stored.file_path.contains("compacted"))
+            .map(|stored| {
+                let stored =
+                    serde_json::from_str::<StoredFileStatistics>(&stored.statistics)?;
+                Ok(stored.compression)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        assert!(!compressions.is_empty());
+        assert!(
+            compressions
+                .iter()
+                .all(|compression| compression.as_deref() == Some("SNAPPY"))
+        );
+        check_table(
+            &lakesoul_table,
+            &sess_ctx,
+            &[
+                "+-------+------+-------+",
+                "| range | hash | value |",
+                "+-------+------+-------+",
+                "| 1     | 1    | 1     |",
+                "| 1     | 2    | 22    |",
+                "| 1     | 3    | 33    |",
+                "+-------+------+-------+",
+            ],
+        )
+        .await
+    }
 
-        check_record_batch().await?;
+    async fn test_compaction_of_arrow_ipc_files() -> Result<()> {
+        let table_name = "test_compaction_of_arrow_ipc_files";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_schema(create_batch(&[], &[], &[])?.schema())
+            .with_primary_keys(vec!["hash".to_string()])
+            .with_range_partitions(vec!["range".to_string()])
+            .with_option(OPTION_KEY_HASH_BUCKET_NUM, "1");
+        create_table(client.clone(), table_name, builder.build()).await?;
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+
+        // the files are written as arrow ipc and parquet
+        for (format, batch) in [
+            ("arrow", create_batch(&[1, 1], &[1, 2], &[1, 2])?),
+            ("parquet", create_batch(&[1, 1], &[2, 3], &[22, 33])?),
+        ] {
+            let schema = batch.schema();
+            let input = MemorySourceConfig::try_new_exec(&[vec![batch]], schema, None)?;
+            let sink = LakeSoulHashSinkExec::new(
+                input,
+                None,
+                lakesoul_table.table_info(),
+                client.clone(),
+            )
+            .await?
+            .with_write_option(OPTION_KEY_DATA_FILE_FORMAT, format);
+            collect(Arc::new(sink), SessionContext::new().task_ctx()).await?;
+        }
+        let files = list_files(&lakesoul_table, client.clone()).await?;
+        assert!(files[0].1.iter().any(|path| path.ends_with(".arrow")));
+
+        let sess_ctx = create_context(client.clone()).await?;
+        assert_eq!(lakesoul_table.compact(&sess_ctx, None).await?, 3);
+        let files = list_files(&lakesoul_table, client).await?;
+        assert_eq!(files[0].1.len(), 1);
+        assert!(files[0].1[0].ends_with(".parquet"));
+        check_table(
+            &lakesoul_table,
+            &sess_ctx,
+            &[
+                "+-------+------+-------+",
+                "| range | hash | value |",
+                "+-------+------+-------+",
+                "| 1     | 1    | 1     |",
+                "| 1     | 2    | 22    |",
+                "| 1     | 3    | 33    |",
+                "+-------+------+-------+",
+            ],
+        )
+        .await
+    }
 
+    async fn test_compaction_skips_single_file_buckets() -> Result<()> {
+        let table_name = "test_compaction_skips_single_file_buckets";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_schema(create_batch(&[], &[], &[])?.schema())
+            .with_primary_keys(vec!["hash".to_string()])
+            .with_range_partitions(vec!["range".to_string()]);
+        create_table(client.clone(), table_name, builder.build()).await?;
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        let sess_ctx = create_context(client.clone()).await?;
+        let keys = (1..=8).collect::<Vec<_>>();
+        lakesoul_table
+            .execute_upsert(create_batch(&[1; 8], &keys, &keys)?)
+            .await?;
+        let files = list_files(&lakesoul_table, client.clone()).await?;
+        assert!(is_compacted(&files[0].1));
+        lakesoul_table
+            .execute_upsert(create_batch(&[1], &[1], &[11])?)
+            .await?;
+        let upserted_files = list_files(&lakesoul_table, client.clone()).await?;
+        let upserted = upserted_files[0]
+            .1
+            .iter()
+            .find(|path| !files[0].1.contains(path))
+            .cloned()
+            .unwrap();
+        let bucket = extract_hash_bucket_id(&upserted);
+
+        assert!(lakesoul_table.compact(&sess_ctx, None).await? > 0);
+        let compacted_files = list_files(&lakesoul_table, client).await?;
+        assert!(is_compacted(&compacted_files[0].1));
+        // only the bucket of the upserted file is rewritten
+        for path in &upserted_files[0].1 {
+            assert_eq!(
+                compacted_files[0].1.contains(path),
+                extract_hash_bucket_id(path) != bucket,
+                "{}",
+                path
+            );
+        }
+        assert_eq!(compacted_files[0].1.len(), files[0].1.len());
+        check_table(
+            &lakesoul_table,
+            &sess_ctx,
+            &[
+                "+-------+------+-------+",
+                "| range | hash | value |",
+                "+-------+------+-------+",
+                "| 1     | 1    | 11    |",
+                "| 1     | 2    | 2     |",
+                "| 1     | 3    | 3     |",
+                "| 1     | 4    | 4     |",
+                "| 1     | 5    | 5     |",
+                "| 1     | 6    | 6     |",
+                "| 1     | 7    | 7     |",
+                "| 1     | 8    | 8     |",
+                "+-------+------+-------+",
+            ],
+        )
+        .await
+    }
 
-        check_record_batch().await?;
+    async fn test_compaction_keeping_partition_columns() -> Result<()> {
+        let table_name = "test_compaction_keeping_partition_columns";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_schema(create_batch(&[], &[], &[])?.schema())
+            .with_primary_keys(vec!["hash".to_string()])
+            .with_range_partitions(vec!["range".to_string()])
+            .with_option(OPTION_KEY_HASH_BUCKET_NUM, "1")
+            .with_keep_partition_columns(true);
+        create_table(client.clone(), table_name, builder.build()).await?;
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        lakesoul_table
+            .execute_upsert(create_batch(&[1, 1], &[1, 2], &[1, 2])?)
+            .await?;
+        lakesoul_table
+            .execute_upsert(create_batch(&[1, 1], &[2, 3], &[22, 33])?)
+            .await?;
+
+        // the setting of the table applies to the written and the compacted files
+        let has_partition_column = |path: &String| -> Result<bool> {
+            let url =
+                Url::parse(path).map_err(|e| DataFusionError::External(Box::new(e)))?;
+            let file =
+                std::fs::File::open(url.path()).map_err(DataFusionError::IoError)?;
+            let builder = ParquetRecordBatchReaderBuilder::try_new(file)
+                .map_err(DataFusionError::ParquetError)?;
+            Ok(builder.schema().field_with_name("range").is_ok())
+        };
+        let files = list_files(&lakesoul_table, client.clone()).await?;
+        for path in &files[0].1 {
+            assert!(has_partition_column(path)?, "{}", path);
+        }
+        let sess_ctx = create_context(client.clone()).await?;
+        assert_eq!(lakesoul_table.compact(&sess_ctx, None).await?, 3);
+        let files = list_files(&lakesoul_table, client).await?;
+        assert_eq!(files[0].1.len(), 1);
+        assert!(has_partition_column(&files[0].1[0])?, "{}", files[0].1[0]);
+        check_table(
+            &lakesoul_table,
+            &sess_ctx,
+            &[
+                "+-------+------+-------+",
+                "| range | hash | value |",
+                "+-------+------+-------+",
+                "| 1     | 1    | 1     |",
+                "| 1     | 2    | 22    |",
+                "| 1     | 3    | 33    |",
+                "+-------+------+-------+",
+            ],
+        )
+        .await
+    }
 
-        compaction(client, table_name, "range=1").await?;
+    async fn test_upsert_after_compaction() -> Result<()> {
+        let table_name = "test_upsert_after_compaction";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_schema(create_batch(&[], &[], &[])?.schema())
+            .with_primary_keys(vec!["hash".to_string()])
+            .with_range_partitions(vec!["range".to_string()]);
+        create_table(client.clone(), table_name, builder.build()).await?;
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        let sess_ctx = create_context(client.clone()).await?;
+        lakesoul_table
+            .execute_upsert(create_batch(&[1, 1, 1, 1], &[1, 2, 3, 4], &[1, 2, 3, 4])?)
+            .await?;
+        lakesoul_table
+            .execute_upsert(create_batch(&[1, 1, 1], &[1, 2, 3], &[11, 22, 33])?)
+            .await?;
+        lakesoul_table
+            .compact(&sess_ctx, Some(col("range").eq(lit(1))))
+            .await?;
+        check_table(
+            &lakesoul_table,
+            &sess_ctx,
+            &[
+                "+-------+------+-------+",
+                "| range | hash | value |",
+                "+-------+------+-------+",
+                "| 1     | 1    | 11    |",
+                "| 1     | 2    | 22    |",
+                "| 1     | 3    | 33    |",
+                "| 1     | 4    | 4     |",
+                "+-------+------+-------+",
+            ],
+        )
+        .await?;
+
+        // the upserted rows supersede the compacted rows
+        lakesoul_table
+            .execute_upsert(create_batch(
+                &[1, 1, 1, 1],
+                &[2, 3, 4, 5],
+                &[222, 333, 444, 555],
+            )?)
+            .await?;
+        let expected = [
+            "+-------+------+-------+",
+            "| range | hash | value |",
+            "+-------+------+-------+",
+            "| 1     | 1    | 11    |",
+            "| 1     | 2    | 222   |",
+            "| 1     | 3    | 333   |",
+            "| 1     | 4    | 444   |",
+            "| 1     | 5    | 555   |",
+            "+-------+------+-------+",
+        ];
+        check_table(&lakesoul_table, &sess_ctx, &expected).await?;
+        lakesoul_table
+            .compact(&sess_ctx, Some(col("range").eq(lit(1))))
+            .await?;
+        let files = list_files(&lakesoul_table, client).await?;
+        assert!(is_compacted(&files[0].1));
+        check_table(&lakesoul_table, &sess_ctx, &expected).await
+    }
 
-        check_record_batch().await?;
+    async fn test_vacuum_after_compaction() -> Result<()> {
+        let table_name = "test_vacuum_after_compaction";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        init_table(table_name, client.clone()).await?;
+        let lakesoul_table = LakeSoulTable::for_name(table_name)
+            .await?
+            .with_min_vacuum_retention(Duration::ZERO);
+        let sess_ctx = create_context(client.clone()).await?;
+        let files = list_files(&lakesoul_table, client.clone()).await?;
+        lakesoul_table.compact(&sess_ctx, None).await?;
+        let superseded =
+            superseded_files(files, &list_files(&lakesoul_table, client.clone()).await?);
+        assert!(!superseded.is_empty());
+
+        // a file left behind by a failed write is not referenced by any commit
+        let table_dir = Url::parse(&lakesoul_table.table_info().table_path)
+            .map_err(|e| DataFusionError::External(Box::new(e)))?
+            .path()
+            .to_string();
+        let orphan = format!("{}/part-orphan_0000.parquet", table_dir);
+        std::fs::write(&orphan, b"orphan").map_err(DataFusionError::IoError)?;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        // the files are younger than the retention
+        let report = lakesoul_table
+            .vacuum(&sess_ctx, Duration::from_secs(3600), true)
+            .await?;
+        assert!(report.obsolete_files.is_empty() && report.orphan_files.is_empty());
+
+        let report = lakesoul_table
+            .vacuum(&sess_ctx, Duration::ZERO, true)
+            .await?;
+        assert!(report.dry_run);
+        assert_eq!(
+            report
+                .obsolete_files
+                .iter()
+                .cloned()
+                .collect::<HashSet<_>>(),
+            superseded
+        );
+        assert_eq!(report.orphan_files.len(), 1);
+        assert!(report.orphan_files[0].ends_with("part-orphan_0000.parquet"));
+        // a dry run only lists the files
+        assert!(std::path::Path::new(&orphan).exists());
+
+        let report = lakesoul_table
+            .vacuum(&sess_ctx, Duration::ZERO, false)
+            .await?;
+        assert_eq!(report.obsolete_files.len(), superseded.len());
+        assert!(!std::path::Path::new(&orphan).exists());
+        for path in &superseded {
+            let url =
+                Url::parse(path).map_err(|e| DataFusionError::External(Box::new(e)))?;
+            assert!(!std::path::Path::new(url.path()).exists(), "{}", path);
+        }
+        // the statistics of the deleted files are deleted with them
+        let stored_paths = client
+            .get_file_statistics_by_table_id(&lakesoul_table.table_info().table_id)
+            .await?
+            .into_iter()
+            .map(|stored| stored.file_path)
+            .collect::<HashSet<_>>();
+        assert!(!stored_paths.is_empty());
+        assert!(stored_paths.is_disjoint(&superseded));
+        // the deleted files are no longer listed, and the compacted files are kept
+        let report = lakesoul_table
+            .vacuum(&sess_ctx, Duration::ZERO, true)
+            .await?;
+        assert!(report.obsolete_files.is_empty() && report.orphan_files.is_empty());
+        check_table(
+            &lakesoul_table,
+            &sess_ctx,
+            &[
+                "+-------+------+-------+",
+                "| range | hash | value |",
+                "+-------+------+-------+",
+                "| 1     | 1    | 11    |",
+                "| 1     | 2    | 2     |",
+                "| 1     | 3    | 33    |",
+                "| 2     | 1    | 33    |",
+                "+-------+------+-------+",
+            ],
+        )
+        .await
+    }
 
+    async fn test_vacuum_keeps_retained_versions() -> Result<()> {
+        let table_name = "test_vacuum_keeps_retained_versions";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        init_table(table_name, client.clone()).await?;
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        let sess_ctx = create_context(client.clone()).await?;
+
+        // the vacuums shorter than the minimum retention are rejected
+        assert_eq!(
+            lakesoul_table.min_vacuum_retention(),
+            DEFAULT_MIN_VACUUM_RETENTION
+        );
+        assert!(
+            lakesoul_table
+                .vacuum(&sess_ctx, Duration::from_secs(3600), true)
+                .await
+                .is_err()
+        );
+
+        let lakesoul_table = lakesoul_table.with_min_vacuum_retention(Duration::ZERO);
+        let files = list_files(&lakesoul_table, client.clone()).await?;
+        lakesoul_table.compact(&sess_ctx, None).await?;
+        let superseded =
+            superseded_files(files, &list_files(&lakesoul_table, client.clone()).await?);
+        assert!(!superseded.is_empty());
+        // the superseded files are older than the retention and not recorded as discarded,
+        // only the versions before the compaction still reference them
+        let modified = SystemTime::now() - Duration::from_secs(7200);
+        for path in &superseded {
+            client
+                .delete_discard_compressed_file_info_by_file_path(path)
+                .await?;
+            let url =
+                Url::parse(path).map_err(|e| DataFusionError::External(Box::new(e)))?;
+            std::fs::File::options()
+                .write(true)
+                .open(url.path())
+                .and_then(|file| file.set_modified(modified))
+                .map_err(DataFusionError::IoError)?;
+        }
+
+        // the versions before the compaction are readable within the retention
+        let report = lakesoul_table
+            .vacuum(&sess_ctx, Duration::from_secs(3600), true)
+            .await?;
+        assert!(report.orphan_files.is_empty(), "{:?}", report.orphan_files);
+
+        // once they are not, their files are orphans
+        let report = lakesoul_table
+            .vacuum(&sess_ctx, Duration::ZERO, true)
+            .await?;
+        assert_eq!(report.orphan_files.len(), superseded.len());
+        assert!(report.orphan_delete_vectors.is_empty());
         Ok(())
     }
 
-    async fn test_simple_compaction_with_merge_operator() -> Result<()> {
+    async fn test_validate_dangling_files() -> Result<()> {
+        let table_name = "test_validate_dangling_files";
         let client = Arc::new(MetaDataClient::from_env().await?);
-        let table_name = "test_simple_compaction_with_merge_operator";
-
-        let _record_batch_1 = RecordBatch::try_from_iter_with_nullable(
-            vec![
-                ("range", Arc::new(Int32Array::from(vec![1, 2, 3, 1, 1])) as ArrayRef, false),
-                ("hash", Arc::new(Int32Array::from(vec![1, 1, 1, 2, 3])) as ArrayRef, false),
-                ("v1", Arc::new(Int32Array::from(vec![1, 1, 1, 2, 3])) as ArrayRef, true),
-                ("v2", Arc::new(StringArray::from(vec!["1", "1", "1", "2", "3"])) as ArrayRef, true),
-            ]
-        )?;
-
-        let _record_batch_2 = RecordBatch::try_from_iter_with_nullable(
-            vec![
-                ("range", Arc::new(Int32Array::from(vec![1, 2, 3, 1, 1])) as ArrayRef, false),
-                ("hash", Arc::new(Int32Array::from(vec![1, 1, 1, 2, 3])) as ArrayRef, false),
-                ("v1", Arc::new(Int32Array::from(vec![1, 1, 1, 2, 3])) as ArrayRef, true),
-                ("v2", Arc::new(StringArray::from(vec!["1", "1", "1", "2", "3"])) as ArrayRef, true),
-            ]
-        )?;
-
-        create_table(client.clone(), table_name, LakeSoulIOConfigBuilder::default().build()).await?;
-
-        compaction_with_merge_op(client.clone(), table_name, "").await?;
-
-        check_record_batch().await?;
-
+        init_table(table_name, client.clone()).await?;
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        let sess_ctx = create_context(client.clone()).await?;
+        let files = list_files(&lakesoul_table, client.clone()).await?;
+        let checked_files = files.iter().map(|(_, paths)| paths.len()).sum::<usize>();
+        let report = lakesoul_table.validate_files(&sess_ctx, false).await?;
+        assert_eq!(report.checked_files, checked_files);
+        assert!(report.dangling_files.is_empty());
+
+        // a crashed write leaves one file missing and another one truncated
+        let local_path = |path: &String| -> Result<String> {
+            Ok(Url::parse(path)
+                .map_err(|e| DataFusionError::External(Box::new(e)))?
+                .path()
+                .to_string())
+        };
+        let missing = &files[0].1[0];
+        let truncated = &files[1].1[0];
+        std::fs::remove_file(local_path(missing)?).map_err(DataFusionError::IoError)?;
+        std::fs::write(local_path(truncated)?, b"PAR1")
+            .map_err(DataFusionError::IoError)?;
+
+        // the validation only reports the dangling files by default
+        let report = lakesoul_table.validate_files(&sess_ctx, false).await?;
+        assert!(!report.removed);
+        let mut dangling_files = report.dangling_files;
+        dangling_files.sort_by(|a, b| a.partition_desc.cmp(&b.partition_desc));
+        assert_eq!(dangling_files.len(), 2);
+        assert_eq!(&dangling_files[0].file_path, missing);
+        assert_eq!(dangling_files[0].actual_size, None);
+        assert_eq!(&dangling_files[1].file_path, truncated);
+        assert_eq!(dangling_files[1].actual_size, Some(4));
+        assert!(dangling_files[1].recorded_size > 4);
+        assert_eq!(list_files(&lakesoul_table, client.clone()).await?, files);
+
+        let report = lakesoul_table.validate_files(&sess_ctx, true).await?;
+        assert!(report.removed);
+        assert_eq!(report.dangling_files.len(), 2);
+        let remaining = list_files(&lakesoul_table, client.clone()).await?;
+        assert_eq!(
+            remaining
+                .iter()
+                .map(|(_, paths)| paths.len())
+                .sum::<usize>(),
+            checked_files - 2
+        );
+        let report = lakesoul_table.validate_files(&sess_ctx, false).await?;
+        assert_eq!(report.checked_files, checked_files - 2);
+        assert!(report.dangling_files.is_empty());
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_all_cases()  -> Result<()> {
+    async fn test_all_cases() -> Result<()> {
+        test_compaction().await?;
+        test_compaction_with_partition_filter().await?;
+        test_compaction_with_table_compression().await?;
+        test_compaction_of_arrow_ipc_files().await?;
+        test_compaction_skips_single_file_buckets().await?;
+        test_compaction_keeping_partition_columns().await?;
         test_upsert_after_compaction().await?;
-        test_simple_compaction_with_merge_operator().await?;
-        test_simple_compaction_with_condition().await?;
-        test_simple_compaction().await?;
+        test_vacuum_after_compaction().await?;
+        test_vacuum_keeps_retained_versions().await?;
+        test_validate_dangling_files().await?;
         Ok(())
     }
-    
-}
\ No newline at end of file
+}
@@ -0,0 +1,403 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+mod delta_tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use arrow::array::{ArrayRef, Int64Array, StringArray};
+    use arrow::datatypes::{DataType, Field, Fields, Schema};
+    use arrow::json::ReaderBuilder;
+    use arrow::record_batch::RecordBatch;
+    use datafusion::assert_batches_eq;
+    use datafusion::error::Result;
+    use datafusion::prelude::SessionContext;
+    use parquet::arrow::ArrowWriter;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use crate::datasource::delta::DeltaTableProvider;
+
+    const Z85_ALPHABET: &[u8; 85] =
+        b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#";
+
+    fn encode_z85(bytes: &[u8]) -> String {
+        let mut padded = bytes.to_vec();
+        padded.resize(bytes.len().div_ceil(4) * 4, 0);
+        let mut encoded = String::new();
+        for chunk in padded.chunks(4) {
+            let value = u32::from_be_bytes(chunk.try_into().unwrap()) as usize;
+            for divisor in [85usize.pow(4), 85usize.pow(3), 85 * 85, 85, 1] {
+                encoded.push(Z85_ALPHABET[value / divisor % 85] as char);
+            }
+        }
+        encoded
+    }
+
+    /// Serialize the deletion vector of the rows below 65536 with a single array
+    /// container.
+    fn serialize_deletion_vector(rows: &[u16]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&1681511377u32.to_le_bytes());
+        bytes.extend_from_slice(&1u64.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&12346u32.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes.extend_from_slice(&(rows.len() as u16 - 1).to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        for row in rows {
+            bytes.extend_from_slice(&row.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn write_data_file(table_dir: &Path, path: &str, ids: Vec<i64>) -> Result<i64> {
+        let names = ids
+            .iter()
+            .map(|id| format!("name_{}", id))
+            .collect::<Vec<_>>();
+        let batch = RecordBatch::try_from_iter([
+            ("id", Arc::new(Int64Array::from(ids)) as ArrayRef),
+            ("name", Arc::new(StringArray::from(names)) as ArrayRef),
+        ])?;
+        let file_path = table_dir.join(path);
+        fs::create_dir_all(file_path.parent().unwrap())?;
+        let mut writer =
+            ArrowWriter::try_new(File::create(&file_path)?, batch.schema(), None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+        Ok(fs::metadata(&file_path)?.len() as i64)
+    }
+
+    fn write_commit(
+        table_dir: &Path,
+        version: i64,
+        actions: Vec<serde_json::Value>,
+    ) -> Result<()> {
+        let log_dir = table_dir.join("_delta_log");
+        fs::create_dir_all(&log_dir)?;
+        let lines = actions
+            .iter()
+            .map(|action| action.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        fs::write(log_dir.join(format!("{:020}.json", version)), lines)?;
+        Ok(())
+    }
+
+    /// Write a checkpoint of the actions of the version, with the columns of the actions
+    /// replayed by the scans.
+    fn write_checkpoint(
+        table_dir: &Path,
+        version: i64,
+        actions: Vec<serde_json::Value>,
+    ) -> Result<()> {
+        let string_map = || {
+            DataType::Map(
+                Arc::new(Field::new(
+                    "key_value",
+                    DataType::Struct(Fields::from(vec![
+                        Field::new("key", DataType::Utf8, false),
+                        Field::new("value", DataType::Utf8, true),
+                    ])),
+                    false,
+                )),
+                false,
+            )
+        };
+        let schema = Arc::new(Schema::new(vec![
+            Field::new(
+                "add",
+                DataType::Struct(Fields::from(vec![
+                    Field::new("path", DataType::Utf8, true),
+                    Field::new("partitionValues", string_map(), true),
+                    Field::new("size", DataType::Int64, true),
+                    Field::new("modificationTime", DataType::Int64, true),
+                    Field::new("dataChange", DataType::Boolean, true),
+                ])),
+                true,
+            ),
+            Field::new(
+                "remove",
+                DataType::Struct(Fields::from(vec![
+                    Field::new("path", DataType::Utf8, true),
+                    Field::new("deletionTimestamp", DataType::Int64, true),
+                    Field::new("dataChange", DataType::Boolean, true),
+                ])),
+                true,
+            ),
+            Field::new(
+                "metaData",
+                DataType::Struct(Fields::from(vec![
+                    Field::new("id", DataType::Utf8, true),
+                    Field::new("schemaString", DataType::Utf8, true),
+                    Field::new(
+                        "partitionColumns",
+                        DataType::List(Arc::new(Field::new_list_field(
+                            DataType::Utf8,
+                            true,
+                        ))),
+                        true,
+                    ),
+                    Field::new("configuration", string_map(), true),
+                ])),
+                true,
+            ),
+            Field::new(
+                "protocol",
+                DataType::Struct(Fields::from(vec![
+                    Field::new("minReaderVersion", DataType::Int32, true),
+                    Field::new("minWriterVersion", DataType::Int32, true),
+                ])),
+                true,
+            ),
+        ]));
+        let mut decoder = ReaderBuilder::new(schema.clone()).build_decoder()?;
+        decoder.serialize(&actions)?;
+        let batch = decoder.flush()?.unwrap();
+        let log_dir = table_dir.join("_delta_log");
+        fs::create_dir_all(&log_dir)?;
+        let file =
+            File::create(log_dir.join(format!("{:020}.checkpoint.parquet", version)))?;
+        let mut writer = ArrowWriter::try_new(file, schema, None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+        Ok(())
+    }
+
+    fn schema_string() -> String {
+        json!({
+            "type": "struct",
+            "fields": [
+                {"name": "id", "type": "long", "nullable": true, "metadata": {}},
+                {"name": "name", "type": "string", "nullable": true, "metadata": {}},
+                {"name": "date", "type": "string", "nullable": true, "metadata": {}},
+            ]
+        })
+        .to_string()
+    }
+
+    /// The protocol and metadata actions of a table partitioned by `date`.
+    fn table_actions() -> Vec<serde_json::Value> {
+        vec![
+            json!({"protocol": {
+                "minReaderVersion": 3,
+                "minWriterVersion": 7,
+                "readerFeatures": ["deletionVectors"],
+                "writerFeatures": ["deletionVectors"],
+            }}),
+            json!({"metaData": {
+                "id": "test",
+                "format": {"provider": "parquet", "options": {}},
+                "schemaString": schema_string(),
+                "partitionColumns": ["date"],
+                "configuration": {},
+            }}),
+        ]
+    }
+
+    fn add_action(path: &str, date: &str, size: i64) -> serde_json::Value {
+        json!({"add": {
+            "path": path,
+            "partitionValues": {"date": date},
+            "size": size,
+            "modificationTime": 0,
+            "dataChange": true,
+        }})
+    }
+
+    async fn read_table(location: &str) -> Result<Vec<RecordBatch>> {
+        let ctx = SessionContext::new();
+        let provider = DeltaTableProvider::try_new(&ctx.state(), location).await?;
+        ctx.register_table("delta", Arc::new(provider))?;
+        ctx.sql("SELECT id, name, date FROM delta ORDER BY id")
+            .await?
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_read_delta_table_with_deletion_vectors() -> Result<()> {
+        let table_dir = env::temp_dir().join(format!("delta_{}", Uuid::new_v4()));
+        let size_a = write_data_file(&table_dir, "date=a/part-0.parquet", vec![1, 2, 3])?;
+        let size_b = write_data_file(&table_dir, "date=b/part-1.parquet", vec![4, 5])?;
+        let mut actions = table_actions();
+        actions.push(add_action("date=a/part-0.parquet", "a", size_a));
+        actions.push(add_action("date=b/part-1.parquet", "b", size_b));
+        write_commit(&table_dir, 0, actions)?;
+        // delete the second row of the first file
+        let deletion_vector = serialize_deletion_vector(&[1]);
+        write_commit(
+            &table_dir,
+            1,
+            vec![
+                json!({"remove": {
+                    "path": "date=a/part-0.parquet",
+                    "deletionTimestamp": 0,
+                    "dataChange": true,
+                }}),
+                json!({"add": {
+                    "path": "date=a/part-0.parquet",
+                    "partitionValues": {"date": "a"},
+                    "size": size_a,
+                    "modificationTime": 0,
+                    "dataChange": true,
+                    "deletionVector": {
+                        "storageType": "i",
+                        "pathOrInlineDv": encode_z85(&deletion_vector),
+                        "sizeInBytes": deletion_vector.len(),
+                        "cardinality": 1,
+                    },
+                }}),
+            ],
+        )?;
+
+        let ctx = SessionContext::new();
+        let location = table_dir.to_str().unwrap();
+        let latest = DeltaTableProvider::try_new(&ctx.state(), location).await?;
+        assert_eq!(latest.version(), 1);
+        ctx.register_table("latest", Arc::new(latest))?;
+        let first =
+            DeltaTableProvider::try_new_with_version(&ctx.state(), location, Some(0))
+                .await?;
+        ctx.register_table("first", Arc::new(first))?;
+
+        let batches = ctx
+            .sql("SELECT id, name, date FROM latest ORDER BY id")
+            .await?
+            .collect()
+            .await?;
+        assert_batches_eq!(
+            [
+                "+----+--------+------+",
+                "| id | name   | date |",
+                "+----+--------+------+",
+                "| 1  | name_1 | a    |",
+                "| 3  | name_3 | a    |",
+                "| 4  | name_4 | b    |",
+                "| 5  | name_5 | b    |",
+                "+----+--------+------+",
+            ],
+            &batches
+        );
+        let batches = ctx
+            .sql("SELECT count(*) AS c FROM first WHERE id > 1 AND date = 'a'")
+            .await?
+            .collect()
+            .await?;
+        assert_batches_eq!(["+---+", "| c |", "+---+", "| 2 |", "+---+"], &batches);
+
+        fs::remove_dir_all(&table_dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_delta_table_from_checkpoint() -> Result<()> {
+        let table_dir = env::temp_dir().join(format!("delta_{}", Uuid::new_v4()));
+        let size_a = write_data_file(&table_dir, "date=a/part-0.parquet", vec![1, 2])?;
+        let size_b = write_data_file(&table_dir, "date=b/part-1.parquet", vec![3])?;
+        let size_c = write_data_file(&table_dir, "date=b/part-2.parquet", vec![4, 5])?;
+        let mut actions = table_actions();
+        actions.push(add_action("date=a/part-0.parquet", "a", size_a));
+        actions.push(add_action("date=b/part-1.parquet", "b", size_b));
+        write_commit(&table_dir, 0, actions)?;
+        let remove = json!({"remove": {
+            "path": "date=b/part-1.parquet",
+            "deletionTimestamp": 0,
+            "dataChange": true,
+        }});
+        write_commit(&table_dir, 1, vec![remove.clone()])?;
+        // the checkpoint of version 1 keeps the removed file as a tombstone
+        let mut actions = table_actions();
+        actions.push(add_action("date=a/part-0.parquet", "a", size_a));
+        actions.push(remove);
+        write_checkpoint(&table_dir, 1, actions)?;
+        write_commit(
+            &table_dir,
+            2,
+            vec![add_action("date=b/part-2.parquet", "b", size_c)],
+        )?;
+        // the commits up to the checkpoint are not read anymore
+        for version in 0..=1 {
+            fs::remove_file(
+                table_dir
+                    .join("_delta_log")
+                    .join(format!("{:020}.json", version)),
+            )?;
+        }
+
+        let batches = read_table(table_dir.to_str().unwrap()).await?;
+        assert_batches_eq!(
+            [
+                "+----+--------+------+",
+                "| id | name   | date |",
+                "+----+--------+------+",
+                "| 1  | name_1 | a    |",
+                "| 2  | name_2 | a    |",
+                "| 4  | name_4 | b    |",
+                "| 5  | name_5 | b    |",
+                "+----+--------+------+",
+            ],
+            &batches
+        );
+
+        fs::remove_dir_all(&table_dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_delta_table_with_uuid_deletion_vector() -> Result<()> {
+        let table_dir = env::temp_dir().join(format!("delta_{}", Uuid::new_v4()));
+        let size_a = write_data_file(&table_dir, "date=a/part-0.parquet", vec![1, 2, 3])?;
+        // the deletion vector file starts with its format version, then holds the size,
+        // the serialized deletion vector and its checksum
+        let deletion_vector = serialize_deletion_vector(&[0, 2]);
+        let uuid = Uuid::new_v4();
+        let mut bytes = vec![1u8];
+        bytes.extend_from_slice(&(deletion_vector.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&deletion_vector);
+        bytes.extend_from_slice(&0u32.to_be_bytes());
+        fs::create_dir_all(table_dir.join("ab"))?;
+        fs::write(
+            table_dir
+                .join("ab")
+                .join(format!("deletion_vector_{}.bin", uuid)),
+            bytes,
+        )?;
+        let mut actions = table_actions();
+        actions.push(json!({"add": {
+            "path": "date=a/part-0.parquet",
+            "partitionValues": {"date": "a"},
+            "size": size_a,
+            "modificationTime": 0,
+            "dataChange": true,
+            "deletionVector": {
+                "storageType": "u",
+                "pathOrInlineDv": format!("ab{}", encode_z85(uuid.as_bytes())),
+                "offset": 1,
+                "sizeInBytes": deletion_vector.len(),
+                "cardinality": 2,
+            },
+        }}));
+        write_commit(&table_dir, 0, actions)?;
+
+        let batches = read_table(table_dir.to_str().unwrap()).await?;
+        assert_batches_eq!(
+            [
+                "+----+--------+------+",
+                "| id | name   | date |",
+                "+----+--------+------+",
+                "| 2  | name_2 | a    |",
+                "+----+--------+------+",
+            ],
+            &batches
+        );
+
+        fs::remove_dir_all(&table_dir)?;
+        Ok(())
+    }
+}
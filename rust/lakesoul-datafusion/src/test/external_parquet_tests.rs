@@ -0,0 +1,100 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+mod external_parquet_tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use arrow::array::{ArrayRef, Int64Array, StringArray};
+    use arrow::record_batch::RecordBatch;
+    use datafusion::error::Result;
+    use datafusion::prelude::SessionContext;
+    use parquet::arrow::ArrowWriter;
+
+    use crate::datasource::external_parquet::ExternalParquetTableProvider;
+    use crate::test::assert_batches_eq;
+
+    fn write_parquet_file(dir: &Path, path: &str, ids: Vec<i64>) -> Result<()> {
+        let names = ids
+            .iter()
+            .map(|id| format!("name_{}", id))
+            .collect::<Vec<_>>();
+        let batch = RecordBatch::try_from_iter([
+            ("id", Arc::new(Int64Array::from(ids)) as ArrayRef),
+            ("name", Arc::new(StringArray::from(names)) as ArrayRef),
+        ])?;
+        let file_path = dir.join(path);
+        fs::create_dir_all(file_path.parent().unwrap())?;
+        let mut writer =
+            ArrowWriter::try_new(File::create(&file_path)?, batch.schema(), None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_external_parquet_directory() -> Result<()> {
+        let dir = env::temp_dir().join(format!("landing_{}", uuid::Uuid::new_v4()));
+        write_parquet_file(&dir, "part-0.parquet", vec![1, 2, 3])?;
+        write_parquet_file(&dir, "2024/01/part-1.parquet", vec![4, 5])?;
+        // files of other formats in the directory are not read
+        fs::write(dir.join("_SUCCESS"), b"")?;
+
+        // the directory is read without a metadata client
+        let ctx = SessionContext::new();
+        let provider = ExternalParquetTableProvider::try_new(
+            &ctx.state(),
+            None,
+            &format!("{}/", dir.to_str().unwrap()),
+        )
+        .await?;
+        ctx.register_table("landing", Arc::new(provider))?;
+
+        let batches = ctx
+            .sql("SELECT id, name FROM landing WHERE id > 1")
+            .await?
+            .collect()
+            .await?;
+        assert_batches_eq(
+            "landing",
+            &[
+                "+----+--------+",
+                "| id | name   |",
+                "+----+--------+",
+                "| 2  | name_2 |",
+                "| 3  | name_3 |",
+                "| 4  | name_4 |",
+                "| 5  | name_5 |",
+                "+----+--------+",
+            ],
+            &batches,
+        );
+
+        // the files added after the provider was created are read as well
+        write_parquet_file(&dir, "part-2.parquet", vec![6])?;
+        let batches = ctx
+            .sql("SELECT count(*) AS c FROM landing")
+            .await?
+            .collect()
+            .await?;
+        assert_batches_eq(
+            "landing",
+            &["+---+", "| c |", "+---+", "| 6 |", "+---+"],
+            &batches,
+        );
+        // the table is read-only
+        let inserted = async {
+            ctx.sql("INSERT INTO landing VALUES (7, 'name_7')")
+                .await?
+                .collect()
+                .await
+        };
+        assert!(inserted.await.is_err());
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
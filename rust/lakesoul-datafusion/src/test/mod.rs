@@ -7,10 +7,14 @@ use std::sync::Arc;
 
 use lakesoul_metadata::MetaDataClient;
 
+mod compaction_tests;
+mod delta_tests;
+mod external_parquet_tests;
 mod hash_tests;
 mod insert_tests;
+#[cfg(feature = "validate-pruning")]
+mod pruning_validation_tests;
 mod upsert_tests;
-// mod compaction_tests;
 // mod streaming_tests;
 #[cfg(feature = "ci")]
 mod integration_tests;
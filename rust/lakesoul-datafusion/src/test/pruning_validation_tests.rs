@@ -0,0 +1,173 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+mod pruning_validation_tests {
+    use std::sync::Arc;
+
+    use arrow::array::{ArrayRef, Int32Array};
+    use arrow::record_batch::RecordBatch;
+    use bytes::Bytes;
+    use datafusion::error::DataFusionError;
+    use datafusion::prelude::{SessionContext, col, lit};
+    use lakesoul_io::lakesoul_io_config::{
+        LakeSoulIOConfigBuilder, create_session_context,
+    };
+    use lakesoul_metadata::{MetaDataClient, MetaDataClientRef};
+    use parquet::file::metadata::{ParquetMetaDataReader, ParquetMetaDataWriter};
+    use parquet::file::statistics::Statistics;
+    use url::Url;
+
+    use crate::catalog::{create_io_config_builder, create_table};
+    use crate::datasource::file_format::invalid_pruning_count;
+    use crate::error::Result;
+    use crate::lakesoul_table::LakeSoulTable;
+
+    fn create_batch(id: &[i32]) -> Result<RecordBatch> {
+        Ok(RecordBatch::try_from_iter([(
+            "id",
+            Arc::new(Int32Array::from(id.to_vec())) as ArrayRef,
+        )])?)
+    }
+
+    async fn create_context(client: MetaDataClientRef) -> Result<SessionContext> {
+        let builder = create_io_config_builder(
+            client,
+            None,
+            false,
+            "default",
+            Default::default(),
+            Default::default(),
+        )
+        .await?;
+        Ok(create_session_context(&mut builder.build())?)
+    }
+
+    /// Replace the min/max statistics of the `id` column in the footers of the data files
+    /// of the table, leaving their rows unchanged.
+    async fn overwrite_statistics(
+        lakesoul_table: &LakeSoulTable,
+        client: MetaDataClientRef,
+        min: i32,
+        max: i32,
+    ) -> Result<()> {
+        for partition_info in client
+            .get_all_partition_info(&lakesoul_table.table_info().table_id)
+            .await?
+        {
+            for path in client
+                .get_data_files_of_single_partition(&partition_info)
+                .await?
+            {
+                let path = Url::parse(&path)
+                    .map_err(|e| DataFusionError::External(Box::new(e)))?
+                    .path()
+                    .to_string();
+                let bytes =
+                    Bytes::from(std::fs::read(&path).map_err(DataFusionError::IoError)?);
+                let metadata = ParquetMetaDataReader::new()
+                    .parse_and_finish(&bytes)
+                    .map_err(DataFusionError::ParquetError)?;
+                // the footer is followed by its length and the magic number
+                let footer_len = u32::from_le_bytes(
+                    bytes[bytes.len() - 8..bytes.len() - 4].try_into().unwrap(),
+                ) as usize;
+                let mut builder = metadata.into_builder();
+                let row_groups = builder
+                    .take_row_groups()
+                    .into_iter()
+                    .map(|row_group| {
+                        let columns = row_group
+                            .columns()
+                            .iter()
+                            .cloned()
+                            .map(|column| {
+                                column
+                                    .into_builder()
+                                    .set_statistics(Statistics::int32(
+                                        Some(min),
+                                        Some(max),
+                                        None,
+                                        Some(0),
+                                        false,
+                                    ))
+                                    .build()
+                            })
+                            .collect::<parquet::errors::Result<Vec<_>>>()?;
+                        row_group
+                            .into_builder()
+                            .set_column_metadata(columns)
+                            .build()
+                    })
+                    .collect::<parquet::errors::Result<Vec<_>>>()
+                    .map_err(DataFusionError::ParquetError)?;
+                let metadata = builder.set_row_groups(row_groups).build();
+                let mut file = bytes[..bytes.len() - 8 - footer_len].to_vec();
+                ParquetMetaDataWriter::new(&mut file, &metadata)
+                    .finish()
+                    .map_err(DataFusionError::ParquetError)?;
+                std::fs::write(&path, file).map_err(DataFusionError::IoError)?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn count_matching_rows(
+        lakesoul_table: &LakeSoulTable,
+        sess_ctx: &SessionContext,
+        threshold: i32,
+    ) -> Result<usize> {
+        let batches = lakesoul_table
+            .to_dataframe(sess_ctx)
+            .await?
+            .filter(col("id").gt(lit(threshold)))?
+            .collect()
+            .await?;
+        Ok(batches.iter().map(RecordBatch::num_rows).sum())
+    }
+
+    async fn test_pruning_validation_counts_wrong_statistics() -> Result<()> {
+        let table_name = "test_pruning_validation_counts_wrong_statistics";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        let builder =
+            LakeSoulIOConfigBuilder::new().with_schema(create_batch(&[])?.schema());
+        create_table(client.clone(), table_name, builder.build()).await?;
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        lakesoul_table
+            .execute_upsert(create_batch(&[1, 2])?)
+            .await?;
+        lakesoul_table
+            .execute_upsert(create_batch(&[3, 4])?)
+            .await?;
+        let sess_ctx = create_context(client.clone()).await?;
+
+        // the statistics of the file of 3 and 4 claim the values of the other file
+        overwrite_statistics(&lakesoul_table, client, 1, 2).await?;
+
+        // the filter prunes no file storing matching rows
+        let invalid = invalid_pruning_count();
+        assert_eq!(count_matching_rows(&lakesoul_table, &sess_ctx, 0).await?, 4);
+        assert_eq!(invalid_pruning_count(), invalid);
+
+        // the filter prunes the file of 3 and 4 by its wrong statistics
+        assert_eq!(count_matching_rows(&lakesoul_table, &sess_ctx, 2).await?, 0);
+        assert_eq!(invalid_pruning_count(), invalid + 1);
+
+        // the plan is not executed by EXPLAIN
+        lakesoul_table
+            .to_dataframe(&sess_ctx)
+            .await?
+            .filter(col("id").gt(lit(2)))?
+            .explain(false, false)?
+            .collect()
+            .await?;
+        assert_eq!(invalid_pruning_count(), invalid + 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_all_cases() -> Result<()> {
+        test_pruning_validation_counts_wrong_statistics().await?;
+        Ok(())
+    }
+}
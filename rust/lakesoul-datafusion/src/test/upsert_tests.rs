@@ -3,28 +3,58 @@
 // SPDX-License-Identifier: Apache-2.0
 mod upsert_with_metadata_tests {
 
-    use chrono::naive::NaiveDate;
+    use chrono::naive::{NaiveDate, NaiveDateTime};
     use std::sync::Arc;
 
     use lakesoul_io::filter::parser::Parser;
 
     use arrow::datatypes::DataType;
 
-    use arrow::array::{ArrayRef, Int32Array, StringArray, TimestampMicrosecondArray};
+    use arrow::array::{
+        ArrayRef, Int32Array, StringArray, TimestampMicrosecondArray,
+        TimestampMillisecondArray,
+    };
     use arrow::datatypes::{Field, Schema, SchemaRef, TimeUnit};
     use arrow::record_batch::RecordBatch;
 
-    use crate::error::Result;
+    use crate::error::{LakeSoulError, Result};
     use crate::lakesoul_table::LakeSoulTable;
     use crate::test::assert_batches_eq;
 
     use lakesoul_io::lakesoul_io_config::{
-        LakeSoulIOConfigBuilder, create_session_context,
+        LakeSoulIOConfigBuilder, MergeStrategy, OPTION_KEY_CDC_COLUMN,
+        OPTION_KEY_HASH_BUCKET_NUM, OPTION_KEY_OUTPUT_TABLE_COLUMN_ORDER,
+        OPTION_KEY_PARQUET_COMPRESSION, create_session_context,
     };
 
     use lakesoul_metadata::{MetaDataClient, MetaDataClientRef};
 
     use crate::catalog::{create_io_config_builder, create_table};
+    use chrono::Utc;
+    use datafusion::common::stats::Precision;
+    use datafusion::datasource::memory::MemorySourceConfig;
+    use datafusion::error::DataFusionError;
+    use datafusion::physical_plan::collect;
+    use datafusion::prelude::{SessionContext, col, lit};
+    use datafusion_substrait::substrait::proto::Plan;
+    use datafusion_substrait::substrait::proto::plan_rel::RelType as PlanRelType;
+    use datafusion_substrait::substrait::proto::rel::RelType;
+    use lakesoul_io::helpers::resolve_file_url;
+    use parquet::arrow::ArrowWriter;
+    use prost::Message;
+    use proto::proto::entity::{CommitOp, DataCommitInfo, DataFileOp, FileOp};
+    use url::Url;
+    use uuid::Uuid;
+
+    use crate::datasource::delete_vector::{
+        delete_vector_path, is_delete_vector, write_delete_vector,
+    };
+    use crate::datasource::file_format::LakeSoulHashSinkExec;
+    use crate::datasource::statistics::StoredFileStatistics;
+    use crate::datasource::substrait::{LakeSoulReadExtension, LakeSoulScan};
+    use crate::datasource::table_provider::LakeSoulTableProvider;
+    use crate::datasource::table_provider::TableSnapshot;
+    use datafusion::datasource::TableProvider;
 
     enum StrOrI32 {
         V1(&'static str),
@@ -447,6 +477,1307 @@ mod upsert_with_metadata_tests {
         .await
     }
 
+    async fn test_merge_and_filter_updated_rows_by_non_primary_key_i32() -> Result<()> {
+        let table_name = "merge_and_filter_updated_rows_by_non_primary_key_i32";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+
+        init_table(
+            create_batch_i32(vec!["hash", "value"], vec![&[1, 2], &[10, 20]]),
+            table_name,
+            SchemaRef::new(Schema::new(
+                ["hash", "value"]
+                    .into_iter()
+                    .map(|name| Field::new(name, DataType::Int32, true))
+                    .collect::<Vec<Field>>(),
+            )),
+            vec!["hash".to_string()],
+            vec![],
+            client.clone(),
+        )
+        .await?;
+
+        // the file of the update does not match the filter, the older version must not leak
+        check_upsert(
+            create_batch_i32(vec!["hash", "value"], vec![&[1], &[1]]),
+            table_name,
+            vec!["hash", "value"],
+            Some("gt(value, 5)".to_string()),
+            client.clone(),
+            &[
+                "+------+-------+",
+                "| hash | value |",
+                "+------+-------+",
+                "| 2    | 20    |",
+                "+------+-------+",
+            ],
+        )
+        .await
+    }
+
+    async fn test_read_change_feed_between_versions_i32() -> Result<()> {
+        let table_name = "read_change_feed_between_versions_i32";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+
+        init_table(
+            create_batch_i32(vec!["hash", "value"], vec![&[1, 2], &[10, 20]]),
+            table_name,
+            SchemaRef::new(Schema::new(
+                ["hash", "value"]
+                    .into_iter()
+                    .map(|name| Field::new(name, DataType::Int32, true))
+                    .collect::<Vec<Field>>(),
+            )),
+            vec!["hash".to_string()],
+            vec![],
+            client.clone(),
+        )
+        .await?;
+        execute_upsert(
+            create_batch_i32(vec!["hash", "value"], vec![&[1], &[100]]),
+            table_name,
+            client.clone(),
+        )
+        .await?;
+        execute_upsert(
+            create_batch_i32(vec!["hash", "value"], vec![&[1, 3], &[200, 30]]),
+            table_name,
+            client.clone(),
+        )
+        .await?;
+
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        let builder = create_io_config_builder(
+            client.clone(),
+            Some(table_name),
+            false,
+            "default",
+            Default::default(),
+            Default::default(),
+        )
+        .await?
+        .with_change_feed(0, 2);
+        let sess_ctx = create_session_context(&mut builder.clone().build())?;
+        let provider = LakeSoulTableProvider::try_new(
+            &sess_ctx.state(),
+            client,
+            builder.build(),
+            lakesoul_table.table_info(),
+            false,
+        )
+        .await?;
+        let result = sess_ctx
+            .read_table(Arc::new(provider))?
+            .select_columns(&["hash", "value"])?
+            .collect()
+            .await?;
+
+        // every version written after version 0 is emitted, the initial rows are not
+        assert_batches_eq(
+            table_name,
+            &[
+                "+------+-------+",
+                "| hash | value |",
+                "+------+-------+",
+                "| 1    | 100   |",
+                "| 1    | 200   |",
+                "| 3    | 30    |",
+                "+------+-------+",
+            ],
+            &result,
+        );
+        Ok(())
+    }
+
+    async fn test_read_change_feed_of_cdc_table() -> Result<()> {
+        let table_name = "read_change_feed_of_cdc_table";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        let cdc_batch = |hash: &[i32], value: &[i32], row_kinds: &[&str]| {
+            RecordBatch::try_from_iter([
+                (
+                    "hash",
+                    Arc::new(Int32Array::from(hash.to_vec())) as ArrayRef,
+                ),
+                (
+                    "value",
+                    Arc::new(Int32Array::from(value.to_vec())) as ArrayRef,
+                ),
+                (
+                    "rowKinds",
+                    Arc::new(StringArray::from(row_kinds.to_vec())) as ArrayRef,
+                ),
+            ])
+        };
+        let batch =
+            cdc_batch(&[1, 2, 3], &[10, 20, 30], &["insert", "insert", "insert"])?;
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_schema(batch.schema())
+            .with_primary_keys(vec!["hash".to_string()])
+            .with_option(OPTION_KEY_CDC_COLUMN, "rowKinds");
+        create_table(client.clone(), table_name, builder.build()).await?;
+        execute_upsert(batch, table_name, client.clone()).await?;
+        execute_upsert(
+            cdc_batch(&[1], &[11], &["update"])?,
+            table_name,
+            client.clone(),
+        )
+        .await?;
+        execute_upsert(
+            cdc_batch(&[2], &[20], &["delete"])?,
+            table_name,
+            client.clone(),
+        )
+        .await?;
+
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        let builder = create_io_config_builder(
+            client.clone(),
+            Some(table_name),
+            true,
+            "default",
+            Default::default(),
+            Default::default(),
+        )
+        .await?
+        .with_change_feed(0, 2);
+        let sess_ctx = create_session_context(&mut builder.clone().build())?;
+        let provider = LakeSoulTableProvider::try_new(
+            &sess_ctx.state(),
+            client,
+            builder.build(),
+            lakesoul_table.table_info(),
+            false,
+        )
+        .await?;
+        let result = sess_ctx
+            .read_table(Arc::new(provider))?
+            .sort(vec![col("hash").sort(true, true)])?
+            .collect()
+            .await?;
+
+        // the change events keep their op column, the deleted row is not filtered away
+        assert_batches_eq(
+            table_name,
+            &[
+                "+------+-------+----------+",
+                "| hash | value | rowKinds |",
+                "+------+-------+----------+",
+                "| 1    | 11    | update   |",
+                "| 2    | 20    | delete   |",
+                "+------+-------+----------+",
+            ],
+            &result,
+        );
+        Ok(())
+    }
+
+    async fn test_select_non_cdc_columns_of_cdc_table() -> Result<()> {
+        let table_name = "select_non_cdc_columns_of_cdc_table";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        let cdc_batch = |hash: &[i32], value: &[i32], row_kinds: &[&str]| {
+            RecordBatch::try_from_iter([
+                (
+                    "hash",
+                    Arc::new(Int32Array::from(hash.to_vec())) as ArrayRef,
+                ),
+                (
+                    "value",
+                    Arc::new(Int32Array::from(value.to_vec())) as ArrayRef,
+                ),
+                (
+                    "rowKinds",
+                    Arc::new(StringArray::from(row_kinds.to_vec())) as ArrayRef,
+                ),
+            ])
+        };
+        let batch =
+            cdc_batch(&[1, 2, 3], &[10, 20, 30], &["insert", "insert", "insert"])?;
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_schema(batch.schema())
+            .with_primary_keys(vec!["hash".to_string()])
+            .with_option(OPTION_KEY_CDC_COLUMN, "rowKinds");
+        create_table(client.clone(), table_name, builder.build()).await?;
+        execute_upsert(batch, table_name, client.clone()).await?;
+        execute_upsert(
+            cdc_batch(&[2], &[20], &["delete"])?,
+            table_name,
+            client.clone(),
+        )
+        .await?;
+
+        // the cdc column is read to drop the deleted rows although it is not selected
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        let builder = create_io_config_builder(
+            client,
+            None,
+            false,
+            "default",
+            Default::default(),
+            Default::default(),
+        )
+        .await?;
+        let sess_ctx = create_session_context(&mut builder.build())?;
+        let result = lakesoul_table
+            .to_dataframe(&sess_ctx)
+            .await?
+            .select_columns(&["hash", "value"])?
+            .collect()
+            .await?;
+        assert_batches_eq(
+            table_name,
+            &[
+                "+------+-------+",
+                "| hash | value |",
+                "+------+-------+",
+                "| 1    | 10    |",
+                "| 3    | 30    |",
+                "+------+-------+",
+            ],
+            &result,
+        );
+        Ok(())
+    }
+
+    async fn test_read_cdc_table_with_delete_markers() -> Result<()> {
+        let table_name = "read_cdc_table_with_delete_markers";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        let cdc_batch = |hash: &[i32], value: &[i32], row_kinds: &[&str]| {
+            RecordBatch::try_from_iter([
+                (
+                    "hash",
+                    Arc::new(Int32Array::from(hash.to_vec())) as ArrayRef,
+                ),
+                (
+                    "value",
+                    Arc::new(Int32Array::from(value.to_vec())) as ArrayRef,
+                ),
+                (
+                    "rowKinds",
+                    Arc::new(StringArray::from(row_kinds.to_vec())) as ArrayRef,
+                ),
+            ])
+        };
+        let batch = cdc_batch(&[1, 2, 3], &[10, 20, 30], &["I", "I", "I"])?;
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_schema(batch.schema())
+            .with_primary_keys(vec!["hash".to_string()])
+            .with_option(OPTION_KEY_CDC_COLUMN, "rowKinds");
+        create_table(client.clone(), table_name, builder.build()).await?;
+        execute_upsert(batch, table_name, client.clone()).await?;
+        execute_upsert(
+            cdc_batch(&[1, 2, 3], &[11, 20, 30], &["U", "D", "-D"])?,
+            table_name,
+            client.clone(),
+        )
+        .await?;
+
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        let read = |markers: Option<Vec<String>>| {
+            let client = client.clone();
+            let table_info = lakesoul_table.table_info();
+            async move {
+                let mut builder = create_io_config_builder(
+                    client.clone(),
+                    Some(table_name),
+                    true,
+                    "default",
+                    Default::default(),
+                    Default::default(),
+                )
+                .await?;
+                if let Some(markers) = markers {
+                    builder = builder.with_cdc_delete_markers(markers);
+                }
+                let sess_ctx = create_session_context(&mut builder.clone().build())?;
+                let provider = LakeSoulTableProvider::try_new(
+                    &sess_ctx.state(),
+                    client,
+                    builder.build(),
+                    table_info,
+                    false,
+                )
+                .await?;
+                let batches = sess_ctx
+                    .read_table(Arc::new(provider))?
+                    .select_columns(&["hash", "value"])?
+                    .collect()
+                    .await?;
+                Ok::<_, LakeSoulError>(batches)
+            }
+        };
+
+        // only the rows marked `delete` are deleted by default
+        assert_batches_eq(
+            table_name,
+            &[
+                "+------+-------+",
+                "| hash | value |",
+                "+------+-------+",
+                "| 1    | 11    |",
+                "| 2    | 20    |",
+                "| 3    | 30    |",
+                "+------+-------+",
+            ],
+            &read(None).await?,
+        );
+        assert_batches_eq(
+            table_name,
+            &[
+                "+------+-------+",
+                "| hash | value |",
+                "+------+-------+",
+                "| 1    | 11    |",
+                "+------+-------+",
+            ],
+            &read(Some(vec!["D".to_string(), "-D".to_string()])).await?,
+        );
+        Ok(())
+    }
+
+    async fn test_validate_scan_schema_of_cdc_table() -> Result<()> {
+        let table_name = "validate_scan_schema_of_cdc_table";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        let batch = RecordBatch::try_from_iter([
+            ("hash", Arc::new(Int32Array::from(vec![1, 2])) as ArrayRef),
+            (
+                "value",
+                Arc::new(Int32Array::from(vec![10, 20])) as ArrayRef,
+            ),
+            (
+                "rowKinds",
+                Arc::new(StringArray::from(vec!["insert", "insert"])) as ArrayRef,
+            ),
+        ])?;
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_schema(batch.schema())
+            .with_primary_keys(vec!["hash".to_string()])
+            .with_option(OPTION_KEY_CDC_COLUMN, "rowKinds");
+        create_table(client.clone(), table_name, builder.build()).await?;
+        execute_upsert(batch, table_name, client.clone()).await?;
+
+        // the projections out of the table column order, with and without the cdc column,
+        // pass the validation of the scan output, enabled in the debug builds
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        let sess_ctx =
+            create_session_context(&mut LakeSoulIOConfigBuilder::new().build())?;
+        let result = lakesoul_table
+            .to_dataframe(&sess_ctx)
+            .await?
+            .select_columns(&["value", "hash"])?
+            .collect()
+            .await?;
+        assert_batches_eq(
+            table_name,
+            &[
+                "+-------+------+",
+                "| value | hash |",
+                "+-------+------+",
+                "| 10    | 1    |",
+                "| 20    | 2    |",
+                "+-------+------+",
+            ],
+            &result,
+        );
+        let result = lakesoul_table
+            .to_dataframe(&sess_ctx)
+            .await?
+            .select_columns(&["rowKinds", "value"])?
+            .collect()
+            .await?;
+        assert_batches_eq(
+            table_name,
+            &[
+                "+----------+-------+",
+                "| rowKinds | value |",
+                "+----------+-------+",
+                "| insert   | 10    |",
+                "| insert   | 20    |",
+                "+----------+-------+",
+            ],
+            &result,
+        );
+        Ok(())
+    }
+
+    async fn test_scan_with_non_monotonic_projection_i32() -> Result<()> {
+        let table_name = "scan_with_non_monotonic_projection_i32";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        init_table(
+            create_batch_i32(
+                vec!["hash", "value", "extra"],
+                vec![&[1, 2], &[10, 20], &[1, 2]],
+            ),
+            table_name,
+            SchemaRef::new(Schema::new(
+                ["hash", "value", "extra"]
+                    .into_iter()
+                    .map(|name| Field::new(name, DataType::Int32, true))
+                    .collect::<Vec<Field>>(),
+            )),
+            vec!["hash".to_string()],
+            vec![],
+            client.clone(),
+        )
+        .await?;
+        execute_upsert(
+            create_batch_i32(vec!["hash", "value", "extra"], vec![&[1], &[100], &[3]]),
+            table_name,
+            client.clone(),
+        )
+        .await?;
+
+        // the merged rows are output in the order of the projection, unless reindexed to
+        // the column order of the table
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        let builder = create_io_config_builder(
+            client.clone(),
+            Some(table_name),
+            true,
+            "default",
+            Default::default(),
+            Default::default(),
+        )
+        .await?;
+        let sess_ctx = create_session_context(&mut builder.clone().build())?;
+        for (table_column_order, expected) in [
+            (
+                false,
+                [
+                    "+-------+------+",
+                    "| extra | hash |",
+                    "+-------+------+",
+                    "| 3     | 1    |",
+                    "| 2     | 2    |",
+                    "+-------+------+",
+                ],
+            ),
+            (
+                true,
+                [
+                    "+------+-------+",
+                    "| hash | extra |",
+                    "+------+-------+",
+                    "| 1    | 3     |",
+                    "| 2    | 2     |",
+                    "+------+-------+",
+                ],
+            ),
+        ] {
+            let conf = builder
+                .clone()
+                .with_option(
+                    OPTION_KEY_OUTPUT_TABLE_COLUMN_ORDER,
+                    table_column_order.to_string(),
+                )
+                .build();
+            let provider = LakeSoulTableProvider::try_new(
+                &sess_ctx.state(),
+                client.clone(),
+                conf,
+                lakesoul_table.table_info(),
+                false,
+            )
+            .await?;
+            let plan = provider
+                .scan(&sess_ctx.state(), Some(&vec![2, 0]), &[], None)
+                .await?;
+            let names = plan
+                .schema()
+                .fields()
+                .iter()
+                .map(|field| field.name().clone())
+                .collect::<Vec<_>>();
+            match table_column_order {
+                true => assert_eq!(names, vec!["hash", "extra"]),
+                false => assert_eq!(names, vec!["extra", "hash"]),
+            }
+            assert_batches_eq(
+                table_name,
+                &expected,
+                &collect(plan, sess_ctx.task_ctx()).await?,
+            );
+        }
+        Ok(())
+    }
+
+    async fn test_count_rows_of_cdc_table() -> Result<()> {
+        let table_name = "count_rows_of_cdc_table";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        let batch = RecordBatch::try_from_iter([
+            (
+                "hash",
+                Arc::new(Int32Array::from(vec![1, 2, 3])) as ArrayRef,
+            ),
+            (
+                "value",
+                Arc::new(Int32Array::from(vec![10, 20, 30])) as ArrayRef,
+            ),
+            (
+                "rowKinds",
+                Arc::new(StringArray::from(vec!["insert", "delete", "insert"]))
+                    as ArrayRef,
+            ),
+        ])?;
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_schema(batch.schema())
+            .with_primary_keys(vec!["hash".to_string()])
+            .with_option(OPTION_KEY_CDC_COLUMN, "rowKinds");
+        create_table(client.clone(), table_name, builder.build()).await?;
+        execute_upsert(batch, table_name, client.clone()).await?;
+
+        // the file holds 3 rows, the deleted one is filtered out by the scan, so the row
+        // count of the file is only an upper bound and COUNT(*) scans the rows
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        let sess_ctx =
+            create_session_context(&mut LakeSoulIOConfigBuilder::new().build())?;
+        sess_ctx
+            .sql("SET datafusion.execution.collect_statistics = true")
+            .await?;
+        let dataframe = lakesoul_table.to_dataframe(&sess_ctx).await?;
+        let statistics = dataframe
+            .clone()
+            .create_physical_plan()
+            .await?
+            .statistics()?;
+        assert!(
+            !matches!(statistics.num_rows, Precision::Exact(_)),
+            "{:?}",
+            statistics.num_rows
+        );
+        assert_eq!(dataframe.count().await?, 2);
+        Ok(())
+    }
+
+    async fn test_delete_rows_with_delete_vectors() -> Result<()> {
+        let table_name = "delete_rows_with_delete_vectors";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_schema(create_batch_i32(vec!["hash", "value"], vec![&[], &[]]).schema())
+            .with_primary_keys(vec!["hash".to_string()]);
+        create_table(client.clone(), table_name, builder.build()).await?;
+        execute_upsert(
+            create_batch_i32(vec!["hash", "value"], vec![&[1, 2, 3], &[10, 20, 30]]),
+            table_name,
+            client.clone(),
+        )
+        .await?;
+        execute_upsert(
+            create_batch_i32(vec!["hash", "value"], vec![&[2, 3], &[21, 31]]),
+            table_name,
+            client.clone(),
+        )
+        .await?;
+
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        let partition_info = client
+            .get_all_partition_info(&lakesoul_table.table_info().table_id)
+            .await?;
+        let files = client
+            .get_data_files_of_single_partition(&partition_info[0])
+            .await?;
+        assert_eq!(files.len(), 2);
+        let sess_ctx =
+            create_session_context(&mut LakeSoulIOConfigBuilder::new().build())?;
+
+        // deleting the latest version of a row reads its previous version
+        let deleted = lakesoul_table
+            .delete_rows(&sess_ctx, &files[1], [0])
+            .await?;
+        assert_eq!(deleted, 1);
+        let result = lakesoul_table
+            .to_dataframe(&sess_ctx)
+            .await?
+            .collect()
+            .await?;
+        assert_batches_eq(
+            table_name,
+            &[
+                "+------+-------+",
+                "| hash | value |",
+                "+------+-------+",
+                "| 1    | 10    |",
+                "| 2    | 20    |",
+                "| 3    | 31    |",
+                "+------+-------+",
+            ],
+            &result,
+        );
+
+        // the rows are added to the rows already deleted from the file
+        lakesoul_table
+            .delete_rows(&sess_ctx, &files[0], [2])
+            .await?;
+        let deleted = lakesoul_table
+            .delete_rows(&sess_ctx, &files[1], [1])
+            .await?;
+        assert_eq!(deleted, 2);
+        let result = lakesoul_table
+            .to_dataframe(&sess_ctx)
+            .await?
+            .collect()
+            .await?;
+        assert_batches_eq(
+            table_name,
+            &[
+                "+------+-------+",
+                "| hash | value |",
+                "+------+-------+",
+                "| 1    | 10    |",
+                "| 2    | 20    |",
+                "+------+-------+",
+            ],
+            &result,
+        );
+        // the previous delete vector of a file is replaced
+        let partition_info = client
+            .get_all_partition_info(&lakesoul_table.table_info().table_id)
+            .await?;
+        let files = client
+            .get_data_files_of_single_partition(&partition_info[0])
+            .await?;
+        assert_eq!(files.len(), 4);
+        assert_eq!(
+            files.iter().filter(|path| is_delete_vector(path)).count(),
+            2
+        );
+        let result = lakesoul_table
+            .to_dataframe(&sess_ctx)
+            .await?
+            .filter(col("hash").gt(lit(1)))?
+            .collect()
+            .await?;
+        assert_batches_eq(
+            table_name,
+            &[
+                "+------+-------+",
+                "| hash | value |",
+                "+------+-------+",
+                "| 2    | 20    |",
+                "+------+-------+",
+            ],
+            &result,
+        );
+        Ok(())
+    }
+
+    async fn test_merge_on_write_with_concurrent_delete_vectors() -> Result<()> {
+        let table_name = "merge_on_write_with_concurrent_delete_vectors";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        let schema = create_batch_i32(vec!["hash", "value"], vec![&[], &[]]).schema();
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_schema(schema.clone())
+            .with_primary_keys(vec!["hash".to_string()]);
+        create_table(client.clone(), table_name, builder.build()).await?;
+        execute_upsert(
+            create_batch_i32(vec!["hash", "value"], vec![&[1, 2, 3], &[10, 20, 30]]),
+            table_name,
+            client.clone(),
+        )
+        .await?;
+
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        let table_info = lakesoul_table.table_info();
+        let partition_info = client.get_all_partition_info(&table_info.table_id).await?;
+        let files = client
+            .get_data_files_of_single_partition(&partition_info[0])
+            .await?;
+        assert_eq!(files.len(), 1);
+        let sess_ctx =
+            create_session_context(&mut LakeSoulIOConfigBuilder::new().build())?;
+        lakesoul_table
+            .delete_rows(&sess_ctx, &files[0], [0])
+            .await?;
+
+        // a delete which read the partition before the first one leaves a second delete
+        // vector of the file
+        let table_url = Url::parse(&table_info.table_path)
+            .map_err(|e| DataFusionError::External(Box::new(e)))?;
+        let path = delete_vector_path(&files[0]);
+        let (object_store_url, location) = resolve_file_url(&path, &table_url)?;
+        let store = sess_ctx.runtime_env().object_store(&object_store_url)?;
+        let size = write_delete_vector(store.as_ref(), &location, &[2]).await?;
+        client
+            .commit_data_commit_info(DataCommitInfo {
+                table_id: table_info.table_id.clone(),
+                partition_desc: partition_info[0].partition_desc.clone(),
+                commit_id: {
+                    let (high, low) = Uuid::new_v4().as_u64_pair();
+                    Some(proto::proto::entity::Uuid { high, low })
+                },
+                file_ops: vec![DataFileOp {
+                    path,
+                    file_op: FileOp::Add.into(),
+                    size: size as i64,
+                    ..Default::default()
+                }],
+                commit_op: CommitOp::AppendCommit.into(),
+                timestamp: Utc::now().timestamp_millis(),
+                committed: false,
+                domain: table_info.domain.clone(),
+            })
+            .await?;
+
+        // the rows of both delete vectors are deleted
+        let expected = [
+            "+------+-------+",
+            "| hash | value |",
+            "+------+-------+",
+            "| 2    | 20    |",
+            "+------+-------+",
+        ];
+        let result = lakesoul_table
+            .to_dataframe(&sess_ctx)
+            .await?
+            .collect()
+            .await?;
+        assert_batches_eq(table_name, &expected, &result);
+
+        // a delete reading the latest version replaces both delete vectors
+        let deleted = lakesoul_table
+            .delete_rows(&sess_ctx, &files[0], [1])
+            .await?;
+        assert_eq!(deleted, 3);
+        let files = client
+            .get_data_files_by_table_name(table_name, "default")
+            .await?;
+        assert_eq!(
+            files.iter().filter(|path| is_delete_vector(path)).count(),
+            1
+        );
+
+        // the deleted rows are dropped by the merge on write, superseding the delete vector
+        let input = MemorySourceConfig::try_new_exec(
+            &[vec![create_batch_i32(
+                vec!["hash", "value"],
+                vec![&[2, 4], &[21, 40]],
+            )]],
+            schema,
+            None,
+        )?;
+        let sink =
+            LakeSoulHashSinkExec::new(input, None, table_info.clone(), client.clone())
+                .await?
+                .with_write_id("merged")
+                .with_merge_on_write(true);
+        collect(Arc::new(sink), SessionContext::new().task_ctx()).await?;
+        let files = client
+            .get_data_files_by_table_name(table_name, "default")
+            .await?;
+        assert!(
+            !files.iter().any(|path| is_delete_vector(path)),
+            "{files:?}"
+        );
+        let result = LakeSoulTable::for_name(table_name)
+            .await?
+            .to_dataframe(&sess_ctx)
+            .await?
+            .collect()
+            .await?;
+        assert_batches_eq(
+            table_name,
+            &[
+                "+------+-------+",
+                "| hash | value |",
+                "+------+-------+",
+                "| 2    | 21    |",
+                "| 4    | 40    |",
+                "+------+-------+",
+            ],
+            &result,
+        );
+        Ok(())
+    }
+
+    async fn test_merge_partial_updates_with_full_rows() -> Result<()> {
+        let table_name = "merge_partial_updates_with_full_rows";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        let names = || vec!["hash", "a", "b"];
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_schema(create_batch_i32(names(), vec![&[], &[], &[]]).schema())
+            .with_primary_keys(vec!["hash".to_string()]);
+        create_table(client.clone(), table_name, builder.build()).await?;
+        // full rows and partial updates, with the columns they do not update left null
+        for batch in [
+            create_batch_i32(names(), vec![&[1, 2, 3], &[10, 20, 30], &[100, 200, 300]]),
+            create_batch_optional_i32(
+                names(),
+                vec![&[Some(1), Some(2)], &[Some(11), None], &[None, Some(201)]],
+            ),
+            create_batch_i32(names(), vec![&[3], &[33], &[333]]),
+            create_batch_optional_i32(names(), vec![&[Some(3)], &[None], &[Some(334)]]),
+        ] {
+            execute_upsert(batch, table_name, client.clone()).await?;
+        }
+
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        for (strategy, expected) in [
+            (
+                MergeStrategy::LastWriteWins,
+                [
+                    "+------+----+-----+",
+                    "| hash | a  | b   |",
+                    "+------+----+-----+",
+                    "| 1    | 11 |     |",
+                    "| 2    |    | 201 |",
+                    "| 3    |    | 334 |",
+                    "+------+----+-----+",
+                ],
+            ),
+            (
+                MergeStrategy::PartialUpdate,
+                [
+                    "+------+----+-----+",
+                    "| hash | a  | b   |",
+                    "+------+----+-----+",
+                    "| 1    | 11 | 100 |",
+                    "| 2    | 20 | 201 |",
+                    "| 3    | 33 | 334 |",
+                    "+------+----+-----+",
+                ],
+            ),
+        ] {
+            let builder = create_io_config_builder(
+                client.clone(),
+                Some(table_name),
+                true,
+                "default",
+                Default::default(),
+                Default::default(),
+            )
+            .await?
+            .with_merge_strategy(strategy);
+            let sess_ctx = create_session_context(&mut builder.clone().build())?;
+            let provider = LakeSoulTableProvider::try_new(
+                &sess_ctx.state(),
+                client.clone(),
+                builder.build(),
+                lakesoul_table.table_info(),
+                false,
+            )
+            .await?;
+            let batches = sess_ctx.read_table(Arc::new(provider))?.collect().await?;
+            assert_batches_eq(table_name, &expected, &batches);
+        }
+        Ok(())
+    }
+
+    async fn test_merge_partial_updates_of_table_property() -> Result<()> {
+        let table_name = "merge_partial_updates_of_table_property";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        let names = || vec!["hash", "a", "b"];
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_schema(create_batch_i32(names(), vec![&[], &[], &[]]).schema())
+            .with_primary_keys(vec!["hash".to_string()])
+            .with_merge_strategy(MergeStrategy::PartialUpdate);
+        create_table(client.clone(), table_name, builder.build()).await?;
+        for batch in [
+            create_batch_i32(names(), vec![&[1, 2], &[10, 20], &[100, 200]]),
+            create_batch_optional_i32(
+                names(),
+                vec![&[Some(1), Some(2)], &[Some(11), None], &[None, Some(201)]],
+            ),
+        ] {
+            execute_upsert(batch, table_name, client.clone()).await?;
+        }
+
+        // the strategy of the table applies without the option set in the session
+        let builder = create_io_config_builder(
+            client,
+            None,
+            false,
+            "default",
+            Default::default(),
+            Default::default(),
+        )
+        .await?;
+        let sess_ctx = create_session_context(&mut builder.build())?;
+        let result = LakeSoulTable::for_name(table_name)
+            .await?
+            .to_dataframe(&sess_ctx)
+            .await?
+            .collect()
+            .await?;
+        assert_batches_eq(
+            table_name,
+            &[
+                "+------+----+-----+",
+                "| hash | a  | b   |",
+                "+------+----+-----+",
+                "| 1    | 11 | 100 |",
+                "| 2    | 20 | 201 |",
+                "+------+----+-----+",
+            ],
+            &result,
+        );
+        Ok(())
+    }
+
+    async fn test_merge_files_of_different_compressions() -> Result<()> {
+        let table_name = "merge_files_of_different_compressions";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        let names = || vec!["range", "hash", "value"];
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_schema(create_batch_i32(names(), vec![&[], &[], &[]]).schema())
+            .with_primary_keys(vec!["hash".to_string()])
+            .with_range_partitions(vec!["range".to_string()]);
+        create_table(client.clone(), table_name, builder.build()).await?;
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+
+        // the files of the same partition and bucket are written with different codecs
+        for (compression, batch) in [
+            (
+                "SNAPPY",
+                create_batch_i32(names(), vec![&[1, 1, 1], &[1, 2, 3], &[1, 2, 3]]),
+            ),
+            (
+                "ZSTD(3)",
+                create_batch_i32(names(), vec![&[1, 1], &[3, 4], &[33, 44]]),
+            ),
+        ] {
+            let schema = batch.schema();
+            let input = MemorySourceConfig::try_new_exec(&[vec![batch]], schema, None)?;
+            let sink = LakeSoulHashSinkExec::new(
+                input,
+                None,
+                lakesoul_table.table_info(),
+                client.clone(),
+            )
+            .await?
+            .with_write_option(OPTION_KEY_PARQUET_COMPRESSION, compression);
+            collect(Arc::new(sink), SessionContext::new().task_ctx()).await?;
+        }
+
+        // the codec of each file is recorded into its statistics
+        let mut compressions = client
+            .get_file_statistics_by_table_id(&lakesoul_table.table_info().table_id)
+            .await?
+            .iter()
+            .map(|stored| {
+                let stored =
+                    serde_json::from_str::<StoredFileStatistics>(&stored.statistics)?;
+                Ok(stored.compression)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        compressions.sort();
+        assert_eq!(
+            compressions,
+            vec![Some("SNAPPY".to_string()), Some("ZSTD".to_string())]
+        );
+
+        // the merge on read decodes each file by its own codec
+        let builder = create_io_config_builder(
+            client,
+            None,
+            false,
+            "default",
+            Default::default(),
+            Default::default(),
+        )
+        .await?;
+        let sess_ctx = create_session_context(&mut builder.clone().build())?;
+        let batches = lakesoul_table
+            .to_dataframe(&sess_ctx)
+            .await?
+            .select_columns(&names())?
+            .collect()
+            .await?;
+        assert_batches_eq(
+            table_name,
+            &[
+                "+-------+------+-------+",
+                "| range | hash | value |",
+                "+-------+------+-------+",
+                "| 1     | 1    | 1     |",
+                "| 1     | 2    | 2     |",
+                "| 1     | 3    | 33    |",
+                "| 1     | 4    | 44    |",
+                "+-------+------+-------+",
+            ],
+            &batches,
+        );
+        Ok(())
+    }
+
+    async fn test_merge_files_of_different_timestamp_units() -> Result<()> {
+        let table_name = "merge_files_of_different_timestamp_units";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        let micros = |ts: &str| {
+            NaiveDateTime::parse_from_str(ts, "%Y-%m-%dT%H:%M:%S%.f")
+                .unwrap()
+                .and_utc()
+                .timestamp_micros()
+        };
+        let batch = create_batch_i32_and_timestamp(
+            vec!["hash", "value", "timestamp"],
+            vec![&[1, 2], &[1, 2]],
+            vec![
+                micros("2024-01-01T00:00:00.123"),
+                micros("2024-01-02T00:00:00.456"),
+            ],
+        );
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_schema(batch.schema())
+            .with_primary_keys(vec!["hash".to_string()])
+            .with_option(OPTION_KEY_HASH_BUCKET_NUM, "1");
+        create_table(client.clone(), table_name, builder.build()).await?;
+        execute_upsert(batch, table_name, client.clone()).await?;
+
+        // another engine wrote the later versions of the rows with millisecond timestamps
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        let table_info = lakesoul_table.table_info();
+        let partition_info = client.get_all_partition_info(&table_info.table_id).await?;
+        let files = client
+            .get_data_files_of_single_partition(&partition_info[0])
+            .await?;
+        assert_eq!(files.len(), 1);
+        let (dir, _) = files[0].rsplit_once('/').unwrap();
+        let path = format!("{}/part-external_0000.parquet", dir);
+        let external = RecordBatch::try_from_iter([
+            ("hash", Arc::new(Int32Array::from(vec![2, 3])) as ArrayRef),
+            (
+                "value",
+                Arc::new(Int32Array::from(vec![22, 33])) as ArrayRef,
+            ),
+            (
+                "timestamp",
+                Arc::new(TimestampMillisecondArray::from(vec![
+                    micros("2024-02-02T00:00:00.789") / 1000,
+                    micros("2024-02-03T00:00:00.012") / 1000,
+                ])) as ArrayRef,
+            ),
+        ])?;
+        let file_path = Url::parse(&path)
+            .ok()
+            .and_then(|url| url.to_file_path().ok())
+            .unwrap();
+        let mut writer = ArrowWriter::try_new(
+            std::fs::File::create(&file_path)?,
+            external.schema(),
+            None,
+        )?;
+        writer.write(&external)?;
+        writer.close()?;
+        client
+            .commit_data_commit_info(DataCommitInfo {
+                table_id: table_info.table_id.clone(),
+                partition_desc: partition_info[0].partition_desc.clone(),
+                commit_id: {
+                    let (high, low) = Uuid::new_v4().as_u64_pair();
+                    Some(proto::proto::entity::Uuid { high, low })
+                },
+                file_ops: vec![DataFileOp {
+                    path,
+                    file_op: FileOp::Add.into(),
+                    size: std::fs::metadata(&file_path)?.len() as i64,
+                    ..Default::default()
+                }],
+                commit_op: CommitOp::AppendCommit.into(),
+                timestamp: Utc::now().timestamp_millis(),
+                committed: false,
+                domain: table_info.domain.clone(),
+            })
+            .await?;
+
+        // the table provider merges the files once their timestamps are coerced into the
+        // unit of the table
+        let builder = create_io_config_builder(
+            client.clone(),
+            Some(table_name),
+            true,
+            "default",
+            Default::default(),
+            Default::default(),
+        )
+        .await?
+        .with_coerce_timestamp_unit(TimeUnit::Microsecond);
+        let sess_ctx = create_session_context(&mut builder.clone().build())?;
+        let provider = LakeSoulTableProvider::try_new(
+            &sess_ctx.state(),
+            client,
+            builder.build(),
+            table_info,
+            false,
+        )
+        .await?;
+        let batches = sess_ctx
+            .read_table(Arc::new(provider))?
+            .select_columns(&["hash", "value", "timestamp"])?
+            .collect()
+            .await?;
+        assert_batches_eq(
+            table_name,
+            &[
+                "+------+-------+-------------------------+",
+                "| hash | value | timestamp               |",
+                "+------+-------+-------------------------+",
+                "| 1    | 1     | 2024-01-01T00:00:00.123 |",
+                "| 2    | 22    | 2024-02-02T00:00:00.789 |",
+                "| 3    | 33    | 2024-02-03T00:00:00.012 |",
+                "+------+-------+-------------------------+",
+            ],
+            &batches,
+        );
+        Ok(())
+    }
+
+    async fn test_merge_by_sequence_column_of_table() -> Result<()> {
+        let table_name = "merge_by_sequence_column_of_table";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        let names = || vec!["hash", "seq", "value"];
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_schema(create_batch_i32(names(), vec![&[], &[], &[]]).schema())
+            .with_primary_keys(vec!["hash".to_string()])
+            .with_sequence_column("seq");
+        create_table(client.clone(), table_name, builder.build()).await?;
+        execute_upsert(
+            create_batch_i32(names(), vec![&[1, 2], &[5, 1], &[10, 20]]),
+            table_name,
+            client.clone(),
+        )
+        .await?;
+        // the later version of the first key has a lower sequence
+        execute_upsert(
+            create_batch_i32(names(), vec![&[1, 2], &[3, 4], &[11, 21]]),
+            table_name,
+            client.clone(),
+        )
+        .await?;
+
+        // the session does not set the sequence column, the one of the table applies
+        let lakesoul_table = LakeSoulTable::for_name(table_name).await?;
+        let builder = create_io_config_builder(
+            client,
+            None,
+            false,
+            "default",
+            Default::default(),
+            Default::default(),
+        )
+        .await?;
+        let sess_ctx = create_session_context(&mut builder.build())?;
+        let result = lakesoul_table
+            .to_dataframe(&sess_ctx)
+            .await?
+            .sort(vec![col("hash").sort(true, true)])?
+            .collect()
+            .await?;
+        assert_batches_eq(
+            table_name,
+            &[
+                "+------+-----+-------+",
+                "| hash | seq | value |",
+                "+------+-----+-------+",
+                "| 1    | 5   | 10    |",
+                "| 2    | 4   | 21    |",
+                "+------+-----+-------+",
+            ],
+            &result,
+        );
+        Ok(())
+    }
+    async fn test_scan_substrait_plan_round_trip() -> Result<()> {
+        let table_name = "scan_substrait_plan_round_trip";
+        let client = Arc::new(MetaDataClient::from_env().await?);
+        let batch =
+            create_batch_i32(vec!["hash", "value"], vec![&[1, 2, 3], &[10, 20, 30]]);
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_schema(batch.schema())
+            .with_primary_keys(vec!["hash".to_string()]);
+        create_table(client.clone(), table_name, builder.build()).await?;
+        execute_upsert(batch, table_name, client.clone()).await?;
+        execute_upsert(
+            create_batch_i32(vec!["hash", "value"], vec![&[2], &[200]]),
+            table_name,
+            client.clone(),
+        )
+        .await?;
+
+        let state =
+            create_session_context(&mut LakeSoulIOConfigBuilder::new().build())?.state();
+        // the plan is exchanged as protobuf bytes, like with a planner of other languages
+        let round_trip = |scan: LakeSoulScan| {
+            let client = client.clone();
+            let state = state.clone();
+            async move {
+                let plan = scan.to_substrait_plan(client.clone(), &state).await?;
+                let plan = Plan::decode(plan.encode_to_vec().as_slice())
+                    .map_err(|e| DataFusionError::External(Box::new(e)))?;
+                let scan =
+                    LakeSoulScan::from_substrait_plan(client, &state, &plan).await?;
+                Ok::<_, LakeSoulError>((plan, scan))
+            }
+        };
+        let read = |scan: LakeSoulScan| {
+            let client = client.clone();
+            let state = state.clone();
+            async move {
+                let plan = scan.create_physical_plan(client, &state).await?;
+                Ok::<_, LakeSoulError>(collect(plan, state.task_ctx()).await?)
+            }
+        };
+
+        // the rows are merged by the primary key before the filter is applied
+        let scan = LakeSoulScan::new(format!("default.{}", table_name))
+            .with_projection(Some(vec![1]))
+            .with_filters(vec![col("hash").gt(lit(1))]);
+        let (mut plan, scan) = round_trip(scan).await?;
+        assert_eq!(scan.projection, Some(vec![1]));
+        assert_eq!(scan.snapshot, None);
+        assert_batches_eq(
+            table_name,
+            &[
+                "+-------+",
+                "| value |",
+                "+-------+",
+                "| 200   |",
+                "| 30    |",
+                "+-------+",
+            ],
+            &read(scan.clone()).await?,
+        );
+        let snapshot_scan = scan.with_snapshot(Some(TableSnapshot::Version(0)));
+        let (_, snapshot_scan) = round_trip(snapshot_scan).await?;
+        assert_eq!(snapshot_scan.snapshot, Some(TableSnapshot::Version(0)));
+        assert_batches_eq(
+            table_name,
+            &[
+                "+-------+",
+                "| value |",
+                "+-------+",
+                "| 20    |",
+                "| 30    |",
+                "+-------+",
+            ],
+            &read(snapshot_scan).await?,
+        );
+
+        // a read described with another merge than the one of the table is rejected
+        let Some(PlanRelType::Root(root)) = &mut plan.relations[0].rel_type else {
+            unreachable!()
+        };
+        let Some(RelType::Read(read_rel)) = &mut root.input.as_mut().unwrap().rel_type
+        else {
+            unreachable!()
+        };
+        let enhancement = read_rel
+            .advanced_extension
+            .as_mut()
+            .and_then(|extension| extension.enhancement.as_mut())
+            .unwrap();
+        let extension: LakeSoulReadExtension =
+            serde_json::from_slice(&enhancement.value)?;
+        assert_eq!(extension.primary_keys, vec!["hash".to_string()]);
+        let append_only = LakeSoulReadExtension {
+            primary_keys: vec![],
+            ..extension
+        };
+        enhancement.value = serde_json::to_vec(&append_only)?.into();
+        let err = LakeSoulScan::from_substrait_plan(client.clone(), &state, &plan)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("merges by the primary keys []"));
+        Ok(())
+    }
+
     async fn test_merge_one_file_with_empty_batch_i32() -> Result<()> {
         let table_name = "merge_one_file_with_empty_batch";
         let client = Arc::new(MetaDataClient::from_env().await?);
@@ -1977,6 +3308,22 @@ mod upsert_with_metadata_tests {
         test_merge_different_column_i32().await?;
         test_merge_different_columns_and_filter_by_non_selected_columns_i32().await?;
         test_merge_different_columns_and_filter_partial_rows_i32().await?;
+        test_merge_and_filter_updated_rows_by_non_primary_key_i32().await?;
+        test_read_change_feed_between_versions_i32().await?;
+        test_read_change_feed_of_cdc_table().await?;
+        test_select_non_cdc_columns_of_cdc_table().await?;
+        test_read_cdc_table_with_delete_markers().await?;
+        test_validate_scan_schema_of_cdc_table().await?;
+        test_scan_with_non_monotonic_projection_i32().await?;
+        test_count_rows_of_cdc_table().await?;
+        test_delete_rows_with_delete_vectors().await?;
+        test_merge_on_write_with_concurrent_delete_vectors().await?;
+        test_merge_partial_updates_with_full_rows().await?;
+        test_merge_partial_updates_of_table_property().await?;
+        test_merge_files_of_different_compressions().await?;
+        test_merge_files_of_different_timestamp_units().await?;
+        test_merge_by_sequence_column_of_table().await?;
+        test_scan_substrait_plan_round_trip().await?;
         test_merge_one_file_with_empty_batch_i32().await?;
         test_merge_multi_files_with_empty_batch_i32().await?;
         test_upsert_without_range_partitions_i32().await?;
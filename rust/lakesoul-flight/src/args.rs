@@ -21,6 +21,14 @@ pub struct Args {
     #[arg(long, default_value = "100.0")]
     pub throughput_limit: String,
 
+    /// 流写入提交合并间隔（毫秒），0 表示每次写入后立即提交
+    #[arg(long, default_value = "0")]
+    pub commit_interval_ms: u64,
+
+    /// 流写入提交合并的累计文件大小阈值（字节）
+    #[arg(long, default_value = "134217728")]
+    pub commit_max_bytes: u64,
+
     #[command(flatten)]
     pub core: CoreArgs,
 }
@@ -32,6 +32,7 @@ use datafusion::sql::sqlparser::dialect::PostgreSqlDialect;
 use futures::{Stream, StreamExt, TryStreamExt};
 use lakesoul_datafusion::catalog::LakeSoulTableProperty;
 use lakesoul_datafusion::lakesoul_table::LakeSoulTable;
+use lakesoul_datafusion::lakesoul_table::commit_coalescer::CommitCoalescer;
 use lakesoul_datafusion::lakesoul_table::helpers::case_fold_column_name;
 use lakesoul_datafusion::serialize::arrow_java::schema_from_metadata_str;
 use lakesoul_io::helpers::get_batch_memory_size;
@@ -209,6 +210,8 @@ pub struct FlightSqlServiceImpl {
     transactional_data: Arc<DashMap<String, TransactionalData>>,
     /// The metrics of the stream write operation.
     metrics: Arc<StreamWriteMetrics>,
+    /// The coalescer of stream write commits, if enabled.
+    commit_coalescer: Option<Arc<CommitCoalescer>>,
     /// The jwt server for authentication.
     jwt_server: Arc<JwtServer>,
     /// The auth switch.
@@ -847,6 +850,11 @@ impl FlightSqlService for FlightSqlServiceImpl {
                     Status::internal(format!("Invalid transaction ID: {}", e))
                 })?;
             self.append_transactional_data(transaction_id, table, flush_result)?;
+        } else if let Some(commit_coalescer) = &self.commit_coalescer {
+            commit_coalescer
+                .append(table, flush_result)
+                .await
+                .map_err(lakesoul_error_to_status)?;
         } else {
             table
                 .commit_flush_result(flush_result)
@@ -1236,6 +1244,25 @@ impl FlightSqlServiceImpl {
 
         let ctx = create_lakesoul_session_ctx(metadata_client.clone(), &args.core)?;
 
+        let commit_coalescer = (args.commit_interval_ms > 0).then(|| {
+            let commit_coalescer = Arc::new(CommitCoalescer::new(
+                std::time::Duration::from_millis(args.commit_interval_ms),
+                args.commit_max_bytes,
+            ));
+            // commit the buffered results of idle streams once their interval elapsed
+            let coalescer = commit_coalescer.clone();
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(coalescer.interval());
+                loop {
+                    ticker.tick().await;
+                    if let Err(e) = coalescer.flush_expired().await {
+                        error!("commit coalesced stream writes failed: {}", e);
+                    }
+                }
+            });
+            commit_coalescer
+        });
+
         Ok(FlightSqlServiceImpl {
             client: metadata_client,
             args,
@@ -1246,6 +1273,7 @@ impl FlightSqlServiceImpl {
             auth_enabled,
             rbac_enabled,
             metrics: Arc::new(StreamWriteMetrics::new(throughput_limit)),
+            commit_coalescer,
             counter: DashMap::new(),
         })
     }
@@ -0,0 +1,112 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Compares the throughput of the merge on read of a primary key table with the batch
+//! size of the session, kept small, and with a larger merge batch size.
+//!
+//! Each file updates a disjoint part of the keys of the base file, so that the merge
+//! emits many small groups of matching rows. The merged batches are aggregated one by one
+//! like a downstream operator would do.
+
+use std::sync::Arc;
+
+use arrow::compute::sum;
+use arrow_array::{ArrayRef, Int64Array, RecordBatch, StringArray};
+use datafusion_common::Result;
+use lakesoul_io::lakesoul_io_config::LakeSoulIOConfigBuilder;
+use lakesoul_io::lakesoul_reader::LakeSoulReader;
+use parquet::arrow::ArrowWriter;
+use tokio::runtime::Builder;
+use tokio::time::Instant;
+
+const NUM_KEYS: i64 = 2_000_000;
+const NUM_FILES: i64 = 8;
+const SESSION_BATCH_SIZE: usize = 64;
+
+fn create_batch(keys: Vec<i64>, version: i64) -> RecordBatch {
+    let values = keys.iter().map(|k| k * version).collect::<Vec<_>>();
+    let names = keys
+        .iter()
+        .map(|k| format!("name_{}_{}", k, version))
+        .collect::<Vec<_>>();
+    RecordBatch::try_from_iter([
+        ("pk", Arc::new(Int64Array::from(keys)) as ArrayRef),
+        ("value", Arc::new(Int64Array::from(values)) as ArrayRef),
+        ("name", Arc::new(StringArray::from(names)) as ArrayRef),
+    ])
+    .unwrap()
+}
+
+fn write_file(path: &std::path::Path, batch: &RecordBatch) -> Result<String> {
+    let mut writer =
+        ArrowWriter::try_new(std::fs::File::create(path)?, batch.schema(), None)?;
+    writer.write(batch)?;
+    writer.close()?;
+    Ok(path.to_str().unwrap().to_string())
+}
+
+/// Reads the merged rows of the files and returns the number of batches, the number of
+/// rows and the elapsed milliseconds.
+async fn read_merged(
+    files: Vec<String>,
+    batch: &RecordBatch,
+    merge_batch_size: Option<usize>,
+) -> Result<(usize, usize, u128)> {
+    let mut builder = LakeSoulIOConfigBuilder::new()
+        .with_files(files)
+        .with_thread_num(2)
+        .with_batch_size(SESSION_BATCH_SIZE)
+        .with_schema(batch.schema())
+        .with_primary_keys(vec!["pk".to_string()]);
+    if let Some(merge_batch_size) = merge_batch_size {
+        builder = builder.with_merge_batch_size(merge_batch_size);
+    }
+    let mut reader = LakeSoulReader::new(builder.build())?;
+    let start = Instant::now();
+    reader.start().await?;
+    let (mut num_batches, mut num_rows, mut total) = (0, 0, 0i64);
+    while let Some(batch) = reader.next_rb().await {
+        let batch = batch?;
+        num_batches += 1;
+        num_rows += batch.num_rows();
+        let values = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        total = total.wrapping_add(sum(values).unwrap_or_default());
+    }
+    let elapsed = start.elapsed().as_millis();
+    println!("checksum of the merged values: {}", total);
+    Ok((num_batches, num_rows, elapsed))
+}
+
+fn main() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let base = create_batch((0..NUM_KEYS).collect(), 1);
+    let mut files = vec![write_file(&temp_dir.path().join("base.parquet"), &base)?];
+    for i in 0..NUM_FILES {
+        // every other key of the i-th slice of the keys
+        let keys = (0..NUM_KEYS)
+            .filter(|k| k % NUM_FILES == i && k % 2 == 0)
+            .collect::<Vec<_>>();
+        let path = temp_dir.path().join(format!("update_{}.parquet", i));
+        files.push(write_file(&path, &create_batch(keys, i + 2))?);
+    }
+
+    let runtime = Builder::new_multi_thread().enable_all().build().unwrap();
+    for merge_batch_size in [None, Some(8192)] {
+        let (num_batches, num_rows, elapsed) =
+            runtime.block_on(read_merged(files.clone(), &base, merge_batch_size))?;
+        println!(
+            "merge batch size {:?}: {} rows in {} batches, {} rows/s ({}ms)",
+            merge_batch_size.unwrap_or(SESSION_BATCH_SIZE),
+            num_rows,
+            num_batches,
+            num_rows as u128 * 1000 / elapsed.max(1),
+            elapsed
+        );
+    }
+    Ok(())
+}
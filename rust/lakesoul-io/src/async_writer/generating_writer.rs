@@ -0,0 +1,79 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Computes the generated columns of the written batches before writing them, see
+//! [`crate::lakesoul_io_config::LakeSoulIOConfigBuilder::with_generated_column`].
+
+use std::collections::HashMap;
+
+use arrow_array::RecordBatch;
+use arrow_schema::SchemaRef;
+use datafusion_common::Result;
+
+use super::{AsyncBatchWriter, WriterFlushResult};
+use crate::helpers::generate_batch_columns;
+
+/// Wrap an async writer to compute the generated columns of each written batch, e.g. the
+/// range partition columns partitioning the batches in a [`super::PartitioningAsyncWriter`].
+pub struct GeneratingAsyncWriter {
+    /// The inner writer of the batches with their generated columns.
+    inner: Box<dyn AsyncBatchWriter + Send>,
+    /// The SQL expressions of the generated columns by their names.
+    generated_columns: HashMap<String, String>,
+    /// The schema of the written batches, giving the types and order of the columns.
+    target_schema: SchemaRef,
+}
+
+impl GeneratingAsyncWriter {
+    pub fn new(
+        inner: Box<dyn AsyncBatchWriter + Send>,
+        generated_columns: HashMap<String, String>,
+        target_schema: SchemaRef,
+    ) -> Self {
+        Self {
+            inner,
+            generated_columns,
+            target_schema,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AsyncBatchWriter for GeneratingAsyncWriter {
+    async fn write_record_batch(&mut self, batch: RecordBatch) -> Result<()> {
+        let batch =
+            generate_batch_columns(&batch, &self.generated_columns, &self.target_schema)?;
+        // the appended generated columns are moved to their place in the target schema
+        let schema = batch.schema();
+        let projection = self
+            .target_schema
+            .fields()
+            .iter()
+            .filter_map(|field| schema.index_of(field.name()).ok())
+            .collect::<Vec<_>>();
+        self.inner
+            .write_record_batch(batch.project(&projection)?)
+            .await
+    }
+
+    async fn flush_and_close(self: Box<Self>) -> Result<WriterFlushResult> {
+        self.inner.flush_and_close().await
+    }
+
+    async fn abort_and_close(self: Box<Self>) -> Result<()> {
+        self.inner.abort_and_close().await
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.inner.schema()
+    }
+
+    fn buffered_size(&self) -> u64 {
+        self.inner.buffered_size()
+    }
+
+    fn memory_size(&self) -> u64 {
+        self.inner.memory_size()
+    }
+}
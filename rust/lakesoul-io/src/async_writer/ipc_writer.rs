@@ -0,0 +1,205 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Implementation of the Arrow IPC writer.
+
+use std::{collections::VecDeque, sync::Arc};
+
+use arrow::ipc::writer::StreamWriter;
+use arrow_array::RecordBatch;
+use arrow_schema::SchemaRef;
+use atomic_refcell::AtomicRefCell;
+use datafusion::{datasource::listing::ListingTableUrl, execution::TaskContext};
+use datafusion_common::{DataFusionError, Result, project_schema};
+use object_store::{ObjectStore, WriteMultipart, path::Path};
+use parquet::format::FileMetaData;
+use url::Url;
+
+use crate::{
+    constant::TBD_PARTITION_DESC,
+    helpers::get_batch_memory_size,
+    lakesoul_io_config::{LakeSoulIOConfig, create_session_context},
+    transform::{uniform_record_batch, uniform_schema},
+};
+
+use super::{
+    AsyncBatchWriter, InMemBuf, MultiPartAsyncWriter, UploadDigest, WriterFlushResult,
+    multipart_writer::{MULTIPART_CHUNK_SIZE, start_multipart_upload},
+};
+
+/// An async writer streaming record batches into an Arrow IPC stream file
+/// with object_store's multi-part upload, the same way as [`MultiPartAsyncWriter`]
+/// does for parquet files.
+///
+/// The encoded messages of each written batch are drained from the in-memory buffer into
+/// the upload right away, so only the batch being encoded is held in memory.
+pub struct ArrowIpcAsyncWriter {
+    /// The in-memory buffer of the ipc async writer.
+    in_mem_buf: InMemBuf,
+    /// The schema of the ipc async writer.
+    schema: SchemaRef,
+    /// The multi-part writer of [`object_store::WriteMultipart`] that is used to upload the data to the object store asynchronously.
+    writer: WriteMultipart,
+    /// The [`StreamWriter`] encoding the batches into the in-memory buffer.
+    ipc_writer: StreamWriter<InMemBuf>,
+    /// The object store of the ipc async writer.
+    object_store: Arc<dyn ObjectStore>,
+    /// The absolute path of the ipc async writer.
+    absolute_path: String,
+    /// The number of rows of the ipc async writer.
+    num_rows: u64,
+    buffered_size: u64,
+    /// The size and content hash of the encoded bytes passed to the upload.
+    digest: UploadDigest,
+}
+
+impl ArrowIpcAsyncWriter {
+    pub async fn try_new_with_context(
+        config: &mut LakeSoulIOConfig,
+        task_context: Arc<TaskContext>,
+    ) -> Result<Self> {
+        if config.files.is_empty() {
+            return Err(DataFusionError::Internal(
+                "wrong number of file names provided for writer".to_string(),
+            ));
+        }
+        let file_name = &config
+            .files
+            .last()
+            .ok_or(DataFusionError::Internal("wrong file name".to_string()))?;
+        let (object_store, _, write_multi_part) =
+            start_multipart_upload(config, file_name, &task_context).await?;
+
+        let in_mem_buf =
+            InMemBuf(Arc::new(AtomicRefCell::new(VecDeque::<u8>::with_capacity(
+                16 * 1024, // 16kb
+            ))));
+        let schema = uniform_schema(config.target_schema.0.clone());
+        let keep_partition_columns = config.keep_partition_columns();
+        let schema_projection_excluding_range = schema
+            .fields()
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, field)| {
+                match !keep_partition_columns
+                    && config.range_partitions.contains(field.name())
+                {
+                    true => None,
+                    false => Some(idx),
+                }
+            })
+            .collect::<Vec<_>>();
+        let writer_schema =
+            project_schema(&schema, Some(&schema_projection_excluding_range))?;
+        let ipc_writer = StreamWriter::try_new(in_mem_buf.clone(), &writer_schema)?;
+
+        Ok(ArrowIpcAsyncWriter {
+            in_mem_buf,
+            schema,
+            writer: write_multi_part,
+            ipc_writer,
+            object_store,
+            absolute_path: file_name.to_string(),
+            num_rows: 0,
+            buffered_size: 0,
+            digest: UploadDigest::new(),
+        })
+    }
+
+    pub async fn try_new(mut config: LakeSoulIOConfig) -> Result<Self> {
+        let task_context = create_session_context(&mut config)?.task_ctx();
+        Self::try_new_with_context(&mut config, task_context).await
+    }
+
+    /// Move the encoded bytes of the in-memory buffer into the upload.
+    async fn drain_buffer(&mut self) -> Result<()> {
+        let mut v = self
+            .in_mem_buf
+            .0
+            .try_borrow_mut()
+            .map_err(|e| DataFusionError::Internal(format!("{:?}", e)))?;
+        if !v.is_empty() {
+            MultiPartAsyncWriter::write_part(&mut self.writer, &mut v, &mut self.digest)
+                .await
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn nun_rows(&self) -> u64 {
+        self.num_rows
+    }
+
+    pub fn absolute_path(&self) -> String {
+        self.absolute_path.clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl AsyncBatchWriter for ArrowIpcAsyncWriter {
+    async fn write_record_batch(&mut self, batch: RecordBatch) -> Result<()> {
+        let batch = uniform_record_batch(batch)?;
+        self.num_rows += batch.num_rows() as u64;
+        self.buffered_size += get_batch_memory_size(&batch)? as u64;
+        self.ipc_writer.write(&batch)?;
+        self.drain_buffer().await
+    }
+
+    async fn flush_and_close(self: Box<Self>) -> Result<WriterFlushResult> {
+        let mut this = *self;
+        // write the end of stream marker
+        this.ipc_writer.finish()?;
+        this.drain_buffer().await?;
+        // shutdown multi-part async writer to complete the upload,
+        // the upload is aborted if the completion still fails after all retries
+        this.writer.finish().await?;
+        let file_path = this.absolute_path.clone();
+        let path = Path::from_url_path(
+            <ListingTableUrl as AsRef<Url>>::as_ref(&ListingTableUrl::parse(&file_path)?)
+                .path(),
+        )?;
+        let object_meta = this.object_store.head(&path).await?;
+        // an ipc file has no parquet footer, only the row count is reported
+        let mut metadata = FileMetaData {
+            version: 0,
+            schema: vec![],
+            num_rows: this.num_rows as i64,
+            row_groups: vec![],
+            key_value_metadata: None,
+            created_by: None,
+            column_orders: None,
+            encryption_algorithm: None,
+            footer_signing_key_metadata: None,
+        };
+        this.digest.report(&mut metadata);
+        Ok(vec![(
+            TBD_PARTITION_DESC.to_string(),
+            file_path,
+            object_meta,
+            metadata,
+        )])
+    }
+
+    async fn abort_and_close(self: Box<Self>) -> Result<()> {
+        let this = *self;
+        this.writer
+            .abort()
+            .await
+            .map_err(DataFusionError::ObjectStore)?;
+        Ok(())
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn buffered_size(&self) -> u64 {
+        self.buffered_size
+    }
+
+    fn memory_size(&self) -> u64 {
+        // the encoded bytes are buffered by the upload until a whole part is available
+        self.digest.size() % MULTIPART_CHUNK_SIZE as u64
+    }
+}
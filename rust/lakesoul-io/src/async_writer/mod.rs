@@ -7,6 +7,9 @@
 mod multipart_writer;
 pub use multipart_writer::MultiPartAsyncWriter;
 
+mod ipc_writer;
+pub use ipc_writer::ArrowIpcAsyncWriter;
+
 mod sort_writer;
 use object_store::ObjectMeta;
 pub use sort_writer::SortAsyncWriter;
@@ -17,6 +20,9 @@ pub use partitioning_writer::PartitioningAsyncWriter;
 mod sendable_writer;
 pub use sendable_writer::AsyncSendableMutableLakeSoulWriter;
 
+mod generating_writer;
+pub use generating_writer::GeneratingAsyncWriter;
+
 use std::{
     any::Any,
     collections::VecDeque,
@@ -39,9 +45,13 @@ use datafusion::{
     },
 };
 use datafusion_common::{DataFusionError, Result};
-use parquet::format::FileMetaData;
+use parquet::format::{FileMetaData, KeyValue};
+
+use crate::constant::{LAKESOUL_FILE_CHECKSUM_KEY, LAKESOUL_FILE_SIZE_KEY};
 
 /// The result of a flush operation with format (partition_desc, file_path, object_meta, file_meta)
+///
+/// The file_meta reports the uploaded size and checksum of the file, see [`FileIntegrity`].
 pub type WriterFlushResult = Vec<(String, String, ObjectMeta, FileMetaData)>;
 
 /// The trait for the async batch writer.
@@ -63,6 +73,12 @@ pub trait AsyncBatchWriter {
     fn buffered_size(&self) -> u64 {
         0
     }
+
+    /// Get the bytes currently held in memory by the writer, i.e. the encoded data not yet
+    /// uploaded and the rows not yet encoded.
+    fn memory_size(&self) -> u64 {
+        0
+    }
 }
 
 /// A VecDeque which is both std::io::Write and bytes::Buf
@@ -96,6 +112,73 @@ impl Write for InMemBuf {
     }
 }
 
+/// The size and content hash of the bytes passed to the upload of a data file.
+struct UploadDigest {
+    size: u64,
+    md5: md5::Context,
+}
+
+impl UploadDigest {
+    fn new() -> Self {
+        Self {
+            size: 0,
+            md5: md5::Context::new(),
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        self.size += bytes.len() as u64;
+        self.md5.consume(bytes);
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Report the size and checksum in the key-value metadata of the flush result, the file
+    /// itself is already closed and left unchanged.
+    fn report(self, metadata: &mut FileMetaData) {
+        let key_value_metadata = metadata.key_value_metadata.get_or_insert_with(Vec::new);
+        key_value_metadata.push(KeyValue::new(
+            LAKESOUL_FILE_SIZE_KEY.to_string(),
+            self.size.to_string(),
+        ));
+        key_value_metadata.push(KeyValue::new(
+            LAKESOUL_FILE_CHECKSUM_KEY.to_string(),
+            format!("{:x}", self.md5.compute()),
+        ));
+    }
+}
+
+/// The size and checksum of a data file as uploaded by its writer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileIntegrity {
+    /// The number of bytes uploaded.
+    pub size: u64,
+    /// The hex encoded md5 checksum of the uploaded bytes.
+    pub checksum: String,
+}
+
+impl FileIntegrity {
+    /// Read the size and checksum reported in the file metadata of a [`WriterFlushResult`],
+    /// absent if the writer did not report them.
+    pub fn from_file_metadata(metadata: &FileMetaData) -> Option<Self> {
+        let value = |key: &str| {
+            metadata
+                .key_value_metadata
+                .as_ref()?
+                .iter()
+                .find(|key_value| key_value.key == key)?
+                .value
+                .clone()
+        };
+        Some(Self {
+            size: value(LAKESOUL_FILE_SIZE_KEY)?.parse().ok()?,
+            checksum: value(LAKESOUL_FILE_CHECKSUM_KEY)?,
+        })
+    }
+}
+
 /// A [`datafusion::physical_plan::execution_plan::ExecutionPlan`] implementation for the receiver stream.
 pub struct ReceiverStreamExec {
     receiver_stream_builder: AtomicRefCell<Option<RecordBatchReceiverStreamBuilder>>,
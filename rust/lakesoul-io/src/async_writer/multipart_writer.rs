@@ -4,9 +4,10 @@
 
 //! Implementation of the multipart writer.
 
-use std::{collections::VecDeque, sync::Arc};
+use std::{collections::VecDeque, sync::Arc, time::Duration};
 
-use arrow_array::RecordBatch;
+use arrow::compute::{concat, partition};
+use arrow_array::{ArrayRef, RecordBatch};
 use arrow_schema::SchemaRef;
 use atomic_refcell::AtomicRefCell;
 use bytes::Bytes;
@@ -15,21 +16,25 @@ use datafusion::{
     execution::{TaskContext, object_store::ObjectStoreUrl},
 };
 use datafusion_common::{DataFusionError, Result, project_schema};
-use object_store::{ObjectStore, WriteMultipart, path::Path};
-use parquet::basic::ZstdLevel;
-use parquet::{
-    arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties,
+use object_store::{
+    MultipartUpload, ObjectStore, PutPayload, PutResult, UploadPart, WriteMultipart,
+    path::Path,
 };
+use parquet::file::metadata::KeyValue;
+use parquet::file::properties::EnabledStatistics;
+use parquet::schema::types::ColumnPath;
+use parquet::{arrow::ArrowWriter, file::properties::WriterProperties};
 use url::Url;
 
 use crate::{
-    constant::TBD_PARTITION_DESC,
+    constant::{LAKESOUL_NULLS_FIRST_KEY, LAKESOUL_PRIMARY_KEYS_KEY, TBD_PARTITION_DESC},
+    encryption::file_encryption_properties,
     helpers::get_batch_memory_size,
     lakesoul_io_config::{LakeSoulIOConfig, create_session_context},
     transform::{uniform_record_batch, uniform_schema},
 };
 
-use super::{AsyncBatchWriter, InMemBuf, WriterFlushResult};
+use super::{AsyncBatchWriter, InMemBuf, UploadDigest, WriterFlushResult};
 
 /// An async writer using object_store's multi-part upload feature for cloud storage.
 /// This writer uses a `VecDeque<u8>` as `std::io::Write` for arrow-rs's ArrowWriter.
@@ -60,6 +65,14 @@ pub struct MultiPartAsyncWriter {
     /// The number of rows of the multi-part async writer.
     num_rows: u64,
     buffered_size: u64,
+    /// The sort key columns whose value runs are never split across row groups.
+    row_group_align_columns: Vec<String>,
+    /// The target number of rows per row group.
+    row_group_size: usize,
+    /// The sort key of the last written row, used to detect a key boundary at the start of a batch.
+    last_key: Option<Vec<ArrayRef>>,
+    /// The size and content hash of the bytes passed to the upload.
+    digest: UploadDigest,
 }
 
 impl MultiPartAsyncWriter {
@@ -76,25 +89,8 @@ impl MultiPartAsyncWriter {
             .files
             .last()
             .ok_or(DataFusionError::Internal("wrong file name".to_string()))?;
-
-        // local style path should have already been handled in create_session_context,
-        // so we don't have to deal with ParseError::RelativeUrlWithoutBase here
-        let (object_store, path) = match Url::parse(file_name.as_str()) {
-            Ok(url) => Ok((
-                task_context
-                    .runtime_env()
-                    .object_store(ObjectStoreUrl::parse(
-                        &url[..url::Position::BeforePath],
-                    )?)?,
-                Path::from_url_path(url.path())?,
-            )),
-            Err(e) => Err(DataFusionError::External(Box::new(e))),
-        }?;
-
-        // get underlying multipart uploader
-        let multipart_upload = object_store.put_multipart(&path).await?;
-        let write_multi_part =
-            WriteMultipart::new_with_chunk_size(multipart_upload, 128 * 1024 * 1024);
+        let (object_store, path, write_multi_part) =
+            start_multipart_upload(config, file_name, &task_context).await?;
 
         let in_mem_buf =
             InMemBuf(Arc::new(AtomicRefCell::new(VecDeque::<u8>::with_capacity(
@@ -102,13 +98,18 @@ impl MultiPartAsyncWriter {
             ))));
         let schema = uniform_schema(config.target_schema.0.clone());
 
+        // the range partition columns are only written on request, their values are
+        // encoded into the path of the file
+        let keep_partition_columns = config.keep_partition_columns();
         // O(nm), n = number of fields, m = number of range partitions
         let schema_projection_excluding_range = schema
             .fields()
             .iter()
             .enumerate()
             .filter_map(|(idx, field)| {
-                match config.range_partitions.contains(field.name()) {
+                match !keep_partition_columns
+                    && config.range_partitions.contains(field.name())
+                {
                     true => None,
                     false => Some(idx),
                 }
@@ -117,27 +118,100 @@ impl MultiPartAsyncWriter {
         let writer_schema =
             project_schema(&schema, Some(&schema_projection_excluding_range))?;
 
-        let max_row_group_size = if config.max_row_group_size * schema.fields().len()
+        let max_row_group_size = config.max_row_group_size()?;
+        let max_row_group_size = if max_row_group_size * schema.fields().len()
             > config.max_row_group_num_values
         {
             config
                 .batch_size
                 .max(config.max_row_group_num_values / schema.fields().len())
         } else {
-            config.max_row_group_size
+            max_row_group_size
         };
-        let arrow_writer = ArrowWriter::try_new(
+        // Row groups of aligned writers are ended at the key boundaries by the writer itself,
+        // so the size limit of the arrow writer must not split them in between.
+        let row_group_align_columns = config.row_group_align_columns();
+        for column in &row_group_align_columns {
+            if writer_schema.field_with_name(column).is_err() {
+                return Err(DataFusionError::Configuration(format!(
+                    "row group align column {} is not a written column",
+                    column
+                )));
+            }
+        }
+        let mut writer_properties = WriterProperties::builder()
+            .set_max_row_group_size(if row_group_align_columns.is_empty() {
+                max_row_group_size
+            } else {
+                usize::MAX
+            })
+            .set_write_batch_size(config.batch_size)
+            .set_compression(config.parquet_compression()?)
+            .set_dictionary_enabled(config.dictionary_enabled());
+        if let Some(data_page_size) = config.data_page_size()? {
+            writer_properties =
+                writer_properties.set_data_page_size_limit(data_page_size);
+        }
+        if let Some(dictionary_page_size) = config.dictionary_page_size()? {
+            writer_properties =
+                writer_properties.set_dictionary_page_size_limit(dictionary_page_size);
+        }
+        for (columns, enabled) in [
+            (config.dictionary_enabled_columns(), true),
+            (config.dictionary_disabled_columns(), false),
+        ] {
+            for column in columns {
+                writer_properties = writer_properties
+                    .set_column_dictionary_enabled(ColumnPath::from(column), enabled);
+            }
+        }
+        for column in config.statistics_disabled_columns() {
+            writer_properties = writer_properties.set_column_statistics_enabled(
+                ColumnPath::from(column),
+                EnabledStatistics::None,
+            );
+        }
+        // The min/max of the aligned columns in each row group are what makes them prunable.
+        for column in &row_group_align_columns {
+            writer_properties = writer_properties.set_column_statistics_enabled(
+                ColumnPath::from(column.as_str()),
+                EnabledStatistics::Page,
+            );
+        }
+        // The bloom filters let point lookups on the primary keys skip the files without the key.
+        if config.primary_key_bloom_filter() {
+            for column in config.primary_keys_slice() {
+                writer_properties = writer_properties.set_column_bloom_filter_enabled(
+                    ColumnPath::from(column.as_str()),
+                    true,
+                );
+            }
+        }
+        // The sensitive columns are encrypted, the plaintext footer keeps the other
+        // columns readable without the keys.
+        if let Some(encryption) = file_encryption_properties(config, &writer_schema)? {
+            writer_properties =
+                writer_properties.with_file_encryption_properties(encryption);
+        }
+        let mut arrow_writer = ArrowWriter::try_new(
             in_mem_buf.clone(),
             writer_schema,
-            Some(
-                WriterProperties::builder()
-                    .set_max_row_group_size(max_row_group_size)
-                    .set_write_batch_size(config.batch_size)
-                    .set_compression(Compression::ZSTD(ZstdLevel::default()))
-                    .set_dictionary_enabled(false)
-                    .build(),
-            ),
+            Some(writer_properties.build()),
         )?;
+        // The recorded primary keys and null ordering the rows are sorted by let a reader
+        // detect the files written before the primary keys or their ordering changed. They
+        // are appended to the key-value metadata of the footer, keeping the other entries.
+        if !config.primary_keys.is_empty() {
+            arrow_writer.append_key_value_metadata(KeyValue::new(
+                LAKESOUL_PRIMARY_KEYS_KEY.to_string(),
+                serde_json::to_string(&config.primary_keys)
+                    .map_err(|e| DataFusionError::External(Box::new(e)))?,
+            ));
+            arrow_writer.append_key_value_metadata(KeyValue::new(
+                LAKESOUL_NULLS_FIRST_KEY.to_string(),
+                config.nulls_first().to_string(),
+            ));
+        }
 
         Ok(MultiPartAsyncWriter {
             in_mem_buf,
@@ -152,6 +226,10 @@ impl MultiPartAsyncWriter {
             absolute_path: file_name.to_string(),
             num_rows: 0,
             buffered_size: 0,
+            row_group_align_columns,
+            row_group_size: max_row_group_size,
+            last_key: None,
+            digest: UploadDigest::new(),
         })
     }
 
@@ -165,6 +243,7 @@ impl MultiPartAsyncWriter {
         arrow_writer: &mut ArrowWriter<InMemBuf>,
         in_mem_buf: &mut InMemBuf,
         writer: &mut WriteMultipart,
+        digest: &mut UploadDigest,
     ) -> Result<()> {
         arrow_writer.write(&batch)?;
         let mut v = in_mem_buf
@@ -172,21 +251,78 @@ impl MultiPartAsyncWriter {
             .try_borrow_mut()
             .map_err(|e| DataFusionError::Internal(format!("{:?}", e)))?;
         if !v.is_empty() {
-            MultiPartAsyncWriter::write_part(writer, &mut v).await
+            MultiPartAsyncWriter::write_part(writer, &mut v, digest).await
         } else {
             Ok(())
         }
     }
 
-    pub async fn write_part(
+    pub(super) async fn write_part(
         writer: &mut WriteMultipart,
         in_mem_buf: &mut VecDeque<u8>,
+        digest: &mut UploadDigest,
     ) -> Result<()> {
         let bytes = Bytes::from(in_mem_buf.drain(..).collect::<Vec<u8>>());
+        digest.update(&bytes);
         writer.put(bytes);
         Ok(())
     }
 
+    /// Write the batch, ending row groups only where the value of the row group align columns
+    /// changes, so that the row groups of sorted input cover non-overlapping key ranges.
+    /// A row group is ended at the first key boundary after it reaches the target size.
+    async fn write_aligned_batch(&mut self, batch: RecordBatch) -> Result<()> {
+        if batch.num_rows() == 0 {
+            return Ok(());
+        }
+        let keys = self
+            .row_group_align_columns
+            .iter()
+            .map(|column| {
+                batch.column_by_name(column).cloned().ok_or_else(|| {
+                    DataFusionError::Internal(format!(
+                        "row group align column {} not found in batch",
+                        column
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let boundaries = key_boundaries(self.last_key.as_deref(), &keys)?;
+        self.last_key =
+            Some(keys.iter().map(|key| key.slice(key.len() - 1, 1)).collect());
+
+        let mut offset = 0;
+        for boundary in boundaries {
+            if self.arrow_writer.in_progress_rows() + boundary - offset
+                < self.row_group_size
+            {
+                continue;
+            }
+            if boundary > offset {
+                self.arrow_writer
+                    .write(&batch.slice(offset, boundary - offset))?;
+                offset = boundary;
+            }
+            self.arrow_writer.flush()?;
+        }
+        if offset < batch.num_rows() {
+            self.arrow_writer
+                .write(&batch.slice(offset, batch.num_rows() - offset))?;
+        }
+
+        let mut v = self
+            .in_mem_buf
+            .0
+            .try_borrow_mut()
+            .map_err(|e| DataFusionError::Internal(format!("{:?}", e)))?;
+        if !v.is_empty() {
+            MultiPartAsyncWriter::write_part(&mut self.writer, &mut v, &mut self.digest)
+                .await
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn nun_rows(&self) -> u64 {
         self.num_rows
     }
@@ -206,11 +342,15 @@ impl AsyncBatchWriter for MultiPartAsyncWriter {
         let batch = uniform_record_batch(batch)?;
         self.num_rows += batch.num_rows() as u64;
         self.buffered_size += get_batch_memory_size(&batch)? as u64;
+        if !self.row_group_align_columns.is_empty() {
+            return self.write_aligned_batch(batch).await;
+        }
         MultiPartAsyncWriter::write_batch(
             batch,
             &mut self.arrow_writer,
             &mut self.in_mem_buf,
             &mut self.writer,
+            &mut self.digest,
         )
         .await
     }
@@ -224,16 +364,19 @@ impl AsyncBatchWriter for MultiPartAsyncWriter {
         let mut this = *self;
         let arrow_writer = this.arrow_writer;
         let file_path = this.absolute_path.clone();
-        let metadata = arrow_writer.close()?;
+        let mut metadata = arrow_writer.close()?;
         let mut v = this
             .in_mem_buf
             .0
             .try_borrow_mut()
             .map_err(|e| DataFusionError::Internal(format!("{:?}", e)))?;
         if !v.is_empty() {
-            MultiPartAsyncWriter::write_part(&mut this.writer, &mut v).await?;
+            MultiPartAsyncWriter::write_part(&mut this.writer, &mut v, &mut this.digest)
+                .await?;
         }
-        // shutdown multi-part async writer to complete the upload
+        this.digest.report(&mut metadata);
+        // shutdown multi-part async writer to complete the upload,
+        // the upload is aborted if the completion still fails after all retries
         this.writer.finish().await?;
         let path = Path::from_url_path(
             <ListingTableUrl as AsRef<Url>>::as_ref(&ListingTableUrl::parse(&file_path)?)
@@ -264,4 +407,477 @@ impl AsyncBatchWriter for MultiPartAsyncWriter {
     fn buffered_size(&self) -> u64 {
         self.buffered_size
     }
+
+    fn memory_size(&self) -> u64 {
+        // the encoded bytes are buffered by the upload until a whole part is available
+        (self.arrow_writer.memory_size()
+            + self.arrow_writer.bytes_written() % MULTIPART_CHUNK_SIZE) as u64
+    }
+}
+
+/// Start the multipart upload of the file, returning the object store and the path of the file.
+pub(super) async fn start_multipart_upload(
+    config: &LakeSoulIOConfig,
+    file_name: &str,
+    task_context: &TaskContext,
+) -> Result<(Arc<dyn ObjectStore>, Path, WriteMultipart)> {
+    // local style path should have already been handled in create_session_context,
+    // so we don't have to deal with ParseError::RelativeUrlWithoutBase here
+    let (object_store, path) = match Url::parse(file_name) {
+        Ok(url) => Ok((
+            task_context
+                .runtime_env()
+                .object_store(ObjectStoreUrl::parse(
+                    &url[..url::Position::BeforePath],
+                )?)?,
+            Path::from_url_path(url.path())?,
+        )),
+        Err(e) => Err(DataFusionError::External(Box::new(e))),
+    }?;
+
+    // get underlying multipart uploader
+    let multipart_upload = Box::new(RetryingMultipartUpload::new(
+        object_store.put_multipart(&path).await?,
+        config.upload_complete_retries()?,
+        config.upload_retry_base_delay()?,
+    ));
+    let write_multi_part =
+        WriteMultipart::new_with_chunk_size(multipart_upload, MULTIPART_CHUNK_SIZE);
+    Ok((object_store, path, write_multi_part))
+}
+
+/// The size of the parts uploaded by [`WriteMultipart`], smaller writes are buffered until a
+/// whole part is available.
+pub(super) const MULTIPART_CHUNK_SIZE: usize = 128 * 1024 * 1024;
+
+/// A [`MultipartUpload`] that retries the completion of the upload with exponential backoff,
+/// as transient errors of cloud storage (e.g. S3 503 Slow Down) would otherwise fail the whole write.
+///
+/// [`WriteMultipart::finish`] aborts the upload once the completion finally fails,
+/// so that no incomplete upload is left behind in the object store.
+#[derive(Debug)]
+struct RetryingMultipartUpload {
+    /// The upload of the object store.
+    inner: Box<dyn MultipartUpload>,
+    /// The number of retries after the first failed completion.
+    retries: usize,
+    /// The delay before the first retry, doubled on each further retry.
+    base_delay: Duration,
+}
+
+impl RetryingMultipartUpload {
+    fn new(
+        inner: Box<dyn MultipartUpload>,
+        retries: usize,
+        base_delay: Duration,
+    ) -> Self {
+        Self {
+            inner,
+            retries,
+            base_delay,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl MultipartUpload for RetryingMultipartUpload {
+    fn put_part(&mut self, data: PutPayload) -> UploadPart {
+        self.inner.put_part(data)
+    }
+
+    async fn complete(&mut self) -> object_store::Result<PutResult> {
+        let mut attempt = 0;
+        loop {
+            match self.inner.complete().await {
+                Ok(result) => return Ok(result),
+                Err(e) if attempt < self.retries => {
+                    let delay = self.base_delay.saturating_mul(1u32 << attempt.min(16));
+                    warn!(
+                        "failed to complete multipart upload (attempt {}), retrying in {:?}: {}",
+                        attempt + 1,
+                        delay,
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn abort(&mut self) -> object_store::Result<()> {
+        self.inner.abort().await
+    }
+}
+
+/// Returns the offsets in the batch where the value of the keys differs from the previous row,
+/// including the offset 0 if it differs from the last key of the previous batch.
+fn key_boundaries(
+    last_key: Option<&[ArrayRef]>,
+    keys: &[ArrayRef],
+) -> Result<Vec<usize>> {
+    let (columns, shift) = match last_key {
+        Some(last_key) => (
+            last_key
+                .iter()
+                .zip(keys)
+                .map(|(last, key)| concat(&[last.as_ref(), key.as_ref()]))
+                .collect::<std::result::Result<Vec<_>, _>>()?,
+            1,
+        ),
+        None => (keys.to_vec(), 0),
+    };
+    Ok(partition(&columns)?
+        .ranges()
+        .into_iter()
+        .map(|range| range.start)
+        .filter(|start| *start >= shift)
+        .map(|start| start - shift)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::{self, Display};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use arrow::array::{ArrayRef, Int64Array, StringArray};
+    use arrow_array::RecordBatch;
+    use datafusion::prelude::SessionContext;
+    use datafusion_common::{DataFusionError, Result};
+    use futures::stream::BoxStream;
+    use object_store::memory::InMemory;
+    use object_store::path::Path;
+    use object_store::{
+        GetOptions, GetResult, ListResult, MultipartUpload, ObjectMeta, ObjectStore,
+        PutMultipartOpts, PutOptions, PutPayload, PutResult, UploadPart,
+    };
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use url::Url;
+
+    use crate::async_writer::{AsyncBatchWriter, FileIntegrity, MultiPartAsyncWriter};
+    use crate::lakesoul_io_config::{
+        LakeSoulIOConfigBuilder, OPTION_KEY_DICTIONARY_PAGE_SIZE,
+    };
+
+    /// The counters of the completions still to fail and the aborted uploads of a [`FlakyStore`].
+    #[derive(Debug, Default)]
+    struct FlakyCounters {
+        failing_completions: AtomicUsize,
+        aborts: AtomicUsize,
+    }
+
+    /// An in-memory store whose multipart uploads fail to complete a given number of times.
+    #[derive(Debug)]
+    struct FlakyStore {
+        inner: InMemory,
+        counters: Arc<FlakyCounters>,
+    }
+
+    impl Display for FlakyStore {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "FlakyStore")
+        }
+    }
+
+    #[derive(Debug)]
+    struct FlakyUpload {
+        inner: Box<dyn MultipartUpload>,
+        counters: Arc<FlakyCounters>,
+    }
+
+    #[async_trait::async_trait]
+    impl MultipartUpload for FlakyUpload {
+        fn put_part(&mut self, data: PutPayload) -> UploadPart {
+            self.inner.put_part(data)
+        }
+
+        async fn complete(&mut self) -> object_store::Result<PutResult> {
+            let failing = self.counters.failing_completions.fetch_update(
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+                |n| n.checked_sub(1),
+            );
+            if failing.is_ok() {
+                return Err(object_store::Error::Generic {
+                    store: "FlakyStore",
+                    source: "503 Slow Down".into(),
+                });
+            }
+            self.inner.complete().await
+        }
+
+        async fn abort(&mut self) -> object_store::Result<()> {
+            self.counters.aborts.fetch_add(1, Ordering::SeqCst);
+            self.inner.abort().await
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl ObjectStore for FlakyStore {
+        async fn put_opts(
+            &self,
+            location: &Path,
+            payload: PutPayload,
+            opts: PutOptions,
+        ) -> object_store::Result<PutResult> {
+            self.inner.put_opts(location, payload, opts).await
+        }
+
+        async fn put_multipart_opts(
+            &self,
+            location: &Path,
+            opts: PutMultipartOpts,
+        ) -> object_store::Result<Box<dyn MultipartUpload>> {
+            Ok(Box::new(FlakyUpload {
+                inner: self.inner.put_multipart_opts(location, opts).await?,
+                counters: self.counters.clone(),
+            }))
+        }
+
+        async fn get_opts(
+            &self,
+            location: &Path,
+            options: GetOptions,
+        ) -> object_store::Result<GetResult> {
+            self.inner.get_opts(location, options).await
+        }
+
+        async fn delete(&self, location: &Path) -> object_store::Result<()> {
+            self.inner.delete(location).await
+        }
+
+        fn list(
+            &self,
+            prefix: Option<&Path>,
+        ) -> BoxStream<'static, object_store::Result<ObjectMeta>> {
+            self.inner.list(prefix)
+        }
+
+        async fn list_with_delimiter(
+            &self,
+            prefix: Option<&Path>,
+        ) -> object_store::Result<ListResult> {
+            self.inner.list_with_delimiter(prefix).await
+        }
+
+        async fn copy(&self, from: &Path, to: &Path) -> object_store::Result<()> {
+            self.inner.copy(from, to).await
+        }
+
+        async fn copy_if_not_exists(
+            &self,
+            from: &Path,
+            to: &Path,
+        ) -> object_store::Result<()> {
+            self.inner.copy_if_not_exists(from, to).await
+        }
+    }
+
+    /// Write a batch to `mock://bucket/test.parquet` of a store failing the given number of completions.
+    async fn write_to_flaky_store(
+        failing_completions: usize,
+        retries: usize,
+    ) -> (Result<()>, Arc<FlakyCounters>, Arc<FlakyStore>) {
+        let counters = Arc::new(FlakyCounters {
+            failing_completions: AtomicUsize::new(failing_completions),
+            aborts: AtomicUsize::new(0),
+        });
+        let store = Arc::new(FlakyStore {
+            inner: InMemory::new(),
+            counters: counters.clone(),
+        });
+        let ctx = SessionContext::new();
+        ctx.register_object_store(&Url::parse("mock://bucket").unwrap(), store.clone());
+
+        let col = Arc::new(Int64Array::from_iter_values([1, 2, 3])) as ArrayRef;
+        let batch = RecordBatch::try_from_iter([("col", col)]).unwrap();
+        let mut config = LakeSoulIOConfigBuilder::new()
+            .with_files(vec!["mock://bucket/test.parquet"])
+            .with_schema(batch.schema())
+            .with_upload_retry(retries, Duration::from_millis(1))
+            .build();
+        let result = async {
+            let mut writer =
+                MultiPartAsyncWriter::try_new_with_context(&mut config, ctx.task_ctx())
+                    .await?;
+            writer.write_record_batch(batch).await?;
+            Box::new(writer).flush_and_close().await?;
+            Ok::<_, DataFusionError>(())
+        }
+        .await;
+        (result, counters, store)
+    }
+
+    #[tokio::test]
+    async fn test_retry_multipart_upload_completion() {
+        let (result, counters, store) = write_to_flaky_store(2, 3).await;
+        assert!(result.is_ok(), "{:?}", result);
+        assert_eq!(counters.failing_completions.load(Ordering::SeqCst), 0);
+        assert_eq!(counters.aborts.load(Ordering::SeqCst), 0);
+        assert!(store.head(&Path::from("test.parquet")).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_abort_multipart_upload_after_retries() {
+        let (result, counters, store) = write_to_flaky_store(2, 1).await;
+        assert!(result.is_err());
+        assert_eq!(counters.aborts.load(Ordering::SeqCst), 1);
+        assert!(store.head(&Path::from("test.parquet")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_with_row_group_and_page_size() -> Result<()> {
+        let store = Arc::new(InMemory::new());
+        let ctx = SessionContext::new();
+        ctx.register_object_store(&Url::parse("mock://bucket").unwrap(), store.clone());
+
+        let col = Arc::new(Int64Array::from_iter_values(0..10)) as ArrayRef;
+        let batch = RecordBatch::try_from_iter([("col", col)])?;
+        let builder = LakeSoulIOConfigBuilder::new()
+            .with_files(vec!["mock://bucket/test.parquet"])
+            .with_schema(batch.schema())
+            .with_data_page_size(1024);
+        let mut config = builder.clone().with_max_row_group_size(4).build();
+        let mut writer =
+            MultiPartAsyncWriter::try_new_with_context(&mut config, ctx.task_ctx())
+                .await?;
+        writer.write_record_batch(batch).await?;
+        Box::new(writer).flush_and_close().await?;
+
+        let bytes = store
+            .get(&Path::from("test.parquet"))
+            .await?
+            .bytes()
+            .await?;
+        let reader = SerializedFileReader::new(bytes)?;
+        let row_group_rows = reader
+            .metadata()
+            .row_groups()
+            .iter()
+            .map(|row_group| row_group.num_rows())
+            .collect::<Vec<_>>();
+        assert_eq!(row_group_rows, vec![4, 4, 2]);
+
+        let mut config = builder.with_max_row_group_size(0).build();
+        assert!(
+            MultiPartAsyncWriter::try_new_with_context(&mut config, ctx.task_ctx())
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_write_with_dictionary_columns() -> Result<()> {
+        let store = Arc::new(InMemory::new());
+        let ctx = SessionContext::new();
+        ctx.register_object_store(&Url::parse("mock://bucket").unwrap(), store.clone());
+
+        let values = (0..100)
+            .map(|i| format!("value_{}", i % 3))
+            .collect::<Vec<_>>();
+        let batch = RecordBatch::try_from_iter([
+            ("a", Arc::new(StringArray::from(values.clone())) as ArrayRef),
+            ("b", Arc::new(StringArray::from(values.clone())) as ArrayRef),
+            ("c", Arc::new(StringArray::from(values)) as ArrayRef),
+        ])?;
+        let dictionary_encoded = |builder: LakeSoulIOConfigBuilder| {
+            let batch = batch.clone();
+            let store = store.clone();
+            let ctx = &ctx;
+            async move {
+                let mut config = builder
+                    .with_files(vec!["mock://bucket/test.parquet"])
+                    .with_schema(batch.schema())
+                    .build();
+                let mut writer = MultiPartAsyncWriter::try_new_with_context(
+                    &mut config,
+                    ctx.task_ctx(),
+                )
+                .await?;
+                writer.write_record_batch(batch).await?;
+                Box::new(writer).flush_and_close().await?;
+                let bytes = store
+                    .get(&Path::from("test.parquet"))
+                    .await?
+                    .bytes()
+                    .await?;
+                let reader = SerializedFileReader::new(bytes)?;
+                Ok::<_, DataFusionError>(
+                    reader
+                        .metadata()
+                        .row_group(0)
+                        .columns()
+                        .iter()
+                        .map(|column| column.dictionary_page_offset().is_some())
+                        .collect::<Vec<_>>(),
+                )
+            }
+        };
+
+        // the dictionary encoding is disabled by default
+        let encoded = dictionary_encoded(LakeSoulIOConfigBuilder::new()).await?;
+        assert_eq!(encoded, vec![false, false, false]);
+        let encoded = dictionary_encoded(
+            LakeSoulIOConfigBuilder::new()
+                .with_dictionary_enabled(true)
+                .with_dictionary_page_size(1024)
+                .with_dictionary_columns(vec![], vec!["b".to_string()]),
+        )
+        .await?;
+        assert_eq!(encoded, vec![true, false, true]);
+        let encoded = dictionary_encoded(
+            LakeSoulIOConfigBuilder::new()
+                .with_dictionary_columns(vec!["c".to_string()], vec![]),
+        )
+        .await?;
+        assert_eq!(encoded, vec![false, false, true]);
+
+        let mut config = LakeSoulIOConfigBuilder::new()
+            .with_files(vec!["mock://bucket/test.parquet"])
+            .with_schema(batch.schema())
+            .with_option(OPTION_KEY_DICTIONARY_PAGE_SIZE, "0")
+            .build();
+        assert!(
+            MultiPartAsyncWriter::try_new_with_context(&mut config, ctx.task_ctx())
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_report_size_and_checksum() -> Result<()> {
+        let store = Arc::new(InMemory::new());
+        let ctx = SessionContext::new();
+        ctx.register_object_store(&Url::parse("mock://bucket").unwrap(), store.clone());
+
+        let col = Arc::new(Int64Array::from_iter_values(0..10)) as ArrayRef;
+        let batch = RecordBatch::try_from_iter([("col", col)])?;
+        let mut config = LakeSoulIOConfigBuilder::new()
+            .with_files(vec!["mock://bucket/test.parquet"])
+            .with_schema(batch.schema())
+            .build();
+        let mut writer =
+            MultiPartAsyncWriter::try_new_with_context(&mut config, ctx.task_ctx())
+                .await?;
+        writer.write_record_batch(batch).await?;
+        let flush_result = Box::new(writer).flush_and_close().await?;
+        let (_, _, object_meta, metadata) = &flush_result[0];
+        let integrity = FileIntegrity::from_file_metadata(metadata).unwrap();
+
+        let bytes = store
+            .get(&Path::from("test.parquet"))
+            .await?
+            .bytes()
+            .await?;
+        assert_eq!(integrity.size, bytes.len() as u64);
+        assert_eq!(integrity.size, object_meta.size);
+        assert_eq!(integrity.checksum, format!("{:x}", md5::compute(&bytes)));
+        Ok(())
+    }
 }
@@ -7,7 +7,7 @@
 use std::{borrow::Borrow, collections::HashMap, sync::Arc};
 
 use arrow_array::RecordBatch;
-use arrow_schema::{SchemaRef, SortOptions};
+use arrow_schema::SchemaRef;
 use datafusion::{
     execution::TaskContext,
     physical_expr::{
@@ -16,8 +16,7 @@ use datafusion::{
     },
     physical_plan::{
         ExecutionPlan, ExecutionPlanProperties, Partitioning, PhysicalExpr,
-        projection::ProjectionExec, sorts::sort::SortExec,
-        stream::RecordBatchReceiverStream,
+        sorts::sort::SortExec, stream::RecordBatchReceiverStream,
     },
 };
 use datafusion_common::{DataFusionError, Result};
@@ -28,12 +27,12 @@ use tokio_stream::StreamExt;
 use crate::{
     datasource::physical_plan::self_incremental_index_column::SelfIncrementalIndexColumnExec,
     helpers::{
-        columnar_values_to_partition_desc, columnar_values_to_sub_path,
-        get_batch_memory_size, get_columnar_values,
+        columnar_values_to_partition_desc, get_batch_memory_size, get_columnar_values,
     },
     lakesoul_io_config::{
         IOSchema, LakeSoulIOConfig, LakeSoulIOConfigBuilder, create_session_context,
     },
+    projection::LakeSoulProjectionExec,
     repartition::RepartitionByRangeAndHashExec,
     transform::uniform_schema,
 };
@@ -155,7 +154,7 @@ impl PartitioningAsyncWriter {
                     Column::new_with_schema(sort_column.as_str(), input_schema.as_ref())?;
                 Ok(PhysicalSortExpr {
                     expr: Arc::new(col),
-                    options: SortOptions::default(),
+                    options: config.primary_key_sort_options(),
                 })
             })
             .collect::<Result<Vec<PhysicalSortExpr>>>()?;
@@ -187,7 +186,10 @@ impl PartitioningAsyncWriter {
                     }
                 })
                 .collect::<Result<Vec<(Arc<dyn PhysicalExpr>, String)>>>()?;
-            Arc::new(ProjectionExec::try_new(proj_expr, sort_exec)?)
+            Arc::new(
+                LakeSoulProjectionExec::try_new(proj_expr, sort_exec)?
+                    .with_max_batch_bytes(config.max_batch_bytes()?),
+            )
         };
 
         let exec_plan = if config.primary_keys.is_empty()
@@ -237,18 +239,21 @@ impl PartitioningAsyncWriter {
         // partitioned_flush_result: PartitionedWriterInfo,
     ) -> Result<Vec<JoinHandle<Result<WriterFlushResult>>>> {
         let mut data = input.execute(partition, context.clone())?;
+        let config = config_builder.clone().build();
+        let path_encoder = config.partition_path_encoder()?;
+        let keep_partition_columns = config.keep_partition_columns();
         // O(nm), n = number of data fields, m = number of range partitions
         let schema_projection_excluding_range = data
             .schema()
             .fields()
             .iter()
             .enumerate()
-            .filter_map(
-                |(idx, field)| match range_partitions.contains(field.name()) {
+            .filter_map(|(idx, field)| {
+                match !keep_partition_columns && range_partitions.contains(field.name()) {
                     true => None,
                     false => Some(idx),
-                },
-            )
+                }
+            })
             .collect::<Vec<_>>();
 
         let mut err = None;
@@ -264,8 +269,7 @@ impl PartitioningAsyncWriter {
                         get_columnar_values(&batch, range_partitions.clone())?;
                     let partition_desc =
                         columnar_values_to_partition_desc(&columnar_values);
-                    let partition_sub_path =
-                        columnar_values_to_sub_path(&columnar_values);
+                    let partition_sub_path = path_encoder.encode(&columnar_values);
                     let batch_excluding_range =
                         batch.project(&schema_projection_excluding_range)?;
 
@@ -7,14 +7,15 @@
 use std::sync::Arc;
 
 use arrow_array::RecordBatch;
-use arrow_schema::{SchemaRef, SortOptions};
+use arrow_schema::SchemaRef;
 use datafusion::{
+    execution::TaskContext,
     physical_expr::{
         LexOrdering, PhysicalSortExpr,
         expressions::{Column, col},
     },
     physical_plan::{
-        ExecutionPlan, PhysicalExpr, projection::ProjectionExec, sorts::sort::SortExec,
+        ExecutionPlan, PhysicalExpr, sorts::sort::SortExec,
         stream::RecordBatchReceiverStream,
     },
 };
@@ -22,7 +23,10 @@ use datafusion_common::{DataFusionError, Result};
 use tokio::{sync::mpsc::Sender, task::JoinHandle};
 use tokio_stream::StreamExt;
 
-use crate::{helpers::get_batch_memory_size, lakesoul_io_config::LakeSoulIOConfig};
+use crate::{
+    helpers::get_batch_memory_size, lakesoul_io_config::LakeSoulIOConfig,
+    projection::LakeSoulProjectionExec,
+};
 
 use super::{
     AsyncBatchWriter, MultiPartAsyncWriter, ReceiverStreamExec, WriterFlushResult,
@@ -52,6 +56,18 @@ impl SortAsyncWriter {
         // runtime: Arc<Runtime>,
     ) -> Result<Self> {
         // let _ = runtime.enter();
+        let task_context = async_writer.task_ctx();
+        Self::try_new_with_writer(Box::new(async_writer), task_context, config)
+    }
+
+    /// Create a sort writer writing the sorted batches into any async writer, e.g. an
+    /// [`ArrowIpcAsyncWriter`](super::ArrowIpcAsyncWriter), sorting them in the task
+    /// context.
+    pub fn try_new_with_writer(
+        mut async_writer: Box<dyn AsyncBatchWriter + Send>,
+        task_context: Arc<TaskContext>,
+        config: LakeSoulIOConfig,
+    ) -> Result<Self> {
         let schema = config.target_schema.0.clone();
         let receiver_stream_builder =
             RecordBatchReceiverStream::builder(schema.clone(), 8);
@@ -67,7 +83,7 @@ impl SortAsyncWriter {
                 let col = Column::new_with_schema(pk.as_str(), &config.target_schema.0)?;
                 Ok(PhysicalSortExpr {
                     expr: Arc::new(col),
-                    options: SortOptions::default(),
+                    options: config.primary_key_sort_options(),
                 })
             })
             .collect::<Result<Vec<PhysicalSortExpr>>>()?;
@@ -98,12 +114,14 @@ impl SortAsyncWriter {
                     }
                 })
                 .collect::<Result<Vec<(Arc<dyn PhysicalExpr>, String)>>>()?;
-            Arc::new(ProjectionExec::try_new(proj_expr, sort_exec)?)
+            Arc::new(
+                LakeSoulProjectionExec::try_new(proj_expr, sort_exec)?
+                    .with_max_batch_bytes(config.max_batch_bytes()?),
+            )
         };
 
-        let mut sorted_stream = exec_plan.execute(0, async_writer.task_ctx())?;
+        let mut sorted_stream = exec_plan.execute(0, task_context)?;
 
-        let mut async_writer = Box::new(async_writer);
         let join_handle = tokio::task::spawn(async move {
             let mut err = None;
             while let Some(batch) = sorted_stream.next().await {
@@ -32,6 +32,19 @@ pub static TIMESTAMP_NANOSECOND_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.9f";
 
 pub static NUM_COLUMN_OPTIMIZE_THRESHOLD: usize = 200;
 
+/// The key-value metadata keys reporting the uploaded size and the hex encoded md5 checksum
+/// of a data file in the flush result of a writer.
+pub static LAKESOUL_FILE_SIZE_KEY: &str = "lakesoul.file.size";
+pub static LAKESOUL_FILE_CHECKSUM_KEY: &str = "lakesoul.file.checksum";
+
+/// The key of the JSON encoded primary keys a data file is sorted by, stored in the key-value
+/// metadata of the parquet footer and carried into the schema of the file scan.
+pub static LAKESOUL_PRIMARY_KEYS_KEY: &str = "lakesoul.primary_keys";
+
+/// The key of whether the null values of the primary keys are ordered first in a data file,
+/// stored and carried like [`LAKESOUL_PRIMARY_KEYS_KEY`].
+pub static LAKESOUL_NULLS_FIRST_KEY: &str = "lakesoul.primary_keys.nulls_first";
+
 lazy_static! {
     pub static ref ARROW_CAST_OPTIONS: CastOptions<'static> = CastOptions {
         safe: false,
@@ -10,30 +10,73 @@ use std::sync::Arc;
 
 use arrow::datatypes::SchemaRef;
 use arrow_cast::can_cast_types;
-use arrow_schema::{ArrowError, FieldRef, Fields, Schema, SchemaBuilder};
+use arrow_schema::{
+    ArrowError, DataType, Field, FieldRef, Fields, Schema, SchemaBuilder, TimeUnit,
+};
 
 use datafusion::datasource::file_format::file_compression_type::FileCompressionType;
 use datafusion::datasource::file_format::{FileFormat, parquet::ParquetFormat};
+use datafusion::datasource::listing::PartitionedFile;
+use datafusion::datasource::physical_plan::parquet::{
+    ParquetAccessPlan, ParquetFileMetrics, RowGroupAccessPlanFilter,
+};
 use datafusion::datasource::physical_plan::{
     FileGroup, FileScanConfig, FileSinkConfig, FileSource,
 };
+use datafusion::execution::object_store::ObjectStoreUrl;
 
+use datafusion::logical_expr::Operator;
 use datafusion::physical_expr::LexRequirement;
+use datafusion::physical_expr::expressions::{BinaryExpr, Column, Literal};
+use datafusion::physical_expr::utils::{reassign_predicate_columns, split_conjunction};
+use datafusion::physical_optimizer::pruning::PruningPredicate;
+use datafusion::physical_plan::limit::LocalLimitExec;
+use datafusion::physical_plan::metrics::ExecutionPlanMetricsSet;
 use datafusion::physical_plan::projection::ProjectionExec;
 use datafusion::physical_plan::{ExecutionPlan, PhysicalExpr};
-use datafusion_common::{DataFusionError, Result, Statistics, project_schema};
+use datafusion_common::stats::Precision;
+use datafusion_common::{
+    DataFusionError, Result, ScalarValue, Statistics, project_schema,
+};
 
+use object_store::path::Path;
 use object_store::{ObjectMeta, ObjectStore};
 
+use crate::constant::{LAKESOUL_NULLS_FIRST_KEY, LAKESOUL_PRIMARY_KEYS_KEY};
 use crate::datasource::{
-    listing::LakeSoulTableProvider, physical_plan::MergeParquetExec,
+    listing::LakeSoulTableProvider,
+    physical_plan::{
+        MergeParquetExec, SkippedFilesExec, infer_arrow_ipc_schema, infer_orc_schema,
+        is_arrow_ipc_file, is_orc_file,
+    },
 };
+use crate::encryption::ScanDecryption;
+use crate::helpers::{ColumnEquality, check_normalized_column_names};
 use crate::lakesoul_io_config::LakeSoulIOConfig;
+use crate::transform::coerce_timestamp_field;
 use async_trait::async_trait;
 use datafusion::catalog::Session;
-use datafusion::datasource::file_format::parquet::fetch_parquet_metadata;
-use futures::{StreamExt, TryStreamExt};
-use parquet::arrow::parquet_to_arrow_schema;
+use datafusion::datasource::file_format::parquet::{
+    fetch_parquet_metadata, statistics_from_parquet_meta_calc,
+};
+use futures::{FutureExt, StreamExt, TryStreamExt};
+use parquet::arrow::async_reader::ParquetObjectReader;
+use parquet::arrow::{
+    ParquetRecordBatchStreamBuilder, ProjectionMask, parquet_to_arrow_schema,
+    parquet_to_arrow_schema_by_columns,
+};
+use parquet::basic::{Repetition, Type as PhysicalType};
+use parquet::bloom_filter::Sbbf;
+use parquet::file::metadata::{FileMetaData, ParquetMetaData};
+use parquet::schema::types::Type;
+
+/// Metadata key of a field whose parquet logical type can not be mapped to arrow.
+/// The value is the description of the original parquet type.
+pub const UNSUPPORTED_LOGICAL_TYPE_KEY: &str = "lakesoul.unsupported_logical_type";
+
+/// Metadata key of a field whose timestamps are coerced into another time unit than the one
+/// stored in the file. The value is the original type of the field.
+const COERCED_TIMESTAMP_KEY: &str = "lakesoul.coerced_timestamp";
 
 /// LakeSoul `FileFormat` implementation for supporting Apache Parquet
 ///
@@ -69,6 +112,8 @@ impl LakeSoulParquetFormat {
     }
 }
 
+/// Fetch the schema of a parquet file. The columns whose logical type can not be mapped to
+/// arrow are read as their physical type instead of failing the whole file.
 async fn fetch_schema(
     store: &dyn ObjectStore,
     file: &ObjectMeta,
@@ -76,11 +121,121 @@ async fn fetch_schema(
 ) -> Result<Schema> {
     let metadata = fetch_parquet_metadata(store, file, metadata_size_hint).await?;
     let file_metadata = metadata.file_metadata();
-    let schema = parquet_to_arrow_schema(
+    match parquet_to_arrow_schema(
         file_metadata.schema_descr(),
         file_metadata.key_value_metadata(),
-    )?;
-    Ok(schema)
+    ) {
+        Ok(schema) => Ok(schema),
+        Err(e) => {
+            debug!(
+                "convert schema of {} failed: {}, retry with physical type fallback",
+                file.location, e
+            );
+            schema_with_physical_fallback(file, file_metadata)
+        }
+    }
+}
+
+/// Map a parquet primitive type to the arrow type of its physical representation,
+/// which is used when the logical type of the column is not supported by arrow.
+fn physical_fallback_type(field: &Type) -> Option<DataType> {
+    match field {
+        Type::PrimitiveType {
+            physical_type,
+            type_length,
+            ..
+        } => Some(match physical_type {
+            PhysicalType::BOOLEAN => DataType::Boolean,
+            PhysicalType::INT32 => DataType::Int32,
+            PhysicalType::INT64 => DataType::Int64,
+            PhysicalType::INT96 => DataType::Timestamp(TimeUnit::Nanosecond, None),
+            PhysicalType::FLOAT => DataType::Float32,
+            PhysicalType::DOUBLE => DataType::Float64,
+            PhysicalType::BYTE_ARRAY => DataType::Binary,
+            PhysicalType::FIXED_LEN_BYTE_ARRAY => DataType::FixedSizeBinary(*type_length),
+        }),
+        Type::GroupType { .. } => None,
+    }
+}
+
+/// Convert the schema of a parquet file column by column, reading the columns whose logical type
+/// can not be mapped to arrow as their physical type.
+/// Such fields are tagged with [`UNSUPPORTED_LOGICAL_TYPE_KEY`] in the field metadata.
+fn schema_with_physical_fallback(
+    file: &ObjectMeta,
+    file_metadata: &FileMetaData,
+) -> Result<Schema> {
+    let schema_descr = file_metadata.schema_descr();
+    let mut fields = Vec::with_capacity(schema_descr.root_schema().get_fields().len());
+    for (idx, parquet_field) in schema_descr.root_schema().get_fields().iter().enumerate()
+    {
+        let mask = ProjectionMask::roots(schema_descr, [idx]);
+        match parquet_to_arrow_schema_by_columns(
+            schema_descr,
+            mask,
+            file_metadata.key_value_metadata(),
+        ) {
+            Ok(schema) => fields.extend(schema.fields().iter().cloned()),
+            Err(e) => {
+                let info = parquet_field.get_basic_info();
+                let data_type = physical_fallback_type(parquet_field)
+                    .ok_or_else(|| DataFusionError::ParquetError(e))?;
+                let parquet_type = info
+                    .logical_type()
+                    .map(|t| format!("{:?}", t))
+                    .unwrap_or_else(|| info.converted_type().to_string());
+                warn!(
+                    "column '{}' of file {} has unsupported parquet type {}, read as {}",
+                    info.name(),
+                    file.location,
+                    parquet_type,
+                    data_type
+                );
+                let nullable =
+                    !(info.has_repetition() && info.repetition() == Repetition::REQUIRED);
+                fields.push(Arc::new(
+                    Field::new(info.name(), data_type, nullable).with_metadata(
+                        HashMap::from([(
+                            UNSUPPORTED_LOGICAL_TYPE_KEY.to_string(),
+                            parquet_type,
+                        )]),
+                    ),
+                ));
+            }
+        }
+    }
+    Ok(Schema::new(fields))
+}
+
+/// Infer the schema of a single parquet file. If the file contains a logical type which can not be
+/// mapped to arrow, the column is read as its physical type instead of failing the whole scan.
+pub async fn infer_file_schema(
+    state: &dyn Session,
+    format: &ParquetFormat,
+    store: &Arc<dyn ObjectStore>,
+    object: &ObjectMeta,
+) -> Result<SchemaRef> {
+    if is_orc_file(object) {
+        return infer_orc_schema(store.clone(), object).await;
+    }
+    if is_arrow_ipc_file(object) {
+        return infer_arrow_ipc_schema(store.clone(), object).await;
+    }
+    match format
+        .infer_schema(state, store, std::slice::from_ref(object))
+        .await
+    {
+        Ok(schema) => Ok(schema),
+        Err(e) => {
+            debug!(
+                "infer schema of {} failed: {}, retry with physical type fallback",
+                object.location, e
+            );
+            Ok(Arc::new(
+                fetch_schema(store.as_ref(), object, format.metadata_size_hint()).await?,
+            ))
+        }
+    }
 }
 
 fn clear_metadata(
@@ -174,18 +329,40 @@ impl FileFormat for LakeSoulParquetFormat {
         objects: &[ObjectMeta],
     ) -> Result<SchemaRef> {
         let schemas: Vec<_> = futures::stream::iter(objects)
-            .map(|object| {
+            .map(|object| async move {
+                if is_orc_file(object) {
+                    return Ok(infer_orc_schema(store.clone(), object)
+                        .await?
+                        .as_ref()
+                        .clone());
+                }
+                if is_arrow_ipc_file(object) {
+                    return Ok(infer_arrow_ipc_schema(store.clone(), object)
+                        .await?
+                        .as_ref()
+                        .clone());
+                }
                 fetch_schema(
                     store.as_ref(),
                     object,
                     self.parquet_format.metadata_size_hint(),
                 )
+                .await
             })
             .boxed() // Workaround https://github.com/rust-lang/rust/issues/64552
             .buffered(state.config_options().execution.meta_fetch_concurrency)
             .try_collect()
             .await?;
 
+        // the files are merged with their timestamps in the coerced unit, instead of the
+        // unit of whichever file comes last
+        let schemas = match self.conf.coerce_timestamp_unit()? {
+            Some(unit) => schemas
+                .into_iter()
+                .map(|schema| coerce_schema_timestamps(&schema, unit))
+                .collect(),
+            None => schemas,
+        };
         let mut out_meta = HashMap::new();
         let mut out_fields = CanCastSchemaBuilder::new();
         for schema in clear_metadata(schemas) {
@@ -210,7 +387,9 @@ impl FileFormat for LakeSoulParquetFormat {
             // merge fields
             fields.iter().try_for_each(|x| out_fields.try_merge(x))?
         }
-        Ok(Arc::new(out_fields.finish().with_metadata(out_meta)))
+        let schema = out_fields.finish().with_metadata(out_meta);
+        check_normalized_column_names(&schema)?;
+        Ok(Arc::new(schema))
     }
 
     async fn infer_stats(
@@ -220,6 +399,9 @@ impl FileFormat for LakeSoulParquetFormat {
         table_schema: SchemaRef,
         object: &ObjectMeta,
     ) -> Result<Statistics> {
+        if is_orc_file(object) || is_arrow_ipc_file(object) {
+            return Ok(Statistics::new_unknown(&table_schema));
+        }
         self.parquet_format
             .infer_stats(state, store, table_schema, object)
             .await
@@ -247,35 +429,88 @@ impl FileFormat for LakeSoulParquetFormat {
         // projection for Table instead of File
         let projection = conf.projection.clone();
         let target_schema = project_schema(&table_schema, projection.as_ref())?;
+        let limit = conf.limit;
+        let merges =
+            !self.conf.primary_keys.is_empty() || !self.conf.cdc_column().is_empty();
 
         let merged_projection = compute_project_column_indices(
             table_schema.clone(),
             target_schema.clone(),
-            self.conf.primary_keys_slice(),
+            &self.conf.merge_columns(),
             &self.conf.cdc_column(),
         );
         let merged_schema = project_schema(&table_schema, merged_projection.as_ref())?;
 
         // files to read
-        let flatten_conf = flatten_file_scan_config(
-            state,
-            self.parquet_format.clone(),
-            conf,
-            self.conf.primary_keys_slice(),
-            &self.conf.cdc_column(),
-            self.conf.partition_schema(),
-            target_schema.clone(),
-        )
-        .await?;
+        let (flatten_conf, skipped_files, _) =
+            flatten_file_scan_config_skipping_unreadable(
+                state,
+                self.parquet_format.clone(),
+                conf,
+                &self.conf.merge_columns(),
+                &self.conf.cdc_column(),
+                self.conf.partition_schema(),
+                target_schema.clone(),
+                self.conf.meta_fetch_concurrency()?,
+                self.conf.coerce_timestamp_unit()?,
+                self.conf.skip_unreadable_files(),
+            )
+            .await?;
+        // the rows of the skipped files are only known from the statistics of the listing
+        let skipped_rows = skipped_files
+            .iter()
+            .map(|SkippedFile { file, error }| {
+                let num_rows = file
+                    .statistics
+                    .as_ref()
+                    .and_then(|statistics| statistics.num_rows.get_value().copied());
+                warn!(
+                    "skip unreadable file {} with {} rows: {}",
+                    file.object_meta.location,
+                    num_rows
+                        .map_or("unknown".to_string(), |num_rows| num_rows.to_string()),
+                    error
+                );
+                num_rows.unwrap_or(0)
+            })
+            .sum::<usize>();
+        let flatten_conf = match limit {
+            Some(limit) if !merges => limit_file_scan_configs(flatten_conf, limit),
+            // the files are read entirely by the merge, which is limited instead
+            Some(_) => flatten_conf
+                .into_iter()
+                .map(|config| FileScanConfig {
+                    limit: None,
+                    ..config
+                })
+                .collect(),
+            None => flatten_conf,
+        };
 
         // merge on read files
+        let decryption =
+            ScanDecryption::try_new(&self.conf, state.runtime_env().clone())?;
         let merge_exec = Arc::new(MergeParquetExec::new(
             merged_schema.clone(),
             flatten_conf,
             predicate,
             self.parquet_format.metadata_size_hint(),
             self.conf.clone(),
+            decryption.as_ref(),
         )?);
+        let merge_exec: Arc<dyn ExecutionPlan> = match limit {
+            Some(limit) if merges => Arc::new(LocalLimitExec::new(merge_exec, limit)),
+            _ => merge_exec,
+        };
+        let merge_exec: Arc<dyn ExecutionPlan> = if skipped_files.is_empty() {
+            merge_exec
+        } else {
+            Arc::new(SkippedFilesExec::new(
+                merge_exec,
+                skipped_files.len(),
+                skipped_rows,
+            ))
+        };
 
         if target_schema.fields().len() < merged_schema.fields().len() {
             let mut projection_expr = vec![];
@@ -316,6 +551,53 @@ impl FileFormat for LakeSoulParquetFormat {
     }
 }
 
+/// The object store of a data file, attached to the extensions of its [`PartitionedFile`]
+/// when the files of a scan are stored in different object stores.
+#[derive(Debug, Clone)]
+pub struct FileObjectStoreUrl(pub ObjectStoreUrl);
+
+/// Returns whether the file is a split of a parquet file, reading only the row groups
+/// starting in its byte range or selected by its [`ParquetAccessPlan`] extension.
+///
+/// The splits let an external scheduler distribute the row groups of a file among several
+/// scans, each of them reading the file with its own split.
+pub fn is_file_split(file: &PartitionedFile) -> bool {
+    file.range.is_some()
+        || file
+            .extensions
+            .as_ref()
+            .is_some_and(|extensions| extensions.is::<ParquetAccessPlan>())
+}
+
+/// Attach the URL of the object store of the data file to the [`PartitionedFile`].
+pub fn with_file_object_store_url(
+    mut file: PartitionedFile,
+    object_store_url: ObjectStoreUrl,
+) -> PartitionedFile {
+    file.extensions = Some(Arc::new(FileObjectStoreUrl(object_store_url)));
+    file
+}
+
+/// Returns the URL of the object store of the data file, falling back to the object store
+/// of the scan when none is attached to the file.
+pub fn file_object_store_url(
+    file: &PartitionedFile,
+    default: &ObjectStoreUrl,
+) -> ObjectStoreUrl {
+    file.extensions
+        .as_ref()
+        .and_then(|extensions| extensions.downcast_ref::<FileObjectStoreUrl>())
+        .map(|url| url.0.clone())
+        .unwrap_or_else(|| default.clone())
+}
+
+/// Flatten the file scan config into one config per file, reading the schema and statistics
+/// of each file from its footer with up to `meta_fetch_concurrency` concurrent fetches.
+///
+/// Each flattened config reads from the object store of its file, see
+/// [`with_file_object_store_url`]. The timestamp columns of the files are read in
+/// `timestamp_unit` if set, see [`LakeSoulIOConfig::coerce_timestamp_unit`].
+#[allow(clippy::too_many_arguments)]
 pub async fn flatten_file_scan_config(
     state: &dyn Session,
     format: Arc<ParquetFormat>,
@@ -324,100 +606,665 @@ pub async fn flatten_file_scan_config(
     cdc_column: &str,
     partition_schema: SchemaRef,
     target_schema: SchemaRef,
+    meta_fetch_concurrency: usize,
+    timestamp_unit: Option<TimeUnit>,
 ) -> Result<Vec<FileScanConfig>> {
-    let object_store_url = conf.object_store_url.clone();
-    let store = state.runtime_env().object_store(object_store_url.clone())?;
-
-    let file_groups = conf.file_groups.clone();
-    let flatten_configs = futures::stream::iter(file_groups)
-        .map(|files| {
-            let store = store.clone();
-            let format = format.clone();
-            let partition_schema = partition_schema.clone();
-            let target_schema = target_schema.clone();
-            let object_store_url = object_store_url.clone();
-            let conf = conf.clone();
-            async move {
-                let configs: Vec<FileScanConfig> = futures::stream::iter(files.files())
-                    .map(|file| {
-                        let store = store.clone();
-                        let format = format.clone();
-                        let partition_schema = partition_schema.clone();
-                        let target_schema = target_schema.clone();
-                        let object_store_url = object_store_url.clone();
-                        let conf = conf.clone();
-                        async move {
-                            let objects = &[file.object_meta.clone()];
-                            let files = vec![file.clone()];
-                            let file_schema =
-                                format.infer_schema(state, &store, objects).await?;
-                            let file_schema = {
-                                let mut builder = SchemaBuilder::new();
-                                // O(nm), n = number of fields, m = number of partition columns
-                                for field in file_schema.fields() {
-                                    if partition_schema
-                                        .field_with_name(field.name())
-                                        .is_err()
-                                    {
-                                        builder.push(field.clone());
-                                    }
-                                }
-                                SchemaRef::new(builder.finish())
-                            };
-                            let statistics = format
-                                .infer_stats(
-                                    state,
-                                    &store,
-                                    file_schema.clone(),
-                                    &file.object_meta,
-                                )
-                                .await?;
-                            let projection = compute_project_column_indices(
-                                file_schema.clone(),
-                                target_schema.clone(),
-                                primary_keys,
-                                cdc_column,
-                            );
-                            let limit = conf.limit;
-                            let table_partition_cols = conf.table_partition_cols.clone();
-                            let output_ordering = conf.output_ordering.clone();
-                            let config = FileScanConfig {
-                                object_store_url: object_store_url.clone(),
-                                file_schema: file_schema.clone(),
-                                file_groups: vec![
-                                    FileGroup::new(files)
-                                        .with_statistics(Arc::new(statistics.clone())),
-                                ],
-                                constraints: Default::default(),
-                                projection,
-                                limit,
-                                table_partition_cols,
-                                output_ordering,
-                                file_compression_type: FileCompressionType::ZSTD,
-                                new_lines_in_values: false,
-                                file_source: format
-                                    .file_source()
-                                    .with_statistics(statistics),
-                                batch_size: None,
-                            };
-                            // flatten_configs.push(config);
-                            Ok::<FileScanConfig, DataFusionError>(config)
-                        }
+    let (flatten_configs, _, _) = flatten_file_scan_config_skipping_unreadable(
+        state,
+        format,
+        conf,
+        primary_keys,
+        cdc_column,
+        partition_schema,
+        target_schema,
+        meta_fetch_concurrency,
+        timestamp_unit,
+        false,
+    )
+    .await?;
+    Ok(flatten_configs)
+}
+
+/// The parquet footers fetched while flattening a scan by the locations of their files, see
+/// [`flatten_file_scan_config_skipping_unreadable`], reused to prune the row groups of the
+/// files.
+pub type ParquetFooters = HashMap<Path, Arc<ParquetMetaData>>;
+
+/// A data file left out of a scan as it could not be opened, see
+/// [`flatten_file_scan_config_skipping_unreadable`].
+#[derive(Debug)]
+pub struct SkippedFile {
+    /// The skipped file.
+    pub file: PartitionedFile,
+    /// The error of opening the file.
+    pub error: DataFusionError,
+}
+
+/// Flatten the file scan config like [`flatten_file_scan_config`], leaving out the files
+/// whose schema or footer can not be read if `skip_unreadable_files`, e.g. corrupt or
+/// missing files, instead of failing the scan.
+///
+/// The skipped files are returned with their errors, in the order of the file groups, along
+/// with the footers of the parquet files read for their schemas and statistics.
+#[allow(clippy::too_many_arguments)]
+pub async fn flatten_file_scan_config_skipping_unreadable(
+    state: &dyn Session,
+    format: Arc<ParquetFormat>,
+    conf: FileScanConfig,
+    primary_keys: &[String],
+    cdc_column: &str,
+    partition_schema: SchemaRef,
+    target_schema: SchemaRef,
+    meta_fetch_concurrency: usize,
+    timestamp_unit: Option<TimeUnit>,
+    skip_unreadable_files: bool,
+) -> Result<(Vec<FileScanConfig>, Vec<SkippedFile>, ParquetFooters)> {
+    // The footers are fetched concurrently and complete in any order, the configs are sorted
+    // back into the order of the files in the file groups afterwards.
+    let files = conf
+        .file_groups
+        .iter()
+        .flat_map(|group| group.files().iter().cloned())
+        .collect::<Vec<_>>();
+    let mut flatten_configs = futures::stream::iter(files.into_iter().enumerate())
+        .map(|(idx, file)| {
+            let skipped = skip_unreadable_files.then(|| file.clone());
+            flatten_file(
+                state,
+                &format,
+                &conf,
+                file,
+                primary_keys,
+                cdc_column,
+                &partition_schema,
+                &target_schema,
+                timestamp_unit,
+            )
+            .map(move |result| match (result, skipped) {
+                (Ok(config), _) => Ok((idx, Ok(config))),
+                (Err(error), Some(file)) => Ok((idx, Err(SkippedFile { file, error }))),
+                (Err(e), None) => Err(e),
+            })
+        })
+        .buffer_unordered(meta_fetch_concurrency.max(1))
+        .try_collect::<Vec<_>>()
+        .await?;
+    flatten_configs.sort_unstable_by_key(|(idx, _)| *idx);
+    let mut configs = Vec::with_capacity(flatten_configs.len());
+    let mut skipped_files = vec![];
+    let mut footers = ParquetFooters::new();
+    for (_, config) in flatten_configs {
+        match config {
+            Ok((config, footer)) => {
+                footers.extend(footer);
+                configs.push(config);
+            }
+            Err(skipped) => skipped_files.push(skipped),
+        }
+    }
+    Ok((configs, skipped_files, footers))
+}
+
+/// Create the [`FileScanConfig`] scanning a single file of the config, with the schema and
+/// statistics read from the footer of the file, returned along by the location of a parquet
+/// file.
+#[allow(clippy::too_many_arguments)]
+async fn flatten_file(
+    state: &dyn Session,
+    format: &Arc<ParquetFormat>,
+    conf: &FileScanConfig,
+    mut file: PartitionedFile,
+    primary_keys: &[String],
+    cdc_column: &str,
+    partition_schema: &SchemaRef,
+    target_schema: &SchemaRef,
+    timestamp_unit: Option<TimeUnit>,
+) -> Result<(FileScanConfig, Option<(Path, Arc<ParquetMetaData>)>)> {
+    let object_store_url = file_object_store_url(&file, &conf.object_store_url);
+    let store = &state.runtime_env().object_store(&object_store_url)?;
+    // the object store is carried by the flattened config, the parquet scan only expects
+    // its own extensions on the file
+    if file
+        .extensions
+        .as_ref()
+        .is_some_and(|extensions| extensions.is::<FileObjectStoreUrl>())
+    {
+        file.extensions = None;
+    }
+    let file_schema =
+        infer_file_schema(state, format.as_ref(), store, &file.object_meta).await?;
+    let file_schema = {
+        let mut builder = SchemaBuilder::new();
+        // O(nm), n = number of fields, m = number of partition columns
+        for field in file_schema.fields() {
+            if partition_schema.field_with_name(field.name()).is_err() {
+                builder.push(field.clone());
+            }
+        }
+        SchemaRef::new(builder.finish())
+    };
+    let has_unsupported_column = file_schema
+        .fields()
+        .iter()
+        .any(|field| field.metadata().contains_key(UNSUPPORTED_LOGICAL_TYPE_KEY));
+    // ORC and Arrow IPC files carry no statistics readable by the parquet format
+    let (statistics, written_sort_metadata, footer) = if is_orc_file(&file.object_meta)
+        || is_arrow_ipc_file(&file.object_meta)
+    {
+        (Statistics::new_unknown(&file_schema), vec![], None)
+    } else {
+        let metadata = fetch_parquet_metadata(
+            store.as_ref(),
+            &file.object_meta,
+            format.metadata_size_hint(),
+        )
+        .await?;
+        let statistics =
+            match statistics_from_parquet_meta_calc(&metadata, file_schema.clone()) {
+                Ok(statistics) => statistics,
+                // statistics of files with unsupported columns are best effort
+                Err(_) if has_unsupported_column => Statistics::new_unknown(&file_schema),
+                Err(e) => return Err(e),
+            };
+        let written_sort_metadata = metadata
+            .file_metadata()
+            .key_value_metadata()
+            .map(|key_values| {
+                key_values
+                    .iter()
+                    .filter(|key_value| {
+                        key_value.key == LAKESOUL_PRIMARY_KEYS_KEY
+                            || key_value.key == LAKESOUL_NULLS_FIRST_KEY
+                    })
+                    .filter_map(|key_value| {
+                        Some((key_value.key.clone(), key_value.value.clone()?))
                     })
-                    .boxed()
-                    .buffered(4)
-                    .try_collect::<Vec<_>>()
-                    .await?;
-                Ok::<Vec<FileScanConfig>, DataFusionError>(configs)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let footer = (file.object_meta.location.clone(), Arc::new(metadata));
+        (statistics, written_sort_metadata, Some(footer))
+    };
+    // the statistics are read in the unit stored in the file and cast afterwards
+    let (file_schema, statistics) = match timestamp_unit {
+        Some(unit) => coerce_file_timestamps(&file_schema, statistics, unit),
+        None => (file_schema, statistics),
+    };
+    // the merge checks the primary keys and the null ordering the file is sorted by
+    // against those of the table
+    let file_schema = if written_sort_metadata.is_empty() {
+        file_schema
+    } else {
+        let mut metadata = file_schema.metadata().clone();
+        metadata.extend(written_sort_metadata);
+        Arc::new(file_schema.as_ref().clone().with_metadata(metadata))
+    };
+    let projection = compute_project_column_indices(
+        file_schema.clone(),
+        target_schema.clone(),
+        primary_keys,
+        cdc_column,
+    );
+    // columns read as their physical type are only allowed when not projected
+    if has_unsupported_column {
+        let projected = projection
+            .clone()
+            .unwrap_or_else(|| (0..file_schema.fields().len()).collect());
+        for idx in projected {
+            let field = file_schema.field(idx);
+            if let Some(parquet_type) = field.metadata().get(UNSUPPORTED_LOGICAL_TYPE_KEY)
+            {
+                return Err(DataFusionError::NotImplemented(format!(
+                    "column '{}' of file {} has parquet type {} which can not be mapped to arrow, \
+                    exclude it from the projection to read the other columns",
+                    field.name(),
+                    file.object_meta.location,
+                    parquet_type
+                )));
+            }
+        }
+    }
+    let config = FileScanConfig {
+        object_store_url,
+        file_schema: file_schema.clone(),
+        file_groups: vec![
+            FileGroup::new(vec![file]).with_statistics(Arc::new(statistics.clone())),
+        ],
+        constraints: Default::default(),
+        projection,
+        limit: conf.limit,
+        table_partition_cols: conf.table_partition_cols.clone(),
+        output_ordering: conf.output_ordering.clone(),
+        file_compression_type: FileCompressionType::ZSTD,
+        new_lines_in_values: false,
+        file_source: format.file_source().with_statistics(statistics),
+        batch_size: None,
+    };
+    Ok((config, footer))
+}
+
+/// Coerce the timestamp columns of the schema into the time unit.
+pub fn coerce_schema_timestamps(schema: &Schema, unit: TimeUnit) -> Schema {
+    Schema::new_with_metadata(
+        schema
+            .fields()
+            .iter()
+            .map(|field| coerce_timestamp_field(field, unit))
+            .collect::<Fields>(),
+        schema.metadata().clone(),
+    )
+}
+
+/// Coerce the timestamp columns of a file schema into the time unit, casting the min and max
+/// statistics of the coerced columns along.
+///
+/// The coerced fields are marked with [`COERCED_TIMESTAMP_KEY`], as the statistics of their
+/// row groups remain in the unit of the file.
+fn coerce_file_timestamps(
+    file_schema: &SchemaRef,
+    statistics: Statistics,
+    unit: TimeUnit,
+) -> (SchemaRef, Statistics) {
+    let mut column_statistics = statistics.column_statistics;
+    let fields = file_schema
+        .fields()
+        .iter()
+        .enumerate()
+        .map(|(idx, field)| {
+            let coerced = coerce_timestamp_field(field, unit);
+            if coerced.data_type() == field.data_type() {
+                return coerced;
             }
+            if let Some(stats) = column_statistics.get_mut(idx) {
+                // the casts are monotonic, so the cast bounds still bound the cast values
+                stats.min_value = cast_precision(&stats.min_value, coerced.data_type());
+                stats.max_value = cast_precision(&stats.max_value, coerced.data_type());
+            }
+            let mut metadata = coerced.metadata().clone();
+            metadata.insert(
+                COERCED_TIMESTAMP_KEY.to_string(),
+                field.data_type().to_string(),
+            );
+            Arc::new(coerced.as_ref().clone().with_metadata(metadata))
+        })
+        .collect::<Fields>();
+    let file_schema = Arc::new(Schema::new_with_metadata(
+        fields,
+        file_schema.metadata().clone(),
+    ));
+    let statistics = Statistics {
+        column_statistics,
+        ..statistics
+    };
+    (file_schema, statistics)
+}
+
+/// Cast a min or max statistic into the data type, dropping it if the cast fails.
+fn cast_precision(
+    value: &Precision<ScalarValue>,
+    data_type: &DataType,
+) -> Precision<ScalarValue> {
+    match value {
+        Precision::Exact(value) => value
+            .cast_to(data_type)
+            .map_or(Precision::Absent, Precision::Exact),
+        Precision::Inexact(value) => value
+            .cast_to(data_type)
+            .map_or(Precision::Absent, Precision::Inexact),
+        Precision::Absent => Precision::Absent,
+    }
+}
+
+/// Collect the equalities of the primary key columns with literals from the conjuncts of the predicate.
+pub fn collect_primary_key_equalities(
+    predicate: &Arc<dyn PhysicalExpr>,
+    primary_keys: &[String],
+) -> Vec<ColumnEquality> {
+    split_conjunction(predicate)
+        .into_iter()
+        .filter_map(|conjunct| {
+            let binary = conjunct.as_any().downcast_ref::<BinaryExpr>()?;
+            if *binary.op() != Operator::Eq {
+                return None;
+            }
+            let (column, literal) = match (
+                binary.left().as_any().downcast_ref::<Column>(),
+                binary.right().as_any().downcast_ref::<Literal>(),
+            ) {
+                (Some(column), Some(literal)) => (column, literal),
+                _ => (
+                    binary.right().as_any().downcast_ref::<Column>()?,
+                    binary.left().as_any().downcast_ref::<Literal>()?,
+                ),
+            };
+            primary_keys
+                .iter()
+                .any(|pk| pk == column.name())
+                .then(|| ColumnEquality {
+                    column_name: column.name().to_string(),
+                    scalar_value: literal.value().clone(),
+                })
+        })
+        .collect()
+}
+
+/// Remove the files whose parquet bloom filters prove that no row matches all equalities.
+///
+/// Each config is expected to scan a single file, as produced by [`flatten_file_scan_config`].
+/// Files or columns without bloom filters are kept.
+pub async fn prune_file_scan_configs_by_bloom_filter(
+    state: &dyn Session,
+    configs: Vec<FileScanConfig>,
+    equalities: &[ColumnEquality],
+) -> Result<Vec<FileScanConfig>> {
+    if equalities.is_empty() {
+        return Ok(configs);
+    }
+    let kept = futures::stream::iter(configs)
+        .map(|config| async move {
+            let store = state
+                .runtime_env()
+                .object_store(config.object_store_url.clone())?;
+            for file in config.file_groups.iter().flat_map(|group| group.files()) {
+                // only parquet files carry bloom filters
+                if is_orc_file(&file.object_meta)
+                    || is_arrow_ipc_file(&file.object_meta)
+                    || bloom_filter_may_contain(
+                        store.clone(),
+                        &file.object_meta,
+                        equalities,
+                    )
+                    .await?
+                {
+                    return Ok::<_, DataFusionError>(Some(config));
+                }
+            }
+            debug!("skip file scan by bloom filter: {:?}", config.file_groups);
+            Ok(None)
         })
         .boxed()
         .buffered(4)
         .try_collect::<Vec<_>>()
         .await?;
-    Ok(flatten_configs.into_iter().flatten().collect())
+    Ok(kept.into_iter().flatten().collect())
+}
+
+/// Keep the leading configs whose files hold at least `limit` rows, so that a scan with a
+/// limit stops opening files once enough rows are produced.
+///
+/// Only the scans without merge may skip files: the rows of primary key tables and of tables
+/// with a cdc column are only known after the merge, which has to be limited instead. The
+/// rows are counted from the exact statistics of the files, so the files from the first one
+/// without an exact row count on, e.g. an ORC or Arrow IPC file or a file restricted to some
+/// of its row groups, are all kept.
+pub fn limit_file_scan_configs(
+    mut configs: Vec<FileScanConfig>,
+    limit: usize,
+) -> Vec<FileScanConfig> {
+    let mut num_rows = 0;
+    let mut kept = configs.len();
+    for (idx, config) in configs.iter().enumerate() {
+        if num_rows >= limit {
+            kept = idx;
+            break;
+        }
+        let file_rows = config.file_groups.iter().try_fold(0, |rows, group| {
+            if group
+                .files()
+                .iter()
+                .any(|file| file.extensions.is_some() || file.range.is_some())
+            {
+                return None;
+            }
+            match group.statistics().map(|statistics| statistics.num_rows) {
+                Some(Precision::Exact(group_rows)) => Some(rows + group_rows),
+                _ => None,
+            }
+        });
+        match file_rows {
+            Some(file_rows) => num_rows += file_rows,
+            None => break,
+        }
+    }
+    configs.truncate(kept);
+    configs
+}
+
+/// Restrict the scan of each parquet file to the row groups whose statistics may satisfy the
+/// predicate, removing the files without any such row group.
+///
+/// Each config is expected to scan a single file, as produced by [`flatten_file_scan_config`].
+/// The selected row groups are attached to the file as a [`ParquetAccessPlan`], so the merge
+/// on read only streams the rows of these groups. The predicate must only refer to columns
+/// shared by all versions of a row, i.e. the primary keys and the range partitions: skipping
+/// the row group of the latest version of a row would emit an older version instead.
+///
+/// The row groups are read from the `footers` fetched while flattening the scan, the footers
+/// of the other files are fetched with up to `meta_fetch_concurrency` concurrent fetches.
+pub async fn prune_file_scan_configs_by_statistics(
+    state: &dyn Session,
+    configs: Vec<FileScanConfig>,
+    predicate: &Arc<dyn PhysicalExpr>,
+    footers: &ParquetFooters,
+    metadata_size_hint: Option<usize>,
+    meta_fetch_concurrency: usize,
+) -> Result<Vec<FileScanConfig>> {
+    let metrics = ExecutionPlanMetricsSet::new();
+    let kept = futures::stream::iter(configs)
+        .map(|config| {
+            prune_row_groups_by_statistics(
+                state,
+                config,
+                predicate,
+                footers,
+                metadata_size_hint,
+                &metrics,
+            )
+        })
+        .boxed()
+        .buffered(meta_fetch_concurrency.max(1))
+        .try_collect::<Vec<_>>()
+        .await?;
+    Ok(kept.into_iter().flatten().collect())
+}
+
+/// Attach the row groups of the file of the config that may satisfy the predicate, or return
+/// `None` if none of them may.
+async fn prune_row_groups_by_statistics(
+    state: &dyn Session,
+    mut config: FileScanConfig,
+    predicate: &Arc<dyn PhysicalExpr>,
+    footers: &ParquetFooters,
+    metadata_size_hint: Option<usize>,
+    metrics: &ExecutionPlanMetricsSet,
+) -> Result<Option<FileScanConfig>> {
+    let Some(mut file) = config
+        .file_groups
+        .first()
+        .and_then(|group| group.files().first())
+        .cloned()
+    else {
+        return Ok(Some(config));
+    };
+    // only parquet files carry row group statistics, which are kept in the unit of the
+    // file for the coerced timestamp columns
+    if is_orc_file(&file.object_meta)
+        || is_arrow_ipc_file(&file.object_meta)
+        || config
+            .file_schema
+            .fields()
+            .iter()
+            .any(|field| field.metadata().contains_key(COERCED_TIMESTAMP_KEY))
+    {
+        return Ok(Some(config));
+    }
+    let pruning_predicate =
+        reassign_predicate_columns(predicate.clone(), &config.file_schema, true)
+            .and_then(|predicate| {
+                PruningPredicate::try_new(predicate, config.file_schema.clone())
+            });
+    let pruning_predicate = match pruning_predicate {
+        Ok(pruning_predicate) if !pruning_predicate.always_true() => pruning_predicate,
+        Ok(_) => return Ok(Some(config)),
+        Err(e) => {
+            debug!("predicate is not applicable to row group statistics: {}", e);
+            return Ok(Some(config));
+        }
+    };
+
+    let metadata = match footers.get(&file.object_meta.location) {
+        Some(metadata) => metadata.clone(),
+        None => {
+            let store = state
+                .runtime_env()
+                .object_store(config.object_store_url.clone())?;
+            Arc::new(
+                fetch_parquet_metadata(
+                    store.as_ref(),
+                    &file.object_meta,
+                    metadata_size_hint,
+                )
+                .await?,
+            )
+        }
+    };
+    let file_metrics =
+        ParquetFileMetrics::new(0, file.object_meta.location.as_ref(), metrics);
+    // the row groups of a split are pruned among the row groups of the split
+    let split_access_plan = file
+        .extensions
+        .as_ref()
+        .and_then(|extensions| extensions.downcast_ref::<ParquetAccessPlan>())
+        .cloned();
+    let is_access_plan_split = split_access_plan.is_some();
+    let mut row_groups = RowGroupAccessPlanFilter::new(
+        split_access_plan
+            .unwrap_or_else(|| ParquetAccessPlan::new_all(metadata.num_row_groups())),
+    );
+    row_groups.prune_by_statistics(
+        &config.file_schema,
+        metadata.file_metadata().schema_descr(),
+        metadata.row_groups(),
+        &pruning_predicate,
+        &file_metrics,
+    );
+    let access_plan = row_groups.build();
+    let num_scanned_row_groups = access_plan.row_group_indexes().len();
+    if num_scanned_row_groups == 0 {
+        debug!(
+            "skip file scan by row group statistics: {:?}",
+            config.file_groups
+        );
+        return Ok(None);
+    }
+    if is_access_plan_split || num_scanned_row_groups < metadata.num_row_groups() {
+        file.extensions = Some(Arc::new(access_plan));
+        config.file_groups = vec![FileGroup::new(vec![file])];
+    }
+    Ok(Some(config))
+}
+
+/// Returns whether any row group of the file may contain a row matching all equalities.
+async fn bloom_filter_may_contain(
+    store: Arc<dyn ObjectStore>,
+    object_meta: &ObjectMeta,
+    equalities: &[ColumnEquality],
+) -> Result<bool> {
+    let reader = ParquetObjectReader::new(store, object_meta.location.clone())
+        .with_file_size(object_meta.size);
+    let mut builder = ParquetRecordBatchStreamBuilder::new(reader).await?;
+    let metadata = builder.metadata().clone();
+    let columns = metadata.file_metadata().schema_descr().columns();
+    let equalities = equalities
+        .iter()
+        .filter_map(|equality| {
+            columns
+                .iter()
+                .position(|column| {
+                    column.path().parts() == std::slice::from_ref(&equality.column_name)
+                        && bloom_filter_physical_type(&equality.scalar_value)
+                            == Some(column.physical_type())
+                })
+                .map(|column_idx| (column_idx, &equality.scalar_value))
+        })
+        .collect::<Vec<_>>();
+    if equalities.is_empty() {
+        return Ok(true);
+    }
+    'row_groups: for row_group_idx in 0..metadata.num_row_groups() {
+        for (column_idx, value) in &equalities {
+            if let Some(bloom_filter) = builder
+                .get_row_group_column_bloom_filter(row_group_idx, *column_idx)
+                .await?
+            {
+                if !bloom_filter_check(&bloom_filter, value) {
+                    continue 'row_groups;
+                }
+            }
+        }
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// The parquet physical type whose bloom filter hashes the bytes checked by [`bloom_filter_check`].
+fn bloom_filter_physical_type(value: &ScalarValue) -> Option<PhysicalType> {
+    match value {
+        ScalarValue::Boolean(Some(_)) => Some(PhysicalType::BOOLEAN),
+        ScalarValue::Int8(Some(_))
+        | ScalarValue::Int16(Some(_))
+        | ScalarValue::Int32(Some(_))
+        | ScalarValue::UInt8(Some(_))
+        | ScalarValue::UInt16(Some(_))
+        | ScalarValue::UInt32(Some(_))
+        | ScalarValue::Date32(Some(_)) => Some(PhysicalType::INT32),
+        ScalarValue::Int64(Some(_)) | ScalarValue::UInt64(Some(_)) => {
+            Some(PhysicalType::INT64)
+        }
+        ScalarValue::Float32(Some(_)) => Some(PhysicalType::FLOAT),
+        ScalarValue::Float64(Some(_)) => Some(PhysicalType::DOUBLE),
+        ScalarValue::Utf8(Some(_))
+        | ScalarValue::LargeUtf8(Some(_))
+        | ScalarValue::Utf8View(Some(_))
+        | ScalarValue::Binary(Some(_))
+        | ScalarValue::LargeBinary(Some(_))
+        | ScalarValue::BinaryView(Some(_)) => Some(PhysicalType::BYTE_ARRAY),
+        _ => None,
+    }
 }
 
+/// Returns whether the value may be contained in the bloom filter.
+fn bloom_filter_check(bloom_filter: &Sbbf, value: &ScalarValue) -> bool {
+    match value {
+        ScalarValue::Boolean(Some(v)) => bloom_filter.check(v),
+        ScalarValue::Int8(Some(v)) => bloom_filter.check(&(*v as i32)),
+        ScalarValue::Int16(Some(v)) => bloom_filter.check(&(*v as i32)),
+        ScalarValue::Int32(Some(v)) | ScalarValue::Date32(Some(v)) => {
+            bloom_filter.check(v)
+        }
+        ScalarValue::UInt8(Some(v)) => bloom_filter.check(&(*v as i32)),
+        ScalarValue::UInt16(Some(v)) => bloom_filter.check(&(*v as i32)),
+        ScalarValue::UInt32(Some(v)) => bloom_filter.check(&(*v as i32)),
+        ScalarValue::Int64(Some(v)) => bloom_filter.check(v),
+        ScalarValue::UInt64(Some(v)) => bloom_filter.check(&(*v as i64)),
+        ScalarValue::Float32(Some(v)) => bloom_filter.check(v),
+        ScalarValue::Float64(Some(v)) => bloom_filter.check(v),
+        ScalarValue::Utf8(Some(v))
+        | ScalarValue::LargeUtf8(Some(v))
+        | ScalarValue::Utf8View(Some(v)) => bloom_filter.check(v.as_str()),
+        ScalarValue::Binary(Some(v))
+        | ScalarValue::LargeBinary(Some(v))
+        | ScalarValue::BinaryView(Some(v)) => bloom_filter.check(v.as_slice()),
+        _ => true,
+    }
+}
+
+/// Compute the indices of the top-level columns of `schema` to read for the `projected_schema`,
+/// including the primary keys, the sequence column and the cdc column needed by the merge.
+///
+/// A struct column of the projected schema may select only some of its nested fields, the
+/// column is read as a whole and pruned to the selected nested fields when it is transformed
+/// to the projected schema. Primary keys are pruned only after the merge.
 pub fn compute_project_column_indices(
     schema: SchemaRef,
     projected_schema: SchemaRef,
@@ -443,3 +1290,348 @@ pub fn compute_project_column_indices(
             .collect::<Vec<_>>(),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::{
+        ArrayRef, Int64Array, TimestampMillisecondArray, TimestampNanosecondArray,
+    };
+    use arrow_array::RecordBatch;
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
+    use datafusion::datasource::file_format::parquet::ParquetFormat;
+    use datafusion::datasource::listing::PartitionedFile;
+    use datafusion::datasource::physical_plan::parquet::ParquetAccessPlan;
+    use datafusion::datasource::physical_plan::{
+        FileGroup, FileScanConfigBuilder, ParquetSource,
+    };
+    use datafusion::execution::object_store::ObjectStoreUrl;
+    use datafusion::logical_expr::Operator;
+    use datafusion::physical_expr::expressions::{BinaryExpr, Column, Literal};
+    use datafusion::physical_plan::PhysicalExpr;
+    use datafusion::prelude::SessionContext;
+    use datafusion_common::stats::Precision;
+    use datafusion_common::{Result, ScalarValue};
+    use object_store::ObjectStore;
+    use object_store::local::LocalFileSystem;
+    use object_store::memory::InMemory;
+    use object_store::path::Path;
+
+    use super::{
+        COERCED_TIMESTAMP_KEY, ParquetFooters, flatten_file_scan_config,
+        flatten_file_scan_config_skipping_unreadable,
+        prune_file_scan_configs_by_statistics, with_file_object_store_url,
+    };
+
+    #[tokio::test]
+    async fn test_flatten_file_scan_config_keeps_file_order() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let store = LocalFileSystem::new();
+        let mut files = vec![];
+        // files of decreasing size, so that the footers of the later files tend to be
+        // fetched first
+        for (idx, num_rows) in [64, 32, 16, 8, 4, 2].into_iter().enumerate() {
+            let id = Arc::new(Int64Array::from_iter_values(0..num_rows)) as ArrayRef;
+            let batch = RecordBatch::try_from_iter([("id", id)])?;
+            let path = temp_dir.path().join(format!("part-{:0>4}.parquet", idx));
+            let mut writer = parquet::arrow::ArrowWriter::try_new(
+                std::fs::File::create(&path)?,
+                batch.schema(),
+                None,
+            )?;
+            writer.write(&batch)?;
+            writer.close()?;
+            let object_meta = store
+                .head(&Path::from_filesystem_path(&path).unwrap())
+                .await?;
+            files.push(PartitionedFile::from(object_meta));
+        }
+
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, true)]));
+        let conf = FileScanConfigBuilder::new(
+            ObjectStoreUrl::local_filesystem(),
+            schema.clone(),
+            Arc::new(ParquetSource::default()),
+        )
+        .with_file_group(FileGroup::new(files[..4].to_vec()))
+        .with_file_group(FileGroup::new(files[4..].to_vec()))
+        .build();
+        let ctx = SessionContext::new();
+        let flatten_conf = flatten_file_scan_config(
+            &ctx.state(),
+            Arc::new(ParquetFormat::default()),
+            conf,
+            &[],
+            "",
+            Arc::new(Schema::empty()),
+            schema,
+            4,
+            None,
+        )
+        .await?;
+        let flatten_files = flatten_conf
+            .iter()
+            .map(|config| {
+                config.file_groups[0].files()[0]
+                    .object_meta
+                    .location
+                    .clone()
+            })
+            .collect::<Vec<_>>();
+        let expected = files
+            .iter()
+            .map(|file| file.object_meta.location.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(flatten_files, expected);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_flatten_file_scan_config_with_multiple_object_stores() -> Result<()> {
+        let ctx = SessionContext::new();
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, true)]));
+        let mut files = vec![];
+        for bucket in ["memory://bucket-a", "memory://bucket-b"] {
+            let store = Arc::new(InMemory::new());
+            let object_store_url = ObjectStoreUrl::parse(bucket)?;
+            ctx.register_object_store(object_store_url.as_ref(), store.clone());
+
+            let id = Arc::new(Int64Array::from_iter_values(0..4)) as ArrayRef;
+            let batch = RecordBatch::try_new(schema.clone(), vec![id])?;
+            let mut buf = vec![];
+            let mut writer =
+                parquet::arrow::ArrowWriter::try_new(&mut buf, batch.schema(), None)?;
+            writer.write(&batch)?;
+            writer.close()?;
+            let location = Path::from("table/part-0000.parquet");
+            store.put(&location, buf.into()).await?;
+            let object_meta = store.head(&location).await?;
+            files.push(with_file_object_store_url(
+                PartitionedFile::from(object_meta),
+                object_store_url,
+            ));
+        }
+
+        let conf = FileScanConfigBuilder::new(
+            ObjectStoreUrl::local_filesystem(),
+            schema.clone(),
+            Arc::new(ParquetSource::default()),
+        )
+        .with_file_group(FileGroup::new(files))
+        .build();
+        let flatten_conf = flatten_file_scan_config(
+            &ctx.state(),
+            Arc::new(ParquetFormat::default()),
+            conf,
+            &[],
+            "",
+            Arc::new(Schema::empty()),
+            schema,
+            4,
+            None,
+        )
+        .await?;
+        let object_store_urls = flatten_conf
+            .iter()
+            .map(|config| config.object_store_url.as_str().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            object_store_urls,
+            ["memory://bucket-a/", "memory://bucket-b/"]
+        );
+        assert!(
+            flatten_conf
+                .iter()
+                .all(|config| config.file_groups[0].files()[0].extensions.is_none())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_prune_file_scan_configs_by_statistics() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("part-0000.parquet");
+        let id = Arc::new(Int64Array::from_iter_values(0..12)) as ArrayRef;
+        let batch = RecordBatch::try_from_iter([("id", id)])?;
+        // three row groups of ids 0..4, 4..8 and 8..12
+        let props = parquet::file::properties::WriterProperties::builder()
+            .set_max_row_group_size(4)
+            .build();
+        let mut writer = parquet::arrow::ArrowWriter::try_new(
+            std::fs::File::create(&path)?,
+            batch.schema(),
+            Some(props),
+        )?;
+        writer.write(&batch)?;
+        writer.close()?;
+        let object_meta = LocalFileSystem::new()
+            .head(&Path::from_filesystem_path(&path).unwrap())
+            .await?;
+
+        let schema = batch.schema();
+        let conf = FileScanConfigBuilder::new(
+            ObjectStoreUrl::local_filesystem(),
+            schema.clone(),
+            Arc::new(ParquetSource::default()),
+        )
+        .with_file_group(FileGroup::new(vec![PartitionedFile::from(object_meta)]))
+        .build();
+        let ctx = SessionContext::new();
+        let (flatten_conf, _, footers) = flatten_file_scan_config_skipping_unreadable(
+            &ctx.state(),
+            Arc::new(ParquetFormat::default()),
+            conf,
+            &["id".to_string()],
+            "",
+            Arc::new(Schema::empty()),
+            schema,
+            4,
+            None,
+            false,
+        )
+        .await?;
+        // the footer read for the schema is reused for the row groups
+        assert_eq!(footers.len(), 1);
+        let id_at_least = |value: i64| {
+            Arc::new(BinaryExpr::new(
+                Arc::new(Column::new("id", 0)),
+                Operator::GtEq,
+                Arc::new(Literal::new(ScalarValue::Int64(Some(value)))),
+            )) as Arc<dyn PhysicalExpr>
+        };
+
+        let pruned = prune_file_scan_configs_by_statistics(
+            &ctx.state(),
+            flatten_conf.clone(),
+            &id_at_least(9),
+            &footers,
+            None,
+            4,
+        )
+        .await?;
+        assert_eq!(pruned.len(), 1);
+        let access_plan = pruned[0].file_groups[0].files()[0]
+            .extensions
+            .as_ref()
+            .and_then(|extensions| extensions.downcast_ref::<ParquetAccessPlan>())
+            .expect("the scanned row groups are attached to the file");
+        assert_eq!(access_plan.row_group_indexes(), vec![2]);
+
+        // no row group is skipped, the file is scanned as is
+        let pruned = prune_file_scan_configs_by_statistics(
+            &ctx.state(),
+            flatten_conf.clone(),
+            &id_at_least(0),
+            &footers,
+            None,
+            4,
+        )
+        .await?;
+        assert!(pruned[0].file_groups[0].files()[0].extensions.is_none());
+
+        let pruned = prune_file_scan_configs_by_statistics(
+            &ctx.state(),
+            flatten_conf,
+            &id_at_least(100),
+            &footers,
+            None,
+            4,
+        )
+        .await?;
+        assert!(pruned.is_empty());
+        Ok(())
+    }
+    #[tokio::test]
+    async fn test_flatten_file_scan_config_coerces_timestamps() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        // the same instants written in nanoseconds and milliseconds by different engines
+        let nanos = Arc::new(TimestampNanosecondArray::from(vec![
+            1_500_000_000,
+            3_000_000_000,
+        ])) as ArrayRef;
+        let millis =
+            Arc::new(TimestampMillisecondArray::from(vec![1_000, 2_000])) as ArrayRef;
+        let mut files = vec![];
+        for (idx, ts) in [nanos, millis].into_iter().enumerate() {
+            let batch = RecordBatch::try_from_iter([("ts", ts)])?;
+            let path = temp_dir.path().join(format!("part-{:0>4}.parquet", idx));
+            let mut writer = parquet::arrow::ArrowWriter::try_new(
+                std::fs::File::create(&path)?,
+                batch.schema(),
+                None,
+            )?;
+            writer.write(&batch)?;
+            writer.close()?;
+            let object_meta = LocalFileSystem::new()
+                .head(&Path::from_filesystem_path(&path).unwrap())
+                .await?;
+            files.push(PartitionedFile::from(object_meta));
+        }
+
+        let micros = DataType::Timestamp(TimeUnit::Microsecond, None);
+        let schema = Arc::new(Schema::new(vec![Field::new("ts", micros.clone(), true)]));
+        let conf = FileScanConfigBuilder::new(
+            ObjectStoreUrl::local_filesystem(),
+            schema.clone(),
+            Arc::new(ParquetSource::default()),
+        )
+        .with_file_group(FileGroup::new(files))
+        .build();
+        let ctx = SessionContext::new();
+        let flatten_conf = flatten_file_scan_config(
+            &ctx.state(),
+            Arc::new(ParquetFormat::default()),
+            conf,
+            &[],
+            "",
+            Arc::new(Schema::empty()),
+            schema,
+            4,
+            Some(TimeUnit::Microsecond),
+        )
+        .await?;
+        let bounds = flatten_conf
+            .iter()
+            .map(|config| {
+                let field = config.file_schema.field(0);
+                assert_eq!(field.data_type(), &micros);
+                assert!(field.metadata().contains_key(COERCED_TIMESTAMP_KEY));
+                let statistics = config.file_groups[0].statistics().unwrap();
+                let column = &statistics.column_statistics[0];
+                (column.min_value.clone(), column.max_value.clone())
+            })
+            .collect::<Vec<_>>();
+        let micros_value = |value| {
+            Precision::Exact(ScalarValue::TimestampMicrosecond(Some(value), None))
+        };
+        assert_eq!(
+            bounds,
+            vec![
+                (micros_value(1_500_000), micros_value(3_000_000)),
+                (micros_value(1_000_000), micros_value(2_000_000)),
+            ]
+        );
+
+        // the row group statistics stay in the unit of the file, so they are not used
+        let ts_after = Arc::new(BinaryExpr::new(
+            Arc::new(Column::new("ts", 0)),
+            Operator::Gt,
+            Arc::new(Literal::new(ScalarValue::TimestampMicrosecond(
+                Some(10_000_000),
+                None,
+            ))),
+        )) as Arc<dyn PhysicalExpr>;
+        let pruned = prune_file_scan_configs_by_statistics(
+            &ctx.state(),
+            flatten_conf,
+            &ts_after,
+            &ParquetFooters::new(),
+            None,
+            4,
+        )
+        .await?;
+        assert_eq!(pruned.len(), 2);
+        Ok(())
+    }
+}
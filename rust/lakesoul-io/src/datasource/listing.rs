@@ -13,6 +13,7 @@ use async_trait::async_trait;
 
 use arrow::datatypes::{Schema, SchemaRef};
 
+use crate::datasource::file_format::with_file_object_store_url;
 use crate::helpers::listing_table_from_lakesoul_io_config;
 use crate::lakesoul_io_config::LakeSoulIOConfig;
 use crate::transform::uniform_schema;
@@ -158,18 +159,20 @@ impl TableProvider for LakeSoulTableProvider {
             None
         };
         let session_state = state.as_any().downcast_ref::<SessionState>().unwrap();
-        let store = state.runtime_env().object_store(object_store_url.clone())?;
+        // each file is read from the object store of its own path
         let partition_files: Result<Vec<PartitionedFile>> =
             future::try_join_all(self.listing_table_paths.iter().map(|url| {
-                let store = store.clone();
+                let store = state.runtime_env().object_store(url.object_store());
                 async move {
-                    Ok(PartitionedFile::from(
-                        store
-                            .head(&Path::from_url_path(
-                                <ListingTableUrl as AsRef<Url>>::as_ref(url).path(),
-                            )?)
-                            .await
-                            .map_err(ObjectStore)?,
+                    let object_meta = store?
+                        .head(&Path::from_url_path(
+                            <ListingTableUrl as AsRef<Url>>::as_ref(url).path(),
+                        )?)
+                        .await
+                        .map_err(ObjectStore)?;
+                    Ok(with_file_object_store_url(
+                        PartitionedFile::from(object_meta),
+                        url.object_store(),
                     ))
                 }
             }))
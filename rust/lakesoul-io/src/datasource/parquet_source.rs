@@ -17,6 +17,7 @@ use datafusion::logical_expr::{
     Expr, Expr::Column, LogicalPlan, LogicalPlanBuilder, TableProviderFilterPushDown, TableType,
 };
 use datafusion::physical_expr::PhysicalSortExpr;
+use datafusion::physical_plan::metrics::{BaselineMetrics, ExecutionPlanMetricsSet, MetricsSet};
 use datafusion::physical_plan::{
     project_schema, DisplayAs, DisplayFormatType, ExecutionPlan, SendableRecordBatchStream,
 };
@@ -197,6 +198,7 @@ struct LakeSoulParquetScanExec {
     default_column_value: Arc<HashMap<String, String>>,
     merge_operators: Arc<HashMap<String, String>>,
     primary_keys: Arc<Vec<String>>,
+    metrics: ExecutionPlanMetricsSet,
 }
 
 impl LakeSoulParquetScanExec {
@@ -232,6 +234,7 @@ impl LakeSoulParquetScanExec {
             default_column_value,
             merge_operators,
             primary_keys,
+            metrics: ExecutionPlanMetricsSet::new(),
         })
     }
 
@@ -267,6 +270,10 @@ impl ExecutionPlan for LakeSoulParquetScanExec {
         self.inputs.clone()
     }
 
+    fn metrics(&self) -> Option<MetricsSet> {
+        Some(self.metrics.clone_inner())
+    }
+
     fn with_new_children(self: Arc<Self>, _: Vec<Arc<dyn ExecutionPlan>>) -> Result<Arc<dyn ExecutionPlan>> {
         Ok(self)
     }
@@ -290,17 +297,17 @@ impl ExecutionPlan for LakeSoulParquetScanExec {
             _context.session_config().batch_size(),
         )?;
 
-        let result = ProjectionStream {
-            expr: self
-                .projections
+        let result = ProjectionStream::new(
+            self.target_schema.clone(),
+            self.projections
                 .iter()
                 .map(|&idx| {
                     datafusion::physical_expr::expressions::col(self.origin_schema().field(idx).name(), &self.schema())
                 })
                 .collect::<Result<Vec<_>>>()?,
-            schema: self.target_schema.clone(),
-            input: merged_stream,
-        };
+            merged_stream,
+            BaselineMetrics::new(&self.metrics, _partition),
+        );
 
         Ok(Box::pin(result))
     }
@@ -0,0 +1,369 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Implementation of the hash bucketed scan execution plan, and of the physical optimizer
+//! rule joining the hash buckets of two scans without repartitioning them.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use arrow_schema::SchemaRef;
+use datafusion::config::ConfigOptions;
+use datafusion::physical_expr::expressions::Column;
+use datafusion::physical_optimizer::PhysicalOptimizerRule;
+use datafusion::physical_plan::execution_plan::{Boundedness, EmissionType};
+use datafusion::physical_plan::joins::{HashJoinExec, PartitionMode, SortMergeJoinExec};
+use datafusion::physical_plan::projection::ProjectionExec;
+use datafusion::{
+    execution::TaskContext,
+    physical_expr::EquivalenceProperties,
+    physical_plan::{
+        DisplayAs, DisplayFormatType, Distribution, ExecutionPlan,
+        ExecutionPlanProperties, Partitioning, PhysicalExpr, PlanProperties,
+        SendableRecordBatchStream,
+    },
+};
+use datafusion_common::tree_node::{Transformed, TreeNode, TreeNodeRecursion};
+use datafusion_common::{DataFusionError, Result};
+
+/// [`ExecutionPlan`] implementation which exposes the hash buckets of a LakeSoul table
+/// as output partitions, the i-th output partition reads the i-th hash bucket.
+///
+/// Each input plan reads all files of one hash bucket and has a single output partition.
+/// The buckets are assigned by the hash function of LakeSoul rather than the one of
+/// [`Partitioning::Hash`], so the output partitioning is reported as unknown unless
+/// [`BucketedJoinRule`] finds the scan joined on its primary keys with a scan of the same
+/// buckets, whose rows of a key are in the bucket of the same id.
+#[derive(Debug)]
+pub struct BucketedScanExec {
+    /// The scan of each hash bucket, indexed by the hash bucket id.
+    buckets: Vec<Arc<dyn ExecutionPlan>>,
+    /// The primary keys the rows are assigned to the hash buckets by.
+    primary_keys: Vec<String>,
+    properties: PlanProperties,
+}
+
+impl BucketedScanExec {
+    /// Create a new [`BucketedScanExec`].
+    ///
+    /// # Arguments
+    ///
+    /// * `buckets` - The scan of each hash bucket, indexed by the hash bucket id
+    /// * `primary_keys` - The primary keys the rows are assigned to the hash buckets by
+    pub fn try_new(
+        buckets: Vec<Arc<dyn ExecutionPlan>>,
+        primary_keys: Vec<String>,
+    ) -> Result<Self> {
+        let Some(first) = buckets.first() else {
+            return Err(DataFusionError::Internal(
+                "BucketedScanExec requires at least one bucket".to_string(),
+            ));
+        };
+        let schema = first.schema();
+        for bucket in &buckets {
+            if bucket.output_partitioning().partition_count() != 1 {
+                return Err(DataFusionError::Internal(format!(
+                    "BucketedScanExec requires single partition buckets, got {}",
+                    bucket.output_partitioning().partition_count()
+                )));
+            }
+            if bucket.schema() != schema {
+                return Err(DataFusionError::Internal(
+                    "BucketedScanExec requires buckets of the same schema".to_string(),
+                ));
+            }
+        }
+        for primary_key in &primary_keys {
+            schema.index_of(primary_key)?;
+        }
+        let properties = PlanProperties::new(
+            EquivalenceProperties::new(schema),
+            Partitioning::UnknownPartitioning(buckets.len()),
+            EmissionType::Incremental,
+            Boundedness::Bounded,
+        );
+        Ok(Self {
+            buckets,
+            primary_keys,
+            properties,
+        })
+    }
+
+    /// Report the output partitioning as [`Partitioning::Hash`] of the primary keys.
+    ///
+    /// Only a plan combining the partitions with the ones of another [`BucketedScanExec`]
+    /// of the same buckets may rely on it, see [`BucketedJoinRule`].
+    pub fn with_hash_partitioning(&self) -> Result<Self> {
+        let schema = self.schema();
+        let exprs = self
+            .primary_keys
+            .iter()
+            .map(|primary_key| {
+                Ok(Arc::new(Column::new_with_schema(primary_key, &schema)?)
+                    as Arc<dyn PhysicalExpr>)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            buckets: self.buckets.clone(),
+            primary_keys: self.primary_keys.clone(),
+            properties: self
+                .properties
+                .clone()
+                .with_partitioning(Partitioning::Hash(exprs, self.buckets.len())),
+        })
+    }
+
+    /// Returns whether the output partitioning is reported as [`Partitioning::Hash`].
+    pub fn is_hash_partitioned(&self) -> bool {
+        matches!(self.properties.partitioning, Partitioning::Hash(..))
+    }
+
+    /// Returns the primary keys the rows are assigned to the hash buckets by.
+    pub fn primary_keys(&self) -> &[String] {
+        &self.primary_keys
+    }
+
+    /// Returns whether the rows of a primary key are in the bucket of the same id as in the
+    /// `other` scan, for the primary keys joined by `keys`, pairs of the columns of this and
+    /// the other scan.
+    ///
+    /// The hash of LakeSoul depends on the data types of the keys, so the joined keys must
+    /// be the primary keys of both scans, in order and of the same types.
+    fn is_co_partitioned(&self, other: &Self, keys: &[(String, String)]) -> bool {
+        self.buckets.len() == other.buckets.len()
+            && keys.len() == self.primary_keys.len()
+            && keys.len() == other.primary_keys.len()
+            && self.primary_keys.iter().zip(&other.primary_keys).all(
+                |(primary_key, other_primary_key)| {
+                    keys.contains(&(primary_key.clone(), other_primary_key.clone()))
+                        && match (
+                            self.schema().field_with_name(primary_key),
+                            other.schema().field_with_name(other_primary_key),
+                        ) {
+                            (Ok(field), Ok(other_field)) => {
+                                field.data_type() == other_field.data_type()
+                            }
+                            _ => false,
+                        }
+                },
+            )
+    }
+}
+
+impl DisplayAs for BucketedScanExec {
+    fn fmt_as(
+        &self,
+        _t: DisplayFormatType,
+        f: &mut std::fmt::Formatter,
+    ) -> std::fmt::Result {
+        write!(f, "BucketedScanExec: buckets={}", self.buckets.len())?;
+        if self.is_hash_partitioned() {
+            write!(f, ", hash_keys=[{}]", self.primary_keys.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl ExecutionPlan for BucketedScanExec {
+    fn name(&self) -> &str {
+        "BucketedScanExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.properties.eq_properties.schema().clone()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        &self.properties
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        self.buckets.iter().collect()
+    }
+
+    fn required_input_distribution(&self) -> Vec<Distribution> {
+        vec![Distribution::SinglePartition; self.buckets.len()]
+    }
+
+    fn benefits_from_input_partitioning(&self) -> Vec<bool> {
+        vec![false; self.buckets.len()]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let exec = Self::try_new(children, self.primary_keys.clone())?;
+        if self.is_hash_partitioned() {
+            Ok(Arc::new(exec.with_hash_partitioning()?))
+        } else {
+            Ok(Arc::new(exec))
+        }
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        let Some(bucket) = self.buckets.get(partition) else {
+            return Err(DataFusionError::Internal(format!(
+                "Invalid partition {} of BucketedScanExec with {} buckets",
+                partition,
+                self.buckets.len()
+            )));
+        };
+        bucket.execute(0, context)
+    }
+}
+
+/// [`PhysicalOptimizerRule`] reporting the partitioning of two [`BucketedScanExec`]s as
+/// [`Partitioning::Hash`] when they are joined on their primary keys, so that the join of
+/// their buckets does not repartition the rows.
+///
+/// The rows of a primary key are in the buckets of the same id in both scans as long as
+/// they have the same number of buckets and their keys the same types, although the
+/// buckets are not assigned by the hash function of DataFusion. A scan joined with any
+/// other plan is left unknown partitioned, so that both sides are repartitioned by the
+/// same hash function. The rule must run before
+/// [`datafusion::physical_optimizer::enforce_distribution::EnforceDistribution`].
+#[derive(Debug, Default)]
+pub struct BucketedJoinRule {}
+
+impl BucketedJoinRule {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl PhysicalOptimizerRule for BucketedJoinRule {
+    fn optimize(
+        &self,
+        plan: Arc<dyn ExecutionPlan>,
+        _config: &ConfigOptions,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        plan.transform_up(|plan| {
+            let Some(keys) = partitioned_join_keys(&plan) else {
+                return Ok(Transformed::no(plan));
+            };
+            let children = plan.children();
+            let (left_keys, right_keys) = keys.into_iter().unzip();
+            let (Some((left, left_keys)), Some((right, right_keys))) = (
+                bucketed_scan(children[0], left_keys),
+                bucketed_scan(children[1], right_keys),
+            ) else {
+                return Ok(Transformed::no(plan));
+            };
+            let keys = left_keys.into_iter().zip(right_keys).collect::<Vec<_>>();
+            if !left.is_co_partitioned(right, &keys) {
+                return Ok(Transformed::no(plan));
+            }
+            debug!("join the hash buckets of the scans on {:?}", keys);
+            let children = children
+                .into_iter()
+                .map(|child| with_hash_partitioned_scan(child.clone()))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Transformed::yes(plan.with_new_children(children)?))
+        })
+        .map(|transformed| transformed.data)
+    }
+
+    fn name(&self) -> &str {
+        "BucketedJoinRule"
+    }
+
+    fn schema_check(&self) -> bool {
+        true
+    }
+}
+
+/// Returns the names of the columns joined by a join requiring both inputs to be hash
+/// partitioned, as pairs of the columns of the left and right inputs.
+fn partitioned_join_keys(plan: &Arc<dyn ExecutionPlan>) -> Option<Vec<(String, String)>> {
+    let on = if let Some(join) = plan.as_any().downcast_ref::<HashJoinExec>() {
+        if join.partition_mode() == &PartitionMode::CollectLeft {
+            return None;
+        }
+        join.on()
+    } else if let Some(join) = plan.as_any().downcast_ref::<SortMergeJoinExec>() {
+        join.on()
+    } else {
+        return None;
+    };
+    on.iter()
+        .map(|(left, right)| {
+            let left = left.as_any().downcast_ref::<Column>()?;
+            let right = right.as_any().downcast_ref::<Column>()?;
+            Some((left.name().to_string(), right.name().to_string()))
+        })
+        .collect()
+}
+
+/// Returns the [`BucketedScanExec`] whose partitions are output by `plan`, with the names
+/// of the columns of the scan output by `plan` as the `columns`.
+///
+/// Only the plans keeping the rows in the partitions of their input are looked through,
+/// the columns are only followed through the projections renaming them.
+fn bucketed_scan(
+    plan: &Arc<dyn ExecutionPlan>,
+    columns: Vec<String>,
+) -> Option<(&BucketedScanExec, Vec<String>)> {
+    if let Some(scan) = plan.as_any().downcast_ref::<BucketedScanExec>() {
+        return Some((scan, columns));
+    }
+    let children = plan.children();
+    if children.len() != 1 {
+        return None;
+    }
+    let input = children[0];
+    if plan.output_partitioning().partition_count()
+        != input.output_partitioning().partition_count()
+        || plan.maintains_input_order() != [true]
+    {
+        return None;
+    }
+    let columns = match plan.as_any().downcast_ref::<ProjectionExec>() {
+        Some(projection) => columns
+            .iter()
+            .map(|column| {
+                projection
+                    .expr()
+                    .iter()
+                    .find(|(_, alias)| alias == column)
+                    .and_then(|(expr, _)| expr.as_any().downcast_ref::<Column>())
+                    .map(|input_column| input_column.name().to_string())
+            })
+            .collect::<Option<Vec<_>>>()?,
+        None => columns,
+    };
+    if columns
+        .iter()
+        .any(|column| input.schema().field_with_name(column).is_err())
+    {
+        return None;
+    }
+    bucketed_scan(input, columns)
+}
+
+/// Replace the [`BucketedScanExec`] found by [`bucketed_scan`] in `plan` by the one
+/// reporting its hash partitioning.
+fn with_hash_partitioned_scan(
+    plan: Arc<dyn ExecutionPlan>,
+) -> Result<Arc<dyn ExecutionPlan>> {
+    plan.transform_down(
+        |plan| match plan.as_any().downcast_ref::<BucketedScanExec>() {
+            Some(scan) => Ok(Transformed::new(
+                Arc::new(scan.with_hash_partitioning()?) as Arc<dyn ExecutionPlan>,
+                true,
+                TreeNodeRecursion::Jump,
+            )),
+            None => Ok(Transformed::no(plan)),
+        },
+    )
+    .map(|transformed| transformed.data)
+}
@@ -105,9 +105,19 @@ impl ExecutionPlan for DefaultColumnExec {
 
     fn with_new_children(
         self: Arc<Self>,
-        _: Vec<Arc<dyn ExecutionPlan>>,
+        mut children: Vec<Arc<dyn ExecutionPlan>>,
     ) -> Result<Arc<dyn ExecutionPlan>> {
-        Ok(self)
+        if children.len() != 1 {
+            return Err(DataFusionError::Internal(format!(
+                "DefaultColumnExec requires exactly one child, got {}",
+                children.len()
+            )));
+        }
+        Ok(Arc::new(Self::new(
+            children.remove(0),
+            self.target_schema.clone(),
+            self.default_column_value.clone(),
+        )?))
     }
 
     fn execute(
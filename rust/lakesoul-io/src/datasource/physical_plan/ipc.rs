@@ -0,0 +1,339 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Implementation of the scan execution plan of Arrow IPC data files.
+
+use std::any::Any;
+use std::io::Cursor;
+use std::sync::Arc;
+
+use arrow::buffer::Buffer;
+use arrow::compute::cast;
+use arrow::ipc::reader::{StreamDecoder, StreamReader};
+use arrow_array::{ArrayRef, RecordBatch, RecordBatchOptions};
+use arrow_schema::SchemaRef;
+use bytes::Bytes;
+use datafusion::datasource::physical_plan::FileScanConfig;
+use datafusion::physical_plan::execution_plan::{Boundedness, EmissionType};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::{
+    datasource::listing::PartitionedFile,
+    execution::TaskContext,
+    physical_expr::EquivalenceProperties,
+    physical_plan::{
+        DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning, PlanProperties,
+        SendableRecordBatchStream,
+    },
+};
+use datafusion_common::{DataFusionError, Result};
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use object_store::{ObjectMeta, ObjectStore};
+
+/// The file extension of Arrow IPC data files.
+const ARROW_IPC_FILE_EXTENSION: &str = "arrow";
+
+/// The continuation marker preceding the length of each message of an IPC stream.
+const IPC_CONTINUATION_MARKER: [u8; 4] = [0xff; 4];
+
+/// Returns whether the data file is an Arrow IPC file, judged by its file extension.
+pub fn is_arrow_ipc_file(object_meta: &ObjectMeta) -> bool {
+    object_meta.location.extension() == Some(ARROW_IPC_FILE_EXTENSION)
+}
+
+/// Returns whether all data files of the scan config are Arrow IPC files.
+pub fn is_arrow_ipc_scan_config(config: &FileScanConfig) -> bool {
+    let mut files = config
+        .file_groups
+        .iter()
+        .flat_map(|group| group.files())
+        .peekable();
+    files.peek().is_some() && files.all(|file| is_arrow_ipc_file(&file.object_meta))
+}
+
+/// Infer the arrow schema of an Arrow IPC stream file from its leading schema message,
+/// without fetching the record batches of the file.
+pub async fn infer_arrow_ipc_schema(
+    store: Arc<dyn ObjectStore>,
+    object_meta: &ObjectMeta,
+) -> Result<SchemaRef> {
+    let prefix = store.get_range(&object_meta.location, 0..8).await?;
+    let (marker, length) = prefix.split_at(4);
+    if marker != IPC_CONTINUATION_MARKER {
+        return Err(DataFusionError::Execution(format!(
+            "Arrow IPC file {} does not start with a stream message",
+            object_meta.location
+        )));
+    }
+    let length = i32::from_le_bytes(length.try_into().unwrap()).max(0) as u64;
+    let message = store
+        .get_range(&object_meta.location, 0..8 + length)
+        .await?;
+    let reader = StreamReader::try_new(Cursor::new(message), None)?;
+    Ok(reader.schema())
+}
+
+/// [`ExecutionPlan`] implementation which scans Arrow IPC stream files of a [`FileScanConfig`].
+///
+/// The output schema is the same as the one of a parquet scan of the config, so that IPC and
+/// parquet scans can be fed into the same [`MergeParquetExec`](super::MergeParquetExec).
+#[derive(Debug)]
+pub struct ArrowIpcScanExec {
+    /// The files to scan, the schema of the files and the projection.
+    config: FileScanConfig,
+    /// The projected output schema.
+    schema: SchemaRef,
+    properties: PlanProperties,
+}
+
+impl ArrowIpcScanExec {
+    /// Create a new [`ArrowIpcScanExec`], each file group of the config is scanned by one output partition.
+    pub fn try_new(config: FileScanConfig) -> Result<Self> {
+        let (schema, _, _, _) = config.project();
+        let properties = PlanProperties::new(
+            EquivalenceProperties::new(schema.clone()),
+            Partitioning::UnknownPartitioning(config.file_groups.len()),
+            EmissionType::Incremental,
+            Boundedness::Bounded,
+        );
+        Ok(Self {
+            config,
+            schema,
+            properties,
+        })
+    }
+
+    /// The scan config of the plan.
+    pub fn config(&self) -> &FileScanConfig {
+        &self.config
+    }
+}
+
+impl DisplayAs for ArrowIpcScanExec {
+    fn fmt_as(
+        &self,
+        _t: DisplayFormatType,
+        f: &mut std::fmt::Formatter,
+    ) -> std::fmt::Result {
+        write!(
+            f,
+            "ArrowIpcScanExec: file_groups={}",
+            self.config.file_groups.len()
+        )
+    }
+}
+
+impl ExecutionPlan for ArrowIpcScanExec {
+    fn name(&self) -> &str {
+        "ArrowIpcScanExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        &self.properties
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        _children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(self)
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        let Some(file_group) = self.config.file_groups.get(partition) else {
+            return Err(DataFusionError::Internal(format!(
+                "Invalid partition {} of ArrowIpcScanExec with {} file groups",
+                partition,
+                self.config.file_groups.len()
+            )));
+        };
+        let store = context
+            .runtime_env()
+            .object_store(&self.config.object_store_url)?;
+        let config = Arc::new(self.config.clone());
+        let schema = self.schema.clone();
+        let stream = futures::stream::iter(file_group.files().to_vec())
+            .then(move |file| {
+                scan_arrow_ipc_file(store.clone(), file, config.clone(), schema.clone())
+            })
+            .try_flatten();
+        Ok(Box::pin(RecordBatchStreamAdapter::new(
+            self.schema.clone(),
+            stream,
+        )))
+    }
+}
+
+/// The state of the decoding of an Arrow IPC file: the remaining bytes of the file, the
+/// decoder and the not yet decoded part of the last fetched bytes.
+type IpcDecodeState = (
+    BoxStream<'static, object_store::Result<Bytes>>,
+    StreamDecoder,
+    Buffer,
+);
+
+/// Decode the next record batch of an Arrow IPC file, fetching its bytes as they are needed.
+async fn next_ipc_batch(
+    (mut bytes, mut decoder, mut buffer): IpcDecodeState,
+) -> Result<Option<(RecordBatch, IpcDecodeState)>> {
+    loop {
+        if buffer.is_empty() {
+            match bytes.try_next().await? {
+                Some(next) => buffer = Buffer::from(next),
+                None => {
+                    decoder.finish()?;
+                    return Ok(None);
+                }
+            }
+        }
+        if let Some(batch) = decoder.decode(&mut buffer)? {
+            return Ok(Some((batch, (bytes, decoder, buffer))));
+        }
+    }
+}
+
+/// Scan the projected columns of an Arrow IPC file, appending the values of the projected partition columns.
+///
+/// The file is streamed from the object store, so only the batch being decoded is held in memory.
+async fn scan_arrow_ipc_file(
+    store: Arc<dyn ObjectStore>,
+    file: PartitionedFile,
+    config: Arc<FileScanConfig>,
+    schema: SchemaRef,
+) -> Result<BoxStream<'static, Result<RecordBatch>>> {
+    let bytes = store.get(&file.object_meta.location).await?.into_stream();
+    let batches = futures::stream::try_unfold(
+        (
+            bytes,
+            StreamDecoder::new(),
+            Buffer::from_vec(Vec::<u8>::new()),
+        ),
+        next_ipc_batch,
+    );
+    let file_columns = config.file_schema.fields().len();
+    let projection = config.projection.clone().unwrap_or_else(|| {
+        (0..file_columns + config.table_partition_cols.len()).collect()
+    });
+    Ok(batches
+        .map(move |batch| {
+            let batch = batch?;
+            let columns = projection
+                .iter()
+                .zip(schema.fields())
+                .map(|(idx, field)| {
+                    let column = if *idx < file_columns {
+                        batch.column_by_name(field.name()).cloned().ok_or_else(|| {
+                            DataFusionError::Execution(format!(
+                                "column {} not found in Arrow IPC file {}",
+                                field.name(),
+                                file.object_meta.location
+                            ))
+                        })?
+                    } else {
+                        file.partition_values[*idx - file_columns]
+                            .to_array_of_size(batch.num_rows())?
+                    };
+                    if column.data_type() == field.data_type() {
+                        Ok(column)
+                    } else {
+                        Ok(cast(&column, field.data_type())?)
+                    }
+                })
+                .collect::<Result<Vec<ArrayRef>>>()?;
+            Ok(RecordBatch::try_new_with_options(
+                schema.clone(),
+                columns,
+                &RecordBatchOptions::new().with_row_count(Some(batch.num_rows())),
+            )?)
+        })
+        .boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::{ArrayRef, Int64Array, StringArray};
+    use arrow::util::pretty::pretty_format_batches;
+    use arrow_array::RecordBatch;
+    use datafusion::datasource::listing::PartitionedFile;
+    use datafusion::datasource::physical_plan::{
+        FileGroup, FileScanConfigBuilder, ParquetSource,
+    };
+    use datafusion::execution::object_store::ObjectStoreUrl;
+    use datafusion::physical_plan::{ExecutionPlan, collect};
+    use datafusion::prelude::SessionContext;
+    use datafusion_common::Result;
+    use object_store::ObjectStore;
+    use object_store::local::LocalFileSystem;
+    use object_store::path::Path;
+
+    use super::{ArrowIpcScanExec, infer_arrow_ipc_schema, is_arrow_ipc_file};
+    use crate::async_writer::{ArrowIpcAsyncWriter, AsyncBatchWriter};
+    use crate::lakesoul_io_config::{DataFileFormat, LakeSoulIOConfigBuilder};
+
+    #[tokio::test]
+    async fn test_write_and_scan_arrow_ipc_file() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let file_path = temp_dir.path().join("part-0000.arrow");
+
+        let id = Arc::new(Int64Array::from(vec![1, 2, 3])) as ArrayRef;
+        let name = Arc::new(StringArray::from(vec!["a", "b", "c"])) as ArrayRef;
+        let batch = RecordBatch::try_from_iter([("id", id), ("name", name)])?;
+        let config = LakeSoulIOConfigBuilder::new()
+            .with_schema(batch.schema())
+            .with_files(vec![file_path.to_str().unwrap().to_string()])
+            .with_data_file_format(DataFileFormat::ArrowIpc)
+            .build();
+        let mut writer = Box::new(ArrowIpcAsyncWriter::try_new(config).await?);
+        writer.write_record_batch(batch.slice(0, 2)).await?;
+        writer.write_record_batch(batch.slice(2, 1)).await?;
+        let flush_result = writer.flush_and_close().await?;
+        assert_eq!(flush_result[0].3.num_rows, 3);
+
+        let store = Arc::new(LocalFileSystem::new()) as Arc<dyn ObjectStore>;
+        let object_meta = store
+            .head(&Path::from_filesystem_path(&file_path).unwrap())
+            .await?;
+        assert!(is_arrow_ipc_file(&object_meta));
+        let file_schema = infer_arrow_ipc_schema(store, &object_meta).await?;
+        assert_eq!(file_schema.field(0).name(), "id");
+
+        let config = FileScanConfigBuilder::new(
+            ObjectStoreUrl::local_filesystem(),
+            file_schema,
+            Arc::new(ParquetSource::default()),
+        )
+        .with_file_group(FileGroup::new(vec![PartitionedFile::from(object_meta)]))
+        .with_projection(Some(vec![1]))
+        .build();
+        let exec = Arc::new(ArrowIpcScanExec::try_new(config)?) as Arc<dyn ExecutionPlan>;
+        let batches = collect(exec, SessionContext::new().task_ctx()).await?;
+        assert_eq!(
+            pretty_format_batches(&batches)?.to_string(),
+            [
+                "+------+", "| name |", "+------+", "| a    |", "| b    |", "| c    |",
+                "+------+",
+            ]
+            .join("\n")
+        );
+        Ok(())
+    }
+}
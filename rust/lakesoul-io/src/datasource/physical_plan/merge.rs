@@ -7,11 +7,19 @@
 use std::sync::Arc;
 use std::{any::Any, collections::HashMap};
 
-use arrow_schema::{Field, Schema, SchemaRef};
+use arrow::compute::concat_batches;
+use arrow_array::RecordBatch;
+use arrow_schema::{DataType, Field, Schema, SchemaRef, SortOptions};
 use datafusion::dataframe::DataFrame;
 use datafusion::logical_expr::Expr;
-use datafusion::physical_expr::{EquivalenceProperties, LexOrdering};
+use datafusion::physical_expr::expressions::Column;
+use datafusion::physical_expr::{
+    EquivalenceProperties, LexOrdering, LexRequirement, PhysicalSortExpr,
+};
 use datafusion::physical_plan::execution_plan::{Boundedness, EmissionType};
+use datafusion::physical_plan::filter::batch_filter;
+use datafusion::physical_plan::sorts::sort::SortExec;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
 use datafusion::physical_plan::{ExecutionPlanProperties, Partitioning, PlanProperties};
 #[allow(deprecated)]
 use datafusion::{
@@ -24,11 +32,18 @@ use datafusion::{
 };
 use datafusion_common::{DFSchemaRef, DataFusionError, Result};
 use datafusion_substrait::substrait::proto::Plan;
+use futures::StreamExt;
 
+use super::defatul_column::DefaultColumnExec;
+use super::{
+    ArrowIpcScanExec, OrcScanExec, is_arrow_ipc_scan_config, is_orc_scan_config,
+};
+use crate::constant::{LAKESOUL_NULLS_FIRST_KEY, LAKESOUL_PRIMARY_KEYS_KEY};
 use crate::default_column_stream::DefaultColumnStream;
 use crate::default_column_stream::empty_schema_stream::EmptySchemaStream;
+use crate::encryption::ScanDecryption;
 use crate::filter::parser::Parser as FilterParser;
-use crate::lakesoul_io_config::LakeSoulIOConfig;
+use crate::lakesoul_io_config::{LakeSoulIOConfig, MergeStrategy};
 use crate::sorted_merge::merge_operator::MergeOperator;
 use crate::sorted_merge::sorted_stream_merger::{SortedStream, SortedStreamMerger};
 
@@ -47,25 +62,67 @@ pub struct MergeParquetExec {
     inputs: Vec<Arc<dyn ExecutionPlan>>,
     /// The io config of the merge on read operation.
     io_config: LakeSoulIOConfig,
+    /// The predicate applied to the merged rows.
+    predicate: Option<Arc<dyn PhysicalExpr>>,
+    /// The ordering by the primary keys required from each input, only set for the inputs
+    /// of files sorted by other keys, see [`align_inputs`].
+    input_orderings: Vec<Option<LexOrdering>>,
     /// The properties of the merge on read operation.
     properties: PlanProperties,
 }
 
 impl MergeParquetExec {
     /// Create a new Parquet reader execution plan provided file list and schema.
+    ///
+    /// The parquet files are decrypted with the `decryption` if given, see
+    /// [`crate::encryption`].
     pub fn new(
         schema: SchemaRef,
         flatten_configs: Vec<FileScanConfig>,
         predicate: Option<Arc<dyn PhysicalExpr>>,
         metadata_size_hint: Option<usize>,
         io_config: LakeSoulIOConfig,
+        decryption: Option<&ScanDecryption>,
     ) -> Result<Self> {
-        // source file parquet scan
+        let inputs = Self::scan_inputs(
+            flatten_configs,
+            predicate,
+            metadata_size_hint,
+            decryption,
+        )?;
+        Self::new_with_scan_inputs(schema, inputs, io_config)
+    }
+
+    /// Create the scans of the files of the flattened configs, one per file, to be merged
+    /// by [`MergeParquetExec::new_with_scan_inputs`].
+    ///
+    /// The scans may be wrapped before the merge, e.g. to drop the deleted rows of a file.
+    pub fn scan_inputs(
+        flatten_configs: Vec<FileScanConfig>,
+        predicate: Option<Arc<dyn PhysicalExpr>>,
+        metadata_size_hint: Option<usize>,
+        decryption: Option<&ScanDecryption>,
+    ) -> Result<Vec<Arc<dyn ExecutionPlan>>> {
+        // source file parquet, orc or arrow ipc scan
         let mut inputs = Vec::<Arc<dyn ExecutionPlan>>::new();
         for config in flatten_configs {
+            if is_orc_scan_config(&config) {
+                inputs.push(Arc::new(OrcScanExec::try_new(config)?));
+                continue;
+            }
+            if is_arrow_ipc_scan_config(&config) {
+                inputs.push(Arc::new(ArrowIpcScanExec::try_new(config)?));
+                continue;
+            }
             let single_exec = Arc::new({
+                let reader_factory = decryption
+                    .map(|decryption| decryption.reader_factory(&config))
+                    .transpose()?;
                 #[allow(deprecated)]
                 let mut builder = ParquetExec::builder(config);
+                if let Some(reader_factory) = reader_factory {
+                    builder = builder.with_parquet_file_reader_factory(reader_factory);
+                }
                 if let Some(predicate) = predicate.clone() {
                     builder = builder.with_predicate(predicate.clone());
                 }
@@ -76,6 +133,16 @@ impl MergeParquetExec {
             });
             inputs.push(single_exec);
         }
+        Ok(inputs)
+    }
+
+    /// Create the merge of the scans of the files created by
+    /// [`MergeParquetExec::scan_inputs`].
+    pub fn new_with_scan_inputs(
+        schema: SchemaRef,
+        inputs: Vec<Arc<dyn ExecutionPlan>>,
+        io_config: LakeSoulIOConfig,
+    ) -> Result<Self> {
         // O(nml), n = number of schema fields, m = number of file schema fields, l = number of files
         let schema = SchemaRef::new(Schema::new(
             schema
@@ -105,6 +172,8 @@ impl MergeParquetExec {
         let default_column_value = Arc::new(io_config.default_column_value);
         let merge_operators: Arc<HashMap<String, String>> =
             Arc::new(io_config.merge_operators);
+        let (inputs, input_orderings) =
+            align_inputs(inputs, &schema, &config, &default_column_value)?;
 
         Ok(Self {
             schema: schema.clone(),
@@ -113,6 +182,8 @@ impl MergeParquetExec {
             default_column_value,
             merge_operators,
             io_config: config,
+            predicate: None,
+            input_orderings,
             properties: PlanProperties::new(
                 EquivalenceProperties::new(schema),
                 Partitioning::UnknownPartitioning(1),
@@ -132,9 +203,27 @@ impl MergeParquetExec {
             "MergeParquetExec::new_with_inputs: {:?}, {:?}, {:?}",
             schema, io_config, default_column_value
         );
+        // columns absent in any of the files are read as nulls
+        let schema = SchemaRef::new(Schema::new_with_metadata(
+            schema
+                .fields()
+                .iter()
+                .map(|field| {
+                    let nullable = field.is_nullable()
+                        || inputs.iter().any(|plan| {
+                            plan.schema().column_with_name(field.name()).is_none()
+                                && !default_column_value.contains_key(field.name())
+                        });
+                    Arc::new(field.as_ref().clone().with_nullable(nullable))
+                })
+                .collect::<Vec<_>>(),
+            schema.metadata().clone(),
+        ));
         let config = io_config.clone();
         let primary_keys = Arc::new(io_config.primary_keys);
         let merge_operators = Arc::new(io_config.merge_operators);
+        let (inputs, input_orderings) =
+            align_inputs(inputs, &schema, &config, &default_column_value)?;
 
         Ok(Self {
             schema: schema.clone(),
@@ -143,6 +232,8 @@ impl MergeParquetExec {
             default_column_value,
             merge_operators,
             io_config: config,
+            predicate: None,
+            input_orderings,
             properties: PlanProperties::new(
                 EquivalenceProperties::new(schema),
                 Partitioning::UnknownPartitioning(1),
@@ -152,6 +243,15 @@ impl MergeParquetExec {
         })
     }
 
+    /// Apply the predicate to the merged rows.
+    ///
+    /// Filtering the inputs before the merge could drop the latest version of a row and emit an
+    /// older one instead, so filters on non primary key columns must be applied after the merge.
+    pub fn with_predicate(mut self, predicate: Option<Arc<dyn PhysicalExpr>>) -> Self {
+        self.predicate = predicate;
+        self
+    }
+
     pub fn primary_keys(&self) -> Arc<Vec<String>> {
         self.primary_keys.clone()
     }
@@ -171,7 +271,10 @@ impl DisplayAs for MergeParquetExec {
         _t: DisplayFormatType,
         f: &mut std::fmt::Formatter,
     ) -> std::fmt::Result {
-        write!(f, "MergeParquetExec")
+        match &self.predicate {
+            Some(predicate) => write!(f, "MergeParquetExec: predicate={}", predicate),
+            None => write!(f, "MergeParquetExec"),
+        }
     }
 }
 
@@ -229,10 +332,19 @@ impl ExecutionPlan for MergeParquetExec {
             default_column_value: self.default_column_value(),
             merge_operators: self.merge_operators(),
             io_config: self.io_config.clone(),
+            predicate: self.predicate.clone(),
+            input_orderings: self.input_orderings.clone(),
             properties: self.properties.clone(),
         }))
     }
 
+    fn required_input_ordering(&self) -> Vec<Option<LexRequirement>> {
+        self.input_orderings
+            .iter()
+            .map(|ordering| ordering.clone().map(LexRequirement::from))
+            .collect()
+    }
+
     fn execute(
         &self,
         partition: usize,
@@ -259,20 +371,213 @@ impl ExecutionPlan for MergeParquetExec {
             stream_init_futs.push(stream);
         }
 
+        let merge_batch_size = self.io_config.merge_batch_size()?;
         let merged_stream = merge_stream(
             stream_init_futs,
             self.schema(),
             self.primary_keys(),
             self.default_column_value(),
             self.merge_operators(),
-            context.session_config().batch_size(),
+            merge_batch_size.unwrap_or(context.session_config().batch_size()),
             self.io_config.clone(),
         )?;
 
-        Ok(merged_stream)
+        let filtered_stream = match &self.predicate {
+            Some(predicate) => {
+                let predicate = predicate.clone();
+                Box::pin(RecordBatchStreamAdapter::new(
+                    self.schema(),
+                    merged_stream.map(move |batch| {
+                        batch.and_then(|batch| batch_filter(&batch, &predicate))
+                    }),
+                ))
+            }
+            None => merged_stream,
+        };
+        match merge_batch_size {
+            Some(target_batch_size) => {
+                Ok(coalesce_stream(filtered_stream, target_batch_size))
+            }
+            None => Ok(filtered_stream),
+        }
     }
 }
 
+/// Coalesce the batches of the stream into batches of at least `target_batch_size` rows,
+/// except the last one.
+///
+/// The batches are concatenated in the order they are polled, so the rows keep the order
+/// of the merge by the primary keys.
+fn coalesce_stream(
+    stream: SendableRecordBatchStream,
+    target_batch_size: usize,
+) -> SendableRecordBatchStream {
+    let schema = stream.schema();
+    let coalesced = futures::stream::unfold(Some(stream), move |stream| async move {
+        let mut stream = stream?;
+        let mut buffered = Vec::<RecordBatch>::new();
+        let mut num_rows = 0;
+        let mut finished = false;
+        while num_rows < target_batch_size {
+            match stream.next().await {
+                Some(Ok(batch)) if batch.num_rows() == 0 => {}
+                Some(Ok(batch)) => {
+                    num_rows += batch.num_rows();
+                    buffered.push(batch);
+                }
+                Some(Err(e)) => return Some((Err(e), None)),
+                None if buffered.is_empty() => return None,
+                None => {
+                    finished = true;
+                    break;
+                }
+            }
+        }
+        let batch = match buffered.len() {
+            1 => Ok(buffered.remove(0)),
+            _ => {
+                concat_batches(&stream.schema(), &buffered).map_err(DataFusionError::from)
+            }
+        };
+        let stream = (!finished).then_some(stream);
+        Some((batch, stream))
+    });
+    Box::pin(RecordBatchStreamAdapter::new(schema, coalesced))
+}
+
+/// Returns whether the rows of the files are merged by their primary keys.
+fn merges_by_primary_keys(config: &LakeSoulIOConfig) -> Result<bool> {
+    // the change feed emits every written row instead of the latest row of each primary key
+    Ok(!(config.skip_merge_on_read()
+        || config.change_feed()?.is_some()
+        || config.primary_keys.is_empty()))
+}
+
+/// Align the output of each file scan to the schema of the merge.
+///
+/// The columns of a file are cast to their type in the table, e.g. a column widened from
+/// Int32 to Int64 after the file was written, except the struct primary keys which are
+/// compared with their full type. The columns absent in a file are materialized as nulls or
+/// their default value, unless the rows are merged by their primary keys: a file written by
+/// a partial upsert lacks the columns it does not update, which the merge takes from the
+/// older versions of the row.
+///
+/// The sorted merge expects every file sorted by the primary keys of the table. A file
+/// written before the primary keys changed, e.g. before a key column was added, is sorted by
+/// its old keys instead, so it is sorted again by the current keys before the merge and
+/// the ordering is returned as the requirement of the input. Its rows keep the old
+/// versioning, a row merges with the rows of the other files sharing all the current keys.
+/// Files which do not record their primary keys, e.g. written by older versions or other
+/// writers, may be sorted by other keys as well, so they are sorted again too.
+fn align_inputs(
+    inputs: Vec<Arc<dyn ExecutionPlan>>,
+    schema: &SchemaRef,
+    config: &LakeSoulIOConfig,
+    default_column_value: &Arc<HashMap<String, String>>,
+) -> Result<(Vec<Arc<dyn ExecutionPlan>>, Vec<Option<LexOrdering>>)> {
+    let keep_absent_columns = merges_by_primary_keys(config)?;
+    let aligned_inputs = inputs
+        .into_iter()
+        .map(|input| {
+            let input_schema = input.schema();
+            let sorted = !keep_absent_columns
+                || sorted_by_primary_keys(
+                    &input_schema,
+                    &config.primary_keys,
+                    config.nulls_first(),
+                );
+            let mut aligned = input_schema.fields().len() == schema.fields().len();
+            let fields = schema
+                .fields()
+                .iter()
+                .filter_map(|field| match input_schema.column_with_name(field.name()) {
+                    Some((idx, input_field)) => {
+                        aligned &= idx < schema.fields().len()
+                            && schema.field(idx).name() == field.name();
+                        if config.primary_keys.contains(field.name())
+                            && matches!(field.data_type(), DataType::Struct(_))
+                            && matches!(input_field.data_type(), DataType::Struct(_))
+                        {
+                            Some(Arc::new(
+                                input_field.clone().with_nullable(field.is_nullable()),
+                            ))
+                        } else {
+                            aligned &= input_field.data_type() == field.data_type();
+                            Some(field.clone())
+                        }
+                    }
+                    None if keep_absent_columns => None,
+                    None => {
+                        aligned = false;
+                        Some(field.clone())
+                    }
+                })
+                .collect::<Vec<_>>();
+            let input = if aligned {
+                input
+            } else {
+                Arc::new(DefaultColumnExec::new(
+                    input,
+                    Arc::new(Schema::new(fields)),
+                    default_column_value.clone(),
+                )?) as Arc<dyn ExecutionPlan>
+            };
+            match primary_key_ordering(
+                &input.schema(),
+                &config.primary_keys,
+                config.primary_key_sort_options(),
+            ) {
+                Some(ordering) if !sorted => {
+                    let sort = SortExec::new(ordering.clone(), input);
+                    Ok((Arc::new(sort) as Arc<dyn ExecutionPlan>, Some(ordering)))
+                }
+                _ => Ok((input, None)),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(aligned_inputs.into_iter().unzip())
+}
+
+/// Returns whether the rows of the input are sorted by the primary keys with the null
+/// ordering, judged by the primary keys and the null ordering recorded by the file in the
+/// schema metadata of its scan. The files recording no primary keys are not known to be
+/// sorted, the ones recording no null ordering order the nulls first.
+fn sorted_by_primary_keys(
+    input_schema: &Schema,
+    primary_keys: &[String],
+    nulls_first: bool,
+) -> bool {
+    let metadata = input_schema.metadata();
+    let written_nulls_first = metadata
+        .get(LAKESOUL_NULLS_FIRST_KEY)
+        .is_none_or(|written| written == "true");
+    metadata
+        .get(LAKESOUL_PRIMARY_KEYS_KEY)
+        .and_then(|keys| serde_json::from_str::<Vec<String>>(keys).ok())
+        .is_some_and(|keys| keys == primary_keys)
+        && written_nulls_first == nulls_first
+}
+
+/// The ordering of the rows of the input by the primary keys, absent if none of the keys is
+/// in the input. A key absent in the input is null in all of its rows and orders nothing.
+pub fn primary_key_ordering(
+    schema: &Schema,
+    primary_keys: &[String],
+    options: SortOptions,
+) -> Option<LexOrdering> {
+    let sort_exprs = primary_keys
+        .iter()
+        .filter_map(|pk| {
+            let idx = schema.index_of(pk).ok()?;
+            Some(PhysicalSortExpr {
+                expr: Arc::new(Column::new(pk, idx)),
+                options,
+            })
+        })
+        .collect::<Vec<_>>();
+    (!sort_exprs.is_empty()).then(|| LexOrdering::new(sort_exprs))
+}
+
 /// Merge the streams into a single stream.
 pub fn merge_stream(
     streams: Vec<SendableRecordBatchStream>,
@@ -284,13 +589,10 @@ pub fn merge_stream(
     config: LakeSoulIOConfig,
 ) -> Result<SendableRecordBatchStream> {
     debug!("merge_stream with config= {:?}", &config);
-    let merge_on_read = if config.skip_merge_on_read() || config.primary_keys.is_empty() {
-        false
-    } else {
-        !(config.files.len() == 1
+    let merge_on_read = merges_by_primary_keys(&config)?
+        && !(config.files.len() == 1
             && config.merge_operators.is_empty()
-            && config.is_compacted())
-    };
+            && config.is_compacted());
     let merge_stream = if !merge_on_read {
         Box::pin(DefaultColumnStream::new_from_streams_with_default(
             streams,
@@ -298,28 +600,58 @@ pub fn merge_stream(
             default_column_value,
         ))
     } else {
+        // The schema may select only some nested fields of a struct column. As the primary keys
+        // are compared as a whole, they are merged with their full type read from the files
+        // and pruned to the selected nested fields afterwards.
         let merge_schema = Arc::new(Schema::new(
             schema
                 .fields
                 .iter()
                 .filter_map(|field| {
-                    if default_column_value.get(field.name()).is_none() {
-                        Some(field.clone())
-                    } else {
-                        None
+                    if default_column_value.get(field.name()).is_some() {
+                        return None;
+                    }
+                    if primary_keys.contains(field.name())
+                        && matches!(field.data_type(), DataType::Struct(_))
+                    {
+                        if let Some(full_field) = streams.first().and_then(|stream| {
+                            stream
+                                .schema()
+                                .field_with_name(field.name())
+                                .ok()
+                                .filter(|full_field| {
+                                    matches!(full_field.data_type(), DataType::Struct(_))
+                                })
+                                .cloned()
+                        }) {
+                            return Some(Arc::new(
+                                full_field.with_nullable(field.is_nullable()),
+                            ));
+                        }
                     }
+                    Some(field.clone())
                 })
                 .collect::<Vec<_>>(),
         )); // merge_schema
+        // with partial updates the null values of a version keep the previous values, but
+        // the cdc kind and the sequence are always the ones of the last version
+        let default_merge_op = match config.merge_strategy()? {
+            MergeStrategy::LastWriteWins => MergeOperator::UseLast,
+            MergeStrategy::PartialUpdate => MergeOperator::UseLastNotNull,
+        };
+        let cdc_column = config.cdc_column();
+        let sequence_column = config.sequence_column();
         let merge_ops = schema
             .fields()
             .iter()
-            .map(|field| {
-                MergeOperator::from_name(
-                    merge_operators
-                        .get(field.name())
-                        .unwrap_or(&String::from("UseLast")),
-                )
+            .map(|field| match merge_operators.get(field.name()) {
+                Some(name) => MergeOperator::from_name(name),
+                None if field.name() == &cdc_column
+                    || sequence_column.as_ref() == Some(field.name()) =>
+                {
+                    MergeOperator::UseLast
+                }
+                None => default_merge_op.clone(),
             })
             .collect::<Vec<_>>();
 
@@ -338,7 +670,10 @@ pub fn merge_stream(
             primary_keys.iter().cloned().collect(),
             batch_size,
             merge_ops,
-        )?;
+            // the keys are compared as the sinks sorted them
+            config.primary_key_sort_options(),
+        )?
+        .with_sequence_column(config.sequence_column().as_deref())?;
         Box::pin(DefaultColumnStream::new_from_streams_with_default(
             vec![Box::pin(merge_stream)],
             schema,
@@ -412,3 +747,559 @@ pub async fn prune_filter_and_execute(
     // return a stream
     df.execute_stream().await
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use arrow::array::{ArrayRef, Int32Array, Int64Array, StringArray};
+    use arrow::util::pretty::pretty_format_batches;
+    use arrow_array::RecordBatch;
+    use arrow_schema::{DataType, Field, Schema};
+    use datafusion::datasource::listing::PartitionedFile;
+    use datafusion::datasource::physical_plan::{
+        FileGroup, FileScanConfig, FileScanConfigBuilder, ParquetSource,
+    };
+    use datafusion::execution::object_store::ObjectStoreUrl;
+    use datafusion::logical_expr::Operator;
+    use datafusion::physical_expr::expressions::{binary, col, lit};
+    use datafusion::physical_plan::{ExecutionPlan, collect};
+    use datafusion::prelude::{SessionConfig, SessionContext};
+    use datafusion_common::Result;
+    use object_store::ObjectStore;
+    use object_store::local::LocalFileSystem;
+    use object_store::path::Path;
+
+    use super::MergeParquetExec;
+    use crate::constant::{LAKESOUL_NULLS_FIRST_KEY, LAKESOUL_PRIMARY_KEYS_KEY};
+    use crate::lakesoul_io_config::{LakeSoulIOConfigBuilder, MergeStrategy};
+
+    /// Writes the batch into a local parquet file and returns its scan config.
+    async fn write_parquet_file(
+        dir: &std::path::Path,
+        name: &str,
+        batch: &RecordBatch,
+    ) -> Result<FileScanConfig> {
+        let path = dir.join(name);
+        let mut writer = parquet::arrow::ArrowWriter::try_new(
+            std::fs::File::create(&path)?,
+            batch.schema(),
+            None,
+        )?;
+        writer.write(batch)?;
+        writer.close()?;
+        let object_meta = LocalFileSystem::new()
+            .head(&Path::from_filesystem_path(&path).unwrap())
+            .await?;
+        Ok(FileScanConfigBuilder::new(
+            ObjectStoreUrl::local_filesystem(),
+            batch.schema(),
+            Arc::new(ParquetSource::default()),
+        )
+        .with_file_group(FileGroup::new(vec![PartitionedFile::from(object_meta)]))
+        .build())
+    }
+
+    async fn merge_files(
+        schema: Arc<Schema>,
+        configs: Vec<FileScanConfig>,
+        primary_keys: Vec<String>,
+    ) -> Result<String> {
+        let io_config = LakeSoulIOConfigBuilder::new()
+            .with_schema(schema.clone())
+            .with_primary_keys(primary_keys)
+            .build();
+        let exec = MergeParquetExec::new(schema, configs, None, None, io_config, None)?;
+        let batches = collect(Arc::new(exec), SessionContext::new().task_ctx()).await?;
+        Ok(pretty_format_batches(&batches)?.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_merge_files_with_added_column() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let id = Arc::new(Int64Array::from(vec![1, 2])) as ArrayRef;
+        let a = Arc::new(StringArray::from(vec!["a", "b"])) as ArrayRef;
+        let old = RecordBatch::try_from_iter([("id", id), ("a", a)])?;
+        let id = Arc::new(Int64Array::from(vec![3])) as ArrayRef;
+        let a = Arc::new(StringArray::from(vec!["c"])) as ArrayRef;
+        let b = Arc::new(Int64Array::from(vec![30])) as ArrayRef;
+        let new = RecordBatch::try_from_iter([("id", id), ("a", a), ("b", b)])?;
+
+        let configs = vec![
+            write_parquet_file(temp_dir.path(), "part-0000.parquet", &old).await?,
+            write_parquet_file(temp_dir.path(), "part-0001.parquet", &new).await?,
+        ];
+        // the column added after the first file was written is read as nulls from it
+        let merged = merge_files(new.schema(), configs, vec![]).await?;
+        assert_eq!(
+            merged,
+            [
+                "+----+---+----+",
+                "| id | a | b  |",
+                "+----+---+----+",
+                "| 1  | a |    |",
+                "| 2  | b |    |",
+                "| 3  | c | 30 |",
+                "+----+---+----+",
+            ]
+            .join("\n")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_merge_files_with_dropped_column() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let id = Arc::new(Int64Array::from(vec![1, 2])) as ArrayRef;
+        let a = Arc::new(StringArray::from(vec!["a", "b"])) as ArrayRef;
+        let b = Arc::new(Int64Array::from(vec![10, 20])) as ArrayRef;
+        let old = RecordBatch::try_from_iter([("id", id), ("a", a), ("b", b)])?;
+        let id = Arc::new(Int64Array::from(vec![2, 3])) as ArrayRef;
+        let a = Arc::new(StringArray::from(vec!["c", "d"])) as ArrayRef;
+        let new = RecordBatch::try_from_iter([("id", id), ("a", a)])?;
+
+        let configs = vec![
+            write_parquet_file(temp_dir.path(), "part-0000.parquet", &old).await?,
+            write_parquet_file(temp_dir.path(), "part-0001.parquet", &new).await?,
+        ];
+        // the column dropped from the table is not read from the first file
+        let merged = merge_files(new.schema(), configs, vec!["id".to_string()]).await?;
+        assert_eq!(
+            merged,
+            [
+                "+----+---+",
+                "| id | a |",
+                "+----+---+",
+                "| 1  | a |",
+                "| 2  | c |",
+                "| 3  | d |",
+                "+----+---+",
+            ]
+            .join("\n")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_merge_files_with_widened_column() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let id = Arc::new(Int64Array::from(vec![1, 2])) as ArrayRef;
+        let v = Arc::new(Int32Array::from(vec![10, 20])) as ArrayRef;
+        let old = RecordBatch::try_from_iter([("id", id), ("v", v)])?;
+        let id = Arc::new(Int64Array::from(vec![2, 3])) as ArrayRef;
+        let v = Arc::new(Int64Array::from(vec![200, 3_000_000_000])) as ArrayRef;
+        let new = RecordBatch::try_from_iter([("id", id), ("v", v)])?;
+        assert_eq!(new.schema().field(1).data_type(), &DataType::Int64);
+
+        let configs = vec![
+            write_parquet_file(temp_dir.path(), "part-0000.parquet", &old).await?,
+            write_parquet_file(temp_dir.path(), "part-0001.parquet", &new).await?,
+        ];
+        // the Int32 values of the first file are merged as the Int64 type of the table
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int64, true),
+            Field::new("v", DataType::Int64, true),
+        ]));
+        let merged = merge_files(schema, configs, vec!["id".to_string()]).await?;
+        assert_eq!(
+            merged,
+            [
+                "+----+------------+",
+                "| id | v          |",
+                "+----+------------+",
+                "| 1  | 10         |",
+                "| 2  | 200        |",
+                "| 3  | 3000000000 |",
+                "+----+------------+",
+            ]
+            .join("\n")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_merge_files_by_sequence_column() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let id = Arc::new(Int64Array::from(vec![1, 2, 3])) as ArrayRef;
+        let v = Arc::new(StringArray::from(vec!["b", "b", "b"])) as ArrayRef;
+        let seq = Arc::new(Int64Array::from(vec![5, 5, 5])) as ArrayRef;
+        let newer = RecordBatch::try_from_iter([("id", id), ("v", v), ("seq", seq)])?;
+        let id = Arc::new(Int64Array::from(vec![1, 2, 3])) as ArrayRef;
+        let v = Arc::new(StringArray::from(vec!["a", "c", "c"])) as ArrayRef;
+        let seq = Arc::new(Int64Array::from(vec![Some(3), Some(7), None])) as ArrayRef;
+        let mixed = RecordBatch::try_from_iter([("id", id), ("v", v), ("seq", seq)])?;
+
+        // the file with the older version of the first key is listed last
+        let configs = vec![
+            write_parquet_file(temp_dir.path(), "part-0001.parquet", &newer).await?,
+            write_parquet_file(temp_dir.path(), "part-0000.parquet", &mixed).await?,
+        ];
+        let schema = newer.schema();
+        let io_config = LakeSoulIOConfigBuilder::new()
+            .with_schema(schema.clone())
+            .with_primary_keys(vec!["id".to_string()])
+            .with_sequence_column("seq")
+            .build();
+        let exec = MergeParquetExec::new(schema, configs, None, None, io_config, None)?;
+        let batches = collect(Arc::new(exec), SessionContext::new().task_ctx()).await?;
+        assert_eq!(
+            pretty_format_batches(&batches)?.to_string(),
+            [
+                "+----+---+-----+",
+                "| id | v | seq |",
+                "+----+---+-----+",
+                "| 1  | b | 5   |",
+                "| 2  | c | 7   |",
+                "| 3  | b | 5   |",
+                "+----+---+-----+",
+            ]
+            .join("\n")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_merge_partial_updates_by_sequence_column() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let batch = |v: Vec<Option<&str>>, w: Vec<Option<&str>>, seq: Vec<i64>| {
+            RecordBatch::try_from_iter([
+                ("id", Arc::new(Int64Array::from(vec![1, 2])) as ArrayRef),
+                ("v", Arc::new(StringArray::from(v)) as ArrayRef),
+                ("w", Arc::new(StringArray::from(w)) as ArrayRef),
+                ("seq", Arc::new(Int64Array::from(seq)) as ArrayRef),
+            ])
+        };
+        // a partial update of v of the first key and of w of the second key
+        let partial = batch(vec![Some("b"), None], vec![None, Some("y")], vec![5, 5])?;
+        let full = batch(
+            vec![Some("a"), Some("a")],
+            vec![Some("x"), Some("x")],
+            vec![3, 7],
+        )?;
+
+        // the full rows are listed last, but only the first one is older
+        let configs = vec![
+            write_parquet_file(temp_dir.path(), "part-0001.parquet", &partial).await?,
+            write_parquet_file(temp_dir.path(), "part-0000.parquet", &full).await?,
+        ];
+        let schema = partial.schema();
+        let io_config = LakeSoulIOConfigBuilder::new()
+            .with_schema(schema.clone())
+            .with_primary_keys(vec!["id".to_string()])
+            .with_sequence_column("seq")
+            .with_merge_strategy(MergeStrategy::PartialUpdate)
+            .build();
+        let exec = MergeParquetExec::new(schema, configs, None, None, io_config, None)?;
+        let batches = collect(Arc::new(exec), SessionContext::new().task_ctx()).await?;
+        assert_eq!(
+            pretty_format_batches(&batches)?.to_string(),
+            [
+                "+----+---+---+-----+",
+                "| id | v | w | seq |",
+                "+----+---+---+-----+",
+                "| 1  | b | x | 5   |",
+                "| 2  | a | x | 7   |",
+                "+----+---+---+-----+",
+            ]
+            .join("\n")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_merge_partial_updates_of_column_missing_from_older_file() -> Result<()>
+    {
+        let temp_dir = tempfile::tempdir()?;
+        let batch = |w: Option<&str>, seq: i64| {
+            RecordBatch::try_from_iter([
+                ("id", Arc::new(Int64Array::from(vec![1])) as ArrayRef),
+                (
+                    "v",
+                    Arc::new(StringArray::from(vec![Some("a")])) as ArrayRef,
+                ),
+                ("w", Arc::new(StringArray::from(vec![w])) as ArrayRef),
+                ("seq", Arc::new(Int64Array::from(vec![seq])) as ArrayRef),
+            ])
+        };
+        // the oldest file was written before w was added to the table
+        let old = batch(None, 1)?.project(&[0, 1, 3])?;
+        // the newest value of w is listed before an older one
+        let configs = vec![
+            write_parquet_file(temp_dir.path(), "part-0000.parquet", &old).await?,
+            write_parquet_file(
+                temp_dir.path(),
+                "part-0001.parquet",
+                &batch(Some("y"), 3)?,
+            )
+            .await?,
+            write_parquet_file(
+                temp_dir.path(),
+                "part-0002.parquet",
+                &batch(Some("x"), 2)?,
+            )
+            .await?,
+        ];
+        let schema = batch(None, 0)?.schema();
+        let io_config = LakeSoulIOConfigBuilder::new()
+            .with_schema(schema.clone())
+            .with_primary_keys(vec!["id".to_string()])
+            .with_sequence_column("seq")
+            .with_merge_strategy(MergeStrategy::PartialUpdate)
+            .build();
+        let exec = MergeParquetExec::new(schema, configs, None, None, io_config, None)?;
+        let batches = collect(Arc::new(exec), SessionContext::new().task_ctx()).await?;
+        assert_eq!(
+            pretty_format_batches(&batches)?.to_string(),
+            [
+                "+----+---+---+-----+",
+                "| id | v | w | seq |",
+                "+----+---+---+-----+",
+                "| 1  | a | y | 3   |",
+                "+----+---+---+-----+",
+            ]
+            .join("\n")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_merge_files_with_null_primary_keys() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let batch = |k1: Vec<Option<i64>>, k2: Vec<Option<&str>>, v: Vec<&str>| {
+            RecordBatch::try_from_iter([
+                ("k1", Arc::new(Int64Array::from(k1)) as ArrayRef),
+                ("k2", Arc::new(StringArray::from(k2)) as ArrayRef),
+                ("v", Arc::new(StringArray::from(v)) as ArrayRef),
+            ])
+        };
+        let primary_keys = vec!["k1".to_string(), "k2".to_string()];
+        for nulls_first in [true, false] {
+            // the files are sorted with the null ordering they are merged with
+            let (old, new) = if nulls_first {
+                (
+                    batch(vec![None, Some(1)], vec![None, Some("x")], vec!["a", "b"])?,
+                    batch(vec![None, Some(1)], vec![None, None], vec!["c", "d"])?,
+                )
+            } else {
+                (
+                    batch(vec![Some(1), None], vec![Some("x"), None], vec!["b", "a"])?,
+                    batch(vec![Some(1), None], vec![None, None], vec!["d", "c"])?,
+                )
+            };
+            let configs = vec![
+                write_parquet_file(
+                    temp_dir.path(),
+                    &format!("part-0000-{nulls_first}.parquet"),
+                    &old,
+                )
+                .await?,
+                write_parquet_file(
+                    temp_dir.path(),
+                    &format!("part-0001-{nulls_first}.parquet"),
+                    &new,
+                )
+                .await?,
+            ];
+            let schema = old.schema();
+            let io_config = LakeSoulIOConfigBuilder::new()
+                .with_schema(schema.clone())
+                .with_primary_keys(primary_keys.clone())
+                .with_nulls_first(nulls_first)
+                .build();
+            let exec =
+                MergeParquetExec::new(schema, configs, None, None, io_config, None)?;
+            let batches =
+                collect(Arc::new(exec), SessionContext::new().task_ctx()).await?;
+            // the rows of the all null key are deduplicated, the last one winning
+            let rows = ["| 1  |    | d |", "| 1  | x  | b |", "|    |    | c |"];
+            let rows = if nulls_first {
+                [rows[2], rows[0], rows[1]]
+            } else {
+                [rows[1], rows[0], rows[2]]
+            };
+            let expected = ["+----+----+---+", "| k1 | k2 | v |", "+----+----+---+"]
+                .into_iter()
+                .chain(rows)
+                .chain(["+----+----+---+"])
+                .collect::<Vec<_>>();
+            assert_eq!(
+                pretty_format_batches(&batches)?.to_string(),
+                expected.join("\n")
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_merge_files_written_under_other_primary_keys() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let region = Arc::new(StringArray::from(vec!["eu", "ap", "us"])) as ArrayRef;
+        let id = Arc::new(Int64Array::from(vec![1, 2, 3])) as ArrayRef;
+        let v = Arc::new(StringArray::from(vec!["a", "b", "c"])) as ArrayRef;
+        let old = RecordBatch::try_from_iter([("region", region), ("id", id), ("v", v)])?;
+        let region = Arc::new(StringArray::from(vec!["ap", "eu"])) as ArrayRef;
+        let id = Arc::new(Int64Array::from(vec![2, 1])) as ArrayRef;
+        let v = Arc::new(StringArray::from(vec!["B", "A"])) as ArrayRef;
+        let new = RecordBatch::try_from_iter([("region", region), ("id", id), ("v", v)])?;
+
+        // the first file was written when the table was keyed by the id only
+        let with_primary_keys = |mut config: FileScanConfig, keys: &str| {
+            let metadata = HashMap::from([(
+                LAKESOUL_PRIMARY_KEYS_KEY.to_string(),
+                keys.to_string(),
+            )]);
+            config.file_schema =
+                Arc::new(config.file_schema.as_ref().clone().with_metadata(metadata));
+            config
+        };
+        let configs = vec![
+            with_primary_keys(
+                write_parquet_file(temp_dir.path(), "part-0000.parquet", &old).await?,
+                r#"["id"]"#,
+            ),
+            with_primary_keys(
+                write_parquet_file(temp_dir.path(), "part-0001.parquet", &new).await?,
+                r#"["region","id"]"#,
+            ),
+        ];
+        let schema = new.schema();
+        let io_config = LakeSoulIOConfigBuilder::new()
+            .with_schema(schema.clone())
+            .with_primary_keys(vec!["region".to_string(), "id".to_string()])
+            .build();
+        let exec = MergeParquetExec::new(schema, configs, None, None, io_config, None)?;
+        // only the file sorted by the old primary keys is sorted again
+        let required = exec.required_input_ordering();
+        assert!(required[0].is_some());
+        assert!(required[1].is_none());
+
+        let batches = collect(Arc::new(exec), SessionContext::new().task_ctx()).await?;
+        assert_eq!(
+            pretty_format_batches(&batches)?.to_string(),
+            [
+                "+--------+----+---+",
+                "| region | id | v |",
+                "+--------+----+---+",
+                "| ap     | 2  | B |",
+                "| eu     | 1  | A |",
+                "| us     | 3  | c |",
+                "+--------+----+---+",
+            ]
+            .join("\n")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_merge_files_written_with_other_null_ordering() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let id = Arc::new(Int64Array::from(vec![None, Some(1), Some(3)])) as ArrayRef;
+        let v = Arc::new(StringArray::from(vec!["a", "b", "c"])) as ArrayRef;
+        let old = RecordBatch::try_from_iter([("id", id), ("v", v)])?;
+        let id = Arc::new(Int64Array::from(vec![Some(1), None])) as ArrayRef;
+        let v = Arc::new(StringArray::from(vec!["A", "B"])) as ArrayRef;
+        let new = RecordBatch::try_from_iter([("id", id), ("v", v)])?;
+
+        // the first file records no primary keys, so it is not known to be sorted
+        let mut new_config =
+            write_parquet_file(temp_dir.path(), "part-0001.parquet", &new).await?;
+        let metadata = HashMap::from([
+            (
+                LAKESOUL_PRIMARY_KEYS_KEY.to_string(),
+                r#"["id"]"#.to_string(),
+            ),
+            (LAKESOUL_NULLS_FIRST_KEY.to_string(), "false".to_string()),
+        ]);
+        new_config.file_schema = Arc::new(
+            new_config
+                .file_schema
+                .as_ref()
+                .clone()
+                .with_metadata(metadata),
+        );
+        let configs = vec![
+            write_parquet_file(temp_dir.path(), "part-0000.parquet", &old).await?,
+            new_config,
+        ];
+        let schema = new.schema();
+        let io_config = LakeSoulIOConfigBuilder::new()
+            .with_schema(schema.clone())
+            .with_primary_keys(vec!["id".to_string()])
+            .with_nulls_first(false)
+            .build();
+        let exec = MergeParquetExec::new(schema, configs, None, None, io_config, None)?;
+        // only the file not recorded as sorted with the null ordering is sorted again
+        let required = exec.required_input_ordering();
+        assert!(required[0].is_some());
+        assert!(required[1].is_none());
+
+        let batches = collect(Arc::new(exec), SessionContext::new().task_ctx()).await?;
+        assert_eq!(
+            pretty_format_batches(&batches)?.to_string(),
+            [
+                "+----+---+",
+                "| id | v |",
+                "+----+---+",
+                "| 1  | A |",
+                "| 3  | c |",
+                "|    | B |",
+                "+----+---+",
+            ]
+            .join("\n")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_merge_files_with_merge_batch_size() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let id = Arc::new(Int64Array::from((0..10).collect::<Vec<i64>>())) as ArrayRef;
+        let v = Arc::new(StringArray::from(vec!["old"; 10])) as ArrayRef;
+        let old = RecordBatch::try_from_iter([("id", id), ("v", v)])?;
+        let id = Arc::new(Int64Array::from(vec![0, 2, 4, 6, 8])) as ArrayRef;
+        let v = Arc::new(StringArray::from(vec!["new"; 5])) as ArrayRef;
+        let new = RecordBatch::try_from_iter([("id", id), ("v", v)])?;
+
+        let configs = vec![
+            write_parquet_file(temp_dir.path(), "part-0000.parquet", &old).await?,
+            write_parquet_file(temp_dir.path(), "part-0001.parquet", &new).await?,
+        ];
+        let schema = new.schema();
+        let io_config = LakeSoulIOConfigBuilder::new()
+            .with_schema(schema.clone())
+            .with_primary_keys(vec!["id".to_string()])
+            .with_merge_batch_size(4)
+            .build();
+        let predicate = binary(col("id", &schema)?, Operator::GtEq, lit(3i64), &schema)?;
+        let exec = MergeParquetExec::new(schema, configs, None, None, io_config, None)?
+            .with_predicate(Some(predicate));
+        let context =
+            SessionContext::new_with_config(SessionConfig::new().with_batch_size(2));
+        let batches = collect(Arc::new(exec), context.task_ctx()).await?;
+        // the rows are merged into batches of 4 rows, the first one left with a single
+        // row by the predicate is coalesced with the next one
+        assert_eq!(
+            batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>(),
+            vec![5, 2]
+        );
+        assert_eq!(
+            pretty_format_batches(&batches)?.to_string(),
+            [
+                "+----+-----+",
+                "| id | v   |",
+                "+----+-----+",
+                "| 3  | old |",
+                "| 4  | new |",
+                "| 5  | old |",
+                "| 6  | new |",
+                "| 7  | old |",
+                "| 8  | new |",
+                "| 9  | old |",
+                "+----+-----+",
+            ]
+            .join("\n")
+        );
+        Ok(())
+    }
+}
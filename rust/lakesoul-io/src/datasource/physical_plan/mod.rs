@@ -4,11 +4,23 @@
 
 //! Module for the [datafusion::datasource::physical_plan] implementation of LakeSoul.
 
+pub use bucketed::{BucketedJoinRule, BucketedScanExec};
 pub use empty_schema::EmptySchemaScanExec;
+pub use ipc::{
+    ArrowIpcScanExec, infer_arrow_ipc_schema, is_arrow_ipc_file, is_arrow_ipc_scan_config,
+};
 pub use merge::MergeParquetExec;
+pub use orc::{OrcScanExec, infer_orc_schema, is_orc_file, is_orc_scan_config};
+pub use skipped_files::SkippedFilesExec;
+pub use table_statistics::TableStatisticsExec;
 
+mod bucketed;
 pub mod defatul_column;
 mod empty_schema;
+mod ipc;
 pub mod merge;
+mod orc;
+mod skipped_files;
+mod table_statistics;
 
 pub mod self_incremental_index_column;
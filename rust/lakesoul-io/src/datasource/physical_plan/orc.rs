@@ -0,0 +1,428 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Implementation of the scan execution plan of ORC data files.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use arrow::compute::cast;
+use arrow_array::{ArrayRef, RecordBatch, RecordBatchOptions};
+use arrow_schema::SchemaRef;
+use bytes::Bytes;
+use datafusion::datasource::physical_plan::FileScanConfig;
+use datafusion::physical_plan::execution_plan::{Boundedness, EmissionType};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::{
+    datasource::listing::PartitionedFile,
+    execution::TaskContext,
+    physical_expr::EquivalenceProperties,
+    physical_plan::{
+        DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning, PlanProperties,
+        SendableRecordBatchStream,
+    },
+};
+use datafusion_common::{DataFusionError, Result};
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::{FutureExt, StreamExt, TryStreamExt};
+use object_store::{ObjectMeta, ObjectStore};
+use orc_rust::ArrowReaderBuilder;
+use orc_rust::projection::ProjectionMask;
+use orc_rust::reader::AsyncChunkReader;
+
+/// The file extension of ORC data files.
+const ORC_FILE_EXTENSION: &str = "orc";
+
+/// Returns whether the data file is an ORC file, judged by its file extension.
+pub fn is_orc_file(object_meta: &ObjectMeta) -> bool {
+    object_meta.location.extension() == Some(ORC_FILE_EXTENSION)
+}
+
+/// Returns whether all data files of the scan config are ORC files.
+pub fn is_orc_scan_config(config: &FileScanConfig) -> bool {
+    let mut files = config
+        .file_groups
+        .iter()
+        .flat_map(|group| group.files())
+        .peekable();
+    files.peek().is_some() && files.all(|file| is_orc_file(&file.object_meta))
+}
+
+/// Reads the byte ranges of an ORC file from the object store.
+struct OrcObjectReader {
+    store: Arc<dyn ObjectStore>,
+    object_meta: ObjectMeta,
+}
+
+impl AsyncChunkReader for OrcObjectReader {
+    fn len(&mut self) -> BoxFuture<'_, std::io::Result<u64>> {
+        let len = self.object_meta.size;
+        async move { Ok(len) }.boxed()
+    }
+
+    fn get_bytes(
+        &mut self,
+        offset_from_start: u64,
+        length: u64,
+    ) -> BoxFuture<'_, std::io::Result<Bytes>> {
+        let range = offset_from_start..offset_from_start + length;
+        async move {
+            self.store
+                .get_range(&self.object_meta.location, range)
+                .await
+                .map_err(std::io::Error::other)
+        }
+        .boxed()
+    }
+}
+
+async fn orc_reader_builder(
+    store: Arc<dyn ObjectStore>,
+    object_meta: &ObjectMeta,
+) -> Result<ArrowReaderBuilder<OrcObjectReader>> {
+    ArrowReaderBuilder::try_new_async(OrcObjectReader {
+        store,
+        object_meta: object_meta.clone(),
+    })
+    .await
+    .map_err(|e| DataFusionError::External(Box::new(e)))
+}
+
+/// Infer the arrow schema of an ORC file from its footer.
+pub async fn infer_orc_schema(
+    store: Arc<dyn ObjectStore>,
+    object_meta: &ObjectMeta,
+) -> Result<SchemaRef> {
+    Ok(orc_reader_builder(store, object_meta).await?.schema())
+}
+
+/// [`ExecutionPlan`] implementation which scans ORC files of a [`FileScanConfig`].
+///
+/// The output schema is the same as the one of a parquet scan of the config, i.e. the projected
+/// file columns followed by the projected partition columns, so that ORC and parquet scans
+/// can be fed into the same [`MergeParquetExec`](super::MergeParquetExec).
+#[derive(Debug)]
+pub struct OrcScanExec {
+    /// The files to scan, the schema of the files and the projection.
+    config: FileScanConfig,
+    /// The projected output schema.
+    schema: SchemaRef,
+    properties: PlanProperties,
+}
+
+impl OrcScanExec {
+    /// Create a new [`OrcScanExec`], each file group of the config is scanned by one output partition.
+    pub fn try_new(config: FileScanConfig) -> Result<Self> {
+        let (schema, _, _, _) = config.project();
+        let properties = PlanProperties::new(
+            EquivalenceProperties::new(schema.clone()),
+            Partitioning::UnknownPartitioning(config.file_groups.len()),
+            EmissionType::Incremental,
+            Boundedness::Bounded,
+        );
+        Ok(Self {
+            config,
+            schema,
+            properties,
+        })
+    }
+
+    /// The scan config of the plan.
+    pub fn config(&self) -> &FileScanConfig {
+        &self.config
+    }
+}
+
+impl DisplayAs for OrcScanExec {
+    fn fmt_as(
+        &self,
+        _t: DisplayFormatType,
+        f: &mut std::fmt::Formatter,
+    ) -> std::fmt::Result {
+        write!(
+            f,
+            "OrcScanExec: file_groups={}",
+            self.config.file_groups.len()
+        )
+    }
+}
+
+impl ExecutionPlan for OrcScanExec {
+    fn name(&self) -> &str {
+        "OrcScanExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        &self.properties
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        _children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(self)
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        let Some(file_group) = self.config.file_groups.get(partition) else {
+            return Err(DataFusionError::Internal(format!(
+                "Invalid partition {} of OrcScanExec with {} file groups",
+                partition,
+                self.config.file_groups.len()
+            )));
+        };
+        let store = context
+            .runtime_env()
+            .object_store(&self.config.object_store_url)?;
+        let batch_size = context.session_config().batch_size();
+        let config = Arc::new(self.config.clone());
+        let schema = self.schema.clone();
+        let stream = futures::stream::iter(file_group.files().to_vec())
+            .then(move |file| {
+                scan_orc_file(
+                    store.clone(),
+                    file,
+                    config.clone(),
+                    schema.clone(),
+                    batch_size,
+                )
+            })
+            .try_flatten();
+        Ok(Box::pin(RecordBatchStreamAdapter::new(
+            self.schema.clone(),
+            stream,
+        )))
+    }
+}
+
+/// Scan the projected columns of an ORC file, appending the values of the projected partition columns.
+async fn scan_orc_file(
+    store: Arc<dyn ObjectStore>,
+    file: PartitionedFile,
+    config: Arc<FileScanConfig>,
+    schema: SchemaRef,
+    batch_size: usize,
+) -> Result<BoxStream<'static, Result<RecordBatch>>> {
+    let builder = orc_reader_builder(store, &file.object_meta).await?;
+    let file_schema = config.file_schema.clone();
+    let file_columns = file_schema.fields().len();
+    let projection = config.projection.clone().unwrap_or_else(|| {
+        (0..file_columns + config.table_partition_cols.len()).collect()
+    });
+    let projected_file_columns = projection
+        .iter()
+        .filter(|idx| **idx < file_columns)
+        .map(|idx| file_schema.field(*idx).name().as_str())
+        .collect::<Vec<_>>();
+    let mask = ProjectionMask::named_roots(
+        builder.file_metadata().root_data_type(),
+        &projected_file_columns,
+    );
+    let reader = builder
+        .with_projection(mask)
+        .with_batch_size(batch_size)
+        .build_async();
+    Ok(reader
+        .map(move |batch| {
+            let batch = batch.map_err(|e| DataFusionError::External(Box::new(e)))?;
+            let columns = projection
+                .iter()
+                .zip(schema.fields())
+                .map(|(idx, field)| {
+                    let column = if *idx < file_columns {
+                        batch.column_by_name(field.name()).cloned().ok_or_else(|| {
+                            DataFusionError::Execution(format!(
+                                "column {} not found in ORC file {}",
+                                field.name(),
+                                file.object_meta.location
+                            ))
+                        })?
+                    } else {
+                        file.partition_values[*idx - file_columns]
+                            .to_array_of_size(batch.num_rows())?
+                    };
+                    if column.data_type() == field.data_type() {
+                        Ok(column)
+                    } else {
+                        Ok(cast(&column, field.data_type())?)
+                    }
+                })
+                .collect::<Result<Vec<ArrayRef>>>()?;
+            Ok(RecordBatch::try_new_with_options(
+                schema.clone(),
+                columns,
+                &RecordBatchOptions::new().with_row_count(Some(batch.num_rows())),
+            )?)
+        })
+        .boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::{ArrayRef, Int64Array, StringArray};
+    use arrow::util::pretty::pretty_format_batches;
+    use arrow_array::RecordBatch;
+    use arrow_schema::{DataType, Field};
+    use datafusion::datasource::listing::PartitionedFile;
+    use datafusion::datasource::physical_plan::ParquetSource;
+    use datafusion::datasource::physical_plan::{FileGroup, FileScanConfigBuilder};
+    use datafusion::execution::object_store::ObjectStoreUrl;
+    use datafusion::physical_plan::{ExecutionPlan, collect};
+    use datafusion::prelude::SessionContext;
+    use datafusion_common::{Result, ScalarValue};
+    use object_store::ObjectStore;
+    use object_store::local::LocalFileSystem;
+    use object_store::path::Path;
+
+    use super::{OrcScanExec, infer_orc_schema, is_orc_file};
+    use crate::datasource::physical_plan::MergeParquetExec;
+    use crate::lakesoul_io_config::LakeSoulIOConfigBuilder;
+
+    /// Returns the scan config of a local data file with its own schema as the file schema.
+    async fn local_file_scan_config(
+        path: &std::path::Path,
+        file_schema: arrow_schema::SchemaRef,
+    ) -> Result<datafusion::datasource::physical_plan::FileScanConfig> {
+        let store = LocalFileSystem::new();
+        let object_meta = store
+            .head(&Path::from_filesystem_path(path).unwrap())
+            .await?;
+        Ok(FileScanConfigBuilder::new(
+            ObjectStoreUrl::local_filesystem(),
+            file_schema,
+            Arc::new(ParquetSource::default()),
+        )
+        .with_file_group(FileGroup::new(vec![PartitionedFile::from(object_meta)]))
+        .build())
+    }
+
+    #[tokio::test]
+    async fn test_scan_orc_file_with_partition_column() -> Result<()> {
+        let id = Arc::new(Int64Array::from(vec![1, 2, 3])) as ArrayRef;
+        let name = Arc::new(StringArray::from(vec!["a", "b", "c"])) as ArrayRef;
+        let batch = RecordBatch::try_from_iter([("id", id), ("name", name)])?;
+
+        let temp_dir = tempfile::tempdir()?;
+        let file_path = temp_dir.path().join("part-0000.orc");
+        let file = std::fs::File::create(&file_path)?;
+        let mut writer = orc_rust::ArrowWriterBuilder::new(file, batch.schema())
+            .try_build()
+            .unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let store = Arc::new(LocalFileSystem::new()) as Arc<dyn ObjectStore>;
+        let object_meta = store
+            .head(&Path::from_filesystem_path(&file_path).unwrap())
+            .await?;
+        assert!(is_orc_file(&object_meta));
+        let file_schema = infer_orc_schema(store, &object_meta).await?;
+
+        let mut file = PartitionedFile::from(object_meta);
+        file.partition_values = vec![ScalarValue::Utf8(Some("2024-01-01".to_string()))];
+        let config = FileScanConfigBuilder::new(
+            ObjectStoreUrl::local_filesystem(),
+            file_schema,
+            Arc::new(ParquetSource::default()),
+        )
+        .with_file_group(FileGroup::new(vec![file]))
+        .with_table_partition_cols(vec![Field::new("date", DataType::Utf8, false)])
+        .with_projection(Some(vec![2, 0]))
+        .build();
+
+        let exec = Arc::new(OrcScanExec::try_new(config)?) as Arc<dyn ExecutionPlan>;
+        let batches = collect(exec, SessionContext::new().task_ctx()).await?;
+        assert_eq!(
+            pretty_format_batches(&batches)?.to_string(),
+            [
+                "+------------+----+",
+                "| date       | id |",
+                "+------------+----+",
+                "| 2024-01-01 | 1  |",
+                "| 2024-01-01 | 2  |",
+                "| 2024-01-01 | 3  |",
+                "+------------+----+",
+            ]
+            .join("\n")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_merge_orc_and_parquet_files() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+
+        let id = Arc::new(Int64Array::from(vec![1, 2])) as ArrayRef;
+        let value = Arc::new(StringArray::from(vec!["a", "b"])) as ArrayRef;
+        let orc_batch = RecordBatch::try_from_iter([("id", id), ("value", value)])?;
+        let orc_path = temp_dir.path().join("part-0000.orc");
+        let mut writer = orc_rust::ArrowWriterBuilder::new(
+            std::fs::File::create(&orc_path)?,
+            orc_batch.schema(),
+        )
+        .try_build()
+        .unwrap();
+        writer.write(&orc_batch).unwrap();
+        writer.close().unwrap();
+
+        let id = Arc::new(Int64Array::from(vec![2, 3])) as ArrayRef;
+        let value = Arc::new(StringArray::from(vec!["c", "d"])) as ArrayRef;
+        let parquet_batch = RecordBatch::try_from_iter([("id", id), ("value", value)])?;
+        let parquet_path = temp_dir.path().join("part-0001.parquet");
+        let mut writer = parquet::arrow::ArrowWriter::try_new(
+            std::fs::File::create(&parquet_path)?,
+            parquet_batch.schema(),
+            None,
+        )?;
+        writer.write(&parquet_batch)?;
+        writer.close()?;
+
+        let schema = parquet_batch.schema();
+        let configs = vec![
+            local_file_scan_config(&orc_path, schema.clone()).await?,
+            local_file_scan_config(&parquet_path, schema.clone()).await?,
+        ];
+        let io_config = LakeSoulIOConfigBuilder::new()
+            .with_schema(schema.clone())
+            .with_primary_keys(vec!["id".to_string()])
+            .build();
+        let exec = Arc::new(MergeParquetExec::new(
+            schema, configs, None, None, io_config, None,
+        )?) as Arc<dyn ExecutionPlan>;
+        let batches = collect(exec, SessionContext::new().task_ctx()).await?;
+        assert_eq!(
+            pretty_format_batches(&batches)?.to_string(),
+            [
+                "+----+-------+",
+                "| id | value |",
+                "+----+-------+",
+                "| 1  | a     |",
+                "| 2  | c     |",
+                "| 3  | d     |",
+                "+----+-------+",
+            ]
+            .join("\n")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Implementation of the execution plan reporting the data files left out of a table scan.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use arrow_schema::SchemaRef;
+use datafusion::{
+    execution::TaskContext,
+    physical_plan::{
+        DisplayAs, DisplayFormatType, ExecutionPlan, PlanProperties,
+        SendableRecordBatchStream,
+        metrics::{ExecutionPlanMetricsSet, MetricBuilder, MetricsSet},
+    },
+};
+use datafusion_common::{DataFusionError, Result, Statistics};
+
+/// [`ExecutionPlan`] implementation which passes the batches of a table scan through and
+/// reports the data files the scan skipped as they could not be opened.
+///
+/// The files are skipped when the scan is planned, so the counts are recorded up front in the
+/// `skipped_files` and `skipped_rows` metrics, the latter from the stored statistics of the
+/// skipped files as far as they are known.
+#[derive(Debug)]
+pub struct SkippedFilesExec {
+    /// The scan of the table.
+    input: Arc<dyn ExecutionPlan>,
+    /// The number of skipped files.
+    skipped_files: usize,
+    /// The approximate number of rows of the skipped files.
+    skipped_rows: usize,
+    /// The metrics of the skipped files.
+    metrics: ExecutionPlanMetricsSet,
+}
+
+impl SkippedFilesExec {
+    /// Create a new [`SkippedFilesExec`].
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The scan of the table
+    /// * `skipped_files` - The number of files left out of the scan
+    /// * `skipped_rows` - The approximate number of rows of the skipped files
+    pub fn new(
+        input: Arc<dyn ExecutionPlan>,
+        skipped_files: usize,
+        skipped_rows: usize,
+    ) -> Self {
+        let metrics = ExecutionPlanMetricsSet::new();
+        MetricBuilder::new(&metrics)
+            .global_counter("skipped_files")
+            .add(skipped_files);
+        MetricBuilder::new(&metrics)
+            .global_counter("skipped_rows")
+            .add(skipped_rows);
+        Self {
+            input,
+            skipped_files,
+            skipped_rows,
+            metrics,
+        }
+    }
+}
+
+impl DisplayAs for SkippedFilesExec {
+    fn fmt_as(
+        &self,
+        _t: DisplayFormatType,
+        f: &mut std::fmt::Formatter,
+    ) -> std::fmt::Result {
+        write!(
+            f,
+            "SkippedFilesExec: files={}, rows~={}",
+            self.skipped_files, self.skipped_rows
+        )
+    }
+}
+
+impl ExecutionPlan for SkippedFilesExec {
+    fn name(&self) -> &str {
+        "SkippedFilesExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        self.input.properties()
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.input]
+    }
+
+    fn maintains_input_order(&self) -> Vec<bool> {
+        vec![true]
+    }
+
+    fn benefits_from_input_partitioning(&self) -> Vec<bool> {
+        vec![false]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        mut children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        if children.len() != 1 {
+            return Err(DataFusionError::Internal(format!(
+                "SkippedFilesExec requires exactly one child, got {}",
+                children.len()
+            )));
+        }
+        Ok(Arc::new(Self::new(
+            children.remove(0),
+            self.skipped_files,
+            self.skipped_rows,
+        )))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        self.input.execute(partition, context)
+    }
+
+    fn metrics(&self) -> Option<MetricsSet> {
+        Some(self.metrics.clone_inner())
+    }
+
+    fn statistics(&self) -> Result<Statistics> {
+        self.input.statistics()
+    }
+}
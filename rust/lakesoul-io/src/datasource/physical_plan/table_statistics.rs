@@ -0,0 +1,121 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Implementation of the execution plan reporting the statistics of a table scan.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use arrow_schema::SchemaRef;
+use datafusion::{
+    execution::TaskContext,
+    physical_plan::{
+        DisplayAs, DisplayFormatType, ExecutionPlan, PlanProperties,
+        SendableRecordBatchStream,
+    },
+};
+use datafusion_common::{DataFusionError, Result, Statistics};
+
+/// [`ExecutionPlan`] implementation which passes the batches of a table scan through and
+/// reports the statistics of the scanned table, e.g. for the optimizer to order joins.
+///
+/// The scans merging the rows on read can not derive their statistics from their inputs,
+/// the statistics are instead aggregated from the metadata of the table.
+#[derive(Debug)]
+pub struct TableStatisticsExec {
+    /// The scan of the table.
+    input: Arc<dyn ExecutionPlan>,
+    /// The statistics of the output of the scan.
+    statistics: Statistics,
+}
+
+impl TableStatisticsExec {
+    /// Create a new [`TableStatisticsExec`].
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The scan of the table
+    /// * `statistics` - The statistics of the columns of the output of the scan
+    pub fn try_new(
+        input: Arc<dyn ExecutionPlan>,
+        statistics: Statistics,
+    ) -> Result<Self> {
+        if statistics.column_statistics.len() != input.schema().fields().len() {
+            return Err(DataFusionError::Internal(format!(
+                "TableStatisticsExec requires statistics of {} columns, got {}",
+                input.schema().fields().len(),
+                statistics.column_statistics.len()
+            )));
+        }
+        Ok(Self { input, statistics })
+    }
+}
+
+impl DisplayAs for TableStatisticsExec {
+    fn fmt_as(
+        &self,
+        _t: DisplayFormatType,
+        f: &mut std::fmt::Formatter,
+    ) -> std::fmt::Result {
+        write!(f, "TableStatisticsExec: rows={}", self.statistics.num_rows)
+    }
+}
+
+impl ExecutionPlan for TableStatisticsExec {
+    fn name(&self) -> &str {
+        "TableStatisticsExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        self.input.properties()
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.input]
+    }
+
+    fn maintains_input_order(&self) -> Vec<bool> {
+        vec![true]
+    }
+
+    fn benefits_from_input_partitioning(&self) -> Vec<bool> {
+        vec![false]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        mut children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        if children.len() != 1 {
+            return Err(DataFusionError::Internal(format!(
+                "TableStatisticsExec requires exactly one child, got {}",
+                children.len()
+            )));
+        }
+        Ok(Arc::new(Self::try_new(
+            children.remove(0),
+            self.statistics.clone(),
+        )?))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        self.input.execute(partition, context)
+    }
+
+    fn statistics(&self) -> Result<Statistics> {
+        Ok(self.statistics.clone())
+    }
+}
@@ -0,0 +1,340 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Parquet modular encryption of the sensitive columns of a table.
+//!
+//! The columns listed in [`OPTION_KEY_ENCRYPTED_COLUMNS`] are encrypted with keys of
+//! their own, while the footer stays plaintext, so that the other columns of the files
+//! remain readable by tools without the keys. The footer is still signed with the footer
+//! key.
+//!
+//! The keys are fetched from a [`KeyManagementService`] registered under the name given
+//! by [`OPTION_KEY_ENCRYPTION_KMS`]. The ids of the keys are stored as key metadata in
+//! the files, so a reader fetches the keys each file was written with, also after the key
+//! id of the table was changed to rotate the keys.
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::ops::Range;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use arrow_schema::Schema;
+use bytes::Bytes;
+use datafusion::datasource::physical_plan::parquet::{
+    DefaultParquetFileReaderFactory, ParquetFileReaderFactory,
+};
+use datafusion::datasource::physical_plan::{FileMeta, FileScanConfig};
+use datafusion::execution::runtime_env::RuntimeEnv;
+use datafusion::physical_plan::metrics::ExecutionPlanMetricsSet;
+use datafusion_common::{DataFusionError, Result};
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use parquet::arrow::arrow_reader::ArrowReaderOptions;
+use parquet::arrow::async_reader::AsyncFileReader;
+use parquet::encryption::decrypt::{FileDecryptionProperties, KeyRetriever};
+use parquet::encryption::encrypt::FileEncryptionProperties;
+use parquet::errors::ParquetError;
+use parquet::file::metadata::ParquetMetaData;
+
+use crate::lakesoul_io_config::{
+    LakeSoulIOConfig, OPTION_KEY_ENCRYPTED_COLUMNS, OPTION_KEY_ENCRYPTION_KMS,
+};
+
+/// A key management service providing the keys of the parquet modular encryption.
+///
+/// The keys are requested for each written and read file, so implementations fetching
+/// them from a remote service should cache them.
+pub trait KeyManagementService: Debug + Send + Sync {
+    /// Get the key of the key id, 16, 24 or 32 bytes long for AES-128, -192 or -256.
+    fn get_key(&self, key_id: &str) -> Result<Vec<u8>>;
+}
+
+static KEY_MANAGEMENT_SERVICES: OnceLock<
+    RwLock<HashMap<String, Arc<dyn KeyManagementService>>>,
+> = OnceLock::new();
+
+fn key_management_services()
+-> &'static RwLock<HashMap<String, Arc<dyn KeyManagementService>>> {
+    KEY_MANAGEMENT_SERVICES.get_or_init(Default::default)
+}
+
+/// Register the key management service under the name, replacing the service previously
+/// registered under it.
+pub fn register_key_management_service(
+    name: impl Into<String>,
+    service: Arc<dyn KeyManagementService>,
+) {
+    key_management_services()
+        .write()
+        .unwrap()
+        .insert(name.into(), service);
+}
+
+/// The key management service of the config, `None` if no service is configured.
+fn configured_service(
+    config: &LakeSoulIOConfig,
+) -> Result<Option<Arc<dyn KeyManagementService>>> {
+    let Some(name) = config.encryption_kms() else {
+        return Ok(None);
+    };
+    match key_management_services().read().unwrap().get(name) {
+        Some(service) => Ok(Some(service.clone())),
+        None => Err(DataFusionError::Configuration(format!(
+            "key management service {} is not registered",
+            name
+        ))),
+    }
+}
+
+/// The encryption of the files written with the config, `None` if no column is encrypted.
+///
+/// The encrypted columns must be columns of the written schema.
+pub fn file_encryption_properties(
+    config: &LakeSoulIOConfig,
+    writer_schema: &Schema,
+) -> Result<Option<FileEncryptionProperties>> {
+    let encrypted_columns = config.encrypted_columns();
+    if encrypted_columns.is_empty() {
+        return Ok(None);
+    }
+    let Some(service) = configured_service(config)? else {
+        return Err(DataFusionError::Configuration(format!(
+            "{} requires a key management service set by {}",
+            OPTION_KEY_ENCRYPTED_COLUMNS, OPTION_KEY_ENCRYPTION_KMS
+        )));
+    };
+    let footer_key_id = config.encryption_key_id();
+    let mut builder = FileEncryptionProperties::builder(service.get_key(footer_key_id)?)
+        .with_footer_key_metadata(footer_key_id.as_bytes().to_vec())
+        .with_plaintext_footer(true);
+    for column in &encrypted_columns {
+        if writer_schema.field_with_name(column).is_err() {
+            return Err(DataFusionError::Configuration(format!(
+                "encrypted column {} is not a written column",
+                column
+            )));
+        }
+        let key_id = format!("{}.{}", footer_key_id, column);
+        builder = builder.with_column_key_and_metadata(
+            column,
+            service.get_key(&key_id)?,
+            key_id.into_bytes(),
+        );
+    }
+    Ok(Some(builder.build()?))
+}
+
+/// The [`KeyRetriever`] fetching the keys of the key ids stored in the files from the key
+/// management service.
+struct ServiceKeyRetriever(Arc<dyn KeyManagementService>);
+
+impl KeyRetriever for ServiceKeyRetriever {
+    fn retrieve_key(&self, key_metadata: &[u8]) -> parquet::errors::Result<Vec<u8>> {
+        let key_id = std::str::from_utf8(key_metadata)
+            .map_err(|e| ParquetError::General(format!("invalid key id: {}", e)))?;
+        self.0
+            .get_key(key_id)
+            .map_err(|e| ParquetError::General(format!("get key {}: {}", key_id, e)))
+    }
+}
+
+/// The decryption of the parquet files read by a scan.
+#[derive(Clone)]
+pub struct ScanDecryption {
+    runtime_env: Arc<RuntimeEnv>,
+    properties: Arc<FileDecryptionProperties>,
+}
+
+impl Debug for ScanDecryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScanDecryption").finish()
+    }
+}
+
+impl ScanDecryption {
+    /// Create the decryption of the scans with the config, `None` if no key management
+    /// service is configured.
+    pub fn try_new(
+        config: &LakeSoulIOConfig,
+        runtime_env: Arc<RuntimeEnv>,
+    ) -> Result<Option<Self>> {
+        let Some(service) = configured_service(config)? else {
+            return Ok(None);
+        };
+        let properties = FileDecryptionProperties::with_key_retriever(Arc::new(
+            ServiceKeyRetriever(service),
+        ))
+        .build()?;
+        Ok(Some(Self {
+            runtime_env,
+            properties: Arc::new(properties),
+        }))
+    }
+
+    /// The factory of the readers decrypting the parquet files of the scan config.
+    pub fn reader_factory(
+        &self,
+        config: &FileScanConfig,
+    ) -> Result<Arc<dyn ParquetFileReaderFactory>> {
+        let store = self.runtime_env.object_store(&config.object_store_url)?;
+        Ok(Arc::new(DecryptingReaderFactory {
+            inner: DefaultParquetFileReaderFactory::new(store),
+            properties: self.properties.clone(),
+        }))
+    }
+}
+
+/// The [`ParquetFileReaderFactory`] of the readers loading the metadata of the files with
+/// the decryption properties, so that the encrypted columns are decrypted when read.
+struct DecryptingReaderFactory {
+    inner: DefaultParquetFileReaderFactory,
+    properties: Arc<FileDecryptionProperties>,
+}
+
+impl Debug for DecryptingReaderFactory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DecryptingReaderFactory")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl ParquetFileReaderFactory for DecryptingReaderFactory {
+    fn create_reader(
+        &self,
+        partition_index: usize,
+        file_meta: FileMeta,
+        metadata_size_hint: Option<usize>,
+        metrics: &ExecutionPlanMetricsSet,
+    ) -> Result<Box<dyn AsyncFileReader + Send>> {
+        let inner = self.inner.create_reader(
+            partition_index,
+            file_meta,
+            metadata_size_hint,
+            metrics,
+        )?;
+        Ok(Box::new(DecryptingReader {
+            inner,
+            properties: self.properties.clone(),
+        }))
+    }
+}
+
+struct DecryptingReader {
+    inner: Box<dyn AsyncFileReader + Send>,
+    properties: Arc<FileDecryptionProperties>,
+}
+
+impl AsyncFileReader for DecryptingReader {
+    fn get_bytes(
+        &mut self,
+        range: Range<u64>,
+    ) -> BoxFuture<'_, parquet::errors::Result<Bytes>> {
+        self.inner.get_bytes(range)
+    }
+
+    fn get_byte_ranges(
+        &mut self,
+        ranges: Vec<Range<u64>>,
+    ) -> BoxFuture<'_, parquet::errors::Result<Vec<Bytes>>> {
+        self.inner.get_byte_ranges(ranges)
+    }
+
+    fn get_metadata<'a>(
+        &'a mut self,
+        options: Option<&'a ArrowReaderOptions>,
+    ) -> BoxFuture<'a, parquet::errors::Result<Arc<ParquetMetaData>>> {
+        let options = options
+            .cloned()
+            .unwrap_or_default()
+            .with_file_decryption_properties(self.properties.as_ref().clone());
+        async move { self.inner.get_metadata(Some(&options)).await }.boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::{ArrayRef, Int64Array, StringArray};
+    use arrow_array::RecordBatch;
+    use datafusion::prelude::SessionContext;
+    use datafusion_common::Result;
+    use object_store::ObjectStore;
+    use object_store::memory::InMemory;
+    use object_store::path::Path;
+    use parquet::arrow::ProjectionMask;
+    use parquet::arrow::arrow_reader::{
+        ArrowReaderOptions, ParquetRecordBatchReaderBuilder,
+    };
+    use url::Url;
+
+    use super::{KeyManagementService, ScanDecryption, register_key_management_service};
+    use crate::async_writer::{AsyncBatchWriter, MultiPartAsyncWriter};
+    use crate::lakesoul_io_config::LakeSoulIOConfigBuilder;
+
+    /// A key management service deriving the 128-bit keys from the key ids.
+    #[derive(Debug)]
+    struct DigestKms;
+
+    impl KeyManagementService for DigestKms {
+        fn get_key(&self, key_id: &str) -> Result<Vec<u8>> {
+            Ok(md5::compute(key_id).0.to_vec())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_column_round_trip() -> Result<()> {
+        register_key_management_service("digest", Arc::new(DigestKms));
+        let store = Arc::new(InMemory::new());
+        let ctx = SessionContext::new();
+        ctx.register_object_store(&Url::parse("mock://bucket").unwrap(), store.clone());
+
+        let id = Arc::new(Int64Array::from_iter_values(0..3)) as ArrayRef;
+        let secret = Arc::new(StringArray::from(vec!["a", "b", "c"])) as ArrayRef;
+        let batch = RecordBatch::try_from_iter([("id", id), ("secret", secret)])?;
+        let mut config = LakeSoulIOConfigBuilder::new()
+            .with_files(vec!["mock://bucket/test.parquet"])
+            .with_schema(batch.schema())
+            .with_encrypted_columns("digest", vec!["secret".to_string()])
+            .build();
+        let mut writer =
+            MultiPartAsyncWriter::try_new_with_context(&mut config, ctx.task_ctx())
+                .await?;
+        writer.write_record_batch(batch.clone()).await?;
+        Box::new(writer).flush_and_close().await?;
+        let bytes = store
+            .get(&Path::from("test.parquet"))
+            .await?
+            .bytes()
+            .await?;
+
+        // the encrypted column can not be read without the keys
+        let read_without_keys = ParquetRecordBatchReaderBuilder::try_new(bytes.clone())
+            .and_then(|builder| builder.build())
+            .map(|reader| reader.collect::<Result<Vec<_>, _>>());
+        assert!(!matches!(read_without_keys, Ok(Ok(_))));
+
+        // the unencrypted columns remain readable without the keys
+        let builder = ParquetRecordBatchReaderBuilder::try_new(bytes.clone())?;
+        let projection = ProjectionMask::columns(builder.parquet_schema(), ["id"]);
+        let batches = builder
+            .with_projection(projection)
+            .build()?
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].num_columns(), 1);
+        assert_eq!(batches[0].column(0), batch.column(0));
+
+        let decryption = ScanDecryption::try_new(&config, ctx.runtime_env())?.unwrap();
+        let options = ArrowReaderOptions::new()
+            .with_file_decryption_properties(decryption.properties.as_ref().clone());
+        let batches =
+            ParquetRecordBatchReaderBuilder::try_new_with_options(bytes, options)?
+                .build()?
+                .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(batches, vec![batch]);
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! The templates of the names of the written data files.
+//!
+//! By default the sink names its files `part-{write_id}_{bucket}` with the input partition
+//! and the file index inserted when they are needed, a template gives the files the names
+//! expected by other writers of the table, e.g. `part-{partition}-{write_id}_{bucket}.c{seq}`
+//! like Spark.
+
+use datafusion_common::{DataFusionError, Result};
+
+use crate::helpers::extract_hash_bucket_id;
+
+/// The placeholder of the id of the write.
+pub const FILE_NAME_WRITE_ID: &str = "write_id";
+
+/// The placeholder of the input partition of the sink writing the file.
+pub const FILE_NAME_PARTITION: &str = "partition";
+
+/// The placeholder of the hash bucket of the rows of the file.
+pub const FILE_NAME_BUCKET: &str = "bucket";
+
+/// The placeholder of the sequence number of the file among the files rolled by the same
+/// input partition into the same range partition and hash bucket, starting at 0.
+pub const FILE_NAME_SEQUENCE: &str = "seq";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Placeholder(&'static str),
+}
+
+/// A template of the names of the written data files, without their extension.
+///
+/// The placeholders `{write_id}`, `{partition}`, `{bucket}` and `{seq}` are replaced at
+/// write time, the numbers are zero padded to 4 digits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileNameTemplate {
+    template: String,
+    segments: Vec<Segment>,
+}
+
+impl FileNameTemplate {
+    /// Parse and validate the template.
+    ///
+    /// The template must name the files of a write uniquely, so it requires the
+    /// `{write_id}`, `{partition}` and `{seq}` placeholders, separated by literals so that
+    /// the numbers cannot run into each other. The files of the tables with primary keys are
+    /// written by hash bucket, so their template requires the `{bucket}` placeholder as the
+    /// last number of the file name, where the readers find the bucket of the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The template of the file names, without the extension
+    /// * `has_primary_keys` - Whether the written table has primary keys
+    pub fn try_new(template: &str, has_primary_keys: bool) -> Result<Self> {
+        let segments = parse_segments(template)?;
+        if template.contains('/') {
+            return Err(invalid_template(template, "must not contain '/'"));
+        }
+        let mut required =
+            vec![FILE_NAME_WRITE_ID, FILE_NAME_PARTITION, FILE_NAME_SEQUENCE];
+        if has_primary_keys {
+            required.push(FILE_NAME_BUCKET);
+        }
+        for placeholder in required {
+            if !segments.contains(&Segment::Placeholder(placeholder)) {
+                return Err(invalid_template(
+                    template,
+                    &format!("requires the {{{}}} placeholder", placeholder),
+                ));
+            }
+        }
+        if segments.windows(2).any(|pair| {
+            matches!(pair, [Segment::Placeholder(_), Segment::Placeholder(_)])
+        }) {
+            return Err(invalid_template(
+                template,
+                "requires a literal between the placeholders",
+            ));
+        }
+        let template = Self {
+            template: template.to_string(),
+            segments,
+        };
+        if has_primary_keys
+            && extract_hash_bucket_id(&template.render("0", 1, 42, 3, "parquet"))
+                != Some(42)
+        {
+            return Err(invalid_template(
+                &template.template,
+                "must end with part-..._{bucket}, optionally followed by a '.' suffix",
+            ));
+        }
+        Ok(template)
+    }
+
+    /// The file name of the template with its placeholders replaced, and the extension.
+    pub fn render(
+        &self,
+        write_id: &str,
+        partition: usize,
+        bucket: usize,
+        seq: usize,
+        extension: &str,
+    ) -> String {
+        let mut name = String::with_capacity(self.template.len() + extension.len() + 16);
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => name.push_str(literal),
+                Segment::Placeholder(FILE_NAME_WRITE_ID) => name.push_str(write_id),
+                Segment::Placeholder(FILE_NAME_PARTITION) => {
+                    name.push_str(&format!("{:0>4}", partition))
+                }
+                Segment::Placeholder(FILE_NAME_BUCKET) => {
+                    name.push_str(&format!("{:0>4}", bucket))
+                }
+                // the sequence, the placeholders are checked when parsed
+                Segment::Placeholder(_) => name.push_str(&format!("{:0>4}", seq)),
+            }
+        }
+        name.push('.');
+        name.push_str(extension);
+        name
+    }
+}
+
+fn parse_segments(template: &str) -> Result<Vec<Segment>> {
+    let mut segments = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            segments.push(Segment::Literal(rest[..start].to_string()));
+        }
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| invalid_template(template, "has an unclosed '{'"))?;
+        let placeholder = match &rest[start + 1..start + end] {
+            FILE_NAME_WRITE_ID => FILE_NAME_WRITE_ID,
+            FILE_NAME_PARTITION => FILE_NAME_PARTITION,
+            FILE_NAME_BUCKET => FILE_NAME_BUCKET,
+            FILE_NAME_SEQUENCE => FILE_NAME_SEQUENCE,
+            other => {
+                return Err(invalid_template(
+                    template,
+                    &format!("has the unknown placeholder {{{}}}", other),
+                ));
+            }
+        };
+        segments.push(Segment::Placeholder(placeholder));
+        rest = &rest[start + end + 1..];
+    }
+    if rest.contains('}') {
+        return Err(invalid_template(template, "has an unopened '}'"));
+    }
+    if !rest.is_empty() {
+        segments.push(Segment::Literal(rest.to_string()));
+    }
+    Ok(segments)
+}
+
+fn invalid_template(template: &str, reason: &str) -> DataFusionError {
+    DataFusionError::Configuration(format!(
+        "invalid file name template {}, it {}",
+        template, reason
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion_common::Result;
+
+    use super::FileNameTemplate;
+    use crate::helpers::extract_hash_bucket_id;
+
+    #[test]
+    fn test_render_spark_style_names() -> Result<()> {
+        let template = FileNameTemplate::try_new(
+            "part-{partition}-{write_id}_{bucket}.c{seq}",
+            true,
+        )?;
+        let name = template.render("AbCd", 2, 7, 1, "parquet");
+        assert_eq!(name, "part-0002-AbCd_0007.c0001.parquet");
+        assert_eq!(extract_hash_bucket_id(&name), Some(7));
+        // the rolled files of the same partition and bucket get other names
+        assert_ne!(name, template.render("AbCd", 2, 7, 2, "parquet"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_bucket_is_optional_without_primary_keys() -> Result<()> {
+        let template = FileNameTemplate::try_new("{write_id}-{partition}-{seq}", false)?;
+        assert_eq!(
+            template.render("AbCd", 0, 0, 3, "arrow"),
+            "AbCd-0000-0003.arrow"
+        );
+        assert!(FileNameTemplate::try_new("{write_id}-{partition}-{seq}", true).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_ambiguous_templates() {
+        for template in [
+            // the rolled files would overwrite each other
+            "part-{write_id}-{partition}_{bucket}",
+            // the files of the input partitions would overwrite each other
+            "part-{write_id}-{seq}_{bucket}",
+            "part-{write_id}-{partition}{seq}_{bucket}",
+            // the readers would not find the bucket of the file
+            "part-{write_id}-{bucket}_{partition}-{seq}",
+            "part-{write_id}/{partition}-{seq}_{bucket}",
+            "part-{write_id}-{partition}-{seq}_{bucket",
+            "part-{write_id}-{partition}-{seq}_{hash}",
+        ] {
+            assert!(
+                FileNameTemplate::try_new(template, true).is_err(),
+                "{template}"
+            );
+        }
+    }
+}
@@ -7,11 +7,12 @@
 //! It includes functions for formatting scalar values, converting partition descriptions,
 //! and applying partition filters.
 
-use arrow::datatypes::UInt32Type;
-use arrow_array::{Array, RecordBatch, UInt32Array};
+use arrow::compute::{SortColumn, lexsort_to_indices, partition, take_record_batch};
+use arrow::datatypes::{Float16Type, Float32Type, Float64Type, UInt32Type};
+use arrow_array::{Array, AsArray, RecordBatch, RecordBatchOptions, UInt32Array};
 use arrow_buffer::i256;
 use arrow_schema::{
-    ArrowError, DataType, Field, Schema, SchemaBuilder, SchemaRef, TimeUnit,
+    ArrowError, DataType, Field, FieldRef, Schema, SchemaBuilder, SchemaRef, TimeUnit,
 };
 use chrono::{DateTime, Duration};
 use datafusion::physical_plan::memory::LazyBatchGenerator;
@@ -22,8 +23,9 @@ use datafusion::{
         physical_plan::FileScanConfig,
     },
     execution::context::{SessionContext, SessionState},
+    execution::object_store::ObjectStoreUrl,
     logical_expr::col,
-    physical_expr::{PhysicalSortExpr, create_physical_expr},
+    physical_expr::{PhysicalSortExpr, create_physical_expr, expressions::Column},
     physical_plan::PhysicalExpr,
     physical_planner::create_physical_sort_expr,
 };
@@ -170,6 +172,132 @@ pub fn get_columnar_values(
         .collect::<Result<Vec<_>>>()
 }
 
+/// Builds the projection of the input columns with the generated columns computed by
+/// their SQL expressions, to be evaluated by a [`crate::projection::ProjectionStream`].
+///
+/// A generated column replaces the input column of the same name, e.g. the nulls filled
+/// in for a column left out of an insert, or is appended otherwise. Its value is cast to the
+/// type of the column in the table schema.
+///
+/// # Arguments
+///
+/// * `generated_columns` - The SQL expressions of the generated columns by their names
+/// * `input_schema` - The schema of the input batches
+/// * `table_schema` - The schema of the table
+///
+/// # Returns
+///
+/// Returns the schema of the projected batches and the projected expressions
+pub fn generated_columns_projection(
+    generated_columns: &HashMap<String, String>,
+    input_schema: &SchemaRef,
+    table_schema: &Schema,
+) -> Result<(SchemaRef, Vec<Arc<dyn PhysicalExpr>>)> {
+    let session_state = SessionContext::new().state();
+    let input_dfschema = DFSchema::try_from(input_schema.as_ref().clone())?;
+    let mut fields = input_schema.fields().to_vec();
+    let mut exprs = fields
+        .iter()
+        .enumerate()
+        .map(|(idx, field)| {
+            Arc::new(Column::new(field.name(), idx)) as Arc<dyn PhysicalExpr>
+        })
+        .collect::<Vec<_>>();
+    let mut generated_columns = generated_columns.iter().collect::<Vec<_>>();
+    generated_columns.sort();
+    for (name, sql) in generated_columns {
+        let table_field = table_schema.field_with_name(name)?;
+        let expr = session_state
+            .create_logical_expr(sql, &input_dfschema)?
+            .cast_to(table_field.data_type(), &input_dfschema)?;
+        let expr = create_physical_expr(
+            &expr,
+            &input_dfschema,
+            session_state.execution_props(),
+        )?;
+        let field = Arc::new(Field::new(
+            name,
+            table_field.data_type().clone(),
+            expr.nullable(input_schema)?,
+        ));
+        match input_schema.index_of(name) {
+            Ok(idx) => {
+                fields[idx] = field;
+                exprs[idx] = expr;
+            }
+            Err(_) => {
+                fields.push(field);
+                exprs.push(expr);
+            }
+        }
+    }
+    let schema = Schema::new_with_metadata(fields, input_schema.metadata().clone());
+    Ok((Arc::new(schema), exprs))
+}
+
+/// Computes the generated columns of the batch by their SQL expressions, see
+/// [`generated_columns_projection`].
+pub fn generate_batch_columns(
+    batch: &RecordBatch,
+    generated_columns: &HashMap<String, String>,
+    table_schema: &Schema,
+) -> Result<RecordBatch> {
+    let (schema, exprs) =
+        generated_columns_projection(generated_columns, &batch.schema(), table_schema)?;
+    let columns = exprs
+        .iter()
+        .map(|expr| expr.evaluate(batch)?.into_array(batch.num_rows()))
+        .collect::<Result<Vec<_>>>()?;
+    let options = RecordBatchOptions::new().with_row_count(Some(batch.num_rows()));
+    Ok(RecordBatch::try_new_with_options(
+        schema, columns, &options,
+    )?)
+}
+
+/// Splits a record batch into the batches of the rows with equal values of the columns.
+///
+/// The rows are sorted by the columns first, so the rows of each batch are contiguous.
+///
+/// # Arguments
+///
+/// * `batch` - The record batch
+/// * `columns` - The names of the columns to split the batch by
+///
+/// # Returns
+///
+/// Returns the batches of the distinct values of the columns, ordered by the values
+pub fn split_batch_by_columns(
+    batch: &RecordBatch,
+    columns: &[String],
+) -> Result<Vec<RecordBatch>> {
+    if columns.is_empty() || batch.num_rows() == 0 {
+        return Ok(vec![batch.clone()]);
+    }
+    let sort_columns = columns
+        .iter()
+        .map(|column| {
+            let values = batch.column_by_name(column).ok_or_else(|| {
+                External(format!("Invalid partition desc of {}", column).into())
+            })?;
+            Ok(SortColumn {
+                values: values.clone(),
+                options: None,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let indices = lexsort_to_indices(&sort_columns, None)?;
+    let sorted = take_record_batch(batch, &indices)?;
+    let sorted_columns = columns
+        .iter()
+        .filter_map(|column| sorted.column_by_name(column).cloned())
+        .collect::<Vec<_>>();
+    Ok(partition(&sorted_columns)?
+        .ranges()
+        .into_iter()
+        .map(|range| sorted.slice(range.start, range.end - range.start))
+        .collect())
+}
+
 /// Formats a [`datafusion::scalar::ScalarValue`] to a string.
 ///
 /// # Arguments
@@ -431,6 +559,11 @@ pub fn into_scalar_value(val: &str, data_type: &DataType) -> Result<ScalarValue>
 /// # Returns
 ///
 /// Returns a sub path by concatenating the column names and [`datafusion::scalar::ScalarValue`]
+///
+/// The sub path only holds the range partitions. The files of the hash buckets of a range
+/// partition share its sub path and end their names with the hash bucket id, the file
+/// group of a bucket being found by [`extract_hash_bucket_id`], so that the partition
+/// descriptors stay the same whatever the number of hash buckets.
 pub fn columnar_values_to_sub_path(columnar_values: &[(String, ScalarValue)]) -> String {
     if columnar_values.is_empty() {
         "/".to_string()
@@ -651,8 +784,54 @@ pub async fn listing_table_from_lakesoul_io_config(
     ))
 }
 
+/// Resolves the path of a data file into the URL of its object store and the location of
+/// the file in that store.
+///
+/// The object store is identified by the scheme and authority of the path, so the files of a
+/// table may be stored in different buckets or file systems. A path without a scheme is
+/// relative to `table_url`.
+///
+/// # Arguments
+///
+/// * `path` - The absolute URI of the data file, or its path relative to the table
+/// * `table_url` - The path of the table
+///
+/// # Examples
+///
+/// ```
+/// use lakesoul_io::helpers::resolve_file_url;
+/// use url::Url;
+///
+/// let table_url = Url::parse("s3://bucket-a/table/").unwrap();
+/// let (store_url, location) =
+///     resolve_file_url("s3://bucket-b/table/part-0000.parquet", &table_url).unwrap();
+/// assert_eq!(store_url.as_str(), "s3://bucket-b/");
+/// assert_eq!(location.as_ref(), "table/part-0000.parquet");
+/// let (store_url, location) = resolve_file_url("part-0001.parquet", &table_url).unwrap();
+/// assert_eq!(store_url.as_str(), "s3://bucket-a/");
+/// assert_eq!(location.as_ref(), "table/part-0001.parquet");
+/// ```
+pub fn resolve_file_url(path: &str, table_url: &Url) -> Result<(ObjectStoreUrl, Path)> {
+    let url = match Url::parse(path) {
+        Ok(url) => url,
+        Err(url::ParseError::RelativeUrlWithoutBase) => {
+            let mut table_url = table_url.clone();
+            if !table_url.path().ends_with('/') {
+                table_url.set_path(&format!("{}/", table_url.path()));
+            }
+            table_url.join(path).map_err(|e| External(Box::new(e)))?
+        }
+        Err(e) => return Err(External(Box::new(e))),
+    };
+    let object_store_url = ObjectStoreUrl::parse(&url[..url::Position::BeforePath])?;
+    let location = Path::from_url_path(url.path()).map_err(object_store::Error::from)?;
+    Ok((object_store_url, location))
+}
+
 /// Gets the [`object_store::ObjectMetadata`] for a list of table paths.
 ///
+/// The metadata of each path is fetched from the object store of the path itself.
+///
 /// # Arguments
 ///
 /// * `sc` - The session state
@@ -665,31 +844,32 @@ pub async fn get_file_object_meta(
     sc: &SessionState,
     table_paths: &[ListingTableUrl],
 ) -> Result<Vec<ObjectMeta>> {
-    let object_store_url = table_paths
-        .first()
-        .ok_or(Internal("no table path".to_string()))?
-        .object_store();
-    let store = sc.runtime_env().object_store(object_store_url.clone())?;
+    if table_paths.is_empty() {
+        return Err(Internal("no table path".to_string()));
+    }
     futures::stream::iter(table_paths)
         .map(|path| {
-            let store = store.clone();
+            let store = sc.runtime_env().object_store(path.object_store());
             async move {
+                let store = store?;
                 let path = Path::from_url_path(
                     <ListingTableUrl as AsRef<Url>>::as_ref(path).path(),
                 )
                 .map_err(object_store::Error::from)?;
-                store.head(&path).await
+                Ok::<_, DataFusionError>(store.head(&path).await?)
             }
         })
         .boxed()
         .buffered(sc.config_options().execution.meta_fetch_concurrency)
         .try_collect()
         .await
-        .map_err(DataFusionError::from)
 }
 
 /// Infers the schema of files from a list of [`ListingTableUrl`] and [`ObjectMeta`].
 ///
+/// The files are grouped by their object stores, the schemas inferred from each store are
+/// merged into one.
+///
 /// # Arguments
 ///
 /// * `sc` - The session state
@@ -706,14 +886,33 @@ pub async fn infer_schema(
     object_metas: &[ObjectMeta],
     file_format: Arc<dyn FileFormat>,
 ) -> Result<SchemaRef> {
-    let object_store_url = table_paths
-        .first()
-        .ok_or(Internal("no table path".to_string()))?
-        .object_store();
-    let store = sc.runtime_env().object_store(object_store_url.clone())?;
+    if table_paths.is_empty() {
+        return Err(Internal("no table path".to_string()));
+    }
+    let mut objects_by_store: Vec<(ObjectStoreUrl, Vec<ObjectMeta>)> = vec![];
+    for (path, object_meta) in zip(table_paths, object_metas) {
+        let object_store_url = path.object_store();
+        match objects_by_store
+            .iter_mut()
+            .find(|(url, _)| *url == object_store_url)
+        {
+            Some((_, objects)) => objects.push(object_meta.clone()),
+            None => objects_by_store.push((object_store_url, vec![object_meta.clone()])),
+        }
+    }
 
     // Resolve the schema
-    file_format.infer_schema(sc, &store, object_metas).await
+    let mut schemas = vec![];
+    for (object_store_url, objects) in objects_by_store {
+        let store = sc.runtime_env().object_store(object_store_url)?;
+        schemas.push(file_format.infer_schema(sc, &store, &objects).await?);
+    }
+    match schemas.len() {
+        1 => Ok(schemas.remove(0)),
+        _ => Ok(Arc::new(Schema::try_merge(
+            schemas.iter().map(|schema| schema.as_ref().clone()),
+        )?)),
+    }
 }
 
 /// Applies a partition filter to a [`JniWrapper`].
@@ -874,6 +1073,71 @@ pub fn get_batch_memory_size(batch: &RecordBatch) -> Result<usize> {
         .sum())
 }
 
+/// Converts a string or binary field into its view type, the way the parquet format infers the
+/// schemas of the files with `force_view_types`.
+pub fn view_type_field(field: &FieldRef) -> FieldRef {
+    let data_type = match field.data_type() {
+        DataType::Utf8 | DataType::LargeUtf8 => DataType::Utf8View,
+        DataType::Binary | DataType::LargeBinary => DataType::BinaryView,
+        _ => return field.clone(),
+    };
+    Arc::new(field.as_ref().clone().with_data_type(data_type))
+}
+
+/// Normalizes the column name the way column names are compared, i.e. trimmed and in lower case.
+pub fn normalize_column_name(name: &str) -> String {
+    name.trim().to_ascii_lowercase()
+}
+
+/// Checks that no two columns of the schema collide once their names are normalized.
+///
+/// Such schemas come from externally written files, e.g. with columns `Id` and `id`, and would
+/// otherwise fail with an ambiguous column reference when the columns are projected.
+pub fn check_normalized_column_names(schema: &Schema) -> Result<()> {
+    let mut normalized_names = HashMap::<String, &String>::new();
+    for field in schema.fields() {
+        if let Some(other) =
+            normalized_names.insert(normalize_column_name(field.name()), field.name())
+        {
+            return Err(DataFusionError::ArrowError(
+                ArrowError::SchemaError(format!(
+                    "Columns '{}' and '{}' collide after normalizing column names",
+                    other,
+                    field.name()
+                )),
+                None,
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Gets the names of the float columns of a [`RecordBatch`] containing NaN values.
+pub fn get_columns_with_nan(batch: &RecordBatch) -> Vec<String> {
+    batch
+        .schema()
+        .fields()
+        .iter()
+        .zip(batch.columns())
+        .filter(|(_, array)| match array.data_type() {
+            DataType::Float16 => array
+                .as_primitive::<Float16Type>()
+                .iter()
+                .any(|value| value.is_some_and(|value| value.is_nan())),
+            DataType::Float32 => array
+                .as_primitive::<Float32Type>()
+                .iter()
+                .any(|value| value.is_some_and(f32::is_nan)),
+            DataType::Float64 => array
+                .as_primitive::<Float64Type>()
+                .iter()
+                .any(|value| value.is_some_and(f64::is_nan)),
+            _ => false,
+        })
+        .map(|(field, _)| field.name().clone())
+        .collect()
+}
+
 /// Gets the file size of a [`FileMetaData`].
 pub fn get_file_size(metadata: &FileMetaData) -> usize {
     let footer_size = metadata
@@ -11,13 +11,14 @@
 //!
 use std::{
     collections::HashMap,
+    str::FromStr,
     sync::{Arc, OnceLock},
     time::Duration,
 };
 
 use anyhow::anyhow;
 use arrow::error::ArrowError;
-use arrow_schema::{Schema, SchemaRef};
+use arrow_schema::{Schema, SchemaRef, SortOptions, TimeUnit};
 use datafusion::error::{DataFusionError, Result};
 use datafusion::execution::SessionStateBuilder;
 use datafusion::execution::context::QueryPlanner;
@@ -35,7 +36,8 @@ use datafusion_common::DataFusionError::External;
 use datafusion_substrait::substrait::proto::Plan;
 use derivative::Derivative;
 use object_store::aws::AmazonS3Builder;
-use object_store::{ClientOptions, RetryConfig};
+use object_store::{ClientOptions, ObjectStore, RetryConfig};
+use parquet::basic::{Compression, ZstdLevel};
 use tracing::debug;
 use url::{ParseError, Url};
 
@@ -44,6 +46,9 @@ use crate::hdfs::Hdfs;
 
 use crate::lakesoul_cache::cache::DiskCache;
 use crate::lakesoul_cache::read_through::ReadThroughCache;
+use crate::partition_path::{
+    HIVE_PARTITION_PATH_ENCODING, PartitionPathEncoder, partition_path_encoder,
+};
 
 static LAKESOUL_CACHE: OnceLock<Arc<DiskCache>> = OnceLock::new();
 
@@ -112,18 +117,202 @@ pub static OPTION_KEY_HASH_BUCKET_ID: &str = "hash_bucket_id";
 pub static OPTION_KEY_HASH_BUCKET_NUM: &str = "hash_bucket_num";
 /// Key for CDC (Change Data Capture) column name
 pub static OPTION_KEY_CDC_COLUMN: &str = "cdc_column";
+/// Key for the comma separated values of the CDC column marking the deleted rows, `delete`
+/// by default
+pub static OPTION_KEY_CDC_DELETE_MARKERS: &str = "cdc_delete_markers";
 /// Key for indicating if data is compacted
 pub static OPTION_KEY_IS_COMPACTED: &str = "is_compacted";
 /// Key for skipping merge operation during read
 pub static OPTION_KEY_SKIP_MERGE_ON_READ: &str = "skip_merge_on_read";
 /// Key for maximum file size in bytes
 pub static OPTION_KEY_MAX_FILE_SIZE: &str = "max_file_size";
+/// Key for maximum number of rows per file
+pub static OPTION_KEY_MAX_FILE_ROWS: &str = "max_file_rows";
+/// Key for the compression codec inside the written parquet files, e.g. `snappy`, `zstd(3)` or `lz4_raw`
+pub static OPTION_KEY_PARQUET_COMPRESSION: &str = "parquet_compression";
+/// Key for reading each partition as of its latest version not newer than the snapshot version
+pub static OPTION_KEY_SNAPSHOT_VERSION: &str = "snapshot_version";
+/// Key for reading the table as of the snapshot timestamp in milliseconds
+pub static OPTION_KEY_SNAPSHOT_TIMESTAMP: &str = "snapshot_timestamp";
+/// Key for the exclusive start version of the change feed
+pub static OPTION_KEY_CHANGE_FEED_FROM_VERSION: &str = "change_feed_from_version";
+/// Key for the inclusive end version of the change feed
+pub static OPTION_KEY_CHANGE_FEED_TO_VERSION: &str = "change_feed_to_version";
 /// Key for spill dir
 pub static OPTION_KEY_SPILL_DIR: &str = "spill_dir";
 /// Key for computing Local Sensitive Hash
 pub static OPTION_KEY_COMPUTE_LSH: &str = "compute_lsh";
 /// Key for using stable sort algorithm
 pub static OPTION_KEY_STABLE_SORT: &str = "stable_sort";
+/// Key for ordering the scan output by the table's column order instead of the projection order
+pub static OPTION_KEY_OUTPUT_TABLE_COLUMN_ORDER: &str = "output_table_column_order";
+/// Key for the comma separated columns written without min/max statistics
+pub static OPTION_KEY_STATISTICS_DISABLED_COLUMNS: &str = "statistics_disabled_columns";
+/// Key for the comma separated sort key columns whose value runs are never split across row groups
+pub static OPTION_KEY_ROW_GROUP_ALIGN_COLUMNS: &str = "row_group_align_columns";
+/// Key for exposing the hash buckets of a primary key table as the output partitions of a scan.
+///
+/// The buckets are assigned by LakeSoul's own hash function rather than DataFusion's, so the
+/// output partitioning is only reported as hash partitioned on the primary keys by
+/// [`crate::datasource::physical_plan::BucketedJoinRule`], for the joins with the scans of
+/// the same buckets, which then do not repartition the rows.
+pub static OPTION_KEY_HASH_PARTITIONED_SCAN: &str = "hash_partitioned_scan";
+/// Key for writing parquet bloom filters of the primary key columns
+pub static OPTION_KEY_PRIMARY_KEY_BLOOM_FILTER: &str = "primary_key_bloom_filter";
+/// Key for the number of retries of completing a multipart upload
+pub static OPTION_KEY_UPLOAD_COMPLETE_RETRIES: &str = "upload_complete_retries";
+/// Key for the base delay in milliseconds of the exponential backoff between upload retries
+pub static OPTION_KEY_UPLOAD_RETRY_BASE_DELAY_MS: &str = "upload_retry_base_delay_ms";
+/// Key for the maximum number of input partitions written concurrently by the sink
+pub static OPTION_KEY_MAX_CONCURRENT_WRITERS: &str = "max_concurrent_writers";
+/// Key for the id embedded in the names of the written files, random for each write if unset
+pub static OPTION_KEY_WRITE_ID: &str = "write_id";
+/// Key for the format of the written data files, `parquet` (default) or `arrow` for Arrow IPC streams
+pub static OPTION_KEY_DATA_FILE_FORMAT: &str = "data_file_format";
+/// Key for the maximum bytes buffered in memory by all open file writers of a sink partition
+pub static OPTION_KEY_MAX_BUFFERED_BYTES: &str = "max_buffered_bytes";
+/// Key for the number of data files whose inferred statistics are cached by a table scan, 0 disables the cache
+pub static OPTION_KEY_STATS_CACHE_SIZE: &str = "stats_cache_size";
+/// Key for the encoding of the range partition values into the sub paths of the data files, `hive` (default) or `directory`
+pub static OPTION_KEY_PARTITION_PATH_ENCODING: &str = "partition_path_encoding";
+/// Key for the template of the names of the files written by the sink, see
+/// [`FileNameTemplate`](crate::file_name::FileNameTemplate)
+pub static OPTION_KEY_FILE_NAME_TEMPLATE: &str = "file_name_template";
+/// Key for merging the written rows of a primary key table into its existing files at write time
+pub static OPTION_KEY_MERGE_ON_WRITE: &str = "merge_on_write";
+/// Key for committing the written partitions of a sink independently instead of atomically
+pub static OPTION_KEY_COMMIT_PER_PARTITION: &str = "commit_per_partition";
+/// Key for failing the commit of a sink if its partitions were committed since the write started
+pub static OPTION_KEY_COMMIT_VERSION_CHECK: &str = "commit_version_check";
+/// Key for writing and committing each input partition of a sink in its own output partition
+pub static OPTION_KEY_PARTITIONED_SINK: &str = "partitioned_sink";
+/// Key for hash partitioning and sorting the written rows by the primary keys before the sink
+pub static OPTION_KEY_CLUSTER_BY_PRIMARY_KEYS: &str = "cluster_by_primary_keys";
+/// Key for keeping the range partition columns in the written data files besides their paths
+pub static OPTION_KEY_KEEP_PARTITION_COLUMNS: &str = "keep_partition_columns";
+/// Key for the maximum number of data file footers fetched concurrently when planning a scan
+pub static OPTION_KEY_META_FETCH_CONCURRENCY: &str = "meta_fetch_concurrency";
+/// Key for leaving the data files that can not be opened out of a scan instead of failing it
+pub static OPTION_KEY_SKIP_UNREADABLE_FILES: &str = "skip_unreadable_files";
+/// Key for coalescing the small batches of a scan into batches of the target size
+pub static OPTION_KEY_COALESCE_SCAN_BATCHES: &str = "coalesce_scan_batches";
+/// Key for the target number of rows of the coalesced batches of a scan
+pub static OPTION_KEY_COALESCE_SCAN_BATCH_SIZE: &str = "coalesce_scan_batch_size";
+/// Key for the best effort maximum size in bytes of the data pages of the written parquet files
+pub static OPTION_KEY_DATA_PAGE_SIZE: &str = "data_page_size";
+/// Key for writing the columns of the parquet files with dictionary encoding, `false` by
+/// default
+pub static OPTION_KEY_DICTIONARY_ENABLED: &str = "dictionary_enabled";
+/// Key for the comma separated columns written with dictionary encoding, overriding
+/// `dictionary_enabled`
+pub static OPTION_KEY_DICTIONARY_ENABLED_COLUMNS: &str = "dictionary_enabled_columns";
+/// Key for the comma separated columns written without dictionary encoding, overriding
+/// `dictionary_enabled`
+pub static OPTION_KEY_DICTIONARY_DISABLED_COLUMNS: &str = "dictionary_disabled_columns";
+/// Key for the best effort maximum size in bytes of the dictionary pages of the written
+/// parquet files, the values are written without dictionary once the limit is reached
+pub static OPTION_KEY_DICTIONARY_PAGE_SIZE: &str = "dictionary_page_size";
+/// Key for the time unit the timestamp columns of the read data files are coerced into,
+/// `s`, `ms`, `us` or `ns`
+pub static OPTION_KEY_COERCE_TIMESTAMP_UNIT: &str = "coerce_timestamp_unit";
+/// Key for reading the string and binary columns of the table as `Utf8View`/`BinaryView`,
+/// overriding `datafusion.execution.parquet.schema_force_view_types` of the session
+pub static OPTION_KEY_FORCE_VIEW_TYPES: &str = "force_view_types";
+/// Key for the column ordering the versions of a primary key when merging, the row with the
+/// highest value wins regardless of the order of the files
+pub static OPTION_KEY_SEQUENCE_COLUMN: &str = "sequence_column";
+/// Key for ordering the null values of the primary keys before the other values when the
+/// rows are sorted and merged by the primary keys, `true` by default
+pub static OPTION_KEY_NULLS_FIRST: &str = "nulls_first";
+/// Key for the target number of rows of the batches output by the merge on read
+pub static OPTION_KEY_MERGE_BATCH_SIZE: &str = "merge_batch_size";
+/// Key for the size in bytes above which the sorted batches of the writers are split before
+/// dropping their aux sort columns
+pub static OPTION_KEY_MAX_BATCH_BYTES: &str = "max_batch_bytes";
+/// Key for the comma separated columns encrypted with parquet modular encryption, see
+/// [`crate::encryption`]
+pub static OPTION_KEY_ENCRYPTED_COLUMNS: &str = "encrypted_columns";
+/// Key for the name of the registered key management service of the encryption keys
+pub static OPTION_KEY_ENCRYPTION_KMS: &str = "encryption_kms";
+/// Key for the id of the footer key, the key of a column has the id `<key id>.<column>`
+pub static OPTION_KEY_ENCRYPTION_KEY_ID: &str = "encryption_key_id";
+/// Key for validating the output schema of the scan plans against the projected schema
+pub static OPTION_KEY_VALIDATE_SCAN_SCHEMA: &str = "validate_scan_schema";
+/// Key for how the versions of a primary key are merged, `last_write_wins` or
+/// `partial_update`, see [`MergeStrategy`]
+pub static OPTION_KEY_MERGE_STRATEGY: &str = "merge_strategy";
+
+/// The format of the data files written by LakeSoul.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DataFileFormat {
+    /// Parquet files, the format of LakeSoul tables.
+    #[default]
+    Parquet,
+    /// Arrow IPC stream files, which are read back without decoding.
+    ArrowIpc,
+}
+
+impl DataFileFormat {
+    /// The file extension of the data files of the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            DataFileFormat::Parquet => "parquet",
+            DataFileFormat::ArrowIpc => "arrow",
+        }
+    }
+}
+
+impl FromStr for DataFileFormat {
+    type Err = DataFusionError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "parquet" => Ok(DataFileFormat::Parquet),
+            "arrow" | "ipc" | "feather" => Ok(DataFileFormat::ArrowIpc),
+            _ => Err(DataFusionError::Configuration(format!(
+                "invalid data file format {}, expected parquet or arrow",
+                s
+            ))),
+        }
+    }
+}
+
+/// How the versions of a primary key are merged into one row on read, for the columns
+/// without a merge operator.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// The last version replaces the whole row, including with null values.
+    #[default]
+    LastWriteWins,
+    /// Each column keeps the value of the last version where it is not null, so that a
+    /// version writing only some columns, with the others null, updates only those.
+    PartialUpdate,
+}
+
+impl MergeStrategy {
+    /// The name of the strategy as set in the options.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MergeStrategy::LastWriteWins => "last_write_wins",
+            MergeStrategy::PartialUpdate => "partial_update",
+        }
+    }
+}
+
+impl FromStr for MergeStrategy {
+    type Err = DataFusionError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "last_write_wins" => Ok(MergeStrategy::LastWriteWins),
+            "partial_update" => Ok(MergeStrategy::PartialUpdate),
+            _ => Err(DataFusionError::Configuration(format!(
+                "invalid merge strategy {}, expected last_write_wins or partial_update",
+                s
+            ))),
+        }
+    }
+}
 
 #[derive(Derivative, Debug)]
 #[derivative(Default, Clone)]
@@ -158,7 +347,12 @@ pub struct LakeSoulIOConfig {
     /// Number of rows per batch for reading/writing
     #[derivative(Default(value = "8192"))]
     pub(crate) batch_size: usize,
-    /// Maximum number of rows per row group when writing
+    /// Maximum number of rows per row group when writing.
+    ///
+    /// The min/max statistics are kept per row group, so smaller row groups let a filtered
+    /// scan skip more rows, at the cost of larger footers and less efficient compression.
+    /// Larger row groups are read with fewer, larger requests and improve the throughput of
+    /// full scans.
     #[derivative(Default(value = "250000"))]
     pub(crate) max_row_group_size: usize,
     /// Maximum number of values per row group when writing
@@ -176,10 +370,15 @@ pub struct LakeSoulIOConfig {
     pub(crate) partition_schema: IOSchema,
     /// Object store configuration options (e.g., S3 credentials)
     pub(crate) object_store_options: HashMap<String, String>,
+    /// Object stores supplied by the caller, used instead of the ones created from the
+    /// object store options
+    pub(crate) object_stores: Vec<(ObjectStoreUrl, Arc<dyn ObjectStore>)>,
     /// Merge operators for each column
     pub(crate) merge_operators: HashMap<String, String>,
     /// Default values for columns
     pub(crate) default_column_value: HashMap<String, String>,
+    /// SQL expressions computing the generated columns from the other columns at write time
+    pub(crate) generated_columns: HashMap<String, String>,
     /// Number of threads for parallel processing
     #[derivative(Default(value = "2"))]
     pub(crate) thread_num: usize,
@@ -248,6 +447,11 @@ impl LakeSoulIOConfig {
         &self.prefix
     }
 
+    /// Returns the SQL expressions of the generated columns by their column names
+    pub fn generated_columns(&self) -> &HashMap<String, String> {
+        &self.generated_columns
+    }
+
     /// Returns whether to keep row order in output
     pub fn keep_ordering(&self) -> bool {
         self.option(OPTION_KEY_KEEP_ORDERS)
@@ -266,6 +470,384 @@ impl LakeSoulIOConfig {
             .map(|x| x.parse().unwrap())
     }
 
+    /// Returns the maximum number of rows per file if set
+    pub fn max_file_rows_option(&self) -> Result<Option<u64>> {
+        let Some(rows) = self.option(OPTION_KEY_MAX_FILE_ROWS) else {
+            return Ok(None);
+        };
+        match rows.parse::<u64>() {
+            Ok(max_file_rows) if max_file_rows > 0 => Ok(Some(max_file_rows)),
+            _ => Err(DataFusionError::Configuration(format!(
+                "invalid max file rows {}, expected a positive number of rows",
+                rows
+            ))),
+        }
+    }
+
+    /// Returns the number of data files whose inferred statistics are cached (defaults to 1024)
+    pub fn stats_cache_size(&self) -> Result<u64> {
+        self.option(OPTION_KEY_STATS_CACHE_SIZE)
+            .map_or(Ok(1024), |size| {
+                size.parse::<u64>().map_err(|e| {
+                    DataFusionError::Configuration(format!(
+                        "invalid stats cache size {}: {}",
+                        size, e
+                    ))
+                })
+            })
+    }
+
+    /// Returns the maximum number of data file footers fetched concurrently (defaults to 32)
+    pub fn meta_fetch_concurrency(&self) -> Result<usize> {
+        let Some(concurrency) = self.option(OPTION_KEY_META_FETCH_CONCURRENCY) else {
+            return Ok(32);
+        };
+        match concurrency.parse::<usize>() {
+            Ok(meta_fetch_concurrency) if meta_fetch_concurrency > 0 => {
+                Ok(meta_fetch_concurrency)
+            }
+            _ => Err(DataFusionError::Configuration(format!(
+                "invalid meta fetch concurrency {}, expected a positive number of fetches",
+                concurrency
+            ))),
+        }
+    }
+
+    /// Returns whether the data files that can not be opened are left out of a scan
+    /// (defaults to false)
+    pub fn skip_unreadable_files(&self) -> bool {
+        self.option(OPTION_KEY_SKIP_UNREADABLE_FILES)
+            .is_some_and(|x| x.eq("true"))
+    }
+
+    /// Returns whether the small batches of a scan are coalesced (defaults to false)
+    pub fn coalesce_scan_batches(&self) -> bool {
+        self.option(OPTION_KEY_COALESCE_SCAN_BATCHES)
+            .is_some_and(|x| x.eq("true"))
+    }
+
+    /// Returns the target number of rows of the coalesced batches of a scan
+    /// (defaults to 8192)
+    pub fn coalesce_scan_batch_size(&self) -> Result<usize> {
+        let Some(size) = self.option(OPTION_KEY_COALESCE_SCAN_BATCH_SIZE) else {
+            return Ok(8192);
+        };
+        match size.parse::<usize>() {
+            Ok(batch_size) if batch_size > 0 => Ok(batch_size),
+            _ => Err(DataFusionError::Configuration(format!(
+                "invalid coalesce scan batch size {}, expected a positive number of rows",
+                size
+            ))),
+        }
+    }
+
+    /// Returns the maximum bytes buffered by all open file writers of a sink partition if set
+    pub fn max_buffered_bytes_option(&self) -> Result<Option<u64>> {
+        self.option(OPTION_KEY_MAX_BUFFERED_BYTES)
+            .map(|bytes| {
+                bytes.parse::<u64>().map_err(|e| {
+                    DataFusionError::Configuration(format!(
+                        "invalid max buffered bytes {}: {}",
+                        bytes, e
+                    ))
+                })
+            })
+            .transpose()
+    }
+
+    /// Returns the compression codec of the written parquet files (defaults to zstd with the default level)
+    pub fn parquet_compression(&self) -> Result<Compression> {
+        match self.option(OPTION_KEY_PARQUET_COMPRESSION) {
+            Some(compression) => Compression::from_str(compression).map_err(|e| {
+                DataFusionError::Configuration(format!(
+                    "invalid parquet compression {}: {}",
+                    compression, e
+                ))
+            }),
+            None => Ok(Compression::ZSTD(ZstdLevel::default())),
+        }
+    }
+
+    /// Returns the maximum number of rows per row group of the written parquet files
+    /// (defaults to 250000)
+    pub fn max_row_group_size(&self) -> Result<usize> {
+        if self.max_row_group_size == 0 {
+            return Err(DataFusionError::Configuration(
+                "max row group size must be greater than 0".to_string(),
+            ));
+        }
+        Ok(self.max_row_group_size)
+    }
+
+    /// Returns the maximum size in bytes of the data pages of the written parquet files if set
+    pub fn data_page_size(&self) -> Result<Option<usize>> {
+        let Some(size) = self.option(OPTION_KEY_DATA_PAGE_SIZE) else {
+            return Ok(None);
+        };
+        match size.parse::<usize>() {
+            Ok(data_page_size) if data_page_size > 0 => Ok(Some(data_page_size)),
+            _ => Err(DataFusionError::Configuration(format!(
+                "invalid data page size {}, expected a positive number of bytes",
+                size
+            ))),
+        }
+    }
+
+    /// Returns whether the columns of the written parquet files are dictionary encoded
+    /// unless set per column (defaults to false)
+    pub fn dictionary_enabled(&self) -> bool {
+        self.option(OPTION_KEY_DICTIONARY_ENABLED)
+            .is_some_and(|x| x.eq("true"))
+    }
+
+    /// Returns the columns written with dictionary encoding (defaults to none)
+    pub fn dictionary_enabled_columns(&self) -> Vec<String> {
+        self.option(OPTION_KEY_DICTIONARY_ENABLED_COLUMNS)
+            .map(|x| {
+                x.split(',')
+                    .filter(|column| !column.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the columns written without dictionary encoding (defaults to none)
+    pub fn dictionary_disabled_columns(&self) -> Vec<String> {
+        self.option(OPTION_KEY_DICTIONARY_DISABLED_COLUMNS)
+            .map(|x| {
+                x.split(',')
+                    .filter(|column| !column.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the maximum size in bytes of the dictionary pages of the written parquet
+    /// files if set
+    pub fn dictionary_page_size(&self) -> Result<Option<usize>> {
+        let Some(size) = self.option(OPTION_KEY_DICTIONARY_PAGE_SIZE) else {
+            return Ok(None);
+        };
+        match size.parse::<usize>() {
+            Ok(dictionary_page_size) if dictionary_page_size > 0 => {
+                Ok(Some(dictionary_page_size))
+            }
+            _ => Err(DataFusionError::Configuration(format!(
+                "invalid dictionary page size {}, expected a positive number of bytes",
+                size
+            ))),
+        }
+    }
+
+    /// Returns the time unit the timestamp columns of the read data files are coerced into
+    /// if set
+    pub fn coerce_timestamp_unit(&self) -> Result<Option<TimeUnit>> {
+        let Some(unit) = self.option(OPTION_KEY_COERCE_TIMESTAMP_UNIT) else {
+            return Ok(None);
+        };
+        match unit.to_ascii_lowercase().as_str() {
+            "s" => Ok(Some(TimeUnit::Second)),
+            "ms" => Ok(Some(TimeUnit::Millisecond)),
+            "us" => Ok(Some(TimeUnit::Microsecond)),
+            "ns" => Ok(Some(TimeUnit::Nanosecond)),
+            _ => Err(DataFusionError::Configuration(format!(
+                "invalid timestamp unit {}, expected s, ms, us or ns",
+                unit
+            ))),
+        }
+    }
+
+    /// Returns whether the string and binary columns are read as their view types if set,
+    /// otherwise the session decides
+    pub fn force_view_types(&self) -> Option<bool> {
+        self.option(OPTION_KEY_FORCE_VIEW_TYPES)
+            .map(|x| x.eq("true"))
+    }
+
+    /// Returns the column ordering the versions of a primary key when merging if set
+    pub fn sequence_column(&self) -> Option<String> {
+        self.option(OPTION_KEY_SEQUENCE_COLUMN)
+            .filter(|x| !x.is_empty())
+            .cloned()
+    }
+
+    /// Returns the merge operators of the columns by column name, see
+    /// [`LakeSoulIOConfigBuilder::with_merge_op`]
+    pub fn merge_operators(&self) -> &HashMap<String, String> {
+        &self.merge_operators
+    }
+
+    /// Returns how the versions of a primary key are merged (defaults to last write wins)
+    pub fn merge_strategy(&self) -> Result<MergeStrategy> {
+        self.option(OPTION_KEY_MERGE_STRATEGY)
+            .map_or(Ok(MergeStrategy::LastWriteWins), |strategy| {
+                strategy.parse()
+            })
+    }
+
+    /// Returns whether the null values of the primary keys are ordered first (defaults to true)
+    pub fn nulls_first(&self) -> bool {
+        self.option(OPTION_KEY_NULLS_FIRST)
+            .is_none_or(|x| x.eq("true"))
+    }
+
+    /// Returns the target number of rows of the batches output by the merge on read if
+    /// set
+    pub fn merge_batch_size(&self) -> Result<Option<usize>> {
+        let Some(size) = self.option(OPTION_KEY_MERGE_BATCH_SIZE) else {
+            return Ok(None);
+        };
+        match size.parse::<usize>() {
+            Ok(batch_size) if batch_size > 0 => Ok(Some(batch_size)),
+            _ => Err(DataFusionError::Configuration(format!(
+                "invalid merge batch size {}, expected a positive number of rows",
+                size
+            ))),
+        }
+    }
+
+    /// Returns the size in bytes above which the sorted batches of the writers are split if
+    /// set
+    pub fn max_batch_bytes(&self) -> Result<Option<usize>> {
+        let Some(bytes) = self.option(OPTION_KEY_MAX_BATCH_BYTES) else {
+            return Ok(None);
+        };
+        match bytes.parse::<usize>() {
+            Ok(max_batch_bytes) if max_batch_bytes > 0 => Ok(Some(max_batch_bytes)),
+            _ => Err(DataFusionError::Configuration(format!(
+                "invalid max batch bytes {}, expected a positive number of bytes",
+                bytes
+            ))),
+        }
+    }
+
+    /// Returns the options of sorting and merging the rows by the primary keys
+    pub fn primary_key_sort_options(&self) -> SortOptions {
+        SortOptions {
+            descending: false,
+            nulls_first: self.nulls_first(),
+        }
+    }
+
+    /// Returns the columns the merge reads besides the projected ones, the primary keys and
+    /// the sequence column
+    pub fn merge_columns(&self) -> Vec<String> {
+        let mut columns = self.primary_keys.clone();
+        columns.extend(self.sequence_column());
+        columns
+    }
+
+    /// Returns the encoder of the sub paths of the written data files (defaults to hive)
+    pub fn partition_path_encoder(&self) -> Result<Arc<dyn PartitionPathEncoder>> {
+        partition_path_encoder(
+            self.option(OPTION_KEY_PARTITION_PATH_ENCODING)
+                .map_or(HIVE_PARTITION_PATH_ENCODING, String::as_str),
+        )
+    }
+
+    /// Returns the template of the names of the files written by the sink if set
+    pub fn file_name_template(&self) -> Option<&String> {
+        self.option(OPTION_KEY_FILE_NAME_TEMPLATE)
+    }
+
+    /// Returns whether the written rows are merged into the existing files of their
+    /// partitions at write time (defaults to false)
+    pub fn merge_on_write(&self) -> bool {
+        self.option(OPTION_KEY_MERGE_ON_WRITE)
+            .is_some_and(|x| x.eq("true"))
+    }
+
+    /// Returns whether the written partitions are committed independently of each other
+    /// (defaults to false)
+    pub fn commit_per_partition(&self) -> bool {
+        self.option(OPTION_KEY_COMMIT_PER_PARTITION)
+            .is_some_and(|x| x.eq("true"))
+    }
+
+    /// Returns whether the commit of the sink fails if one of its partitions was committed
+    /// since the write started (defaults to false)
+    pub fn commit_version_check(&self) -> bool {
+        self.option(OPTION_KEY_COMMIT_VERSION_CHECK)
+            .is_some_and(|x| x.eq("true"))
+    }
+
+    /// Returns whether each input partition of the sink is written and committed by its own
+    /// output partition (defaults to false)
+    pub fn partitioned_sink(&self) -> bool {
+        self.option(OPTION_KEY_PARTITIONED_SINK)
+            .is_some_and(|x| x.eq("true"))
+    }
+
+    /// Returns whether the written rows are hash partitioned and sorted by the primary keys
+    /// before the sink (defaults to false)
+    pub fn cluster_by_primary_keys(&self) -> bool {
+        self.option(OPTION_KEY_CLUSTER_BY_PRIMARY_KEYS)
+            .is_some_and(|x| x.eq("true"))
+    }
+
+    /// Returns whether the range partition columns are written into the data files as well as
+    /// encoded into their paths (defaults to false)
+    pub fn keep_partition_columns(&self) -> bool {
+        self.option(OPTION_KEY_KEEP_PARTITION_COLUMNS)
+            .is_some_and(|x| x.eq("true"))
+    }
+
+    /// Returns the format of the written data files (defaults to parquet)
+    pub fn data_file_format(&self) -> Result<DataFileFormat> {
+        self.option(OPTION_KEY_DATA_FILE_FORMAT)
+            .map_or(Ok(DataFileFormat::Parquet), |format| format.parse())
+    }
+
+    /// Returns the snapshot version to read if set
+    pub fn snapshot_version(&self) -> Result<Option<i32>> {
+        self.option(OPTION_KEY_SNAPSHOT_VERSION)
+            .map(|version| {
+                version.parse::<i32>().map_err(|e| {
+                    DataFusionError::Configuration(format!(
+                        "invalid snapshot version {}: {}",
+                        version, e
+                    ))
+                })
+            })
+            .transpose()
+    }
+
+    /// Returns the snapshot timestamp in milliseconds to read if set
+    pub fn snapshot_timestamp(&self) -> Result<Option<i64>> {
+        self.option(OPTION_KEY_SNAPSHOT_TIMESTAMP)
+            .map(|timestamp| {
+                timestamp.parse::<i64>().map_err(|e| {
+                    DataFusionError::Configuration(format!(
+                        "invalid snapshot timestamp {}: {}",
+                        timestamp, e
+                    ))
+                })
+            })
+            .transpose()
+    }
+
+    /// Returns the exclusive start and inclusive end version of the change feed if set
+    pub fn change_feed(&self) -> Result<Option<(i32, i32)>> {
+        let (Some(from), Some(to)) = (
+            self.option(OPTION_KEY_CHANGE_FEED_FROM_VERSION),
+            self.option(OPTION_KEY_CHANGE_FEED_TO_VERSION),
+        ) else {
+            return Ok(None);
+        };
+        match (from.parse::<i32>(), to.parse::<i32>()) {
+            (Ok(from_version), Ok(to_version)) if from_version <= to_version => {
+                Ok(Some((from_version, to_version)))
+            }
+            _ => Err(DataFusionError::Configuration(format!(
+                "invalid change feed from version {} to version {}, expected versions \
+                with the start not after the end",
+                from, to
+            ))),
+        }
+    }
+
     /// Returns the memory pool size in bytes if set
     pub fn pool_size(&self) -> Option<usize> {
         self.option(OPTION_KEY_POOL_SIZE)
@@ -290,6 +872,24 @@ impl LakeSoulIOConfig {
             .map_or_else(String::new, |x| x.to_string())
     }
 
+    /// Returns the values of the CDC column marking the deleted rows (defaults to `delete`)
+    pub fn cdc_delete_markers(&self) -> Vec<String> {
+        let markers = self
+            .option(OPTION_KEY_CDC_DELETE_MARKERS)
+            .map(|x| {
+                x.split(',')
+                    .filter(|marker| !marker.is_empty())
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        if markers.is_empty() {
+            vec!["delete".to_string()]
+        } else {
+            markers
+        }
+    }
+
     /// Returns whether the data is compacted, default is false
     pub fn is_compacted(&self) -> bool {
         self.option(OPTION_KEY_IS_COMPACTED)
@@ -313,6 +913,128 @@ impl LakeSoulIOConfig {
         self.option(OPTION_KEY_STABLE_SORT)
             .is_some_and(|x| x.eq("true"))
     }
+
+    /// Returns whether the scan output follows the table's column order (defaults to false)
+    pub fn output_table_column_order(&self) -> bool {
+        self.option(OPTION_KEY_OUTPUT_TABLE_COLUMN_ORDER)
+            .is_some_and(|x| x.eq("true"))
+    }
+
+    /// Returns whether the output schema of the scan plans is validated against the
+    /// projected schema, always the case in debug builds (defaults to false)
+    pub fn validate_scan_schema(&self) -> bool {
+        cfg!(debug_assertions)
+            || self
+                .option(OPTION_KEY_VALIDATE_SCAN_SCHEMA)
+                .is_some_and(|x| x.eq("true"))
+    }
+
+    /// Returns the columns whose value runs are never split across row groups (defaults to none)
+    pub fn row_group_align_columns(&self) -> Vec<String> {
+        self.option(OPTION_KEY_ROW_GROUP_ALIGN_COLUMNS)
+            .map(|x| {
+                x.split(',')
+                    .filter(|column| !column.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns whether the scan outputs one partition per hash bucket (defaults to false)
+    pub fn hash_partitioned_scan(&self) -> bool {
+        self.option(OPTION_KEY_HASH_PARTITIONED_SCAN)
+            .is_some_and(|x| x.eq("true"))
+    }
+
+    /// Returns whether bloom filters of the primary key columns are written (defaults to false)
+    pub fn primary_key_bloom_filter(&self) -> bool {
+        self.option(OPTION_KEY_PRIMARY_KEY_BLOOM_FILTER)
+            .is_some_and(|x| x.eq("true"))
+    }
+
+    /// Returns the number of retries of completing a multipart upload (defaults to 3)
+    pub fn upload_complete_retries(&self) -> Result<usize> {
+        self.option(OPTION_KEY_UPLOAD_COMPLETE_RETRIES)
+            .map_or(Ok(3), |retries| {
+                retries.parse::<usize>().map_err(|e| {
+                    DataFusionError::Configuration(format!(
+                        "invalid upload complete retries {}: {}",
+                        retries, e
+                    ))
+                })
+            })
+    }
+
+    /// Returns the base delay of the exponential backoff between upload retries (defaults to 100ms)
+    pub fn upload_retry_base_delay(&self) -> Result<Duration> {
+        self.option(OPTION_KEY_UPLOAD_RETRY_BASE_DELAY_MS)
+            .map_or(Ok(100), |delay| {
+                delay.parse::<u64>().map_err(|e| {
+                    DataFusionError::Configuration(format!(
+                        "invalid upload retry base delay {}: {}",
+                        delay, e
+                    ))
+                })
+            })
+            .map(Duration::from_millis)
+    }
+
+    /// Returns the maximum number of input partitions written concurrently (defaults to the number of CPUs)
+    pub fn max_concurrent_writers(&self) -> Result<usize> {
+        let Some(writers) = self.option(OPTION_KEY_MAX_CONCURRENT_WRITERS) else {
+            return Ok(num_cpus::get());
+        };
+        match writers.parse::<usize>() {
+            Ok(max_concurrent_writers) if max_concurrent_writers > 0 => {
+                Ok(max_concurrent_writers)
+            }
+            _ => Err(DataFusionError::Configuration(format!(
+                "invalid max concurrent writers {}, expected a positive number of writers",
+                writers
+            ))),
+        }
+    }
+
+    /// Returns the id embedded in the names of the written files if set
+    pub fn write_id(&self) -> Option<&String> {
+        self.option(OPTION_KEY_WRITE_ID)
+    }
+
+    /// Returns the columns written without statistics (defaults to none)
+    pub fn statistics_disabled_columns(&self) -> Vec<String> {
+        self.option(OPTION_KEY_STATISTICS_DISABLED_COLUMNS)
+            .map(|x| {
+                x.split(',')
+                    .filter(|column| !column.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the columns encrypted in the written files (defaults to none)
+    pub fn encrypted_columns(&self) -> Vec<String> {
+        self.option(OPTION_KEY_ENCRYPTED_COLUMNS)
+            .map(|x| {
+                x.split(',')
+                    .filter(|column| !column.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the name of the key management service of the encryption if set
+    pub fn encryption_kms(&self) -> Option<&String> {
+        self.option(OPTION_KEY_ENCRYPTION_KMS)
+    }
+
+    /// Returns the id of the footer key of the encryption (defaults to `lakesoul`)
+    pub fn encryption_key_id(&self) -> &str {
+        self.option(OPTION_KEY_ENCRYPTION_KEY_ID)
+            .map_or("lakesoul", String::as_str)
+    }
 }
 
 #[derive(Derivative, Debug)]
@@ -467,6 +1189,9 @@ impl LakeSoulIOConfigBuilder {
 
     /// Sets the maximum number of rows per row group when writing
     ///
+    /// Smaller row groups improve the pruning of filtered scans, larger row groups improve
+    /// the throughput of full scans.
+    ///
     /// # Arguments
     ///
     /// * `max_row_group_size` - The maximum number of rows per row group when writing
@@ -594,6 +1319,48 @@ impl LakeSoulIOConfigBuilder {
         self
     }
 
+    /// Sets the SQL expression computing a generated column at write time
+    ///
+    /// The expression is evaluated over the other columns of the written rows, e.g.
+    /// `date_trunc('day', ts)` deriving a range partition column from a timestamp column,
+    /// and its result replaces the value of the column in the written rows. A generated
+    /// range partition column is only encoded into the file paths, like the other range
+    /// partition columns, so it is not stored in the data files. The writers created by
+    /// [`crate::lakesoul_writer::create_writer`] compute the generated columns before
+    /// partitioning and sorting the written rows.
+    ///
+    /// # Arguments
+    ///
+    /// * `field_name` - The name of the generated column
+    /// * `expr` - The SQL expression computing the column
+    pub fn with_generated_column(
+        mut self,
+        field_name: impl Into<String>,
+        expr: impl Into<String>,
+    ) -> Self {
+        self.config
+            .generated_columns
+            .insert(field_name.into(), expr.into());
+        self
+    }
+
+    /// Uses the object store for the paths of the URL instead of creating one from the
+    /// object store options or the environment, e.g. a store configured with the
+    /// credentials of a tenant.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL of the object store, e.g. `s3://bucket`
+    /// * `store` - The object store of the paths of the URL
+    pub fn with_object_store(
+        mut self,
+        url: ObjectStoreUrl,
+        store: Arc<dyn ObjectStore>,
+    ) -> Self {
+        self.config.object_stores.push((url, store));
+        self
+    }
+
     /// Adds an object store option
     ///
     /// # Arguments
@@ -666,6 +1433,544 @@ impl LakeSoulIOConfigBuilder {
         self
     }
 
+    /// Sets the compression codec inside the written parquet files
+    ///
+    /// # Arguments
+    ///
+    /// * `compression` - The parquet compression codec, e.g. [`Compression::SNAPPY`]
+    pub fn with_parquet_compression(self, compression: Compression) -> Self {
+        let compression = match compression {
+            Compression::UNCOMPRESSED => "uncompressed".to_string(),
+            Compression::SNAPPY => "snappy".to_string(),
+            Compression::GZIP(level) => format!("gzip({})", level.compression_level()),
+            Compression::LZO => "lzo".to_string(),
+            Compression::BROTLI(level) => {
+                format!("brotli({})", level.compression_level())
+            }
+            Compression::LZ4 => "lz4".to_string(),
+            Compression::ZSTD(level) => format!("zstd({})", level.compression_level()),
+            Compression::LZ4_RAW => "lz4_raw".to_string(),
+        };
+        self.with_option(OPTION_KEY_PARQUET_COMPRESSION, compression)
+    }
+
+    /// Sets the retries of completing the multipart uploads of the written files.
+    ///
+    /// The `n`-th retry waits `base_delay * 2^(n-1)`, the upload is aborted once all retries failed.
+    ///
+    /// # Arguments
+    ///
+    /// * `retries` - The number of retries after the first failed attempt
+    /// * `base_delay` - The delay before the first retry
+    pub fn with_upload_retry(self, retries: usize, base_delay: Duration) -> Self {
+        self.with_option(OPTION_KEY_UPLOAD_COMPLETE_RETRIES, retries.to_string())
+            .with_option(
+                OPTION_KEY_UPLOAD_RETRY_BASE_DELAY_MS,
+                base_delay.as_millis().to_string(),
+            )
+    }
+
+    /// Sets the maximum size of the data pages of the written parquet files.
+    ///
+    /// The page index holds the min/max of each page, so smaller pages let a filtered scan
+    /// skip more rows within a row group, while larger pages compress better and cost less
+    /// page headers. The limit is best effort, checked after each written batch of values.
+    ///
+    /// # Arguments
+    ///
+    /// * `data_page_size` - The maximum size in bytes of a data page
+    pub fn with_data_page_size(self, data_page_size: usize) -> Self {
+        self.with_option(OPTION_KEY_DATA_PAGE_SIZE, data_page_size.to_string())
+    }
+
+    /// Writes the columns of the parquet files with dictionary encoding, except for the
+    /// columns set otherwise with [`Self::with_dictionary_columns`].
+    ///
+    /// The dictionary encoding shrinks the columns of few distinct values, e.g. the low
+    /// cardinality strings, while the columns of many distinct values pay for a
+    /// dictionary which overflows its page size and falls back to the plain encoding.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether the columns are dictionary encoded by default
+    pub fn with_dictionary_enabled(self, enabled: bool) -> Self {
+        self.with_option(OPTION_KEY_DICTIONARY_ENABLED, enabled.to_string())
+    }
+
+    /// Sets the columns written with and without dictionary encoding, overriding the
+    /// default of [`Self::with_dictionary_enabled`].
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - The names of the columns written with dictionary encoding
+    /// * `disabled` - The names of the columns written without dictionary encoding
+    pub fn with_dictionary_columns(
+        self,
+        enabled: Vec<String>,
+        disabled: Vec<String>,
+    ) -> Self {
+        self.with_option(OPTION_KEY_DICTIONARY_ENABLED_COLUMNS, enabled.join(","))
+            .with_option(OPTION_KEY_DICTIONARY_DISABLED_COLUMNS, disabled.join(","))
+    }
+
+    /// Sets the maximum size of the dictionary pages of the written parquet files, the
+    /// values of a column chunk are written without dictionary once it is reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `dictionary_page_size` - The maximum size in bytes of a dictionary page
+    pub fn with_dictionary_page_size(self, dictionary_page_size: usize) -> Self {
+        self.with_option(
+            OPTION_KEY_DICTIONARY_PAGE_SIZE,
+            dictionary_page_size.to_string(),
+        )
+    }
+
+    /// Coerces the timestamp columns of the read data files into one time unit.
+    ///
+    /// Files written by different engines store the same timestamp column as legacy Int96
+    /// values, read as nanoseconds, or with different units. Coercing them into one unit
+    /// gives all files of the table the same schema, the values are truncated when coerced
+    /// into a coarser unit.
+    ///
+    /// # Arguments
+    ///
+    /// * `unit` - The time unit of the timestamp columns
+    pub fn with_coerce_timestamp_unit(self, unit: TimeUnit) -> Self {
+        let unit = match unit {
+            TimeUnit::Second => "s",
+            TimeUnit::Millisecond => "ms",
+            TimeUnit::Microsecond => "us",
+            TimeUnit::Nanosecond => "ns",
+        };
+        self.with_option(OPTION_KEY_COERCE_TIMESTAMP_UNIT, unit)
+    }
+
+    /// Reads the string and binary columns of the table as `Utf8View`/`BinaryView` instead of
+    /// `Utf8`/`Binary`, regardless of the session setting.
+    ///
+    /// The view types avoid copying the values of the decoded pages, which is much faster on
+    /// string heavy scans. The range partition columns keep their type.
+    ///
+    /// # Arguments
+    ///
+    /// * `force_view_types` - Whether to read the view types
+    pub fn with_force_view_types(self, force_view_types: bool) -> Self {
+        self.with_option(OPTION_KEY_FORCE_VIEW_TYPES, force_view_types.to_string())
+    }
+
+    /// Sets the number of data files whose inferred statistics are cached by a table scan.
+    ///
+    /// The cache is shared by the scans of all tables, it holds the files of the largest
+    /// size set by any of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `stats_cache_size` - The number of cached files, 0 disables the cache
+    pub fn with_stats_cache_size(self, stats_cache_size: u64) -> Self {
+        self.with_option(OPTION_KEY_STATS_CACHE_SIZE, stats_cache_size.to_string())
+    }
+
+    /// Sets the maximum number of data file footers fetched concurrently when planning a scan.
+    ///
+    /// The schemas and statistics of the files are read from their footers, so planning a
+    /// scan of many files is bound by the latency of the object store without concurrency.
+    ///
+    /// # Arguments
+    ///
+    /// * `meta_fetch_concurrency` - The maximum number of concurrent fetches
+    pub fn with_meta_fetch_concurrency(self, meta_fetch_concurrency: usize) -> Self {
+        self.with_option(
+            OPTION_KEY_META_FETCH_CONCURRENCY,
+            meta_fetch_concurrency.to_string(),
+        )
+    }
+
+    /// Sets whether the data files that can not be opened are left out of a scan.
+    ///
+    /// By default a corrupt or missing data file fails the whole scan. Skipping such files
+    /// keeps the rest of the table readable at the cost of silently losing their rows, so the
+    /// skipped files are logged with the approximate number of rows lost and counted by the
+    /// `skipped_files` and `skipped_rows` metrics of the scan plan.
+    ///
+    /// # Arguments
+    ///
+    /// * `skip_unreadable_files` - Whether to skip the unreadable data files
+    pub fn with_skip_unreadable_files(self, skip_unreadable_files: bool) -> Self {
+        self.with_option(
+            OPTION_KEY_SKIP_UNREADABLE_FILES,
+            skip_unreadable_files.to_string(),
+        )
+    }
+
+    /// Sets whether the small batches of a scan are coalesced.
+    ///
+    /// A scan over many small files, or a merge emitting few rows per batch, produces many
+    /// small batches. Coalescing them on top of the scan hands the downstream operators
+    /// batches of about [`with_coalesce_scan_batch_size`](Self::with_coalesce_scan_batch_size)
+    /// rows.
+    ///
+    /// # Arguments
+    ///
+    /// * `coalesce_scan_batches` - Whether to coalesce the batches of a scan
+    pub fn with_coalesce_scan_batches(self, coalesce_scan_batches: bool) -> Self {
+        self.with_option(
+            OPTION_KEY_COALESCE_SCAN_BATCHES,
+            coalesce_scan_batches.to_string(),
+        )
+    }
+
+    /// Sets the target number of rows of the coalesced batches of a scan.
+    ///
+    /// # Arguments
+    ///
+    /// * `batch_size` - The target number of rows of the coalesced batches
+    pub fn with_coalesce_scan_batch_size(self, batch_size: usize) -> Self {
+        self.with_option(OPTION_KEY_COALESCE_SCAN_BATCH_SIZE, batch_size.to_string())
+    }
+
+    /// Sets the maximum bytes buffered in memory by the open file writers of a sink partition.
+    ///
+    /// Once the open writers of all range partitions buffer more than this, the file of the
+    /// writer buffering the most is closed and the partition continues in a new file.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_buffered_bytes` - The maximum buffered bytes
+    pub fn with_max_buffered_bytes(self, max_buffered_bytes: u64) -> Self {
+        self.with_option(
+            OPTION_KEY_MAX_BUFFERED_BYTES,
+            max_buffered_bytes.to_string(),
+        )
+    }
+
+    /// Sets the maximum number of input partitions written concurrently.
+    ///
+    /// Each written input partition buffers its open files, the remaining partitions wait until
+    /// one of the running writers has finished.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_concurrent_writers` - The maximum number of concurrent writers
+    pub fn with_max_concurrent_writers(self, max_concurrent_writers: usize) -> Self {
+        self.with_option(
+            OPTION_KEY_MAX_CONCURRENT_WRITERS,
+            max_concurrent_writers.to_string(),
+        )
+    }
+
+    /// Sets the id embedded in the names of the written files.
+    ///
+    /// A retried write with the same id overwrites the files of the failed attempt instead of
+    /// leaving them behind, the id must be alphanumeric.
+    ///
+    /// # Arguments
+    ///
+    /// * `write_id` - The id of the write
+    pub fn with_write_id(self, write_id: impl Into<String>) -> Self {
+        self.with_option(OPTION_KEY_WRITE_ID, write_id.into())
+    }
+
+    /// Sets the encoding of the range partition values into the sub paths of the data files.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoding` - The name of the encoding, `hive` or `directory`
+    pub fn with_partition_path_encoding(self, encoding: impl Into<String>) -> Self {
+        self.with_option(OPTION_KEY_PARTITION_PATH_ENCODING, encoding.into())
+    }
+
+    /// Sets the template of the names of the files written by the sink.
+    ///
+    /// The placeholders `{write_id}`, `{partition}`, `{bucket}` and `{seq}` are replaced at
+    /// write time, e.g. `part-{partition}-{write_id}_{bucket}.c{seq}` for Spark style names.
+    /// The template is validated when the write starts, so that it names every rolled file
+    /// of every input partition and hash bucket uniquely, see
+    /// [`FileNameTemplate::try_new`](crate::file_name::FileNameTemplate::try_new).
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The template of the file names, without the extension
+    pub fn with_file_name_template(self, template: impl Into<String>) -> Self {
+        self.with_option(OPTION_KEY_FILE_NAME_TEMPLATE, template.into())
+    }
+
+    /// Sets whether the written rows are merged into the existing files of their partitions.
+    ///
+    /// The rows of a primary key table are then merged with the files of their hash bucket
+    /// into compacted files, which replace the superseded files at commit, so that readers
+    /// have no merge on read to do.
+    ///
+    /// # Arguments
+    ///
+    /// * `merge_on_write` - Whether to merge at write time
+    pub fn with_merge_on_write(self, merge_on_write: bool) -> Self {
+        self.with_option(OPTION_KEY_MERGE_ON_WRITE, merge_on_write.to_string())
+    }
+
+    /// Sets whether the written partitions are committed independently of each other.
+    ///
+    /// By default all partitions of a write are committed in one transaction, so a failed
+    /// commit leaves none of them visible. Committing each partition on its own gives up this
+    /// atomicity so that a long bulk load keeps the partitions already committed and only the
+    /// failed ones have to be written again, as listed in the `msg` column of the sink output.
+    ///
+    /// # Arguments
+    ///
+    /// * `commit_per_partition` - Whether to commit each partition independently
+    pub fn with_commit_per_partition(self, commit_per_partition: bool) -> Self {
+        self.with_option(
+            OPTION_KEY_COMMIT_PER_PARTITION,
+            commit_per_partition.to_string(),
+        )
+    }
+
+    /// Sets whether the commit of the sink fails if one of its partitions was committed since
+    /// the write started.
+    ///
+    /// The versions of the partitions are read when the write starts and compared with their
+    /// current versions before the commit, so that a write conflicting with a concurrent
+    /// commit fails instead of committing on top of it. The failed write commits nothing and
+    /// can be retried with the same write id, see
+    /// `LakeSoulHashSinkExec::with_commit_version_check`.
+    ///
+    /// # Arguments
+    ///
+    /// * `commit_version_check` - Whether to check the versions of the partitions at commit
+    pub fn with_commit_version_check(self, commit_version_check: bool) -> Self {
+        self.with_option(
+            OPTION_KEY_COMMIT_VERSION_CHECK,
+            commit_version_check.to_string(),
+        )
+    }
+
+    /// Sets whether each input partition of the sink is written and committed by its own
+    /// output partition.
+    ///
+    /// The input of the sink is then hash partitioned on the primary keys instead of being
+    /// funneled through a single partition, and each output partition commits the files of
+    /// its input partition on its own. Not supported with merge on write.
+    ///
+    /// # Arguments
+    ///
+    /// * `partitioned_sink` - Whether to write each input partition independently
+    pub fn with_partitioned_sink(self, partitioned_sink: bool) -> Self {
+        self.with_option(OPTION_KEY_PARTITIONED_SINK, partitioned_sink.to_string())
+    }
+
+    /// Sets whether the written rows are hash partitioned and sorted by the primary keys
+    /// before the sink.
+    ///
+    /// Each input partition of the sink then holds the rows of one hash bucket sorted by the
+    /// keys, so that a single sorted file is written per hash bucket and range partition,
+    /// which makes the merge on read cheaper. Ignored for tables without primary keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `cluster_by_primary_keys` - Whether to cluster the written rows by the primary keys
+    pub fn with_cluster_by_primary_keys(self, cluster_by_primary_keys: bool) -> Self {
+        self.with_option(
+            OPTION_KEY_CLUSTER_BY_PRIMARY_KEYS,
+            cluster_by_primary_keys.to_string(),
+        )
+    }
+
+    /// Sets whether parquet bloom filters of the primary key columns are written.
+    ///
+    /// The point lookups on the primary keys of a table skip the files whose bloom filters
+    /// miss the key, as long as the table writes them. The setting is stored with the table
+    /// when it is created.
+    ///
+    /// # Arguments
+    ///
+    /// * `bloom_filter` - Whether to write the bloom filters
+    pub fn with_primary_key_bloom_filter(self, bloom_filter: bool) -> Self {
+        self.with_option(
+            OPTION_KEY_PRIMARY_KEY_BLOOM_FILTER,
+            bloom_filter.to_string(),
+        )
+    }
+
+    /// Sets whether the range partition columns are written into the data files.
+    ///
+    /// The values of the range partitions are always encoded into the paths of the data files,
+    /// keeping them in the files as well lets readers unaware of the path encoding see them.
+    /// LakeSoul reads them from the paths either way, and writes them into the files merged on
+    /// write or compacted as well. The setting is stored with the table when it is created.
+    ///
+    /// # Arguments
+    ///
+    /// * `keep_partition_columns` - Whether to write the range partition columns
+    pub fn with_keep_partition_columns(self, keep_partition_columns: bool) -> Self {
+        self.with_option(
+            OPTION_KEY_KEEP_PARTITION_COLUMNS,
+            keep_partition_columns.to_string(),
+        )
+    }
+
+    /// Sets the format of the written data files.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The format of the data files
+    pub fn with_data_file_format(self, format: DataFileFormat) -> Self {
+        self.with_option(OPTION_KEY_DATA_FILE_FORMAT, format.extension().to_string())
+    }
+
+    /// Reads the change feed instead of the latest rows.
+    ///
+    /// The rows written by the commits after `from_version` up to `to_version` are emitted
+    /// as they were written, including the rows marked as deleted by the cdc column.
+    ///
+    /// # Arguments
+    ///
+    /// * `from_version` - The exclusive start version of each partition
+    /// * `to_version` - The inclusive end version of each partition
+    pub fn with_change_feed(self, from_version: i32, to_version: i32) -> Self {
+        self.with_option(
+            OPTION_KEY_CHANGE_FEED_FROM_VERSION,
+            from_version.to_string(),
+        )
+        .with_option(OPTION_KEY_CHANGE_FEED_TO_VERSION, to_version.to_string())
+    }
+
+    /// Sets the values of the CDC column marking the deleted rows, which are dropped by the
+    /// scans after the merge.
+    ///
+    /// The CDC sources mark the deletes differently, e.g. `D` or `-D`, all the given markers
+    /// are treated as deletes. The markers must not contain commas. Without markers the rows
+    /// marked `delete` are deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `markers` - The values of the CDC column marking the deleted rows
+    pub fn with_cdc_delete_markers(self, markers: Vec<String>) -> Self {
+        self.with_option(OPTION_KEY_CDC_DELETE_MARKERS, markers.join(","))
+    }
+
+    /// Sets the column ordering the versions of a primary key when merging.
+    ///
+    /// Among the rows of a primary key, the row with the highest value of the column wins,
+    /// and the rows of equal values are merged in the order of the files. Rows with a null
+    /// value, or read from files without the column, lose against any value.
+    ///
+    /// With merge operators other than `UseLast`, e.g. with the partial update
+    /// [`MergeStrategy`], the versions of different files are merged in the order of the
+    /// highest value of each file, while the rows of a key within one file are merged in
+    /// the order of the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `sequence_column` - The name of the sequence column
+    pub fn with_sequence_column(self, sequence_column: impl Into<String>) -> Self {
+        self.with_option(OPTION_KEY_SEQUENCE_COLUMN, sequence_column.into())
+    }
+
+    /// Sets how the versions of a primary key are merged for the columns without a merge
+    /// operator.
+    ///
+    /// With [`MergeStrategy::PartialUpdate`] the null values of a version do not
+    /// overwrite the values of the previous versions, like with the `UseLastNotNull`
+    /// merge operator, so that partial rows can be upserted with the columns they do not
+    /// update left null. The cdc column and the sequence column are still taken from the
+    /// last version.
+    ///
+    /// # Arguments
+    ///
+    /// * `strategy` - The merge strategy
+    pub fn with_merge_strategy(self, strategy: MergeStrategy) -> Self {
+        self.with_option(OPTION_KEY_MERGE_STRATEGY, strategy.name().to_string())
+    }
+
+    /// Sets whether the null values of the primary keys are ordered before the other values,
+    /// the default, or after them. The order applies to the sort of the written rows and to
+    /// the merge of the files on read, so the files of a table must be merged with the null
+    /// ordering they were written with.
+    ///
+    /// Keys are equal when their values are equal or both null, so the rows of a key with
+    /// null components are deduplicated like the rows of any other key, the last one
+    /// winning. In particular a CDC delete row whose keys are all null deletes the rows
+    /// written before with all null keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `nulls_first` - Whether the null values are ordered first
+    pub fn with_nulls_first(self, nulls_first: bool) -> Self {
+        self.with_option(OPTION_KEY_NULLS_FIRST, nulls_first.to_string())
+    }
+
+    /// Sets the size in bytes above which the sorted batches of the writers are split, see
+    /// [`ProjectionStream::with_max_batch_bytes`](crate::projection::ProjectionStream::with_max_batch_bytes).
+    ///
+    /// The writers sorting the rows with aux sort columns drop these columns from the
+    /// sorted batches, the batches larger than the size are split before, so that the
+    /// batches written into the files stay about this size.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_batch_bytes` - The size in bytes above which a batch is split, positive
+    pub fn with_max_batch_bytes(self, max_batch_bytes: usize) -> Self {
+        self.with_option(OPTION_KEY_MAX_BATCH_BYTES, max_batch_bytes.to_string())
+    }
+
+    /// Sets the target number of rows of the batches output by the merge on read, the
+    /// batch size of the session by default.
+    ///
+    /// The rows of a primary key table are merged into batches of this size, and the
+    /// batches left small by the filters applied after the merge are coalesced in their
+    /// order until they reach it, so the output stays sorted by the primary keys. Unlike
+    /// [`with_coalesce_scan_batches`](Self::with_coalesce_scan_batches), which coalesces
+    /// the batches of the DataFusion table scans only, it applies to every reader of the
+    /// merged files, e.g. the readers of the native io library.
+    ///
+    /// # Arguments
+    ///
+    /// * `merge_batch_size` - The target number of rows of the merged batches, positive
+    pub fn with_merge_batch_size(self, merge_batch_size: usize) -> Self {
+        self.with_option(OPTION_KEY_MERGE_BATCH_SIZE, merge_batch_size.to_string())
+    }
+
+    /// Validates that the output schema of the scan plans has exactly the names and types
+    /// of the projected columns, failing the planning with the differences otherwise.
+    ///
+    /// The validation is always enabled in debug builds.
+    ///
+    /// # Arguments
+    ///
+    /// * `validate` - Whether the output schema of the scan plans is validated
+    pub fn with_validate_scan_schema(self, validate: bool) -> Self {
+        self.with_option(OPTION_KEY_VALIDATE_SCAN_SCHEMA, validate.to_string())
+    }
+
+    /// Encrypts the columns in the written parquet files with the keys of the key
+    /// management service, see [`crate::encryption`].
+    ///
+    /// The files are decrypted on read with the keys of the same service, which must be
+    /// registered with [`crate::encryption::register_key_management_service`].
+    ///
+    /// # Arguments
+    ///
+    /// * `kms` - The name of the registered key management service
+    /// * `columns` - The names of the encrypted columns
+    pub fn with_encrypted_columns(
+        self,
+        kms: impl Into<String>,
+        columns: Vec<String>,
+    ) -> Self {
+        self.with_option(OPTION_KEY_ENCRYPTION_KMS, kms.into())
+            .with_option(OPTION_KEY_ENCRYPTED_COLUMNS, columns.join(","))
+    }
+
+    /// Sets the id of the footer key of the encryption, the key of a column has the id
+    /// `<key id>.<column>`. Changing the key id rotates the keys of the written files.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_id` - The id of the footer key
+    pub fn with_encryption_key_id(self, key_id: impl Into<String>) -> Self {
+        self.with_option(OPTION_KEY_ENCRYPTION_KEY_ID, key_id.into())
+    }
+
     /// Sets the random number generator seed for Local Sensitive Hash
     ///
     /// # Arguments
@@ -952,6 +2257,13 @@ fn register_object_store(
             {
                 Ok(format!("file://{}", path))
             }
+            // e.g. the scheme of a supplied object store
+            _ if runtime
+                .object_store(ObjectStoreUrl::parse(&url[..url::Position::BeforePath])?)
+                .is_ok() =>
+            {
+                Ok(path.to_owned())
+            }
             _ => Err(DataFusionError::ObjectStore(
                 object_store::Error::NotSupported {
                     source: "FileSystem is not supported".into(),
@@ -1029,6 +2341,13 @@ pub fn create_session_context_with_planner(
     }
     let runtime = runtime_conf.build()?;
 
+    // the supplied object stores are registered first, so that none is created for their
+    // paths
+    for (url, store) in &config.object_stores {
+        info!("NativeIO register supplied object store {}", url.as_str());
+        runtime.register_object_store(url.as_ref(), store.clone());
+    }
+
     // firstly, parse default fs if exist
     let default_fs = config
         .object_store_options
@@ -1090,7 +2409,24 @@ pub fn create_session_context_with_planner(
 
 #[cfg(test)]
 mod tests {
-    use crate::lakesoul_io_config::{LakeSoulIOConfigBuilder, create_session_context};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::lakesoul_io_config::{
+        LakeSoulIOConfigBuilder, OPTION_KEY_CHANGE_FEED_FROM_VERSION,
+        OPTION_KEY_CHANGE_FEED_TO_VERSION, OPTION_KEY_MAX_BUFFERED_BYTES,
+        OPTION_KEY_MAX_CONCURRENT_WRITERS, OPTION_KEY_MAX_FILE_ROWS,
+        OPTION_KEY_MERGE_BATCH_SIZE, OPTION_KEY_META_FETCH_CONCURRENCY,
+        OPTION_KEY_PARQUET_COMPRESSION, OPTION_KEY_SNAPSHOT_TIMESTAMP,
+        OPTION_KEY_SNAPSHOT_VERSION, OPTION_KEY_STATS_CACHE_SIZE,
+        OPTION_KEY_UPLOAD_COMPLETE_RETRIES, OPTION_KEY_UPLOAD_RETRY_BASE_DELAY_MS,
+        create_session_context,
+    };
+    use datafusion::execution::object_store::ObjectStoreUrl;
+    use object_store::memory::InMemory;
+    use object_store::path::Path;
+    use object_store::{ObjectStore, PutPayload};
+    use parquet::basic::{Compression, ZstdLevel};
 
     #[test]
     fn test_path_normalize() {
@@ -1116,4 +2452,185 @@ mod tests {
         assert_eq!(conf.prefetch_size, 1);
         assert_eq!(conf.parquet_filter_pushdown, false);
     }
+
+    #[test]
+    fn test_parquet_compression() {
+        let conf = LakeSoulIOConfigBuilder::new().build();
+        assert_eq!(
+            conf.parquet_compression().unwrap(),
+            Compression::ZSTD(ZstdLevel::default())
+        );
+        for compression in [
+            Compression::SNAPPY,
+            Compression::ZSTD(ZstdLevel::try_new(9).unwrap()),
+            Compression::LZ4_RAW,
+        ] {
+            let conf = LakeSoulIOConfigBuilder::new()
+                .with_parquet_compression(compression)
+                .build();
+            assert_eq!(conf.parquet_compression().unwrap(), compression);
+        }
+        let conf = LakeSoulIOConfigBuilder::new()
+            .with_option(OPTION_KEY_PARQUET_COMPRESSION, "zstd(100)")
+            .build();
+        assert!(conf.parquet_compression().is_err());
+    }
+
+    #[test]
+    fn test_merge_batch_size() {
+        let conf = LakeSoulIOConfigBuilder::new().build();
+        assert_eq!(conf.merge_batch_size().unwrap(), None);
+        let conf = LakeSoulIOConfigBuilder::new()
+            .with_merge_batch_size(4)
+            .build();
+        assert_eq!(conf.merge_batch_size().unwrap(), Some(4));
+        for size in ["0", "-1", "many"] {
+            let conf = LakeSoulIOConfigBuilder::new()
+                .with_option(OPTION_KEY_MERGE_BATCH_SIZE, size)
+                .build();
+            assert!(conf.merge_batch_size().is_err(), "{size}");
+        }
+    }
+
+    #[test]
+    fn test_meta_fetch_concurrency() {
+        let conf = LakeSoulIOConfigBuilder::new().build();
+        assert_eq!(conf.meta_fetch_concurrency().unwrap(), 32);
+        assert_eq!(conf.max_buffered_bytes_option().unwrap(), None);
+        let conf = LakeSoulIOConfigBuilder::new()
+            .with_meta_fetch_concurrency(4)
+            .with_option(OPTION_KEY_MAX_BUFFERED_BYTES, "1024")
+            .build();
+        assert_eq!(conf.meta_fetch_concurrency().unwrap(), 4);
+        assert_eq!(conf.max_buffered_bytes_option().unwrap(), Some(1024));
+        for value in ["0", "-1", "many"] {
+            let conf = LakeSoulIOConfigBuilder::new()
+                .with_option(OPTION_KEY_META_FETCH_CONCURRENCY, value)
+                .build();
+            assert!(conf.meta_fetch_concurrency().is_err(), "{value}");
+        }
+        let conf = LakeSoulIOConfigBuilder::new()
+            .with_option(OPTION_KEY_MAX_BUFFERED_BYTES, "many")
+            .build();
+        assert!(conf.max_buffered_bytes_option().is_err());
+        for value in ["0", "many"] {
+            let conf = LakeSoulIOConfigBuilder::new()
+                .with_option(OPTION_KEY_MAX_FILE_ROWS, value)
+                .build();
+            assert!(conf.max_file_rows_option().is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn test_max_concurrent_writers() {
+        let conf = LakeSoulIOConfigBuilder::new().build();
+        assert_eq!(conf.max_concurrent_writers().unwrap(), num_cpus::get());
+        let conf = LakeSoulIOConfigBuilder::new()
+            .with_max_concurrent_writers(2)
+            .build();
+        assert_eq!(conf.max_concurrent_writers().unwrap(), 2);
+        for value in ["0", "-1", "many"] {
+            let conf = LakeSoulIOConfigBuilder::new()
+                .with_option(OPTION_KEY_MAX_CONCURRENT_WRITERS, value)
+                .build();
+            assert!(conf.max_concurrent_writers().is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn test_stats_cache_size() {
+        let conf = LakeSoulIOConfigBuilder::new().build();
+        assert_eq!(conf.stats_cache_size().unwrap(), 1024);
+        let conf = LakeSoulIOConfigBuilder::new()
+            .with_stats_cache_size(0)
+            .build();
+        assert_eq!(conf.stats_cache_size().unwrap(), 0);
+        let conf = LakeSoulIOConfigBuilder::new()
+            .with_option(OPTION_KEY_STATS_CACHE_SIZE, "-1")
+            .build();
+        assert!(conf.stats_cache_size().is_err());
+    }
+
+    #[test]
+    fn test_upload_retries() {
+        let conf = LakeSoulIOConfigBuilder::new().build();
+        assert_eq!(conf.upload_complete_retries().unwrap(), 3);
+        assert_eq!(
+            conf.upload_retry_base_delay().unwrap(),
+            Duration::from_millis(100)
+        );
+        let conf = LakeSoulIOConfigBuilder::new()
+            .with_option(OPTION_KEY_UPLOAD_COMPLETE_RETRIES, "several")
+            .with_option(OPTION_KEY_UPLOAD_RETRY_BASE_DELAY_MS, "1s")
+            .build();
+        assert!(conf.upload_complete_retries().is_err());
+        assert!(conf.upload_retry_base_delay().is_err());
+    }
+
+    #[test]
+    fn test_change_feed() {
+        let conf = LakeSoulIOConfigBuilder::new()
+            .with_option(OPTION_KEY_CHANGE_FEED_FROM_VERSION, "1")
+            .build();
+        assert_eq!(conf.change_feed().unwrap(), None);
+        let conf = LakeSoulIOConfigBuilder::new()
+            .with_change_feed(1, 3)
+            .build();
+        assert_eq!(conf.change_feed().unwrap(), Some((1, 3)));
+        for (from, to) in [("1", "latest"), ("first", "3"), ("3", "1")] {
+            let conf = LakeSoulIOConfigBuilder::new()
+                .with_option(OPTION_KEY_CHANGE_FEED_FROM_VERSION, from)
+                .with_option(OPTION_KEY_CHANGE_FEED_TO_VERSION, to)
+                .build();
+            assert!(conf.change_feed().is_err(), "{from} {to}");
+        }
+    }
+
+    #[test]
+    fn test_snapshot_options() {
+        let conf = LakeSoulIOConfigBuilder::new().build();
+        assert_eq!(conf.snapshot_version().unwrap(), None);
+        assert_eq!(conf.snapshot_timestamp().unwrap(), None);
+        let conf = LakeSoulIOConfigBuilder::new()
+            .with_option(OPTION_KEY_SNAPSHOT_VERSION, "2")
+            .with_option(OPTION_KEY_SNAPSHOT_TIMESTAMP, "1700000000000")
+            .build();
+        assert_eq!(conf.snapshot_version().unwrap(), Some(2));
+        assert_eq!(conf.snapshot_timestamp().unwrap(), Some(1700000000000));
+        let conf = LakeSoulIOConfigBuilder::new()
+            .with_option(OPTION_KEY_SNAPSHOT_VERSION, "latest")
+            .with_option(OPTION_KEY_SNAPSHOT_TIMESTAMP, "2024-01-01")
+            .build();
+        assert!(conf.snapshot_version().is_err());
+        assert!(conf.snapshot_timestamp().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_supplied_object_store() {
+        let store = Arc::new(InMemory::new());
+        store
+            .put(
+                &Path::from("warehouse/data"),
+                PutPayload::from_static(b"tenant"),
+            )
+            .await
+            .unwrap();
+        // no credentials are needed for the paths of the supplied store
+        let url = ObjectStoreUrl::parse("s3://tenant-bucket").unwrap();
+        let mut conf = LakeSoulIOConfigBuilder::new()
+            .with_prefix("s3://tenant-bucket/warehouse".to_string())
+            .with_files(vec!["s3://tenant-bucket/warehouse/data"])
+            .with_object_store(url.clone(), store)
+            .build();
+        let sess_ctx = create_session_context(&mut conf).unwrap();
+        let registered = sess_ctx.runtime_env().object_store(&url).unwrap();
+        let bytes = registered
+            .get(&Path::from("warehouse/data"))
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(bytes.as_ref(), b"tenant");
+    }
 }
@@ -395,7 +395,9 @@ impl SyncSendableMutableLakeSoulReader {
 mod tests {
     use super::*;
     use arrow::array::as_primitive_array;
-    use arrow_array::{ArrayRef, Int64Array, StringArray};
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Int64Type;
+    use arrow_array::{ArrayRef, Int64Array, StringArray, StructArray};
     use rand::distr::SampleString;
     use std::mem::ManuallyDrop;
     use std::ops::Not;
@@ -406,7 +408,10 @@ mod tests {
     use tokio::runtime::Builder;
 
     use arrow::datatypes::{DataType, Field, Schema, TimestampSecondType};
-    use arrow::util::pretty::print_batches;
+    use arrow::util::pretty::{pretty_format_batches, print_batches};
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    use crate::constant::LAKESOUL_PRIMARY_KEYS_KEY;
 
     use rand::Rng;
 
@@ -911,6 +916,101 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_read_file_with_colliding_column_names() -> Result<()> {
+        let batch = RecordBatch::try_from_iter([
+            ("Id", Arc::new(Int64Array::from(vec![1, 2])) as ArrayRef),
+            ("id", Arc::new(Int64Array::from(vec![3, 4])) as ArrayRef),
+        ])?;
+        let path = tempfile::tempdir()?.into_path().join("colliding.parquet");
+        let mut writer = parquet::arrow::ArrowWriter::try_new(
+            std::fs::File::create(&path)?,
+            batch.schema(),
+            None,
+        )?;
+        writer.write(&batch)?;
+        writer.close()?;
+
+        let reader_conf = LakeSoulIOConfigBuilder::new()
+            .with_files(vec![path.into_os_string().into_string().unwrap()])
+            .with_thread_num(1)
+            .with_batch_size(256)
+            .set_inferring_schema(true)
+            .build();
+        let mut reader = LakeSoulReader::new(reader_conf)?;
+        let err = reader.start().await.unwrap_err().to_string();
+        assert!(
+            err.contains("Columns 'Id' and 'id' collide"),
+            "unexpected error: {}",
+            err
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_nested_fields_of_struct_columns() -> Result<()> {
+        let int_field = |name: &str| Arc::new(Field::new(name, DataType::Int64, false));
+        let struct_array = |names: [&str; 2], values: [[i64; 2]; 2]| {
+            Arc::new(StructArray::from(vec![
+                (
+                    int_field(names[0]),
+                    Arc::new(Int64Array::from(values[0].to_vec())) as ArrayRef,
+                ),
+                (
+                    int_field(names[1]),
+                    Arc::new(Int64Array::from(values[1].to_vec())) as ArrayRef,
+                ),
+            ])) as ArrayRef
+        };
+        // the primary keys differ only in the nested field `y`, which is not selected
+        let batch = RecordBatch::try_from_iter([
+            ("k", struct_array(["x", "y"], [[1, 1], [1, 2]])),
+            ("v", struct_array(["a", "b"], [[10, 30], [20, 40]])),
+        ])?;
+        let path = tempfile::tempdir()?.into_path().join("nested.parquet");
+        let mut writer = parquet::arrow::ArrowWriter::try_new(
+            std::fs::File::create(&path)?,
+            batch.schema(),
+            None,
+        )?;
+        writer.write(&batch)?;
+        writer.close()?;
+
+        let pruned_struct = |name: &str, child: &str| {
+            Field::new(name, DataType::Struct(vec![int_field(child)].into()), false)
+        };
+        let target_schema = Arc::new(Schema::new(vec![
+            pruned_struct("k", "x"),
+            pruned_struct("v", "a"),
+        ]));
+        let reader_conf = LakeSoulIOConfigBuilder::new()
+            .with_files(vec![path.into_os_string().into_string().unwrap()])
+            .with_thread_num(1)
+            .with_batch_size(256)
+            .with_schema(target_schema)
+            .with_primary_keys(vec!["k".to_string()])
+            .build();
+        let mut reader = LakeSoulReader::new(reader_conf)?;
+        reader.start().await?;
+        let mut values = vec![];
+        while let Some(rb) = reader.next_rb().await {
+            let rb = rb?;
+            for name in ["k", "v"] {
+                let column = rb.column_by_name(name).unwrap().as_struct();
+                assert_eq!(column.num_columns(), 1);
+            }
+            let v = rb.column_by_name("v").unwrap().as_struct();
+            values.extend(
+                as_primitive_array::<Int64Type>(v.column(0))
+                    .values()
+                    .iter()
+                    .copied(),
+            );
+        }
+        assert_eq!(values, vec![10, 30]);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_read_file_with_partition_column() -> Result<()> {
         let schema = Arc::new(Schema::new(vec![
@@ -1223,4 +1323,87 @@ mod tests {
         println!("time cost: {:?}ms", start.elapsed().as_millis()); // ms
         Ok(())
     }
+
+    #[test]
+    fn test_read_files_written_under_other_primary_keys() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let write = |name: &str, batch: RecordBatch, primary_keys: &[&str]| {
+            let path = temp_dir
+                .path()
+                .join(name)
+                .into_os_string()
+                .into_string()
+                .unwrap();
+            let writer_conf = LakeSoulIOConfigBuilder::new()
+                .with_files(vec![path.clone()])
+                .with_schema(batch.schema())
+                .with_primary_keys(primary_keys.iter().map(|pk| pk.to_string()).collect())
+                .build();
+            let mut writer = SyncSendableMutableLakeSoulWriter::try_new(
+                writer_conf,
+                Builder::new_multi_thread().enable_all().build().unwrap(),
+            )?;
+            writer.write_batch(batch)?;
+            writer.flush_and_close()?;
+            Ok::<_, DataFusionError>(path)
+        };
+        // the first file was written when the table was keyed by the id only
+        let region = Arc::new(StringArray::from(vec!["eu", "ap", "us"])) as ArrayRef;
+        let id = Arc::new(Int64Array::from(vec![1, 2, 3])) as ArrayRef;
+        let v = Arc::new(StringArray::from(vec!["a", "b", "c"])) as ArrayRef;
+        let old = RecordBatch::try_from_iter([("region", region), ("id", id), ("v", v)])?;
+        let region = Arc::new(StringArray::from(vec!["eu", "ap"])) as ArrayRef;
+        let id = Arc::new(Int64Array::from(vec![1, 2])) as ArrayRef;
+        let v = Arc::new(StringArray::from(vec!["A", "B"])) as ArrayRef;
+        let new = RecordBatch::try_from_iter([("region", region), ("id", id), ("v", v)])?;
+        let schema = new.schema();
+        let old_path = write("part-0000.parquet", old, &["id"])?;
+        let new_path = write("part-0001.parquet", new, &["region", "id"])?;
+
+        // the primary keys are appended to the key-value metadata of the footer
+        let reader = SerializedFileReader::new(std::fs::File::open(&new_path)?)?;
+        let keys = reader
+            .metadata()
+            .file_metadata()
+            .key_value_metadata()
+            .map(|key_values| {
+                key_values
+                    .iter()
+                    .map(|key_value| key_value.key.as_str())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        assert!(keys.contains(&"ARROW:schema"));
+        assert!(keys.contains(&LAKESOUL_PRIMARY_KEYS_KEY));
+
+        let reader_conf = LakeSoulIOConfigBuilder::new()
+            .with_files(vec![old_path, new_path])
+            .with_schema(schema)
+            .with_primary_keys(vec!["region".to_string(), "id".to_string()])
+            .build();
+        let runtime = Builder::new_multi_thread().enable_all().build().unwrap();
+        let mut reader = SyncSendableMutableLakeSoulReader::new(
+            LakeSoulReader::new(reader_conf)?,
+            runtime,
+        );
+        reader.start_blocked()?;
+        let mut batches = vec![];
+        while let Some(batch) = reader.next_rb_blocked() {
+            batches.push(batch?);
+        }
+        assert_eq!(
+            pretty_format_batches(&batches)?.to_string(),
+            [
+                "+--------+----+---+",
+                "| region | id | v |",
+                "+--------+----+---+",
+                "| ap     | 2  | B |",
+                "| eu     | 1  | A |",
+                "| us     | 3  | c |",
+                "+--------+----+---+",
+            ]
+            .join("\n")
+        );
+        Ok(())
+    }
 }
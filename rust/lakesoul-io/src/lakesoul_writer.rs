@@ -37,11 +37,13 @@ use tokio::runtime::Runtime;
 use tokio::sync::Mutex;
 
 use crate::async_writer::{
-    AsyncBatchWriter, MultiPartAsyncWriter, PartitioningAsyncWriter, SortAsyncWriter,
-    WriterFlushResult,
+    ArrowIpcAsyncWriter, AsyncBatchWriter, GeneratingAsyncWriter, MultiPartAsyncWriter,
+    PartitioningAsyncWriter, SortAsyncWriter, WriterFlushResult,
 };
 use crate::helpers::{get_batch_memory_size, get_file_exist_col};
-use crate::lakesoul_io_config::{IOSchema, LakeSoulIOConfig};
+use crate::lakesoul_io_config::{
+    DataFileFormat, IOSchema, LakeSoulIOConfig, create_session_context,
+};
 use crate::local_sensitive_hash::LSH;
 use crate::transform::uniform_schema;
 
@@ -76,34 +78,57 @@ pub async fn create_writer(
     let mut writer_config = config.clone();
     let writer: Box<dyn AsyncBatchWriter + Send> = if config.use_dynamic_partition {
         Box::new(PartitioningAsyncWriter::try_new(writer_config)?)
-    } else if !writer_config.primary_keys.is_empty() && !writer_config.keep_ordering() {
-        // sort primary key table
-        writer_config.target_schema = IOSchema(uniform_schema(writer_schema));
-        if writer_config.files.is_empty() && !writer_config.prefix().is_empty() {
-            writer_config.files = vec![format!(
-                "{}/part-{}_{:0>4}.parquet",
-                writer_config.prefix(),
-                rand::distr::Alphanumeric.sample_string(&mut rand::rng(), 16),
-                writer_config.hash_bucket_id()
-            )];
-        }
-        let writer = MultiPartAsyncWriter::try_new(writer_config).await?;
-        Box::new(SortAsyncWriter::try_new(writer, config)?)
     } else {
-        // else multipart
+        // else multipart, writing parquet or arrow ipc files
+        let format = writer_config.data_file_format()?;
         writer_config.target_schema = IOSchema(uniform_schema(writer_schema));
         if writer_config.files.is_empty() && !writer_config.prefix().is_empty() {
             writer_config.files = vec![format!(
-                "{}/part-{}_{:0>4}.parquet",
+                "{}/part-{}_{:0>4}.{}",
                 writer_config.prefix(),
                 rand::distr::Alphanumeric.sample_string(&mut rand::rng(), 16),
-                writer_config.hash_bucket_id()
+                writer_config.hash_bucket_id(),
+                format.extension()
             )];
         }
-        let writer = MultiPartAsyncWriter::try_new(writer_config).await?;
-        Box::new(writer)
+        let task_context = create_session_context(&mut writer_config)?.task_ctx();
+        let writer: Box<dyn AsyncBatchWriter + Send> = match format {
+            DataFileFormat::Parquet => Box::new(
+                MultiPartAsyncWriter::try_new_with_context(
+                    &mut writer_config,
+                    task_context.clone(),
+                )
+                .await?,
+            ),
+            DataFileFormat::ArrowIpc => Box::new(
+                ArrowIpcAsyncWriter::try_new_with_context(
+                    &mut writer_config,
+                    task_context.clone(),
+                )
+                .await?,
+            ),
+        };
+        if !writer_config.primary_keys.is_empty() && !writer_config.keep_ordering() {
+            // sort primary key table
+            Box::new(SortAsyncWriter::try_new_with_writer(
+                writer,
+                task_context,
+                config.clone(),
+            )?)
+        } else {
+            writer
+        }
     };
-    Ok(writer)
+    // the generated columns are computed before the batches are partitioned or sorted
+    if config.generated_columns().is_empty() {
+        Ok(writer)
+    } else {
+        Ok(Box::new(GeneratingAsyncWriter::new(
+            writer,
+            config.generated_columns().clone(),
+            config.target_schema(),
+        )))
+    }
 }
 
 // inner is sort writer
@@ -404,14 +429,19 @@ impl SyncSendableMutableLakeSoulWriter {
 #[cfg(test)]
 mod tests {
     use crate::{
-        lakesoul_io_config::{LakeSoulIOConfigBuilder, OPTION_KEY_MEM_LIMIT},
+        lakesoul_io_config::{
+            DataFileFormat, LakeSoulIOConfigBuilder, OPTION_KEY_MEM_LIMIT,
+            OPTION_KEY_PRIMARY_KEY_BLOOM_FILTER, OPTION_KEY_ROW_GROUP_ALIGN_COLUMNS,
+        },
         lakesoul_reader::LakeSoulReader,
         lakesoul_writer::{
             AsyncBatchWriter, MultiPartAsyncWriter, SyncSendableMutableLakeSoulWriter,
+            create_writer,
         },
     };
     use rand::distr::SampleString;
 
+    use arrow::ipc::reader::StreamReader;
     use arrow::{
         array::{ArrayRef, Int64Array},
         record_batch::RecordBatch,
@@ -420,6 +450,10 @@ mod tests {
     use arrow_schema::{DataType, Field, Schema, TimeUnit};
     use datafusion::error::Result;
     use parquet::arrow::arrow_reader::ParquetRecordBatchReader;
+    use parquet::file::properties::ReaderProperties;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::file::serialized_reader::ReadOptionsBuilder;
+    use parquet::file::statistics::Statistics;
     use rand::Rng;
     use std::{fs::File, sync::Arc};
     use tokio::{runtime::Builder, time::Instant};
@@ -510,8 +544,140 @@ mod tests {
         })
     }
 
+    #[tokio::test]
+    async fn test_parquet_async_write_with_row_group_align() -> Result<()> {
+        let key = Arc::new(Int64Array::from_iter_values([1, 1, 1, 2, 2, 3])) as ArrayRef;
+        let value = Arc::new(Int64Array::from_iter_values(0..6)) as ArrayRef;
+        let first = RecordBatch::try_from_iter([("key", key), ("value", value)])?;
+        let key = Arc::new(Int64Array::from_iter_values([3, 3, 4, 5])) as ArrayRef;
+        let value = Arc::new(Int64Array::from_iter_values(6..10)) as ArrayRef;
+        let second = RecordBatch::try_from_iter([("key", key), ("value", value)])?;
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir
+            .into_path()
+            .join("test.parquet")
+            .into_os_string()
+            .into_string()
+            .unwrap();
+        let writer_conf = LakeSoulIOConfigBuilder::new()
+            .with_files(vec![path.clone()])
+            .with_batch_size(256)
+            .with_max_row_group_size(2)
+            .with_schema(first.schema())
+            .with_option(OPTION_KEY_ROW_GROUP_ALIGN_COLUMNS, "key")
+            .build();
+        let mut async_writer = MultiPartAsyncWriter::try_new(writer_conf).await?;
+        async_writer.write_record_batch(first).await?;
+        async_writer.write_record_batch(second).await?;
+        Box::new(async_writer).flush_and_close().await?;
+
+        let reader = SerializedFileReader::new(File::open(path)?)?;
+        let ranges = reader
+            .metadata()
+            .row_groups()
+            .iter()
+            .map(|row_group| match row_group.column(0).statistics() {
+                Some(Statistics::Int64(stats)) => {
+                    (*stats.min_opt().unwrap(), *stats.max_opt().unwrap())
+                }
+                other => panic!("unexpected statistics {:?}", other),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, vec![(1, 1), (2, 2), (3, 3), (4, 5)]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_parquet_async_write_with_primary_key_bloom_filter() -> Result<()> {
+        let key = Arc::new(StringArray::from(vec!["a", "b", "c"])) as ArrayRef;
+        let value = Arc::new(Int64Array::from_iter_values(0..3)) as ArrayRef;
+        let batch = RecordBatch::try_from_iter([("key", key), ("value", value)])?;
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir
+            .into_path()
+            .join("test.parquet")
+            .into_os_string()
+            .into_string()
+            .unwrap();
+        let writer_conf = LakeSoulIOConfigBuilder::new()
+            .with_files(vec![path.clone()])
+            .with_batch_size(256)
+            .with_schema(batch.schema())
+            .with_primary_keys(vec!["key".to_string()])
+            .with_option(OPTION_KEY_PRIMARY_KEY_BLOOM_FILTER, "true")
+            .build();
+        let mut async_writer = MultiPartAsyncWriter::try_new(writer_conf).await?;
+        async_writer.write_record_batch(batch).await?;
+        Box::new(async_writer).flush_and_close().await?;
+
+        let options = ReadOptionsBuilder::new()
+            .with_reader_properties(
+                ReaderProperties::builder()
+                    .set_read_bloom_filter(true)
+                    .build(),
+            )
+            .build();
+        let reader = SerializedFileReader::new_with_options(File::open(path)?, options)?;
+        let row_group = reader.get_row_group(0)?;
+        let bloom_filter = row_group.get_column_bloom_filter(0).unwrap();
+        assert!(bloom_filter.check("b"));
+        assert!(!bloom_filter.check("z"));
+        // non primary key columns are written without bloom filter
+        assert!(row_group.get_column_bloom_filter(1).is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_arrow_ipc_write_of_primary_key_table() -> Result<()> {
+        let key = Arc::new(Int64Array::from(vec![3, 1, 2])) as ArrayRef;
+        let value = Arc::new(StringArray::from(vec!["c", "a", "b"])) as ArrayRef;
+        let batch = RecordBatch::try_from_iter([("key", key), ("value", value)])?;
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir
+            .into_path()
+            .join("test.arrow")
+            .into_os_string()
+            .into_string()
+            .unwrap();
+        let writer_conf = LakeSoulIOConfigBuilder::new()
+            .with_files(vec![path.clone()])
+            .with_schema(batch.schema())
+            .with_primary_keys(vec!["key".to_string()])
+            .with_data_file_format(DataFileFormat::ArrowIpc)
+            .build();
+        let mut writer = create_writer(writer_conf).await?;
+        writer.write_record_batch(batch).await?;
+        writer.flush_and_close().await?;
+
+        // the primary key table is written in the configured format, sorted on its keys
+        let reader = StreamReader::try_new(File::open(path)?, None)?;
+        let batches = reader.collect::<std::result::Result<Vec<_>, _>>()?;
+        let keys = batches
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .column(0)
+                    .as_any()
+                    .downcast_ref::<Int64Array>()
+                    .unwrap()
+                    .values()
+                    .to_vec()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![1, 2, 3]);
+        Ok(())
+    }
+
     #[test]
     fn test_parquet_async_write_with_aux_sort() -> Result<()> {
+        // the sorted batches are written whole or split into single rows
+        for max_batch_bytes in [None, Some(1)] {
+            write_with_aux_sort(max_batch_bytes)?;
+        }
+        Ok(())
+    }
+
+    fn write_with_aux_sort(max_batch_bytes: Option<usize>) -> Result<()> {
         let runtime = Builder::new_multi_thread().enable_all().build().unwrap();
         let col = Arc::new(Int64Array::from_iter_values([3, 2, 3])) as ArrayRef;
         let col1 = Arc::new(Int64Array::from_iter_values([5, 3, 2])) as ArrayRef;
@@ -532,8 +698,12 @@ mod tests {
             .with_max_row_group_size(2)
             .with_schema(to_write.schema())
             .with_primary_keys(vec!["col".to_string()])
-            .with_aux_sort_column("col2".to_string())
-            .build();
+            .with_aux_sort_column("col2".to_string());
+        let writer_conf = match max_batch_bytes {
+            Some(max_batch_bytes) => writer_conf.with_max_batch_bytes(max_batch_bytes),
+            None => writer_conf,
+        }
+        .build();
 
         let mut writer =
             SyncSendableMutableLakeSoulWriter::try_new(writer_conf, runtime)?;
@@ -564,6 +734,48 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_parquet_async_write_with_nulls_last() -> Result<()> {
+        let runtime = Builder::new_multi_thread().enable_all().build().unwrap();
+        let k1 = Arc::new(Int64Array::from(vec![None, Some(1), Some(1)])) as ArrayRef;
+        let k2 = Arc::new(StringArray::from(vec![None, Some("x"), None])) as ArrayRef;
+        let value = Arc::new(Int64Array::from_iter_values([0, 1, 2])) as ArrayRef;
+        let to_write =
+            RecordBatch::try_from_iter([("k1", k1), ("k2", k2), ("value", value)])?;
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir
+            .into_path()
+            .join("test.parquet")
+            .into_os_string()
+            .into_string()
+            .unwrap();
+        let writer_conf = LakeSoulIOConfigBuilder::new()
+            .with_files(vec![path.clone()])
+            .with_thread_num(2)
+            .with_batch_size(256)
+            .with_schema(to_write.schema())
+            .with_primary_keys(vec!["k1".to_string(), "k2".to_string()])
+            .with_nulls_first(false)
+            .build();
+
+        let mut writer =
+            SyncSendableMutableLakeSoulWriter::try_new(writer_conf, runtime)?;
+        writer.write_batch(to_write)?;
+        writer.flush_and_close()?;
+
+        // the rows with null keys are sorted after the other rows of their prefix
+        let file = File::open(path)?;
+        let mut record_batch_reader =
+            ParquetRecordBatchReader::try_new(file, 1024).unwrap();
+        let actual_batch = record_batch_reader
+            .next()
+            .expect("No batch found")
+            .expect("Unable to get batch");
+        let expected = Int64Array::from_iter_values([1, 2, 0]);
+        assert_eq!(actual_batch.column(2).to_data(), expected.to_data());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_s3_read_write() -> Result<()> {
         let common_conf_builder = LakeSoulIOConfigBuilder::new()
@@ -52,6 +52,7 @@
 //! - `lakesoul_writer` - Core writing functionality
 //! - `lakesoul_io_config` - Configuration types
 //! - `datasource` - Data source implementations
+//! - `encryption` - Parquet modular encryption of sensitive columns
 //! - `sorted_merge` - Sorted merge operations
 //! - `repartition` - Data repartitioning utilities
 //! - `filter` - Filter pushdown support
@@ -64,6 +65,8 @@ extern crate tracing;
 
 pub mod async_writer;
 pub mod datasource;
+pub mod encryption;
+pub mod file_name;
 pub mod filter;
 pub mod hash_utils;
 pub mod helpers;
@@ -72,7 +75,8 @@ pub mod lakesoul_io_config;
 pub mod lakesoul_reader;
 pub mod lakesoul_writer;
 pub mod local_sensitive_hash;
-mod projection;
+pub mod partition_path;
+pub mod projection;
 pub mod repartition;
 pub mod sorted_merge;
 
@@ -0,0 +1,118 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! The encodings of the range partition values into the sub paths of the data files.
+//!
+//! The partition descriptors stored in the metadata keep their `key=value` format, the
+//! encoding only decides the directory layout of the written data files. The paths are
+//! never decoded, the partition values of the files are read from the metadata.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use datafusion_common::{DataFusionError, Result, ScalarValue};
+
+use crate::helpers::{columnar_values_to_sub_path, format_scalar_value};
+
+/// The name of the Hive style `key=value` encoding.
+pub const HIVE_PARTITION_PATH_ENCODING: &str = "hive";
+
+/// The name of the directory encoding writing one directory per value without the key.
+pub const DIRECTORY_PARTITION_PATH_ENCODING: &str = "directory";
+
+/// Encodes the values of the range partition columns into the sub path of the data files.
+pub trait PartitionPathEncoder: Debug + Send + Sync {
+    /// Encode the values of the range partition columns, in the order of the range partitions,
+    /// into a sub path starting and ending with `/`.
+    fn encode(&self, columnar_values: &[(String, ScalarValue)]) -> String;
+}
+
+/// The Hive style encoding, a directory `key=value` per range partition column.
+#[derive(Debug, Default)]
+pub struct HivePartitionPathEncoder;
+
+impl PartitionPathEncoder for HivePartitionPathEncoder {
+    fn encode(&self, columnar_values: &[(String, ScalarValue)]) -> String {
+        columnar_values_to_sub_path(columnar_values)
+    }
+}
+
+/// The non-Hive encoding, a directory per range partition column named by the value only.
+#[derive(Debug, Default)]
+pub struct DirectoryPartitionPathEncoder;
+
+impl PartitionPathEncoder for DirectoryPartitionPathEncoder {
+    fn encode(&self, columnar_values: &[(String, ScalarValue)]) -> String {
+        if columnar_values.is_empty() {
+            "/".to_string()
+        } else {
+            format!(
+                "/{}/",
+                columnar_values
+                    .iter()
+                    .map(|(_, v)| format_scalar_value(v))
+                    .collect::<Vec<_>>()
+                    .join("/")
+            )
+        }
+    }
+}
+
+/// Returns the [`PartitionPathEncoder`] of the encoding name.
+pub fn partition_path_encoder(name: &str) -> Result<Arc<dyn PartitionPathEncoder>> {
+    match name.to_ascii_lowercase().as_str() {
+        HIVE_PARTITION_PATH_ENCODING => Ok(Arc::new(HivePartitionPathEncoder)),
+        DIRECTORY_PARTITION_PATH_ENCODING => Ok(Arc::new(DirectoryPartitionPathEncoder)),
+        _ => Err(DataFusionError::Configuration(format!(
+            "invalid partition path encoding {}, expected {} or {}",
+            name, HIVE_PARTITION_PATH_ENCODING, DIRECTORY_PARTITION_PATH_ENCODING
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion_common::{Result, ScalarValue};
+
+    use super::{
+        DirectoryPartitionPathEncoder, HivePartitionPathEncoder, PartitionPathEncoder,
+        partition_path_encoder,
+    };
+
+    fn columnar_values() -> Vec<(String, ScalarValue)> {
+        vec![
+            ("date".to_string(), ScalarValue::Date32(Some(19723))),
+            (
+                "region".to_string(),
+                ScalarValue::Utf8(Some("eu-west".to_string())),
+            ),
+            ("bucket".to_string(), ScalarValue::Int32(Some(7))),
+        ]
+    }
+
+    #[test]
+    fn test_hive_encoding() {
+        let encoder = HivePartitionPathEncoder;
+        assert_eq!(
+            encoder.encode(&columnar_values()),
+            "/date=2024-01-01/region=eu-west/bucket=7/"
+        );
+        assert_eq!(encoder.encode(&[]), "/");
+    }
+
+    #[test]
+    fn test_directory_encoding() {
+        let encoder = DirectoryPartitionPathEncoder;
+        assert_eq!(encoder.encode(&columnar_values()), "/2024-01-01/eu-west/7/");
+        assert_eq!(encoder.encode(&[]), "/");
+    }
+
+    #[test]
+    fn test_partition_path_encoder_by_name() -> Result<()> {
+        let sub_path = partition_path_encoder("directory")?.encode(&columnar_values());
+        assert_eq!(sub_path, "/2024-01-01/eu-west/7/");
+        assert!(partition_path_encoder("unknown").is_err());
+        Ok(())
+    }
+}
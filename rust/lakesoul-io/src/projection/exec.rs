@@ -0,0 +1,296 @@
+// SPDX-FileCopyrightText: LakeSoul Contributors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Implementation of the projection execution plan producing [`ProjectionStream`]s.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use arrow::datatypes::{Field, Schema, SchemaRef};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::TaskContext;
+use datafusion::physical_expr::PhysicalExpr;
+use datafusion::physical_expr::equivalence::ProjectionMapping;
+use datafusion::physical_plan::metrics::{
+    BaselineMetrics, ExecutionPlanMetricsSet, MetricsSet,
+};
+use datafusion::physical_plan::{
+    DisplayAs, DisplayFormatType, ExecutionPlan, ExecutionPlanProperties, PlanProperties,
+    SendableRecordBatchStream,
+};
+
+use super::ProjectionStream;
+
+/// [`ExecutionPlan`] implementation which evaluates a list of expressions on each batch of
+/// its input with a [`ProjectionStream`].
+#[derive(Debug)]
+pub struct LakeSoulProjectionExec {
+    /// The expressions to project, with the names of the output columns.
+    expr: Vec<(Arc<dyn PhysicalExpr>, String)>,
+    /// The input plan.
+    input: Arc<dyn ExecutionPlan>,
+    /// The schema of the projected output.
+    schema: SchemaRef,
+    /// The input size in bytes above which a batch is split before the projection.
+    max_batch_bytes: Option<usize>,
+    /// The metrics of the projection.
+    metrics: ExecutionPlanMetricsSet,
+    properties: PlanProperties,
+}
+
+impl LakeSoulProjectionExec {
+    /// Create a new [`LakeSoulProjectionExec`].
+    ///
+    /// # Arguments
+    ///
+    /// * `expr` - The expressions to project, with the names of the output columns
+    /// * `input` - The input plan
+    pub fn try_new(
+        expr: Vec<(Arc<dyn PhysicalExpr>, String)>,
+        input: Arc<dyn ExecutionPlan>,
+    ) -> Result<Self> {
+        let input_schema = input.schema();
+        let fields = expr
+            .iter()
+            .map(|(e, name)| {
+                Ok(Field::new(
+                    name,
+                    e.data_type(&input_schema)?,
+                    e.nullable(&input_schema)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let schema = Arc::new(Schema::new_with_metadata(
+            fields,
+            input_schema.metadata().clone(),
+        ));
+
+        // the ordering and partitioning of the input carry over to the projected columns
+        let projection_mapping = ProjectionMapping::try_new(&expr, &input_schema)?;
+        let input_eq_properties = input.equivalence_properties();
+        let eq_properties =
+            input_eq_properties.project(&projection_mapping, schema.clone());
+        let partitioning = input
+            .output_partitioning()
+            .project(&projection_mapping, input_eq_properties);
+        let properties = PlanProperties::new(
+            eq_properties,
+            partitioning,
+            input.pipeline_behavior(),
+            input.boundedness(),
+        );
+        Ok(Self {
+            expr,
+            input,
+            schema,
+            max_batch_bytes: None,
+            metrics: ExecutionPlanMetricsSet::new(),
+            properties,
+        })
+    }
+
+    /// Split the input batches larger than `max_batch_bytes` before projecting them, see
+    /// [`ProjectionStream::with_max_batch_bytes`].
+    pub fn with_max_batch_bytes(mut self, max_batch_bytes: Option<usize>) -> Self {
+        self.max_batch_bytes = max_batch_bytes;
+        self
+    }
+
+    /// The expressions to project, with the names of the output columns.
+    pub fn expr(&self) -> &[(Arc<dyn PhysicalExpr>, String)] {
+        &self.expr
+    }
+
+    /// The input plan.
+    pub fn input(&self) -> &Arc<dyn ExecutionPlan> {
+        &self.input
+    }
+}
+
+impl DisplayAs for LakeSoulProjectionExec {
+    fn fmt_as(
+        &self,
+        _t: DisplayFormatType,
+        f: &mut std::fmt::Formatter,
+    ) -> std::fmt::Result {
+        let expr = self
+            .expr
+            .iter()
+            .map(|(e, name)| format!("{} as {}", e, name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(f, "LakeSoulProjectionExec: expr=[{}]", expr)
+    }
+}
+
+impl ExecutionPlan for LakeSoulProjectionExec {
+    fn name(&self) -> &str {
+        "LakeSoulProjectionExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        &self.properties
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![&self.input]
+    }
+
+    fn maintains_input_order(&self) -> Vec<bool> {
+        vec![true]
+    }
+
+    fn benefits_from_input_partitioning(&self) -> Vec<bool> {
+        vec![false]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        mut children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        if children.len() != 1 {
+            return Err(DataFusionError::Internal(format!(
+                "LakeSoulProjectionExec requires exactly one child, got {}",
+                children.len()
+            )));
+        }
+        Ok(Arc::new(
+            Self::try_new(self.expr.clone(), children.remove(0))?
+                .with_max_batch_bytes(self.max_batch_bytes),
+        ))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> Result<SendableRecordBatchStream> {
+        Ok(Box::pin(
+            ProjectionStream::new(
+                self.schema.clone(),
+                self.expr.iter().map(|(e, _)| e.clone()).collect(),
+                self.input.execute(partition, context)?,
+                BaselineMetrics::new(&self.metrics, partition),
+            )
+            .with_max_batch_bytes(self.max_batch_bytes),
+        ))
+    }
+
+    fn metrics(&self) -> Option<MetricsSet> {
+        Some(self.metrics.clone_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::{ArrayRef, AsArray, Int32Array};
+    use arrow::datatypes::Int32Type;
+    use arrow::record_batch::RecordBatch;
+    use arrow::util::pretty::pretty_format_batches;
+    use datafusion::datasource::memory::MemorySourceConfig;
+    use datafusion::error::Result;
+    use datafusion::logical_expr::Operator;
+    use datafusion::physical_expr::expressions::{BinaryExpr, col};
+    use datafusion::physical_plan::{ExecutionPlan, collect};
+    use datafusion::prelude::SessionContext;
+
+    use super::LakeSoulProjectionExec;
+
+    #[tokio::test]
+    async fn test_projection_exec() -> Result<()> {
+        let a = Arc::new(Int32Array::from(vec![1, 2, 3])) as ArrayRef;
+        let b = Arc::new(Int32Array::from(vec![10, 20, 30])) as ArrayRef;
+        let batch = RecordBatch::try_from_iter([("a", a), ("b", b)])?;
+        let schema = batch.schema();
+        let input =
+            MemorySourceConfig::try_new_exec(&[vec![batch]], schema.clone(), None)?;
+
+        let sum = Arc::new(BinaryExpr::new(
+            col("a", &schema)?,
+            Operator::Plus,
+            col("b", &schema)?,
+        ));
+        let exec = Arc::new(LakeSoulProjectionExec::try_new(
+            vec![
+                (col("b", &schema)?, "b".to_string()),
+                (sum, "sum".to_string()),
+            ],
+            input,
+        )?) as Arc<dyn ExecutionPlan>;
+        let batches = collect(exec.clone(), SessionContext::new().task_ctx()).await?;
+        assert_eq!(
+            pretty_format_batches(&batches)?.to_string(),
+            [
+                "+----+-----+",
+                "| b  | sum |",
+                "+----+-----+",
+                "| 10 | 11  |",
+                "| 20 | 22  |",
+                "| 30 | 33  |",
+                "+----+-----+",
+            ]
+            .join("\n")
+        );
+        assert_eq!(exec.metrics().and_then(|m| m.output_rows()), Some(3));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_projection_exec_splits_large_batches() -> Result<()> {
+        let a = Arc::new(Int32Array::from_iter_values(0..100)) as ArrayRef;
+        let batch = RecordBatch::try_from_iter([("a", a)])?;
+        let schema = batch.schema();
+        let input =
+            MemorySourceConfig::try_new_exec(&[vec![batch]], schema.clone(), None)?;
+
+        // 400 bytes of input are split into chunks of 30 rows
+        let exec = Arc::new(
+            LakeSoulProjectionExec::try_new(
+                vec![(col("a", &schema)?, "a".to_string())],
+                input.clone(),
+            )?
+            .with_max_batch_bytes(Some(120)),
+        ) as Arc<dyn ExecutionPlan>;
+        let batches = collect(exec, SessionContext::new().task_ctx()).await?;
+        let num_rows = batches
+            .iter()
+            .map(RecordBatch::num_rows)
+            .collect::<Vec<_>>();
+        assert_eq!(num_rows, vec![30, 30, 30, 10]);
+        let values = batches
+            .iter()
+            .flat_map(|batch| {
+                batch
+                    .column(0)
+                    .as_primitive::<Int32Type>()
+                    .values()
+                    .to_vec()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(values, (0..100).collect::<Vec<_>>());
+
+        // a projection without columns keeps the row counts of the chunks
+        let exec = Arc::new(
+            LakeSoulProjectionExec::try_new(vec![], input)?
+                .with_max_batch_bytes(Some(120)),
+        ) as Arc<dyn ExecutionPlan>;
+        let batches = collect(exec, SessionContext::new().task_ctx()).await?;
+        let num_rows = batches
+            .iter()
+            .map(RecordBatch::num_rows)
+            .collect::<Vec<_>>();
+        assert_eq!(num_rows, vec![30, 30, 30, 10]);
+        Ok(())
+    }
+}
@@ -4,6 +4,7 @@
 
 //! This module provides the implementation of the projection operator, projection implementation is refer from datafusion.
 
+use std::collections::VecDeque;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
@@ -13,14 +14,82 @@ use arrow::record_batch::{RecordBatch, RecordBatchOptions};
 
 use datafusion::error::Result;
 use datafusion::physical_expr::PhysicalExpr;
+use datafusion::physical_plan::metrics::BaselineMetrics;
 use datafusion::physical_plan::{RecordBatchStream, SendableRecordBatchStream};
 
 use futures::{Stream, StreamExt};
 
+use crate::helpers::get_batch_memory_size;
+
+pub use exec::LakeSoulProjectionExec;
+
+mod exec;
+
 impl ProjectionStream {
+    /// Create a new [`ProjectionStream`].
+    ///
+    /// # Arguments
+    ///
+    /// * `schema` - The schema of the projected output
+    /// * `expr` - The expressions to project
+    /// * `input` - The input stream
+    /// * `baseline_metrics` - The metrics recording the elapsed compute and output rows,
+    ///   usually created from the [`datafusion::physical_plan::metrics::ExecutionPlanMetricsSet`]
+    ///   of the plan executing the stream
+    pub fn new(
+        schema: SchemaRef,
+        expr: Vec<Arc<dyn PhysicalExpr>>,
+        input: SendableRecordBatchStream,
+        baseline_metrics: BaselineMetrics,
+    ) -> Self {
+        Self {
+            schema,
+            expr,
+            input,
+            baseline_metrics,
+            max_batch_bytes: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Split the input batches larger than `max_batch_bytes` before projecting them.
+    ///
+    /// The projected size of a batch is estimated from its input size, so a large input batch
+    /// is sliced into chunks of about `max_batch_bytes`, and each chunk is only projected
+    /// once the consumer polls for it. This bounds the arrays materialized at once by wide or
+    /// expensive projections, at the cost of more and smaller output batches.
+    pub fn with_max_batch_bytes(mut self, max_batch_bytes: Option<usize>) -> Self {
+        self.max_batch_bytes = max_batch_bytes.filter(|bytes| *bytes > 0);
+        self
+    }
+
+    /// Returns the metrics of the projection.
+    pub fn metrics(&self) -> &BaselineMetrics {
+        &self.baseline_metrics
+    }
+
+    /// Project the first chunk of the batch, queueing the remaining chunks if it is split.
+    fn split_and_project(&mut self, batch: RecordBatch) -> Result<RecordBatch> {
+        let Some(max_batch_bytes) = self.max_batch_bytes else {
+            return self.batch_project(&batch);
+        };
+        let batch_bytes = get_batch_memory_size(&batch)?;
+        if batch_bytes <= max_batch_bytes || batch.num_rows() <= 1 {
+            return self.batch_project(&batch);
+        }
+        let num_rows = batch.num_rows();
+        let chunk_rows = (num_rows * max_batch_bytes / batch_bytes).max(1);
+        self.pending.extend(
+            (chunk_rows..num_rows)
+                .step_by(chunk_rows)
+                .map(|offset| batch.slice(offset, chunk_rows.min(num_rows - offset))),
+        );
+        self.batch_project(&batch.slice(0, chunk_rows))
+    }
+
     fn batch_project(&self, batch: &RecordBatch) -> Result<RecordBatch> {
         // records time on drop
-        // let _timer = self.baseline_metrics.elapsed_compute().timer();
+        let _timer = self.baseline_metrics.elapsed_compute().timer();
         let arrays = self
             .expr
             .iter()
@@ -49,6 +118,12 @@ pub struct ProjectionStream {
     pub(crate) expr: Vec<Arc<dyn PhysicalExpr>>,
     /// The input stream.
     pub(crate) input: SendableRecordBatchStream,
+    /// The metrics of the projection.
+    pub(crate) baseline_metrics: BaselineMetrics,
+    /// The input size in bytes above which a batch is split before the projection.
+    pub(crate) max_batch_bytes: Option<usize>,
+    /// The chunks of a split input batch waiting to be projected.
+    pub(crate) pending: VecDeque<RecordBatch>,
 }
 
 impl Stream for ProjectionStream {
@@ -58,15 +133,25 @@ impl Stream for ProjectionStream {
         mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Option<Self::Item>> {
-        self.input.poll_next_unpin(cx).map(|x| match x {
-            Some(Ok(batch)) => Some(self.batch_project(&batch)),
+        if let Some(chunk) = self.pending.pop_front() {
+            let poll = Poll::Ready(Some(self.batch_project(&chunk)));
+            return self.baseline_metrics.record_poll(poll);
+        }
+        let poll = self.input.poll_next_unpin(cx).map(|x| match x {
+            Some(Ok(batch)) => Some(self.split_and_project(batch)),
             other => other,
-        })
+        });
+        self.baseline_metrics.record_poll(poll)
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        // same number of record batches
-        self.input.size_hint()
+        // at least the same number of record batches, more if they are split
+        let (lower, upper) = self.input.size_hint();
+        let lower = lower + self.pending.len();
+        match self.max_batch_bytes {
+            Some(_) => (lower, None),
+            None => (lower, upper.map(|upper| upper + self.pending.len())),
+        }
     }
 }
 
@@ -12,7 +12,7 @@ use arrow::{
     array::ArrayRef,
     datatypes::SchemaRef,
     record_batch::RecordBatch,
-    row::{Row, Rows},
+    row::{OwnedRow, Row, Rows},
 };
 use arrow_array::Array;
 use arrow_cast::pretty::pretty_format_batches;
@@ -33,6 +33,8 @@ pub struct SortKeyBatchRange {
     pub(crate) batch: Arc<RecordBatch>,
     /// The reference of rows in this batch
     pub(crate) rows: Arc<Rows>,
+    /// The rows of the sequence column in this batch, if the merge is ordered by one
+    pub(crate) sequences: Option<Arc<Rows>>,
 }
 
 impl SortKeyBatchRange {
@@ -52,6 +54,7 @@ impl SortKeyBatchRange {
             batch_idx,
             batch,
             rows,
+            sequences: None,
         }
     }
 
@@ -70,11 +73,18 @@ impl SortKeyBatchRange {
             stream_idx,
             batch,
             rows,
+            sequences: None,
         };
         range.advance();
         range
     }
 
+    /// Set the rows of the sequence column of the batch, ordering the rows of a sort key
+    pub(crate) fn with_sequences(mut self, sequences: Option<Arc<Rows>>) -> Self {
+        self.sequences = sequences;
+        self
+    }
+
     /// Returns the [`Schema`](arrow_schema::Schema) of the record batch.
     pub fn schema(&self) -> SchemaRef {
         self.batch.schema()
@@ -90,6 +100,23 @@ impl SortKeyBatchRange {
         self.rows.row(self.begin_row)
     }
 
+    /// Return the index of the row of this range merged last, which is the last row with the
+    /// highest sequence if the batch has sequences
+    pub(crate) fn last_row(&self) -> usize {
+        match &self.sequences {
+            None => self.end_row - 1,
+            Some(sequences) => (self.begin_row..self.end_row)
+                .reduce(|last, row| {
+                    if sequences.row(row) >= sequences.row(last) {
+                        row
+                    } else {
+                        last
+                    }
+                })
+                .unwrap_or(self.end_row - 1),
+        }
+    }
+
     #[inline(always)]
     /// Return the stream index of this range
     pub fn stream_idx(&self) -> usize {
@@ -170,6 +197,7 @@ impl Clone for SortKeyBatchRange {
             self.batch.clone(),
             self.rows.clone(),
         )
+        .with_sequences(self.sequences.clone())
     }
 }
 
@@ -244,6 +272,11 @@ pub struct SortKeyBatchRanges {
 
     /// The current batch range for collecting SortKeyArrayRange of current primary key
     pub(crate) batch_range: Option<SortKeyBatchRange>,
+
+    /// vector with length=column_num
+    /// the highest sequence of each collected range of the column, in the order of the
+    /// collected ranges of the column
+    sequences: Vec<SmallVec<[Option<OwnedRow>; 4]>>,
 }
 
 impl SortKeyBatchRanges {
@@ -256,6 +289,7 @@ impl SortKeyBatchRanges {
             fields_map,
             schema: schema.clone(),
             batch_range: None,
+            sequences: vec![smallvec![]; schema.fields().len()],
         }
     }
 
@@ -269,15 +303,30 @@ impl SortKeyBatchRanges {
     }
 
     /// add one SortKeyBatchRange into SortKeyBatchRanges, collect SortKeyArrayRange of each column into sort_key_array_ranges
+    ///
+    /// If the ranges carry sequences, the ranges of each column are collected in the order
+    /// of their highest sequence instead of the order they are added in, the ranges without
+    /// sequences first. The columns missing from some streams are ordered by the sequences
+    /// of their own ranges only.
     pub fn add_range_in_batch(&mut self, range: SortKeyBatchRange) {
         if self.is_empty() {
             self.set_batch_range(Some(range.clone()));
         }
+        let sequence = range
+            .sequences
+            .as_ref()
+            .map(|sequences| sequences.row(range.last_row()).owned());
         let schema = range.schema();
         for column_idx in 0..schema.fields().len() {
             let range_col = range.column(column_idx);
             let target_schema_idx = self.fields_map[range.stream_idx()][column_idx];
-            self.sort_key_array_ranges[target_schema_idx].push(range_col);
+            let sequences = &mut self.sequences[target_schema_idx];
+            let position = sequences
+                .iter()
+                .position(|collected| collected > &sequence)
+                .unwrap_or(sequences.len());
+            sequences.insert(position, sequence.clone());
+            self.sort_key_array_ranges[target_schema_idx].insert(position, range_col);
         }
     }
 
@@ -313,6 +362,8 @@ pub struct UseLastSortKeyArrayRange {
     pub(crate) column_idx: usize,
     /// The stream index
     pub(crate) stream_idx: usize,
+    /// The rows of the sequence column in the batch
+    pub(crate) sequences: Option<Arc<Rows>>,
 }
 
 impl UseLastSortKeyArrayRange {
@@ -327,6 +378,13 @@ impl UseLastSortKeyArrayRange {
     pub fn array_ref_by_col(&self, column_idx: usize) -> ArrayRef {
         unsafe { self.batch.columns().get_unchecked(column_idx).clone() }
     }
+
+    /// Return the sequence of the row, `None` ranks below any sequence
+    pub(crate) fn sequence(&self) -> Option<Row<'_>> {
+        self.sequences
+            .as_ref()
+            .map(|sequences| sequences.row(self.row_idx))
+    }
 }
 
 impl Clone for UseLastSortKeyArrayRange {
@@ -337,6 +395,7 @@ impl Clone for UseLastSortKeyArrayRange {
             batch: self.batch.clone(),
             column_idx: self.column_idx,
             stream_idx: self.stream_idx,
+            sequences: self.sequences.clone(),
         }
     }
 }
@@ -388,36 +447,52 @@ impl UseLastSortKeyBatchRanges {
 
     /// add one SortKeyBatchRange into UseLastSortKeyBatchRanges,
     /// collect UseLastSortKeyArrayRange of each column into last_index_of_array
+    ///
+    /// If the ranges carry sequences, a row replaces the collected one only if its sequence is
+    /// not lower, so the row with the highest sequence wins regardless of the stream order.
     pub fn add_range_in_batch(&mut self, range: &SortKeyBatchRange) {
         if self.is_empty() {
             self.set_batch_range(Some(range.clone()));
         }
+        let row_idx = range.last_row();
+        let sequence = range
+            .sequences
+            .as_ref()
+            .map(|sequences| sequences.row(row_idx));
         unsafe {
             if self.is_partial_merge {
                 let range_col = self.fields_map.get_unchecked(range.stream_idx());
                 for column_idx in 0..range.columns() {
                     let target_schema_idx = range_col.get_unchecked(column_idx);
-                    *self
+                    let last = self
                         .last_index_of_array
-                        .get_unchecked_mut(*target_schema_idx) =
-                        Some(UseLastSortKeyArrayRange {
-                            row_idx: range.end_row - 1,
-                            batch_idx: range.batch_idx,
-                            batch: range.batch(),
-                            column_idx,
-                            stream_idx: range.stream_idx(),
-                        });
-                }
-            } else {
-                // full column merge. we just need to record batch idx of this row
-                *self.last_index_of_array.get_unchecked_mut(0) =
-                    Some(UseLastSortKeyArrayRange {
-                        row_idx: range.end_row - 1,
+                        .get_unchecked_mut(*target_schema_idx);
+                    if last.as_ref().is_some_and(|last| last.sequence() > sequence) {
+                        continue;
+                    }
+                    *last = Some(UseLastSortKeyArrayRange {
+                        row_idx,
                         batch_idx: range.batch_idx,
                         batch: range.batch(),
-                        column_idx: 0,
+                        column_idx,
                         stream_idx: range.stream_idx(),
+                        sequences: range.sequences.clone(),
                     });
+                }
+            } else {
+                // full column merge. we just need to record batch idx of this row
+                let last = self.last_index_of_array.get_unchecked_mut(0);
+                if last.as_ref().is_some_and(|last| last.sequence() > sequence) {
+                    return;
+                }
+                *last = Some(UseLastSortKeyArrayRange {
+                    row_idx,
+                    batch_idx: range.batch_idx,
+                    batch: range.batch(),
+                    column_idx: 0,
+                    stream_idx: range.stream_idx(),
+                    sequences: range.sequences.clone(),
+                });
             }
         }
     }
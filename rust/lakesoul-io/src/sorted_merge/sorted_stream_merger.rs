@@ -16,6 +16,7 @@ use crate::sorted_merge::sort_key_range::SortKeyBatchRange;
 
 use arrow::record_batch::RecordBatch;
 use arrow::row::{RowConverter, SortField};
+use arrow_schema::SortOptions;
 use datafusion::arrow::datatypes::SchemaRef;
 use datafusion::error::Result;
 use datafusion::physical_expr::PhysicalExpr;
@@ -98,6 +99,10 @@ pub(crate) struct SortedStreamMerger {
     /// row converter for sort fields
     row_converters: Vec<RowConverter>,
 
+    /// The expression and row converter of the sequence column of each stream, `None` if the
+    /// merge is not ordered by a sequence column or the stream lacks it
+    sequence_converters: Vec<Option<(Arc<dyn PhysicalExpr>, RowConverter)>>,
+
     /// The accumulated indexes for the next record batch
     batch_idx_counter: usize,
 
@@ -115,12 +120,16 @@ impl SortedStreamMerger {
     /// * `primary_keys` - The primary keys of the RecordBatches.
     /// * `batch_size` - The batch size of the RecordBatches.
     /// * `merge_operator` - The merge operator to use.
+    /// * `sort_options` - The ordering of the primary keys the streams are sorted by, the
+    ///   same as the one of the sort of the written files, see
+    ///   [`LakeSoulIOConfig::primary_key_sort_options`](crate::lakesoul_io_config::LakeSoulIOConfig::primary_key_sort_options).
     pub(crate) fn new_from_streams(
         streams: Vec<SortedStream>,
         target_schema: SchemaRef,
         primary_keys: Vec<String>,
         batch_size: usize,
         merge_operator: Vec<MergeOperator>,
+        sort_options: SortOptions,
     ) -> Result<Self> {
         let streams_num = streams.len();
 
@@ -144,7 +153,7 @@ impl SortedStreamMerger {
                     .map(move |pk| {
                         let data_type =
                             schema.field_with_name(pk.as_str())?.data_type().clone();
-                        Ok(SortField::new(data_type))
+                        Ok(SortField::new_with_options(data_type, sort_options))
                     })
                     .collect::<Result<Vec<_>>>()?;
                 Ok(RowConverter::new(sort_fields)?)
@@ -190,11 +199,42 @@ impl SortedStreamMerger {
             aborted: false,
             range_combiner: combiner,
             row_converters,
+            sequence_converters: (0..streams_num).map(|_| None).collect(),
             batch_idx_counter: 0,
             initialized: vec![false; streams_num],
         })
     }
 
+    /// Order the rows of each primary key by the sequence column instead of the stream order,
+    /// the row with the highest sequence wins and ties are merged in the stream order.
+    ///
+    /// The streams lacking the column lose against any sequence. With merge operators
+    /// other than `UseLast`, the ranges of the streams are merged in the order of their
+    /// highest sequence, and the rows of a range in the stream order.
+    pub(crate) fn with_sequence_column(
+        mut self,
+        sequence_column: Option<&str>,
+    ) -> Result<Self> {
+        let Some(sequence_column) = sequence_column else {
+            return Ok(self);
+        };
+        self.sequence_converters = self
+            .streams
+            .streams
+            .iter()
+            .map(|stream| {
+                let schema = stream.get_ref().schema();
+                let Ok(field) = schema.field_with_name(sequence_column) else {
+                    return Ok(None);
+                };
+                let converter =
+                    RowConverter::new(vec![SortField::new(field.data_type().clone())])?;
+                Ok(Some((col(sequence_column, &schema)?, converter)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(self)
+    }
+
     /// If the stream at the given index is not exhausted, and the last batch range for the
     /// stream is finished, poll the stream for the next RecordBatch and create a new
     /// batch range for the stream from the returned result
@@ -236,6 +276,16 @@ impl SortedStreamMerger {
                             }
                         };
 
+                        let sequences = match &self.sequence_converters[idx] {
+                            Some((expr, converter)) => {
+                                let sequence = expr
+                                    .evaluate(&batch)?
+                                    .into_array(batch.num_rows())?;
+                                Some(Arc::new(converter.convert_columns(&[sequence])?))
+                            }
+                            None => None,
+                        };
+
                         self.batch_idx_counter += 1;
                         let (batch, rows) = (Arc::new(batch), Arc::new(rows));
                         let range = SortKeyBatchRange::new_and_init(
@@ -244,7 +294,8 @@ impl SortedStreamMerger {
                             self.batch_idx_counter,
                             batch,
                             rows,
-                        );
+                        )
+                        .with_sequences(sequences);
 
                         self.range_finished[idx] = false;
 
@@ -363,6 +414,7 @@ mod tests {
     use arrow::record_batch::RecordBatch;
     use arrow::util::pretty::print_batches;
     use arrow_array::Float64Array;
+    use arrow_schema::SortOptions;
     use datafusion::assert_batches_eq;
     use datafusion::error::Result;
     use datafusion::execution::context::TaskContext;
@@ -423,6 +475,7 @@ mod tests {
             vec![String::from("int0")],
             1024,
             vec![],
+            SortOptions::default(),
         )
         .unwrap();
         let merged_result = common::collect(Box::pin(merge_stream)).await.unwrap();
@@ -586,6 +639,7 @@ mod tests {
             vec![String::from("a")],
             2,
             vec![],
+            SortOptions::default(),
         )
         .unwrap();
         let merged = common::collect(Box::pin(merge_stream)).await.unwrap();
@@ -771,6 +825,7 @@ mod tests {
             vec![String::from("id")],
             2,
             vec![],
+            SortOptions::default(),
         )
         .unwrap();
         let merged = common::collect(Box::pin(merge_stream)).await.unwrap();
@@ -1003,6 +1058,7 @@ mod tests {
                 MergeOperator::UseLast,
                 MergeOperator::UseLast,
             ],
+            SortOptions::default(),
         )
         .unwrap();
         let merged = common::collect(Box::pin(merge_stream)).await.unwrap();
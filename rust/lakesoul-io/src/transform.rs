@@ -63,6 +63,32 @@ pub fn uniform_schema(orig_schema: SchemaRef) -> SchemaRef {
     ))
 }
 
+/// Coerce a timestamp field, or the timestamp fields nested in a struct, into the time unit,
+/// keeping the time zones and the metadata of the fields.
+pub fn coerce_timestamp_field(field: &FieldRef, unit: TimeUnit) -> FieldRef {
+    match field.data_type() {
+        DataType::Timestamp(orig_unit, tz) if *orig_unit != unit => Arc::new(
+            field
+                .as_ref()
+                .clone()
+                .with_data_type(DataType::Timestamp(unit, tz.clone())),
+        ),
+        DataType::Struct(fields) => {
+            let fields = fields
+                .iter()
+                .map(|field| coerce_timestamp_field(field, unit))
+                .collect::<Fields>();
+            Arc::new(
+                field
+                    .as_ref()
+                    .clone()
+                    .with_data_type(DataType::Struct(fields)),
+            )
+        }
+        _ => field.clone(),
+    }
+}
+
 pub fn uniform_record_batch(batch: RecordBatch) -> Result<RecordBatch> {
     transform_record_batch(
         uniform_schema(batch.schema()),
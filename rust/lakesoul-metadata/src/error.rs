@@ -45,6 +45,16 @@ pub enum LakeSoulMetaDataError {
     Internal(String),
     #[error("Not found error: {0}")]
     NotFound(String),
+    #[error(
+        "partition {partition_desc} of table {table_id} was committed concurrently, \
+    read version {read_version:?}, current version {current_version:?}"
+    )]
+    CommitConflict {
+        table_id: String,
+        partition_desc: String,
+        read_version: Option<i32>,
+        current_version: Option<i32>,
+    },
     #[error("Other error: {0}")]
     Other(#[from] GenericError),
 }
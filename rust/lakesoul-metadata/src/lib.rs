@@ -63,6 +63,8 @@ enum ResultType {
     DataCommitInfo,
     /// The result type for the table_path_id with only table path.
     TablePathIdWithOnlyPath,
+    /// The result type for the file_statistics.
+    FileStatistics,
     /// The result type for the partition_info with only commit_op.
     PartitionInfoWithOnlyCommitOp,
     /// The result type for the discard_compressed_file_info.
@@ -174,6 +176,14 @@ pub enum DaoType {
     ListDiscardCompressedFileInfoBeforeTimestamp = DAO_TYPE_QUERY_LIST_OFFSET + 12,
     /// The coded type for the Data Access Object for list discard compressed file by filter condition.
     ListDiscardCompressedFileByFilterCondition = DAO_TYPE_QUERY_LIST_OFFSET + 13,
+    /// The coded type for the Data Access Object for list file statistics by table id.
+    ListFileStatisticsByTableId = DAO_TYPE_QUERY_LIST_OFFSET + 14,
+    /// The coded type for the Data Access Object for list the latest partition versions up to a timestamp by table id.
+    ListPartitionByTableIdUpToTimestamp = DAO_TYPE_QUERY_LIST_OFFSET + 15,
+    /// The coded type for the Data Access Object for list the latest partition versions up to a version by table id.
+    ListPartitionByTableIdUpToVersion = DAO_TYPE_QUERY_LIST_OFFSET + 16,
+    /// The coded type for the Data Access Object for list file statistics by table id and partition description list.
+    ListFileStatisticsByTableIdAndPartitionDescList = DAO_TYPE_QUERY_LIST_OFFSET + 17,
 
     // ==== Coded Insert One ====
     /// The coded type for the Data Access Object for insert namespace.
@@ -190,9 +200,12 @@ pub enum DaoType {
     InsertDataCommitInfo = DAO_TYPE_INSERT_ONE_OFFSET + 5,
     /// The coded type for the Data Access Object for insert discard compressed file info.
     InsertDiscardCompressedFileInfo = DAO_TYPE_INSERT_ONE_OFFSET + 6,
+    /// The coded type for the Data Access Object for insert or replace file statistics.
+    InsertFileStatistics = DAO_TYPE_INSERT_ONE_OFFSET + 7,
 
     // ==== Coded Transaction Insert List ====
-    /// The coded type for the Data Access Object for transaction insert partition info.
+    /// The coded type for the Data Access Object for transaction insert partition info, along
+    /// with the data commit infos of their snapshots if given.
     TransactionInsertPartitionInfo = DAO_TYPE_TRANSACTION_INSERT_LIST_OFFSET,
     /// The coded type for the Data Access Object for transaction insert data commit info.
     TransactionInsertDataCommitInfo = DAO_TYPE_TRANSACTION_INSERT_LIST_OFFSET + 1,
@@ -263,6 +276,16 @@ pub enum DaoType {
     /// The coded type for the Data Access Object for delete discard compressed file by filter condition.
     DeleteDiscardCompressedFileByFilterCondition = DAO_TYPE_UPDATE_OFFSET + 17,
     DeleteDiscardCompressedFileInfoByTablePath = DAO_TYPE_UPDATE_OFFSET + 18,
+
+    // ==== Coded Update FileStatistics ====
+    /// The coded type for the Data Access Object for delete file statistics by table id.
+    DeleteFileStatisticsByTableId = DAO_TYPE_UPDATE_OFFSET + 19,
+
+    /// The coded type for the Data Access Object for delete file statistics by table id and file path.
+    DeleteFileStatisticsByTableIdAndFilePath = DAO_TYPE_UPDATE_OFFSET + 21,
+
+    /// The coded type for the Data Access Object for update table schema by table id and the current table schema.
+    UpdateTableSchemaByIdAndSchema = DAO_TYPE_UPDATE_OFFSET + 20,
 }
 
 /// Get the prepared statement for the coded Data Access Object.
@@ -344,6 +367,22 @@ async fn get_prepared_statement<'a>(
                 group by table_id, partition_desc) t
             left join partition_info m
             on t.table_id = m.table_id and t.partition_desc = m.partition_desc and t.max = m.version",
+        DaoType::ListPartitionByTableIdUpToTimestamp =>
+            "select m.table_id, t.partition_desc, m.version, m.commit_op, m.snapshot, m.timestamp, m.expression, m.domain
+            from (select table_id, partition_desc,max(version)
+                from partition_info
+                where table_id = $1::TEXT and timestamp <= $2::BIGINT
+                group by table_id, partition_desc) t
+            left join partition_info m
+            on t.table_id = m.table_id and t.partition_desc = m.partition_desc and t.max = m.version",
+        DaoType::ListPartitionByTableIdUpToVersion =>
+            "select m.table_id, t.partition_desc, m.version, m.commit_op, m.snapshot, m.timestamp, m.expression, m.domain
+            from (select table_id, partition_desc,max(version)
+                from partition_info
+                where table_id = $1::TEXT and version <= $2::INT
+                group by table_id, partition_desc) t
+            left join partition_info m
+            on t.table_id = m.table_id and t.partition_desc = m.partition_desc and t.max = m.version",
         DaoType::ListPartitionVersionByTableIdAndPartitionDescAndTimestampRange =>
             "select table_id, partition_desc, version, commit_op, snapshot, timestamp, expression, domain
             from partition_info
@@ -371,6 +410,14 @@ async fn get_prepared_statement<'a>(
         DaoType::ListAllDiscardCompressedFileInfo =>
             "select file_path, table_path, partition_desc, timestamp, t_date
             from discard_compressed_file_info",
+        DaoType::ListFileStatisticsByTableId =>
+            "select table_id, file_path, partition_desc, statistics
+            from file_statistics
+            where table_id = $1::TEXT",
+        DaoType::ListFileStatisticsByTableIdAndPartitionDescList =>
+            "select table_id, file_path, partition_desc, statistics
+            from file_statistics
+            where table_id = $1::TEXT and partition_desc = any($2::TEXT[])",
         DaoType::ListDiscardCompressedFileInfoBeforeTimestamp =>
             "select file_path, table_path, partition_desc, timestamp, t_date
             from discard_compressed_file_info
@@ -450,6 +497,16 @@ async fn get_prepared_statement<'a>(
                 t_date
             )
             values($1::TEXT, $2::TEXT, $3::TEXT, $4::BIGINT, $5::DATE)",
+        DaoType::InsertFileStatistics =>
+            "insert into file_statistics(
+                table_id,
+                file_path,
+                partition_desc,
+                statistics
+            )
+            values($1::TEXT, $2::TEXT, $3::TEXT, $4::JSON)
+            on conflict (table_id, file_path)
+            do update set partition_desc = excluded.partition_desc, statistics = excluded.statistics",
 
         // Query Scalar
         DaoType::GetLatestTimestampFromPartitionInfo =>
@@ -524,6 +581,15 @@ async fn get_prepared_statement<'a>(
         DaoType::DeleteDiscardCompressedFileInfoByTablePath =>
             "delete from discard_compressed_file_info
             where table_path = $1::TEXT",
+        DaoType::DeleteFileStatisticsByTableId =>
+            "delete from file_statistics
+            where table_id = $1::TEXT",
+        DaoType::DeleteFileStatisticsByTableIdAndFilePath =>
+            "delete from file_statistics
+            where table_id = $1::TEXT and file_path = $2::TEXT",
+        DaoType::UpdateTableSchemaByIdAndSchema =>
+            "update table_info set table_schema = $3::TEXT
+            where table_id = $1::TEXT and table_schema = $2::TEXT",
         DaoType::DeleteDiscardCompressedFileByFilterCondition =>
             "delete from discard_compressed_file_info
             where table_path = $1::TEXT and partition_desc = $2::TEXT and timestamp <= $3::BIGINT",
@@ -637,7 +703,9 @@ pub async fn execute_query(
                 Err(e) => return Err(LakeSoulMetaDataError::from(e)),
             }
         }
-        DaoType::ListPartitionByTableId | DaoType::ListAllPathTablePathByNamespace
+        DaoType::ListPartitionByTableId
+        | DaoType::ListAllPathTablePathByNamespace
+        | DaoType::ListFileStatisticsByTableId
             if params.len() == 1 =>
         {
             let result = client.query(&statement, &[&params[0]]).await;
@@ -699,6 +767,24 @@ pub async fn execute_query(
                 Err(e) => return Err(LakeSoulMetaDataError::from(e)),
             }
         }
+        DaoType::ListPartitionByTableIdUpToTimestamp if params.len() == 2 => {
+            let result = client
+                .query(&statement, &[&params[0], &i64::from_str(&params[1])?])
+                .await;
+            match result {
+                Ok(rows) => rows,
+                Err(e) => return Err(LakeSoulMetaDataError::from(e)),
+            }
+        }
+        DaoType::ListPartitionByTableIdUpToVersion if params.len() == 2 => {
+            let result = client
+                .query(&statement, &[&params[0], &i32::from_str(&params[1])?])
+                .await;
+            match result {
+                Ok(rows) => rows,
+                Err(e) => return Err(LakeSoulMetaDataError::from(e)),
+            }
+        }
         DaoType::SelectTableDomainById if params.len() == 1 => {
             let result = client.query(&statement, &[&params[0]]).await;
             match result {
@@ -726,6 +812,17 @@ pub async fn execute_query(
                 Err(e) => return Err(LakeSoulMetaDataError::from(e)),
             }
         }
+        DaoType::ListFileStatisticsByTableIdAndPartitionDescList if params.len() == 2 => {
+            let partition_descs =
+                params[1].split(PARTITION_DESC_DELIM).collect::<Vec<&str>>();
+            let result = client
+                .query(&statement, &[&params[0], &partition_descs])
+                .await;
+            match result {
+                Ok(rows) => rows,
+                Err(e) => return Err(LakeSoulMetaDataError::from(e)),
+            }
+        }
         DaoType::ListPartitionDescByTableIdAndParList if params.len() == 2 => {
             let statement = "\
                 select
@@ -850,6 +947,8 @@ pub async fn execute_query(
         | DaoType::SelectTableDomainById => ResultType::TableNameId,
 
         DaoType::ListPartitionByTableId
+        | DaoType::ListPartitionByTableIdUpToTimestamp
+        | DaoType::ListPartitionByTableIdUpToVersion
         | DaoType::ListPartitionDescByTableIdAndParList
         | DaoType::SelectPartitionVersionByTableIdAndDescAndVersion
         | DaoType::SelectOnePartitionVersionByTableIdAndDesc
@@ -876,6 +975,11 @@ pub async fn execute_query(
         | DaoType::ListDiscardCompressedFileByFilterCondition => {
             ResultType::DiscardCompressedFileInfo
         }
+
+        DaoType::ListFileStatisticsByTableId
+        | DaoType::ListFileStatisticsByTableIdAndPartitionDescList => {
+            ResultType::FileStatistics
+        }
         _ => {
             eprintln!(
                 "Invalid query_type={:?} when parsing query result type",
@@ -1052,6 +1156,21 @@ pub async fn execute_query(
                 ..Default::default()
             }
         }
+        ResultType::FileStatistics => {
+            let file_statistics: Vec<entity::FileStatistics> = rows
+                .iter()
+                .map(|row| entity::FileStatistics {
+                    table_id: row.get(0),
+                    file_path: row.get(1),
+                    partition_desc: row.get(2),
+                    statistics: row.get::<_, serde_json::Value>(3).to_string(),
+                })
+                .collect();
+            entity::JniWrapper {
+                file_statistics,
+                ..Default::default()
+            }
+        }
     };
     Ok(wrapper.encode_to_vec())
 }
@@ -1188,6 +1307,22 @@ pub async fn execute_insert(
                 )
                 .await
         }
+        DaoType::InsertFileStatistics if wrapper.file_statistics.len() == 1 => {
+            let file_statistics = wrapper.file_statistics.first().unwrap();
+            let statistics: serde_json::Value =
+                serde_json::from_str(&file_statistics.statistics)?;
+            client
+                .execute(
+                    &statement,
+                    &[
+                        &file_statistics.table_id,
+                        &file_statistics.file_path,
+                        &file_statistics.partition_desc,
+                        &statistics,
+                    ],
+                )
+                .await
+        }
         DaoType::TransactionInsertPartitionInfo => {
             let mut partition_info_list = wrapper.partition_info.clone();
             let snapshot_container = partition_info_list.pop().unwrap();
@@ -1220,6 +1355,105 @@ pub async fn execute_insert(
                     Err(e) => return Err(LakeSoulMetaDataError::from(e)),
                 };
 
+                // the data commit infos are inserted in the same transaction as the
+                // partition versions referencing them, so that a failed commit leaves none
+                if !wrapper.data_commit_info.is_empty() {
+                    let data_commit_info_statement = match transaction
+                        .prepare(
+                            "insert into data_commit_info(
+                            table_id,
+                            partition_desc,
+                            commit_id,
+                            file_ops,
+                            commit_op,
+                            timestamp,
+                            committed,
+                            domain
+                        )
+                        values($1::TEXT, $2::TEXT, $3::UUID, $4::_data_file_op, $5::TEXT, $6::BIGINT, $7::BOOL, $8::TEXT)",
+                        )
+                        .await
+                    {
+                        Ok(statement) => statement,
+                        Err(e) => return Err(LakeSoulMetaDataError::from(e)),
+                    };
+                    for data_commit_info in &wrapper.data_commit_info {
+                        let file_ops = data_commit_info
+                            .file_ops
+                            .iter()
+                            .map(DataFileOp::from_proto_data_file_op)
+                            .collect::<Result<Vec<DataFileOp>>>()?;
+                        let commit_id = data_commit_info.commit_id.as_ref().ok_or(
+                            LakeSoulMetaDataError::Internal(
+                                "commit_id missing".to_string(),
+                            ),
+                        )?;
+                        let _uuid =
+                            uuid::Uuid::from_u64_pair(commit_id.high, commit_id.low);
+                        let result = transaction
+                            .execute(
+                                &data_commit_info_statement,
+                                &[
+                                    &data_commit_info.table_id,
+                                    &data_commit_info.partition_desc,
+                                    &_uuid,
+                                    &file_ops,
+                                    &data_commit_info.commit_op().as_str_name(),
+                                    &data_commit_info.timestamp,
+                                    &data_commit_info.committed,
+                                    &data_commit_info.domain,
+                                ],
+                            )
+                            .await;
+                        // unlike a concurrently inserted partition version, a failed
+                        // insert of a data commit info is no commit conflict
+                        if let Err(e) = result {
+                            transaction.rollback().await?;
+                            return Err(LakeSoulMetaDataError::from(e));
+                        }
+                    }
+                }
+
+                // the statistics of the committed files are only visible with the commit
+                if !wrapper.file_statistics.is_empty() {
+                    let file_statistics_statement = match transaction
+                        .prepare(
+                            "insert into file_statistics(
+                            table_id,
+                            file_path,
+                            partition_desc,
+                            statistics
+                        )
+                        values($1::TEXT, $2::TEXT, $3::TEXT, $4::JSON)
+                        on conflict (table_id, file_path)
+                        do update set partition_desc = excluded.partition_desc, statistics = excluded.statistics",
+                        )
+                        .await
+                    {
+                        Ok(statement) => statement,
+                        Err(e) => return Err(LakeSoulMetaDataError::from(e)),
+                    };
+                    for file_statistics in &wrapper.file_statistics {
+                        let statistics: serde_json::Value =
+                            serde_json::from_str(&file_statistics.statistics)?;
+                        let result = transaction
+                            .execute(
+                                &file_statistics_statement,
+                                &[
+                                    &file_statistics.table_id,
+                                    &file_statistics.file_path,
+                                    &file_statistics.partition_desc,
+                                    &statistics,
+                                ],
+                            )
+                            .await;
+                        if let Err(e) = result {
+                            transaction.rollback().await?;
+                            return Err(LakeSoulMetaDataError::from(e));
+                        }
+                    }
+                }
+
                 for partition_info in &partition_info_list {
                     let snapshot = partition_info
                         .snapshot
@@ -1458,6 +1692,7 @@ pub async fn execute_update(
         | DaoType::DeleteTablePathIdByTablePath
         | DaoType::DeleteDiscardCompressedFileInfoByFilePath
         | DaoType::DeleteDiscardCompressedFileInfoByTablePath
+        | DaoType::DeleteFileStatisticsByTableId
             if params.len() == 1 =>
         {
             client.execute(&statement, &[&params[0]]).await
@@ -1474,6 +1709,7 @@ pub async fn execute_update(
         | DaoType::DeleteTableNameIdByTableNameAndNamespace
         | DaoType::DeletePartitionInfoByTableIdAndPartitionDesc
         | DaoType::DeleteDataCommitInfoByTableIdAndPartitionDesc
+        | DaoType::DeleteFileStatisticsByTableIdAndFilePath
             if params.len() == 2 =>
         {
             client.execute(&statement, &[&params[0], &params[1]]).await
@@ -1485,6 +1721,11 @@ pub async fn execute_update(
             let properties: serde_json::Value = serde_json::from_str(&params[1])?;
             client.execute(&statement, &[&params[0], &properties]).await
         }
+        DaoType::UpdateTableSchemaByIdAndSchema if params.len() == 3 => {
+            client
+                .execute(&statement, &[&params[0], &params[1], &params[2]])
+                .await
+        }
         DaoType::DeletePreviousVersionPartition if params.len() == 3 => {
             let ts = i64::from_str(&params[2])?;
             client
@@ -1687,7 +1928,8 @@ pub async fn clean_meta_for_test(client: &PooledClient) -> Result<i32> {
             delete from table_path_id;
             delete from table_name_id;
             delete from partition_info;
-            delete from discard_compressed_file_info",
+            delete from discard_compressed_file_info;
+            delete from file_statistics",
         )
         .await;
     match result {
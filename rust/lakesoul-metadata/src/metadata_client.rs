@@ -7,7 +7,10 @@
 use std::fmt::{Debug, Formatter};
 use std::ops::DerefMut;
 use std::sync::Arc;
-use std::{collections::HashMap, env, fs, vec};
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs, vec,
+};
 
 use postgres::Config;
 use prost::Message;
@@ -15,8 +18,9 @@ use tokio::sync::Mutex;
 use url::Url;
 
 use proto::proto::entity::{
-    self, CommitOp, DataCommitInfo, JniWrapper, MetaInfo, Namespace, PartitionInfo,
-    TableInfo, TableNameId, TablePathId,
+    self, CommitOp, DataCommitInfo, DataFileOp, DiscardCompressedFileInfo, FileOp,
+    FileStatistics, JniWrapper, MetaInfo, Namespace, PartitionInfo, TableInfo,
+    TableNameId, TablePathId,
 };
 
 use crate::error::{LakeSoulMetaDataError, Result};
@@ -182,6 +186,7 @@ impl MetaDataClient {
         self.delete_table_path_id_by_table_id(table_id).await?;
         self.delete_partition_info_by_table_id(table_id).await?;
         self.delete_data_commit_info_by_table_id(table_id).await?;
+        self.delete_file_statistics_by_table_id(table_id).await?;
         self.delete_table_info_by_id_and_path(table_id, table_path)
             .await?;
         Ok(())
@@ -221,6 +226,17 @@ impl MetaDataClient {
         .await
     }
 
+    pub async fn delete_file_statistics_by_table_id(
+        &self,
+        table_id: &str,
+    ) -> Result<i32> {
+        self.execute_update(
+            DaoType::DeleteFileStatisticsByTableId as i32,
+            [table_id].join(PARAM_DELIM),
+        )
+        .await
+    }
+
     pub async fn delete_table_info_by_id_and_path(
         &self,
         id: &str,
@@ -353,14 +369,21 @@ impl MetaDataClient {
         .await
     }
 
+    /// Insert the partition versions, and the data commit infos of their snapshots and the
+    /// statistics of their files if any, in one transaction. The last partition info only
+    /// carries the snapshot of the data commit infos to mark as committed.
     async fn transaction_insert_partition_info(
         &self,
         partition_info_list: Vec<PartitionInfo>,
+        data_commit_info_list: Vec<DataCommitInfo>,
+        file_statistics: Vec<FileStatistics>,
     ) -> Result<i32> {
         self.execute_insert(
             DaoType::TransactionInsertPartitionInfo as i32,
             JniWrapper {
                 partition_info: partition_info_list,
+                data_commit_info: data_commit_info_list,
+                file_statistics,
                 ..Default::default()
             },
         )
@@ -382,6 +405,20 @@ impl MetaDataClient {
         &self,
         meta_info: MetaInfo,
         commit_op: CommitOp,
+    ) -> Result<()> {
+        self.commit_data_with_data_commit_infos(meta_info, commit_op, vec![], vec![])
+            .await
+    }
+
+    /// Commit the partitions like [`Self::commit_data`], inserting the not yet inserted data
+    /// commit infos of their snapshots and the statistics of their files in the transaction of
+    /// the new partition versions.
+    async fn commit_data_with_data_commit_infos(
+        &self,
+        meta_info: MetaInfo,
+        commit_op: CommitOp,
+        data_commit_info_list: Vec<DataCommitInfo>,
+        file_statistics: Vec<FileStatistics>,
     ) -> Result<()> {
         let table_info = meta_info.table_info.ok_or(LakeSoulMetaDataError::Internal(
             "table info missing".to_string(),
@@ -409,6 +446,28 @@ impl MetaDataClient {
         let cur_map = self
             .get_cur_partition_map(&table_info.table_id, &partition_desc_list)
             .await?;
+        // the versions of the read partitions must still be current, a concurrent commit
+        // after this check inserts the same versions and fails the insert below
+        if !meta_info.read_partition_info.is_empty() {
+            for partition_desc in &partition_desc_list {
+                // a negative read version stands for a partition absent when it was read
+                let read_version = meta_info
+                    .read_partition_info
+                    .iter()
+                    .find(|p| &p.partition_desc == partition_desc)
+                    .map(|p| p.version)
+                    .filter(|version| *version >= 0);
+                let current_version = cur_map.get(partition_desc).map(|p| p.version);
+                if read_version != current_version {
+                    return Err(LakeSoulMetaDataError::CommitConflict {
+                        table_id: table_info.table_id.clone(),
+                        partition_desc: partition_desc.clone(),
+                        read_version,
+                        current_version,
+                    });
+                }
+            }
+        }
         let domain = self
             .get_table_domain(table_info.table_id.as_str())
             .await?
@@ -455,8 +514,13 @@ impl MetaDataClient {
                     .map(|p| p.version)
                     .max()
                     .unwrap_or(0);
-                self.transaction_insert_partition_info(new_partition_list)
-                    .await?;
+                self.insert_partition_versions(
+                    &table_info.table_id,
+                    new_partition_list,
+                    data_commit_info_list,
+                    file_statistics,
+                )
+                .await?;
                 info!(
                     "Commit Done for {:?}, partition_version={:?}",
                     commit_op, partition_version
@@ -468,6 +532,7 @@ impl MetaDataClient {
                 let read_partition_map: HashMap<String, PartitionInfo> = meta_info
                     .read_partition_info
                     .iter()
+                    .filter(|p| p.version >= 0)
                     .map(|p| (p.partition_desc.clone(), p.clone()))
                     .collect();
 
@@ -507,9 +572,16 @@ impl MetaDataClient {
 
                     new_partition_list.push(cur_partition_info);
                 }
-
-                self.transaction_insert_partition_info(new_partition_list)
-                    .await?;
+                // the last element is taken as the snapshot container of the insert
+                new_partition_list.push(PartitionInfo::default());
+
+                self.insert_partition_versions(
+                    &table_info.table_id,
+                    new_partition_list,
+                    data_commit_info_list,
+                    file_statistics,
+                )
+                .await?;
                 Ok(())
             }
 
@@ -517,6 +589,7 @@ impl MetaDataClient {
                 let read_partition_map: HashMap<String, PartitionInfo> = meta_info
                     .read_partition_info
                     .iter()
+                    .filter(|p| p.version >= 0)
                     .map(|p| (p.partition_desc.clone(), p.clone()))
                     .collect();
 
@@ -541,14 +614,72 @@ impl MetaDataClient {
 
                     new_partition_list.push(cur_partition_info);
                 }
+                // the last element is taken as the snapshot container of the insert
+                new_partition_list.push(PartitionInfo::default());
 
-                self.transaction_insert_partition_info(new_partition_list)
-                    .await?;
+                self.transaction_insert_partition_info(
+                    new_partition_list,
+                    data_commit_info_list,
+                    file_statistics,
+                )
+                .await?;
                 Ok(())
             }
         }
     }
 
+    /// Insert the new versions of the partitions in one transaction, along with the data
+    /// commit infos of their snapshots and the statistics of their files.
+    ///
+    /// The transaction is rolled back if one of the versions exists already, i.e. the
+    /// partition was committed concurrently since its current version was read, which
+    /// fails with [`LakeSoulMetaDataError::CommitConflict`].
+    async fn insert_partition_versions(
+        &self,
+        table_id: &str,
+        new_partition_list: Vec<PartitionInfo>,
+        data_commit_info_list: Vec<DataCommitInfo>,
+        file_statistics: Vec<FileStatistics>,
+    ) -> Result<()> {
+        // the last element only carries the snapshot of the committed data commit infos
+        let new_versions = new_partition_list
+            .iter()
+            .take(new_partition_list.len().saturating_sub(1))
+            .map(|p| (p.partition_desc.clone(), p.version))
+            .collect::<Vec<_>>();
+        let inserted = self
+            .transaction_insert_partition_info(
+                new_partition_list,
+                data_commit_info_list,
+                file_statistics,
+            )
+            .await?;
+        if inserted > 0 || new_versions.is_empty() {
+            return Ok(());
+        }
+        let partition_descs = new_versions
+            .iter()
+            .map(|(partition_desc, _)| partition_desc.clone())
+            .collect::<Vec<_>>();
+        let cur_map = self
+            .get_cur_partition_map(table_id, &partition_descs)
+            .await?;
+        let (partition_desc, version) = new_versions
+            .iter()
+            .find(|(partition_desc, version)| {
+                cur_map
+                    .get(partition_desc)
+                    .is_some_and(|p| p.version >= *version)
+            })
+            .unwrap_or(&new_versions[0]);
+        Err(LakeSoulMetaDataError::CommitConflict {
+            table_id: table_id.to_string(),
+            partition_desc: partition_desc.clone(),
+            read_version: (*version > 0).then(|| version - 1),
+            current_version: cur_map.get(partition_desc).map(|p| p.version),
+        })
+    }
+
     async fn get_cur_partition_map(
         &self,
         table_id: &str,
@@ -619,6 +750,105 @@ impl MetaDataClient {
         .await
     }
 
+    /// Commit the data commit infos of multiple partitions of a table at once.
+    ///
+    /// The data commit infos and the new versions of all partitions are inserted in one
+    /// transaction, see [`Self::commit_data_commit_info_batch_with_read_partitions`].
+    pub async fn commit_data_commit_info_batch(
+        &self,
+        data_commit_info_list: Vec<DataCommitInfo>,
+    ) -> Result<()> {
+        self.commit_data_commit_info_batch_with_read_partitions(
+            data_commit_info_list,
+            vec![],
+        )
+        .await
+    }
+
+    /// Commit the data commit infos of multiple partitions of a table at once, replacing the
+    /// snapshots of the partitions read by a compaction or update.
+    ///
+    /// The data commit infos are inserted in the transaction inserting the new versions of
+    /// the partitions, so either all partitions are committed or none, and a failed commit
+    /// leaves no data commit info behind. They are put in the domain of the table, and must
+    /// be of the same table and commit op and have new commit ids. The commit fails with [`LakeSoulMetaDataError::CommitConflict`] without changing any
+    /// partition if one of the `read_partition_info` is no longer the current version of its
+    /// partition, i.e. the partition was committed concurrently since it was read. A read
+    /// partition with a negative version must still be absent, and the partitions missing
+    /// from a non-empty `read_partition_info` too. The check is completed by the transaction
+    /// inserting the new versions, which fails if another commit inserted them first.
+    pub async fn commit_data_commit_info_batch_with_read_partitions(
+        &self,
+        data_commit_info_list: Vec<DataCommitInfo>,
+        read_partition_info: Vec<PartitionInfo>,
+    ) -> Result<()> {
+        self.commit_data_commit_info_batch_with_statistics(
+            data_commit_info_list,
+            vec![],
+            read_partition_info,
+        )
+        .await
+    }
+
+    /// Commit the data commit infos of multiple partitions of a table at once like
+    /// [`Self::commit_data_commit_info_batch_with_read_partitions`], inserting the statistics
+    /// of the committed files in the same transaction, so that a failed commit leaves no
+    /// statistics behind.
+    pub async fn commit_data_commit_info_batch_with_statistics(
+        &self,
+        mut data_commit_info_list: Vec<DataCommitInfo>,
+        file_statistics: Vec<FileStatistics>,
+        read_partition_info: Vec<PartitionInfo>,
+    ) -> Result<()> {
+        let Some(first) = data_commit_info_list.first() else {
+            return Ok(());
+        };
+        let table_id = first.table_id.clone();
+        let commit_op = first.commit_op;
+        if data_commit_info_list
+            .iter()
+            .any(|info| info.table_id != table_id || info.commit_op != commit_op)
+        {
+            return Err(LakeSoulMetaDataError::Internal(
+                "data commit infos of a batch commit must share table_id and commit_op"
+                    .to_string(),
+            ));
+        }
+        let domain = self.get_table_domain(&table_id).await?.domain;
+        let list_partition = data_commit_info_list
+            .iter_mut()
+            .map(|info| {
+                let commit_id = info.commit_id.ok_or(LakeSoulMetaDataError::Internal(
+                    "commit_id missing".to_string(),
+                ))?;
+                info.domain = domain.clone();
+                Ok(PartitionInfo {
+                    table_id: table_id.clone(),
+                    partition_desc: info.partition_desc.clone(),
+                    commit_op,
+                    domain: domain.clone(),
+                    snapshot: vec![commit_id],
+                    ..Default::default()
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let table_info = self.get_table_info_by_table_id(&table_id).await?;
+        self.commit_data_with_data_commit_infos(
+            MetaInfo {
+                table_info,
+                list_partition,
+                read_partition_info,
+                ..Default::default()
+            },
+            CommitOp::try_from(commit_op).map_err(|_| {
+                LakeSoulMetaDataError::Internal("unknown commit_op".to_string())
+            })?,
+            data_commit_info_list,
+            file_statistics,
+        )
+        .await
+    }
+
     pub async fn get_table_domain(&self, table_id: &str) -> Result<TableNameId> {
         match self
             .execute_query(
@@ -797,25 +1027,50 @@ impl MetaDataClient {
         Ok(data_files)
     }
 
+    /// Returns the data files of the snapshot of the partition, in their commit order.
+    ///
+    /// A file deleted by a later commit of the snapshot, e.g. a tombstone written by
+    /// [`Self::commit_data_commit_info_batch`] with [`FileOp::Del`] file ops, is left out,
+    /// while the physical file stays in place until it is vacuumed.
     pub async fn get_data_files_of_single_partition(
         &self,
         partition_info: &PartitionInfo,
     ) -> Result<Vec<String>> {
+        Ok(self
+            .get_data_file_ops_of_single_partition(partition_info)
+            .await?
+            .into_iter()
+            .map(|file_op| file_op.path)
+            .collect())
+    }
+
+    /// Returns the add file ops of the data files of the snapshot of the partition, in
+    /// their commit order, see [`Self::get_data_files_of_single_partition`].
+    ///
+    /// The file ops carry the sizes of the files recorded when they were committed.
+    pub async fn get_data_file_ops_of_single_partition(
+        &self,
+        partition_info: &PartitionInfo,
+    ) -> Result<Vec<DataFileOp>> {
         let data_commit_info_list = self
             .get_data_commit_info_of_single_partition(partition_info)
             .await?;
-        // let data_commit_info_list = Vec::<DataCommitInfo>::new();
-        let data_file_list = data_commit_info_list
+        // walk the file ops backwards, a delete hides the earlier adds of its path
+        let mut deleted = HashSet::new();
+        let mut data_file_ops = Vec::new();
+        for file_op in data_commit_info_list
             .iter()
-            .flat_map(|data_commit_info| {
-                data_commit_info
-                    .file_ops
-                    .iter()
-                    .map(|file_op| file_op.path.clone())
-                    .collect::<Vec<String>>()
-            })
-            .collect::<Vec<String>>();
-        Ok(data_file_list)
+            .rev()
+            .flat_map(|data_commit_info| data_commit_info.file_ops.iter().rev())
+        {
+            if file_op.file_op == FileOp::Del as i32 {
+                deleted.insert(file_op.path.as_str());
+            } else if !deleted.contains(file_op.path.as_str()) {
+                data_file_ops.push(file_op.clone());
+            }
+        }
+        data_file_ops.reverse();
+        Ok(data_file_ops)
     }
 
     async fn get_data_commit_info_of_single_partition(
@@ -882,6 +1137,109 @@ impl MetaDataClient {
         }
     }
 
+    /// Get the latest version of each partition committed at or before the timestamp in milliseconds.
+    ///
+    /// Partitions without any version at that time are not returned.
+    pub async fn get_all_partition_info_as_of_timestamp(
+        &self,
+        table_id: &str,
+        timestamp: i64,
+    ) -> Result<Vec<PartitionInfo>> {
+        match self
+            .execute_query(
+                DaoType::ListPartitionByTableIdUpToTimestamp as i32,
+                [table_id, timestamp.to_string().as_str()].join(PARAM_DELIM),
+            )
+            .await
+        {
+            Ok(wrapper) => Ok(wrapper.partition_info),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get the latest version of each partition not newer than the version.
+    pub async fn get_all_partition_info_as_of_version(
+        &self,
+        table_id: &str,
+        version: i32,
+    ) -> Result<Vec<PartitionInfo>> {
+        match self
+            .execute_query(
+                DaoType::ListPartitionByTableIdUpToVersion as i32,
+                [table_id, version.to_string().as_str()].join(PARAM_DELIM),
+            )
+            .await
+        {
+            Ok(wrapper) => Ok(wrapper.partition_info),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get the versions of a partition within the inclusive version range.
+    pub async fn get_partition_versions_in_range(
+        &self,
+        table_id: &str,
+        partition_desc: &str,
+        start_version: i32,
+        end_version: i32,
+    ) -> Result<Vec<PartitionInfo>> {
+        match self
+            .execute_query(
+                DaoType::ListPartitionVersionByTableIdAndPartitionDescAndVersionRange
+                    as i32,
+                [
+                    table_id,
+                    partition_desc,
+                    start_version.to_string().as_str(),
+                    end_version.to_string().as_str(),
+                ]
+                .join(PARAM_DELIM),
+            )
+            .await
+        {
+            Ok(wrapper) => Ok(wrapper.partition_info),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get the commits added to a partition by the append and merge commits of the versions
+    /// after `from_version` up to `to_version`, in commit order.
+    ///
+    /// Compaction and update commits rewrite existing data and are not reported as changes.
+    pub async fn get_changed_commits_of_partition(
+        &self,
+        table_id: &str,
+        partition_desc: &str,
+        from_version: i32,
+        to_version: i32,
+    ) -> Result<Vec<entity::Uuid>> {
+        let mut versions = self
+            .get_partition_versions_in_range(
+                table_id,
+                partition_desc,
+                from_version,
+                to_version,
+            )
+            .await?;
+        versions.sort_by_key(|partition_info| partition_info.version);
+        let mut changed = vec![];
+        let mut previous: Option<&PartitionInfo> = None;
+        for partition_info in &versions {
+            let is_change = matches!(
+                partition_info.commit_op(),
+                CommitOp::AppendCommit | CommitOp::MergeCommit
+            );
+            if partition_info.version > from_version && is_change {
+                changed.extend(partition_info.snapshot.iter().filter(|commit_id| {
+                    !previous
+                        .is_some_and(|previous| previous.snapshot.contains(commit_id))
+                }));
+            }
+            previous = Some(partition_info);
+        }
+        Ok(changed)
+    }
+
     pub async fn get_single_data_commit_info(
         &self,
         table_id: &str,
@@ -926,6 +1284,130 @@ impl MetaDataClient {
         }
     }
 
+    /// Delete the statistics of a data file of a table.
+    pub async fn delete_file_statistics_by_file_path(
+        &self,
+        table_id: &str,
+        file_path: &str,
+    ) -> Result<i32> {
+        self.execute_update(
+            DaoType::DeleteFileStatisticsByTableIdAndFilePath as i32,
+            [table_id, file_path].join(PARAM_DELIM),
+        )
+        .await
+    }
+
+    /// Insert the statistics of a data file, replacing the existing statistics of the file.
+    pub async fn insert_file_statistics(
+        &self,
+        file_statistics: FileStatistics,
+    ) -> Result<i32> {
+        self.execute_insert(
+            DaoType::InsertFileStatistics as i32,
+            JniWrapper {
+                file_statistics: vec![file_statistics],
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// Get the stored statistics of all data files of a table.
+    pub async fn get_file_statistics_by_table_id(
+        &self,
+        table_id: &str,
+    ) -> Result<Vec<FileStatistics>> {
+        match self
+            .execute_query(
+                DaoType::ListFileStatisticsByTableId as i32,
+                table_id.to_string(),
+            )
+            .await
+        {
+            Ok(wrapper) => Ok(wrapper.file_statistics),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get the stored statistics of the data files of the given partitions of a table, so
+    /// that a scan does not load the statistics of the partitions it does not read.
+    pub async fn get_file_statistics_by_table_id_and_partition_list(
+        &self,
+        table_id: &str,
+        partition_desc_list: &[String],
+    ) -> Result<Vec<FileStatistics>> {
+        if partition_desc_list.is_empty() {
+            return Ok(vec![]);
+        }
+        match self
+            .execute_query(
+                DaoType::ListFileStatisticsByTableIdAndPartitionDescList as i32,
+                [
+                    table_id,
+                    partition_desc_list.join(PARTITION_DESC_DELIM).as_str(),
+                ]
+                .join(PARAM_DELIM),
+            )
+            .await
+        {
+            Ok(wrapper) => Ok(wrapper.file_statistics),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Record the data files which are no longer part of the snapshots of their partitions,
+    /// so that they are deleted by a vacuum once they are discarded longer than its retention.
+    ///
+    /// The files are recorded in one transaction.
+    pub async fn insert_discard_compressed_file_info(
+        &self,
+        discard_compressed_file_info: Vec<DiscardCompressedFileInfo>,
+    ) -> Result<i32> {
+        if discard_compressed_file_info.is_empty() {
+            return Ok(0);
+        }
+        self.execute_insert(
+            DaoType::TransactionInsertDiscardCompressedFile as i32,
+            JniWrapper {
+                discard_compressed_file_info,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// Get the data files of a partition of the table discarded before the timestamp in
+    /// milliseconds.
+    pub async fn get_discard_compressed_file_info_before(
+        &self,
+        table_path: &str,
+        partition_desc: &str,
+        timestamp: i64,
+    ) -> Result<Vec<DiscardCompressedFileInfo>> {
+        match self
+            .execute_query(
+                DaoType::ListDiscardCompressedFileByFilterCondition as i32,
+                [table_path, partition_desc, &timestamp.to_string()].join(PARAM_DELIM),
+            )
+            .await
+        {
+            Ok(wrapper) => Ok(wrapper.discard_compressed_file_info),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Delete the record of a discarded data file once it is deleted from the object store.
+    pub async fn delete_discard_compressed_file_info_by_file_path(
+        &self,
+        file_path: &str,
+    ) -> Result<i32> {
+        self.execute_update(
+            DaoType::DeleteDiscardCompressedFileInfoByFilePath as i32,
+            [file_path].join(PARAM_DELIM),
+        )
+        .await
+    }
+
     pub fn get_client_secret(&self) -> &String {
         &self.secret
     }
@@ -1016,6 +1498,26 @@ impl MetaDataClient {
             )))
         }
     }
+
+    /// Replace the serialized schema of the table if it is still `expected_schema`, leaving
+    /// its name and path unchanged.
+    ///
+    /// Returns false, leaving the schema unchanged, if the schema was changed since it was
+    /// read, so that the change is made again upon the current schema.
+    pub async fn compare_and_swap_table_schema(
+        &self,
+        table_id: &str,
+        expected_schema: &str,
+        table_schema: &str,
+    ) -> Result<bool> {
+        let updated = self
+            .execute_update(
+                DaoType::UpdateTableSchemaByIdAndSchema as i32,
+                [table_id, expected_schema, table_schema].join(PARAM_DELIM),
+            )
+            .await?;
+        Ok(updated == 1)
+    }
 }
 
 pub fn table_path_id_from_table_info(table_info: &TableInfo) -> TablePathId {
@@ -160,3 +160,12 @@ create table if not exists discard_compressed_file_info
     t_date date,
     PRIMARY KEY (file_path)
 );
+
+create table if not exists file_statistics
+(
+    table_id       text,
+    file_path      text,
+    partition_desc text,
+    statistics     json,
+    PRIMARY KEY (table_id, file_path)
+);